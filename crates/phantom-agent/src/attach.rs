@@ -0,0 +1,231 @@
+//! Attaching to a running process.
+//!
+//! `phantom attach` loads the agent into a running process with dlopen(), too
+//! late for its exports to interpose anything: every object has already bound
+//! its calls. Phantom then calls `phantom_attach()`, which walks each loaded
+//! object's relocations and points the GOT slots of the hooked libc functions
+//! at the agent. Only slots bound to the function `real!` calls, or lazy ones
+//! not bound yet, are redirected. A dlopen()ed agent's `real!` sees libc but not
+//! libraries such as libssl that the process loaded itself, so TLS and
+//! io_uring calls are left alone; objects loaded later call libc directly.
+
+use std::ffi::CStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use libc::{c_int, c_void, size_t};
+
+/// The hooked libc functions `phantom_attach` redirects.
+const HOOKS: &[&CStr] = &[
+    c"sendto",
+    c"sendmsg",
+    c"sendmmsg",
+    c"accept",
+    c"accept4",
+    c"connect",
+    c"dup",
+    c"dup2",
+    c"dup3",
+    c"fcntl",
+    c"fcntl64",
+    c"send",
+    c"recv",
+    c"write",
+    c"read",
+    c"recvfrom",
+    c"recvmsg",
+    c"writev",
+    c"readv",
+    c"shutdown",
+    c"close",
+    c"close_range",
+];
+
+/// `R_X86_64_GLOB_DAT`, which fills a GOT slot with a symbol's address.
+const R_GLOB_DAT: u32 = 6;
+/// `R_X86_64_JUMP_SLOT`, the same for a PLT call, possibly bound lazily.
+const R_JUMP_SLOT: u32 = 7;
+
+const DT_NULL: i64 = 0;
+const DT_PLTRELSZ: i64 = 2;
+const DT_STRTAB: i64 = 5;
+const DT_SYMTAB: i64 = 6;
+const DT_RELA: i64 = 7;
+const DT_RELASZ: i64 = 8;
+const DT_JMPREL: i64 = 23;
+
+/// `Elf64_Dyn`.
+#[repr(C)]
+struct ElfDyn {
+    tag: i64,
+    val: u64,
+}
+
+/// `Elf64_Rela`.
+#[repr(C)]
+struct ElfRela {
+    offset: u64,
+    info: u64,
+    _addend: i64,
+}
+
+/// What `phantom_attach` redirects, handed to `attach_object`.
+struct Attach {
+    /// An address inside the agent, whose own slots are left alone.
+    agent: usize,
+    /// Each hooked function with its address in libc and the agent's hook.
+    hooks: Vec<(&'static CStr, usize, usize)>,
+    page_size: usize,
+    redirected: c_int,
+}
+
+/// Points the process's calls to the hooked libc functions at the agent,
+/// for an agent loaded into a running process. Returns how many GOT slots
+/// lead to the agent, counting those an earlier call redirected, or -1
+/// when the agent cannot look itself up.
+#[unsafe(no_mangle)]
+pub extern "C" fn phantom_attach() -> c_int {
+    let agent = phantom_attach as *const c_void;
+    // SAFETY: Dl_info is plain data, filled in by dladdr.
+    let mut info: libc::Dl_info = unsafe { std::mem::zeroed() };
+    // SAFETY: dladdr only looks the address up.
+    if unsafe { libc::dladdr(agent, &mut info) } == 0 {
+        return -1;
+    }
+    // SAFETY: dli_fname names the agent, which is loaded; RTLD_NOLOAD only
+    // takes another reference to it.
+    let handle = unsafe { libc::dlopen(info.dli_fname, libc::RTLD_NOW | libc::RTLD_NOLOAD) };
+    if handle.is_null() {
+        return -1;
+    }
+    let hooks = HOOKS
+        .iter()
+        .filter_map(|&name| {
+            // SAFETY: dlsym with a NUL-terminated name; a null result
+            // means absent. RTLD_NEXT finds what `real!` calls.
+            let real = unsafe { libc::dlsym(libc::RTLD_NEXT, name.as_ptr()) } as usize;
+            // SAFETY: as above; the agent's own export comes first.
+            let hook = unsafe { libc::dlsym(handle, name.as_ptr()) } as usize;
+            (real != 0 && hook != 0 && real != hook).then_some((name, real, hook))
+        })
+        .collect();
+    // SAFETY: drops the reference taken above; the agent stays loaded.
+    unsafe { libc::dlclose(handle) };
+    // SAFETY: sysconf has no preconditions.
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+    let mut attach = Attach {
+        agent: agent as usize,
+        hooks,
+        page_size,
+        redirected: 0,
+    };
+    // SAFETY: attach_object takes `data` as the Attach it is given here.
+    unsafe { libc::dl_iterate_phdr(Some(attach_object), (&raw mut attach).cast()) };
+    attach.redirected
+}
+
+/// `dl_iterate_phdr` callback redirecting one object's GOT slots.
+unsafe extern "C" fn attach_object(
+    info: *mut libc::dl_phdr_info,
+    _size: size_t,
+    data: *mut c_void,
+) -> c_int {
+    // SAFETY: dl_iterate_phdr passes a valid info, and `data` is the
+    // Attach from phantom_attach.
+    let (info, attach) = unsafe { (&*info, &mut *data.cast::<Attach>()) };
+    let base = info.dlpi_addr as usize;
+    // SAFETY: dlpi_phdr points to dlpi_phnum program headers.
+    let phdrs = unsafe { std::slice::from_raw_parts(info.dlpi_phdr, usize::from(info.dlpi_phnum)) };
+    let segments = |kind| {
+        phdrs
+            .iter()
+            .filter(move |p| p.p_type == kind)
+            .map(move |p| {
+                let start = base + p.p_vaddr as usize;
+                start..start + p.p_memsz as usize
+            })
+    };
+    let loaded: Vec<_> = segments(libc::PT_LOAD).collect();
+    if loaded.iter().any(|r| r.contains(&attach.agent)) {
+        return 0;
+    }
+    let Some(dynamic) = segments(libc::PT_DYNAMIC).next() else {
+        return 0;
+    };
+    let relro = segments(libc::PT_GNU_RELRO).next();
+
+    let mut table = [0usize; DT_JMPREL as usize + 1];
+    let mut entry = dynamic.start as *const ElfDyn;
+    loop {
+        // SAFETY: the dynamic section runs up to a DT_NULL entry.
+        let ElfDyn { tag, val } = unsafe { entry.read() };
+        if tag == DT_NULL {
+            break;
+        }
+        if let Some(slot) = usize::try_from(tag).ok().and_then(|i| table.get_mut(i)) {
+            *slot = val as usize;
+        }
+        entry = entry.wrapping_add(1);
+    }
+    // glibc relocates these addresses in place; musl leaves them relative.
+    let addr = |tag: i64| match table[tag as usize] {
+        0 => 0,
+        a if a < base => base + a,
+        a => a,
+    };
+    let (strtab, symtab) = (addr(DT_STRTAB), addr(DT_SYMTAB));
+    if strtab == 0 || symtab == 0 {
+        return 0;
+    }
+    for (relocs, size) in [(DT_RELA, DT_RELASZ), (DT_JMPREL, DT_PLTRELSZ)] {
+        let relocs = addr(relocs);
+        if relocs == 0 {
+            continue;
+        }
+        let count = table[size as usize] / size_of::<ElfRela>();
+        // SAFETY: the table holds `count` relocations.
+        let relocs = unsafe { std::slice::from_raw_parts(relocs as *const ElfRela, count) };
+        for reloc in relocs {
+            let kind = reloc.info as u32;
+            if kind != R_GLOB_DAT && kind != R_JUMP_SLOT {
+                continue;
+            }
+            let sym = (symtab as *const libc::Elf64_Sym).wrapping_add((reloc.info >> 32) as usize);
+            // SAFETY: relocations name symbols of the object's own table,
+            // whose names are NUL-terminated in its string table.
+            let name = unsafe { CStr::from_ptr((strtab + (*sym).st_name as usize) as *const _) };
+            let Some(&(_, real, hook)) = attach.hooks.iter().find(|(n, ..)| *n == name) else {
+                continue;
+            };
+            let at = base + reloc.offset as usize;
+            // SAFETY: the relocation's target is one of the object's
+            // aligned GOT slots.
+            let slot = unsafe { &*(at as *const AtomicUsize) };
+            let bound = slot.load(Ordering::Acquire);
+            if bound == hook {
+                // Redirected by an earlier attach.
+                attach.redirected += 1;
+                continue;
+            }
+            // A lazy slot not called yet points back into the object's PLT.
+            let lazy = kind == R_JUMP_SLOT && loaded.iter().any(|r| r.contains(&bound));
+            if bound != real && !lazy {
+                continue;
+            }
+            let page = (at & !(attach.page_size - 1)) as *mut c_void;
+            let read_only = relro.as_ref().is_some_and(|r| r.contains(&at));
+            let writable = libc::PROT_READ | libc::PROT_WRITE;
+            // SAFETY: the page holds the object's GOT, writable only while
+            // the slot is updated.
+            if read_only && unsafe { libc::mprotect(page, attach.page_size, writable) } != 0 {
+                continue;
+            }
+            slot.store(hook, Ordering::Release);
+            attach.redirected += 1;
+            if read_only {
+                // SAFETY: as above.
+                unsafe { libc::mprotect(page, attach.page_size, libc::PROT_READ) };
+            }
+        }
+    }
+    0
+}
@@ -0,0 +1,208 @@
+//! HTTP/1.x chunked transfer coding, decoded as the bytes arrive.
+
+use std::collections::HashMap;
+use std::sync::atomic::Ordering;
+
+use crate::{PARSE_ERRORS, capture_bodies, max_buf};
+
+/// Longest chunk-size or trailer line accepted.
+const CHUNK_MAX_LINE: usize = 4096;
+
+#[derive(Default, PartialEq)]
+enum ChunkPhase {
+    /// Reading a `size[;ext]` line.
+    #[default]
+    Size,
+    /// Copying chunk data.
+    Data,
+    /// Skipping the CRLF after chunk data.
+    DataEnd,
+    /// Reading trailer lines after the last chunk.
+    Trailer,
+    /// The empty line after the trailers was read.
+    Done,
+}
+
+/// Incremental decoder for `Transfer-Encoding: chunked` bodies.
+#[derive(Default)]
+pub(crate) struct ChunkedDecoder {
+    phase: ChunkPhase,
+    /// Partial size or trailer line.
+    line: Vec<u8>,
+    /// Data bytes left in the current chunk.
+    remaining: usize,
+    /// Trailer fields after the last chunk (lower-cased names).
+    pub(crate) trailers: HashMap<String, String>,
+}
+
+impl ChunkedDecoder {
+    /// Decodes `data`, appending chunk payloads to `out` unless `keep` is
+    /// false. Returns whether the body is complete, or `None` for malformed
+    /// framing.
+    pub(crate) fn feed(&mut self, mut data: &[u8], out: &mut Vec<u8>, keep: bool) -> Option<bool> {
+        while !data.is_empty() && self.phase != ChunkPhase::Done {
+            match self.phase {
+                ChunkPhase::Size | ChunkPhase::Trailer => {
+                    let (line, rest) = match data.iter().position(|&b| b == b'\n') {
+                        Some(nl) => (&data[..nl], Some(&data[nl + 1..])),
+                        None => (data, None),
+                    };
+                    if self.line.len() + line.len() > CHUNK_MAX_LINE {
+                        return None;
+                    }
+                    self.line.extend_from_slice(line);
+                    let Some(rest) = rest else { break };
+                    data = rest;
+                    let line = std::mem::take(&mut self.line);
+                    let line = line.strip_suffix(b"\r").unwrap_or(&line);
+                    if self.phase == ChunkPhase::Trailer {
+                        if line.is_empty() {
+                            self.phase = ChunkPhase::Done;
+                        } else if let Some(colon) = line.iter().position(|&b| b == b':') {
+                            let name = String::from_utf8_lossy(&line[..colon]).to_lowercase();
+                            let value = String::from_utf8_lossy(&line[colon + 1..]);
+                            self.trailers.insert(name, value.trim().to_string());
+                        }
+                        continue;
+                    }
+                    let size = line.split(|&b| b == b';').next().unwrap_or_default();
+                    let size = std::str::from_utf8(size).ok()?.trim();
+                    self.remaining = usize::from_str_radix(size, 16).ok()?;
+                    self.phase = if self.remaining == 0 {
+                        ChunkPhase::Trailer
+                    } else {
+                        ChunkPhase::Data
+                    };
+                }
+                ChunkPhase::Data => {
+                    let n = self.remaining.min(data.len());
+                    if keep && out.len() < max_buf() {
+                        out.extend_from_slice(&data[..n]);
+                    }
+                    self.remaining -= n;
+                    data = &data[n..];
+                    if self.remaining == 0 {
+                        self.phase = ChunkPhase::DataEnd;
+                    }
+                }
+                ChunkPhase::DataEnd => match data[0] {
+                    b'\r' => data = &data[1..],
+                    b'\n' => {
+                        data = &data[1..];
+                        self.phase = ChunkPhase::Size;
+                    }
+                    // Missing terminator: read the next size line anyway.
+                    _ => self.phase = ChunkPhase::Size,
+                },
+                ChunkPhase::Done => {}
+            }
+        }
+        Some(self.phase == ChunkPhase::Done)
+    }
+}
+
+/// A response body being received with chunked transfer coding.
+#[derive(Default)]
+pub(crate) struct ChunkedBody {
+    pub(crate) decoder: ChunkedDecoder,
+    pub(crate) body: Vec<u8>,
+    /// The last chunk and trailers arrived (or the framing broke).
+    pub(crate) done: bool,
+}
+
+impl ChunkedBody {
+    pub(crate) fn feed(&mut self, data: &[u8]) {
+        // On malformed framing, emit what was decoded instead of waiting
+        // for the connection to close.
+        self.done = self
+            .decoder
+            .feed(data, &mut self.body, capture_bodies())
+            .unwrap_or_else(|| {
+                PARSE_ERRORS.fetch_add(1, Ordering::Relaxed);
+                true
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feeds `data` one byte at a time.
+    fn feed_bytewise(decoder: &mut ChunkedDecoder, data: &[u8], out: &mut Vec<u8>) -> Option<bool> {
+        let mut done = false;
+        for b in data {
+            done = decoder.feed(std::slice::from_ref(b), out, true)?;
+        }
+        Some(done)
+    }
+
+    #[test]
+    fn test_chunks_with_extensions_and_trailers() {
+        let data = b"5;name=v\r\nhello\r\n7\r\n, world\r\n0\r\nX-Checksum: abc\r\n\r\n";
+        let mut decoder = ChunkedDecoder::default();
+        let mut out = Vec::new();
+        assert_eq!(decoder.feed(data, &mut out, true), Some(true));
+        assert_eq!(out, b"hello, world");
+        assert_eq!(decoder.trailers["x-checksum"], "abc");
+    }
+
+    #[test]
+    fn test_split_at_every_byte() {
+        let data = b"a\r\n0123456789\r\n0\r\n\r\n";
+        let mut decoder = ChunkedDecoder::default();
+        let mut out = Vec::new();
+        assert_eq!(
+            feed_bytewise(&mut decoder, &data[..data.len() - 1], &mut out),
+            Some(false)
+        );
+        assert_eq!(decoder.feed(b"\n", &mut out, true), Some(true));
+        assert_eq!(out, b"0123456789");
+    }
+
+    #[test]
+    fn test_discards_payload_when_not_kept() {
+        let mut decoder = ChunkedDecoder::default();
+        let mut out = Vec::new();
+        assert_eq!(
+            decoder.feed(b"3\r\nabc\r\n0\r\n\r\n", &mut out, false),
+            Some(true)
+        );
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn test_bare_lf_and_missing_terminator() {
+        let mut decoder = ChunkedDecoder::default();
+        let mut out = Vec::new();
+        assert_eq!(
+            decoder.feed(b"2\nab2\ncd\n0\n\n", &mut out, true),
+            Some(true)
+        );
+        assert_eq!(out, b"abcd");
+    }
+
+    #[test]
+    fn test_malformed_framing() {
+        let mut out = Vec::new();
+        assert_eq!(
+            ChunkedDecoder::default().feed(b"zz\r\n", &mut out, true),
+            None
+        );
+        let long = vec![b'0'; CHUNK_MAX_LINE + 1];
+        assert_eq!(ChunkedDecoder::default().feed(&long, &mut out, true), None);
+    }
+
+    #[test]
+    fn test_body_done_on_last_chunk_or_bad_framing() {
+        let mut body = ChunkedBody::default();
+        body.feed(b"3\r\nabc\r\n");
+        assert!(!body.done);
+        body.feed(b"0\r\n\r\n");
+        assert!(body.done);
+
+        let mut body = ChunkedBody::default();
+        body.feed(b"nothex\r\n");
+        assert!(body.done);
+    }
+}
@@ -0,0 +1,301 @@
+//! HTTP/1.x requests and responses: detection, header parsing with
+//! `httparse`, and the trace emitted once a response completes.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::chunked::ChunkedBody;
+use crate::propagate::{hex, take_propagated};
+use crate::{
+    FdState, ReqInfo, TimingMsg, TraceMsg, body_b64, body_overflow_file, capture_bodies,
+    connection_dest, connection_id, connection_label, connection_phases, connection_tls,
+    current_tid, emit_msg, now_ms, process_comm,
+};
+
+const HTTP_METHODS: &[&[u8]] = &[
+    b"GET ",
+    b"POST ",
+    b"PUT ",
+    b"DELETE ",
+    b"PATCH ",
+    b"HEAD ",
+    b"OPTIONS ",
+    b"TRACE ",
+    b"CONNECT ",
+];
+
+pub(crate) fn looks_like_http_request(data: &[u8]) -> bool {
+    HTTP_METHODS.iter().any(|m| data.starts_with(m))
+}
+
+/// Parses a request once its headers are complete. A chunked body is decoded
+/// as far as `buf` goes and returned alongside, to be fed the rest.
+pub(crate) fn try_parse_request(buf: &[u8]) -> Option<(ReqInfo, Option<Box<ChunkedBody>>)> {
+    let mut headers_storage = [httparse::EMPTY_HEADER; 64];
+    let mut req = httparse::Request::new(&mut headers_storage);
+    let httparse::Status::Complete(headers_end) = req.parse(buf).ok()? else {
+        return None;
+    };
+
+    let method = req.method?.to_string();
+    let path = req.path?.to_string();
+    let mut hmap = HashMap::new();
+    let mut host = String::new();
+    let mut content_length = 0usize;
+    let mut is_chunked = false;
+
+    for h in req.headers.iter() {
+        let name = h.name.to_lowercase();
+        let value = String::from_utf8_lossy(h.value).into_owned();
+        if name == "host" {
+            host = value.clone();
+        }
+        if name == "content-length" {
+            content_length = value.parse().unwrap_or(0);
+        }
+        if name == "transfer-encoding" && value.to_lowercase().contains("chunked") {
+            is_chunked = true;
+        }
+        hmap.insert(name, value);
+    }
+
+    let url = if path.starts_with("http://") || path.starts_with("https://") {
+        path
+    } else {
+        format!("http://{host}{path}")
+    };
+
+    // Only take body bytes that are already in the buffer.
+    let (body, chunked) = if is_chunked {
+        let mut chunked = Box::<ChunkedBody>::default();
+        chunked.feed(&buf[headers_end..]);
+        (Vec::new(), Some(chunked))
+    } else {
+        let body_end = (headers_end + content_length).min(buf.len());
+        let body = match capture_bodies() {
+            true => buf[headers_end..body_end].to_vec(),
+            false => Vec::new(),
+        };
+        (body, None)
+    };
+
+    let req = ReqInfo {
+        method,
+        url,
+        headers: hmap,
+        body,
+        started_at: Instant::now(),
+        timestamp_ms: now_ms(),
+        conn_id: 0,
+        dest_addr: None,
+        tls_info: None,
+        timing: TimingMsg::default(),
+        first_byte_at: None,
+        propagated: None,
+        request_size: buf.len() as u64,
+        response_size: 0,
+        tid: current_tid(),
+    };
+    Some((req, chunked))
+}
+
+/// State for a request whose body has been sent (or given up on): wait for
+/// the response.
+pub(crate) fn collecting_response(req: Box<ReqInfo>, tls: bool) -> FdState {
+    FdState::CollectingResponse {
+        req,
+        buf: Vec::new(),
+        tls,
+        status_code: None,
+        resp_headers: None,
+        content_length: None,
+        headers_end: None,
+        chunked: None,
+        skipped: 0,
+        last_recv: Instant::now(),
+    }
+}
+
+/// State after a request's headers are parsed: keep decoding a chunked body
+/// until its last chunk, otherwise wait for the response.
+pub(crate) fn request_sent(
+    key: usize,
+    mut req: ReqInfo,
+    chunked: Option<Box<ChunkedBody>>,
+    tls: bool,
+) -> FdState {
+    req.conn_id = connection_id(key);
+    req.dest_addr = connection_dest(key, tls);
+    req.tls_info = connection_tls(key, tls);
+    req.timing = connection_phases(key, tls);
+    req.propagated = take_propagated(0);
+    match chunked {
+        Some(body) if !body.done => FdState::SendingChunkedRequest {
+            req: Box::new(req),
+            body,
+            tls,
+        },
+        Some(body) => {
+            req.body = body.body;
+            collecting_response(Box::new(req), tls)
+        }
+        None => collecting_response(Box::new(req), tls),
+    }
+}
+
+/// Informational (1xx) statuses other than `101 Switching Protocols`, which
+/// are followed by the final response on the same connection.
+pub(crate) fn is_interim_status(status: u16) -> bool {
+    (100..200).contains(&status) && status != 101
+}
+
+/// Whether `data` starts with an interim `100 Continue` response.
+pub(crate) fn is_continue_response(data: &[u8]) -> bool {
+    data.starts_with(b"HTTP/1.") && data.get(8..13) == Some(b" 100 ")
+}
+
+pub(crate) struct RespMeta {
+    pub(crate) status_code: u16,
+    pub(crate) headers: HashMap<String, String>,
+    pub(crate) content_length: Option<usize>,
+    pub(crate) headers_end: usize,
+    pub(crate) chunked: bool,
+}
+
+pub(crate) fn try_parse_response_headers(buf: &[u8]) -> Option<RespMeta> {
+    let mut headers_storage = [httparse::EMPTY_HEADER; 64];
+    let mut resp = httparse::Response::new(&mut headers_storage);
+    let httparse::Status::Complete(headers_end) = resp.parse(buf).ok()? else {
+        return None;
+    };
+
+    let status_code = resp.code?;
+    let mut hmap = HashMap::new();
+    let mut content_length: Option<usize> = None;
+    let mut is_chunked = false;
+
+    for h in resp.headers.iter() {
+        let name = h.name.to_lowercase();
+        let value = String::from_utf8_lossy(h.value).into_owned();
+        if name == "content-length" {
+            content_length = value.parse().ok();
+        }
+        if name == "transfer-encoding" && value.to_lowercase().contains("chunked") {
+            is_chunked = true;
+        }
+        hmap.insert(name, value);
+    }
+
+    // Chunked framing overrides any Content-Length (RFC 9112 §6.3).
+    if is_chunked {
+        content_length = None;
+    }
+
+    Some(RespMeta {
+        status_code,
+        headers: hmap,
+        content_length,
+        headers_end,
+        chunked: is_chunked,
+    })
+}
+
+pub(crate) fn do_emit(
+    req: ReqInfo,
+    status_code: u16,
+    resp_headers: HashMap<String, String>,
+    resp_trailers: HashMap<String, String>,
+    resp_body: &[u8],
+    duration: Duration,
+    tls: bool,
+) {
+    let url = if tls && req.url.starts_with("http://") {
+        req.url.replacen("http://", "https://", 1)
+    } else {
+        req.url
+    };
+    emit_msg(TraceMsg {
+        method: req.method,
+        url,
+        status_code,
+        request_headers: req.headers,
+        response_headers: resp_headers,
+        response_trailers: resp_trailers,
+        request_body_b64: body_b64(&req.body),
+        response_body_b64: body_b64(resp_body),
+        request_body_file: body_overflow_file(&req.body, "request"),
+        response_body_file: body_overflow_file(resp_body, "response"),
+        duration_ms: duration.as_millis() as u64,
+        timestamp_ms: req.timestamp_ms,
+        dest_addr: req.dest_addr,
+        protocol_version: "HTTP/1.1".to_string(),
+        connection_id: connection_label(req.conn_id),
+        pid: std::process::id(),
+        tid: req.tid,
+        comm: process_comm(),
+        tls: req.tls_info,
+        trace_id: req.propagated.map(|ctx| hex(&ctx.trace_id)),
+        span_id: req.propagated.map(|ctx| hex(&ctx.span_id)),
+        timing: TimingMsg {
+            ttfb_ms: req
+                .first_byte_at
+                .map(|at| at.duration_since(req.started_at).as_millis() as u64),
+            ..req.timing
+        },
+        request_size: req.request_size,
+        response_size: req.response_size,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_back_to_back_requests_parse_one_at_a_time() {
+        let first = b"POST /a HTTP/1.1\r\nHost: x\r\nContent-Length: 3\r\n\r\nabc";
+        let mut buf = first.to_vec();
+        buf.extend_from_slice(b"GET /b HTTP/1.1\r\nHost: x\r\n\r\n");
+        let (req, chunked) = try_parse_request(&buf).unwrap();
+        assert_eq!(
+            (req.method.as_str(), req.url.as_str()),
+            ("POST", "http://x/a")
+        );
+        assert_eq!(req.body, b"abc");
+        assert!(chunked.is_none());
+        assert!(try_parse_request(b"GET / HTTP/1.1\r\nHost: x\r\n").is_none());
+    }
+
+    #[test]
+    fn test_chunked_request_is_decoded_as_far_as_buffered() {
+        let buf = b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n";
+        let (_, chunked) = try_parse_request(buf).unwrap();
+        let chunked = chunked.unwrap();
+        assert!(!chunked.done);
+        assert_eq!(chunked.body, b"abc");
+    }
+
+    #[test]
+    fn test_interim_responses() {
+        assert!(is_interim_status(100) && is_interim_status(103));
+        assert!(!is_interim_status(101) && !is_interim_status(200));
+        assert!(is_continue_response(b"HTTP/1.1 100 Continue\r\n\r\n"));
+        assert!(!is_continue_response(b"HTTP/1.1 200 OK\r\n\r\n"));
+        assert!(!is_continue_response(b"HTTP/1.1 10"));
+    }
+
+    #[test]
+    fn test_chunked_response_overrides_content_length() {
+        let head = b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\nTransfer-Encoding: chunked\r\n\r\n";
+        let meta = try_parse_response_headers(&[&head[..], b"0\r\n"].concat()).unwrap();
+        assert_eq!(meta.status_code, 200);
+        assert!(meta.chunked);
+        assert_eq!(meta.content_length, None);
+        assert_eq!(meta.headers_end, head.len());
+
+        let meta =
+            try_parse_response_headers(b"HTTP/1.1 204 No Content\r\nContent-Length: 0\r\n\r\n");
+        assert_eq!(meta.unwrap().content_length, Some(0));
+        assert!(try_parse_response_headers(b"HTTP/1.1 200 OK\r\n").is_none());
+    }
+}
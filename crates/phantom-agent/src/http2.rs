@@ -0,0 +1,786 @@
+//! HTTP/2 frame parsing, HPACK decoding and per-stream/connection state.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::Ordering;
+use std::time::Instant;
+
+use crate::propagate::{TraceContext, hex, take_propagated};
+use crate::{
+    PARSE_ERRORS, TimingMsg, TlsInfo, TraceMsg, body_b64, body_overflow_file, capture_bodies,
+    connection_label, current_tid, emit_msg, max_buf, now_ms, process_comm,
+};
+
+/// HTTP/2 client connection preface (RFC 7540 §3.5).
+pub(crate) const H2_PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+/// Size of an HTTP/2 frame header in bytes.
+pub(crate) const H2_FRAME_HDR_LEN: usize = 9;
+// Frame types we care about.
+pub(crate) const H2_TYPE_DATA: u8 = 0x0;
+pub(crate) const H2_TYPE_HEADERS: u8 = 0x1;
+const H2_TYPE_SETTINGS: u8 = 0x4;
+const H2_TYPE_CONTINUATION: u8 = 0x9;
+// Frame flags.
+const H2_FLAG_ACK: u8 = 0x1;
+pub(crate) const H2_FLAG_END_STREAM: u8 = 0x1;
+pub(crate) const H2_FLAG_END_HEADERS: u8 = 0x4;
+pub(crate) const H2_FLAG_PADDED: u8 = 0x8;
+const H2_FLAG_PRIORITY: u8 = 0x20;
+/// SETTINGS_HEADER_TABLE_SIZE identifier (RFC 7540 §6.5.2).
+const H2_SETTINGS_HEADER_TABLE_SIZE: u16 = 0x1;
+
+/// Parse the 9-byte HTTP/2 frame header.
+/// Returns `(payload_len, frame_type, flags, stream_id)` or `None` if buf is too short.
+pub(crate) fn parse_h2_frame_header(buf: &[u8]) -> Option<(usize, u8, u8, u32)> {
+    if buf.len() < H2_FRAME_HDR_LEN {
+        return None;
+    }
+    let payload_len = ((buf[0] as usize) << 16) | ((buf[1] as usize) << 8) | (buf[2] as usize);
+    let frame_type = buf[3];
+    let flags = buf[4];
+    // Mask off the reserved R bit (bit 31).
+    let stream_id = u32::from_be_bytes([buf[5] & 0x7f, buf[6], buf[7], buf[8]]);
+    Some((payload_len, frame_type, flags, stream_id))
+}
+
+/// Return the `[start, end)` byte range of the header block fragment within a
+/// HEADERS frame payload, stripping optional padding and priority bytes.
+pub(crate) fn h2_header_block_range(payload: &[u8], flags: u8) -> (usize, usize) {
+    let mut start = 0usize;
+    let mut end = payload.len();
+
+    if flags & H2_FLAG_PADDED != 0 {
+        if payload.is_empty() {
+            return (0, 0);
+        }
+        let pad_len = payload[0] as usize;
+        start += 1;
+        end = end.saturating_sub(pad_len);
+    }
+    if flags & H2_FLAG_PRIORITY != 0 {
+        start += 5; // 4 bytes stream dependency + 1 byte weight
+    }
+    if start > end { (0, 0) } else { (start, end) }
+}
+
+/// Per-stream state for a single HTTP/2 request-response pair.
+pub(crate) struct H2Stream {
+    req_method: Option<String>,
+    req_path: Option<String>,
+    req_authority: Option<String>,
+    req_scheme: Option<String>,
+    req_headers: HashMap<String, String>,
+    req_body: Vec<u8>,
+    /// True once we have seen END_STREAM on the request side.
+    req_done: bool,
+    started_at: Instant,
+    timestamp_ms: u64,
+    pub(crate) resp_status: Option<u16>,
+    resp_headers: HashMap<String, String>,
+    /// Header block received after the response headers (HTTP/2 trailers).
+    resp_trailers: HashMap<String, String>,
+    resp_body: Vec<u8>,
+    /// True once we have seen END_STREAM on the response side.
+    resp_done: bool,
+    tls: bool,
+    conn_id: u64,
+    dest_addr: Option<String>,
+    tls_info: Option<TlsInfo>,
+    /// Connection phases, on the connection's first stream.
+    timing: TimingMsg,
+    /// When the response headers arrived.
+    first_byte_at: Option<Instant>,
+    /// Trace context inserted into the request headers.
+    propagated: Option<TraceContext>,
+    /// Bytes of the HEADERS, CONTINUATION and DATA frames sent on the stream.
+    request_size: u64,
+    /// Bytes of those frames received on the stream.
+    response_size: u64,
+    /// Thread that opened the stream.
+    tid: u32,
+}
+
+impl H2Stream {
+    fn new(tls: bool, conn_id: u64, dest_addr: Option<String>, tls_info: Option<TlsInfo>) -> Self {
+        Self {
+            conn_id,
+            dest_addr,
+            tls_info,
+            timing: TimingMsg::default(),
+            first_byte_at: None,
+            propagated: None,
+            request_size: 0,
+            response_size: 0,
+            req_method: None,
+            req_path: None,
+            req_authority: None,
+            req_scheme: None,
+            req_headers: HashMap::new(),
+            req_body: Vec::new(),
+            req_done: false,
+            started_at: Instant::now(),
+            timestamp_ms: now_ms(),
+            resp_status: None,
+            resp_headers: HashMap::new(),
+            resp_trailers: HashMap::new(),
+            resp_body: Vec::new(),
+            resp_done: false,
+            tls,
+            tid: current_tid(),
+        }
+    }
+}
+
+/// Per-connection state for an HTTP/2 connection.
+pub(crate) struct H2ConnState {
+    tls: bool,
+    /// Connection id shared by all streams on this connection.
+    conn_id: u64,
+    /// Destination of a TLS or Unix socket connection, copied into each stream.
+    dest_addr: Option<String>,
+    /// TLS session details, copied into each stream.
+    tls_info: Option<TlsInfo>,
+    /// Connection phases, handed to the first request stream.
+    pub(crate) timing: TimingMsg,
+    /// The application sends its own `traceparent`: none is inserted.
+    pub(crate) app_propagates: bool,
+    /// Buffered outgoing (app→server) bytes not yet consumed into complete frames.
+    pub(crate) send_buf: Vec<u8>,
+    /// Buffered incoming (server→app) bytes not yet consumed into complete frames.
+    pub(crate) recv_buf: Vec<u8>,
+    /// HPACK decoder for client-sent request headers.
+    send_hpack: H2HeaderDecoder,
+    /// HPACK decoder for server-sent response headers.
+    recv_hpack: H2HeaderDecoder,
+    /// Active streams keyed by HTTP/2 stream ID.
+    pub(crate) streams: HashMap<u32, H2Stream>,
+    // CONTINUATION frame accumulation (send direction).
+    pub(crate) send_cont_sid: Option<u32>,
+    send_cont_buf: Vec<u8>,
+    send_cont_end_stream: bool,
+    /// Frame bytes of the header block being accumulated.
+    send_cont_size: u64,
+    // CONTINUATION frame accumulation (recv direction).
+    recv_cont_sid: Option<u32>,
+    recv_cont_buf: Vec<u8>,
+    recv_cont_end_stream: bool,
+    recv_cont_size: u64,
+}
+
+impl H2ConnState {
+    pub(crate) fn new(
+        tls: bool,
+        conn_id: u64,
+        dest_addr: Option<String>,
+        tls_info: Option<TlsInfo>,
+    ) -> Self {
+        Self {
+            tls,
+            conn_id,
+            dest_addr,
+            tls_info,
+            timing: TimingMsg::default(),
+            app_propagates: false,
+            send_buf: Vec::new(),
+            recv_buf: Vec::new(),
+            send_hpack: H2HeaderDecoder::new(),
+            recv_hpack: H2HeaderDecoder::new(),
+            streams: HashMap::new(),
+            send_cont_sid: None,
+            send_cont_buf: Vec::new(),
+            send_cont_end_stream: false,
+            send_cont_size: 0,
+            recv_cont_sid: None,
+            recv_cont_buf: Vec::new(),
+            recv_cont_end_stream: false,
+            recv_cont_size: 0,
+        }
+    }
+}
+
+/// Dynamic table size both sides start with (RFC 7541 §4.2).
+const HPACK_DEFAULT_TABLE_SIZE: usize = 4096;
+
+/// HPACK decoder for one direction of a connection, with the table size
+/// limit the encoder on that side has to keep to.
+struct H2HeaderDecoder {
+    decoder: hpack::Decoder<'static>,
+    /// Dynamic table size the encoder last signalled.
+    table_size: usize,
+    /// Largest size the encoder may signal: the decoding side's
+    /// SETTINGS_HEADER_TABLE_SIZE, once acknowledged.
+    table_limit: usize,
+    /// SETTINGS_HEADER_TABLE_SIZE values sent, oldest first, awaiting ACK.
+    pending_limits: VecDeque<usize>,
+}
+
+impl H2HeaderDecoder {
+    fn new() -> Self {
+        Self {
+            decoder: hpack::Decoder::new(),
+            table_size: HPACK_DEFAULT_TABLE_SIZE,
+            table_limit: HPACK_DEFAULT_TABLE_SIZE,
+            pending_limits: VecDeque::new(),
+        }
+    }
+
+    /// The decoding side sent a SETTINGS frame. Without a table size in it
+    /// the acknowledgement leaves the limit as the SETTINGS before it set it.
+    fn settings_sent(&mut self, table_limit: Option<usize>) {
+        let previous = self.pending_limits.back().copied();
+        let limit = table_limit.or(previous).unwrap_or(self.table_limit);
+        self.pending_limits.push_back(limit);
+    }
+
+    /// The encoder acknowledged the oldest outstanding SETTINGS: from here on
+    /// its table fits the new limit, so entries beyond it are gone.
+    fn settings_acked(&mut self) {
+        let Some(limit) = self.pending_limits.pop_front() else {
+            return;
+        };
+        self.table_limit = limit;
+        if self.table_size > limit {
+            self.set_table_size(limit);
+        }
+    }
+
+    fn set_table_size(&mut self, size: usize) {
+        self.table_size = size;
+        self.decoder.set_max_table_size(size);
+    }
+
+    /// Decodes a header block, first applying the dynamic table size updates
+    /// it starts with. A corrupt block, or an update above the limit, yields
+    /// no headers and is counted as a parse error.
+    fn decode(&mut self, mut block: &[u8]) -> Vec<(Vec<u8>, Vec<u8>)> {
+        // Size updates (`001xxxxx`) may only open a header block (§4.2).
+        while block.first().is_some_and(|b| b & 0xe0 == 0x20) {
+            let Some((size, len)) = hpack_integer(block, 5) else {
+                PARSE_ERRORS.fetch_add(1, Ordering::Relaxed);
+                return Vec::new();
+            };
+            if size > self.table_limit {
+                PARSE_ERRORS.fetch_add(1, Ordering::Relaxed);
+                return Vec::new();
+            }
+            self.set_table_size(size);
+            block = &block[len..];
+        }
+        self.decoder.decode(block).unwrap_or_else(|_| {
+            PARSE_ERRORS.fetch_add(1, Ordering::Relaxed);
+            Vec::new()
+        })
+    }
+}
+
+/// Decodes an HPACK integer with an `prefix_bits`-bit prefix (RFC 7541
+/// §5.1), returning it with the bytes it took.
+fn hpack_integer(buf: &[u8], prefix_bits: u32) -> Option<(usize, usize)> {
+    let max_prefix = (1usize << prefix_bits) - 1;
+    let mut value = (*buf.first()? as usize) & max_prefix;
+    if value < max_prefix {
+        return Some((value, 1));
+    }
+    let mut shift = 0;
+    for (i, &byte) in buf.iter().enumerate().skip(1) {
+        // More than four continuation bytes is beyond any table size.
+        if shift > 28 {
+            return None;
+        }
+        value += ((byte & 0x7f) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+        shift += 7;
+    }
+    None
+}
+
+/// SETTINGS_HEADER_TABLE_SIZE from a SETTINGS frame payload, if present
+/// (the last one wins).
+fn h2_settings_table_size(payload: &[u8]) -> Option<usize> {
+    payload
+        .chunks_exact(6)
+        .filter(|setting| setting[..2] == H2_SETTINGS_HEADER_TABLE_SIZE.to_be_bytes())
+        .map(|setting| {
+            u32::from_be_bytes([setting[2], setting[3], setting[4], setting[5]]) as usize
+        })
+        .next_back()
+}
+
+/// Tracks a SETTINGS frame seen in one direction. Its header table size
+/// bounds the opposite encoder, whose blocks `decoder` reads, once that side
+/// ACKs it. An ACK instead applies the oldest SETTINGS the other side sent,
+/// which bound the encoder `peer` follows.
+fn apply_h2_settings(
+    flags: u8,
+    payload: &[u8],
+    decoder: &mut H2HeaderDecoder,
+    peer: &mut H2HeaderDecoder,
+) {
+    if flags & H2_FLAG_ACK != 0 {
+        peer.settings_acked();
+    } else {
+        decoder.settings_sent(h2_settings_table_size(payload));
+    }
+}
+
+/// Apply decoded HPACK name-value pairs to a stream's request pseudo-headers and
+/// regular headers.
+fn apply_h2_request_headers(stream: &mut H2Stream, headers: Vec<(Vec<u8>, Vec<u8>)>) {
+    for (name, value) in headers {
+        let name = String::from_utf8_lossy(&name).into_owned();
+        let value = String::from_utf8_lossy(&value).into_owned();
+        match name.as_str() {
+            ":method" => stream.req_method = Some(value),
+            ":path" => stream.req_path = Some(value),
+            ":scheme" => stream.req_scheme = Some(value),
+            ":authority" => stream.req_authority = Some(value),
+            n if !n.starts_with(':') => {
+                stream.req_headers.insert(n.to_string(), value);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Apply decoded HPACK name-value pairs to a stream's response pseudo-headers and
+/// regular headers.
+fn apply_h2_response_headers(stream: &mut H2Stream, headers: Vec<(Vec<u8>, Vec<u8>)>) {
+    stream.first_byte_at.get_or_insert_with(Instant::now);
+    // A block without `:status` after a final status is the trailer section.
+    let is_trailers = stream.resp_status.is_some_and(|s| s >= 200)
+        && !headers.iter().any(|(name, _)| name == b":status");
+    if is_trailers {
+        for (name, value) in headers {
+            stream.resp_trailers.insert(
+                String::from_utf8_lossy(&name).into_owned(),
+                String::from_utf8_lossy(&value).into_owned(),
+            );
+        }
+        return;
+    }
+    for (name, value) in headers {
+        let name = String::from_utf8_lossy(&name).into_owned();
+        let value = String::from_utf8_lossy(&value).into_owned();
+        if name == ":status" {
+            stream.resp_status = value.parse().ok();
+        } else if !name.starts_with(':') {
+            stream.resp_headers.insert(name, value);
+        }
+    }
+}
+
+/// Process all complete HTTP/2 frames in `h2.send_buf` (outgoing / request side).
+pub(crate) fn process_h2_send_frames(h2: &mut H2ConnState) {
+    // Skip the 24-byte client connection preface if present at the start.
+    if h2.send_buf.starts_with(H2_PREFACE) {
+        h2.send_buf.drain(..H2_PREFACE.len());
+    }
+
+    // Walk frames in place and drain the consumed prefix once at the end;
+    // the buffer is moved out so stream state can be updated meanwhile.
+    let mut buf = std::mem::take(&mut h2.send_buf);
+    let mut pos = 0;
+    while let Some((payload_len, frame_type, flags, stream_id)) = parse_h2_frame_header(&buf[pos..])
+    {
+        let total = H2_FRAME_HDR_LEN + payload_len;
+        if buf.len() - pos < total {
+            break; // Frame not yet fully buffered.
+        }
+        let payload = &buf[pos + H2_FRAME_HDR_LEN..pos + total];
+        pos += total;
+
+        let tls = h2.tls;
+        let conn_id = h2.conn_id;
+        match frame_type {
+            H2_TYPE_HEADERS if stream_id > 0 => {
+                let end_stream = flags & H2_FLAG_END_STREAM != 0;
+                let end_headers = flags & H2_FLAG_END_HEADERS != 0;
+                let (hb_start, hb_end) = h2_header_block_range(payload, flags);
+                let hblock = &payload[hb_start..hb_end];
+
+                if end_headers {
+                    let decoded = h2.send_hpack.decode(hblock);
+                    let stream = h2.streams.entry(stream_id).or_insert_with(|| {
+                        H2Stream::new(tls, conn_id, h2.dest_addr.clone(), h2.tls_info.clone())
+                    });
+                    if stream.timing.is_empty() {
+                        stream.timing = std::mem::take(&mut h2.timing);
+                    }
+                    if let Some(ctx) = take_propagated(stream_id) {
+                        stream.propagated = Some(ctx);
+                    }
+                    apply_h2_request_headers(stream, decoded);
+                    h2.app_propagates |= stream.propagated.is_none()
+                        && stream.req_headers.contains_key("traceparent");
+                    stream.req_done |= end_stream;
+                    stream.request_size += total as u64;
+                } else {
+                    // Header block continues in CONTINUATION frames.
+                    h2.send_cont_sid = Some(stream_id);
+                    h2.send_cont_buf = hblock.to_vec();
+                    h2.send_cont_end_stream = end_stream;
+                    h2.send_cont_size = total as u64;
+                }
+            }
+            H2_TYPE_DATA if stream_id > 0 => {
+                let end_stream = flags & H2_FLAG_END_STREAM != 0;
+                // Strip padding.
+                let (data_start, data_end) = if flags & H2_FLAG_PADDED != 0 && !payload.is_empty() {
+                    let pad = payload[0] as usize;
+                    (1, payload.len().saturating_sub(pad))
+                } else {
+                    (0, payload.len())
+                };
+                if let Some(stream) = h2.streams.get_mut(&stream_id) {
+                    stream.request_size += total as u64;
+                    if capture_bodies() && stream.req_body.len() < max_buf() {
+                        stream
+                            .req_body
+                            .extend_from_slice(&payload[data_start..data_end]);
+                    }
+                    stream.req_done |= end_stream;
+                }
+            }
+            H2_TYPE_CONTINUATION if stream_id > 0 && h2.send_cont_sid == Some(stream_id) => {
+                h2.send_cont_buf.extend_from_slice(payload);
+                h2.send_cont_size += total as u64;
+                if flags & H2_FLAG_END_HEADERS != 0 {
+                    let hblock = std::mem::take(&mut h2.send_cont_buf);
+                    let decoded = h2.send_hpack.decode(&hblock);
+                    let end_stream = h2.send_cont_end_stream;
+                    let stream = h2.streams.entry(stream_id).or_insert_with(|| {
+                        H2Stream::new(tls, conn_id, h2.dest_addr.clone(), h2.tls_info.clone())
+                    });
+                    if stream.timing.is_empty() {
+                        stream.timing = std::mem::take(&mut h2.timing);
+                    }
+                    if let Some(ctx) = take_propagated(stream_id) {
+                        stream.propagated = Some(ctx);
+                    }
+                    apply_h2_request_headers(stream, decoded);
+                    h2.app_propagates |= stream.propagated.is_none()
+                        && stream.req_headers.contains_key("traceparent");
+                    stream.req_done |= end_stream;
+                    stream.request_size += std::mem::take(&mut h2.send_cont_size);
+                    h2.send_cont_sid = None;
+                    h2.send_cont_end_stream = false;
+                }
+            }
+            // Client SETTINGS bound the server's encoder, which the
+            // response decoder follows; a client ACK accepts server ones.
+            H2_TYPE_SETTINGS if stream_id == 0 => {
+                apply_h2_settings(flags, payload, &mut h2.recv_hpack, &mut h2.send_hpack);
+            }
+            _ => {} // WINDOW_UPDATE, PING, GOAWAY, etc. — ignore.
+        }
+    }
+    buf.drain(..pos);
+    h2.send_buf = buf;
+}
+
+/// Process all complete HTTP/2 frames in `h2.recv_buf` (incoming / response side).
+pub(crate) fn process_h2_recv_frames(h2: &mut H2ConnState) {
+    let mut buf = std::mem::take(&mut h2.recv_buf);
+    let mut pos = 0;
+    while let Some((payload_len, frame_type, flags, stream_id)) = parse_h2_frame_header(&buf[pos..])
+    {
+        let total = H2_FRAME_HDR_LEN + payload_len;
+        if buf.len() - pos < total {
+            break;
+        }
+        let payload = &buf[pos + H2_FRAME_HDR_LEN..pos + total];
+        pos += total;
+
+        let tls = h2.tls;
+        let conn_id = h2.conn_id;
+        match frame_type {
+            H2_TYPE_HEADERS if stream_id > 0 => {
+                let end_stream = flags & H2_FLAG_END_STREAM != 0;
+                let end_headers = flags & H2_FLAG_END_HEADERS != 0;
+                let (hb_start, hb_end) = h2_header_block_range(payload, flags);
+                let hblock = &payload[hb_start..hb_end];
+
+                if end_headers {
+                    let decoded = h2.recv_hpack.decode(hblock);
+                    let stream = h2.streams.entry(stream_id).or_insert_with(|| {
+                        H2Stream::new(tls, conn_id, h2.dest_addr.clone(), h2.tls_info.clone())
+                    });
+                    apply_h2_response_headers(stream, decoded);
+                    stream.resp_done |= end_stream;
+                    stream.response_size += total as u64;
+                } else {
+                    h2.recv_cont_sid = Some(stream_id);
+                    h2.recv_cont_buf = hblock.to_vec();
+                    h2.recv_cont_end_stream = end_stream;
+                    h2.recv_cont_size = total as u64;
+                }
+            }
+            H2_TYPE_DATA if stream_id > 0 => {
+                let end_stream = flags & H2_FLAG_END_STREAM != 0;
+                let (data_start, data_end) = if flags & H2_FLAG_PADDED != 0 && !payload.is_empty() {
+                    let pad = payload[0] as usize;
+                    (1, payload.len().saturating_sub(pad))
+                } else {
+                    (0, payload.len())
+                };
+                if let Some(stream) = h2.streams.get_mut(&stream_id) {
+                    stream.response_size += total as u64;
+                    if capture_bodies() && stream.resp_body.len() < max_buf() {
+                        stream
+                            .resp_body
+                            .extend_from_slice(&payload[data_start..data_end]);
+                    }
+                    stream.resp_done |= end_stream;
+                }
+            }
+            H2_TYPE_CONTINUATION if stream_id > 0 && h2.recv_cont_sid == Some(stream_id) => {
+                h2.recv_cont_buf.extend_from_slice(payload);
+                h2.recv_cont_size += total as u64;
+                if flags & H2_FLAG_END_HEADERS != 0 {
+                    let hblock = std::mem::take(&mut h2.recv_cont_buf);
+                    let decoded = h2.recv_hpack.decode(&hblock);
+                    let end_stream = h2.recv_cont_end_stream;
+                    let stream = h2.streams.entry(stream_id).or_insert_with(|| {
+                        H2Stream::new(tls, conn_id, h2.dest_addr.clone(), h2.tls_info.clone())
+                    });
+                    apply_h2_response_headers(stream, decoded);
+                    stream.resp_done |= end_stream;
+                    stream.response_size += std::mem::take(&mut h2.recv_cont_size);
+                    h2.recv_cont_sid = None;
+                    h2.recv_cont_end_stream = false;
+                }
+            }
+            H2_TYPE_SETTINGS if stream_id == 0 => {
+                apply_h2_settings(flags, payload, &mut h2.send_hpack, &mut h2.recv_hpack);
+            }
+            _ => {}
+        }
+    }
+    buf.drain(..pos);
+    h2.recv_buf = buf;
+}
+
+/// Remove and return all streams that have a complete response (status + END_STREAM).
+pub(crate) fn drain_completed_h2_streams(h2: &mut H2ConnState) -> Vec<H2Stream> {
+    let done_ids: Vec<u32> = h2
+        .streams
+        .iter()
+        .filter(|(_, s)| s.resp_status.is_some() && s.resp_done)
+        .map(|(id, _)| *id)
+        .collect();
+    let mut completed = Vec::with_capacity(done_ids.len());
+    for id in done_ids {
+        if let Some(stream) = h2.streams.remove(&id) {
+            completed.push(stream);
+        }
+    }
+    completed
+}
+
+/// Build and send a `TraceMsg` for a completed HTTP/2 stream.
+pub(crate) fn emit_h2_stream(stream: H2Stream) {
+    let method = stream.req_method.unwrap_or_else(|| "GET".to_string());
+    let path = stream.req_path.unwrap_or_else(|| "/".to_string());
+    let authority = stream.req_authority.unwrap_or_default();
+    let scheme = stream
+        .req_scheme
+        .unwrap_or_else(|| if stream.tls { "https" } else { "http" }.to_string());
+    let url = format!("{scheme}://{authority}{path}");
+    let status_code = stream.resp_status.unwrap_or(0);
+    let duration = stream.started_at.elapsed();
+
+    emit_msg(TraceMsg {
+        method,
+        url,
+        status_code,
+        request_headers: stream.req_headers,
+        response_headers: stream.resp_headers,
+        response_trailers: stream.resp_trailers,
+        request_body_b64: body_b64(&stream.req_body),
+        response_body_b64: body_b64(&stream.resp_body),
+        request_body_file: body_overflow_file(&stream.req_body, "request"),
+        response_body_file: body_overflow_file(&stream.resp_body, "response"),
+        duration_ms: duration.as_millis() as u64,
+        timestamp_ms: stream.timestamp_ms,
+        dest_addr: stream.dest_addr,
+        protocol_version: "HTTP/2".to_string(),
+        connection_id: connection_label(stream.conn_id),
+        pid: std::process::id(),
+        tid: stream.tid,
+        comm: process_comm(),
+        tls: stream.tls_info,
+        trace_id: stream.propagated.map(|ctx| hex(&ctx.trace_id)),
+        span_id: stream.propagated.map(|ctx| hex(&ctx.span_id)),
+        timing: TimingMsg {
+            ttfb_ms: stream
+                .first_byte_at
+                .map(|at| at.duration_since(stream.started_at).as_millis() as u64),
+            ..stream.timing
+        },
+        request_size: stream.request_size,
+        response_size: stream.response_size,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(frame_type: u8, flags: u8, stream_id: u32, payload: &[u8]) -> Vec<u8> {
+        let mut out = (payload.len() as u32).to_be_bytes()[1..].to_vec();
+        out.extend_from_slice(&[frame_type, flags]);
+        out.extend_from_slice(&stream_id.to_be_bytes());
+        out.extend_from_slice(payload);
+        out
+    }
+
+    fn table_size_setting(size: u32) -> Vec<u8> {
+        let mut out = H2_SETTINGS_HEADER_TABLE_SIZE.to_be_bytes().to_vec();
+        out.extend_from_slice(&size.to_be_bytes());
+        out
+    }
+
+    fn encode(headers: &[(&str, &str)]) -> Vec<u8> {
+        hpack::Encoder::new().encode(headers.iter().map(|(n, v)| (n.as_bytes(), v.as_bytes())))
+    }
+
+    #[test]
+    fn test_hpack_integer() {
+        assert_eq!(hpack_integer(&[0x2a], 5), Some((10, 1)));
+        // RFC 7541 §C.1.2: 1337 with a 5-bit prefix.
+        assert_eq!(hpack_integer(&[0x1f, 0x9a, 0x0a], 5), Some((1337, 3)));
+        assert_eq!(hpack_integer(&[0x1f, 0x9a], 5), None);
+        assert_eq!(
+            hpack_integer(&[0x1f, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01], 5),
+            None
+        );
+        assert_eq!(hpack_integer(&[], 5), None);
+    }
+
+    #[test]
+    fn test_settings_table_size_last_one_wins() {
+        let mut payload = table_size_setting(100);
+        payload.extend_from_slice(&[0x00, 0x03, 0, 0, 0, 100]); // MAX_CONCURRENT_STREAMS
+        payload.extend_from_slice(&table_size_setting(0));
+        assert_eq!(h2_settings_table_size(&payload), Some(0));
+        assert_eq!(h2_settings_table_size(&[0x00, 0x03, 0, 0, 0, 100]), None);
+    }
+
+    #[test]
+    fn test_frame_header_and_header_block_range() {
+        let header = frame(H2_TYPE_HEADERS, H2_FLAG_END_HEADERS, 0x8000_0003, &[0; 5]);
+        assert_eq!(
+            parse_h2_frame_header(&header),
+            Some((5, H2_TYPE_HEADERS, H2_FLAG_END_HEADERS, 3))
+        );
+        assert_eq!(parse_h2_frame_header(&header[..8]), None);
+
+        // Pad length, priority, block, padding.
+        let payload = [2, 0, 0, 0, 1, 16, 0xaa, 0xbb, 0, 0];
+        let flags = H2_FLAG_PADDED | H2_FLAG_PRIORITY;
+        assert_eq!(h2_header_block_range(&payload, flags), (6, 8));
+        assert_eq!(h2_header_block_range(&[9, 0], H2_FLAG_PADDED), (0, 0));
+        assert_eq!(h2_header_block_range(&[], H2_FLAG_PADDED), (0, 0));
+    }
+
+    #[test]
+    fn test_table_limit_applies_once_acked() {
+        let mut decoder = H2HeaderDecoder::new();
+        let status = encode(&[(":status", "200")]);
+        decoder.settings_sent(Some(0));
+        // Before the ACK the encoder may still use the old limit.
+        let mut block = vec![0x3f, 0xe1, 0x1f]; // size update to 4096
+        block.extend_from_slice(&status);
+        assert_eq!(decoder.decode(&block).len(), 1);
+
+        decoder.settings_acked();
+        assert_eq!((decoder.table_limit, decoder.table_size), (0, 0));
+        assert!(decoder.decode(&block).is_empty());
+        let mut block = vec![0x20]; // size update to 0
+        block.extend_from_slice(&status);
+        assert_eq!(decoder.decode(&block).len(), 1);
+    }
+
+    #[test]
+    fn test_settings_without_table_size_keep_the_limit() {
+        let mut decoder = H2HeaderDecoder::new();
+        let mut peer = H2HeaderDecoder::new();
+        apply_h2_settings(0, &table_size_setting(256), &mut decoder, &mut peer);
+        apply_h2_settings(0, &[], &mut decoder, &mut peer);
+        // An ACK applies the other side's SETTINGS to `peer`.
+        apply_h2_settings(H2_FLAG_ACK, &[], &mut peer, &mut decoder);
+        assert_eq!(decoder.table_limit, 256);
+        apply_h2_settings(H2_FLAG_ACK, &[], &mut peer, &mut decoder);
+        assert_eq!(decoder.table_limit, 256);
+        assert!(decoder.pending_limits.is_empty());
+        assert_eq!(peer.table_limit, HPACK_DEFAULT_TABLE_SIZE);
+    }
+
+    #[test]
+    fn test_request_and_response_frames_complete_a_stream() {
+        let mut h2 = H2ConnState::new(true, 1, None, None);
+        let request = encode(&[
+            (":method", "POST"),
+            (":scheme", "https"),
+            (":authority", "api.example.com"),
+            (":path", "/v1/items"),
+            ("content-type", "application/json"),
+        ]);
+        let (first, rest) = request.split_at(10);
+        let mut send = H2_PREFACE.to_vec();
+        send.extend(frame(H2_TYPE_HEADERS, 0, 1, first));
+        send.extend(frame(H2_TYPE_CONTINUATION, H2_FLAG_END_HEADERS, 1, rest));
+        send.extend(frame(H2_TYPE_DATA, H2_FLAG_END_STREAM, 1, b"{}"));
+        // Split mid-frame: the tail waits in the buffer.
+        h2.send_buf.extend_from_slice(&send[..send.len() - 1]);
+        process_h2_send_frames(&mut h2);
+        assert!(!h2.send_buf.is_empty());
+        h2.send_buf.extend_from_slice(&send[send.len() - 1..]);
+        process_h2_send_frames(&mut h2);
+        assert!(h2.send_buf.is_empty());
+
+        let stream = &h2.streams[&1];
+        assert_eq!(stream.req_method.as_deref(), Some("POST"));
+        assert_eq!(stream.req_path.as_deref(), Some("/v1/items"));
+        assert_eq!(stream.req_headers["content-type"], "application/json");
+        assert!(stream.req_done);
+
+        h2.recv_buf = frame(
+            H2_TYPE_HEADERS,
+            H2_FLAG_END_HEADERS,
+            1,
+            &encode(&[(":status", "201")]),
+        );
+        process_h2_recv_frames(&mut h2);
+        assert!(drain_completed_h2_streams(&mut h2).is_empty());
+        h2.recv_buf = frame(H2_TYPE_DATA, H2_FLAG_END_STREAM, 1, b"ok");
+        h2.recv_buf.extend(frame(
+            H2_TYPE_HEADERS,
+            H2_FLAG_END_HEADERS | H2_FLAG_END_STREAM,
+            3,
+            &encode(&[(":status", "204")]),
+        ));
+        process_h2_recv_frames(&mut h2);
+
+        let mut done = drain_completed_h2_streams(&mut h2);
+        done.sort_by_key(|s| s.resp_status);
+        assert_eq!(done.len(), 2);
+        assert_eq!(done[0].resp_status, Some(201));
+        assert_eq!(done[0].request_size, (send.len() - H2_PREFACE.len()) as u64);
+        assert_eq!(done[1].resp_status, Some(204));
+        assert!(h2.streams.is_empty());
+    }
+
+    #[test]
+    fn test_server_settings_bound_the_request_decoder_after_ack() {
+        let mut h2 = H2ConnState::new(false, 1, None, None);
+        h2.recv_buf = frame(H2_TYPE_SETTINGS, 0, 0, &table_size_setting(0));
+        process_h2_recv_frames(&mut h2);
+        assert_eq!(h2.send_hpack.table_limit, HPACK_DEFAULT_TABLE_SIZE);
+        h2.send_buf = frame(H2_TYPE_SETTINGS, H2_FLAG_ACK, 0, &[]);
+        process_h2_send_frames(&mut h2);
+        assert_eq!(h2.send_hpack.table_limit, 0);
+        assert_eq!(h2.recv_hpack.table_limit, HPACK_DEFAULT_TABLE_SIZE);
+    }
+}
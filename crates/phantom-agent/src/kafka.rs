@@ -0,0 +1,813 @@
+//! Kafka wire protocol request/response pairs.
+//!
+//! Connections are recognised by a plausible request header sent to the Kafka
+//! port, 9092 unless `PHANTOM_KAFKA_PORT` says otherwise. Every message is
+//! `size:i32 | payload`; requests start with `api_key:i16 | api_version:i16 |
+//! correlation_id:i32 | client_id`, responses with the `correlation_id` they
+//! answer. Only the first `KAFKA_MAX_HEAD` bytes of a message are kept, which
+//! is enough for topic names and error codes; record batches are skipped.
+//! Produce, Fetch and Metadata bodies are decoded for topics and errors.
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use std::time::Instant;
+
+use crate::{
+    FdState, MAX_BODY_DEFAULT, Shard, config_var, connection_peer, max_buf, now_ms, read_be_u32,
+};
+
+const KAFKA_DEFAULT_PORT: u16 = 9092;
+/// Leading bytes of each message kept for decoding.
+const KAFKA_MAX_HEAD: usize = MAX_BODY_DEFAULT;
+/// Largest message accepted (the broker's default `socket.request.max.bytes`).
+const KAFKA_MAX_MESSAGE: u32 = 100 * 1024 * 1024;
+/// Highest api_key accepted when probing a new connection.
+const KAFKA_MAX_API_KEY: i16 = 100;
+/// Maximum in-flight requests tracked per connection.
+const KAFKA_MAX_PENDING: usize = 1024;
+/// Topic names reported per request.
+const KAFKA_MAX_TOPICS: usize = 32;
+
+const KAFKA_PRODUCE: i16 = 0;
+const KAFKA_FETCH: i16 = 1;
+const KAFKA_METADATA: i16 = 3;
+const KAFKA_API_VERSIONS: i16 = 18;
+
+static KAFKA_PORT: OnceLock<u16> = OnceLock::new();
+
+fn kafka_port() -> u16 {
+    *KAFKA_PORT.get_or_init(|| {
+        config_var("PHANTOM_KAFKA_PORT")
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(KAFKA_DEFAULT_PORT)
+    })
+}
+
+fn kafka_api_name(api_key: i16) -> String {
+    const NAMES: &[&str] = &[
+        "Produce",
+        "Fetch",
+        "ListOffsets",
+        "Metadata",
+        "LeaderAndIsr",
+        "StopReplica",
+        "UpdateMetadata",
+        "ControlledShutdown",
+        "OffsetCommit",
+        "OffsetFetch",
+        "FindCoordinator",
+        "JoinGroup",
+        "Heartbeat",
+        "LeaveGroup",
+        "SyncGroup",
+        "DescribeGroups",
+        "ListGroups",
+        "SaslHandshake",
+        "ApiVersions",
+        "CreateTopics",
+        "DeleteTopics",
+        "DeleteRecords",
+        "InitProducerId",
+        "OffsetForLeaderEpoch",
+        "AddPartitionsToTxn",
+        "AddOffsetsToTxn",
+        "EndTxn",
+        "WriteTxnMarkers",
+        "TxnOffsetCommit",
+        "DescribeAcls",
+        "CreateAcls",
+        "DeleteAcls",
+        "DescribeConfigs",
+        "AlterConfigs",
+        "AlterReplicaLogDirs",
+        "DescribeLogDirs",
+        "SaslAuthenticate",
+        "CreatePartitions",
+        "CreateDelegationToken",
+        "RenewDelegationToken",
+        "ExpireDelegationToken",
+        "DescribeDelegationToken",
+        "DeleteGroups",
+        "ElectLeaders",
+        "IncrementalAlterConfigs",
+        "AlterPartitionReassignments",
+        "ListPartitionReassignments",
+        "OffsetDelete",
+    ];
+    usize::try_from(api_key)
+        .ok()
+        .and_then(|i| NAMES.get(i))
+        .map_or_else(|| format!("ApiKey({api_key})"), |name| name.to_string())
+}
+
+/// True when `api_version` of `api_key` uses the flexible (KIP-482) encoding:
+/// compact strings/arrays and tagged fields. Only known for the APIs whose
+/// bodies are decoded.
+fn kafka_flexible(api_key: i16, api_version: i16) -> bool {
+    match api_key {
+        KAFKA_PRODUCE => api_version >= 9,
+        KAFKA_FETCH => api_version >= 12,
+        KAFKA_METADATA => api_version >= 9,
+        KAFKA_API_VERSIONS => api_version >= 3,
+        _ => false,
+    }
+}
+
+/// Cheap check for a request header, so that only likely Kafka connections
+/// pay for a `getpeername`.
+fn looks_like_kafka_request(data: &[u8]) -> bool {
+    let (Some(size), Some(api_key), Some(api_version)) = (
+        read_be_u32(data, 0),
+        data.get(4..6).map(|b| i16::from_be_bytes([b[0], b[1]])),
+        data.get(6..8).map(|b| i16::from_be_bytes([b[0], b[1]])),
+    ) else {
+        return false;
+    };
+    (10..=KAFKA_MAX_MESSAGE).contains(&size)
+        && (0..=KAFKA_MAX_API_KEY).contains(&api_key)
+        && (0..=50).contains(&api_version)
+}
+
+/// One direction of a Kafka connection: splits size-prefixed messages,
+/// keeping the head of each and skipping the rest.
+#[derive(Default)]
+struct KafkaStream {
+    buf: Vec<u8>,
+    /// Bytes of the current message still to be discarded.
+    skip: usize,
+}
+
+impl KafkaStream {
+    /// Appends `data` and returns the head of every message it completes
+    /// (or whose head it completes), or `None` for invalid framing.
+    fn feed(&mut self, mut data: &[u8]) -> Option<Vec<Vec<u8>>> {
+        if self.skip > 0 {
+            let n = self.skip.min(data.len());
+            self.skip -= n;
+            data = &data[n..];
+        }
+        if self.buf.len() + data.len() > max_buf() {
+            return None;
+        }
+        self.buf.extend_from_slice(data);
+
+        let mut out = Vec::new();
+        let mut pos = 0;
+        while let Some(size) = read_be_u32(&self.buf, pos) {
+            if size > KAFKA_MAX_MESSAGE {
+                return None;
+            }
+            let body = pos + 4;
+            let end = body + size as usize;
+            let want = (size as usize).min(KAFKA_MAX_HEAD);
+            let Some(head) = self.buf.get(body..body + want) else {
+                break;
+            };
+            out.push(head.to_vec());
+            if self.buf.len() >= end {
+                pos = end;
+            } else {
+                self.skip = end - self.buf.len();
+                pos = self.buf.len();
+            }
+        }
+        self.buf.drain(..pos);
+        Some(out)
+    }
+}
+
+/// Cursor over a Kafka message in either the classic or flexible encoding.
+/// Every read returns `None` once the (possibly truncated) buffer runs out.
+struct KafkaReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    flexible: bool,
+}
+
+impl<'a> KafkaReader<'a> {
+    fn new(buf: &'a [u8], flexible: bool) -> Self {
+        Self {
+            buf,
+            pos: 0,
+            flexible,
+        }
+    }
+
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        let bytes = self.buf.get(self.pos..self.pos.checked_add(n)?)?;
+        self.pos += n;
+        Some(bytes)
+    }
+
+    fn i16(&mut self) -> Option<i16> {
+        Some(i16::from_be_bytes(self.take(2)?.try_into().ok()?))
+    }
+
+    fn i32(&mut self) -> Option<i32> {
+        Some(i32::from_be_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn uvarint(&mut self) -> Option<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let b = *self.take(1)?.first()?;
+            value |= u64::from(b & 0x7f) << shift;
+            if b & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    /// Length of a string, bytes or array field; `None` inside for null.
+    fn field_len(&mut self, classic: Option<i64>) -> Option<Option<usize>> {
+        let len = match classic {
+            Some(len) => len,
+            None => self.uvarint()? as i64 - 1,
+        };
+        Some(usize::try_from(len).ok())
+    }
+
+    fn string(&mut self) -> Option<Option<String>> {
+        let classic = if self.flexible {
+            None
+        } else {
+            Some(i64::from(self.i16()?))
+        };
+        match self.field_len(classic)? {
+            Some(n) => Some(Some(String::from_utf8_lossy(self.take(n)?).into_owned())),
+            None => Some(None),
+        }
+    }
+
+    fn skip_bytes(&mut self) -> Option<()> {
+        let classic = if self.flexible {
+            None
+        } else {
+            Some(i64::from(self.i32()?))
+        };
+        if let Some(n) = self.field_len(classic)? {
+            self.take(n)?;
+        }
+        Some(())
+    }
+
+    /// Element count of an array; a null array counts as empty.
+    fn array(&mut self) -> Option<usize> {
+        let classic = if self.flexible {
+            None
+        } else {
+            Some(i64::from(self.i32()?))
+        };
+        Some(self.field_len(classic)?.unwrap_or(0))
+    }
+
+    fn skip_tagged_fields(&mut self) -> Option<()> {
+        if self.flexible {
+            for _ in 0..self.uvarint()? {
+                self.uvarint()?;
+                let size = usize::try_from(self.uvarint()?).ok()?;
+                self.take(size)?;
+            }
+        }
+        Some(())
+    }
+}
+
+struct KafkaPending {
+    api_key: i16,
+    api_version: i16,
+    client_id: Option<String>,
+    topics: Vec<String>,
+    started_at: Instant,
+    timestamp_ms: u64,
+}
+
+#[derive(serde::Serialize)]
+pub(crate) struct KafkaTraceMsg {
+    kind: &'static str,
+    api_key: i16,
+    api: String,
+    api_version: i16,
+    correlation_id: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_id: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    topics: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) error_code: Option<i16>,
+    /// Produce with `acks=0`: the broker sends no response.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    no_response: bool,
+    pub(crate) dest_addr: String,
+    duration_ms: u64,
+    timestamp_ms: u64,
+}
+
+/// Topic names of a Produce request body, and whether it asked for acks.
+fn kafka_produce_topics(
+    r: &mut KafkaReader,
+    version: i16,
+    topics: &mut Vec<String>,
+) -> Option<bool> {
+    if version >= 3 {
+        r.string()?; // transactional_id
+    }
+    let acks = r.i16()?;
+    r.i32()?; // timeout_ms
+    for _ in 0..r.array()?.min(KAFKA_MAX_TOPICS) {
+        topics.extend(r.string()?);
+        // Partition record batches usually run past the kept head.
+        for _ in 0..r.array()? {
+            r.i32()?; // index
+            r.skip_bytes()?; // records
+            r.skip_tagged_fields()?;
+        }
+        r.skip_tagged_fields()?;
+    }
+    Some(acks != 0)
+}
+
+/// Topic names of a Fetch request body (v13+ names topics by ID only).
+fn kafka_fetch_topics(r: &mut KafkaReader, version: i16, topics: &mut Vec<String>) -> Option<()> {
+    if version < 15 {
+        r.i32()?; // replica_id
+    }
+    r.take(8)?; // max_wait_ms, min_bytes
+    if version >= 3 {
+        r.i32()?; // max_bytes
+    }
+    if version >= 4 {
+        r.take(1)?; // isolation_level
+    }
+    if version >= 7 {
+        r.take(8)?; // session_id, session_epoch
+    }
+    for _ in 0..r.array()?.min(KAFKA_MAX_TOPICS) {
+        if version >= 13 {
+            r.take(16)?; // topic_id
+        } else {
+            topics.extend(r.string()?);
+        }
+        for _ in 0..r.array()? {
+            r.take(4)?; // partition
+            if version >= 9 {
+                r.take(4)?; // current_leader_epoch
+            }
+            r.take(8)?; // fetch_offset
+            if version >= 12 {
+                r.take(4)?; // last_fetched_epoch
+            }
+            if version >= 5 {
+                r.take(8)?; // log_start_offset
+            }
+            r.take(4)?; // partition_max_bytes
+            r.skip_tagged_fields()?;
+        }
+        r.skip_tagged_fields()?;
+    }
+    Some(())
+}
+
+/// Topic names of a Metadata request body (none means all topics).
+fn kafka_metadata_topics(
+    r: &mut KafkaReader,
+    version: i16,
+    topics: &mut Vec<String>,
+) -> Option<()> {
+    for _ in 0..r.array()?.min(KAFKA_MAX_TOPICS) {
+        if version >= 10 {
+            r.take(16)?; // topic_id
+        }
+        topics.extend(r.string()?);
+        r.skip_tagged_fields()?;
+    }
+    Some(())
+}
+
+/// First non-zero error code of a Produce, Fetch or Metadata response body.
+fn kafka_response_error(r: &mut KafkaReader, api_key: i16, version: i16) -> Option<i16> {
+    let nonzero = |code: i16| (code != 0).then_some(code);
+    match api_key {
+        KAFKA_PRODUCE => {
+            for _ in 0..r.array()? {
+                r.string()?; // name
+                let partitions = r.array()?;
+                if partitions > 0 {
+                    r.i32()?; // index
+                    // Partitions carry variable-length record errors after
+                    // their code, so only the first one is read.
+                    return nonzero(r.i16()?);
+                }
+                r.skip_tagged_fields()?;
+            }
+            None
+        }
+        KAFKA_FETCH => {
+            if version >= 1 {
+                r.i32()?; // throttle_time_ms
+            }
+            if version >= 7 {
+                if let Some(code) = nonzero(r.i16()?) {
+                    return Some(code);
+                }
+                r.i32()?; // session_id
+            }
+            if r.array()? > 0 {
+                if version >= 13 {
+                    r.take(16)?; // topic_id
+                } else {
+                    r.string()?;
+                }
+                if r.array()? > 0 {
+                    r.i32()?; // partition_index
+                    return nonzero(r.i16()?);
+                }
+            }
+            None
+        }
+        KAFKA_METADATA => {
+            if version >= 3 {
+                r.i32()?; // throttle_time_ms
+            }
+            for _ in 0..r.array()? {
+                r.i32()?; // node_id
+                r.string()?; // host
+                r.i32()?; // port
+                if version >= 1 {
+                    r.string()?; // rack
+                }
+                r.skip_tagged_fields()?;
+            }
+            if version >= 2 {
+                r.string()?; // cluster_id
+            }
+            if version >= 1 {
+                r.i32()?; // controller_id
+            }
+            // Each topic starts with its error code; the rest varies by
+            // version, so only the first topic is checked.
+            if r.array()? > 0 {
+                return nonzero(r.i16()?);
+            }
+            None
+        }
+        _ => None,
+    }
+}
+
+/// Per-connection state for a Kafka client connection.
+pub(crate) struct KafkaConnState {
+    send: KafkaStream,
+    recv: KafkaStream,
+    dest_addr: String,
+    /// In-flight requests by correlation ID.
+    pending: HashMap<i32, KafkaPending>,
+}
+
+impl KafkaConnState {
+    fn new(dest_addr: String) -> Self {
+        Self {
+            send: KafkaStream::default(),
+            recv: KafkaStream::default(),
+            dest_addr,
+            pending: HashMap::new(),
+        }
+    }
+
+    fn finish(
+        &self,
+        correlation_id: i32,
+        req: KafkaPending,
+        error_code: Option<i16>,
+        no_response: bool,
+    ) -> KafkaTraceMsg {
+        KafkaTraceMsg {
+            kind: "kafka",
+            api_key: req.api_key,
+            api: kafka_api_name(req.api_key),
+            api_version: req.api_version,
+            correlation_id,
+            client_id: req.client_id,
+            topics: req.topics,
+            error_code,
+            no_response,
+            dest_addr: self.dest_addr.clone(),
+            duration_ms: req.started_at.elapsed().as_millis() as u64,
+            timestamp_ms: req.timestamp_ms,
+        }
+    }
+
+    /// Client → server bytes. Returns requests that expect no response, or
+    /// `None` when tracking should stop.
+    fn on_send(&mut self, data: &[u8]) -> Option<Vec<KafkaTraceMsg>> {
+        let mut done = Vec::new();
+        for msg in self.send.feed(data)? {
+            let mut r = KafkaReader::new(&msg, false);
+            let (Some(api_key), Some(api_version), Some(correlation_id)) =
+                (r.i16(), r.i16(), r.i32())
+            else {
+                return None;
+            };
+            // The header's client_id is a classic string even in flexible versions.
+            let client_id = r.string().flatten();
+            r.flexible = kafka_flexible(api_key, api_version);
+            let mut topics = Vec::new();
+            let mut acks = true;
+            if r.skip_tagged_fields().is_some() {
+                match api_key {
+                    KAFKA_PRODUCE => {
+                        acks =
+                            kafka_produce_topics(&mut r, api_version, &mut topics).unwrap_or(true);
+                    }
+                    KAFKA_FETCH => {
+                        kafka_fetch_topics(&mut r, api_version, &mut topics);
+                    }
+                    KAFKA_METADATA => {
+                        kafka_metadata_topics(&mut r, api_version, &mut topics);
+                    }
+                    _ => {}
+                }
+            }
+            let req = KafkaPending {
+                api_key,
+                api_version,
+                client_id,
+                topics,
+                started_at: Instant::now(),
+                timestamp_ms: now_ms(),
+            };
+            if !acks {
+                done.push(self.finish(correlation_id, req, None, true));
+            } else if self.pending.len() < KAFKA_MAX_PENDING {
+                self.pending.insert(correlation_id, req);
+            }
+        }
+        Some(done)
+    }
+
+    /// Server → client bytes. Returns the requests they answer, or `None`
+    /// when tracking should stop.
+    fn on_recv(&mut self, data: &[u8]) -> Option<Vec<KafkaTraceMsg>> {
+        let mut done = Vec::new();
+        for msg in self.recv.feed(data)? {
+            let mut r = KafkaReader::new(&msg, false);
+            let correlation_id = r.i32()?;
+            let Some(req) = self.pending.remove(&correlation_id) else {
+                continue;
+            };
+            // ApiVersions responses keep the classic header so that old
+            // clients can always read them.
+            r.flexible =
+                req.api_key != KAFKA_API_VERSIONS && kafka_flexible(req.api_key, req.api_version);
+            let error_code = r
+                .skip_tagged_fields()
+                .and_then(|()| kafka_response_error(&mut r, req.api_key, req.api_version));
+            done.push(self.finish(correlation_id, req, error_code, false));
+        }
+        Some(done)
+    }
+}
+
+/// Feed bytes in one direction to the Kafka tracker for `key`.
+///
+/// Returns `None` when the connection is not (or no longer) tracked as
+/// Kafka, otherwise the requests completed by this segment.
+pub(crate) fn track_kafka(
+    map: &mut Shard,
+    key: usize,
+    data: &[u8],
+    outgoing: bool,
+    tls: bool,
+) -> Option<Vec<KafkaTraceMsg>> {
+    if let Some(FdState::Kafka(kafka)) = map.get_mut(&key) {
+        let done = if outgoing {
+            kafka.on_send(data)
+        } else {
+            kafka.on_recv(data)
+        };
+        if done.is_none() {
+            map.remove(&key);
+        }
+        return Some(done.unwrap_or_default());
+    }
+    if !outgoing || map.contains_key(&key) || !looks_like_kafka_request(data) {
+        return None;
+    }
+    let (dest_addr, port) = connection_peer(key, tls)?;
+    if port != kafka_port() {
+        return None;
+    }
+    let mut kafka = Box::new(KafkaConnState::new(dest_addr));
+    let done = kafka.on_send(data);
+    if done.is_some() {
+        map.insert(key, FdState::Kafka(kafka));
+    }
+    Some(done.unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a message body field by field.
+    #[derive(Default)]
+    struct Enc(Vec<u8>);
+
+    impl Enc {
+        fn i16(mut self, v: i16) -> Self {
+            self.0.extend_from_slice(&v.to_be_bytes());
+            self
+        }
+
+        fn i32(mut self, v: i32) -> Self {
+            self.0.extend_from_slice(&v.to_be_bytes());
+            self
+        }
+
+        fn str(self, s: &str) -> Self {
+            let mut enc = self.i16(s.len() as i16);
+            enc.0.extend_from_slice(s.as_bytes());
+            enc
+        }
+
+        /// A flexible-version uvarint (all values here fit one byte).
+        fn uvarint(mut self, v: u8) -> Self {
+            self.0.push(v);
+            self
+        }
+
+        fn compact_str(self, s: &str) -> Self {
+            let mut enc = self.uvarint(s.len() as u8 + 1);
+            enc.0.extend_from_slice(s.as_bytes());
+            enc
+        }
+
+        fn raw(mut self, bytes: &[u8]) -> Self {
+            self.0.extend_from_slice(bytes);
+            self
+        }
+
+        /// The size-prefixed message.
+        fn message(self) -> Vec<u8> {
+            let mut out = (self.0.len() as u32).to_be_bytes().to_vec();
+            out.extend(self.0);
+            out
+        }
+    }
+
+    fn request(api_key: i16, version: i16, correlation_id: i32) -> Enc {
+        Enc::default()
+            .i16(api_key)
+            .i16(version)
+            .i32(correlation_id)
+            .str("app")
+    }
+
+    fn conn() -> KafkaConnState {
+        KafkaConnState::new("10.0.0.2:9092".to_string())
+    }
+
+    #[test]
+    fn test_metadata_request_and_error_response() {
+        let mut kafka = conn();
+        let req = request(KAFKA_METADATA, 1, 5)
+            .i32(2)
+            .str("orders")
+            .str("users")
+            .message();
+        assert!(looks_like_kafka_request(&req));
+        assert!(kafka.on_send(&req).unwrap().is_empty());
+        // No brokers, controller id, one topic with UNKNOWN_TOPIC_OR_PARTITION.
+        let resp = Enc::default().i32(5).i32(0).i32(1).i32(1).i16(3).message();
+        let done = kafka.on_recv(&resp).unwrap();
+        assert_eq!(done.len(), 1);
+        assert_eq!(done[0].api, "Metadata");
+        assert_eq!(done[0].correlation_id, 5);
+        assert_eq!(done[0].client_id.as_deref(), Some("app"));
+        assert_eq!(done[0].topics, ["orders", "users"]);
+        assert_eq!(done[0].error_code, Some(3));
+    }
+
+    #[test]
+    fn test_produce_without_acks_completes_at_send() {
+        let mut kafka = conn();
+        let req = request(KAFKA_PRODUCE, 3, 1)
+            .i16(-1) // no transactional id
+            .i16(0) // acks
+            .i32(1000)
+            .i32(1)
+            .str("events")
+            .i32(1)
+            .i32(0)
+            .i32(3)
+            .raw(b"abc")
+            .message();
+        let done = kafka.on_send(&req).unwrap();
+        assert_eq!(done.len(), 1);
+        assert!(done[0].no_response);
+        assert_eq!(done[0].topics, ["events"]);
+        assert!(kafka.pending.is_empty());
+    }
+
+    #[test]
+    fn test_flexible_produce_request_and_response() {
+        let mut kafka = conn();
+        let req = request(KAFKA_PRODUCE, 9, 2)
+            .uvarint(0) // header tagged fields
+            .uvarint(0) // null transactional id
+            .i16(1)
+            .i32(1000)
+            .uvarint(2)
+            .compact_str("clicks")
+            .uvarint(1) // no partitions
+            .uvarint(0)
+            .message();
+        assert!(kafka.on_send(&req).unwrap().is_empty());
+        let resp = Enc::default()
+            .i32(2)
+            .uvarint(0)
+            .uvarint(2)
+            .compact_str("clicks")
+            .uvarint(2)
+            .i32(0)
+            .i16(0)
+            .message();
+        let done = kafka.on_recv(&resp).unwrap();
+        assert_eq!(done[0].topics, ["clicks"]);
+        assert_eq!(done[0].api_version, 9);
+        assert_eq!(done[0].error_code, None);
+    }
+
+    #[test]
+    fn test_fetch_response_error_code() {
+        let mut kafka = conn();
+        let req = request(KAFKA_FETCH, 4, 7)
+            .i32(-1)
+            .i32(500)
+            .i32(1)
+            .i32(1 << 20)
+            .raw(&[0])
+            .i32(1)
+            .str("logs")
+            .i32(0)
+            .message();
+        assert!(kafka.on_send(&req).unwrap().is_empty());
+        // Throttle, one topic, one partition with OFFSET_OUT_OF_RANGE.
+        let resp = Enc::default()
+            .i32(7)
+            .i32(0)
+            .i32(1)
+            .str("logs")
+            .i32(1)
+            .i32(0)
+            .i16(1)
+            .message();
+        let done = kafka.on_recv(&resp).unwrap();
+        assert_eq!(done[0].api, "Fetch");
+        assert_eq!(done[0].topics, ["logs"]);
+        assert_eq!(done[0].error_code, Some(1));
+    }
+
+    #[test]
+    fn test_large_messages_are_skipped_past_their_head() {
+        let mut kafka = conn();
+        let records = vec![9; KAFKA_MAX_HEAD * 2];
+        let big = request(KAFKA_PRODUCE, 3, 1)
+            .i16(-1)
+            .i16(1)
+            .i32(1000)
+            .i32(1)
+            .str("bulk")
+            .i32(1)
+            .i32(0)
+            .i32(records.len() as i32)
+            .raw(&records)
+            .message();
+        let (first, rest) = big.split_at(KAFKA_MAX_HEAD + 100);
+        assert!(kafka.on_send(first).unwrap().is_empty());
+        assert!(kafka.send.buf.is_empty());
+        let mut rest = rest.to_vec();
+        rest.extend(request(18, 0, 2).message());
+        assert!(kafka.on_send(&rest).unwrap().is_empty());
+        assert_eq!(kafka.pending.len(), 2);
+        assert_eq!(kafka.pending[&1].topics, ["bulk"]);
+
+        // Responses may come back in pieces; unknown ids are ignored.
+        let resp = Enc::default().i32(2).i16(0).message();
+        let mut stray = Enc::default().i32(99).message();
+        stray.extend_from_slice(&resp[..3]);
+        assert!(kafka.on_recv(&stray).unwrap().is_empty());
+        let done = kafka.on_recv(&resp[3..]).unwrap();
+        assert_eq!(done[0].api, "ApiVersions");
+    }
+
+    #[test]
+    fn test_rejects_non_kafka_bytes() {
+        assert!(!looks_like_kafka_request(b"GET / HTTP/1.1\r\n"));
+        assert!(!looks_like_kafka_request(&[0, 0, 0, 20, 0x7f, 0, 0, 0]));
+        let mut kafka = conn();
+        assert!(kafka.on_send(&[0xff, 0xff, 0xff, 0xff]).is_none());
+        assert_eq!(kafka_api_name(999), "ApiKey(999)");
+    }
+}
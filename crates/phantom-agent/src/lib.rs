@@ -3,11 +3,79 @@
 //! Build as a `dylib` and inject with:
 //!   `LD_PRELOAD=/path/to/libphantom_agent.so PHANTOM_SOCKET=/tmp/phantom.sock <cmd>`
 //!
-//! The agent hooks `send()` / `recv()` / `close()` from libc to intercept
-//! plain-text HTTP/1.x traffic, and `SSL_write()` / `SSL_read()` / `SSL_free()`
-//! from OpenSSL/LibreSSL/BoringSSL to intercept HTTPS traffic (plaintext above
-//! the TLS layer). Both HTTP/1.x and HTTP/2 are captured. Captured traces are
-//! sent as JSON datagrams over a Unix datagram socket to the phantom main process.
+//! The agent hooks `send()` / `recv()` / `shutdown()` / `close()` from libc,
+//! along with the `sendto()` / `recvfrom()`, `sendmsg()` / `recvmsg()` and
+//! `write()` / `read()` / `writev()` / `readv()` variants on sockets, to
+//! intercept plain-text HTTP/1.x traffic. HTTPS traffic is intercepted as plaintext
+//! above the TLS layer through `SSL_write()` / `SSL_read()` (and their `_ex`
+//! forms) / `SSL_shutdown()` / `SSL_free()` from OpenSSL/LibreSSL/BoringSSL,
+//! their wolfSSL and mbedTLS counterparts, and NSPR's `PR_Write()` /
+//! `PR_Read()` / `PR_Close()` on NSS TLS sockets. Both HTTP/1.x and HTTP/2 are
+//! captured, as well as Apache Thrift RPCs (binary/compact protocol, framed or
+//! buffered transport), PostgreSQL queries (port 5432, or
+//! `PHANTOM_POSTGRES_PORT`), Redis commands (port 6379, or
+//! `PHANTOM_REDIS_PORT`) and Kafka requests (port 9092, or
+//! `PHANTOM_KAFKA_PORT`). Connections upgraded to WebSocket keep being
+//! followed, and each frame is reported as it arrives; `text/event-stream`
+//! responses are reported at their headers, followed by one message per event.
+//! Hooks hand finished traces to a background emitter thread through a
+//! bounded queue; the thread serializes them and sends them as JSON datagrams
+//! (MessagePack with `PHANTOM_IPC_FORMAT=msgpack`, which carries bodies as raw
+//! bytes) over a Unix datagram socket to the phantom main process; traces too
+//! large for one datagram are split into fragments that the main process
+//! reassembles.
+//! `PHANTOM_IPC_MODE=seqpacket` or `stream` sends them over a connection
+//! instead, which blocks rather than drops when phantom falls behind. With
+//! `PHANTOM_BATCH_MS` set, small messages are coalesced and sent together at
+//! most that many milliseconds later. `PHANTOM_SAMPLE_RATE` (e.g. `0.1`) keeps
+//! only that fraction of traces, plus every failed one, and `PHANTOM_INCLUDE` /
+//! `PHANTOM_EXCLUDE` (comma-separated host or `/path` globs) select which
+//! destinations are traced at all. `PHANTOM_PROTOCOLS` (e.g. `http,http2`)
+//! limits which protocols are followed; everything is by default.
+//!
+//! Any of these settings can also come from a TOML file named by
+//! `PHANTOM_CONFIG`, with keys in lower case without the prefix
+//! (`sample_rate = 0.1`, `include = ["api.example.com"]`). Variables set in
+//! the environment override the file.
+//!
+//! Plain sockets returned by `accept()` / `accept4()` are inbound connections:
+//! there requests are read from what the process receives and responses from
+//! what it sends, so a server process has the requests it serves traced as
+//! well as the ones it makes. Descriptors duplicated with `dup()` /
+//! `dup2()` / `dup3()` / `fcntl(F_DUPFD)` share their connection's state,
+//! which is torn down when the last of them is closed.
+//!
+//! HTTPS traces over OpenSSL-compatible libraries carry the session's SNI,
+//! protocol version, cipher suite and ALPN protocol. Traces also break their
+//! duration down like `curl -w`: time to first response byte, and for the
+//! first request on a connection the time spent in `connect()` and the TLS
+//! handshake. With `PHANTOM_PROPAGATE=1` outbound HTTP requests without a
+//! `traceparent` get one, carrying the ids of their captured trace.
+//!
+//! HTTP/3 runs over QUIC, whose encryption lives inside the client's own QUIC
+//! stack rather than libssl, so it cannot be read at the TLS layer and goes
+//! uncaptured. With `PHANTOM_BLOCK_QUIC=1` the agent refuses outgoing QUIC
+//! Initial packets (`sendto`/`sendmsg`/`send` on UDP sockets); clients then
+//! fall back to HTTP/2 or HTTP/1.1 over TCP, which is captured.
+//!
+//! Socket I/O submitted through io_uring bypasses libc. With
+//! `PHANTOM_IO_URING=1` the agent also hooks liburing's submit and wait
+//! functions and reads transfers from the submission and completion rings.
+//!
+//! Bodies longer than `PHANTOM_MAX_BODY` bytes (default 16 KB) are truncated;
+//! when `PHANTOM_BODY_DIR` is set they are also written in full to a file
+//! referenced from the trace. Each connection buffers at most
+//! `PHANTOM_MAX_BUF` bytes (default 512 KB), which also bounds those files.
+//! With `PHANTOM_CAPTURE_BODIES=0` no body bytes are kept at all: traces carry
+//! headers and timing only.
+//!
+//! Every `PHANTOM_STATS_MS` (default 5 s) the agent reports its own health as
+//! an `agent_stats` message: hooks that fired, messages sent and dropped, and
+//! parse errors.
+//!
+//! `phantom attach` loads the agent into a process that is already running
+//! and calls `phantom_attach()`, which redirects the process's libc socket
+//! calls to the hooks (x86_64 only); TLS libraries are not hooked that way.
 //!
 //! **Note**: HTTPS capture requires the target to dynamically link `libssl`.
 //! Statically-linked TLS (e.g. Go's native crypto, Rust's rustls) is not
@@ -15,14 +83,51 @@
 
 #![cfg(target_os = "linux")]
 
-use std::cell::Cell;
-use std::collections::HashMap;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::mem::ManuallyDrop;
+use std::os::fd::{AsRawFd, FromRawFd, IntoRawFd};
 use std::os::unix::net::UnixDatagram;
-use std::sync::{Mutex, OnceLock};
+use std::path::PathBuf;
+use std::sync::atomic::{
+    AtomicBool, AtomicI32, AtomicU8, AtomicU32, AtomicU64, AtomicUsize, Ordering,
+};
+use std::sync::mpsc::{SyncSender, TrySendError};
+use std::sync::{Mutex, MutexGuard, Once, OnceLock};
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use libc::{c_int, c_void, size_t, ssize_t};
 
+#[cfg(target_arch = "x86_64")]
+mod attach;
+mod chunked;
+mod http1;
+mod http2;
+mod kafka;
+mod postgres;
+mod propagate;
+mod redis;
+mod sse;
+mod thrift;
+mod websocket;
+
+use crate::chunked::ChunkedBody;
+use crate::http1::{
+    collecting_response, do_emit, is_continue_response, is_interim_status, looks_like_http_request,
+    request_sent, try_parse_request, try_parse_response_headers,
+};
+use crate::http2::{
+    H2_PREFACE, H2ConnState, H2Stream, drain_completed_h2_streams, emit_h2_stream,
+    process_h2_recv_frames, process_h2_send_frames,
+};
+use crate::kafka::{KafkaConnState, KafkaTraceMsg, track_kafka};
+use crate::postgres::{PgConnState, PgTraceMsg, track_postgres_recv, track_postgres_send};
+use crate::propagate::{TraceContext, forget_tls_rewrite, tls_write_propagated, write_propagated};
+use crate::redis::{RedisConnState, RedisTraceMsg, track_redis_recv, track_redis_send};
+use crate::sse::{SseEventMsg, SseState, is_event_stream, track_sse};
+use crate::thrift::{ThriftConnState, ThriftTraceMsg, track_thrift};
+use crate::websocket::{WsConnState, WsFrameMsg, is_websocket_upgrade, track_websocket};
+
 // ─────────────────────────────────────────────────────────────────────────────
 // Re-entry guard — prevents recursive hook calls (e.g. if our code calls send,
 // or SSL_write internally calls send)
@@ -33,7 +138,122 @@ thread_local! {
 }
 
 // ─────────────────────────────────────────────────────────────────────────────
-// IPC — send JSON datagrams to phantom via UnixDatagram::send_to()
+// Configuration file (`PHANTOM_CONFIG`)
+//
+// `PHANTOM_CONFIG=/path/phantom-agent.toml` names a TOML file of settings, so
+// one capture policy can be shared instead of a long list of variables. Each
+// top-level key stands for the variable of the same name, upper-cased and
+// prefixed with `PHANTOM_`: `sample_rate = 0.1` is `PHANTOM_SAMPLE_RATE=0.1`.
+// Booleans become `1`/`0` and arrays comma-separated lists. A variable set in
+// the environment always wins over the file.
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[derive(Default)]
+struct ConfigFile {
+    /// Values keyed by the variable they stand for (`PHANTOM_SAMPLE_RATE`).
+    values: HashMap<String, String>,
+    /// Why the file could not be used, reported once as a diagnostic.
+    error: Option<String>,
+}
+
+static CONFIG_FILE: OnceLock<ConfigFile> = OnceLock::new();
+static CONFIG_ERROR_REPORTED: AtomicBool = AtomicBool::new(false);
+
+fn config_file() -> &'static ConfigFile {
+    CONFIG_FILE.get_or_init(|| {
+        let Some(path) = std::env::var_os("PHANTOM_CONFIG") else {
+            return ConfigFile::default();
+        };
+        let path = PathBuf::from(path);
+        match std::fs::read_to_string(&path) {
+            Ok(text) => parse_config(&text).unwrap_or_else(|e| ConfigFile {
+                values: HashMap::new(),
+                error: Some(format!("ignoring PHANTOM_CONFIG {}: {e}", path.display())),
+            }),
+            Err(e) => ConfigFile {
+                values: HashMap::new(),
+                error: Some(format!(
+                    "cannot read PHANTOM_CONFIG {}: {e}",
+                    path.display()
+                )),
+            },
+        }
+    })
+}
+
+fn parse_config(text: &str) -> Result<ConfigFile, String> {
+    let table: toml::Table = text
+        .parse()
+        .map_err(|e: toml::de::Error| e.message().to_string())?;
+    let mut values = HashMap::new();
+    let mut skipped = Vec::new();
+    for (key, value) in table {
+        let value = match value {
+            toml::Value::Array(items) => items
+                .iter()
+                .map(config_scalar)
+                .collect::<Option<Vec<_>>>()
+                .map(|items| items.join(",")),
+            value => config_scalar(&value),
+        };
+        match value {
+            Some(value) => {
+                let name = format!("PHANTOM_{}", key.to_ascii_uppercase().replace('-', "_"));
+                values.insert(name, value);
+            }
+            None => skipped.push(key),
+        }
+    }
+    let error = (!skipped.is_empty()).then(|| {
+        format!(
+            "PHANTOM_CONFIG: unsupported value for {}",
+            skipped.join(", ")
+        )
+    });
+    Ok(ConfigFile { values, error })
+}
+
+fn config_scalar(value: &toml::Value) -> Option<String> {
+    match value {
+        toml::Value::String(s) => Some(s.clone()),
+        toml::Value::Integer(n) => Some(n.to_string()),
+        toml::Value::Float(f) => Some(f.to_string()),
+        toml::Value::Boolean(b) => Some(if *b { "1" } else { "0" }.to_string()),
+        _ => None,
+    }
+}
+
+/// The setting `name` (`PHANTOM_*`): the environment variable when set,
+/// otherwise the value from the `PHANTOM_CONFIG` file.
+fn config_var(name: &str) -> Option<String> {
+    std::env::var(name)
+        .ok()
+        .or_else(|| config_file().values.get(name).cloned())
+}
+
+/// Reports a config file that could not be read or parsed, once. Called from
+/// the hooks rather than while loading, which may happen inside [`emit_msg`].
+fn report_config_error() {
+    if CONFIG_ERROR_REPORTED.load(Ordering::Relaxed) {
+        return;
+    }
+    let Some(error) = &config_file().error else {
+        return;
+    };
+    if CONFIG_ERROR_REPORTED.swap(true, Ordering::AcqRel) {
+        return;
+    }
+    emit_msg(DiagnosticMsg {
+        kind: "diagnostic",
+        message: error.clone(),
+        pid: std::process::id(),
+        comm: process_comm(),
+        timestamp_ms: now_ms(),
+    });
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// IPC — send JSON (or MessagePack) datagrams to phantom via UnixDatagram::send_to()
 //
 // We intentionally use `send_to()` (→ `sendto()` syscall) rather than
 // `send()` to avoid re-entering our own `send` hook.
@@ -41,28 +261,129 @@ thread_local! {
 
 /// Maximum datagram payload.  Linux UDS datagrams are limited to ~64 KB.
 const MAX_DATAGRAM: usize = 60_000;
-/// Maximum body bytes stored per trace (keeps datagrams small).
-const MAX_BODY: usize = 16_384;
-/// Maximum bytes we buffer per connection before giving up.
-const MAX_BUF: usize = 512 * 1024;
-
-static IPC_SOCKET: OnceLock<Option<(UnixDatagram, String)>> = OnceLock::new();
-
-fn ipc() -> Option<(&'static UnixDatagram, &'static str)> {
-    IPC_SOCKET
-        .get_or_init(|| {
-            let path = std::env::var("PHANTOM_SOCKET").ok()?;
-            // `unbound()` creates an anonymous datagram socket.
-            // `send_to()` on it calls sendto(2), NOT send(2) — safe from recursion.
-            let sock = UnixDatagram::unbound().ok()?;
-            Some((sock, path))
-        })
-        .as_ref()
-        .map(|(s, p)| (s, p.as_str()))
+/// Default for the body bytes stored per trace (keeps datagrams small);
+/// `PHANTOM_MAX_BODY` overrides it.
+const MAX_BODY_DEFAULT: usize = 16_384;
+/// Default for the bytes buffered per connection before giving up;
+/// `PHANTOM_MAX_BUF` overrides it.
+const MAX_BUF_DEFAULT: usize = 512 * 1024;
+/// Smallest per-connection buffer, so a header block still fits.
+const MAX_BUF_MIN: usize = 16_384;
+
+static MAX_BODY: OnceLock<usize> = OnceLock::new();
+static MAX_BUF: OnceLock<usize> = OnceLock::new();
+
+fn env_size(name: &str) -> Option<usize> {
+    config_var(name)?.trim().parse().ok()
+}
+
+/// Body bytes stored per trace; longer bodies are truncated (and written to
+/// `PHANTOM_BODY_DIR` when set).
+fn max_body() -> usize {
+    *MAX_BODY.get_or_init(|| env_size("PHANTOM_MAX_BODY").unwrap_or(MAX_BODY_DEFAULT))
+}
+
+/// Bytes buffered per connection and body, never less than [`max_body`].
+fn max_buf() -> usize {
+    *MAX_BUF.get_or_init(|| {
+        env_size("PHANTOM_MAX_BUF")
+            .unwrap_or(MAX_BUF_DEFAULT)
+            .max(max_body())
+            .max(MAX_BUF_MIN)
+    })
+}
+
+static CAPTURE_BODIES: OnceLock<bool> = OnceLock::new();
+
+/// False with `PHANTOM_CAPTURE_BODIES=0`: HTTP bodies are still framed, to
+/// find where each message ends, but their bytes are never copied.
+fn capture_bodies() -> bool {
+    *CAPTURE_BODIES.get_or_init(|| config_var("PHANTOM_CAPTURE_BODIES").is_none_or(|v| v != "0"))
+}
+
+/// How messages reach phantom (`PHANTOM_IPC_MODE`, set by `phantom run`).
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum IpcMode {
+    /// One datagram per message; dropped when phantom falls behind.
+    Datagram,
+    /// A connected `SOCK_SEQPACKET` socket: each datagram-sized frame is
+    /// delivered, and senders block while phantom catches up.
+    SeqPacket,
+    /// A connected `SOCK_STREAM` socket carrying messages prefixed with their
+    /// length (u32 LE), with no size limit.
+    Stream,
+}
+
+static IPC_MODE: OnceLock<IpcMode> = OnceLock::new();
+
+fn ipc_mode() -> IpcMode {
+    *IPC_MODE.get_or_init(|| match config_var("PHANTOM_IPC_MODE").as_deref() {
+        Some("seqpacket") => IpcMode::SeqPacket,
+        Some("stream") => IpcMode::Stream,
+        _ => IpcMode::Datagram,
+    })
+}
+
+static IPC_MSGPACK: OnceLock<bool> = OnceLock::new();
+
+/// Whether messages are encoded as MessagePack (`PHANTOM_IPC_FORMAT=msgpack`)
+/// rather than JSON. MessagePack carries bodies as raw bytes instead of
+/// base64; phantom accepts either.
+fn ipc_msgpack() -> bool {
+    *IPC_MSGPACK.get_or_init(|| config_var("PHANTOM_IPC_FORMAT").is_some_and(|f| f == "msgpack"))
+}
+
+/// Serializes `msg` in the configured encoding, appending to `out`.
+fn encode_msg<T: serde::Serialize>(out: &mut Vec<u8>, msg: &T) -> bool {
+    if ipc_msgpack() {
+        rmp_serde::encode::write_named(out, msg).is_ok()
+    } else {
+        serde_json::to_writer(out, msg).is_ok()
+    }
+}
+
+static IPC_PATH: OnceLock<Option<String>> = OnceLock::new();
+/// The agent's IPC socket, `-1` until first use: an anonymous datagram socket
+/// or, in the connected modes, a connection to phantom. A forked child drops
+/// the inherited one and opens its own (see [`fork_child`]).
+static IPC_FD: AtomicI32 = AtomicI32::new(-1);
+/// Serializes writes on a stream connection so messages do not interleave.
+static IPC_WRITE: Mutex<()> = Mutex::new(());
+
+fn ipc_path() -> Option<&'static str> {
+    IPC_PATH
+        .get_or_init(|| config_var("PHANTOM_SOCKET"))
+        .as_deref()
+}
+
+fn ipc() -> Option<(ManuallyDrop<UnixDatagram>, &'static str)> {
+    let path = ipc_path()?;
+    let mut fd = IPC_FD.load(Ordering::Acquire);
+    if fd < 0 {
+        // `unbound()` creates an anonymous datagram socket.
+        // `send_to()` on it calls sendto(2), NOT send(2) — safe from recursion.
+        let sock = UnixDatagram::unbound().ok()?;
+        fd = match IPC_FD.compare_exchange(
+            -1,
+            sock.as_raw_fd(),
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        ) {
+            Ok(_) => sock.into_raw_fd(),
+            // Another thread got there first; `sock` is closed on drop.
+            Err(current) => current,
+        };
+    }
+    // SAFETY: IPC_FD owns an open datagram socket; ManuallyDrop keeps this
+    // borrowed handle from closing it.
+    Some((
+        ManuallyDrop::new(unsafe { UnixDatagram::from_raw_fd(fd) }),
+        path,
+    ))
 }
 
 // ─────────────────────────────────────────────────────────────────────────────
-// IPC message (serialised to JSON and sent as a datagram)
+// IPC message (serialised to JSON or MessagePack and sent as a datagram)
 // ─────────────────────────────────────────────────────────────────────────────
 
 #[derive(serde::Serialize)]
@@ -72,624 +393,1217 @@ struct TraceMsg {
     status_code: u16,
     request_headers: HashMap<String, String>,
     response_headers: HashMap<String, String>,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    response_trailers: HashMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_body_b64: Option<WireBytes>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_body_b64: Option<WireBytes>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    request_body_b64: Option<String>,
+    request_body_file: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    response_body_b64: Option<String>,
+    response_body_file: Option<String>,
     duration_ms: u64,
     timestamp_ms: u64,
     #[serde(skip_serializing_if = "Option::is_none")]
     dest_addr: Option<String>,
     protocol_version: String,
+    connection_id: String,
+    pid: u32,
+    /// Thread that sent the request.
+    tid: u32,
+    comm: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tls: Option<TlsInfo>,
+    #[serde(skip_serializing_if = "TimingMsg::is_empty")]
+    timing: TimingMsg,
+    /// Ids of the `traceparent` the agent inserted into the request.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trace_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    span_id: Option<String>,
+    /// Bytes of the request and response as sent and received: head and
+    /// (still encoded) body, or the HTTP/2 frames of the stream.
+    request_size: u64,
+    response_size: u64,
 }
 
-fn emit_msg(msg: &TraceMsg) {
-    let Some((sock, path)) = ipc() else { return };
-    let Ok(data) = serde_json::to_vec(msg) else {
-        return;
-    };
-    if data.len() <= MAX_DATAGRAM {
-        let _ = sock.send_to(&data, path);
-    }
+/// Where a request's time went, in milliseconds. The connection phases are
+/// only set on the first request of a connection.
+#[derive(Clone, Copy, Default, serde::Serialize)]
+struct TimingMsg {
+    /// `connect()` call until the connection was up.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    connect_ms: Option<u64>,
+    /// Connection up until the first TLS write went through.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tls_handshake_ms: Option<u64>,
+    /// Request start until the first response byte.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ttfb_ms: Option<u64>,
 }
 
-// ─────────────────────────────────────────────────────────────────────────────
-// Base64 encoder (avoids adding an external crate to the dylib)
-// ─────────────────────────────────────────────────────────────────────────────
-
-fn b64_encode(data: &[u8]) -> String {
-    const T: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
-    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
-    for chunk in data.chunks(3) {
-        let b0 = chunk[0] as u32;
-        let b1 = if chunk.len() > 1 { chunk[1] as u32 } else { 0 };
-        let b2 = if chunk.len() > 2 { chunk[2] as u32 } else { 0 };
-        let n = (b0 << 16) | (b1 << 8) | b2;
-        out.push(T[((n >> 18) & 63) as usize] as char);
-        out.push(T[((n >> 12) & 63) as usize] as char);
-        out.push(if chunk.len() > 1 {
-            T[((n >> 6) & 63) as usize] as char
-        } else {
-            '='
-        });
-        out.push(if chunk.len() > 2 {
-            T[(n & 63) as usize] as char
-        } else {
-            '='
-        });
+impl TimingMsg {
+    fn is_empty(&self) -> bool {
+        self.connect_ms.is_none() && self.tls_handshake_ms.is_none() && self.ttfb_ms.is_none()
     }
-    out
 }
 
-fn body_b64(raw: &[u8]) -> Option<String> {
-    if raw.is_empty() {
-        None
-    } else {
-        let trunc = &raw[..raw.len().min(MAX_BODY)];
-        Some(b64_encode(trunc))
-    }
+thread_local! {
+    /// Per-thread serialization buffer, reused across messages so emitting a
+    /// trace does not grow a fresh Vec from scratch each time.
+    static MSG_BUF: RefCell<Vec<u8>> = RefCell::new(Vec::with_capacity(MAX_DATAGRAM));
 }
 
-// ─────────────────────────────────────────────────────────────────────────────
-// HTTP/2 — constants, frame parsing, per-stream/connection state
-// ─────────────────────────────────────────────────────────────────────────────
+// Messages larger than one datagram are split into fragments:
+// `magic | message id: u64 LE | index: u16 LE | total: u16 LE | payload`.
+// The collector (LdPreloadCaptureBackend) reassembles them by message id.
 
-/// HTTP/2 client connection preface (RFC 7540 §3.5).
-const H2_PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
-/// Size of an HTTP/2 frame header in bytes.
-const H2_FRAME_HDR_LEN: usize = 9;
-// Frame types we care about.
-const H2_TYPE_DATA: u8 = 0x0;
-const H2_TYPE_HEADERS: u8 = 0x1;
-const H2_TYPE_CONTINUATION: u8 = 0x9;
-// Frame flags.
-const H2_FLAG_END_STREAM: u8 = 0x1;
-const H2_FLAG_END_HEADERS: u8 = 0x4;
-const H2_FLAG_PADDED: u8 = 0x8;
-const H2_FLAG_PRIORITY: u8 = 0x20;
-
-/// Parse the 9-byte HTTP/2 frame header.
-/// Returns `(payload_len, frame_type, flags, stream_id)` or `None` if buf is too short.
-fn parse_h2_frame_header(buf: &[u8]) -> Option<(usize, u8, u8, u32)> {
-    if buf.len() < H2_FRAME_HDR_LEN {
-        return None;
-    }
-    let payload_len = ((buf[0] as usize) << 16) | ((buf[1] as usize) << 8) | (buf[2] as usize);
-    let frame_type = buf[3];
-    let flags = buf[4];
-    // Mask off the reserved R bit (bit 31).
-    let stream_id = u32::from_be_bytes([buf[5] & 0x7f, buf[6], buf[7], buf[8]]);
-    Some((payload_len, frame_type, flags, stream_id))
-}
+/// First bytes of a fragment; JSON messages never start with NUL.
+const FRAGMENT_MAGIC: &[u8; 4] = b"\0PHF";
+const FRAGMENT_HEADER: usize = 16;
+/// Most fragments per message (~3.8 MB); larger messages are dropped.
+const MAX_FRAGMENTS: usize = 64;
 
-/// Return the `[start, end)` byte range of the header block fragment within a
-/// HEADERS frame payload, stripping optional padding and priority bytes.
-fn h2_header_block_range(payload: &[u8], flags: u8) -> (usize, usize) {
-    let mut start = 0usize;
-    let mut end = payload.len();
+static FRAGMENT_SEQ: AtomicU32 = AtomicU32::new(0);
 
-    if flags & H2_FLAG_PADDED != 0 {
-        if payload.is_empty() {
-            return (0, 0);
+/// Sends `data` with `send_frame` as one frame, or as fragments when it does
+/// not fit in one. Stops at the first frame that cannot be sent; returns
+/// whether all were.
+fn send_fragmented(data: &[u8], mut send_frame: impl FnMut(&[u8]) -> bool) -> bool {
+    if data.len() <= MAX_DATAGRAM {
+        return send_frame(data);
+    }
+    let chunk = MAX_DATAGRAM - FRAGMENT_HEADER;
+    let total = data.len().div_ceil(chunk);
+    if total > MAX_FRAGMENTS {
+        return false;
+    }
+    // The pid keeps ids from different processes sharing the socket apart.
+    let id = (u64::from(std::process::id()) << 32)
+        | u64::from(FRAGMENT_SEQ.fetch_add(1, Ordering::Relaxed));
+    let mut frame = Vec::with_capacity(MAX_DATAGRAM);
+    for (index, part) in data.chunks(chunk).enumerate() {
+        frame.clear();
+        frame.extend_from_slice(FRAGMENT_MAGIC);
+        frame.extend_from_slice(&id.to_le_bytes());
+        frame.extend_from_slice(&(index as u16).to_le_bytes());
+        frame.extend_from_slice(&(total as u16).to_le_bytes());
+        frame.extend_from_slice(part);
+        if !send_frame(&frame) {
+            return false;
         }
-        let pad_len = payload[0] as usize;
-        start += 1;
-        end = end.saturating_sub(pad_len);
     }
-    if flags & H2_FLAG_PRIORITY != 0 {
-        start += 5; // 4 bytes stream dependency + 1 byte weight
+    true
+}
+
+/// Connects a `SOCK_SEQPACKET` or `SOCK_STREAM` socket to phantom. Calls the
+/// real libc functions so the agent's own connection is never traced.
+fn ipc_connect(path: &str, ty: c_int) -> Option<c_int> {
+    // SAFETY: an all-zero sockaddr_un is a valid (empty) address.
+    let mut addr: libc::sockaddr_un = unsafe { std::mem::zeroed() };
+    if path.len() >= addr.sun_path.len() {
+        return None;
+    }
+    addr.sun_family = libc::AF_UNIX as libc::sa_family_t;
+    for (dst, &b) in addr.sun_path.iter_mut().zip(path.as_bytes()) {
+        *dst = b as libc::c_char;
+    }
+    // SAFETY: plain socket(2) call.
+    let fd = unsafe { libc::socket(libc::AF_UNIX, ty | libc::SOCK_CLOEXEC, 0) };
+    if fd < 0 {
+        return None;
+    }
+    let len = std::mem::size_of::<libc::sockaddr_un>() as libc::socklen_t;
+    // SAFETY: addr is a valid sockaddr_un of `len` bytes.
+    let result = unsafe {
+        redhook::real!(connect)(
+            fd,
+            &addr as *const libc::sockaddr_un as *const libc::sockaddr,
+            len,
+        )
+    };
+    if result != 0 {
+        // SAFETY: fd was opened above and is not shared.
+        unsafe { redhook::real!(close)(fd) };
+        return None;
     }
-    if start > end { (0, 0) } else { (start, end) }
+    Some(fd)
 }
 
-/// Per-stream state for a single HTTP/2 request-response pair.
-struct H2Stream {
-    req_method: Option<String>,
-    req_path: Option<String>,
-    req_authority: Option<String>,
-    req_scheme: Option<String>,
-    req_headers: HashMap<String, String>,
-    req_body: Vec<u8>,
-    /// True once we have seen END_STREAM on the request side.
-    req_done: bool,
-    started_at: Instant,
-    timestamp_ms: u64,
-    resp_status: Option<u16>,
-    resp_headers: HashMap<String, String>,
-    resp_body: Vec<u8>,
-    /// True once we have seen END_STREAM on the response side.
-    resp_done: bool,
-    tls: bool,
-}
-
-impl H2Stream {
-    fn new(tls: bool) -> Self {
-        let ts = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_millis() as u64;
-        Self {
-            req_method: None,
-            req_path: None,
-            req_authority: None,
-            req_scheme: None,
-            req_headers: HashMap::new(),
-            req_body: Vec::new(),
-            req_done: false,
-            started_at: Instant::now(),
-            timestamp_ms: ts,
-            resp_status: None,
-            resp_headers: HashMap::new(),
-            resp_body: Vec::new(),
-            resp_done: false,
-            tls,
+/// The connection to phantom in the connected modes, opened on first use.
+fn ipc_conn(ty: c_int) -> Option<c_int> {
+    let fd = IPC_FD.load(Ordering::Acquire);
+    if fd >= 0 {
+        return Some(fd);
+    }
+    let new = ipc_connect(ipc_path()?, ty)?;
+    match IPC_FD.compare_exchange(-1, new, Ordering::AcqRel, Ordering::Acquire) {
+        Ok(_) => Some(new),
+        Err(current) => {
+            // Another thread connected first.
+            // SAFETY: `new` was opened by this call and is not shared.
+            unsafe { redhook::real!(close)(new) };
+            Some(current)
         }
     }
 }
 
-/// Per-connection state for an HTTP/2 connection.
-struct H2ConnState {
-    tls: bool,
-    /// Buffered outgoing (app→server) bytes not yet consumed into complete frames.
-    send_buf: Vec<u8>,
-    /// Buffered incoming (server→app) bytes not yet consumed into complete frames.
-    recv_buf: Vec<u8>,
-    /// HPACK decoder for client-sent request headers.
-    send_hpack: hpack::Decoder<'static>,
-    /// HPACK decoder for server-sent response headers.
-    recv_hpack: hpack::Decoder<'static>,
-    /// Active streams keyed by HTTP/2 stream ID.
-    streams: HashMap<u32, H2Stream>,
-    // CONTINUATION frame accumulation (send direction).
-    send_cont_sid: Option<u32>,
-    send_cont_buf: Vec<u8>,
-    send_cont_end_stream: bool,
-    // CONTINUATION frame accumulation (recv direction).
-    recv_cont_sid: Option<u32>,
-    recv_cont_buf: Vec<u8>,
-    recv_cont_end_stream: bool,
-}
-
-impl H2ConnState {
-    fn new(tls: bool) -> Self {
-        Self {
-            tls,
-            send_buf: Vec::new(),
-            recv_buf: Vec::new(),
-            send_hpack: hpack::Decoder::new(),
-            recv_hpack: hpack::Decoder::new(),
-            streams: HashMap::new(),
-            send_cont_sid: None,
-            send_cont_buf: Vec::new(),
-            send_cont_end_stream: false,
-            recv_cont_sid: None,
-            recv_cont_buf: Vec::new(),
-            recv_cont_end_stream: false,
-        }
-    }
-}
-
-/// Apply decoded HPACK name-value pairs to a stream's request pseudo-headers and
-/// regular headers.
-fn apply_h2_request_headers(stream: &mut H2Stream, headers: Vec<(Vec<u8>, Vec<u8>)>) {
-    for (name, value) in headers {
-        let name = String::from_utf8_lossy(&name).into_owned();
-        let value = String::from_utf8_lossy(&value).into_owned();
-        match name.as_str() {
-            ":method" => stream.req_method = Some(value),
-            ":path" => stream.req_path = Some(value),
-            ":scheme" => stream.req_scheme = Some(value),
-            ":authority" => stream.req_authority = Some(value),
-            n if !n.starts_with(':') => {
-                stream.req_headers.insert(n.to_string(), value);
-            }
-            _ => {}
-        }
+/// Drops a broken stream connection so the next message reconnects. Called
+/// with [`IPC_WRITE`] held, so no other thread is writing to it.
+fn ipc_disconnect(fd: c_int) {
+    if IPC_FD
+        .compare_exchange(fd, -1, Ordering::AcqRel, Ordering::Acquire)
+        .is_ok()
+    {
+        // SAFETY: the connection was owned by IPC_FD, which no longer names it.
+        unsafe { redhook::real!(close)(fd) };
     }
 }
 
-/// Apply decoded HPACK name-value pairs to a stream's response pseudo-headers and
-/// regular headers.
-fn apply_h2_response_headers(stream: &mut H2Stream, headers: Vec<(Vec<u8>, Vec<u8>)>) {
-    for (name, value) in headers {
-        let name = String::from_utf8_lossy(&name).into_owned();
-        let value = String::from_utf8_lossy(&value).into_owned();
-        if name == ":status" {
-            stream.resp_status = value.parse().ok();
-        } else if !name.starts_with(':') {
-            stream.resp_headers.insert(name, value);
+/// Writes all of `data`, retrying short writes and interrupted calls.
+/// `MSG_NOSIGNAL` keeps a closed collector from raising SIGPIPE in the host.
+fn ipc_write_all(fd: c_int, mut data: &[u8]) -> bool {
+    while !data.is_empty() {
+        // SAFETY: data is a valid slice for the duration of the call.
+        let n = unsafe {
+            redhook::real!(send)(
+                fd,
+                data.as_ptr() as *const c_void,
+                data.len(),
+                libc::MSG_NOSIGNAL,
+            )
+        };
+        if n > 0 {
+            data = &data[n as usize..];
+        } else if n < 0 && std::io::Error::last_os_error().kind() == std::io::ErrorKind::Interrupted
+        {
+            continue;
+        } else {
+            return false;
         }
     }
+    true
 }
 
-/// Process all complete HTTP/2 frames in `h2.send_buf` (outgoing / request side).
-fn process_h2_send_frames(h2: &mut H2ConnState) {
-    // Skip the 24-byte client connection preface if present at the start.
-    if h2.send_buf.starts_with(H2_PREFACE) {
-        h2.send_buf.drain(..H2_PREFACE.len());
+/// Sends one serialized message (or batch) in the configured [`IpcMode`],
+/// counting it as dropped when it cannot be sent.
+fn ipc_send(data: &[u8]) {
+    let sent = match ipc_mode() {
+        IpcMode::Datagram => ipc().is_some_and(|(sock, path)| {
+            send_fragmented(data, |frame| sock.send_to(frame, path).is_ok())
+        }),
+        // Frames are sent whole, so threads need no lock; a failed connection
+        // is kept rather than closed under a concurrent send.
+        IpcMode::SeqPacket => ipc_conn(libc::SOCK_SEQPACKET)
+            .is_some_and(|fd| send_fragmented(data, |frame| ipc_write_all(fd, frame))),
+        IpcMode::Stream => ipc_send_stream(data),
+    };
+    if !sent {
+        DROPPED.fetch_add(1, Ordering::Relaxed);
     }
+}
 
-    while let Some((payload_len, frame_type, flags, stream_id)) =
-        parse_h2_frame_header(&h2.send_buf)
-    {
-        let total = H2_FRAME_HDR_LEN + payload_len;
-        if h2.send_buf.len() < total {
-            break; // Frame not yet fully buffered.
-        }
-
-        // Clone payload so we can drain the buffer cleanly.
-        let payload = h2.send_buf[H2_FRAME_HDR_LEN..total].to_vec();
-        h2.send_buf.drain(..total);
-
-        let tls = h2.tls;
-        match frame_type {
-            H2_TYPE_HEADERS if stream_id > 0 => {
-                let end_stream = flags & H2_FLAG_END_STREAM != 0;
-                let end_headers = flags & H2_FLAG_END_HEADERS != 0;
-                let (hb_start, hb_end) = h2_header_block_range(&payload, flags);
-                let hblock = &payload[hb_start..hb_end];
-
-                if end_headers {
-                    let decoded = h2.send_hpack.decode(hblock).unwrap_or_default();
-                    let stream = h2
-                        .streams
-                        .entry(stream_id)
-                        .or_insert_with(|| H2Stream::new(tls));
-                    apply_h2_request_headers(stream, decoded);
-                    stream.req_done |= end_stream;
-                } else {
-                    // Header block continues in CONTINUATION frames.
-                    h2.send_cont_sid = Some(stream_id);
-                    h2.send_cont_buf = hblock.to_vec();
-                    h2.send_cont_end_stream = end_stream;
-                }
-            }
-            H2_TYPE_DATA if stream_id > 0 => {
-                let end_stream = flags & H2_FLAG_END_STREAM != 0;
-                // Strip padding.
-                let (data_start, data_end) = if flags & H2_FLAG_PADDED != 0 && !payload.is_empty() {
-                    let pad = payload[0] as usize;
-                    (1, payload.len().saturating_sub(pad))
-                } else {
-                    (0, payload.len())
-                };
-                if let Some(stream) = h2.streams.get_mut(&stream_id) {
-                    if stream.req_body.len() < MAX_BUF {
-                        stream
-                            .req_body
-                            .extend_from_slice(&payload[data_start..data_end]);
-                    }
-                    stream.req_done |= end_stream;
-                }
-            }
-            H2_TYPE_CONTINUATION if stream_id > 0 && h2.send_cont_sid == Some(stream_id) => {
-                h2.send_cont_buf.extend_from_slice(&payload);
-                if flags & H2_FLAG_END_HEADERS != 0 {
-                    let hblock = std::mem::take(&mut h2.send_cont_buf);
-                    let decoded = h2.send_hpack.decode(&hblock).unwrap_or_default();
-                    let end_stream = h2.send_cont_end_stream;
-                    let stream = h2
-                        .streams
-                        .entry(stream_id)
-                        .or_insert_with(|| H2Stream::new(tls));
-                    apply_h2_request_headers(stream, decoded);
-                    stream.req_done |= end_stream;
-                    h2.send_cont_sid = None;
-                    h2.send_cont_end_stream = false;
-                }
-            }
-            _ => {} // SETTINGS, WINDOW_UPDATE, PING, GOAWAY, etc. — ignore.
-        }
+/// Writes one length-prefixed message to the stream connection, dropping the
+/// connection when the write fails.
+fn ipc_send_stream(data: &[u8]) -> bool {
+    let Ok(len) = u32::try_from(data.len()) else {
+        return false;
+    };
+    let Ok(_guard) = IPC_WRITE.lock() else {
+        return false;
+    };
+    let Some(fd) = ipc_conn(libc::SOCK_STREAM) else {
+        return false;
+    };
+    if !ipc_write_all(fd, &len.to_le_bytes()) || !ipc_write_all(fd, data) {
+        ipc_disconnect(fd);
+        return false;
     }
+    true
 }
 
-/// Process all complete HTTP/2 frames in `h2.recv_buf` (incoming / response side).
-fn process_h2_recv_frames(h2: &mut H2ConnState) {
-    while let Some((payload_len, frame_type, flags, stream_id)) =
-        parse_h2_frame_header(&h2.recv_buf)
-    {
-        let total = H2_FRAME_HDR_LEN + payload_len;
-        if h2.recv_buf.len() < total {
-            break;
-        }
+// Batching (`PHANTOM_BATCH_MS`): small messages are held back and sent
+// together as `magic | (length: u32 LE | message)*`, at most one datagram's
+// worth, instead of one syscall each. A flusher thread sends a pending batch
+// after the interval, and an atexit handler sends the last one (`_exit()` and
+// fatal signals still lose it).
 
-        let payload = h2.recv_buf[H2_FRAME_HDR_LEN..total].to_vec();
-        h2.recv_buf.drain(..total);
-
-        let tls = h2.tls;
-        match frame_type {
-            H2_TYPE_HEADERS if stream_id > 0 => {
-                let end_stream = flags & H2_FLAG_END_STREAM != 0;
-                let end_headers = flags & H2_FLAG_END_HEADERS != 0;
-                let (hb_start, hb_end) = h2_header_block_range(&payload, flags);
-                let hblock = &payload[hb_start..hb_end];
-
-                if end_headers {
-                    let decoded = h2.recv_hpack.decode(hblock).unwrap_or_default();
-                    let stream = h2
-                        .streams
-                        .entry(stream_id)
-                        .or_insert_with(|| H2Stream::new(tls));
-                    apply_h2_response_headers(stream, decoded);
-                    stream.resp_done |= end_stream;
-                } else {
-                    h2.recv_cont_sid = Some(stream_id);
-                    h2.recv_cont_buf = hblock.to_vec();
-                    h2.recv_cont_end_stream = end_stream;
-                }
-            }
-            H2_TYPE_DATA if stream_id > 0 => {
-                let end_stream = flags & H2_FLAG_END_STREAM != 0;
-                let (data_start, data_end) = if flags & H2_FLAG_PADDED != 0 && !payload.is_empty() {
-                    let pad = payload[0] as usize;
-                    (1, payload.len().saturating_sub(pad))
-                } else {
-                    (0, payload.len())
-                };
-                if let Some(stream) = h2.streams.get_mut(&stream_id) {
-                    if stream.resp_body.len() < MAX_BUF {
-                        stream
-                            .resp_body
-                            .extend_from_slice(&payload[data_start..data_end]);
-                    }
-                    stream.resp_done |= end_stream;
-                }
-            }
-            H2_TYPE_CONTINUATION if stream_id > 0 && h2.recv_cont_sid == Some(stream_id) => {
-                h2.recv_cont_buf.extend_from_slice(&payload);
-                if flags & H2_FLAG_END_HEADERS != 0 {
-                    let hblock = std::mem::take(&mut h2.recv_cont_buf);
-                    let decoded = h2.recv_hpack.decode(&hblock).unwrap_or_default();
-                    let end_stream = h2.recv_cont_end_stream;
-                    let stream = h2
-                        .streams
-                        .entry(stream_id)
-                        .or_insert_with(|| H2Stream::new(tls));
-                    apply_h2_response_headers(stream, decoded);
-                    stream.resp_done |= end_stream;
-                    h2.recv_cont_sid = None;
-                    h2.recv_cont_end_stream = false;
-                }
-            }
-            _ => {}
+/// First bytes of a batch; JSON and MessagePack messages never start with NUL.
+const BATCH_MAGIC: &[u8; 4] = b"\0PHB";
+
+static BATCH_INTERVAL: OnceLock<Option<Duration>> = OnceLock::new();
+/// Messages waiting to be sent, starting with [`BATCH_MAGIC`] when non-empty.
+static BATCH: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+/// Pid of the process the flusher thread was started in (see
+/// [`CLOSE_REAPER_PID`]).
+static BATCH_FLUSHER_PID: AtomicU32 = AtomicU32::new(0);
+
+fn batch_interval() -> Option<Duration> {
+    *BATCH_INTERVAL.get_or_init(|| {
+        let ms = env_size("PHANTOM_BATCH_MS").unwrap_or(0) as u64;
+        (ms > 0).then(|| Duration::from_millis(ms))
+    })
+}
+
+/// Sends one serialized message, through the batch when batching is on.
+fn ipc_emit(data: &[u8]) {
+    let batch = batch_interval().and_then(|interval| {
+        // Larger messages go out on their own, after what is already batched.
+        if BATCH_MAGIC.len() + 4 + data.len() > MAX_DATAGRAM {
+            flush_batch();
+            return None;
         }
+        Some((interval, BATCH.lock().ok()?))
+    });
+    let Some((interval, mut batch)) = batch else {
+        ipc_send(data);
+        return;
+    };
+    if batch.len() + 4 + data.len() > MAX_DATAGRAM {
+        ipc_send(&batch);
+        batch.clear();
+    }
+    if batch.is_empty() {
+        batch.extend_from_slice(BATCH_MAGIC);
+        start_batch_flusher(interval);
     }
+    batch.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    batch.extend_from_slice(data);
 }
 
-/// Remove and return all streams that have a complete response (status + END_STREAM).
-fn drain_completed_h2_streams(h2: &mut H2ConnState) -> Vec<H2Stream> {
-    let done_ids: Vec<u32> = h2
-        .streams
-        .iter()
-        .filter(|(_, s)| s.resp_status.is_some() && s.resp_done)
-        .map(|(id, _)| *id)
-        .collect();
-    let mut completed = Vec::with_capacity(done_ids.len());
-    for id in done_ids {
-        if let Some(stream) = h2.streams.remove(&id) {
-            completed.push(stream);
-        }
-    }
-    completed
-}
-
-/// Build and send a `TraceMsg` for a completed HTTP/2 stream.
-fn emit_h2_stream(stream: H2Stream) {
-    let method = stream.req_method.unwrap_or_else(|| "GET".to_string());
-    let path = stream.req_path.unwrap_or_else(|| "/".to_string());
-    let authority = stream.req_authority.unwrap_or_default();
-    let scheme = stream
-        .req_scheme
-        .unwrap_or_else(|| if stream.tls { "https" } else { "http" }.to_string());
-    let url = format!("{scheme}://{authority}{path}");
-    let status_code = stream.resp_status.unwrap_or(0);
-    let duration = stream.started_at.elapsed();
-
-    emit_msg(&TraceMsg {
-        method,
-        url,
-        status_code,
-        request_headers: stream.req_headers,
-        response_headers: stream.resp_headers,
-        request_body_b64: body_b64(&stream.req_body),
-        response_body_b64: body_b64(&stream.resp_body),
-        duration_ms: duration.as_millis() as u64,
-        timestamp_ms: stream.timestamp_ms,
-        dest_addr: None,
-        protocol_version: "HTTP/2".to_string(),
-    });
+/// Sends the pending batch, if any.
+fn flush_batch() {
+    let Ok(mut batch) = BATCH.lock() else { return };
+    if batch.len() > BATCH_MAGIC.len() {
+        ipc_send(&batch);
+    }
+    batch.clear();
+}
+
+fn start_batch_flusher(interval: Duration) {
+    let pid = std::process::id();
+    if BATCH_FLUSHER_PID.swap(pid, Ordering::AcqRel) == pid {
+        return;
+    }
+    register_at_exit();
+    let _ = std::thread::Builder::new()
+        .name("phantom-batch".to_string())
+        .spawn(move || {
+            // Everything this thread does is the agent's own I/O.
+            IN_HOOK.with(|g| g.set(true));
+            loop {
+                std::thread::sleep(interval);
+                flush_batch();
+            }
+        });
 }
 
 // ─────────────────────────────────────────────────────────────────────────────
-// Per-connection state machine
+// Emitter thread — hooks queue finished messages, serialization and sending
+// happen off the application's I/O path
 //
-// Keyed by `usize`: file descriptors (small integers) for plain sockets,
-// SSL* pointer addresses (large heap values) for TLS connections.
-// No collision is possible because FDs are in [0, 65535] and heap pointers
-// are well above that range on 64-bit systems.
+// The queue is a bounded channel. When it is full a datagram-mode message is
+// dropped, as the datagram itself would be; the connected modes wait for room
+// instead. If the thread cannot be started, messages are sent inline.
 // ─────────────────────────────────────────────────────────────────────────────
 
-struct ReqInfo {
-    method: String,
-    url: String,
-    headers: HashMap<String, String>,
-    body: Vec<u8>,
-    started_at: Instant,
-    timestamp_ms: u64,
-}
+/// Messages queued before hooks start dropping (or waiting).
+const EMIT_QUEUE: usize = 4096;
+/// How long `exit()` waits for queued messages to be sent.
+const EXIT_DRAIN: Duration = Duration::from_millis(200);
 
-enum FdState {
-    /// Still accumulating the HTTP request bytes.
-    CollectingRequest { buf: Vec<u8> },
-    /// Request fully parsed; accumulating HTTP response bytes.
-    CollectingResponse {
-        req: Box<ReqInfo>,
-        buf: Vec<u8>,
-        tls: bool,
-        // Populated once response headers are parsed:
-        status_code: Option<u16>,
-        resp_headers: Option<HashMap<String, String>>,
-        content_length: Option<usize>,
-        headers_end: Option<usize>,
-    },
-    /// HTTP/2 connection (may carry many multiplexed streams).
-    Http2(Box<H2ConnState>),
+/// A finished message on its way to phantom; serializes as the message itself.
+#[derive(serde::Serialize)]
+#[serde(untagged)]
+enum AgentMsg {
+    Trace(Box<TraceMsg>),
+    Thrift(ThriftTraceMsg),
+    Postgres(PgTraceMsg),
+    Redis(RedisTraceMsg),
+    Kafka(KafkaTraceMsg),
+    WebSocket(WsFrameMsg),
+    Sse(SseEventMsg),
+    Diagnostic(DiagnosticMsg),
+    Stats(StatsMsg),
 }
 
-static STATE_MAP: OnceLock<Mutex<HashMap<usize, FdState>>> = OnceLock::new();
+impl From<TraceMsg> for AgentMsg {
+    fn from(msg: TraceMsg) -> Self {
+        Self::Trace(Box::new(msg))
+    }
+}
 
-fn state_map() -> &'static Mutex<HashMap<usize, FdState>> {
-    STATE_MAP.get_or_init(|| Mutex::new(HashMap::new()))
+impl From<ThriftTraceMsg> for AgentMsg {
+    fn from(msg: ThriftTraceMsg) -> Self {
+        Self::Thrift(msg)
+    }
 }
 
-// ─────────────────────────────────────────────────────────────────────────────
-// HTTP detection
-// ─────────────────────────────────────────────────────────────────────────────
+impl From<PgTraceMsg> for AgentMsg {
+    fn from(msg: PgTraceMsg) -> Self {
+        Self::Postgres(msg)
+    }
+}
 
-const HTTP_METHODS: &[&[u8]] = &[
-    b"GET ",
-    b"POST ",
-    b"PUT ",
-    b"DELETE ",
-    b"PATCH ",
-    b"HEAD ",
-    b"OPTIONS ",
-    b"TRACE ",
-    b"CONNECT ",
-];
+impl From<RedisTraceMsg> for AgentMsg {
+    fn from(msg: RedisTraceMsg) -> Self {
+        Self::Redis(msg)
+    }
+}
 
-fn looks_like_http_request(data: &[u8]) -> bool {
-    HTTP_METHODS.iter().any(|m| data.starts_with(m))
+impl From<KafkaTraceMsg> for AgentMsg {
+    fn from(msg: KafkaTraceMsg) -> Self {
+        Self::Kafka(msg)
+    }
 }
 
-// ─────────────────────────────────────────────────────────────────────────────
-// HTTP parsing helpers (using httparse)
-// ─────────────────────────────────────────────────────────────────────────────
+impl From<WsFrameMsg> for AgentMsg {
+    fn from(msg: WsFrameMsg) -> Self {
+        Self::WebSocket(msg)
+    }
+}
 
-fn try_parse_request(buf: &[u8]) -> Option<ReqInfo> {
-    let mut headers_storage = [httparse::EMPTY_HEADER; 64];
-    let mut req = httparse::Request::new(&mut headers_storage);
-    let httparse::Status::Complete(headers_end) = req.parse(buf).ok()? else {
-        return None;
-    };
+impl From<SseEventMsg> for AgentMsg {
+    fn from(msg: SseEventMsg) -> Self {
+        Self::Sse(msg)
+    }
+}
 
-    let method = req.method?.to_string();
-    let path = req.path?.to_string();
-    let mut hmap = HashMap::new();
-    let mut host = String::new();
-    let mut content_length = 0usize;
+impl From<DiagnosticMsg> for AgentMsg {
+    fn from(msg: DiagnosticMsg) -> Self {
+        Self::Diagnostic(msg)
+    }
+}
 
-    for h in req.headers.iter() {
-        let name = h.name.to_lowercase();
-        let value = String::from_utf8_lossy(h.value).into_owned();
-        if name == "host" {
-            host = value.clone();
-        }
-        if name == "content-length" {
-            content_length = value.parse().unwrap_or(0);
-        }
-        hmap.insert(name, value);
+impl From<StatsMsg> for AgentMsg {
+    fn from(msg: StatsMsg) -> Self {
+        Self::Stats(msg)
     }
+}
 
-    let url = if path.starts_with("http://") || path.starts_with("https://") {
-        path
-    } else {
-        format!("http://{host}{path}")
-    };
+/// Bumped in a forked child, whose copy of the emitter has no thread behind it.
+static EMIT_GEN: AtomicU32 = AtomicU32::new(0);
+/// An emitter queue and the generation it was started in.
+type EmitQueue = Option<(u32, SyncSender<AgentMsg>)>;
 
-    // Only take body bytes that are already in the buffer.
-    let body_end = (headers_end + content_length).min(buf.len());
-    let body = buf[headers_end..body_end].to_vec();
+/// The emitter's queue.
+static EMITTER: Mutex<EmitQueue> = Mutex::new(None);
+/// Messages queued but not yet sent.
+static EMIT_PENDING: AtomicUsize = AtomicUsize::new(0);
+static AT_EXIT: Once = Once::new();
 
-    let ts = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_millis() as u64;
-
-    Some(ReqInfo {
-        method,
-        url,
-        headers: hmap,
-        body,
-        started_at: Instant::now(),
-        timestamp_ms: ts,
-    })
+thread_local! {
+    /// This thread's handle on the emitter queue, so queueing takes no lock.
+    static EMIT_TX: RefCell<EmitQueue> = const { RefCell::new(None) };
 }
 
-struct RespMeta {
-    status_code: u16,
-    headers: HashMap<String, String>,
-    content_length: Option<usize>,
-    headers_end: usize,
+/// The queue of the emitter thread for `generation`, starting the thread
+/// the first time.
+fn emitter_tx(generation: u32) -> Option<SyncSender<AgentMsg>> {
+    let mut emitter = EMITTER.lock().ok()?;
+    if let Some((g, tx)) = emitter.as_ref()
+        && *g == generation
+    {
+        return Some(tx.clone());
+    }
+    let (tx, rx) = std::sync::mpsc::sync_channel::<AgentMsg>(EMIT_QUEUE);
+    std::thread::Builder::new()
+        .name("phantom-emitter".to_string())
+        .spawn(move || {
+            // Everything this thread does is the agent's own I/O.
+            IN_HOOK.with(|g| g.set(true));
+            for msg in rx {
+                send_msg(&msg);
+                EMIT_PENDING.fetch_sub(1, Ordering::AcqRel);
+            }
+        })
+        .ok()?;
+    register_at_exit();
+    *emitter = Some((generation, tx.clone()));
+    Some(tx)
 }
 
-fn try_parse_response_headers(buf: &[u8]) -> Option<RespMeta> {
-    let mut headers_storage = [httparse::EMPTY_HEADER; 64];
-    let mut resp = httparse::Response::new(&mut headers_storage);
-    let httparse::Status::Complete(headers_end) = resp.parse(buf).ok()? else {
-        return None;
-    };
-
-    let status_code = resp.code?;
-    let mut hmap = HashMap::new();
-    let mut content_length: Option<usize> = None;
-    let mut is_chunked = false;
+fn register_at_exit() {
+    AT_EXIT.call_once(|| {
+        // SAFETY: agent_at_exit is a plain function that lives for the process.
+        unsafe { libc::atexit(agent_at_exit) };
+    });
+}
 
-    for h in resp.headers.iter() {
-        let name = h.name.to_lowercase();
-        let value = String::from_utf8_lossy(h.value).into_owned();
-        if name == "content-length" {
-            content_length = value.parse().ok();
-        }
-        if name == "transfer-encoding" && value.to_lowercase().contains("chunked") {
-            is_chunked = true;
-        }
-        hmap.insert(name, value);
+/// Gives the emitter a moment to send what is queued, then sends the batch.
+extern "C" fn agent_at_exit() {
+    let was_in_hook = IN_HOOK.try_with(|g| g.replace(true));
+    let deadline = Instant::now() + EXIT_DRAIN;
+    while EMIT_PENDING.load(Ordering::Acquire) > 0 && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(1));
     }
-
-    // For chunked responses we wait until the connection closes.
-    if is_chunked {
-        content_length = None;
+    // A last report, so short-lived processes still show their drops.
+    if STATS_STARTED.load(Ordering::Acquire) {
+        send_msg(&stats_msg().into());
+    }
+    flush_batch();
+    if let Ok(was_in_hook) = was_in_hook {
+        let _ = IN_HOOK.try_with(|g| g.set(was_in_hook));
     }
-
-    Some(RespMeta {
-        status_code,
-        headers: hmap,
-        content_length,
-        headers_end,
-    })
 }
 
 // ─────────────────────────────────────────────────────────────────────────────
-// Emit a completed HTTP/1.x trace
+// Protocol selection (`PHANTOM_PROTOCOLS`)
+//
+// A comma-separated list of the protocols to follow, e.g. `http,http2`. Only
+// those state machines look at traffic, so an unrelated service on a
+// well-known port is not misread and connections of unlisted protocols cost
+// nothing past the check. Unset (or empty) follows everything; unknown names
+// are ignored. WebSocket and SSE hang off an HTTP/1 response: with them off
+// the upgrade or event-stream response is traced, what follows is not.
 // ─────────────────────────────────────────────────────────────────────────────
 
-fn do_emit(
-    req: ReqInfo,
-    status_code: u16,
-    resp_headers: HashMap<String, String>,
-    resp_body: &[u8],
-    duration: Duration,
-    tls: bool,
-) {
-    let url = if tls && req.url.starts_with("http://") {
-        req.url.replacen("http://", "https://", 1)
+#[derive(Clone, Copy)]
+enum Protocol {
+    Http,
+    Http2,
+    WebSocket,
+    Sse,
+    Thrift,
+    Postgres,
+    Redis,
+    Kafka,
+}
+
+impl Protocol {
+    const ALL: [Self; 8] = [
+        Self::Http,
+        Self::Http2,
+        Self::WebSocket,
+        Self::Sse,
+        Self::Thrift,
+        Self::Postgres,
+        Self::Redis,
+        Self::Kafka,
+    ];
+
+    /// Names accepted in `PHANTOM_PROTOCOLS`.
+    fn names(self) -> &'static [&'static str] {
+        match self {
+            Self::Http => &["http", "http1"],
+            Self::Http2 => &["http2", "h2", "grpc"],
+            Self::WebSocket => &["websocket", "ws"],
+            Self::Sse => &["sse"],
+            Self::Thrift => &["thrift"],
+            Self::Postgres => &["postgres", "postgresql", "pg"],
+            Self::Redis => &["redis"],
+            Self::Kafka => &["kafka"],
+        }
+    }
+
+    fn bit(self) -> u32 {
+        1 << self as u32
+    }
+}
+
+static PROTOCOLS: OnceLock<u32> = OnceLock::new();
+
+/// Whether `PHANTOM_PROTOCOLS` leaves `protocol` on.
+fn protocol_enabled(protocol: Protocol) -> bool {
+    let enabled = *PROTOCOLS.get_or_init(|| {
+        let list = config_var("PHANTOM_PROTOCOLS").unwrap_or_default();
+        if list.trim().is_empty() {
+            return u32::MAX;
+        }
+        list.split(',')
+            .map(str::trim)
+            .filter_map(|name| {
+                Protocol::ALL
+                    .into_iter()
+                    .find(|p| p.names().iter().any(|n| n.eq_ignore_ascii_case(name)))
+            })
+            .fold(0, |mask, p| mask | p.bit())
+    });
+    enabled & protocol.bit() != 0
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Host/path filters (`PHANTOM_INCLUDE` / `PHANTOM_EXCLUDE`)
+//
+// Comma-separated globs (`*` any run, `?` one character). A pattern starting
+// with `/` matches the URL path (without query), any other the host (without
+// port, case-insensitively). With includes set, only messages matching one are
+// kept; a message matching an exclude is dropped. Protocol traces match their
+// destination host; messages with neither URL nor destination always pass.
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[derive(Default)]
+struct TargetFilter {
+    include: Vec<String>,
+    exclude: Vec<String>,
+}
+
+static TARGET_FILTER: OnceLock<TargetFilter> = OnceLock::new();
+
+fn target_filter() -> &'static TargetFilter {
+    TARGET_FILTER.get_or_init(|| TargetFilter {
+        include: env_patterns("PHANTOM_INCLUDE"),
+        exclude: env_patterns("PHANTOM_EXCLUDE"),
+    })
+}
+
+fn env_patterns(name: &str) -> Vec<String> {
+    let Some(value) = config_var(name) else {
+        return Vec::new();
+    };
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .map(|p| {
+            if p.starts_with('/') {
+                p.to_string()
+            } else {
+                p.to_ascii_lowercase()
+            }
+        })
+        .collect()
+}
+
+impl TargetFilter {
+    fn allows(&self, host: Option<&str>, path: Option<&str>) -> bool {
+        if self.include.is_empty() && self.exclude.is_empty() {
+            return true;
+        }
+        let host = host.map(str::to_ascii_lowercase);
+        let matches = |pattern: &String| match pattern.starts_with('/') {
+            true => path.is_some_and(|path| glob_match(pattern, path)),
+            false => host
+                .as_deref()
+                .is_some_and(|host| glob_match(pattern, host)),
+        };
+        (self.include.is_empty() || self.include.iter().any(matches))
+            && !self.exclude.iter().any(matches)
+    }
+}
+
+/// Anchored glob match: `*` matches any run of bytes, `?` exactly one.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let (p, t) = (pattern.as_bytes(), text.as_bytes());
+    let (mut pi, mut ti) = (0, 0);
+    // Position after the last `*`, and the text position it was tried at.
+    let mut star: Option<(usize, usize)> = None;
+    while ti < t.len() {
+        if pi < p.len() && (p[pi] == b'?' || p[pi] == t[ti]) {
+            pi += 1;
+            ti += 1;
+        } else if pi < p.len() && p[pi] == b'*' {
+            star = Some((pi + 1, ti));
+            pi += 1;
+        } else if let Some((after, tried)) = star {
+            // Let the last `*` swallow one more byte and retry.
+            star = Some((after, tried + 1));
+            pi = after;
+            ti = tried + 1;
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|&b| b == b'*')
+}
+
+/// Host (without port) of a URL's authority or a `host:port` destination.
+fn strip_port(authority: &str) -> &str {
+    if let Some(v6) = authority.strip_prefix('[') {
+        return v6.split(']').next().unwrap_or(v6);
+    }
+    match authority.rsplit_once(':') {
+        Some((host, port)) if port.bytes().all(|b| b.is_ascii_digit()) => host,
+        _ => authority,
+    }
+}
+
+/// Host and path (without query or fragment) of an absolute URL.
+fn url_host_path(url: &str) -> (&str, &str) {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let (authority, path) = rest.find('/').map_or((rest, "/"), |i| rest.split_at(i));
+    let path = path.split(['?', '#']).next().unwrap_or(path);
+    (strip_port(authority), path)
+}
+
+impl AgentMsg {
+    /// Whether `PHANTOM_INCLUDE` / `PHANTOM_EXCLUDE` drop the message.
+    fn filtered_out(&self) -> bool {
+        let filter = target_filter();
+        if filter.include.is_empty() && filter.exclude.is_empty() {
+            return false;
+        }
+        let url = match self {
+            Self::Trace(t) => &t.url,
+            Self::WebSocket(f) => &f.url,
+            Self::Sse(e) => &e.url,
+            Self::Postgres(t) => return !filter.allows(Some(strip_port(&t.dest_addr)), None),
+            Self::Redis(t) => return !filter.allows(Some(strip_port(&t.dest_addr)), None),
+            Self::Kafka(t) => return !filter.allows(Some(strip_port(&t.dest_addr)), None),
+            Self::Thrift(_) | Self::Diagnostic(_) | Self::Stats(_) => return false,
+        };
+        let (host, path) = url_host_path(url);
+        !filter.allows(Some(host), Some(path))
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Sampling (`PHANTOM_SAMPLE_RATE`) — keep a fraction of traces, and every
+// failure
+//
+// Each request/response trace is kept with the given probability; failed ones
+// (HTTP 4xx/5xx or non-zero `grpc-status`, PostgreSQL and Redis errors, Kafka
+// error codes, Thrift exceptions) are always kept. WebSocket frames and SSE
+// events belong to a stream and are not sampled.
+// ─────────────────────────────────────────────────────────────────────────────
+
+static SAMPLE_RATE: OnceLock<Option<f64>> = OnceLock::new();
+
+thread_local! {
+    /// xorshift64* state, seeded on first use.
+    static SAMPLE_RNG: Cell<u64> = const { Cell::new(0) };
+}
+
+/// The fraction of traces to keep, or `None` to keep all.
+fn sample_rate() -> Option<f64> {
+    *SAMPLE_RATE.get_or_init(|| {
+        let rate: f64 = config_var("PHANTOM_SAMPLE_RATE")?.trim().parse().ok()?;
+        (rate < 1.0).then(|| rate.max(0.0))
+    })
+}
+
+/// A uniform value in `[0, 1)`.
+fn sample_roll() -> f64 {
+    let next = |mut x: u64| {
+        if x == 0 {
+            // Seed from the clock and this thread's stack address.
+            let nanos = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .subsec_nanos();
+            x = ((u64::from(nanos) << 32) ^ (&x as *const u64 as u64)) | 1;
+        }
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        x
+    };
+    let x = SAMPLE_RNG
+        .try_with(|state| {
+            let x = next(state.get());
+            state.set(x);
+            x
+        })
+        .unwrap_or_else(|_| next(0));
+    (x.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 11) as f64 / (1u64 << 53) as f64
+}
+
+impl AgentMsg {
+    /// Whether the message reports a failed request, which sampling keeps.
+    fn is_failure(&self) -> bool {
+        let grpc_failed = |headers: &HashMap<String, String>| {
+            headers.get("grpc-status").is_some_and(|s| s != "0")
+        };
+        match self {
+            Self::Trace(t) => {
+                t.status_code >= 400
+                    || grpc_failed(&t.response_trailers)
+                    || grpc_failed(&t.response_headers)
+            }
+            Self::Thrift(t) => t.message_type == "exception",
+            Self::Postgres(t) => t.error.is_some(),
+            Self::Redis(t) => t.error.is_some(),
+            Self::Kafka(t) => t.error_code.is_some_and(|code| code != 0),
+            Self::WebSocket(_) | Self::Sse(_) | Self::Diagnostic(_) | Self::Stats(_) => false,
+        }
+    }
+
+    /// Whether the message is dropped by sampling.
+    fn sampled_out(&self) -> bool {
+        let Some(rate) = sample_rate() else {
+            return false;
+        };
+        match self {
+            Self::WebSocket(_) | Self::Sse(_) | Self::Diagnostic(_) | Self::Stats(_) => false,
+            _ => !self.is_failure() && sample_roll() >= rate,
+        }
+    }
+}
+
+/// Queues a finished message for the emitter thread.
+fn emit_msg(msg: impl Into<AgentMsg>) {
+    let msg = msg.into();
+    if msg.filtered_out() || msg.sampled_out() {
+        return;
+    }
+    let mut msg = Some(msg);
+    let _ = EMIT_TX.try_with(|cell| {
+        let Ok(mut cached) = cell.try_borrow_mut() else {
+            return;
+        };
+        let generation = EMIT_GEN.load(Ordering::Acquire);
+        if cached.as_ref().is_none_or(|(g, _)| *g != generation) {
+            *cached = emitter_tx(generation).map(|tx| (generation, tx));
+        }
+        let Some((_, tx)) = cached.as_ref() else {
+            return;
+        };
+        let Some(queued) = msg.take() else { return };
+        EMIT_PENDING.fetch_add(1, Ordering::AcqRel);
+        let result = if ipc_mode() == IpcMode::Datagram {
+            tx.try_send(queued)
+        } else {
+            tx.send(queued).map_err(|e| TrySendError::Disconnected(e.0))
+        };
+        if let Err(e) = result {
+            EMIT_PENDING.fetch_sub(1, Ordering::AcqRel);
+            match e {
+                TrySendError::Disconnected(back) => msg = Some(back),
+                TrySendError::Full(_) => {
+                    DROPPED.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+    });
+    // No emitter (thread teardown, or the thread could not be started).
+    if let Some(msg) = msg {
+        send_msg(&msg);
+    }
+}
+
+/// Serializes and sends one message.
+fn send_msg(msg: &AgentMsg) {
+    let emit = |data: &mut Vec<u8>| {
+        if encode_msg(data, msg) {
+            SENT.fetch_add(1, Ordering::Relaxed);
+            ipc_emit(data);
+        } else {
+            DROPPED.fetch_add(1, Ordering::Relaxed);
+        }
+    };
+    let reused = MSG_BUF.try_with(|cell| {
+        let Ok(mut data) = cell.try_borrow_mut() else {
+            return false;
+        };
+        data.clear();
+        emit(&mut data);
+        true
+    });
+    // The buffer is gone during thread teardown; never panic inside a hook.
+    if !matches!(reused, Ok(true)) {
+        emit(&mut Vec::new());
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Base64 encoder (avoids adding an external crate to the dylib)
+// ─────────────────────────────────────────────────────────────────────────────
+
+const B64_TABLE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encodes three input bytes into four output bytes.
+#[inline]
+fn b64_quad(b0: u8, b1: u8, b2: u8) -> [u8; 4] {
+    let n = (u32::from(b0) << 16) | (u32::from(b1) << 8) | u32::from(b2);
+    [
+        B64_TABLE[(n >> 18) as usize & 63],
+        B64_TABLE[(n >> 12) as usize & 63],
+        B64_TABLE[(n >> 6) as usize & 63],
+        B64_TABLE[n as usize & 63],
+    ]
+}
+
+/// Standard padded base64, written into a buffer allocated once at its
+/// final size.
+fn b64_encode(data: &[u8]) -> String {
+    let mut out = vec![0u8; data.len().div_ceil(3) * 4];
+    let chunks = data.chunks_exact(3);
+    let rem = chunks.remainder();
+    let mut dst = out.chunks_exact_mut(4);
+    for (src, dst) in chunks.zip(&mut dst) {
+        dst.copy_from_slice(&b64_quad(src[0], src[1], src[2]));
+    }
+    if let Some(dst) = dst.next() {
+        let quad = b64_quad(rem[0], rem.get(1).copied().unwrap_or(0), 0);
+        dst.copy_from_slice(&quad);
+        dst[3] = b'=';
+        if rem.len() == 1 {
+            dst[2] = b'=';
+        }
+    }
+    // SAFETY: every byte written above comes from B64_TABLE or is b'=', all ASCII.
+    unsafe { String::from_utf8_unchecked(out) }
+}
+
+/// Body or payload bytes: base64 text in JSON, raw bytes in MessagePack.
+struct WireBytes(Vec<u8>);
+
+impl serde::Serialize for WireBytes {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&b64_encode(&self.0))
+        } else {
+            serializer.serialize_bytes(&self.0)
+        }
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+/// Big-endian `u32` at offset `at` of `buf`.
+fn read_be_u32(buf: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(buf.get(at..at + 4)?.try_into().ok()?))
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Connection identity — requests sharing a keep-alive connection share an id
+// ─────────────────────────────────────────────────────────────────────────────
+
+static CONN_IDS: OnceLock<Mutex<HashMap<usize, u64>>> = OnceLock::new();
+static CONN_SEQ: AtomicU64 = AtomicU64::new(1);
+
+/// Returns the id of the connection behind `key`, assigning a fresh one the
+/// first time the key carries HTTP. Ids are released in `process_teardown`,
+/// so a reused fd number starts a new connection.
+fn connection_id(key: usize) -> u64 {
+    let Ok(mut ids) = CONN_IDS.get_or_init(|| Mutex::new(HashMap::new())).lock() else {
+        return 0;
+    };
+    *ids.entry(key)
+        .or_insert_with(|| CONN_SEQ.fetch_add(1, Ordering::Relaxed))
+}
+
+fn release_connection_id(key: usize) {
+    if let Some(Ok(mut ids)) = CONN_IDS.get().map(Mutex::lock) {
+        ids.remove(&key);
+    }
+}
+
+/// Process-qualified connection id as reported to phantom.
+fn connection_label(id: u64) -> String {
+    format!("{}-{id}", std::process::id())
+}
+
+static COMM: OnceLock<String> = OnceLock::new();
+
+/// Command name of this process. A forked child keeps its parent's name, and
+/// exec reloads the agent, so reading it once is enough; the pid is not cached.
+fn process_comm() -> String {
+    COMM.get_or_init(|| {
+        std::fs::read_to_string("/proc/self/comm")
+            .map(|s| s.trim_end().to_string())
+            .unwrap_or_default()
+    })
+    .clone()
+}
+
+thread_local! {
+    /// This thread's kernel id, `0` until first read; reset in a forked child.
+    static TID: Cell<u32> = const { Cell::new(0) };
+}
+
+/// Kernel id (TID) of the calling thread.
+fn current_tid() -> u32 {
+    // SAFETY: gettid(2) takes no arguments and cannot fail.
+    let gettid = || unsafe { libc::syscall(libc::SYS_gettid) } as u32;
+    TID.try_with(|tid| {
+        if tid.get() == 0 {
+            tid.set(gettid());
+        }
+        tid.get()
+    })
+    .unwrap_or_else(|_| gettid())
+}
+
+fn body_b64(raw: &[u8]) -> Option<WireBytes> {
+    if raw.is_empty() {
+        None
     } else {
-        req.url
+        let trunc = &raw[..raw.len().min(max_body())];
+        Some(WireBytes(trunc.to_vec()))
+    }
+}
+
+/// Directory for bodies larger than `max_body()` (`PHANTOM_BODY_DIR`); unset
+/// means oversized bodies are only truncated.
+static BODY_DIR: OnceLock<Option<PathBuf>> = OnceLock::new();
+static BODY_SEQ: AtomicU64 = AtomicU64::new(0);
+
+/// Writes a body that will be truncated in the datagram to its own file and
+/// returns the path. Bodies are buffered up to `max_buf()`, so that is also the
+/// most a file can hold.
+fn body_overflow_file(raw: &[u8], side: &str) -> Option<String> {
+    if raw.len() <= max_body() {
+        return None;
+    }
+    let dir = BODY_DIR
+        .get_or_init(|| config_var("PHANTOM_BODY_DIR").map(PathBuf::from))
+        .as_ref()?;
+    let seq = BODY_SEQ.fetch_add(1, Ordering::Relaxed);
+    let path = dir.join(format!("agent-{}-{seq}.{side}.bin", std::process::id()));
+    std::fs::create_dir_all(dir).ok()?;
+    std::fs::write(&path, raw).ok()?;
+    Some(path.to_string_lossy().into_owned())
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Per-connection state machine
+//
+// Keyed by `usize`: file descriptors (small integers) for plain sockets,
+// SSL* pointer addresses (large heap values) for TLS connections.
+// No collision is possible because FDs are in [0, 65535] and heap pointers
+// are well above that range on 64-bit systems.
+// ─────────────────────────────────────────────────────────────────────────────
+
+struct ReqInfo {
+    method: String,
+    url: String,
+    headers: HashMap<String, String>,
+    body: Vec<u8>,
+    started_at: Instant,
+    timestamp_ms: u64,
+    /// Set from `connection_id()` once the request is attributed to a key.
+    conn_id: u64,
+    /// Destination of a TLS or Unix socket connection, set alongside `conn_id`.
+    dest_addr: Option<String>,
+    /// TLS session details, set alongside `conn_id`.
+    tls_info: Option<TlsInfo>,
+    /// Connection phases, set alongside `conn_id` on a connection's first request.
+    timing: TimingMsg,
+    /// When the first response byte arrived.
+    first_byte_at: Option<Instant>,
+    /// Trace context inserted into the request headers.
+    propagated: Option<TraceContext>,
+    /// Bytes written for the request so far.
+    request_size: u64,
+    /// Bytes received for the response so far.
+    response_size: u64,
+    /// Thread that sent the request.
+    tid: u32,
+}
+
+enum FdState {
+    /// Still accumulating the HTTP request bytes (`sent` counts them all,
+    /// `buf` keeps at most `max_buf()`).
+    CollectingRequest { buf: Vec<u8>, sent: usize },
+    /// Request headers sent; the chunked request body is still being sent.
+    SendingChunkedRequest {
+        req: Box<ReqInfo>,
+        body: Box<ChunkedBody>,
+        tls: bool,
+    },
+    /// Request fully parsed; accumulating HTTP response bytes.
+    CollectingResponse {
+        req: Box<ReqInfo>,
+        buf: Vec<u8>,
+        tls: bool,
+        // Populated once response headers are parsed:
+        status_code: Option<u16>,
+        resp_headers: Option<HashMap<String, String>>,
+        content_length: Option<usize>,
+        headers_end: Option<usize>,
+        /// Set once headers announce `Transfer-Encoding: chunked`; the body
+        /// is then decoded here instead of accumulating in `buf`.
+        chunked: Option<Box<ChunkedBody>>,
+        /// Body bytes counted but not kept in `buf` (`PHANTOM_CAPTURE_BODIES=0`).
+        skipped: usize,
+        /// When response data last arrived (or the request was sent).
+        last_recv: Instant,
+    },
+    /// HTTP/2 connection (may carry many multiplexed streams).
+    Http2(Box<H2ConnState>),
+    /// Thrift RPC connection.
+    Thrift(Box<ThriftConnState>),
+    /// PostgreSQL client connection.
+    Postgres(Box<PgConnState>),
+    /// Redis client connection.
+    Redis(Box<RedisConnState>),
+    /// Kafka client connection.
+    Kafka(Box<KafkaConnState>),
+    /// HTTP/1.1 connection upgraded to WebSocket.
+    WebSocket(Box<WsConnState>),
+    /// `text/event-stream` response whose headers were already emitted.
+    Sse(Box<SseState>),
+}
+
+/// Connection state is split across `1 << STATE_SHARD_BITS` separately
+/// locked maps, so hooks on different connections rarely wait on each other.
+const STATE_SHARD_BITS: u32 = 5;
+
+/// One shard of the connection state. Derefs to the state map; `touched`
+/// records when each key last carried traffic, for the idle sweep.
+#[derive(Default)]
+struct Shard {
+    states: HashMap<usize, FdState>,
+    touched: HashMap<usize, Instant>,
+}
+
+impl Shard {
+    fn touch(&mut self, key: usize) {
+        self.touched.insert(key, Instant::now());
+    }
+
+    /// Stores the state for `key`. A key new to a full shard first evicts
+    /// the least recently touched connection.
+    fn insert(&mut self, key: usize, state: FdState) -> Option<FdState> {
+        if !self.states.contains_key(&key) && self.states.len() >= shard_capacity() {
+            self.evict_oldest();
+        }
+        self.states.insert(key, state)
+    }
+
+    fn remove(&mut self, key: &usize) -> Option<FdState> {
+        self.touched.remove(key);
+        self.states.remove(key)
+    }
+
+    fn clear(&mut self) {
+        self.states.clear();
+        self.touched.clear();
+    }
+}
+
+impl std::ops::Deref for Shard {
+    type Target = HashMap<usize, FdState>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.states
+    }
+}
+
+impl std::ops::DerefMut for Shard {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.states
+    }
+}
+
+type StateShard = Mutex<Shard>;
+
+static STATE_MAP: OnceLock<[StateShard; 1 << STATE_SHARD_BITS]> = OnceLock::new();
+
+fn state_shards() -> &'static [StateShard; 1 << STATE_SHARD_BITS] {
+    STATE_MAP.get_or_init(|| {
+        register_fork_handlers();
+        std::array::from_fn(|_| Mutex::new(Shard::default()))
+    })
+}
+
+/// Shard for `key`. Keys are small dense fds or aligned pointers, so the bits
+/// are mixed (Fibonacci hashing) before the top ones pick the shard.
+fn shard_index(key: usize) -> usize {
+    ((key as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15) >> (u64::BITS - STATE_SHARD_BITS)) as usize
+}
+
+/// The map holding the state for `key`. Code holding one shard never locks
+/// another, except [`rekey_connection`], which locks both in index order.
+fn state_map(key: usize) -> &'static StateShard {
+    &state_shards()[shard_index(key)]
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// fork() — the child starts from a clean slate
+//
+// A forked child inherits the parent's in-flight connection state and its IPC
+// socket, while only the forking thread survives. The fork handlers hold the
+// state, connection-id and IPC locks across `fork()` so the child never
+// inherits them mid-update; the child then drops the inherited state (those
+// requests belong to the parent), any queued or batched messages and the IPC
+// socket, and starts its own emitter on the next trace. Traces carry
+// `getpid()` from when they complete, so they name the child. After `exec()`
+// the agent is loaded afresh.
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Locks taken by `fork_prepare`, in the order the hooks and the emitter
+/// take them.
+struct ForkGuards {
+    /// Every state shard that could be locked, in index order.
+    map: Vec<MutexGuard<'static, Shard>>,
+    ids: Option<MutexGuard<'static, HashMap<usize, u64>>>,
+    emitter: Option<MutexGuard<'static, EmitQueue>>,
+    batch: Option<MutexGuard<'static, Vec<u8>>>,
+    _ipc: Option<MutexGuard<'static, ()>>,
+}
+
+thread_local! {
+    /// Locks taken by `fork_prepare`, released on both sides of the fork.
+    static FORK_GUARDS: RefCell<Option<ForkGuards>> = const { RefCell::new(None) };
+}
+
+static FORK_HANDLERS: Once = Once::new();
+
+fn register_fork_handlers() {
+    FORK_HANDLERS.call_once(|| {
+        // SAFETY: the handlers are plain functions that live for the process.
+        unsafe { libc::pthread_atfork(Some(fork_prepare), Some(fork_parent), Some(fork_child)) };
+    });
+}
+
+extern "C" fn fork_prepare() {
+    // Same order as the hooks: the state shards first, then connection ids, then
+    // the emitter (messages are queued with the others held); the emitter
+    // thread takes the batch and then the IPC writer.
+    let guards = ForkGuards {
+        map: state_shards()
+            .iter()
+            .filter_map(|shard| shard.lock().ok())
+            .collect(),
+        ids: CONN_IDS.get().and_then(|ids| ids.lock().ok()),
+        emitter: EMITTER.lock().ok(),
+        batch: BATCH.lock().ok(),
+        _ipc: IPC_WRITE.lock().ok(),
     };
-    emit_msg(&TraceMsg {
-        method: req.method,
-        url,
-        status_code,
-        request_headers: req.headers,
-        response_headers: resp_headers,
-        request_body_b64: body_b64(&req.body),
-        response_body_b64: body_b64(resp_body),
-        duration_ms: duration.as_millis() as u64,
-        timestamp_ms: req.timestamp_ms,
-        dest_addr: None,
-        protocol_version: "HTTP/1.1".to_string(),
+    let _ = FORK_GUARDS.try_with(|cell| *cell.borrow_mut() = Some(guards));
+}
+
+extern "C" fn fork_parent() {
+    let _ = FORK_GUARDS.try_with(|cell| cell.borrow_mut().take());
+}
+
+extern "C" fn fork_child() {
+    let was_in_hook = IN_HOOK.with(|g| g.replace(true));
+    let _ = FORK_GUARDS.try_with(|cell| {
+        if let Some(guards) = cell.borrow_mut().take() {
+            for mut map in guards.map {
+                map.clear();
+            }
+            if let Some(mut ids) = guards.ids {
+                ids.clear();
+            }
+            // The emitter thread did not survive; the parent sends what was
+            // queued or batched.
+            if let Some(mut emitter) = guards.emitter {
+                *emitter = None;
+            }
+            if let Some(mut batch) = guards.batch {
+                batch.clear();
+            }
+        }
     });
+    EMIT_GEN.fetch_add(1, Ordering::AcqRel);
+    // The forking thread lives on in the child under a new id.
+    let _ = TID.try_with(|tid| tid.set(0));
+    IDLE_REAPER_STARTED.store(false, Ordering::Release);
+    STATS_STARTED.store(false, Ordering::Release);
+    EMIT_PENDING.store(0, Ordering::Release);
+    // The child reports its own counts under its own pid.
+    for counter in [&SENT, &DROPPED, &PARSE_ERRORS] {
+        counter.store(0, Ordering::Relaxed);
+    }
+    HOOKS_SEEN.store(0, Ordering::Relaxed);
+    let fd = IPC_FD.swap(-1, Ordering::AcqRel);
+    if fd >= 0 {
+        // SAFETY: the inherited IPC socket is owned by the agent; the
+        // parent's copy stays open. A connection is closed without shutdown,
+        // so the parent's stays usable.
+        unsafe { redhook::real!(close)(fd) };
+    }
+    IN_HOOK.with(|g| g.set(was_in_hook));
 }
 
 // ─────────────────────────────────────────────────────────────────────────────
@@ -700,229 +1614,2956 @@ fn do_emit(
 // ─────────────────────────────────────────────────────────────────────────────
 
 fn process_outgoing(key: usize, data: &[u8], tls: bool) {
-    let mut map = match state_map().lock() {
+    start_idle_reaper();
+    start_stats_heartbeat();
+    report_evictions();
+    report_config_error();
+    let mut map = match state_map(key).lock() {
         Ok(m) => m,
         Err(_) => return,
     };
+    map.touch(key);
 
     // ── HTTP/2 path ──────────────────────────────────────────────────────────
     // If we already know this connection is HTTP/2, route directly.
     if let Some(FdState::Http2(h2)) = map.get_mut(&key) {
-        if h2.send_buf.len() < MAX_BUF {
+        if h2.send_buf.len() < max_buf() {
             h2.send_buf.extend_from_slice(data);
         }
         process_h2_send_frames(h2);
         return;
     }
     // Detect a new HTTP/2 connection by its client preface.
-    if data.starts_with(H2_PREFACE) {
-        let mut h2 = Box::new(H2ConnState::new(tls));
+    if data.starts_with(H2_PREFACE) && protocol_enabled(Protocol::Http2) {
+        let dest_addr = connection_dest(key, tls);
+        let tls_info = connection_tls(key, tls);
+        let mut h2 = Box::new(H2ConnState::new(
+            tls,
+            connection_id(key),
+            dest_addr,
+            tls_info,
+        ));
+        h2.timing = connection_phases(key, tls);
         h2.send_buf.extend_from_slice(data);
         process_h2_send_frames(&mut h2);
         map.insert(key, FdState::Http2(h2));
         return;
     }
 
-    // ── HTTP/1.x path ────────────────────────────────────────────────────────
-    if looks_like_http_request(data) {
-        // Start fresh tracking for this key (may overwrite stale state).
-        let buf = data.to_vec();
-        if let Some(req_info) = try_parse_request(&buf) {
-            map.insert(
-                key,
-                FdState::CollectingResponse {
-                    req: Box::new(req_info),
-                    buf: Vec::new(),
-                    tls,
-                    status_code: None,
-                    resp_headers: None,
-                    content_length: None,
-                    headers_end: None,
+    // ── WebSocket path ───────────────────────────────────────────────────────
+    if let Some(frames) = track_websocket(&mut map, key, data, true) {
+        drop(map);
+        for msg in frames {
+            emit_msg(msg);
+        }
+        return;
+    }
+
+    // ── PostgreSQL path ──────────────────────────────────────────────────────
+    if protocol_enabled(Protocol::Postgres) && track_postgres_send(&mut map, key, data, tls) {
+        return;
+    }
+
+    // ── Redis path ───────────────────────────────────────────────────────────
+    if protocol_enabled(Protocol::Redis) && track_redis_send(&mut map, key, data, tls) {
+        return;
+    }
+
+    // ── Kafka path ───────────────────────────────────────────────────────────
+    if protocol_enabled(Protocol::Kafka)
+        && let Some(done) = track_kafka(&mut map, key, data, true, tls)
+    {
+        drop(map);
+        for msg in done {
+            emit_msg(msg);
+        }
+        return;
+    }
+
+    // ── Thrift path ──────────────────────────────────────────────────────────
+    if protocol_enabled(Protocol::Thrift)
+        && let Some(done) = track_thrift(&mut map, key, data)
+    {
+        drop(map);
+        if let Some(msg) = done {
+            emit_msg(msg);
+        }
+        return;
+    }
+
+    // ── HTTP/1.x path ────────────────────────────────────────────────────────
+    // A chunked request body comes first: its bytes may look like anything.
+    if let Some(FdState::SendingChunkedRequest { req, body, .. }) = map.get_mut(&key) {
+        req.request_size += data.len() as u64;
+        body.feed(data);
+        if body.done
+            && let Some(FdState::SendingChunkedRequest { mut req, body, tls }) = map.remove(&key)
+        {
+            req.body = body.body;
+            map.insert(key, collecting_response(req, tls));
+        }
+        return;
+    }
+
+    if looks_like_http_request(data) && protocol_enabled(Protocol::Http) {
+        // A new request on a keep-alive connection ends a response still
+        // being collected (its length unknown, or cut short): emit what
+        // arrived instead of dropping it.
+        let stale = match map.get(&key) {
+            Some(FdState::CollectingResponse { .. }) => map.remove(&key),
+            _ => None,
+        };
+
+        // Start fresh tracking for this key (may overwrite stale state).
+        // Parse straight from `data`; only an incomplete request is copied.
+        if let Some((req_info, chunked)) = try_parse_request(data) {
+            map.insert(key, request_sent(key, req_info, chunked, tls));
+        } else {
+            map.insert(
+                key,
+                FdState::CollectingRequest {
+                    buf: data.to_vec(),
+                    sent: data.len(),
                 },
             );
-        } else {
-            map.insert(key, FdState::CollectingRequest { buf });
+        }
+
+        if let Some(state) = stale {
+            drop(map);
+            emit_unfinished_response(state);
         }
     } else {
         // Possible continuation of an incomplete request.
-        let transition = if let Some(FdState::CollectingRequest { buf }) = map.get_mut(&key) {
-            if buf.len() < MAX_BUF {
-                buf.extend_from_slice(data);
+        let transition = match map.get_mut(&key) {
+            Some(FdState::CollectingRequest { buf, sent }) => {
+                *sent += data.len();
+                if buf.len() < max_buf() {
+                    buf.extend_from_slice(data);
+                }
+                // returns owned ReqInfo if complete
+                try_parse_request(buf).map(|(mut req, chunked)| {
+                    req.request_size = *sent as u64;
+                    (req, chunked)
+                })
             }
-            try_parse_request(buf) // returns owned ReqInfo if complete
-        } else {
-            return; // not tracking this key
+            // The rest of a Content-Length body sent after its headers.
+            Some(FdState::CollectingResponse { req, .. }) => {
+                req.request_size += data.len() as u64;
+                return;
+            }
+            _ => return, // not tracking this key
         };
 
         // Borrow of map.get_mut() ends here (transition is owned).
-        if let Some(req_info) = transition {
-            map.insert(
-                key,
-                FdState::CollectingResponse {
-                    req: Box::new(req_info),
-                    buf: Vec::new(),
-                    tls,
-                    status_code: None,
-                    resp_headers: None,
-                    content_length: None,
-                    headers_end: None,
-                },
-            );
+        if let Some((req_info, chunked)) = transition {
+            map.insert(key, request_sent(key, req_info, chunked, tls));
+        }
+    }
+}
+
+fn process_incoming(key: usize, data: &[u8]) {
+    report_evictions();
+    // ── HTTP/2 path ──────────────────────────────────────────────────────────
+    // Handle HTTP/2 streams, collecting those that have a complete response.
+    // We release the lock before emitting.
+    let h2_completed = {
+        let mut map = match state_map(key).lock() {
+            Ok(m) => m,
+            Err(_) => return,
+        };
+        map.touch(key);
+        if let Some(FdState::Http2(h2)) = map.get_mut(&key) {
+            if h2.recv_buf.len() < max_buf() {
+                h2.recv_buf.extend_from_slice(data);
+            }
+            process_h2_recv_frames(h2);
+            Some(drain_completed_h2_streams(h2))
+        } else {
+            None
+        }
+    }; // lock released
+
+    if let Some(completed) = h2_completed {
+        for stream in completed {
+            emit_h2_stream(stream);
+        }
+        return;
+    }
+
+    // ── WebSocket path ───────────────────────────────────────────────────────
+    let websocket = {
+        let mut map = match state_map(key).lock() {
+            Ok(m) => m,
+            Err(_) => return,
+        };
+        track_websocket(&mut map, key, data, false)
+    }; // lock released
+
+    if let Some(frames) = websocket {
+        for msg in frames {
+            emit_msg(msg);
+        }
+        return;
+    }
+
+    // ── Server-Sent Events path ──────────────────────────────────────────────
+    let sse = {
+        let mut map = match state_map(key).lock() {
+            Ok(m) => m,
+            Err(_) => return,
+        };
+        track_sse(&mut map, key, data)
+    }; // lock released
+
+    if let Some(events) = sse {
+        for msg in events {
+            emit_msg(msg);
+        }
+        return;
+    }
+
+    // ── PostgreSQL path ──────────────────────────────────────────────────────
+    let postgres = {
+        let mut map = match state_map(key).lock() {
+            Ok(m) => m,
+            Err(_) => return,
+        };
+        track_postgres_recv(&mut map, key, data)
+    }; // lock released
+
+    if let Some(done) = postgres {
+        for msg in done {
+            emit_msg(msg);
+        }
+        return;
+    }
+
+    // ── Redis path ───────────────────────────────────────────────────────────
+    let redis = {
+        let mut map = match state_map(key).lock() {
+            Ok(m) => m,
+            Err(_) => return,
+        };
+        track_redis_recv(&mut map, key, data)
+    }; // lock released
+
+    if let Some(done) = redis {
+        for msg in done {
+            emit_msg(msg);
+        }
+        return;
+    }
+
+    // ── Kafka path ───────────────────────────────────────────────────────────
+    let kafka = {
+        let mut map = match state_map(key).lock() {
+            Ok(m) => m,
+            Err(_) => return,
+        };
+        track_kafka(&mut map, key, data, false, false)
+    }; // lock released
+
+    if let Some(done) = kafka {
+        for msg in done {
+            emit_msg(msg);
+        }
+        return;
+    }
+
+    // ── Thrift path ──────────────────────────────────────────────────────────
+    let thrift = {
+        let mut map = match state_map(key).lock() {
+            Ok(m) => m,
+            Err(_) => return,
+        };
+        track_thrift(&mut map, key, data)
+    }; // lock released
+
+    if let Some(done) = thrift {
+        if let Some(msg) = done {
+            emit_msg(msg);
+        }
+        return;
+    }
+
+    // ── HTTP/1.x path ────────────────────────────────────────────────────────
+    // Phase 1: accumulate, parse headers if ready, check completeness.
+    // Return owned FdState if the response is complete (to emit outside the lock).
+    let to_emit = {
+        let mut map = match state_map(key).lock() {
+            Ok(m) => m,
+            Err(_) => return,
+        };
+
+        // The server answered before the chunked upload finished: keep the
+        // body decoded so far and collect the response. An interim
+        // `100 Continue` is what the client waits for before sending it.
+        if matches!(map.get(&key), Some(FdState::SendingChunkedRequest { .. })) {
+            if is_continue_response(data) {
+                return;
+            }
+            if let Some(FdState::SendingChunkedRequest { mut req, body, tls }) = map.remove(&key) {
+                req.body = body.body;
+                map.insert(key, collecting_response(req, tls));
+            }
+        }
+
+        let complete = match map.get_mut(&key) {
+            Some(FdState::CollectingResponse {
+                req,
+                buf,
+                status_code,
+                resp_headers,
+                content_length,
+                headers_end,
+                chunked,
+                skipped,
+                last_recv,
+                ..
+            }) => {
+                *last_recv = Instant::now();
+                req.first_byte_at.get_or_insert(*last_recv);
+                req.response_size += data.len() as u64;
+                if let Some(body) = chunked {
+                    body.feed(data);
+                } else if headers_end.is_some() && !capture_bodies() {
+                    *skipped += data.len();
+                } else {
+                    if buf.len() < max_buf() {
+                        buf.extend_from_slice(data);
+                    }
+
+                    // Parse response headers once. Interim responses
+                    // (`100 Continue`, `103 Early Hints`) come before the
+                    // final one: drop them and keep parsing.
+                    while headers_end.is_none() {
+                        let Some(meta) = try_parse_response_headers(buf) else {
+                            break;
+                        };
+                        if is_interim_status(meta.status_code) {
+                            buf.drain(..meta.headers_end);
+                            continue;
+                        }
+                        // The body length is known to be zero regardless of
+                        // headers (RFC 9112 §6.3), so a keep-alive connection
+                        // moves straight on to the next request.
+                        let no_body = req.method == "HEAD" || matches!(meta.status_code, 204 | 304);
+                        // Event streams undo their own chunking.
+                        if meta.chunked && !no_body && !is_event_stream(Some(&meta.headers)) {
+                            let mut body = Box::<ChunkedBody>::default();
+                            body.feed(&buf[meta.headers_end..]);
+                            buf.truncate(meta.headers_end);
+                            *chunked = Some(body);
+                        }
+                        *status_code = Some(meta.status_code);
+                        *resp_headers = Some(meta.headers);
+                        *content_length = if no_body {
+                            Some(0)
+                        } else {
+                            meta.content_length
+                        };
+                        *headers_end = Some(meta.headers_end);
+                        if content_length.is_none() && chunked.is_none() {
+                            start_close_reaper();
+                        }
+                    }
+
+                    // Headers-only mode: count body bytes that came with the
+                    // headers instead of keeping them. WebSocket frames and
+                    // events after the headers are still needed.
+                    if let Some(he) = *headers_end
+                        && !capture_bodies()
+                        && chunked.is_none()
+                        && !is_websocket_upgrade(*status_code, resp_headers.as_ref())
+                        && !is_event_stream(resp_headers.as_ref())
+                    {
+                        *skipped += buf.len() - he;
+                        buf.truncate(he);
+                    }
+                }
+
+                // Check if the body is complete: the last chunk arrived, or
+                // Content-Length bytes did. A WebSocket upgrade has no body:
+                // whatever follows the headers is frames. An event stream is
+                // emitted at its headers; events follow.
+                chunked.as_ref().is_some_and(|body| body.done)
+                    || is_websocket_upgrade(*status_code, resp_headers.as_ref())
+                    || is_event_stream(resp_headers.as_ref())
+                    || matches!(
+                        (*content_length, *headers_end),
+                        (Some(cl), Some(he)) if buf.len() + *skipped >= he + cl
+                    )
+            }
+            _ => false,
+        };
+
+        if complete {
+            map.remove(&key) // → Option<FdState>, owned
+        } else {
+            None
+        }
+    }; // Lock released here
+
+    if let Some(FdState::CollectingResponse {
+        req,
+        buf,
+        tls,
+        status_code: Some(sc),
+        resp_headers: Some(rh),
+        content_length,
+        headers_end: Some(he),
+        chunked,
+        ..
+    }) = to_emit
+    {
+        if is_websocket_upgrade(Some(sc), Some(&rh)) && protocol_enabled(Protocol::WebSocket) {
+            let scheme = if tls { "wss" } else { "ws" };
+            let dest_addr = connection_dest(key, tls)
+                .or_else(|| connection_peer(key, tls).map(|(addr, _)| addr));
+            let url = req.url.replacen("http", scheme, 1);
+            let ws = Box::new(WsConnState::new(url, req.conn_id, dest_addr));
+            let duration = req.started_at.elapsed();
+            do_emit(*req, sc, rh, HashMap::new(), &[], duration, tls);
+
+            let frames = {
+                let mut map = match state_map(key).lock() {
+                    Ok(m) => m,
+                    Err(_) => return,
+                };
+                map.insert(key, FdState::WebSocket(ws));
+                // Frames may have arrived in the same segment as the headers.
+                track_websocket(&mut map, key, &buf[he..], false)
+            }; // lock released
+            for msg in frames.unwrap_or_default() {
+                emit_msg(msg);
+            }
+            return;
+        }
+        if is_event_stream(Some(&rh)) && protocol_enabled(Protocol::Sse) {
+            let chunked = rh
+                .get("transfer-encoding")
+                .is_some_and(|te| te.to_ascii_lowercase().contains("chunked"));
+            let url = if tls {
+                req.url.replacen("http://", "https://", 1)
+            } else {
+                req.url.clone()
+            };
+            let sse = Box::new(SseState::new(url, req.conn_id, req.started_at, chunked));
+            let duration = req.started_at.elapsed();
+            do_emit(*req, sc, rh, HashMap::new(), &[], duration, tls);
+
+            let events = {
+                let mut map = match state_map(key).lock() {
+                    Ok(m) => m,
+                    Err(_) => return,
+                };
+                map.insert(key, FdState::Sse(sse));
+                // Events may have arrived in the same segment as the headers.
+                track_sse(&mut map, key, &buf[he..])
+            }; // lock released
+            for msg in events.unwrap_or_default() {
+                emit_msg(msg);
+            }
+            return;
+        }
+        let duration = req.started_at.elapsed();
+        if let Some(body) = chunked {
+            let ChunkedBody { decoder, body, .. } = *body;
+            do_emit(*req, sc, rh, decoder.trailers, &body, duration, tls);
+            return;
+        }
+        let cl = content_length.unwrap_or(0);
+        let body_end = (he + cl).min(buf.len());
+        do_emit(
+            *req,
+            sc,
+            rh,
+            HashMap::new(),
+            &buf[he..body_end],
+            duration,
+            tls,
+        );
+    }
+}
+
+fn process_teardown(key: usize) {
+    release_connection_id(key);
+    forget_tls_session(key);
+    forget_tls_rewrite(key);
+    flush_connection(key);
+}
+
+/// The peer closed its side (`recv()` returned 0): nothing more will arrive,
+/// so emit what is pending. The fd stays open, so its connection id is kept.
+fn process_eof(key: usize) {
+    flush_connection(key);
+}
+
+/// Drops the state for `key`, emitting any response that got far enough.
+fn flush_connection(key: usize) {
+    let state = {
+        let mut map = match state_map(key).lock() {
+            Ok(m) => m,
+            Err(_) => return,
+        };
+        map.remove(&key)
+    }; // Lock released
+
+    if let Some(state) = state {
+        emit_pending(state);
+    }
+}
+
+/// Emits what a connection's state holds that got far enough to report.
+fn emit_pending(state: FdState) {
+    match state {
+        // HTTP/1.x: emit partial response (e.g. connection-close semantics).
+        state @ FdState::CollectingResponse { .. } => emit_unfinished_response(state),
+        // HTTP/2: emit any streams for which we received at least a response status.
+        FdState::Http2(h2) => {
+            for (_sid, stream) in h2.streams {
+                if stream.resp_status.is_some() {
+                    emit_h2_stream(stream);
+                }
+            }
+        }
+        // If headers were never parsed, we have nothing useful to emit.
+        _ => {}
+    }
+}
+
+/// Emits an HTTP/1.x response that never reached its end, with the body
+/// received so far. Nothing is emitted before the response headers arrive.
+fn emit_unfinished_response(state: FdState) {
+    let FdState::CollectingResponse {
+        req,
+        buf,
+        tls,
+        status_code: Some(sc),
+        resp_headers: Some(rh),
+        content_length,
+        headers_end: Some(he),
+        chunked,
+        ..
+    } = state
+    else {
+        return;
+    };
+    let duration = req.started_at.elapsed();
+    if let Some(body) = chunked {
+        // Truncated chunked body: emit what was decoded.
+        let ChunkedBody { decoder, body, .. } = *body;
+        do_emit(*req, sc, rh, decoder.trailers, &body, duration, tls);
+        return;
+    }
+    let cl = content_length.unwrap_or_else(|| buf.len().saturating_sub(he));
+    let body_end = (he + cl).min(buf.len());
+    do_emit(
+        *req,
+        sc,
+        rh,
+        HashMap::new(),
+        &buf[he..body_end],
+        duration,
+        tls,
+    );
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Close-delimited responses — emitted at EOF, or once the connection idles
+//
+// A response with neither Content-Length nor chunked coding ends when the
+// server closes the connection. The agent sees that as `recv()` returning 0
+// and emits the response right away, but an application that stops reading
+// and leaks the socket never gets there, nor to `close()`. A background
+// thread therefore emits such responses once no data has arrived for a while.
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Idle time before a close-delimited response is emitted, unless
+/// `PHANTOM_CLOSE_IDLE_MS` says otherwise (`0` disables the sweep).
+const CLOSE_IDLE_DEFAULT_MS: u64 = 2000;
+
+static CLOSE_IDLE: OnceLock<Option<Duration>> = OnceLock::new();
+/// Pid of the process the sweep thread was started in. Threads do not survive
+/// `fork()`, so a child starts its own.
+static CLOSE_REAPER_PID: AtomicU32 = AtomicU32::new(0);
+
+fn close_idle_timeout() -> Option<Duration> {
+    *CLOSE_IDLE.get_or_init(|| {
+        let ms = config_var("PHANTOM_CLOSE_IDLE_MS")
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(CLOSE_IDLE_DEFAULT_MS);
+        (ms > 0).then(|| Duration::from_millis(ms))
+    })
+}
+
+/// Starts the idle sweep the first time a close-delimited response is seen.
+/// If the thread cannot be started, EOF and `close()` still emit them.
+fn start_close_reaper() {
+    let Some(timeout) = close_idle_timeout() else {
+        return;
+    };
+    let pid = std::process::id();
+    if CLOSE_REAPER_PID.swap(pid, Ordering::AcqRel) == pid {
+        return;
+    }
+    let _ = std::thread::Builder::new()
+        .name("phantom-reaper".to_string())
+        .spawn(move || {
+            // Everything this thread does is the agent's own I/O.
+            IN_HOOK.with(|g| g.set(true));
+            loop {
+                std::thread::sleep(timeout / 4);
+                for state in take_idle_responses(timeout) {
+                    emit_unfinished_response(state);
+                }
+            }
+        });
+}
+
+/// Removes close-delimited responses that have been idle for `timeout`.
+fn take_idle_responses(timeout: Duration) -> Vec<FdState> {
+    state_shards()
+        .iter()
+        .filter_map(|shard| shard.lock().ok())
+        .flat_map(|mut map| take_idle_in(&mut map, timeout))
+        .collect()
+}
+
+fn take_idle_in(map: &mut Shard, timeout: Duration) -> Vec<FdState> {
+    let idle: Vec<usize> = map
+        .iter()
+        .filter(|(_, state)| {
+            matches!(
+                state,
+                FdState::CollectingResponse {
+                    headers_end: Some(_),
+                    content_length: None,
+                    chunked: None,
+                    last_recv,
+                    ..
+                } if last_recv.elapsed() >= timeout
+            )
+        })
+        .map(|(&key, _)| key)
+        .collect();
+    idle.iter().filter_map(|key| map.remove(key)).collect()
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Idle connections — state left behind by sockets that go quiet
+//
+// Leaked sockets and idle pool connections never reach EOF or `close()`, so
+// whatever they buffered would stay in the state map for good. A low-priority
+// thread sweeps connections without traffic for `PHANTOM_IDLE_TIMEOUT_MS`:
+// HTTP/1.x state is dropped, emitting a response that was already parsed, and
+// HTTP/2 connections emit and forget their open streams. The HPACK tables and
+// the state of other protocols are kept, since decoding resumes with them.
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Default idle time before a connection is swept (`0` disables the sweep).
+const IDLE_TIMEOUT_DEFAULT_MS: u64 = 300_000;
+
+static IDLE_TIMEOUT: OnceLock<Option<Duration>> = OnceLock::new();
+/// Set once the sweep thread runs; cleared in a forked child, which starts
+/// its own.
+static IDLE_REAPER_STARTED: AtomicBool = AtomicBool::new(false);
+
+fn idle_timeout() -> Option<Duration> {
+    *IDLE_TIMEOUT.get_or_init(|| {
+        let ms =
+            env_size("PHANTOM_IDLE_TIMEOUT_MS").map_or(IDLE_TIMEOUT_DEFAULT_MS, |ms| ms as u64);
+        (ms > 0).then(|| Duration::from_millis(ms))
+    })
+}
+
+fn start_idle_reaper() {
+    if IDLE_REAPER_STARTED.load(Ordering::Acquire)
+        || IDLE_REAPER_STARTED.swap(true, Ordering::AcqRel)
+    {
+        return;
+    }
+    let Some(timeout) = idle_timeout() else {
+        return;
+    };
+    let _ = std::thread::Builder::new()
+        .name("phantom-idle".to_string())
+        .spawn(move || {
+            // Everything this thread does is the agent's own I/O.
+            IN_HOOK.with(|g| g.set(true));
+            // On Linux this lowers only the calling thread.
+            // SAFETY: plain setpriority(2) call.
+            unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, 10) };
+            loop {
+                std::thread::sleep((timeout / 4).max(Duration::from_secs(1)));
+                sweep_idle_connections(timeout);
+            }
+        });
+}
+
+fn sweep_idle_connections(timeout: Duration) {
+    for shard in state_shards() {
+        let (states, streams) = {
+            let Ok(mut map) = shard.lock() else { continue };
+            map.take_idle(timeout)
+        }; // lock released
+        states.into_iter().for_each(emit_pending);
+        streams.into_iter().for_each(emit_h2_stream);
+    }
+}
+
+impl Shard {
+    /// Takes the HTTP/1.x state of keys idle for `timeout`, and the streams
+    /// with a response status of idle HTTP/2 connections.
+    fn take_idle(&mut self, timeout: Duration) -> (Vec<FdState>, Vec<H2Stream>) {
+        let Self { states, touched } = self;
+        touched.retain(|key, _| states.contains_key(key));
+        let now = Instant::now();
+        let idle: Vec<usize> = touched
+            .iter()
+            .filter(|(_, at)| now.duration_since(**at) >= timeout)
+            .map(|(&key, _)| key)
+            .collect();
+        let mut taken = Vec::new();
+        let mut streams = Vec::new();
+        for key in idle {
+            match states.get_mut(&key) {
+                Some(FdState::Http2(h2)) => {
+                    let open = h2.streams.drain().map(|(_, stream)| stream);
+                    streams.extend(open.filter(|stream| stream.resp_status.is_some()));
+                    touched.insert(key, now);
+                }
+                Some(
+                    FdState::CollectingRequest { .. }
+                    | FdState::SendingChunkedRequest { .. }
+                    | FdState::CollectingResponse { .. },
+                ) => {
+                    touched.remove(&key);
+                    taken.extend(states.remove(&key));
+                }
+                _ => {
+                    touched.insert(key, now);
+                }
+            }
+        }
+        (taken, streams)
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Connection cap — least recently touched state is evicted
+//
+// A process opening thousands of sockets would otherwise grow the state map
+// without bound. Each shard holds its share of `PHANTOM_MAX_CONNECTIONS`;
+// storing state for a new key in a full shard drops the state that has gone
+// longest without traffic. Evictions are counted and reported to phantom as
+// a `diagnostic` message, at most once a second.
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Default cap on connections with state.
+const MAX_CONNECTIONS_DEFAULT: usize = 8192;
+const EVICTION_REPORT_INTERVAL_MS: u64 = 1000;
+
+static SHARD_CAPACITY: OnceLock<usize> = OnceLock::new();
+/// Evictions not yet reported.
+static EVICTIONS: AtomicU64 = AtomicU64::new(0);
+static EVICTIONS_REPORTED_MS: AtomicU64 = AtomicU64::new(0);
+
+fn max_connections() -> usize {
+    env_size("PHANTOM_MAX_CONNECTIONS").unwrap_or(MAX_CONNECTIONS_DEFAULT)
+}
+
+/// Connections with state each shard may hold.
+fn shard_capacity() -> usize {
+    *SHARD_CAPACITY.get_or_init(|| max_connections().div_ceil(1 << STATE_SHARD_BITS).max(1))
+}
+
+impl Shard {
+    fn evict_oldest(&mut self) {
+        // Keys without a recorded touch sort first.
+        let oldest = self
+            .states
+            .keys()
+            .min_by_key(|key| self.touched.get(*key))
+            .copied();
+        if let Some(key) = oldest {
+            self.remove(&key);
+            EVICTIONS.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+struct DiagnosticMsg {
+    kind: &'static str,
+    message: String,
+    pid: u32,
+    comm: String,
+    timestamp_ms: u64,
+}
+
+/// Reports evictions since the last report, unless one went out within the
+/// last second. Called outside the state locks.
+fn report_evictions() {
+    if EVICTIONS.load(Ordering::Relaxed) == 0 {
+        return;
+    }
+    let now = now_ms();
+    let last = EVICTIONS_REPORTED_MS.load(Ordering::Relaxed);
+    if now < last + EVICTION_REPORT_INTERVAL_MS
+        || EVICTIONS_REPORTED_MS
+            .compare_exchange(last, now, Ordering::AcqRel, Ordering::Relaxed)
+            .is_err()
+    {
+        return;
+    }
+    let count = EVICTIONS.swap(0, Ordering::AcqRel);
+    if count == 0 {
+        return;
+    }
+    emit_msg(DiagnosticMsg {
+        kind: "diagnostic",
+        message: format!(
+            "dropped state of {count} least recently used connection(s): more than {} tracked \
+             (PHANTOM_MAX_CONNECTIONS)",
+            max_connections()
+        ),
+        pid: std::process::id(),
+        comm: process_comm(),
+        timestamp_ms: now,
+    });
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Heartbeat — periodic `agent_stats` report of the agent's own health
+//
+// Once the first traffic is seen, a thread reports every
+// `PHANTOM_STATS_MS` (default 5 s, `0` disables) which hooks have fired, how
+// many messages were sent and dropped, and how many HPACK or chunked-framing
+// errors occurred. Counts are totals since the process started; a last report
+// goes out at exit.
+// ─────────────────────────────────────────────────────────────────────────────
+
+const STATS_INTERVAL_DEFAULT_MS: u64 = 5000;
+
+static STATS_INTERVAL: OnceLock<Option<Duration>> = OnceLock::new();
+/// Set once the heartbeat thread runs; cleared in a forked child.
+static STATS_STARTED: AtomicBool = AtomicBool::new(false);
+/// Messages serialized and handed to the IPC socket (or batch).
+static SENT: AtomicU64 = AtomicU64::new(0);
+/// Messages lost on the way: emitter queue full, too large to fragment,
+/// serialization or socket failure. A failed batch counts once.
+static DROPPED: AtomicU64 = AtomicU64::new(0);
+/// Corrupt HPACK blocks and malformed chunked bodies.
+static PARSE_ERRORS: AtomicU64 = AtomicU64::new(0);
+/// `HOOK_*` bits of the hook families that intercepted traffic.
+static HOOKS_SEEN: AtomicU32 = AtomicU32::new(0);
+
+const HOOK_SOCKET: u32 = 1 << 0;
+const HOOK_OPENSSL: u32 = 1 << 1;
+const HOOK_WOLFSSL: u32 = 1 << 2;
+const HOOK_NSS: u32 = 1 << 3;
+const HOOK_IO_URING: u32 = 1 << 4;
+const HOOK_NAMES: [(u32, &str); 5] = [
+    (HOOK_SOCKET, "socket"),
+    (HOOK_OPENSSL, "openssl"),
+    (HOOK_WOLFSSL, "wolfssl"),
+    (HOOK_NSS, "nss"),
+    (HOOK_IO_URING, "io_uring"),
+];
+
+fn note_hook(bit: u32) {
+    // Hooks run on every I/O call; skip the write once the bit is set.
+    if HOOKS_SEEN.load(Ordering::Relaxed) & bit == 0 {
+        HOOKS_SEEN.fetch_or(bit, Ordering::Relaxed);
+    }
+}
+
+fn stats_interval() -> Option<Duration> {
+    *STATS_INTERVAL.get_or_init(|| {
+        let ms = env_size("PHANTOM_STATS_MS").map_or(STATS_INTERVAL_DEFAULT_MS, |ms| ms as u64);
+        (ms > 0).then(|| Duration::from_millis(ms))
+    })
+}
+
+#[derive(serde::Serialize)]
+struct StatsMsg {
+    kind: &'static str,
+    pid: u32,
+    comm: String,
+    hooks: Vec<&'static str>,
+    sent: u64,
+    dropped: u64,
+    errors: u64,
+    /// Connections with state in the agent right now.
+    connections: usize,
+    timestamp_ms: u64,
+}
+
+fn stats_msg() -> StatsMsg {
+    let seen = HOOKS_SEEN.load(Ordering::Relaxed);
+    StatsMsg {
+        kind: "agent_stats",
+        pid: std::process::id(),
+        comm: process_comm(),
+        hooks: HOOK_NAMES
+            .iter()
+            .filter(|(bit, _)| seen & bit != 0)
+            .map(|&(_, name)| name)
+            .collect(),
+        sent: SENT.load(Ordering::Relaxed),
+        dropped: DROPPED.load(Ordering::Relaxed),
+        errors: PARSE_ERRORS.load(Ordering::Relaxed),
+        connections: state_shards()
+            .iter()
+            .filter_map(|shard| shard.lock().ok().map(|map| map.len()))
+            .sum(),
+        timestamp_ms: now_ms(),
+    }
+}
+
+fn start_stats_heartbeat() {
+    let Some(interval) = stats_interval() else {
+        return;
+    };
+    if STATS_STARTED.load(Ordering::Acquire) || STATS_STARTED.swap(true, Ordering::AcqRel) {
+        return;
+    }
+    register_at_exit();
+    let _ = std::thread::Builder::new()
+        .name("phantom-stats".to_string())
+        .spawn(move || {
+            // Everything this thread does is the agent's own I/O.
+            IN_HOOK.with(|g| g.set(true));
+            loop {
+                emit_msg(stats_msg());
+                std::thread::sleep(interval);
+            }
+        });
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// QUIC fallback — refuse HTTP/3 connection attempts so clients retry over TCP
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// QUIC version 2 (RFC 9369), whose Initial packet type is 0b01 instead of 0b00.
+const QUIC_V2: u32 = 0x6b33_43cf;
+/// Client Initial datagrams are padded to at least this size (RFC 9000 §14.1).
+const QUIC_MIN_INITIAL: usize = 1200;
+
+static BLOCK_QUIC: OnceLock<bool> = OnceLock::new();
+
+fn quic_blocked() -> bool {
+    *BLOCK_QUIC.get_or_init(|| config_var("PHANTOM_BLOCK_QUIC").is_some_and(|v| v == "1"))
+}
+
+/// True for a client QUIC Initial packet: long header with the fixed bit set,
+/// a non-zero version and the Initial packet type for that version.
+fn is_quic_initial(data: &[u8]) -> bool {
+    if data.len() < QUIC_MIN_INITIAL || data[0] & 0xc0 != 0xc0 {
+        return false;
+    }
+    let version = u32::from_be_bytes([data[1], data[2], data[3], data[4]]);
+    let packet_type = (data[0] >> 4) & 0x03;
+    match version {
+        0 => false, // Version negotiation.
+        QUIC_V2 => packet_type == 0b01,
+        _ => packet_type == 0b00,
+    }
+}
+
+fn is_udp_socket(fd: c_int) -> bool {
+    let mut sock_type: c_int = 0;
+    let mut len = std::mem::size_of::<c_int>() as libc::socklen_t;
+    // SAFETY: sock_type/len are valid out-pointers sized for SO_TYPE.
+    let rc = unsafe {
+        libc::getsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            libc::SO_TYPE,
+            &mut sock_type as *mut c_int as *mut c_void,
+            &mut len,
+        )
+    };
+    rc == 0 && sock_type == libc::SOCK_DGRAM
+}
+
+/// Decides whether an outgoing datagram must be refused. The cheap checks
+/// run first so ordinary traffic never pays for the `getsockopt`.
+fn should_block_quic(fd: c_int, data: &[u8]) -> bool {
+    quic_blocked() && is_quic_initial(data) && is_udp_socket(fd)
+}
+
+/// First iovec of a `msghdr` as a byte slice (QUIC stacks put the whole
+/// datagram, or at least its header, in the first buffer).
+///
+/// # Safety
+/// `msg` must be null or point to a valid `msghdr` as passed to `sendmsg(2)`.
+unsafe fn first_iov<'a>(msg: *const libc::msghdr) -> &'a [u8] {
+    // SAFETY: caller guarantees `msg` is a valid msghdr; iov pointers come
+    // from the application's own sendmsg call.
+    unsafe {
+        if msg.is_null() || (*msg).msg_iov.is_null() || (*msg).msg_iovlen == 0 {
+            return &[];
+        }
+        let iov = &*(*msg).msg_iov;
+        if iov.iov_base.is_null() {
+            return &[];
+        }
+        std::slice::from_raw_parts(iov.iov_base as *const u8, iov.iov_len)
+    }
+}
+
+/// Fail the call the way a local firewall rule would.
+fn refuse_quic() -> ssize_t {
+    // SAFETY: __errno_location returns this thread's errno slot.
+    unsafe { *libc::__errno_location() = libc::EPERM };
+    -1
+}
+
+redhook::hook! {
+    unsafe fn sendto(
+        sockfd:    c_int,
+        buf:       *const c_void,
+        len:       size_t,
+        flags:     c_int,
+        dest_addr: *const libc::sockaddr,
+        addrlen:   libc::socklen_t
+    ) -> ssize_t => phantom_sendto {
+        if !buf.is_null() {
+            // SAFETY: buf points to `len` readable bytes (sendto contract).
+            let data = unsafe { std::slice::from_raw_parts(buf as *const u8, len) };
+            if should_block_quic(sockfd, data) {
+                return refuse_quic();
+            }
+        }
+        // Without an address this is send(2) on a connected socket. The
+        // agent's own datagrams always name phantom's socket, so they never
+        // come through here (and run under IN_HOOK regardless).
+        if dest_addr.is_null() {
+            // SAFETY: buf points to `len` readable bytes (sendto contract);
+            // the real sendto(2) gets a live slice.
+            let propagated = unsafe {
+                write_propagated(sockfd, buf, len, |data| {
+                    redhook::real!(sendto)(sockfd, data.as_ptr().cast(), data.len(), flags, dest_addr, addrlen)
+                })
+            };
+            if let Some(result) = propagated {
+                return result;
+            }
+        }
+        // SAFETY: delegating to the real libc sendto(2).
+        let result = unsafe { redhook::real!(sendto)(sockfd, buf, len, flags, dest_addr, addrlen) };
+        if result > 0 && dest_addr.is_null() {
+            IN_HOOK.with(|g| {
+                if !g.get() {
+                    g.set(true);
+                    // SAFETY: buf points to `result` bytes that were sent successfully.
+                    let data = unsafe { std::slice::from_raw_parts(buf as *const u8, result as usize) };
+                    socket_sent(sockfd, data);
+                    g.set(false);
+                }
+            });
+        }
+        result
+    }
+}
+
+redhook::hook! {
+    unsafe fn sendmsg(
+        sockfd: c_int,
+        msg:    *const libc::msghdr,
+        flags:  c_int
+    ) -> ssize_t => phantom_sendmsg {
+        // SAFETY: msg is the application's msghdr for this sendmsg call.
+        if should_block_quic(sockfd, unsafe { first_iov(msg) }) {
+            return refuse_quic();
+        }
+        // SAFETY: delegating to the real libc sendmsg(2).
+        let result = unsafe { redhook::real!(sendmsg)(sockfd, msg, flags) };
+        if !msg.is_null() {
+            // SAFETY: msg is valid; `result` bytes of its iovecs were sent.
+            unsafe { capture_iov(sockfd, (*msg).msg_iov, (*msg).msg_iovlen, result, true) };
+        }
+        result
+    }
+}
+
+redhook::hook! {
+    unsafe fn sendmmsg(
+        sockfd: c_int,
+        msgvec: *mut libc::mmsghdr,
+        vlen:   libc::c_uint,
+        flags:  c_int
+    ) -> c_int => phantom_sendmmsg {
+        if !msgvec.is_null() && vlen > 0 {
+            // SAFETY: msgvec points to `vlen` mmsghdrs; we only read the first.
+            let first = unsafe { first_iov(&(*msgvec).msg_hdr) };
+            if should_block_quic(sockfd, first) {
+                return refuse_quic() as c_int;
+            }
+        }
+        // SAFETY: delegating to the real libc sendmmsg(2).
+        unsafe { redhook::real!(sendmmsg)(sockfd, msgvec, vlen, flags) }
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Socket detection — write()/read() also serve files, pipes and terminals
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Descriptors below this have their kind cached; higher ones are rare
+/// enough to `fstat` on every call.
+const FD_KINDS: usize = 4096;
+const FD_UNKNOWN: u8 = 0;
+const FD_SOCKET: u8 = 1;
+const FD_OTHER: u8 = 2;
+
+/// Kind of each low descriptor, `FD_UNKNOWN` until first checked. Atomics
+/// rather than a locked map: every `write()`/`read()` consults it.
+static FD_KIND: [AtomicU8; FD_KINDS] = [const { AtomicU8::new(FD_UNKNOWN) }; FD_KINDS];
+
+fn fd_kind_slot(fd: c_int) -> Option<&'static AtomicU8> {
+    usize::try_from(fd).ok().and_then(|i| FD_KIND.get(i))
+}
+
+/// Whether `fd` is a socket. The answer is cached until the descriptor is
+/// closed or replaced, so file I/O through `write()`/`read()` costs one
+/// `fstat` per descriptor.
+fn is_socket(fd: c_int) -> bool {
+    let slot = fd_kind_slot(fd);
+    match slot.map(|kind| kind.load(Ordering::Relaxed)) {
+        Some(FD_SOCKET) => return true,
+        Some(FD_OTHER) => return false,
+        _ => {}
+    }
+    // SAFETY: an all-zero stat is a valid value.
+    let mut st: libc::stat = unsafe { std::mem::zeroed() };
+    // SAFETY: st is a valid out-pointer for fstat(2).
+    if unsafe { libc::fstat(fd, &mut st) } != 0 {
+        return false;
+    }
+    let socket = st.st_mode & libc::S_IFMT == libc::S_IFSOCK;
+    if let Some(kind) = slot {
+        kind.store(if socket { FD_SOCKET } else { FD_OTHER }, Ordering::Relaxed);
+    }
+    socket
+}
+
+/// Drops the cached kind of `fd`, which now names another file or nothing.
+/// Lock-free, so hooks call it even when nested or after `fork()`.
+fn forget_fd(fd: c_int) {
+    if let Some(kind) = fd_kind_slot(fd) {
+        kind.store(FD_UNKNOWN, Ordering::Relaxed);
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Inbound connections — accept()/accept4()
+//
+// On a socket the application accepted, the peer is the client: the bytes it
+// receives are requests and the bytes it sends are responses. The libc hooks
+// hand those to the state machines with the directions swapped, so the same
+// client-side tracking traces the requests a server process serves. Only plain
+// sockets are covered; TLS sessions are always treated as client connections.
+// ─────────────────────────────────────────────────────────────────────────────
+
+static INBOUND_FDS: OnceLock<Mutex<HashSet<c_int>>> = OnceLock::new();
+/// Set on the first `accept()`, so processes that never accept skip the lock.
+static ANY_INBOUND: AtomicBool = AtomicBool::new(false);
+
+fn mark_inbound(fd: c_int) {
+    ANY_INBOUND.store(true, Ordering::Relaxed);
+    if let Ok(mut fds) = INBOUND_FDS
+        .get_or_init(|| Mutex::new(HashSet::new()))
+        .lock()
+    {
+        fds.insert(fd);
+    }
+}
+
+fn is_inbound(fd: c_int) -> bool {
+    ANY_INBOUND.load(Ordering::Relaxed)
+        && INBOUND_FDS
+            .get()
+            .and_then(|fds| fds.lock().ok())
+            .is_some_and(|fds| fds.contains(&fd))
+}
+
+fn forget_inbound(fd: c_int) {
+    if let Some(Ok(mut fds)) = INBOUND_FDS.get().map(Mutex::lock) {
+        fds.remove(&fd);
+    }
+}
+
+/// Bytes the application sent on socket `fd`.
+fn socket_sent(fd: c_int, data: &[u8]) {
+    note_hook(HOOK_SOCKET);
+    note_first_write(fd_key(fd) as c_int);
+    if is_inbound(fd) {
+        process_incoming(fd_key(fd), data);
+    } else {
+        process_outgoing(fd_key(fd), data, false);
+    }
+}
+
+/// Bytes the application received on socket `fd`.
+fn socket_received(fd: c_int, data: &[u8]) {
+    note_hook(HOOK_SOCKET);
+    if is_inbound(fd) {
+        process_outgoing(fd_key(fd), data, false);
+    } else {
+        process_incoming(fd_key(fd), data);
+    }
+}
+
+redhook::hook! {
+    unsafe fn accept(
+        sockfd:  c_int,
+        addr:    *mut libc::sockaddr,
+        addrlen: *mut libc::socklen_t
+    ) -> c_int => phantom_accept {
+        // SAFETY: delegating to the real libc accept(2).
+        let fd = unsafe { redhook::real!(accept)(sockfd, addr, addrlen) };
+        if fd >= 0 {
+            IN_HOOK.with(|g| {
+                if !g.get() {
+                    g.set(true);
+                    mark_inbound(fd);
+                    g.set(false);
+                }
+            });
+        }
+        fd
+    }
+}
+
+redhook::hook! {
+    unsafe fn accept4(
+        sockfd:  c_int,
+        addr:    *mut libc::sockaddr,
+        addrlen: *mut libc::socklen_t,
+        flags:   c_int
+    ) -> c_int => phantom_accept4 {
+        // SAFETY: delegating to the real libc accept4(2).
+        let fd = unsafe { redhook::real!(accept4)(sockfd, addr, addrlen, flags) };
+        if fd >= 0 {
+            IN_HOOK.with(|g| {
+                if !g.get() {
+                    g.set(true);
+                    mark_inbound(fd);
+                    g.set(false);
+                }
+            });
+        }
+        fd
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Connection timing
+//
+// `connect()` records when it was called. A blocking connect is up when it
+// returns; a non-blocking one reports no completion the agent can see, so it
+// counts as up at the socket's first write. The first request on the
+// connection takes the result; on a TLS socket the session takes it first and
+// adds the handshake, which ends when the first `SSL_write` goes through.
+// ─────────────────────────────────────────────────────────────────────────────
+
+struct ConnectTiming {
+    started: Instant,
+    connected: Option<Instant>,
+}
+
+static CONNECTS: OnceLock<Mutex<HashMap<c_int, ConnectTiming>>> = OnceLock::new();
+/// Entries of `CONNECTS` still waiting for their first write, so that writes
+/// skip the lock when there are none.
+static CONNECTS_PENDING: AtomicUsize = AtomicUsize::new(0);
+
+fn note_connect(fd: c_int, started: Instant, connected: Option<Instant>) {
+    if let Ok(mut connects) = CONNECTS.get_or_init(|| Mutex::new(HashMap::new())).lock() {
+        if connected.is_none() {
+            CONNECTS_PENDING.fetch_add(1, Ordering::Relaxed);
+        }
+        connects.insert(fd, ConnectTiming { started, connected });
+    }
+}
+
+/// `fd` is being written to: a pending non-blocking connect is up.
+fn note_first_write(fd: c_int) {
+    if CONNECTS_PENDING.load(Ordering::Relaxed) == 0 {
+        return;
+    }
+    if let Some(Ok(mut connects)) = CONNECTS.get().map(Mutex::lock)
+        && let Some(timing) = connects.get_mut(&fd)
+        && timing.connected.is_none()
+    {
+        timing.connected = Some(Instant::now());
+        CONNECTS_PENDING.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Removes the connect timing of `fd`, returning when `connect()` was called
+/// and when the connection came up.
+fn take_connect(fd: c_int) -> Option<(Instant, Instant)> {
+    let timing = CONNECTS.get()?.lock().ok()?.remove(&fd)?;
+    match timing.connected {
+        Some(connected) => Some((timing.started, connected)),
+        None => {
+            CONNECTS_PENDING.fetch_sub(1, Ordering::Relaxed);
+            None
+        }
+    }
+}
+
+fn millis_between(from: Instant, to: Instant) -> u64 {
+    to.saturating_duration_since(from).as_millis() as u64
+}
+
+/// Connection phases for the first request on `key`; empty afterwards.
+fn connection_phases(key: usize, tls: bool) -> TimingMsg {
+    if tls {
+        return TLS_SESSIONS
+            .get()
+            .and_then(|sessions| sessions.lock().ok())
+            .and_then(|mut sessions| Some(std::mem::take(&mut sessions.get_mut(&key)?.timing)))
+            .unwrap_or_default();
+    }
+    let Ok(fd) = c_int::try_from(key) else {
+        return TimingMsg::default();
+    };
+    TimingMsg {
+        connect_ms: take_connect(fd).map(|(started, connected)| millis_between(started, connected)),
+        ..TimingMsg::default()
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Unix domain sockets — local daemons such as `/var/run/docker.sock`
+//
+// Their traffic runs through the same hooks as TCP, but they have no ip:port.
+// `connect()` records the socket path so traces on them can name it, as
+// `unix:<path>` (`unix:@<name>` for the abstract namespace). The same table
+// caches the `ip:port` of plain TCP peers once `connection_dest` looks it up.
+// ─────────────────────────────────────────────────────────────────────────────
+
+static SOCKET_PEERS: OnceLock<Mutex<HashMap<c_int, String>>> = OnceLock::new();
+
+/// `unix:` address for a `sockaddr_un` of `len` bytes, `None` for other
+/// families and unnamed sockets.
+///
+/// # Safety
+/// `addr` must be null or point to `len` readable bytes.
+unsafe fn unix_sockaddr_label(addr: *const libc::sockaddr, len: libc::socklen_t) -> Option<String> {
+    let path_offset = std::mem::offset_of!(libc::sockaddr_un, sun_path);
+    let len = (len as usize).min(std::mem::size_of::<libc::sockaddr_un>());
+    // SAFETY: the caller guarantees `len` readable bytes; the family is read
+    // only when they cover it.
+    if addr.is_null()
+        || len <= path_offset
+        || unsafe { (*addr).sa_family } != libc::AF_UNIX as libc::sa_family_t
+    {
+        return None;
+    }
+    // SAFETY: in a sockaddr_un the path fills the rest of the `len` bytes.
+    let path = unsafe {
+        std::slice::from_raw_parts((addr as *const u8).add(path_offset), len - path_offset)
+    };
+    match path.split_first() {
+        Some((0, name)) => Some(format!("unix:@{}", String::from_utf8_lossy(name))),
+        _ => {
+            let end = path.iter().position(|&b| b == 0).unwrap_or(path.len());
+            (end > 0).then(|| format!("unix:{}", String::from_utf8_lossy(&path[..end])))
+        }
+    }
+}
+
+fn note_socket_peer(fd: c_int, label: String) {
+    if let Ok(mut peers) = SOCKET_PEERS
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+    {
+        peers.insert(fd, label);
+    }
+}
+
+fn socket_peer(fd: c_int) -> Option<String> {
+    SOCKET_PEERS.get()?.lock().ok()?.get(&fd).cloned()
+}
+
+fn forget_socket_peer(fd: c_int) {
+    if let Some(Ok(mut peers)) = SOCKET_PEERS.get().map(Mutex::lock) {
+        peers.remove(&fd);
+    }
+}
+
+redhook::hook! {
+    unsafe fn connect(
+        sockfd:  c_int,
+        addr:    *const libc::sockaddr,
+        addrlen: libc::socklen_t
+    ) -> c_int => phantom_connect {
+        let started = Instant::now();
+        // SAFETY: delegating to the real libc connect(2).
+        let result = unsafe { redhook::real!(connect)(sockfd, addr, addrlen) };
+        // Non-blocking sockets report EINPROGRESS (EAGAIN for AF_UNIX) and
+        // finish connecting in the background. The caller reads errno next,
+        // so it is put back after the bookkeeping.
+        let errno = std::io::Error::last_os_error().raw_os_error().unwrap_or(0);
+        if result == 0 || errno == libc::EINPROGRESS || errno == libc::EAGAIN {
+            IN_HOOK.with(|g| {
+                if !g.get() {
+                    g.set(true);
+                    // A socket being connected is new: anything still recorded
+                    // under its number is from an earlier socket whose close()
+                    // was not seen, so it is torn down as at close().
+                    fd_closed(sockfd);
+                    // SAFETY: addr points to `addrlen` bytes supplied to connect(2).
+                    if let Some(label) = unsafe { unix_sockaddr_label(addr, addrlen) } {
+                        note_socket_peer(sockfd, label);
+                    }
+                    note_connect(sockfd, started, (result == 0).then(Instant::now));
+                    g.set(false);
+                }
+            });
+            // SAFETY: __errno_location returns this thread's errno.
+            unsafe { *libc::__errno_location() = errno };
+        }
+        result
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Descriptor duplication — dup()/dup2()/dup3()/fcntl(F_DUPFD)
+//
+// Duplicates share one open socket, so their traffic belongs to one
+// connection. State stays keyed by the fd that first carried it; duplicates
+// are mapped onto that key, and the connection is torn down only when the
+// last descriptor for it is closed. If the keying fd is closed first, the
+// state moves to a surviving duplicate, since the old number may be reused.
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[derive(Default)]
+struct FdAliases {
+    /// Duplicate → the fd its connection is keyed by.
+    key_of: HashMap<c_int, c_int>,
+    /// Keying fd → its open duplicates.
+    dups: HashMap<c_int, Vec<c_int>>,
+}
+
+static FD_ALIASES: OnceLock<Mutex<FdAliases>> = OnceLock::new();
+/// Set on the first duplication, so processes that never dup skip the lock.
+static ANY_DUP: AtomicBool = AtomicBool::new(false);
+
+/// State-map key for the connection behind `fd`.
+fn fd_key(fd: c_int) -> usize {
+    if ANY_DUP.load(Ordering::Relaxed)
+        && let Some(Ok(aliases)) = FD_ALIASES.get().map(Mutex::lock)
+        && let Some(&key) = aliases.key_of.get(&fd)
+    {
+        return key as usize;
+    }
+    fd as usize
+}
+
+/// Records `new` as a duplicate of `old`. A `new` that was open before has
+/// already been released through [`fd_closed`].
+fn note_dup(old: c_int, new: c_int) {
+    if old == new {
+        return;
+    }
+    if is_inbound(old) {
+        mark_inbound(new);
+    }
+    ANY_DUP.store(true, Ordering::Relaxed);
+    let Ok(mut aliases) = FD_ALIASES.get_or_init(Mutex::default).lock() else {
+        return;
+    };
+    let key = aliases.key_of.get(&old).copied().unwrap_or(old);
+    aliases.key_of.insert(new, key);
+    aliases.dups.entry(key).or_default().push(new);
+}
+
+/// Drops `fd` from the alias table. Returns the key to tear down when `fd`
+/// was the last open descriptor for its connection.
+fn release_fd(fd: c_int) -> Option<usize> {
+    if !ANY_DUP.load(Ordering::Relaxed) {
+        return Some(fd as usize);
+    }
+    let Some(Ok(mut aliases)) = FD_ALIASES.get().map(Mutex::lock) else {
+        return Some(fd as usize);
+    };
+    if let Some(key) = aliases.key_of.remove(&fd) {
+        if let Some(dups) = aliases.dups.get_mut(&key) {
+            dups.retain(|&d| d != fd);
+            if dups.is_empty() {
+                aliases.dups.remove(&key);
+            }
+        }
+        return None;
+    }
+    let mut dups = aliases.dups.remove(&fd)?;
+    let heir = dups.remove(0);
+    aliases.key_of.remove(&heir);
+    for &d in &dups {
+        aliases.key_of.insert(d, heir);
+    }
+    if !dups.is_empty() {
+        aliases.dups.insert(heir, dups);
+    }
+    drop(aliases);
+    rekey_connection(fd as usize, heir as usize);
+    None
+}
+
+/// Moves the state and connection id tracked under `from` to `to`.
+fn rekey_connection(from: usize, to: usize) {
+    let (a, b) = (shard_index(from), shard_index(to));
+    let shards = state_shards();
+    if a == b {
+        if let Ok(mut map) = shards[a].lock()
+            && let Some(state) = map.remove(&from)
+        {
+            map.insert(to, state);
+            map.touch(to);
+        }
+    } else {
+        // Both shards are held, lower index first, so no hook sees the state
+        // in neither.
+        let (lo, hi) = (shards[a.min(b)].lock(), shards[a.max(b)].lock());
+        if let (Ok(lo), Ok(hi)) = (lo, hi) {
+            let (mut src, mut dst) = if a < b { (lo, hi) } else { (hi, lo) };
+            if let Some(state) = src.remove(&from) {
+                dst.insert(to, state);
+                dst.touch(to);
+            }
+        }
+    }
+    if let Some(Ok(mut ids)) = CONN_IDS.get().map(Mutex::lock)
+        && let Some(id) = ids.remove(&from)
+    {
+        ids.insert(to, id);
+    }
+    if let Some(Ok(mut peers)) = SOCKET_PEERS.get().map(Mutex::lock)
+        && let Some(path) = peers.remove(&(from as c_int))
+    {
+        peers.insert(to as c_int, path);
+    }
+    if let Some(Ok(mut connects)) = CONNECTS.get().map(Mutex::lock)
+        && let Some(timing) = connects.remove(&(from as c_int))
+    {
+        connects.insert(to as c_int, timing);
+    }
+}
+
+/// `fd` was closed, explicitly or as the target of `dup2()`/`dup3()`.
+fn fd_closed(fd: c_int) {
+    forget_fd(fd);
+    forget_inbound(fd);
+    if let Some(key) = release_fd(fd) {
+        forget_socket_peer(key as c_int);
+        take_connect(key as c_int);
+        process_teardown(key);
+    }
+}
+
+/// Bookkeeping after a successful duplication of `old` onto `new`.
+fn capture_dup(old: c_int, new: c_int, replaced: bool) {
+    IN_HOOK.with(|g| {
+        if !g.get() {
+            g.set(true);
+            if replaced && new != old {
+                fd_closed(new);
+            }
+            note_dup(old, new);
+            g.set(false);
+        }
+    });
+}
+
+redhook::hook! {
+    unsafe fn dup(oldfd: c_int) -> c_int => phantom_dup {
+        // SAFETY: delegating to the real libc dup(2).
+        let fd = unsafe { redhook::real!(dup)(oldfd) };
+        if fd >= 0 {
+            forget_fd(fd);
+            capture_dup(oldfd, fd, false);
+        }
+        fd
+    }
+}
+
+redhook::hook! {
+    unsafe fn dup2(oldfd: c_int, newfd: c_int) -> c_int => phantom_dup2 {
+        // SAFETY: delegating to the real libc dup2(2).
+        let fd = unsafe { redhook::real!(dup2)(oldfd, newfd) };
+        if fd >= 0 {
+            // Whatever `newfd` referred to before was closed.
+            forget_fd(fd);
+            capture_dup(oldfd, fd, true);
+        }
+        fd
+    }
+}
+
+redhook::hook! {
+    unsafe fn dup3(oldfd: c_int, newfd: c_int, flags: c_int) -> c_int => phantom_dup3 {
+        // SAFETY: delegating to the real libc dup3(2).
+        let fd = unsafe { redhook::real!(dup3)(oldfd, newfd, flags) };
+        if fd >= 0 {
+            forget_fd(fd);
+            capture_dup(oldfd, fd, true);
+        }
+        fd
+    }
+}
+
+// fcntl(2) is variadic. Its one optional argument is an int, long or pointer,
+// which the Linux calling conventions pass exactly like a fixed `long`, so the
+// hooks take it as one and forward it unchanged. glibc 2.28+ exports the same
+// function as `fcntl64`, which newer binaries may call instead.
+
+redhook::hook! {
+    unsafe fn fcntl(fd: c_int, cmd: c_int, arg: libc::c_long) -> c_int => phantom_fcntl {
+        // SAFETY: delegating to the real libc fcntl(2).
+        let result = unsafe { redhook::real!(fcntl)(fd, cmd, arg) };
+        if result >= 0 && matches!(cmd, libc::F_DUPFD | libc::F_DUPFD_CLOEXEC) {
+            forget_fd(result);
+            capture_dup(fd, result, false);
+        }
+        result
+    }
+}
+
+redhook::hook! {
+    unsafe fn fcntl64(fd: c_int, cmd: c_int, arg: libc::c_long) -> c_int => phantom_fcntl64 {
+        // SAFETY: delegating to the real glibc fcntl64.
+        let result = unsafe { redhook::real!(fcntl64)(fd, cmd, arg) };
+        if result >= 0 && matches!(cmd, libc::F_DUPFD | libc::F_DUPFD_CLOEXEC) {
+            forget_fd(result);
+            capture_dup(fd, result, false);
+        }
+        result
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Scatter/gather I/O — iovecs are reassembled before reaching the state machines
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Copies the first `len` bytes spread over `iovcnt` iovecs into one buffer.
+///
+/// # Safety
+/// `iov` must be null or point to `iovcnt` iovecs whose buffers hold at
+/// least `len` initialised bytes between them.
+unsafe fn gather_iov(iov: *const libc::iovec, iovcnt: usize, len: usize) -> Vec<u8> {
+    let mut out = Vec::with_capacity(len);
+    if iov.is_null() {
+        return out;
+    }
+    // SAFETY: caller guarantees `iov` points to `iovcnt` iovecs.
+    let iovs = unsafe { std::slice::from_raw_parts(iov, iovcnt) };
+    for v in iovs {
+        let take = (len - out.len()).min(v.iov_len);
+        if take > 0 && !v.iov_base.is_null() {
+            // SAFETY: the first `take` bytes of this buffer were transferred.
+            out.extend_from_slice(unsafe {
+                std::slice::from_raw_parts(v.iov_base as *const u8, take)
+            });
+        }
+        if out.len() == len {
+            break;
+        }
+    }
+    out
+}
+
+/// Routes the outcome of a vectored socket call (`result` bytes moved
+/// through `iov`) like its `send()`/`recv()` counterpart would.
+///
+/// # Safety
+/// As for [`gather_iov`], with `result` as the length when positive.
+unsafe fn capture_iov(
+    fd: c_int,
+    iov: *const libc::iovec,
+    iovcnt: usize,
+    result: ssize_t,
+    outgoing: bool,
+) {
+    if result < 0 || (result == 0 && (outgoing || iovcnt == 0)) {
+        return;
+    }
+    IN_HOOK.with(|g| {
+        if !g.get() {
+            g.set(true);
+            if is_socket(fd) {
+                if result == 0 {
+                    // Orderly shutdown by the peer.
+                    process_eof(fd_key(fd));
+                } else {
+                    // SAFETY: forwarded from the caller.
+                    let data = unsafe { gather_iov(iov, iovcnt, result as usize) };
+                    if outgoing {
+                        socket_sent(fd, &data);
+                    } else {
+                        socket_received(fd, &data);
+                    }
+                }
+            }
+            g.set(false);
+        }
+    });
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Hooks — libc (plain HTTP)
+// ─────────────────────────────────────────────────────────────────────────────
+
+redhook::hook! {
+    unsafe fn send(
+        sockfd: c_int,
+        buf:    *const c_void,
+        len:    size_t,
+        flags:  c_int
+    ) -> ssize_t => phantom_send {
+        // Connected UDP sockets may send QUIC with plain send(2).
+        if !buf.is_null() {
+            // SAFETY: buf points to `len` readable bytes (send contract).
+            let data = unsafe { std::slice::from_raw_parts(buf as *const u8, len) };
+            if should_block_quic(sockfd, data) {
+                return refuse_quic();
+            }
+        }
+        // SAFETY: buf points to `len` readable bytes (send contract); the
+        // real send(2) gets a live slice.
+        let propagated = unsafe {
+            write_propagated(sockfd, buf, len, |data| {
+                redhook::real!(send)(sockfd, data.as_ptr().cast(), data.len(), flags)
+            })
+        };
+        if let Some(result) = propagated {
+            return result;
+        }
+        // SAFETY: delegating to the real libc send(2).
+        let result = unsafe { redhook::real!(send)(sockfd, buf, len, flags) };
+        if result > 0 {
+            IN_HOOK.with(|g| {
+                if !g.get() {
+                    g.set(true);
+                    // SAFETY: buf points to `result` readable bytes (guaranteed by send contract).
+                    let data = unsafe { std::slice::from_raw_parts(buf as *const u8, result as usize) };
+                    socket_sent(sockfd, data);
+                    g.set(false);
+                }
+            });
+        }
+        result
+    }
+}
+
+redhook::hook! {
+    unsafe fn recv(
+        sockfd: c_int,
+        buf:    *mut c_void,
+        len:    size_t,
+        flags:  c_int
+    ) -> ssize_t => phantom_recv {
+        // SAFETY: delegating to the real libc recv(2).
+        let result = unsafe { redhook::real!(recv)(sockfd, buf, len, flags) };
+        if result > 0 {
+            IN_HOOK.with(|g| {
+                if !g.get() {
+                    g.set(true);
+                    // SAFETY: buf holds `result` initialised bytes written by recv(2).
+                    let data = unsafe { std::slice::from_raw_parts(buf as *const u8, result as usize) };
+                    socket_received(sockfd, data);
+                    g.set(false);
+                }
+            });
+        } else if result == 0 && len > 0 {
+            // Orderly shutdown by the peer.
+            IN_HOOK.with(|g| {
+                if !g.get() {
+                    g.set(true);
+                    process_eof(fd_key(sockfd));
+                    g.set(false);
+                }
+            });
+        }
+        result
+    }
+}
+
+// write()/read() carry socket traffic for programs that never call
+// send()/recv(); bytes on other descriptors are passed through untouched.
+
+redhook::hook! {
+    unsafe fn write(
+        fd:    c_int,
+        buf:   *const c_void,
+        count: size_t
+    ) -> ssize_t => phantom_write {
+        // SAFETY: buf points to `count` readable bytes (write contract); the
+        // real write(2) gets a live slice.
+        let propagated = unsafe {
+            write_propagated(fd, buf, count, |data| {
+                redhook::real!(write)(fd, data.as_ptr().cast(), data.len())
+            })
+        };
+        if let Some(result) = propagated {
+            return result;
+        }
+        // SAFETY: delegating to the real libc write(2).
+        let result = unsafe { redhook::real!(write)(fd, buf, count) };
+        if result > 0 {
+            IN_HOOK.with(|g| {
+                if !g.get() {
+                    g.set(true);
+                    if is_socket(fd) {
+                        // SAFETY: buf points to `result` bytes that were written successfully.
+                        let data = unsafe { std::slice::from_raw_parts(buf as *const u8, result as usize) };
+                        socket_sent(fd, data);
+                    }
+                    g.set(false);
+                }
+            });
+        }
+        result
+    }
+}
+
+redhook::hook! {
+    unsafe fn read(
+        fd:    c_int,
+        buf:   *mut c_void,
+        count: size_t
+    ) -> ssize_t => phantom_read {
+        // SAFETY: delegating to the real libc read(2).
+        let result = unsafe { redhook::real!(read)(fd, buf, count) };
+        if result > 0 || (result == 0 && count > 0) {
+            IN_HOOK.with(|g| {
+                if !g.get() {
+                    g.set(true);
+                    if is_socket(fd) {
+                        if result > 0 {
+                            // SAFETY: buf holds `result` initialised bytes written by read(2).
+                            let data = unsafe { std::slice::from_raw_parts(buf as *const u8, result as usize) };
+                            socket_received(fd, data);
+                        } else {
+                            // Orderly shutdown by the peer.
+                            process_eof(fd_key(fd));
+                        }
+                    }
+                    g.set(false);
+                }
+            });
+        }
+        result
+    }
+}
+
+redhook::hook! {
+    unsafe fn recvfrom(
+        sockfd:   c_int,
+        buf:      *mut c_void,
+        len:      size_t,
+        flags:    c_int,
+        src_addr: *mut libc::sockaddr,
+        addrlen:  *mut libc::socklen_t
+    ) -> ssize_t => phantom_recvfrom {
+        // SAFETY: delegating to the real libc recvfrom(2).
+        let result = unsafe { redhook::real!(recvfrom)(sockfd, buf, len, flags, src_addr, addrlen) };
+        if result > 0 || (result == 0 && len > 0) {
+            IN_HOOK.with(|g| {
+                if !g.get() {
+                    g.set(true);
+                    if result > 0 {
+                        // SAFETY: buf holds `result` initialised bytes written by recvfrom(2).
+                        let data = unsafe { std::slice::from_raw_parts(buf as *const u8, result as usize) };
+                        socket_received(sockfd, data);
+                    } else {
+                        // Orderly shutdown by the peer.
+                        process_eof(fd_key(sockfd));
+                    }
+                    g.set(false);
+                }
+            });
+        }
+        result
+    }
+}
+
+redhook::hook! {
+    unsafe fn recvmsg(
+        sockfd: c_int,
+        msg:    *mut libc::msghdr,
+        flags:  c_int
+    ) -> ssize_t => phantom_recvmsg {
+        // SAFETY: delegating to the real libc recvmsg(2).
+        let result = unsafe { redhook::real!(recvmsg)(sockfd, msg, flags) };
+        if !msg.is_null() {
+            // SAFETY: msg is valid; recvmsg(2) filled `result` bytes of its iovecs.
+            unsafe { capture_iov(sockfd, (*msg).msg_iov, (*msg).msg_iovlen, result, false) };
+        }
+        result
+    }
+}
+
+redhook::hook! {
+    unsafe fn writev(
+        fd:     c_int,
+        iov:    *const libc::iovec,
+        iovcnt: c_int
+    ) -> ssize_t => phantom_writev {
+        // SAFETY: delegating to the real libc writev(2).
+        let result = unsafe { redhook::real!(writev)(fd, iov, iovcnt) };
+        // SAFETY: `result` bytes of the application's iovecs were written.
+        unsafe { capture_iov(fd, iov, iovcnt.max(0) as usize, result, true) };
+        result
+    }
+}
+
+redhook::hook! {
+    unsafe fn readv(
+        fd:     c_int,
+        iov:    *const libc::iovec,
+        iovcnt: c_int
+    ) -> ssize_t => phantom_readv {
+        // SAFETY: delegating to the real libc readv(2).
+        let result = unsafe { redhook::real!(readv)(fd, iov, iovcnt) };
+        // SAFETY: readv(2) filled `result` bytes of the application's iovecs.
+        unsafe { capture_iov(fd, iov, iovcnt.max(0) as usize, result, false) };
+        result
+    }
+}
+
+redhook::hook! {
+    unsafe fn shutdown(sockfd: c_int, how: c_int) -> c_int => phantom_shutdown {
+        // SAFETY: delegating to the real libc shutdown(2).
+        let result = unsafe { redhook::real!(shutdown)(sockfd, how) };
+        // Once the read side is shut nothing more can arrive, and clients
+        // doing this often exit without ever calling close(). SHUT_WR alone
+        // only ends the request; the response is still to come.
+        if result == 0 && matches!(how, libc::SHUT_RD | libc::SHUT_RDWR) {
+            IN_HOOK.with(|g| {
+                if !g.get() {
+                    g.set(true);
+                    process_teardown(fd_key(sockfd));
+                    g.set(false);
+                }
+            });
+        }
+        result
+    }
+}
+
+redhook::hook! {
+    unsafe fn close(fd: c_int) -> c_int => phantom_close {
+        // SAFETY: delegating to the real libc close(2).
+        let result = unsafe { redhook::real!(close)(fd) };
+        forget_fd(fd);
+        IN_HOOK.with(|g| {
+            if !g.get() {
+                g.set(true);
+                fd_closed(fd);
+                g.set(false);
+            }
+        });
+        result
+    }
+}
+
+// close_range(2) (glibc 2.34+) is mostly called between fork() and exec(),
+// where the bookkeeping mutexes may be held by threads the child does not
+// have, so only the lock-free socket-kind cache is reset.
+redhook::hook! {
+    unsafe fn close_range(first: libc::c_uint, last: libc::c_uint, flags: c_int) -> c_int => phantom_close_range {
+        // SAFETY: delegating to the real glibc close_range.
+        let result = unsafe { redhook::real!(close_range)(first, last, flags) };
+        // CLOSE_RANGE_CLOEXEC only marks the descriptors close-on-exec.
+        if result == 0 && flags as libc::c_uint & libc::CLOSE_RANGE_CLOEXEC == 0 {
+            let last = (last as usize).min(FD_KINDS - 1);
+            for kind in FD_KIND.get(first as usize..=last).unwrap_or_default() {
+                kind.store(FD_UNKNOWN, Ordering::Relaxed);
+            }
+        }
+        result
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// io_uring — socket I/O submitted through liburing (opt-in)
+//
+// Reads and writes submitted through io_uring never reach the libc hooks.
+// With `PHANTOM_IO_URING=1` the agent hooks liburing's exported submit and
+// wait functions instead. At submission it notes each send/recv/read/write SQE
+// by `user_data`, copying the bytes of outgoing ones while their buffer is
+// known to be valid. Completions are read straight from the CQ ring right
+// after a wait returns, before the application has looked at them; those it
+// picks up without waiting are read at its next submission. The transferred
+// bytes then go through the same paths as the libc hooks.
+//
+// Only a shared liburing can be interposed. Fixed files, provided buffers and
+// multishot receives are skipped, and rings driven through raw syscalls (e.g.
+// the `io-uring` crate) are not seen.
+// ─────────────────────────────────────────────────────────────────────────────
+
+const IORING_OP_READV: u8 = 1;
+const IORING_OP_WRITEV: u8 = 2;
+const IORING_OP_SENDMSG: u8 = 9;
+const IORING_OP_RECVMSG: u8 = 10;
+const IORING_OP_READ: u8 = 22;
+const IORING_OP_WRITE: u8 = 23;
+const IORING_OP_SEND: u8 = 26;
+const IORING_OP_RECV: u8 = 27;
+/// `sqe->flags`: `fd` indexes the registered file table.
+const IOSQE_FIXED_FILE: u8 = 1 << 0;
+/// `sqe->flags`: the kernel picks the receive buffer from a provided group.
+const IOSQE_BUFFER_SELECT: u8 = 1 << 5;
+/// `ring->flags`: SQEs are 128 bytes instead of 64.
+const IORING_SETUP_SQE128: u32 = 1 << 10;
+/// `ring->flags`: CQEs are 32 bytes instead of 16.
+const IORING_SETUP_CQE32: u32 = 1 << 11;
+/// Submitted operations remembered per ring until they complete.
+const URING_MAX_PENDING: usize = 4096;
+
+/// liburing's `struct io_uring_sq`; the layout is the same in every 2.x
+/// release (later fields replaced padding).
+#[repr(C)]
+struct UringSq {
+    _khead: *mut u32,
+    _ktail: *mut u32,
+    kring_mask: *mut u32,
+    _kring_entries: *mut u32,
+    _kflags: *mut u32,
+    _kdropped: *mut u32,
+    _array: *mut u32,
+    sqes: *mut u8,
+    /// First SQE not yet flushed to the kernel.
+    sqe_head: u32,
+    /// One past the last SQE handed out by `io_uring_get_sqe()`.
+    sqe_tail: u32,
+    _ring_sz: usize,
+    _ring_ptr: *mut c_void,
+    _pad: [u32; 4],
+}
+
+/// liburing's `struct io_uring_cq`.
+#[repr(C)]
+struct UringCq {
+    khead: *mut u32,
+    ktail: *mut u32,
+    kring_mask: *mut u32,
+    _kring_entries: *mut u32,
+    _kflags: *mut u32,
+    _koverflow: *mut u32,
+    cqes: *mut u8,
+    _ring_sz: usize,
+    _ring_ptr: *mut c_void,
+    _pad: [u32; 4],
+}
+
+/// Leading fields of liburing's `struct io_uring`.
+#[repr(C)]
+struct Uring {
+    sq: UringSq,
+    cq: UringCq,
+    flags: u32,
+}
+
+/// Leading fields of `struct io_uring_sqe`.
+#[repr(C)]
+struct UringSqe {
+    opcode: u8,
+    flags: u8,
+    _ioprio: u16,
+    fd: i32,
+    _off: u64,
+    addr: u64,
+    len: u32,
+    _op_flags: u32,
+    user_data: u64,
+}
+
+/// `struct io_uring_cqe` without the CQE32 extension.
+#[repr(C)]
+struct UringCqe {
+    user_data: u64,
+    res: i32,
+    _flags: u32,
+}
+
+/// Buffer of a submitted operation, as far as capture needs it.
+enum UringBuf {
+    /// Outgoing bytes, copied at submission.
+    Sent(Vec<u8>),
+    /// Incoming flat buffer: address and length.
+    Flat(usize, usize),
+    /// Incoming `iovec` array: address and count.
+    Iov(usize, usize),
+    /// Incoming `msghdr` address.
+    Msg(usize),
+}
+
+struct UringOp {
+    fd: c_int,
+    buf: UringBuf,
+}
+
+#[derive(Default)]
+struct UringState {
+    /// CQ ring position up to which completions have been read.
+    cq_seen: Option<u32>,
+    /// Submitted operations by `user_data`.
+    pending: HashMap<u64, UringOp>,
+}
+
+/// A completed transfer; `data` is empty for EOF on a receive.
+struct UringIo {
+    fd: c_int,
+    outgoing: bool,
+    data: Vec<u8>,
+}
+
+static URING_ENABLED: OnceLock<bool> = OnceLock::new();
+static URINGS: OnceLock<Mutex<HashMap<usize, UringState>>> = OnceLock::new();
+
+fn uring_enabled() -> bool {
+    *URING_ENABLED.get_or_init(|| config_var("PHANTOM_IO_URING").is_some_and(|v| v == "1"))
+}
+
+fn urings() -> &'static Mutex<HashMap<usize, UringState>> {
+    URINGS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Notes the socket transfers queued on `ring` that are about to be submitted.
+///
+/// # Safety
+/// `ring` must point to an initialised liburing `struct io_uring`.
+unsafe fn uring_note_submissions(ring: *const Uring) {
+    // SAFETY: forwarded from the caller.
+    let uring = unsafe { &*ring };
+    let sq = &uring.sq;
+    if sq.sqes.is_null() || sq.kring_mask.is_null() {
+        return;
+    }
+    // SAFETY: kring_mask points into the mapped SQ ring.
+    let mask = unsafe { *sq.kring_mask };
+    let stride = if uring.flags & IORING_SETUP_SQE128 != 0 {
+        128
+    } else {
+        64
+    };
+    let queued = sq.sqe_tail.wrapping_sub(sq.sqe_head).min(mask + 1);
+    let Ok(mut rings) = urings().lock() else {
+        return;
+    };
+    let state = rings.entry(ring as usize).or_default();
+    for n in 0..queued {
+        let idx = (sq.sqe_head.wrapping_add(n) & mask) as usize;
+        // SAFETY: SQEs between sqe_head and sqe_tail were filled in by the
+        // application and lie inside the mapped SQE array.
+        let sqe = unsafe { &*(sq.sqes.add(idx * stride) as *const UringSqe) };
+        if sqe.flags & IOSQE_FIXED_FILE != 0
+            || state.pending.len() >= URING_MAX_PENDING
+            || !is_socket(sqe.fd)
+        {
+            continue;
+        }
+        let (addr, len) = (sqe.addr as usize, sqe.len as usize);
+        let selected = sqe.flags & IOSQE_BUFFER_SELECT != 0;
+        let buf = match sqe.opcode {
+            IORING_OP_SEND | IORING_OP_WRITE if addr != 0 => {
+                // SAFETY: the buffer must stay valid until the SQE completes.
+                let bytes = unsafe { std::slice::from_raw_parts(addr as *const u8, len) };
+                UringBuf::Sent(bytes[..len.min(max_buf())].to_vec())
+            }
+            IORING_OP_WRITEV => {
+                // SAFETY: the iovecs and their buffers are valid until completion.
+                UringBuf::Sent(unsafe { gather_iov(addr as *const libc::iovec, len, max_buf()) })
+            }
+            IORING_OP_SENDMSG if addr != 0 => {
+                // SAFETY: the msghdr and its iovecs are valid until completion.
+                let msg = unsafe { &*(addr as *const libc::msghdr) };
+                UringBuf::Sent(unsafe { gather_iov(msg.msg_iov, msg.msg_iovlen, max_buf()) })
+            }
+            IORING_OP_RECV | IORING_OP_READ if !selected && addr != 0 => UringBuf::Flat(addr, len),
+            IORING_OP_READV if !selected && addr != 0 => UringBuf::Iov(addr, len),
+            IORING_OP_RECVMSG if !selected && addr != 0 => UringBuf::Msg(addr),
+            _ => continue,
+        };
+        state
+            .pending
+            .insert(sqe.user_data, UringOp { fd: sqe.fd, buf });
+    }
+}
+
+/// Collects the transfers completed on `ring` since the last look.
+///
+/// # Safety
+/// `ring` must point to an initialised liburing `struct io_uring`, and the
+/// buffers of receives that completed must not have been released yet.
+unsafe fn uring_take_completions(ring: *const Uring) -> Vec<UringIo> {
+    // SAFETY: forwarded from the caller.
+    let uring = unsafe { &*ring };
+    let cq = &uring.cq;
+    if cq.cqes.is_null() || cq.khead.is_null() || cq.ktail.is_null() || cq.kring_mask.is_null() {
+        return Vec::new();
+    }
+    let Ok(mut rings) = urings().lock() else {
+        return Vec::new();
+    };
+    let Some(state) = rings.get_mut(&(ring as usize)) else {
+        return Vec::new();
+    };
+    // SAFETY: khead/ktail/kring_mask point into the mapped CQ ring; the
+    // kernel publishes new tails with release ordering.
+    let (tail, head, mask) = unsafe {
+        (
+            AtomicU32::from_ptr(cq.ktail).load(Ordering::Acquire),
+            AtomicU32::from_ptr(cq.khead).load(Ordering::Acquire),
+            *cq.kring_mask,
+        )
+    };
+    let stride = if uring.flags & IORING_SETUP_CQE32 != 0 {
+        32
+    } else {
+        16
+    };
+    let mut pos = state.cq_seen.unwrap_or(head);
+    // Completions overwritten since the last look are lost.
+    if tail.wrapping_sub(pos) > mask + 1 {
+        pos = tail.wrapping_sub(mask + 1);
+    }
+    let mut done = Vec::new();
+    while pos != tail {
+        let idx = (pos & mask) as usize;
+        pos = pos.wrapping_add(1);
+        // SAFETY: entries before the tail are complete CQEs in the mapped ring.
+        let cqe = unsafe { &*(cq.cqes.add(idx * stride) as *const UringCqe) };
+        let Some(op) = state.pending.remove(&cqe.user_data) else {
+            continue;
+        };
+        let Ok(n) = usize::try_from(cqe.res) else {
+            continue; // failed
+        };
+        let (outgoing, data) = match op.buf {
+            UringBuf::Sent(mut bytes) => {
+                bytes.truncate(n);
+                (true, bytes)
+            }
+            // SAFETY: the kernel wrote `n` bytes into the receive buffer.
+            UringBuf::Flat(addr, len) => (false, unsafe {
+                std::slice::from_raw_parts(addr as *const u8, n.min(len)).to_vec()
+            }),
+            // SAFETY: as above, spread over the iovecs.
+            UringBuf::Iov(addr, count) => (false, unsafe {
+                gather_iov(addr as *const libc::iovec, count, n)
+            }),
+            UringBuf::Msg(addr) => {
+                // SAFETY: the msghdr stays valid with its receive buffers.
+                let msg = unsafe { &*(addr as *const libc::msghdr) };
+                (false, unsafe { gather_iov(msg.msg_iov, msg.msg_iovlen, n) })
+            }
+        };
+        if outgoing && data.is_empty() {
+            continue;
+        }
+        done.push(UringIo {
+            fd: op.fd,
+            outgoing,
+            data,
+        });
+    }
+    state.cq_seen = Some(tail);
+    done
+}
+
+fn uring_deliver(done: Vec<UringIo>) {
+    if !done.is_empty() {
+        note_hook(HOOK_IO_URING);
+    }
+    for io in done {
+        if io.outgoing {
+            socket_sent(io.fd, &io.data);
+        } else if io.data.is_empty() {
+            // Orderly shutdown by the peer.
+            process_eof(fd_key(io.fd));
+        } else {
+            socket_received(io.fd, &io.data);
+        }
+    }
+}
+
+/// Runs `f` under the re-entry guard when io_uring capture is enabled.
+fn with_uring(f: impl FnOnce()) {
+    if !uring_enabled() {
+        return;
+    }
+    IN_HOOK.with(|g| {
+        if !g.get() {
+            g.set(true);
+            f();
+            g.set(false);
+        }
+    });
+}
+
+/// Before a submission: read earlier completions, then note the new SQEs.
+///
+/// # Safety
+/// As for [`uring_take_completions`].
+unsafe fn uring_before_submit(ring: *mut c_void) {
+    with_uring(|| {
+        // SAFETY: forwarded from the caller.
+        let done = unsafe { uring_take_completions(ring as *const Uring) };
+        uring_deliver(done);
+        // SAFETY: forwarded from the caller.
+        unsafe { uring_note_submissions(ring as *const Uring) };
+    });
+}
+
+/// After a wait: read the completions it made available.
+///
+/// # Safety
+/// As for [`uring_take_completions`].
+unsafe fn uring_after_wait(ring: *mut c_void) {
+    with_uring(|| {
+        // SAFETY: forwarded from the caller.
+        let done = unsafe { uring_take_completions(ring as *const Uring) };
+        uring_deliver(done);
+    });
+}
+
+redhook::hook! {
+    unsafe fn io_uring_submit(ring: *mut c_void) -> c_int => phantom_io_uring_submit {
+        // SAFETY: ring is the application's initialised struct io_uring.
+        unsafe { uring_before_submit(ring) };
+        // SAFETY: delegating to the real io_uring_submit.
+        unsafe { redhook::real!(io_uring_submit)(ring) }
+    }
+}
+
+redhook::hook! {
+    unsafe fn io_uring_submit_and_wait(
+        ring:    *mut c_void,
+        wait_nr: u32
+    ) -> c_int => phantom_io_uring_submit_and_wait {
+        // SAFETY: ring is the application's initialised struct io_uring.
+        unsafe { uring_before_submit(ring) };
+        // SAFETY: delegating to the real io_uring_submit_and_wait.
+        let result = unsafe { redhook::real!(io_uring_submit_and_wait)(ring, wait_nr) };
+        // SAFETY: as above.
+        unsafe { uring_after_wait(ring) };
+        result
+    }
+}
+
+redhook::hook! {
+    unsafe fn io_uring_submit_and_wait_timeout(
+        ring:    *mut c_void,
+        cqe_ptr: *mut *mut c_void,
+        wait_nr: u32,
+        ts:      *mut c_void,
+        sigmask: *mut c_void
+    ) -> c_int => phantom_io_uring_submit_and_wait_timeout {
+        // SAFETY: ring is the application's initialised struct io_uring.
+        unsafe { uring_before_submit(ring) };
+        // SAFETY: delegating to the real io_uring_submit_and_wait_timeout.
+        let result = unsafe {
+            redhook::real!(io_uring_submit_and_wait_timeout)(ring, cqe_ptr, wait_nr, ts, sigmask)
+        };
+        // SAFETY: as above.
+        unsafe { uring_after_wait(ring) };
+        result
+    }
+}
+
+redhook::hook! {
+    unsafe fn __io_uring_get_cqe(
+        ring:    *mut c_void,
+        cqe_ptr: *mut *mut c_void,
+        submit:  u32,
+        wait_nr: u32,
+        sigmask: *mut c_void
+    ) -> c_int => phantom_io_uring_get_cqe {
+        // SAFETY: delegating to the real __io_uring_get_cqe (behind the
+        // inline io_uring_wait_cqe / io_uring_peek_cqe helpers).
+        let result = unsafe { redhook::real!(__io_uring_get_cqe)(ring, cqe_ptr, submit, wait_nr, sigmask) };
+        // SAFETY: ring is the application's initialised struct io_uring.
+        unsafe { uring_after_wait(ring) };
+        result
+    }
+}
+
+redhook::hook! {
+    unsafe fn io_uring_wait_cqes(
+        ring:    *mut c_void,
+        cqe_ptr: *mut *mut c_void,
+        wait_nr: u32,
+        ts:      *mut c_void,
+        sigmask: *mut c_void
+    ) -> c_int => phantom_io_uring_wait_cqes {
+        // SAFETY: delegating to the real io_uring_wait_cqes.
+        let result = unsafe { redhook::real!(io_uring_wait_cqes)(ring, cqe_ptr, wait_nr, ts, sigmask) };
+        // SAFETY: ring is the application's initialised struct io_uring.
+        unsafe { uring_after_wait(ring) };
+        result
+    }
+}
+
+redhook::hook! {
+    unsafe fn io_uring_peek_batch_cqe(
+        ring:  *mut c_void,
+        cqes:  *mut *mut c_void,
+        count: u32
+    ) -> u32 => phantom_io_uring_peek_batch_cqe {
+        // SAFETY: delegating to the real io_uring_peek_batch_cqe.
+        let result = unsafe { redhook::real!(io_uring_peek_batch_cqe)(ring, cqes, count) };
+        // SAFETY: ring is the application's initialised struct io_uring.
+        unsafe { uring_after_wait(ring) };
+        result
+    }
+}
+
+redhook::hook! {
+    unsafe fn io_uring_queue_exit(ring: *mut c_void) => phantom_io_uring_queue_exit {
+        with_uring(|| {
+            if let Ok(mut rings) = urings().lock() {
+                rings.remove(&(ring as usize));
+            }
+        });
+        // SAFETY: delegating to the real io_uring_queue_exit.
+        unsafe { redhook::real!(io_uring_queue_exit)(ring) }
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// TLS session sockets
+//
+// TLS traffic is keyed by the session pointer, which says nothing about where
+// it goes. The first write on a session looks up the socket underneath through
+// the library's own accessor (resolved with dlsym, so none of them is linked)
+// and records its peer until teardown. mbedTLS hides its transport behind
+// opaque BIO callbacks, so its sessions have no peer.
+//
+// The handshake is over by then, so OpenSSL sessions also have their SNI,
+// protocol version, cipher suite and ALPN protocol read once and attached to
+// every HTTP trace on them.
+// ─────────────────────────────────────────────────────────────────────────────
+
+type TlsGetFdFn = unsafe extern "C" fn(session: *mut c_void) -> c_int;
+
+/// TLS library that owns a session pointer.
+#[derive(Clone, Copy)]
+enum TlsLib {
+    OpenSsl,
+    WolfSsl,
+    Nss,
+}
+
+impl TlsLib {
+    /// The library's session → socket accessor, when it is loaded.
+    fn get_fd(self) -> Option<TlsGetFdFn> {
+        static OPENSSL: OnceLock<Option<TlsGetFdFn>> = OnceLock::new();
+        static WOLFSSL: OnceLock<Option<TlsGetFdFn>> = OnceLock::new();
+        static NSS: OnceLock<Option<TlsGetFdFn>> = OnceLock::new();
+        let (cell, name) = match self {
+            Self::OpenSsl => (&OPENSSL, c"SSL_get_fd"),
+            Self::WolfSsl => (&WOLFSSL, c"wolfSSL_get_fd"),
+            Self::Nss => (&NSS, c"PR_FileDesc2NativeHandle"),
+        };
+        *cell.get_or_init(|| {
+            // SAFETY: dlsym with a NUL-terminated name; a null result means absent.
+            let sym = unsafe { libc::dlsym(libc::RTLD_DEFAULT, name.as_ptr()) };
+            // SAFETY: each accessor takes the session pointer and returns the fd.
+            (!sym.is_null()).then(|| unsafe { std::mem::transmute::<*mut c_void, TlsGetFdFn>(sym) })
+        })
+    }
+}
+
+/// Negotiated details of a TLS session, sent with its HTTP traces.
+#[derive(Clone, Default, serde::Serialize)]
+struct TlsInfo {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    server_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cipher: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    alpn: Option<String>,
+}
+
+type SslGetServernameFn =
+    unsafe extern "C" fn(ssl: *const c_void, kind: c_int) -> *const libc::c_char;
+type SslGetStrFn = unsafe extern "C" fn(ssl: *const c_void) -> *const libc::c_char;
+type SslGetCipherFn = unsafe extern "C" fn(ssl: *const c_void) -> *const c_void;
+type CipherGetNameFn = unsafe extern "C" fn(cipher: *const c_void) -> *const libc::c_char;
+type SslGetAlpnFn =
+    unsafe extern "C" fn(ssl: *const c_void, data: *mut *const u8, len: *mut libc::c_uint);
+
+/// `TLSEXT_NAMETYPE_host_name`, the only server name type there is.
+const TLSEXT_NAMETYPE_HOST_NAME: c_int = 0;
+
+/// OpenSSL's session accessors, each `None` when the loaded library lacks it.
+struct SslInfoApi {
+    servername: Option<SslGetServernameFn>,
+    version: Option<SslGetStrFn>,
+    current_cipher: Option<SslGetCipherFn>,
+    cipher_name: Option<CipherGetNameFn>,
+    alpn_selected: Option<SslGetAlpnFn>,
+}
+
+static SSL_INFO_API: OnceLock<SslInfoApi> = OnceLock::new();
+
+/// Address of `name` among the loaded libraries.
+fn dlsym_default(name: &std::ffi::CStr) -> Option<*mut c_void> {
+    // SAFETY: dlsym with a NUL-terminated name; a null result means absent.
+    let sym = unsafe { libc::dlsym(libc::RTLD_DEFAULT, name.as_ptr()) };
+    (!sym.is_null()).then_some(sym)
+}
+
+/// Copies a NUL-terminated string owned by the TLS library.
+///
+/// # Safety
+/// `ptr` must be null or point to a NUL-terminated string.
+unsafe fn ssl_string(ptr: *const libc::c_char) -> Option<String> {
+    // SAFETY: non-null per the check; NUL-terminated per the caller.
+    (!ptr.is_null()).then(|| {
+        unsafe { std::ffi::CStr::from_ptr(ptr) }
+            .to_string_lossy()
+            .into_owned()
+    })
+}
+
+/// SNI, version, cipher and ALPN of the OpenSSL session `ssl`.
+///
+/// # Safety
+/// `ssl` must be a live `SSL*`.
+unsafe fn openssl_info(ssl: *const c_void) -> TlsInfo {
+    // SAFETY: each symbol, when present, has the signature it is cast to.
+    let api = SSL_INFO_API.get_or_init(|| unsafe {
+        SslInfoApi {
+            servername: dlsym_default(c"SSL_get_servername")
+                .map(|sym| std::mem::transmute::<*mut c_void, SslGetServernameFn>(sym)),
+            version: dlsym_default(c"SSL_get_version")
+                .map(|sym| std::mem::transmute::<*mut c_void, SslGetStrFn>(sym)),
+            current_cipher: dlsym_default(c"SSL_get_current_cipher")
+                .map(|sym| std::mem::transmute::<*mut c_void, SslGetCipherFn>(sym)),
+            cipher_name: dlsym_default(c"SSL_CIPHER_get_name")
+                .map(|sym| std::mem::transmute::<*mut c_void, CipherGetNameFn>(sym)),
+            alpn_selected: dlsym_default(c"SSL_get0_alpn_selected")
+                .map(|sym| std::mem::transmute::<*mut c_void, SslGetAlpnFn>(sym)),
+        }
+    });
+    // SAFETY: ssl is live per the caller; the library owns every returned
+    // string and the ALPN bytes for the session's lifetime.
+    unsafe {
+        let cipher = api
+            .current_cipher
+            .map(|f| f(ssl))
+            .filter(|c| !c.is_null())
+            .zip(api.cipher_name)
+            .and_then(|(c, name)| ssl_string(name(c)));
+        let alpn = api.alpn_selected.and_then(|f| {
+            let mut data: *const u8 = std::ptr::null();
+            let mut len: libc::c_uint = 0;
+            f(ssl, &mut data, &mut len);
+            (!data.is_null() && len > 0).then(|| {
+                String::from_utf8_lossy(std::slice::from_raw_parts(data, len as usize)).into_owned()
+            })
+        });
+        TlsInfo {
+            server_name: api
+                .servername
+                .and_then(|f| ssl_string(f(ssl, TLSEXT_NAMETYPE_HOST_NAME))),
+            version: api
+                .version
+                .and_then(|f| ssl_string(f(ssl)))
+                .filter(|v| v != "unknown"),
+            cipher,
+            alpn,
+        }
+    }
+}
+
+/// What is known about the connection under a TLS session.
+struct TlsSession {
+    /// Peer of the underlying socket, `None` when it has none (e.g. a memory BIO).
+    peer: Option<(String, u16)>,
+    /// Negotiated details, for libraries that report them.
+    info: Option<TlsInfo>,
+    /// Connect and handshake phases, until the first request takes them.
+    timing: TimingMsg,
+}
+
+/// Each TLS session seen writing. Entries live until `process_teardown`.
+static TLS_SESSIONS: OnceLock<Mutex<HashMap<usize, TlsSession>>> = OnceLock::new();
+
+/// Records the peer and negotiated details of TLS session `key` the first
+/// time it is seen.
+fn note_tls_session(key: usize, lib: TlsLib) {
+    note_hook(match lib {
+        TlsLib::OpenSsl => HOOK_OPENSSL,
+        TlsLib::WolfSsl => HOOK_WOLFSSL,
+        TlsLib::Nss => HOOK_NSS,
+    });
+    let Ok(mut sessions) = TLS_SESSIONS
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+    else {
+        return;
+    };
+    if sessions.contains_key(&key) {
+        return;
+    }
+    // SAFETY: key is the live session pointer the hook was called with.
+    let fd = lib
+        .get_fd()
+        .map(|get_fd| unsafe { get_fd(key as *mut c_void) });
+    let info = match lib {
+        // SAFETY: as above; OpenSSL keys are `SSL*`.
+        TlsLib::OpenSsl => Some(unsafe { openssl_info(key as *const c_void) }),
+        TlsLib::WolfSsl | TlsLib::Nss => None,
+    };
+    let fd = fd.filter(|&fd| fd >= 0);
+    let timing = fd
+        .and_then(|fd| take_connect(fd_key(fd) as c_int))
+        .map(|(started, connected)| TimingMsg {
+            connect_ms: Some(millis_between(started, connected)),
+            tls_handshake_ms: Some(millis_between(connected, Instant::now())),
+            ttfb_ms: None,
+        })
+        .unwrap_or_default();
+    sessions.insert(
+        key,
+        TlsSession {
+            peer: fd.and_then(peer_addr),
+            info,
+            timing,
+        },
+    );
+}
+
+fn forget_tls_session(key: usize) {
+    if let Some(Ok(mut sessions)) = TLS_SESSIONS.get().map(Mutex::lock) {
+        sessions.remove(&key);
+    }
+}
+
+/// Peer address of a connected TCP socket, as `"ip:port"` plus the port.
+fn peer_addr(fd: c_int) -> Option<(String, u16)> {
+    // SAFETY: an all-zero sockaddr_storage is a valid value.
+    let mut storage: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
+    let mut len = std::mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+    // SAFETY: storage/len are valid out-pointers sized for any address family.
+    let rc = unsafe {
+        libc::getpeername(
+            fd,
+            &mut storage as *mut libc::sockaddr_storage as *mut libc::sockaddr,
+            &mut len,
+        )
+    };
+    if rc != 0 {
+        return None;
+    }
+    let addr = match storage.ss_family as c_int {
+        libc::AF_INET => {
+            // SAFETY: ss_family says the storage holds a sockaddr_in.
+            let sin = unsafe { &*(&storage as *const _ as *const libc::sockaddr_in) };
+            std::net::SocketAddr::from((
+                std::net::Ipv4Addr::from(u32::from_be(sin.sin_addr.s_addr)),
+                u16::from_be(sin.sin_port),
+            ))
+        }
+        libc::AF_INET6 => {
+            // SAFETY: ss_family says the storage holds a sockaddr_in6.
+            let sin6 = unsafe { &*(&storage as *const _ as *const libc::sockaddr_in6) };
+            std::net::SocketAddr::from((
+                std::net::Ipv6Addr::from(sin6.sin6_addr.s6_addr),
+                u16::from_be(sin6.sin6_port),
+            ))
+        }
+        _ => return None,
+    };
+    Some((addr.to_string(), addr.port()))
+}
+
+/// Peer address of the connection behind `key`: the socket itself, or the
+/// one under a TLS session.
+fn connection_peer(key: usize, tls: bool) -> Option<(String, u16)> {
+    if tls {
+        let sessions = TLS_SESSIONS.get()?.lock().ok()?;
+        sessions.get(&key)?.peer.clone()
+    } else {
+        peer_addr(c_int::try_from(key).ok()?)
+    }
+}
+
+/// Negotiated details of the TLS session `key`, when it is one.
+fn connection_tls(key: usize, tls: bool) -> Option<TlsInfo> {
+    if !tls {
+        return None;
+    }
+    let sessions = TLS_SESSIONS.get()?.lock().ok()?;
+    sessions.get(&key)?.info.clone()
+}
+
+/// `dest_addr` recorded for HTTP traces on `key`: the peer of a TLS session,
+/// the path of a Unix socket, or the remote `ip:port` of an outbound TCP
+/// connection — looked up on first use and cached until the fd closes.
+fn connection_dest(key: usize, tls: bool) -> Option<String> {
+    if tls {
+        return connection_peer(key, tls).map(|(addr, _)| addr);
+    }
+    let fd = c_int::try_from(key).ok()?;
+    if let Some(label) = socket_peer(fd) {
+        return Some(label);
+    }
+    // A server-side socket's peer is the client, not the destination.
+    if is_inbound(fd) {
+        return None;
+    }
+    let (addr, _) = peer_addr(fd)?;
+    note_socket_peer(fd, addr.clone());
+    Some(addr)
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Hooks — OpenSSL / LibreSSL / BoringSSL (HTTPS)
+//
+// SSL_write / SSL_read operate on plaintext above the TLS layer, so we can
+// capture the decrypted HTTP traffic. SSL_shutdown / SSL_free clean up on
+// connection close.
+//
+// The IN_HOOK guard prevents double-capture: when SSL_write internally calls
+// send(), the send hook sees IN_HOOK=true and skips.
+// ─────────────────────────────────────────────────────────────────────────────
+
+redhook::hook! {
+    unsafe fn SSL_write(
+        ssl: *mut c_void,
+        buf: *const c_void,
+        num: c_int
+    ) -> c_int => phantom_ssl_write {
+        let len = usize::try_from(num).unwrap_or(0);
+        // SAFETY: buf points to `num` readable bytes (SSL_write contract);
+        // the real SSL_write gets a live slice of at most c_int::MAX bytes.
+        let propagated = unsafe {
+            tls_write_propagated(ssl, buf, len, c_int::MAX as usize, |data| {
+                let result = redhook::real!(SSL_write)(ssl, data.as_ptr().cast(), data.len() as c_int);
+                (result, usize::try_from(result).ok().filter(|&n| n > 0))
+            })
+        };
+        if let Some((result, covered)) = propagated {
+            return covered.map_or(result, |n| n as c_int);
+        }
+        let result = unsafe { redhook::real!(SSL_write)(ssl, buf, num) };
+        if result > 0 {
+            IN_HOOK.with(|g| {
+                if !g.get() {
+                    g.set(true);
+                    // SAFETY: buf points to `result` bytes that were written successfully.
+                    let data = unsafe { std::slice::from_raw_parts(buf as *const u8, result as usize) };
+                    note_tls_session(ssl as usize, TlsLib::OpenSsl);
+                    process_outgoing(ssl as usize, data, true);
+                    g.set(false);
+                }
+            });
+        }
+        result
+    }
+}
+
+redhook::hook! {
+    unsafe fn SSL_read(
+        ssl: *mut c_void,
+        buf: *mut c_void,
+        num: c_int
+    ) -> c_int => phantom_ssl_read {
+        let result = unsafe { redhook::real!(SSL_read)(ssl, buf, num) };
+        if result > 0 {
+            IN_HOOK.with(|g| {
+                if !g.get() {
+                    g.set(true);
+                    // SAFETY: buf holds `result` decrypted bytes from SSL_read.
+                    let data = unsafe { std::slice::from_raw_parts(buf as *const u8, result as usize) };
+                    process_incoming(ssl as usize, data);
+                    g.set(false);
+                }
+            });
+        } else if result == 0 && num > 0 {
+            // `close_notify` from the peer, or EOF on the transport.
+            IN_HOOK.with(|g| {
+                if !g.get() {
+                    g.set(true);
+                    process_eof(ssl as usize);
+                    g.set(false);
+                }
+            });
         }
+        result
     }
 }
 
-fn process_incoming(key: usize, data: &[u8]) {
-    // ── HTTP/2 path ──────────────────────────────────────────────────────────
-    // Handle HTTP/2 streams, collecting those that have a complete response.
-    // We release the lock before emitting.
-    let h2_completed = {
-        let mut map = match state_map().lock() {
-            Ok(m) => m,
-            Err(_) => return,
-        };
-        if let Some(FdState::Http2(h2)) = map.get_mut(&key) {
-            if h2.recv_buf.len() < MAX_BUF {
-                h2.recv_buf.extend_from_slice(data);
-            }
-            process_h2_recv_frames(h2);
-            Some(drain_completed_h2_streams(h2))
-        } else {
-            None
-        }
-    }; // lock released
+// OpenSSL 1.1.1+ `_ex` variants report the byte count through an out-param
+// and return 1 on success, 0 on failure.
 
-    if let Some(completed) = h2_completed {
-        for stream in completed {
-            emit_h2_stream(stream);
-        }
-        return;
-    }
+/// `SSL_get_error` result for a connection closed by the peer's `close_notify`.
+const SSL_ERROR_ZERO_RETURN: c_int = 6;
 
-    // ── HTTP/1.x path ────────────────────────────────────────────────────────
-    // Phase 1: accumulate, parse headers if ready, check completeness.
-    // Return owned FdState if the response is complete (to emit outside the lock).
-    let to_emit = {
-        let mut map = match state_map().lock() {
-            Ok(m) => m,
-            Err(_) => return,
-        };
+type SslGetErrorFn = unsafe extern "C" fn(ssl: *const c_void, ret: c_int) -> c_int;
 
-        let complete = match map.get_mut(&key) {
-            Some(FdState::CollectingResponse {
-                buf,
-                status_code,
-                resp_headers,
-                content_length,
-                headers_end,
-                ..
-            }) => {
-                if buf.len() < MAX_BUF {
-                    buf.extend_from_slice(data);
-                }
+static SSL_GET_ERROR: OnceLock<Option<SslGetErrorFn>> = OnceLock::new();
 
-                // Parse response headers once.
-                #[allow(clippy::collapsible_if)]
-                if headers_end.is_none() {
-                    if let Some(meta) = try_parse_response_headers(buf) {
-                        *status_code = Some(meta.status_code);
-                        *resp_headers = Some(meta.headers);
-                        *content_length = meta.content_length;
-                        *headers_end = Some(meta.headers_end);
-                    }
-                }
+/// Whether a failed `SSL_read_ex` on `ssl` means the peer closed the session.
+fn ssl_closed_by_peer(ssl: *mut c_void) -> bool {
+    let get_error = SSL_GET_ERROR.get_or_init(|| {
+        // SAFETY: dlsym with a NUL-terminated name; a null result means absent.
+        let sym = unsafe { libc::dlsym(libc::RTLD_DEFAULT, c"SSL_get_error".as_ptr()) };
+        // SAFETY: a non-null SSL_get_error has this signature.
+        (!sym.is_null()).then(|| unsafe { std::mem::transmute::<*mut c_void, SslGetErrorFn>(sym) })
+    });
+    // SAFETY: ssl is the live SSL* the failed call was made on.
+    get_error.is_some_and(|f| unsafe { f(ssl, 0) } == SSL_ERROR_ZERO_RETURN)
+}
 
-                // Check if we have Content-Length bytes of body.
-                matches!(
-                    (*content_length, *headers_end),
-                    (Some(cl), Some(he)) if buf.len() >= he + cl
-                )
+redhook::hook! {
+    unsafe fn SSL_write_ex(
+        ssl:     *mut c_void,
+        buf:     *const c_void,
+        num:     size_t,
+        written: *mut size_t
+    ) -> c_int => phantom_ssl_write_ex {
+        if !written.is_null() {
+            // SAFETY: buf points to `num` readable bytes (SSL_write_ex
+            // contract); the real SSL_write_ex gets a live slice.
+            let propagated = unsafe {
+                tls_write_propagated(ssl, buf, num, usize::MAX, |data| {
+                    let mut n: size_t = 0;
+                    let result = redhook::real!(SSL_write_ex)(ssl, data.as_ptr().cast(), data.len(), &mut n);
+                    (result, (result == 1).then_some(n))
+                })
+            };
+            if let Some((result, covered)) = propagated {
+                if let Some(n) = covered {
+                    // SAFETY: written is non-null, checked above.
+                    unsafe { *written = n };
+                }
+                return result;
             }
-            _ => false,
-        };
-
-        if complete {
-            map.remove(&key) // → Option<FdState>, owned
-        } else {
-            None
         }
-    }; // Lock released here
+        // SAFETY: delegating to the real SSL_write_ex.
+        let result = unsafe { redhook::real!(SSL_write_ex)(ssl, buf, num, written) };
+        if result == 1 && !written.is_null() {
+            // SAFETY: on success SSL_write_ex stores the byte count in `written`.
+            let len = unsafe { *written };
+            IN_HOOK.with(|g| {
+                if !g.get() && len > 0 {
+                    g.set(true);
+                    // SAFETY: buf points to `len` bytes that were written successfully.
+                    let data = unsafe { std::slice::from_raw_parts(buf as *const u8, len) };
+                    note_tls_session(ssl as usize, TlsLib::OpenSsl);
+                    process_outgoing(ssl as usize, data, true);
+                    g.set(false);
+                }
+            });
+        }
+        result
+    }
+}
 
-    if let Some(FdState::CollectingResponse {
-        req,
-        buf,
-        tls,
-        status_code: Some(sc),
-        resp_headers: Some(rh),
-        content_length,
-        headers_end: Some(he),
-    }) = to_emit
-    {
-        let cl = content_length.unwrap_or(0);
-        let body_end = (he + cl).min(buf.len());
-        let duration = req.started_at.elapsed();
-        do_emit(*req, sc, rh, &buf[he..body_end], duration, tls);
+redhook::hook! {
+    unsafe fn SSL_read_ex(
+        ssl:       *mut c_void,
+        buf:       *mut c_void,
+        num:       size_t,
+        readbytes: *mut size_t
+    ) -> c_int => phantom_ssl_read_ex {
+        // SAFETY: delegating to the real SSL_read_ex.
+        let result = unsafe { redhook::real!(SSL_read_ex)(ssl, buf, num, readbytes) };
+        IN_HOOK.with(|g| {
+            if g.get() {
+                return;
+            }
+            g.set(true);
+            if result == 1 && !readbytes.is_null() {
+                // SAFETY: on success SSL_read_ex stores the byte count in `readbytes`.
+                let len = unsafe { *readbytes };
+                if len > 0 {
+                    // SAFETY: buf holds `len` decrypted bytes from SSL_read_ex.
+                    let data = unsafe { std::slice::from_raw_parts(buf as *const u8, len) };
+                    process_incoming(ssl as usize, data);
+                }
+            } else if result == 0 && num > 0 && ssl_closed_by_peer(ssl) {
+                process_eof(ssl as usize);
+            }
+            g.set(false);
+        });
+        result
     }
 }
 
-fn process_teardown(key: usize) {
-    let state = {
-        let mut map = match state_map().lock() {
-            Ok(m) => m,
-            Err(_) => return,
-        };
-        map.remove(&key)
-    }; // Lock released
+redhook::hook! {
+    unsafe fn SSL_shutdown(ssl: *mut c_void) -> c_int => phantom_ssl_shutdown {
+        // The session is over even if the SSL object lives on until a much
+        // later SSL_free: flush partial (chunked or close-delimited) responses.
+        IN_HOOK.with(|g| {
+            if !g.get() {
+                g.set(true);
+                process_teardown(ssl as usize);
+                g.set(false);
+            }
+        });
+        // SAFETY: delegating to the real SSL_shutdown.
+        unsafe { redhook::real!(SSL_shutdown)(ssl) }
+    }
+}
 
-    match state {
-        // HTTP/1.x: emit partial response (e.g. chunked or connection-close semantics).
-        Some(FdState::CollectingResponse {
-            req,
-            buf,
-            tls,
-            status_code: Some(sc),
-            resp_headers: Some(rh),
-            content_length,
-            headers_end: Some(he),
-        }) => {
-            let cl = content_length.unwrap_or_else(|| buf.len().saturating_sub(he));
-            let body_end = (he + cl).min(buf.len());
-            let duration = req.started_at.elapsed();
-            do_emit(*req, sc, rh, &buf[he..body_end], duration, tls);
-        }
-        // HTTP/2: emit any streams for which we received at least a response status.
-        Some(FdState::Http2(h2)) => {
-            for (_sid, stream) in h2.streams {
-                if stream.resp_status.is_some() {
-                    emit_h2_stream(stream);
-                }
+redhook::hook! {
+    unsafe fn SSL_free(ssl: *mut c_void) => phantom_ssl_free {
+        // Emit any buffered partial response before freeing the SSL context.
+        IN_HOOK.with(|g| {
+            if !g.get() {
+                g.set(true);
+                process_teardown(ssl as usize);
+                g.set(false);
             }
-        }
-        // If headers were never parsed, we have nothing useful to emit.
-        _ => {}
+        });
+        // SAFETY: delegating to the real SSL_free.
+        unsafe { redhook::real!(SSL_free)(ssl) }
     }
 }
 
 // ─────────────────────────────────────────────────────────────────────────────
-// Hooks — libc (plain HTTP)
+// Hooks — wolfSSL and mbedTLS (HTTPS in embedded-style clients)
+//
+// Same shape as the OpenSSL hooks, keyed by the WOLFSSL* / mbedtls_ssl_context*
+// pointer. Each hook only ever runs when the application calls that library,
+// so whichever one is loaded is picked up at runtime by symbol lookup; the
+// libraries must be dynamically linked for LD_PRELOAD to interpose.
 // ─────────────────────────────────────────────────────────────────────────────
 
+/// mbedTLS error for a `close_notify` from the peer.
+const MBEDTLS_ERR_SSL_PEER_CLOSE_NOTIFY: c_int = -0x7880;
+
 redhook::hook! {
-    unsafe fn send(
-        sockfd: c_int,
-        buf:    *const c_void,
-        len:    size_t,
-        flags:  c_int
-    ) -> ssize_t => phantom_send {
-        // SAFETY: delegating to the real libc send(2).
-        let result = unsafe { redhook::real!(send)(sockfd, buf, len, flags) };
+    unsafe fn wolfSSL_write(
+        ssl: *mut c_void,
+        buf: *const c_void,
+        sz:  c_int
+    ) -> c_int => phantom_wolfssl_write {
+        // SAFETY: delegating to the real wolfSSL_write.
+        let result = unsafe { redhook::real!(wolfSSL_write)(ssl, buf, sz) };
         if result > 0 {
             IN_HOOK.with(|g| {
                 if !g.get() {
                     g.set(true);
-                    // SAFETY: buf points to `result` readable bytes (guaranteed by send contract).
+                    // SAFETY: buf points to `result` bytes that were written successfully.
                     let data = unsafe { std::slice::from_raw_parts(buf as *const u8, result as usize) };
-                    process_outgoing(sockfd as usize, data, false);
+                    note_tls_session(ssl as usize, TlsLib::WolfSsl);
+                    process_outgoing(ssl as usize, data, true);
                     g.set(false);
                 }
             });
@@ -932,21 +4573,25 @@ redhook::hook! {
 }
 
 redhook::hook! {
-    unsafe fn recv(
-        sockfd: c_int,
-        buf:    *mut c_void,
-        len:    size_t,
-        flags:  c_int
-    ) -> ssize_t => phantom_recv {
-        // SAFETY: delegating to the real libc recv(2).
-        let result = unsafe { redhook::real!(recv)(sockfd, buf, len, flags) };
-        if result > 0 {
+    unsafe fn wolfSSL_read(
+        ssl: *mut c_void,
+        buf: *mut c_void,
+        sz:  c_int
+    ) -> c_int => phantom_wolfssl_read {
+        // SAFETY: delegating to the real wolfSSL_read.
+        let result = unsafe { redhook::real!(wolfSSL_read)(ssl, buf, sz) };
+        if result > 0 || (result == 0 && sz > 0) {
             IN_HOOK.with(|g| {
                 if !g.get() {
                     g.set(true);
-                    // SAFETY: buf holds `result` initialised bytes written by recv(2).
-                    let data = unsafe { std::slice::from_raw_parts(buf as *const u8, result as usize) };
-                    process_incoming(sockfd as usize, data);
+                    if result > 0 {
+                        // SAFETY: buf holds `result` decrypted bytes from wolfSSL_read.
+                        let data = unsafe { std::slice::from_raw_parts(buf as *const u8, result as usize) };
+                        process_incoming(ssl as usize, data);
+                    } else {
+                        // Clean shutdown by the peer.
+                        process_eof(ssl as usize);
+                    }
                     g.set(false);
                 }
             });
@@ -956,43 +4601,34 @@ redhook::hook! {
 }
 
 redhook::hook! {
-    unsafe fn close(fd: c_int) -> c_int => phantom_close {
-        // SAFETY: delegating to the real libc close(2).
-        let result = unsafe { redhook::real!(close)(fd) };
+    unsafe fn wolfSSL_free(ssl: *mut c_void) => phantom_wolfssl_free {
+        // Emit any buffered partial response before freeing the session.
         IN_HOOK.with(|g| {
             if !g.get() {
                 g.set(true);
-                process_teardown(fd as usize);
+                process_teardown(ssl as usize);
                 g.set(false);
             }
         });
-        result
+        // SAFETY: delegating to the real wolfSSL_free.
+        unsafe { redhook::real!(wolfSSL_free)(ssl) }
     }
 }
 
-// ─────────────────────────────────────────────────────────────────────────────
-// Hooks — OpenSSL / LibreSSL / BoringSSL (HTTPS)
-//
-// SSL_write / SSL_read operate on plaintext above the TLS layer, so we can
-// capture the decrypted HTTP traffic. SSL_free cleans up on connection close.
-//
-// The IN_HOOK guard prevents double-capture: when SSL_write internally calls
-// send(), the send hook sees IN_HOOK=true and skips.
-// ─────────────────────────────────────────────────────────────────────────────
-
 redhook::hook! {
-    unsafe fn SSL_write(
+    unsafe fn mbedtls_ssl_write(
         ssl: *mut c_void,
-        buf: *const c_void,
-        num: c_int
-    ) -> c_int => phantom_ssl_write {
-        let result = unsafe { redhook::real!(SSL_write)(ssl, buf, num) };
+        buf: *const u8,
+        len: size_t
+    ) -> c_int => phantom_mbedtls_ssl_write {
+        // SAFETY: delegating to the real mbedtls_ssl_write.
+        let result = unsafe { redhook::real!(mbedtls_ssl_write)(ssl, buf, len) };
         if result > 0 {
             IN_HOOK.with(|g| {
                 if !g.get() {
                     g.set(true);
                     // SAFETY: buf points to `result` bytes that were written successfully.
-                    let data = unsafe { std::slice::from_raw_parts(buf as *const u8, result as usize) };
+                    let data = unsafe { std::slice::from_raw_parts(buf, result as usize) };
                     process_outgoing(ssl as usize, data, true);
                     g.set(false);
                 }
@@ -1003,19 +4639,26 @@ redhook::hook! {
 }
 
 redhook::hook! {
-    unsafe fn SSL_read(
+    unsafe fn mbedtls_ssl_read(
         ssl: *mut c_void,
-        buf: *mut c_void,
-        num: c_int
-    ) -> c_int => phantom_ssl_read {
-        let result = unsafe { redhook::real!(SSL_read)(ssl, buf, num) };
-        if result > 0 {
+        buf: *mut u8,
+        len: size_t
+    ) -> c_int => phantom_mbedtls_ssl_read {
+        // SAFETY: delegating to the real mbedtls_ssl_read.
+        let result = unsafe { redhook::real!(mbedtls_ssl_read)(ssl, buf, len) };
+        // 0 is EOF on the transport; close_notify comes back as an error.
+        let eof = (result == 0 && len > 0) || result == MBEDTLS_ERR_SSL_PEER_CLOSE_NOTIFY;
+        if result > 0 || eof {
             IN_HOOK.with(|g| {
                 if !g.get() {
                     g.set(true);
-                    // SAFETY: buf holds `result` decrypted bytes from SSL_read.
-                    let data = unsafe { std::slice::from_raw_parts(buf as *const u8, result as usize) };
-                    process_incoming(ssl as usize, data);
+                    if result > 0 {
+                        // SAFETY: buf holds `result` decrypted bytes from mbedtls_ssl_read.
+                        let data = unsafe { std::slice::from_raw_parts(buf as *const u8, result as usize) };
+                        process_incoming(ssl as usize, data);
+                    } else {
+                        process_eof(ssl as usize);
+                    }
                     g.set(false);
                 }
             });
@@ -1025,8 +4668,8 @@ redhook::hook! {
 }
 
 redhook::hook! {
-    unsafe fn SSL_free(ssl: *mut c_void) => phantom_ssl_free {
-        // Emit any buffered partial response before freeing the SSL context.
+    unsafe fn mbedtls_ssl_free(ssl: *mut c_void) => phantom_mbedtls_ssl_free {
+        // Emit any buffered partial response before the context is wiped.
         IN_HOOK.with(|g| {
             if !g.get() {
                 g.set(true);
@@ -1034,7 +4677,202 @@ redhook::hook! {
                 g.set(false);
             }
         });
-        // SAFETY: delegating to the real SSL_free.
-        unsafe { redhook::real!(SSL_free)(ssl) }
+        // SAFETY: delegating to the real mbedtls_ssl_free.
+        unsafe { redhook::real!(mbedtls_ssl_free)(ssl) }
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Hooks — NSS / NSPR (HTTPS in Firefox, Thunderbird, NSS builds of curl)
+//
+// NSS layers TLS onto an NSPR `PRFileDesc`, and applications move plaintext
+// with PR_Write / PR_Read on the top layer. Those calls also serve plain
+// sockets and files, so only descriptors NSS reports as secured are captured
+// here, keyed by the PRFileDesc pointer; plain sockets are seen by the libc
+// hooks underneath. The real call runs under IN_HOOK so the ciphertext it
+// writes to the socket is not captured a second time.
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// `SSL_SecurityStatus` from libssl3 (NSS).
+type SslSecurityStatusFn = unsafe extern "C" fn(
+    fd: *mut c_void,
+    on: *mut c_int,
+    cipher: *mut *mut libc::c_char,
+    key_size: *mut c_int,
+    secret_key_size: *mut c_int,
+    issuer: *mut *mut libc::c_char,
+    subject: *mut *mut libc::c_char,
+) -> c_int;
+
+static NSS_SECURITY_STATUS: OnceLock<Option<SslSecurityStatusFn>> = OnceLock::new();
+
+/// Whether `fd` is an NSS socket with TLS switched on. Always false when
+/// libssl3 is not loaded.
+fn is_nss_secure(fd: *mut c_void) -> bool {
+    let status = NSS_SECURITY_STATUS.get_or_init(|| {
+        // SAFETY: dlsym with a NUL-terminated name; a null result means absent.
+        let sym = unsafe { libc::dlsym(libc::RTLD_DEFAULT, c"SSL_SecurityStatus".as_ptr()) };
+        // SAFETY: a non-null SSL_SecurityStatus has this signature.
+        (!sym.is_null())
+            .then(|| unsafe { std::mem::transmute::<*mut c_void, SslSecurityStatusFn>(sym) })
+    });
+    let Some(status) = status else {
+        return false;
+    };
+    let mut on: c_int = 0;
+    let (no_str, no_int) = (std::ptr::null_mut(), std::ptr::null_mut());
+    // SAFETY: fd is the application's PRFileDesc; NSS skips null out-pointers
+    // and fails (SECFailure = -1) for descriptors that are not SSL sockets.
+    let rc = unsafe { status(fd, &mut on, no_str, no_int, no_int, no_str, no_str) };
+    rc == 0 && on != 0
+}
+
+redhook::hook! {
+    unsafe fn PR_Write(
+        fd:     *mut c_void,
+        buf:    *const c_void,
+        amount: i32
+    ) -> i32 => phantom_pr_write {
+        if IN_HOOK.with(Cell::get) || !is_nss_secure(fd) {
+            // SAFETY: delegating to the real PR_Write.
+            return unsafe { redhook::real!(PR_Write)(fd, buf, amount) };
+        }
+        IN_HOOK.with(|g| g.set(true));
+        // SAFETY: delegating to the real PR_Write.
+        let result = unsafe { redhook::real!(PR_Write)(fd, buf, amount) };
+        if result > 0 {
+            // SAFETY: buf points to `result` bytes that were written successfully.
+            let data = unsafe { std::slice::from_raw_parts(buf as *const u8, result as usize) };
+            note_tls_session(fd as usize, TlsLib::Nss);
+            process_outgoing(fd as usize, data, true);
+        }
+        IN_HOOK.with(|g| g.set(false));
+        result
+    }
+}
+
+redhook::hook! {
+    unsafe fn PR_Read(
+        fd:     *mut c_void,
+        buf:    *mut c_void,
+        amount: i32
+    ) -> i32 => phantom_pr_read {
+        if IN_HOOK.with(Cell::get) || !is_nss_secure(fd) {
+            // SAFETY: delegating to the real PR_Read.
+            return unsafe { redhook::real!(PR_Read)(fd, buf, amount) };
+        }
+        IN_HOOK.with(|g| g.set(true));
+        // SAFETY: delegating to the real PR_Read.
+        let result = unsafe { redhook::real!(PR_Read)(fd, buf, amount) };
+        if result > 0 {
+            // SAFETY: buf holds `result` decrypted bytes from PR_Read.
+            let data = unsafe { std::slice::from_raw_parts(buf as *const u8, result as usize) };
+            process_incoming(fd as usize, data);
+        } else if result == 0 && amount > 0 {
+            // `close_notify` from the peer, or EOF on the transport.
+            process_eof(fd as usize);
+        }
+        IN_HOOK.with(|g| g.set(false));
+        result
+    }
+}
+
+redhook::hook! {
+    unsafe fn PR_Close(fd: *mut c_void) -> c_int => phantom_pr_close {
+        // Emit any buffered partial response while the descriptor is alive.
+        // The libc close() underneath tears down the socket's own state.
+        IN_HOOK.with(|g| {
+            if !g.get() {
+                g.set(true);
+                process_teardown(fd as usize);
+                g.set(false);
+            }
+        });
+        // SAFETY: delegating to the real PR_Close.
+        unsafe { redhook::real!(PR_Close)(fd) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_small_message_is_sent_as_one_frame() {
+        let mut frames = Vec::new();
+        assert!(send_fragmented(&[7; MAX_DATAGRAM], |f| {
+            frames.push(f.to_vec());
+            true
+        }));
+        assert_eq!(frames, [vec![7; MAX_DATAGRAM]]);
+    }
+
+    #[test]
+    fn test_large_message_is_fragmented_and_reassembles() {
+        let data: Vec<u8> = (0..MAX_DATAGRAM * 2 + 10).map(|i| i as u8).collect();
+        let mut frames = Vec::new();
+        assert!(send_fragmented(&data, |f| {
+            frames.push(f.to_vec());
+            true
+        }));
+        assert_eq!(frames.len(), 3);
+        let mut joined = Vec::new();
+        for (i, frame) in frames.iter().enumerate() {
+            assert!(frame.len() <= MAX_DATAGRAM);
+            assert_eq!(&frame[..4], FRAGMENT_MAGIC);
+            assert_eq!(frame[4..12], frames[0][4..12], "one message id");
+            assert_eq!(u16::from_le_bytes([frame[12], frame[13]]), i as u16);
+            assert_eq!(u16::from_le_bytes([frame[14], frame[15]]), 3);
+            joined.extend_from_slice(&frame[FRAGMENT_HEADER..]);
+        }
+        assert_eq!(joined, data);
+    }
+
+    #[test]
+    fn test_fragmenting_gives_up_on_oversized_or_failed_sends() {
+        let chunk = MAX_DATAGRAM - FRAGMENT_HEADER;
+        let mut sent = 0;
+        assert!(!send_fragmented(
+            &vec![0; chunk * MAX_FRAGMENTS + 1],
+            |_| {
+                sent += 1;
+                true
+            }
+        ));
+        assert_eq!(sent, 0);
+        assert!(!send_fragmented(&vec![0; chunk * 4], |_| {
+            sent += 1;
+            sent < 2
+        }));
+        assert_eq!(sent, 2);
+    }
+
+    #[test]
+    fn test_full_shard_evicts_least_recently_touched() {
+        let state = || FdState::CollectingRequest {
+            buf: vec![],
+            sent: 0,
+        };
+        let capacity = shard_capacity();
+        let mut shard = Shard::default();
+        let base = Instant::now();
+        for key in 0..capacity {
+            shard.insert(key, state());
+            shard
+                .touched
+                .insert(key, base + Duration::from_millis(key as u64));
+        }
+        // Replacing an existing key never evicts.
+        shard.insert(0, state());
+        assert_eq!(shard.len(), capacity);
+
+        shard.touched.insert(0, base + Duration::from_secs(3600));
+        shard.insert(capacity, state());
+        assert_eq!(shard.len(), capacity);
+        assert!(shard.contains_key(&0) && !shard.contains_key(&1));
+
+        // A key never touched goes before any touched one.
+        shard.insert(capacity + 1, state());
+        assert!(shard.contains_key(&2) && !shard.contains_key(&capacity));
     }
 }
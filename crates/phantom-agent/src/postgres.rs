@@ -0,0 +1,671 @@
+//! PostgreSQL queries over the v3 frontend/backend protocol (simple and
+//! extended query).
+//!
+//! Connections are recognised by their untyped startup packet (or SSLRequest)
+//! sent to the Postgres port, 5432 unless `PHANTOM_POSTGRES_PORT` says
+//! otherwise. After startup every message is `type:u8 | len:u32 | payload`.
+//! Each simple `Query` completes at `ReadyForQuery`; each extended-protocol
+//! `Execute` completes at its `CommandComplete` or `ErrorResponse`. A server
+//! that accepts SSLRequest switches the socket to TLS, which the libc hooks
+//! cannot read, so tracking of the socket stops there; the session's startup
+//! packet is then seen again above the TLS layer, on the same port.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::OnceLock;
+use std::time::Instant;
+
+use crate::{FdState, Shard, config_var, connection_peer, max_body, max_buf, now_ms, read_be_u32};
+
+const PG_DEFAULT_PORT: u16 = 5432;
+const PG_PROTOCOL_V3: u32 = 196_608;
+const PG_SSL_REQUEST: u32 = 80_877_103;
+const PG_GSSENC_REQUEST: u32 = 80_877_104;
+/// Largest startup packet accepted when probing a new connection.
+const PG_MAX_STARTUP: usize = 10_000;
+/// Maximum queued queries / prepared statements tracked per connection.
+const PG_MAX_PENDING: usize = 1024;
+/// Frontend message types whose payload is needed.
+const PG_FRONTEND_KEEP: &[u8] = b"QPBEC";
+/// Backend message types whose payload is needed.
+const PG_BACKEND_KEEP: &[u8] = b"CE";
+
+static PG_PORT: OnceLock<u16> = OnceLock::new();
+
+fn postgres_port() -> u16 {
+    *PG_PORT.get_or_init(|| {
+        config_var("PHANTOM_POSTGRES_PORT")
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(PG_DEFAULT_PORT)
+    })
+}
+
+/// Cheap check for a startup packet, SSLRequest or GSSENCRequest, so that
+/// only likely Postgres connections pay for a `getpeername`.
+fn looks_like_pg_startup(data: &[u8]) -> bool {
+    let (Some(len), Some(code)) = (read_be_u32(data, 0), read_be_u32(data, 4)) else {
+        return false;
+    };
+    (8..=PG_MAX_STARTUP as u32).contains(&len)
+        && matches!(code, PG_PROTOCOL_V3 | PG_SSL_REQUEST | PG_GSSENC_REQUEST)
+}
+
+/// Splits the NUL-terminated string at the start of `buf` from the rest.
+/// A truncated payload may lack the terminator; the whole buffer is the string.
+fn pg_cstr(buf: &[u8]) -> (String, &[u8]) {
+    match buf.iter().position(|&b| b == 0) {
+        Some(nul) => (
+            String::from_utf8_lossy(&buf[..nul]).into_owned(),
+            &buf[nul + 1..],
+        ),
+        None => (String::from_utf8_lossy(buf).into_owned(), &[]),
+    }
+}
+
+/// One direction of a Postgres connection. Reassembles messages from
+/// segments and skips over the payload of messages that are only counted
+/// (e.g. `DataRow`), so large result sets are never buffered.
+#[derive(Default)]
+struct PgStream {
+    buf: Vec<u8>,
+    /// Bytes of the current message still to be discarded.
+    skip: usize,
+}
+
+impl PgStream {
+    /// Appends `data` and returns every message it completes as
+    /// `(type, payload)`. Payloads are kept only for types in `keep` (cut at
+    /// `max_body()`); other types come back with an empty payload.
+    /// Returns `None` when the stream is not valid Postgres framing.
+    fn feed(&mut self, mut data: &[u8], keep: &[u8]) -> Option<Vec<(u8, Vec<u8>)>> {
+        if self.skip > 0 {
+            let n = self.skip.min(data.len());
+            self.skip -= n;
+            data = &data[n..];
+        }
+        if self.buf.len() + data.len() > max_buf() {
+            return None;
+        }
+        self.buf.extend_from_slice(data);
+
+        let mut out = Vec::new();
+        let mut pos = 0;
+        while let Some(len) = read_be_u32(&self.buf, pos + 1) {
+            let ty = self.buf[pos];
+            let len = len as usize;
+            if len < 4 || !ty.is_ascii_alphanumeric() {
+                return None;
+            }
+            let body = pos + 5;
+            let end = pos + 1 + len;
+            let payload = if keep.contains(&ty) {
+                let want = (len - 4).min(max_body());
+                let payload = self.buf.get(body..body + want);
+                let Some(payload) = payload else { break };
+                payload.to_vec()
+            } else {
+                Vec::new()
+            };
+            out.push((ty, payload));
+            if self.buf.len() >= end {
+                pos = end;
+            } else {
+                self.skip = end - self.buf.len();
+                pos = self.buf.len();
+            }
+        }
+        self.buf.drain(..pos);
+        Some(out)
+    }
+}
+
+struct PgQuery {
+    text: String,
+    /// Extended-protocol `Execute` (completes at `CommandComplete`) rather
+    /// than a simple `Query` (completes at `ReadyForQuery`).
+    extended: bool,
+    started_at: Instant,
+    timestamp_ms: u64,
+    data_rows: u64,
+    tag: Option<String>,
+    error: Option<PgErrorMsg>,
+}
+
+enum PgPending {
+    Query(PgQuery),
+    /// A client `Sync`: the next `ReadyForQuery` closes the batch before it.
+    Sync,
+}
+
+/// Per-connection state for a PostgreSQL connection.
+pub(crate) struct PgConnState {
+    send: PgStream,
+    recv: PgStream,
+    /// Still expecting an untyped startup packet from the client.
+    startup: bool,
+    /// Sent SSLRequest/GSSENCRequest; the server answers with one byte.
+    awaiting_encryption_reply: bool,
+    user: Option<String>,
+    database: Option<String>,
+    dest_addr: String,
+    /// Prepared statement name → query text (`""` is the unnamed statement).
+    statements: HashMap<String, String>,
+    /// Portal name → query text of the statement bound to it.
+    portals: HashMap<String, String>,
+    pending: VecDeque<PgPending>,
+}
+
+#[derive(serde::Serialize)]
+pub(crate) struct PgErrorMsg {
+    severity: String,
+    code: String,
+    message: String,
+}
+
+#[derive(serde::Serialize)]
+pub(crate) struct PgTraceMsg {
+    kind: &'static str,
+    query: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    command: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rows: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) error: Option<PgErrorMsg>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    database: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user: Option<String>,
+    pub(crate) dest_addr: String,
+    duration_ms: u64,
+    timestamp_ms: u64,
+}
+
+/// Parses `ErrorResponse` fields: `code:u8 | value\0`, ended by a zero byte.
+fn parse_pg_error(mut payload: &[u8]) -> PgErrorMsg {
+    let mut err = PgErrorMsg {
+        severity: String::new(),
+        code: String::new(),
+        message: String::new(),
+    };
+    while let Some((&field, rest)) = payload.split_first() {
+        if field == 0 {
+            break;
+        }
+        let (value, rest) = pg_cstr(rest);
+        match field {
+            // 'V' is the non-localized severity; prefer it over 'S'.
+            b'V' => err.severity = value,
+            b'S' if err.severity.is_empty() => err.severity = value,
+            b'C' => err.code = value,
+            b'M' => err.message = value,
+            _ => {}
+        }
+        payload = rest;
+    }
+    err
+}
+
+/// Row count from a completion tag such as `"SELECT 5"` or `"INSERT 0 3"`.
+fn pg_tag_rows(tag: &str) -> Option<u64> {
+    let mut words = tag.split_whitespace();
+    let command = words.next()?;
+    let counted = matches!(
+        command,
+        "SELECT" | "INSERT" | "UPDATE" | "DELETE" | "MERGE" | "FETCH" | "MOVE" | "COPY"
+    );
+    if counted {
+        words.last()?.parse().ok()
+    } else {
+        None
+    }
+}
+
+impl PgConnState {
+    fn new(dest_addr: String) -> Self {
+        Self {
+            send: PgStream::default(),
+            recv: PgStream::default(),
+            startup: true,
+            awaiting_encryption_reply: false,
+            user: None,
+            database: None,
+            dest_addr,
+            statements: HashMap::new(),
+            portals: HashMap::new(),
+            pending: VecDeque::new(),
+        }
+    }
+
+    fn finish(&self, q: PgQuery) -> PgTraceMsg {
+        let command = q
+            .tag
+            .as_deref()
+            .and_then(|t| t.split_whitespace().next())
+            .map(str::to_string);
+        let rows = q
+            .tag
+            .as_deref()
+            .and_then(pg_tag_rows)
+            .or((q.data_rows > 0).then_some(q.data_rows));
+        PgTraceMsg {
+            kind: "postgres",
+            query: q.text,
+            command,
+            rows,
+            error: q.error,
+            database: self.database.clone(),
+            user: self.user.clone(),
+            dest_addr: self.dest_addr.clone(),
+            duration_ms: q.started_at.elapsed().as_millis() as u64,
+            timestamp_ms: q.timestamp_ms,
+        }
+    }
+
+    fn queue(&mut self, text: String, extended: bool) {
+        if self.pending.len() < PG_MAX_PENDING {
+            self.pending.push_back(PgPending::Query(PgQuery {
+                text,
+                extended,
+                started_at: Instant::now(),
+                timestamp_ms: now_ms(),
+                data_rows: 0,
+                tag: None,
+                error: None,
+            }));
+        }
+    }
+
+    /// Consumes the startup packet. Returns `false` for packets that end
+    /// tracking (e.g. CancelRequest) or malformed input.
+    fn on_startup(&mut self) -> bool {
+        let (Some(len), Some(code)) = (
+            read_be_u32(&self.send.buf, 0),
+            read_be_u32(&self.send.buf, 4),
+        ) else {
+            return true; // wait for the rest of the header
+        };
+        let len = len as usize;
+        if !(8..=PG_MAX_STARTUP).contains(&len) {
+            return false;
+        }
+        if self.send.buf.len() < len {
+            return true;
+        }
+        match code {
+            PG_SSL_REQUEST | PG_GSSENC_REQUEST => self.awaiting_encryption_reply = true,
+            PG_PROTOCOL_V3 => {
+                let mut params = &self.send.buf[8..len];
+                while !params.is_empty() && params[0] != 0 {
+                    let (name, rest) = pg_cstr(params);
+                    let (value, rest) = pg_cstr(rest);
+                    match name.as_str() {
+                        "user" => self.user = Some(value),
+                        "database" => self.database = Some(value),
+                        _ => {}
+                    }
+                    params = rest;
+                }
+                self.startup = false;
+            }
+            _ => return false,
+        }
+        self.send.buf.drain(..len);
+        true
+    }
+
+    /// Client → server bytes. Returns `false` when tracking should stop.
+    fn on_send(&mut self, data: &[u8]) -> bool {
+        if self.startup {
+            if self.send.buf.len() + data.len() > PG_MAX_STARTUP {
+                return false;
+            }
+            self.send.buf.extend_from_slice(data);
+            // The client may send SSLRequest and, after 'N', the startup packet.
+            while self.startup && !self.awaiting_encryption_reply && !self.send.buf.is_empty() {
+                let before = self.send.buf.len();
+                if !self.on_startup() {
+                    return false;
+                }
+                if self.send.buf.len() == before {
+                    return true;
+                }
+            }
+            if self.startup || self.send.buf.is_empty() {
+                return true;
+            }
+            // Typed messages pipelined behind the startup packet.
+            let rest = std::mem::take(&mut self.send.buf);
+            return self.on_send(&rest);
+        }
+
+        let Some(messages) = self.send.feed(data, PG_FRONTEND_KEEP) else {
+            return false;
+        };
+        for (ty, payload) in messages {
+            match ty {
+                b'Q' => {
+                    let (text, _) = pg_cstr(&payload);
+                    self.queue(text, false);
+                }
+                b'P' => {
+                    let (name, rest) = pg_cstr(&payload);
+                    let (text, _) = pg_cstr(rest);
+                    if self.statements.len() < PG_MAX_PENDING || self.statements.contains_key(&name)
+                    {
+                        self.statements.insert(name, text);
+                    }
+                }
+                b'B' => {
+                    let (portal, rest) = pg_cstr(&payload);
+                    let (statement, _) = pg_cstr(rest);
+                    let text = self.statements.get(&statement).cloned().unwrap_or_default();
+                    if self.portals.len() < PG_MAX_PENDING || self.portals.contains_key(&portal) {
+                        self.portals.insert(portal, text);
+                    }
+                }
+                b'E' => {
+                    let (portal, _) = pg_cstr(&payload);
+                    let text = self.portals.get(&portal).cloned().unwrap_or_default();
+                    self.queue(text, true);
+                }
+                b'C' => {
+                    // Close: 'S' statement or 'P' portal, then its name.
+                    if let Some((&what, rest)) = payload.split_first() {
+                        let (name, _) = pg_cstr(rest);
+                        match what {
+                            b'S' => self.statements.remove(&name),
+                            _ => self.portals.remove(&name),
+                        };
+                    }
+                }
+                b'S' if self.pending.len() < PG_MAX_PENDING => {
+                    self.pending.push_back(PgPending::Sync);
+                }
+                _ => {}
+            }
+        }
+        true
+    }
+
+    /// The query the server is currently answering.
+    fn current(&mut self) -> Option<&mut PgQuery> {
+        match self.pending.front_mut() {
+            Some(PgPending::Query(q)) => Some(q),
+            _ => None,
+        }
+    }
+
+    fn pop_current(&mut self) -> Option<PgQuery> {
+        match self.pending.front() {
+            Some(PgPending::Query(_)) => match self.pending.pop_front() {
+                Some(PgPending::Query(q)) => Some(q),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Server → client bytes. Returns the queries they complete, or `None`
+    /// when tracking should stop.
+    fn on_recv(&mut self, mut data: &[u8]) -> Option<Vec<PgTraceMsg>> {
+        if self.awaiting_encryption_reply {
+            let (&reply, rest) = data.split_first()?;
+            if reply != b'N' {
+                return None; // TLS or GSS encryption follows
+            }
+            self.awaiting_encryption_reply = false;
+            data = rest;
+            // The startup packet may already be buffered behind the request.
+            if !self.send.buf.is_empty() {
+                let buffered = std::mem::take(&mut self.send.buf);
+                if !self.on_send(&buffered) {
+                    return None;
+                }
+            }
+        }
+
+        let mut done = Vec::new();
+        for (ty, payload) in self.recv.feed(data, PG_BACKEND_KEEP)? {
+            match ty {
+                b'D' => {
+                    if let Some(q) = self.current() {
+                        q.data_rows += 1;
+                    }
+                }
+                b'C' | b'I' => {
+                    let tag = (ty == b'C').then(|| pg_cstr(&payload).0);
+                    let extended = self.current().map(|q| {
+                        q.tag = tag;
+                        q.extended
+                    });
+                    if extended == Some(true)
+                        && let Some(q) = self.pop_current()
+                    {
+                        done.push(self.finish(q));
+                    }
+                }
+                b'E' => {
+                    let error = parse_pg_error(&payload);
+                    let extended = self.current().map(|q| {
+                        q.error = Some(error);
+                        q.extended
+                    });
+                    match extended {
+                        Some(true) => {
+                            if let Some(q) = self.pop_current() {
+                                done.push(self.finish(q));
+                            }
+                        }
+                        Some(false) => {}
+                        // Startup or authentication failure: the server closes.
+                        None if self.pending.is_empty() => return None,
+                        None => {}
+                    }
+                }
+                b'Z' => {
+                    // Extended queries still queued were skipped after an error.
+                    while let Some(front) = self.pending.pop_front() {
+                        match front {
+                            PgPending::Query(q) if q.extended => {}
+                            PgPending::Query(q) => {
+                                done.push(self.finish(q));
+                                break;
+                            }
+                            PgPending::Sync => break,
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        Some(done)
+    }
+}
+
+/// Feed client bytes to the Postgres tracker for `key`.
+///
+/// Returns `false` when the connection is not (or no longer) tracked as
+/// Postgres, leaving the other protocol trackers to look at it.
+pub(crate) fn track_postgres_send(map: &mut Shard, key: usize, data: &[u8], tls: bool) -> bool {
+    if let Some(FdState::Postgres(pg)) = map.get_mut(&key) {
+        if !pg.on_send(data) {
+            map.remove(&key);
+        }
+        return true;
+    }
+    if map.contains_key(&key) || !looks_like_pg_startup(data) {
+        return false;
+    }
+    let Some((dest_addr, port)) = connection_peer(key, tls) else {
+        return false;
+    };
+    if port != postgres_port() {
+        return false;
+    }
+    let mut pg = Box::new(PgConnState::new(dest_addr));
+    if pg.on_send(data) {
+        map.insert(key, FdState::Postgres(pg));
+    }
+    true
+}
+
+/// Feed server bytes to the Postgres tracker for `key`.
+///
+/// Returns `None` when the connection is not Postgres, otherwise the queries
+/// completed by this segment.
+pub(crate) fn track_postgres_recv(
+    map: &mut Shard,
+    key: usize,
+    data: &[u8],
+) -> Option<Vec<PgTraceMsg>> {
+    let Some(FdState::Postgres(pg)) = map.get_mut(&key) else {
+        return None;
+    };
+    match pg.on_recv(data) {
+        Some(done) => Some(done),
+        None => {
+            map.remove(&key);
+            Some(Vec::new())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A typed message: `ty | len | payload`.
+    fn msg(ty: u8, payload: &[u8]) -> Vec<u8> {
+        let mut out = vec![ty];
+        out.extend_from_slice(&(payload.len() as u32 + 4).to_be_bytes());
+        out.extend_from_slice(payload);
+        out
+    }
+
+    fn startup() -> Vec<u8> {
+        let params = b"user\0alice\0database\0shop\0\0";
+        let mut out = ((8 + params.len()) as u32).to_be_bytes().to_vec();
+        out.extend_from_slice(&PG_PROTOCOL_V3.to_be_bytes());
+        out.extend_from_slice(params);
+        out
+    }
+
+    fn connected() -> PgConnState {
+        let mut pg = PgConnState::new("10.0.0.1:5432".to_string());
+        assert!(pg.on_send(&startup()));
+        assert!(!pg.startup);
+        pg
+    }
+
+    #[test]
+    fn test_startup_packet_is_recognised() {
+        assert!(looks_like_pg_startup(&startup()));
+        let mut ssl = 8u32.to_be_bytes().to_vec();
+        ssl.extend_from_slice(&PG_SSL_REQUEST.to_be_bytes());
+        assert!(looks_like_pg_startup(&ssl));
+        assert!(!looks_like_pg_startup(b"GET / HTTP/1.1\r\n"));
+        let pg = connected();
+        assert_eq!(pg.user.as_deref(), Some("alice"));
+        assert_eq!(pg.database.as_deref(), Some("shop"));
+    }
+
+    #[test]
+    fn test_simple_query_completes_at_ready_for_query() {
+        let mut pg = connected();
+        assert!(pg.on_send(&msg(b'Q', b"SELECT * FROM t\0")));
+        let mut reply = msg(b'T', b"rowdesc");
+        reply.extend(msg(b'D', b"row1"));
+        reply.extend(msg(b'D', b"row2"));
+        reply.extend(msg(b'C', b"SELECT 2\0"));
+        assert!(pg.on_recv(&reply).unwrap().is_empty());
+        let done = pg.on_recv(&msg(b'Z', b"I")).unwrap();
+        assert_eq!(done.len(), 1);
+        assert_eq!(done[0].query, "SELECT * FROM t");
+        assert_eq!(done[0].command.as_deref(), Some("SELECT"));
+        assert_eq!(done[0].rows, Some(2));
+        assert_eq!(done[0].database.as_deref(), Some("shop"));
+        assert!(done[0].error.is_none());
+    }
+
+    #[test]
+    fn test_extended_query_uses_the_prepared_statement_text() {
+        let mut pg = connected();
+        let mut send = msg(b'P', b"s1\0UPDATE t SET a = $1\0\0\0");
+        send.extend(msg(b'B', b"\0s1\0\0\0\0\0\0\0"));
+        send.extend(msg(b'E', b"\0\0\0\0\0"));
+        send.extend(msg(b'S', b""));
+        assert!(pg.on_send(&send));
+        let mut reply = msg(b'1', b"");
+        reply.extend(msg(b'2', b""));
+        reply.extend(msg(b'C', b"UPDATE 3\0"));
+        let done = pg.on_recv(&reply).unwrap();
+        assert_eq!(done[0].query, "UPDATE t SET a = $1");
+        assert_eq!(done[0].rows, Some(3));
+        assert!(pg.on_recv(&msg(b'Z', b"I")).unwrap().is_empty());
+        assert!(pg.pending.is_empty());
+    }
+
+    #[test]
+    fn test_error_response_is_attached_to_the_query() {
+        let mut pg = connected();
+        assert!(pg.on_send(&msg(b'Q', b"SELECT nope\0")));
+        let mut reply = msg(
+            b'E',
+            b"SERROR\0VERROR\0C42703\0Mcolumn \"nope\" does not exist\0\0",
+        );
+        reply.extend(msg(b'Z', b"I"));
+        let done = pg.on_recv(&reply).unwrap();
+        let error = done[0].error.as_ref().unwrap();
+        assert_eq!(error.severity, "ERROR");
+        assert_eq!(error.code, "42703");
+        assert_eq!(error.message, "column \"nope\" does not exist");
+        assert_eq!(done[0].command, None);
+    }
+
+    #[test]
+    fn test_messages_split_across_segments_and_large_rows_skipped() {
+        let mut pg = connected();
+        let query = msg(b'Q', b"SELECT blob FROM t\0");
+        assert!(pg.on_send(&query[..3]));
+        assert!(pg.on_send(&query[3..]));
+        let row = msg(b'D', &vec![7; 100_000]);
+        assert!(pg.on_recv(&row[..50_000]).unwrap().is_empty());
+        assert!(pg.recv.buf.is_empty());
+        let mut rest = row[50_000..].to_vec();
+        rest.extend(msg(b'C', b"SELECT 1\0"));
+        rest.extend(msg(b'Z', b"I"));
+        let done = pg.on_recv(&rest).unwrap();
+        assert_eq!(done[0].query, "SELECT blob FROM t");
+        assert_eq!(done[0].rows, Some(1));
+    }
+
+    #[test]
+    fn test_refused_ssl_request_continues_in_plaintext() {
+        let mut pg = PgConnState::new("10.0.0.1:5432".to_string());
+        let mut ssl = 8u32.to_be_bytes().to_vec();
+        ssl.extend_from_slice(&PG_SSL_REQUEST.to_be_bytes());
+        assert!(pg.on_send(&ssl));
+        assert!(pg.awaiting_encryption_reply);
+        assert!(pg.on_recv(b"N").unwrap().is_empty());
+        assert!(pg.on_send(&startup()));
+        assert_eq!(pg.user.as_deref(), Some("alice"));
+
+        // An accepted one switches the socket to TLS.
+        let mut pg = PgConnState::new("10.0.0.1:5432".to_string());
+        assert!(pg.on_send(&ssl));
+        assert!(pg.on_recv(b"S").is_none());
+    }
+
+    #[test]
+    fn test_tag_rows() {
+        assert_eq!(pg_tag_rows("SELECT 5"), Some(5));
+        assert_eq!(pg_tag_rows("INSERT 0 3"), Some(3));
+        assert_eq!(pg_tag_rows("CREATE TABLE"), None);
+    }
+
+    #[test]
+    fn test_malformed_framing_stops_tracking() {
+        let mut pg = connected();
+        assert!(!pg.on_send(&[b'Q', 0, 0, 0, 1]));
+    }
+}
@@ -0,0 +1,644 @@
+//! Trace context propagation (`PHANTOM_PROPAGATE=1`).
+//!
+//! Outbound requests are rewritten before they reach the real write: an
+//! HTTP/1 request gets a `traceparent` line after its request line, and an
+//! HTTP/2 HEADERS frame a `traceparent` field at the end of its header block,
+//! as a literal without indexing so neither side's HPACK table changes. The
+//! ids in it become the captured trace's own, so spans of instrumented
+//! downstream services hang off it. Requests that already carry `traceparent`
+//! are left alone.
+//!
+//! Only requests whose headers are complete in one write are rewritten, and
+//! HTTP/2 frames only when the write starts on a frame boundary. The caller is
+//! told byte counts of its own buffer; a partial write that stops inside an
+//! insertion is completed first, since the caller cannot resend those bytes.
+//! `writev()` and `sendmsg()` are not rewritten.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+use libc::{c_int, c_void, size_t, ssize_t};
+
+use crate::http1::looks_like_http_request;
+use crate::http2::{
+    H2_FLAG_END_HEADERS, H2_FRAME_HDR_LEN, H2_PREFACE, H2_TYPE_HEADERS, h2_header_block_range,
+    parse_h2_frame_header,
+};
+use crate::{
+    FdState, IN_HOOK, TlsLib, config_var, fd_key, is_inbound, is_socket, note_tls_session,
+    process_outgoing, socket_sent, state_map,
+};
+
+static PROPAGATE: OnceLock<bool> = OnceLock::new();
+
+/// Whether outbound requests get a `traceparent` header.
+fn propagate() -> bool {
+    *PROPAGATE.get_or_init(|| config_var("PHANTOM_PROPAGATE").is_some_and(|v| v == "1"))
+}
+
+/// W3C trace context put on a rewritten request.
+#[derive(Clone, Copy)]
+pub(crate) struct TraceContext {
+    pub(crate) trace_id: [u8; 16],
+    pub(crate) span_id: [u8; 8],
+}
+
+impl TraceContext {
+    /// Fresh random ids, `None` if the kernel gives no randomness.
+    fn generate() -> Option<Self> {
+        let mut bytes = [0u8; 24];
+        // SAFETY: bytes is writable for its whole length.
+        let n = unsafe { libc::getrandom(bytes.as_mut_ptr().cast(), bytes.len(), 0) };
+        if n != bytes.len() as ssize_t {
+            return None;
+        }
+        let mut ctx = Self {
+            trace_id: [0; 16],
+            span_id: [0; 8],
+        };
+        ctx.trace_id.copy_from_slice(&bytes[..16]);
+        ctx.span_id.copy_from_slice(&bytes[16..]);
+        Some(ctx)
+    }
+
+    /// `traceparent` value: version `00`, sampled.
+    fn traceparent(&self) -> String {
+        format!("00-{}-{}-01", hex(&self.trace_id), hex(&self.span_id))
+    }
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+thread_local! {
+    /// Contexts inserted by the write being recorded on this thread, by
+    /// HTTP/2 stream id (`0` for HTTP/1), for the requests parsed from it.
+    static PROPAGATED: RefCell<Vec<(u32, TraceContext)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// The context inserted into the request on `stream_id` by this write.
+pub(crate) fn take_propagated(stream_id: u32) -> Option<TraceContext> {
+    PROPAGATED
+        .try_with(|propagated| {
+            let mut propagated = propagated.borrow_mut();
+            let i = propagated.iter().position(|(id, _)| *id == stream_id)?;
+            Some(propagated.swap_remove(i).1)
+        })
+        .ok()
+        .flatten()
+}
+
+fn clear_propagated() {
+    let _ = PROPAGATED.try_with(|propagated| propagated.borrow_mut().clear());
+}
+
+/// `len` bytes spliced in at offset `at` of the caller's buffer. From `from`
+/// on (the frame whose length was patched) the bytes sent differ from it.
+struct Insertion {
+    from: usize,
+    at: usize,
+    len: usize,
+}
+
+/// A write's bytes with trace context inserted.
+struct Rewrite {
+    data: Vec<u8>,
+    insertions: Vec<Insertion>,
+    /// Context inserted into each request, by HTTP/2 stream id.
+    contexts: Vec<(u32, TraceContext)>,
+}
+
+impl Rewrite {
+    /// `base` (the caller's bytes, possibly patched in place) with each
+    /// piece inserted; pieces are in buffer order.
+    fn splice(
+        base: Vec<u8>,
+        pieces: Vec<(Insertion, Vec<u8>)>,
+        contexts: Vec<(u32, TraceContext)>,
+    ) -> Self {
+        let extra: usize = pieces.iter().map(|(_, bytes)| bytes.len()).sum();
+        let mut data = Vec::with_capacity(base.len() + extra);
+        let mut insertions = Vec::with_capacity(pieces.len());
+        let mut copied = 0;
+        for (insertion, bytes) in pieces {
+            data.extend_from_slice(&base[copied..insertion.at]);
+            data.extend_from_slice(&bytes);
+            copied = insertion.at;
+            insertions.push(insertion);
+        }
+        data.extend_from_slice(&base[copied..]);
+        Self {
+            data,
+            insertions,
+            contexts,
+        }
+    }
+
+    /// Hands the inserted contexts to the requests about to be recorded
+    /// from this write.
+    fn install(&self) {
+        let _ = PROPAGATED.try_with(|propagated| *propagated.borrow_mut() = self.contexts.clone());
+    }
+
+    /// Bytes of the caller's buffer covered by the first `sent` bytes of the
+    /// rewrite. When `sent` stops inside an insertion, the rest of it is
+    /// written with `write` first.
+    fn caller_bytes(
+        &self,
+        sent: usize,
+        fd: Option<c_int>,
+        write_rest: impl FnMut(&[u8]) -> ssize_t,
+    ) -> usize {
+        let mut shift = 0;
+        for insertion in &self.insertions {
+            let from = insertion.from + shift;
+            let end = insertion.at + shift + insertion.len;
+            if sent <= from {
+                break;
+            }
+            if sent < end {
+                write_all(&self.data[sent..end], fd, write_rest);
+                return insertion.at;
+            }
+            shift += insertion.len;
+        }
+        sent - shift
+    }
+}
+
+/// Writes all of `data`, waiting up to a second at a time for `fd` to drain
+/// when it is non-blocking. Gives up on any other error.
+fn write_all(mut data: &[u8], fd: Option<c_int>, mut write_rest: impl FnMut(&[u8]) -> ssize_t) {
+    while !data.is_empty() {
+        let n = write_rest(data);
+        if n > 0 {
+            data = &data[n as usize..];
+            continue;
+        }
+        let errno = std::io::Error::last_os_error().raw_os_error();
+        if errno == Some(libc::EINTR) {
+            continue;
+        }
+        let Some(fd) = fd.filter(|_| errno == Some(libc::EAGAIN)) else {
+            return;
+        };
+        let mut pfd = libc::pollfd {
+            fd,
+            events: libc::POLLOUT,
+            revents: 0,
+        };
+        // SAFETY: pfd is a valid pollfd array of one.
+        if unsafe { libc::poll(&mut pfd, 1, 1000) } <= 0 {
+            return;
+        }
+    }
+}
+
+/// `data`, about to be written on `key`, with trace context inserted into
+/// the requests it starts, or `None` when there is nothing to insert.
+fn propagation_rewrite(key: usize, data: &[u8]) -> Option<Rewrite> {
+    let map = state_map(key).lock().ok()?;
+    match map.get(&key) {
+        Some(FdState::Http2(h2))
+            if h2.send_buf.is_empty() && h2.send_cont_sid.is_none() && !h2.app_propagates =>
+        {
+            rewrite_h2(data, 0, |id| !h2.streams.contains_key(&id))
+        }
+        None | Some(FdState::CollectingResponse { .. }) if data.starts_with(H2_PREFACE) => {
+            rewrite_h2(data, H2_PREFACE.len(), |_| true)
+        }
+        None | Some(FdState::CollectingResponse { .. }) => rewrite_http1(data),
+        _ => None,
+    }
+}
+
+fn rewrite_http1(data: &[u8]) -> Option<Rewrite> {
+    if !looks_like_http_request(data) {
+        return None;
+    }
+    let mut headers = [httparse::EMPTY_HEADER; 64];
+    let mut req = httparse::Request::new(&mut headers);
+    let httparse::Status::Complete(_) = req.parse(data).ok()? else {
+        return None;
+    };
+    if req
+        .headers
+        .iter()
+        .any(|h| h.name.eq_ignore_ascii_case("traceparent"))
+    {
+        return None;
+    }
+    let at = data.iter().position(|&b| b == b'\n')? + 1;
+    let ctx = TraceContext::generate()?;
+    let line = format!("traceparent: {}\r\n", ctx.traceparent()).into_bytes();
+    let insertion = Insertion {
+        from: at,
+        at,
+        len: line.len(),
+    };
+    Some(Rewrite::splice(
+        data.to_vec(),
+        vec![(insertion, line)],
+        vec![(0, ctx)],
+    ))
+}
+
+const TRACEPARENT: &[u8] = b"traceparent";
+/// `traceparent` as an HPACK Huffman-coded string (RFC 7541 Appendix B).
+const TRACEPARENT_HUFFMAN: &[u8] = &[0x4d, 0x83, 0x21, 0x6b, 0x1d, 0x85, 0xa9, 0x3f];
+/// Frame size every HTTP/2 peer accepts; frames that would outgrow it are
+/// left alone.
+const H2_MIN_MAX_FRAME_SIZE: usize = 16_384;
+
+/// `traceparent` as an HPACK literal field without indexing, new name.
+fn hpack_traceparent(ctx: &TraceContext) -> Vec<u8> {
+    let value = ctx.traceparent();
+    // Both strings are shorter than 127 bytes, so each length is one byte.
+    let mut field = vec![0x00, TRACEPARENT.len() as u8];
+    field.extend_from_slice(TRACEPARENT);
+    field.push(value.len() as u8);
+    field.extend_from_slice(value.as_bytes());
+    field
+}
+
+/// Inserts `traceparent` into the HEADERS frames opening new streams in
+/// `data`, whose frames start at `start`. A block naming `traceparent` in
+/// the clear or Huffman-coded already has one.
+fn rewrite_h2(data: &[u8], start: usize, is_new_stream: impl Fn(u32) -> bool) -> Option<Rewrite> {
+    let mut patched = data.to_vec();
+    let mut pieces = Vec::new();
+    let mut contexts = Vec::new();
+    let mut pos = start;
+    while let Some((payload_len, frame_type, flags, stream_id)) =
+        parse_h2_frame_header(&data[pos..])
+    {
+        let total = H2_FRAME_HDR_LEN + payload_len;
+        if data.len() - pos < total {
+            break;
+        }
+        let payload = &data[pos + H2_FRAME_HDR_LEN..pos + total];
+        let (hb_start, hb_end) = h2_header_block_range(payload, flags);
+        let block = &payload[hb_start..hb_end];
+        if frame_type == H2_TYPE_HEADERS
+            && flags & H2_FLAG_END_HEADERS != 0
+            && stream_id % 2 == 1
+            && hb_end > hb_start
+            && is_new_stream(stream_id)
+            && !contains(block, TRACEPARENT)
+            && !contains(block, TRACEPARENT_HUFFMAN)
+            && let Some(ctx) = TraceContext::generate()
+        {
+            let field = hpack_traceparent(&ctx);
+            let new_len = payload_len + field.len();
+            if new_len <= H2_MIN_MAX_FRAME_SIZE {
+                patched[pos..pos + 3].copy_from_slice(&(new_len as u32).to_be_bytes()[1..]);
+                let insertion = Insertion {
+                    from: pos,
+                    at: pos + H2_FRAME_HDR_LEN + hb_end,
+                    len: field.len(),
+                };
+                pieces.push((insertion, field));
+                contexts.push((stream_id, ctx));
+            }
+        }
+        pos += total;
+    }
+    (!pieces.is_empty()).then(|| Rewrite::splice(patched, pieces, contexts))
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|w| w == needle)
+}
+
+/// Writes `len` bytes at `buf` to socket `fd` with trace context inserted,
+/// and records them. The rewrite, the write through `real_write`, the
+/// recording and the completion of a partial write all run under
+/// [`IN_HOOK`]. `None` when called from inside another hook or there is
+/// nothing to insert; the caller then writes its buffer as is.
+///
+/// # Safety
+/// `buf` must be null or point to `len` readable bytes.
+pub(crate) unsafe fn write_propagated(
+    fd: c_int,
+    buf: *const c_void,
+    len: size_t,
+    real_write: impl Fn(&[u8]) -> ssize_t,
+) -> Option<ssize_t> {
+    if !propagate() || buf.is_null() || len == 0 {
+        return None;
+    }
+    IN_HOOK.with(|g| {
+        if g.get() {
+            return None;
+        }
+        g.set(true);
+        let result = (is_socket(fd) && !is_inbound(fd))
+            .then(|| {
+                // SAFETY: buf points to `len` readable bytes per the caller.
+                let data = unsafe { std::slice::from_raw_parts(buf as *const u8, len) };
+                let rewrite = propagation_rewrite(fd_key(fd), data)?;
+                let result = real_write(&rewrite.data);
+                rewrite.install();
+                if result > 0 {
+                    socket_sent(fd, &rewrite.data[..result as usize]);
+                }
+                clear_propagated();
+                if result <= 0 {
+                    return Some(result);
+                }
+                Some(rewrite.caller_bytes(result as usize, Some(fd), &real_write) as ssize_t)
+            })
+            .flatten();
+        g.set(false);
+        result
+    })
+}
+
+/// A failed rewritten TLS write: the caller's buffer, its length and the rewrite.
+type PendingRewrite = (usize, usize, Rewrite);
+
+/// Rewrites of TLS writes that failed, by session: OpenSSL wants a retried
+/// `SSL_write` to pass the same buffer, so a retry with the same caller
+/// buffer sends the same rewrite.
+static TLS_REWRITES: OnceLock<Mutex<HashMap<usize, PendingRewrite>>> = OnceLock::new();
+
+/// [`write_propagated`] for TLS session `ssl`. `real_write` makes the real
+/// call and returns its result and the bytes it wrote, `None` when it failed;
+/// a failed write keeps the rewrite for the retry, which sends it again. The
+/// rewrite is only used when it is at most `limit` bytes. Returns the real
+/// call's result and the bytes of the caller's buffer it covered.
+///
+/// # Safety
+/// `buf` must be null or point to `len` readable bytes.
+pub(crate) unsafe fn tls_write_propagated<R>(
+    ssl: *mut c_void,
+    buf: *const c_void,
+    len: usize,
+    limit: usize,
+    real_write: impl Fn(&[u8]) -> (R, Option<usize>),
+) -> Option<(R, Option<usize>)> {
+    if !propagate() || buf.is_null() || len == 0 {
+        return None;
+    }
+    IN_HOOK.with(|g| {
+        if g.get() {
+            return None;
+        }
+        g.set(true);
+        let pending = TLS_REWRITES
+            .get()
+            .and_then(|pending| pending.lock().ok()?.remove(&(ssl as usize)))
+            .filter(|&(ptr, n, _)| (ptr, n) == (buf as usize, len))
+            .map(|(_, _, rewrite)| rewrite);
+        let rewrite = pending
+            .or_else(|| {
+                // SAFETY: buf points to `len` readable bytes per the caller.
+                let data = unsafe { std::slice::from_raw_parts(buf as *const u8, len) };
+                propagation_rewrite(ssl as usize, data)
+            })
+            .filter(|rewrite| rewrite.data.len() <= limit);
+        let result = rewrite.map(|rewrite| {
+            let (result, sent) = real_write(&rewrite.data);
+            rewrite.install();
+            if let Some(n) = sent.filter(|&n| n > 0) {
+                note_tls_session(ssl as usize, TlsLib::OpenSsl);
+                process_outgoing(ssl as usize, &rewrite.data[..n], true);
+            }
+            clear_propagated();
+            let Some(sent) = sent else {
+                if let Ok(mut pending) = TLS_REWRITES
+                    .get_or_init(|| Mutex::new(HashMap::new()))
+                    .lock()
+                {
+                    pending.insert(ssl as usize, (buf as usize, len, rewrite));
+                }
+                return (result, None);
+            };
+            let write_rest = |rest: &[u8]| real_write(rest).1.map_or(-1, |n| n as ssize_t);
+            (result, Some(rewrite.caller_bytes(sent, None, write_rest)))
+        });
+        g.set(false);
+        result
+    })
+}
+
+pub(crate) fn forget_tls_rewrite(key: usize) {
+    if let Some(Ok(mut pending)) = TLS_REWRITES.get().map(Mutex::lock) {
+        pending.remove(&key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http2::{H2_FLAG_END_STREAM, H2_FLAG_PADDED, H2_TYPE_DATA};
+
+    /// A 9-byte HTTP/2 frame header.
+    fn frame_header(len: usize, frame_type: u8, flags: u8, stream_id: u32) -> Vec<u8> {
+        let mut header = (len as u32).to_be_bytes()[1..].to_vec();
+        header.extend_from_slice(&[frame_type, flags]);
+        header.extend_from_slice(&stream_id.to_be_bytes());
+        header
+    }
+
+    /// `:method GET`, `:scheme https`, `:path /` and `:authority a`.
+    const BLOCK: &[u8] = &[0x82, 0x87, 0x84, 0x41, 0x01, b'a'];
+
+    #[test]
+    fn test_rewrite_http1_inserts_traceparent_after_request_line() {
+        let data = b"GET /x HTTP/1.1\r\nHost: a\r\n\r\n";
+        let rewrite = rewrite_http1(data).unwrap();
+        let (stream_id, ctx) = rewrite.contexts[0];
+        assert_eq!(stream_id, 0);
+        let expected = format!(
+            "GET /x HTTP/1.1\r\ntraceparent: {}\r\nHost: a\r\n\r\n",
+            ctx.traceparent()
+        );
+        assert_eq!(rewrite.data, expected.as_bytes());
+        assert_eq!(rewrite.insertions.len(), 1);
+        assert_eq!(rewrite.insertions[0].at, 17);
+        assert_eq!(rewrite.insertions[0].len, "traceparent: \r\n".len() + 55);
+    }
+
+    #[test]
+    fn test_rewrite_http1_keeps_existing_traceparent() {
+        let data = b"GET / HTTP/1.1\r\nTraceparent: 00-x-y-01\r\n\r\n";
+        assert!(rewrite_http1(data).is_none());
+        assert!(rewrite_http1(b"GET / HTTP/1.1\r\nHost: a\r\n").is_none());
+    }
+
+    #[test]
+    fn test_traceparent_format() {
+        let ctx = TraceContext {
+            trace_id: [0xab; 16],
+            span_id: [0x01; 8],
+        };
+        assert_eq!(
+            ctx.traceparent(),
+            "00-abababababababababababababababab-0101010101010101-01"
+        );
+    }
+
+    #[test]
+    fn test_hpack_traceparent_is_literal_without_indexing() {
+        let ctx = TraceContext {
+            trace_id: [0x11; 16],
+            span_id: [0x22; 8],
+        };
+        let field = hpack_traceparent(&ctx);
+        let mut expected = vec![0x00, 11];
+        expected.extend_from_slice(b"traceparent");
+        expected.push(55);
+        expected.extend_from_slice(ctx.traceparent().as_bytes());
+        assert_eq!(field, expected);
+        let decoded = hpack::Decoder::new().decode(&field).unwrap();
+        assert_eq!(
+            decoded,
+            vec![(b"traceparent".to_vec(), ctx.traceparent().into_bytes())]
+        );
+    }
+
+    #[test]
+    fn test_huffman_traceparent_matches_encoded_name() {
+        // Literal without indexing, Huffman-coded name, empty value.
+        let mut field = vec![0x00, 0x80 | TRACEPARENT_HUFFMAN.len() as u8];
+        field.extend_from_slice(TRACEPARENT_HUFFMAN);
+        field.push(0x00);
+        let decoded = hpack::Decoder::new().decode(&field).unwrap();
+        assert_eq!(decoded, vec![(b"traceparent".to_vec(), Vec::new())]);
+    }
+
+    #[test]
+    fn test_rewrite_h2_patches_frame_length_and_appends_field() {
+        let flags = H2_FLAG_END_HEADERS | H2_FLAG_END_STREAM;
+        let mut data = H2_PREFACE.to_vec();
+        data.extend(frame_header(BLOCK.len(), H2_TYPE_HEADERS, flags, 1));
+        data.extend_from_slice(BLOCK);
+        let rewrite = rewrite_h2(&data, H2_PREFACE.len(), |_| true).unwrap();
+        let (stream_id, ctx) = rewrite.contexts[0];
+        assert_eq!(stream_id, 1);
+
+        let field = hpack_traceparent(&ctx);
+        let mut expected = H2_PREFACE.to_vec();
+        expected.extend(frame_header(
+            BLOCK.len() + field.len(),
+            H2_TYPE_HEADERS,
+            flags,
+            1,
+        ));
+        expected.extend_from_slice(BLOCK);
+        expected.extend_from_slice(&field);
+        assert_eq!(rewrite.data, expected);
+
+        let payload = &rewrite.data[H2_PREFACE.len() + H2_FRAME_HDR_LEN..];
+        let decoded = hpack::Decoder::new().decode(payload).unwrap();
+        assert_eq!(decoded.last().unwrap().0, b"traceparent");
+    }
+
+    #[test]
+    fn test_rewrite_h2_inserts_before_padding_and_skips_other_frames() {
+        let flags = H2_FLAG_END_HEADERS | H2_FLAG_PADDED;
+        let mut payload = vec![2];
+        payload.extend_from_slice(BLOCK);
+        payload.extend_from_slice(&[0, 0]);
+        let mut data = frame_header(payload.len(), H2_TYPE_HEADERS, flags, 3);
+        data.extend_from_slice(&payload);
+        data.extend(frame_header(2, H2_TYPE_DATA, H2_FLAG_END_STREAM, 3));
+        data.extend_from_slice(b"hi");
+        let rewrite = rewrite_h2(&data, 0, |_| true).unwrap();
+        let field = hpack_traceparent(&rewrite.contexts[0].1);
+
+        let mut expected = frame_header(payload.len() + field.len(), H2_TYPE_HEADERS, flags, 3);
+        expected.push(2);
+        expected.extend_from_slice(BLOCK);
+        expected.extend_from_slice(&field);
+        expected.extend_from_slice(&[0, 0]);
+        expected.extend(frame_header(2, H2_TYPE_DATA, H2_FLAG_END_STREAM, 3));
+        expected.extend_from_slice(b"hi");
+        assert_eq!(rewrite.data, expected);
+        assert_eq!(rewrite.insertions[0].from, 0);
+        assert_eq!(rewrite.insertions[0].at, H2_FRAME_HDR_LEN + 1 + BLOCK.len());
+    }
+
+    #[test]
+    fn test_rewrite_h2_leaves_known_streams_and_traceparent_alone() {
+        let flags = H2_FLAG_END_HEADERS;
+        let mut data = frame_header(BLOCK.len(), H2_TYPE_HEADERS, flags, 5);
+        data.extend_from_slice(BLOCK);
+        assert!(rewrite_h2(&data, 0, |id| id != 5).is_none());
+
+        let mut block = BLOCK.to_vec();
+        block.extend_from_slice(&[0x00, 11]);
+        block.extend_from_slice(b"traceparent");
+        block.extend_from_slice(&[1, b'x']);
+        let mut data = frame_header(block.len(), H2_TYPE_HEADERS, flags, 5);
+        data.extend_from_slice(&block);
+        assert!(rewrite_h2(&data, 0, |_| true).is_none());
+
+        // Without END_HEADERS the block continues in CONTINUATION frames.
+        let mut data = frame_header(BLOCK.len(), H2_TYPE_HEADERS, 0, 5);
+        data.extend_from_slice(BLOCK);
+        assert!(rewrite_h2(&data, 0, |_| true).is_none());
+    }
+
+    fn sample_rewrite() -> Rewrite {
+        let ctx = TraceContext {
+            trace_id: [0; 16],
+            span_id: [0; 8],
+        };
+        // Caller's 10 bytes with 4 inserted at 6; the patched frame starts at 2.
+        let insertion = Insertion {
+            from: 2,
+            at: 6,
+            len: 4,
+        };
+        Rewrite::splice(
+            b"0123456789".to_vec(),
+            vec![(insertion, b"abcd".to_vec())],
+            vec![(1, ctx)],
+        )
+    }
+
+    #[test]
+    fn test_splice_inserts_at_offset() {
+        assert_eq!(sample_rewrite().data, b"012345abcd6789");
+    }
+
+    #[test]
+    fn test_caller_bytes_maps_sent_bytes_to_the_callers_buffer() {
+        let rewrite = sample_rewrite();
+        let mut rest = Vec::new();
+        let mut write_rest = |bytes: &[u8]| {
+            rest.extend_from_slice(bytes);
+            bytes.len() as ssize_t
+        };
+        assert_eq!(rewrite.caller_bytes(2, None, &mut write_rest), 2);
+        assert_eq!(rewrite.caller_bytes(10, None, &mut write_rest), 6);
+        assert_eq!(rewrite.caller_bytes(14, None, &mut write_rest), 10);
+        assert!(rest.is_empty());
+    }
+
+    #[test]
+    fn test_caller_bytes_completes_a_write_stopping_in_the_patched_frame() {
+        let rewrite = sample_rewrite();
+        let mut rest = Vec::new();
+        let write_rest = |bytes: &[u8]| {
+            // Two bytes at a time, as a short write would.
+            let n = bytes.len().min(2);
+            rest.extend_from_slice(&bytes[..n]);
+            n as ssize_t
+        };
+        assert_eq!(rewrite.caller_bytes(4, None, write_rest), 6);
+        assert_eq!(rest, b"45abcd");
+
+        let mut rest = Vec::new();
+        let write_rest = |bytes: &[u8]| {
+            rest.extend_from_slice(bytes);
+            bytes.len() as ssize_t
+        };
+        assert_eq!(rewrite.caller_bytes(8, None, write_rest), 6);
+        assert_eq!(rest, b"cd");
+    }
+}
@@ -0,0 +1,685 @@
+//! Redis commands and replies over RESP2/RESP3.
+//!
+//! Connections are recognised by a RESP array sent to the Redis port, 6379
+//! unless `PHANTOM_REDIS_PORT` says otherwise. Clients send each command as
+//! an array of bulk strings (or, rarely, an inline line); the server answers
+//! each command with one reply value, in order. RESP3 push messages (`>`) are
+//! out-of-band and never answer a command. Pipelined commands wait in a FIFO
+//! and are matched to replies in order, each timed from its own send. After
+//! SUBSCRIBE-family commands and MONITOR the server streams unsolicited
+//! replies, so no further commands are queued on that connection.
+
+use std::collections::VecDeque;
+use std::sync::OnceLock;
+use std::time::Instant;
+
+use crate::{FdState, Shard, config_var, connection_peer, max_buf, now_ms};
+
+const REDIS_DEFAULT_PORT: u16 = 6379;
+/// Leading arguments (after the command name) kept per command.
+const REDIS_MAX_ARGS: usize = 8;
+/// Bytes kept per argument; longer keys and values are shortened.
+const REDIS_MAX_ARG_LEN: usize = 64;
+/// Bytes of an error reply's message kept.
+const REDIS_MAX_ERROR_LEN: usize = 512;
+/// Deepest reply nesting accepted before the stream is considered garbage.
+const REDIS_MAX_DEPTH: usize = 64;
+
+/// Commands that take a subcommand, reported as `"<COMMAND> <SUBCOMMAND>"`.
+const REDIS_CONTAINER_COMMANDS: &[&str] = &[
+    "ACL", "CLIENT", "CLUSTER", "COMMAND", "CONFIG", "DEBUG", "FUNCTION", "LATENCY", "MEMORY",
+    "MODULE", "OBJECT", "PUBSUB", "SCRIPT", "SLOWLOG", "XGROUP", "XINFO",
+];
+/// Container commands whose argument after the subcommand is a key.
+const REDIS_KEYED_CONTAINERS: &[&str] = &["OBJECT", "XGROUP", "XINFO", "MEMORY"];
+/// Commands whose first argument is not a key.
+const REDIS_KEYLESS_COMMANDS: &[&str] = &[
+    "AUTH",
+    "BGREWRITEAOF",
+    "BGSAVE",
+    "DBSIZE",
+    "DISCARD",
+    "ECHO",
+    "EVAL",
+    "EVALSHA",
+    "EVAL_RO",
+    "EVALSHA_RO",
+    "EXEC",
+    "FCALL",
+    "FCALL_RO",
+    "FLUSHALL",
+    "FLUSHDB",
+    "HELLO",
+    "INFO",
+    "KEYS",
+    "LASTSAVE",
+    "MONITOR",
+    "MULTI",
+    "PING",
+    "PSUBSCRIBE",
+    "PUBLISH",
+    "PUNSUBSCRIBE",
+    "QUIT",
+    "RANDOMKEY",
+    "READONLY",
+    "READWRITE",
+    "RESET",
+    "ROLE",
+    "SAVE",
+    "SCAN",
+    "SELECT",
+    "SHUTDOWN",
+    "SPUBLISH",
+    "SSUBSCRIBE",
+    "SUBSCRIBE",
+    "SUNSUBSCRIBE",
+    "SWAPDB",
+    "TIME",
+    "UNSUBSCRIBE",
+    "WAIT",
+    "XREAD",
+    "XREADGROUP",
+];
+/// Commands after which the server streams replies nobody asked for.
+const REDIS_STREAMING_COMMANDS: &[&str] = &["MONITOR", "PSUBSCRIBE", "SSUBSCRIBE", "SUBSCRIBE"];
+/// Maximum in-flight commands tracked per connection.
+const REDIS_MAX_PENDING: usize = 1024;
+/// Commands whose arguments may carry credentials and are never reported.
+const REDIS_REDACTED_COMMANDS: &[&str] = &["AUTH", "HELLO", "MIGRATE", "ACL SETUSER", "CONFIG SET"];
+
+static REDIS_PORT: OnceLock<u16> = OnceLock::new();
+
+fn redis_port() -> u16 {
+    *REDIS_PORT.get_or_init(|| {
+        config_var("PHANTOM_REDIS_PORT")
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(REDIS_DEFAULT_PORT)
+    })
+}
+
+/// Splits the CRLF-terminated line starting at `buf[pos..]`, returning it
+/// (without the terminator) and the offset just past it.
+fn resp_line(buf: &[u8], pos: usize) -> Option<(&[u8], usize)> {
+    let rest = buf.get(pos..)?;
+    let end = rest.windows(2).position(|w| w == b"\r\n")?;
+    Some((&rest[..end], pos + end + 2))
+}
+
+fn resp_int(s: &[u8]) -> Option<i64> {
+    std::str::from_utf8(s).ok()?.parse().ok()
+}
+
+/// Lossy UTF-8 text of `bytes`, cut at `max` bytes with a trailing `…`.
+fn redis_text(bytes: &[u8], max: usize) -> String {
+    if bytes.len() <= max {
+        return String::from_utf8_lossy(bytes).into_owned();
+    }
+    let mut s = String::from_utf8_lossy(&bytes[..max]).into_owned();
+    s.push('…');
+    s
+}
+
+/// A command read from the client.
+struct RedisCommand {
+    /// Leading arguments, the command name first, each cut at
+    /// `REDIS_MAX_ARG_LEN`.
+    args: Vec<Vec<u8>>,
+    /// Arguments after the command name.
+    arg_count: u32,
+}
+
+/// Client → server direction: reassembles commands from segments, keeping
+/// only the first bytes of each argument so large values are never buffered.
+#[derive(Default)]
+struct RespCommandReader {
+    buf: Vec<u8>,
+    /// Bulk arguments still expected for the command being read.
+    remaining: usize,
+    args: Vec<Vec<u8>>,
+    count: u32,
+    /// Bytes of the current argument (and its CRLF) still to be discarded.
+    skip: usize,
+}
+
+impl RespCommandReader {
+    /// Appends `data` and returns every command it completes, or `None`
+    /// when the stream is not valid RESP.
+    fn feed(&mut self, mut data: &[u8]) -> Option<Vec<RedisCommand>> {
+        if self.skip > 0 {
+            let n = self.skip.min(data.len());
+            self.skip -= n;
+            data = &data[n..];
+        }
+        if self.buf.len() + data.len() > max_buf() {
+            return None;
+        }
+        self.buf.extend_from_slice(data);
+
+        let mut out = Vec::new();
+        let mut pos = 0;
+        while pos < self.buf.len() {
+            if self.remaining == 0 {
+                let Some((line, next)) = resp_line(&self.buf, pos) else {
+                    break;
+                };
+                pos = next;
+                if let Some(header) = line.strip_prefix(b"*") {
+                    let n = resp_int(header)?;
+                    if n > 0 {
+                        self.remaining = usize::try_from(n).ok()?;
+                        self.count = 0;
+                        self.args.clear();
+                    }
+                } else {
+                    // Inline command: space-separated words on one line.
+                    let words: Vec<&[u8]> = line
+                        .split(|b| b.is_ascii_whitespace())
+                        .filter(|w| !w.is_empty())
+                        .collect();
+                    if let Some((_, rest)) = words.split_first() {
+                        out.push(RedisCommand {
+                            args: words
+                                .iter()
+                                .take(REDIS_MAX_ARGS + 1)
+                                .map(|w| w[..w.len().min(REDIS_MAX_ARG_LEN + 1)].to_vec())
+                                .collect(),
+                            arg_count: rest.len() as u32,
+                        });
+                    }
+                }
+                continue;
+            }
+
+            let Some((line, body)) = resp_line(&self.buf, pos) else {
+                break;
+            };
+            let len = usize::try_from(resp_int(line.strip_prefix(b"$")?)?).ok()?;
+            let end = body + len + 2;
+            // One byte past the limit marks the argument as shortened.
+            let want = len.min(REDIS_MAX_ARG_LEN + 1);
+            let Some(head) = self.buf.get(body..body + want) else {
+                break;
+            };
+            if self.args.len() <= REDIS_MAX_ARGS {
+                self.args.push(head.to_vec());
+            }
+            self.count += 1;
+            if self.buf.len() >= end {
+                pos = end;
+            } else {
+                self.skip = end - self.buf.len();
+                pos = self.buf.len();
+            }
+            self.remaining -= 1;
+            if self.remaining == 0 {
+                out.push(RedisCommand {
+                    args: std::mem::take(&mut self.args),
+                    arg_count: self.count.saturating_sub(1),
+                });
+            }
+        }
+        self.buf.drain(..pos);
+        Some(out)
+    }
+}
+
+/// Type and error message of a reply, read from its first line.
+struct RedisReply {
+    reply_type: &'static str,
+    error: Option<String>,
+    /// RESP3 push: out-of-band, not an answer to any command.
+    push: bool,
+}
+
+/// Server → client direction: walks reply values without buffering them,
+/// so large bulk strings and arrays cost only their header lines.
+#[derive(Default)]
+struct RespReplyReader {
+    buf: Vec<u8>,
+    /// Bytes of the current bulk string (and its CRLF) still to be discarded.
+    skip: usize,
+    /// Elements still expected by each open aggregate, innermost last, and
+    /// whether it is an attribute (which is not itself an element).
+    open: Vec<(usize, bool)>,
+    /// The top-level reply being read.
+    current: Option<RedisReply>,
+}
+
+impl RespReplyReader {
+    /// Marks one value as read, closing every aggregate it completes.
+    /// Returns the top-level reply once it is whole.
+    fn complete_value(&mut self) -> Option<RedisReply> {
+        while let Some((left, attribute)) = self.open.last_mut() {
+            *left -= 1;
+            if *left > 0 {
+                return None;
+            }
+            let attribute = *attribute;
+            self.open.pop();
+            if attribute {
+                return None;
+            }
+        }
+        // An attribute (`|`) finishing at top level leaves `current` empty.
+        self.current.take()
+    }
+
+    /// Appends `data` and returns every reply it completes (pushes
+    /// included), or `None` when the stream is not valid RESP.
+    fn feed(&mut self, mut data: &[u8]) -> Option<Vec<RedisReply>> {
+        if self.skip > 0 {
+            let n = self.skip.min(data.len());
+            self.skip -= n;
+            data = &data[n..];
+        }
+        if self.buf.len() + data.len() > max_buf() {
+            return None;
+        }
+        self.buf.extend_from_slice(data);
+
+        let mut out = Vec::new();
+        let mut pos = 0;
+        while let Some((line, next)) = resp_line(&self.buf, pos) {
+            let start = pos;
+            let (&ty, body) = line.split_first()?;
+            let top = self.open.is_empty() && self.current.is_none();
+            let len = match ty {
+                b'$' | b'!' | b'=' | b'*' | b'~' | b'%' | b'>' | b'|' => resp_int(body)?,
+                b'+' | b'-' | b':' | b'_' | b'#' | b',' | b'(' => 0,
+                _ => return None,
+            };
+            if top && ty != b'|' {
+                let reply_type = match (ty, len) {
+                    (b'$' | b'*', -1) | (b'_', _) => "null",
+                    (b'+', _) => "simple",
+                    (b'-' | b'!', _) => "error",
+                    (b':', _) => "integer",
+                    (b'$', _) => "bulk",
+                    (b'*' | b'>', _) => "array",
+                    (b'%', _) => "map",
+                    (b'~', _) => "set",
+                    (b'#', _) => "boolean",
+                    (b',', _) => "double",
+                    (b'(', _) => "big_number",
+                    _ => "verbatim",
+                };
+                let error = (ty == b'-').then(|| redis_text(body, REDIS_MAX_ERROR_LEN));
+                self.current = Some(RedisReply {
+                    reply_type,
+                    error,
+                    push: ty == b'>',
+                });
+            }
+            pos = next;
+
+            match ty {
+                b'$' | b'!' | b'=' if len >= 0 => {
+                    let len = len as usize;
+                    let end = next + len + 2;
+                    if top && ty == b'!' {
+                        let want = len.min(REDIS_MAX_ERROR_LEN);
+                        let Some(msg) = self.buf.get(next..next + want) else {
+                            // Re-read the header once the message is in.
+                            self.current = None;
+                            pos = start;
+                            break;
+                        };
+                        let msg = redis_text(msg, REDIS_MAX_ERROR_LEN);
+                        if let Some(reply) = &mut self.current {
+                            reply.error = Some(msg);
+                        }
+                    }
+                    if self.buf.len() >= end {
+                        pos = end;
+                    } else {
+                        self.skip = end - self.buf.len();
+                        pos = self.buf.len();
+                    }
+                }
+                b'*' | b'~' | b'%' | b'>' | b'|' if len > 0 => {
+                    if self.open.len() >= REDIS_MAX_DEPTH {
+                        return None;
+                    }
+                    let n = usize::try_from(len).ok()?;
+                    let n = if matches!(ty, b'%' | b'|') { n * 2 } else { n };
+                    self.open.push((n, ty == b'|'));
+                    // Attributes precede the value they describe.
+                    continue;
+                }
+                _ => {}
+            }
+            if ty == b'|' && len <= 0 {
+                continue;
+            }
+            if let Some(reply) = self.complete_value() {
+                out.push(reply);
+            }
+        }
+        self.buf.drain(..pos);
+        Some(out)
+    }
+}
+
+struct RedisPending {
+    command: String,
+    key: Option<String>,
+    args: Vec<String>,
+    arg_count: u32,
+    started_at: Instant,
+    timestamp_ms: u64,
+}
+
+impl RedisPending {
+    fn new(cmd: RedisCommand) -> Option<Self> {
+        let mut args = cmd.args.into_iter();
+        let name = String::from_utf8_lossy(&args.next()?).to_ascii_uppercase();
+        let mut rest: Vec<Vec<u8>> = args.collect();
+        let mut command = name.clone();
+        if REDIS_CONTAINER_COMMANDS.contains(&name.as_str()) && !rest.is_empty() {
+            let sub = rest.remove(0);
+            command = format!(
+                "{name} {}",
+                String::from_utf8_lossy(&sub).to_ascii_uppercase()
+            );
+        }
+        let keyed = if REDIS_CONTAINER_COMMANDS.contains(&name.as_str()) {
+            REDIS_KEYED_CONTAINERS.contains(&name.as_str())
+        } else {
+            !REDIS_KEYLESS_COMMANDS.contains(&name.as_str())
+        };
+        let key =
+            (keyed && !rest.is_empty()).then(|| redis_text(&rest.remove(0), REDIS_MAX_ARG_LEN));
+        let args = if REDIS_REDACTED_COMMANDS.contains(&command.as_str()) {
+            Vec::new()
+        } else {
+            rest.iter()
+                .map(|a| redis_text(a, REDIS_MAX_ARG_LEN))
+                .collect()
+        };
+        Some(Self {
+            command,
+            key,
+            args,
+            arg_count: cmd.arg_count,
+            started_at: Instant::now(),
+            timestamp_ms: now_ms(),
+        })
+    }
+}
+
+#[derive(serde::Serialize)]
+pub(crate) struct RedisTraceMsg {
+    kind: &'static str,
+    command: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    key: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    args: Vec<String>,
+    arg_count: u32,
+    reply_type: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) error: Option<String>,
+    pub(crate) dest_addr: String,
+    duration_ms: u64,
+    timestamp_ms: u64,
+}
+
+/// Per-connection state for a Redis connection.
+pub(crate) struct RedisConnState {
+    send: RespCommandReader,
+    recv: RespReplyReader,
+    dest_addr: String,
+    /// Commands awaiting a reply, oldest first.
+    pending: VecDeque<RedisPending>,
+    /// A SUBSCRIBE-family command or MONITOR was sent; replies no longer
+    /// line up with commands.
+    streaming: bool,
+}
+
+impl RedisConnState {
+    fn new(dest_addr: String) -> Self {
+        Self {
+            send: RespCommandReader::default(),
+            recv: RespReplyReader::default(),
+            dest_addr,
+            pending: VecDeque::new(),
+            streaming: false,
+        }
+    }
+
+    /// Client → server bytes. Returns `false` when tracking should stop.
+    fn on_send(&mut self, data: &[u8]) -> bool {
+        let Some(commands) = self.send.feed(data) else {
+            return false;
+        };
+        for cmd in commands {
+            if self.streaming {
+                break;
+            }
+            let Some(cmd) = RedisPending::new(cmd) else {
+                continue;
+            };
+            if REDIS_STREAMING_COMMANDS.contains(&cmd.command.as_str()) {
+                // Commands already queued are still answered in order.
+                self.streaming = true;
+            } else if self.pending.len() < REDIS_MAX_PENDING {
+                self.pending.push_back(cmd);
+            }
+        }
+        true
+    }
+
+    /// Server → client bytes. Returns the commands they answer, or `None`
+    /// when tracking should stop.
+    fn on_recv(&mut self, data: &[u8]) -> Option<Vec<RedisTraceMsg>> {
+        let mut done = Vec::new();
+        for reply in self.recv.feed(data)? {
+            if reply.push {
+                continue;
+            }
+            let Some(cmd) = self.pending.pop_front() else {
+                continue; // e.g. a message on a subscribed channel
+            };
+            done.push(RedisTraceMsg {
+                kind: "redis",
+                command: cmd.command,
+                key: cmd.key,
+                args: cmd.args,
+                arg_count: cmd.arg_count,
+                reply_type: reply.reply_type,
+                error: reply.error,
+                dest_addr: self.dest_addr.clone(),
+                duration_ms: cmd.started_at.elapsed().as_millis() as u64,
+                timestamp_ms: cmd.timestamp_ms,
+            });
+        }
+        Some(done)
+    }
+}
+
+/// Feed client bytes to the Redis tracker for `key`.
+///
+/// Returns `false` when the connection is not (or no longer) tracked as
+/// Redis, leaving the other protocol trackers to look at it.
+pub(crate) fn track_redis_send(map: &mut Shard, key: usize, data: &[u8], tls: bool) -> bool {
+    if let Some(FdState::Redis(redis)) = map.get_mut(&key) {
+        if !redis.on_send(data) {
+            map.remove(&key);
+        }
+        return true;
+    }
+    if map.contains_key(&key) || !data.starts_with(b"*") {
+        return false;
+    }
+    let Some((dest_addr, port)) = connection_peer(key, tls) else {
+        return false;
+    };
+    if port != redis_port() {
+        return false;
+    }
+    let mut redis = Box::new(RedisConnState::new(dest_addr));
+    if redis.on_send(data) {
+        map.insert(key, FdState::Redis(redis));
+    }
+    true
+}
+
+/// Feed server bytes to the Redis tracker for `key`.
+///
+/// Returns `None` when the connection is not Redis, otherwise the commands
+/// answered by this segment.
+pub(crate) fn track_redis_recv(
+    map: &mut Shard,
+    key: usize,
+    data: &[u8],
+) -> Option<Vec<RedisTraceMsg>> {
+    let Some(FdState::Redis(redis)) = map.get_mut(&key) else {
+        return None;
+    };
+    match redis.on_recv(data) {
+        Some(done) => Some(done),
+        None => {
+            map.remove(&key);
+            Some(Vec::new())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conn() -> RedisConnState {
+        RedisConnState::new("127.0.0.1:6379".to_string())
+    }
+
+    #[test]
+    fn test_pipelined_commands_match_replies_in_order() {
+        let mut redis = conn();
+        assert!(
+            redis.on_send(
+                b"*2\r\n$3\r\nGET\r\n$1\r\nk\r\n*3\r\n$3\r\nset\r\n$1\r\na\r\n$1\r\nb\r\n"
+            )
+        );
+        let done = redis.on_recv(b"$1\r\nv\r\n+OK\r\n").unwrap();
+        assert_eq!(done.len(), 2);
+        assert_eq!(done[0].command, "GET");
+        assert_eq!(done[0].key.as_deref(), Some("k"));
+        assert_eq!(done[0].reply_type, "bulk");
+        assert_eq!(done[1].command, "SET");
+        assert_eq!(done[1].key.as_deref(), Some("a"));
+        assert_eq!(done[1].args, ["b"]);
+        assert_eq!(done[1].arg_count, 2);
+        assert_eq!(done[1].reply_type, "simple");
+        assert!(redis.pending.is_empty());
+    }
+
+    #[test]
+    fn test_commands_and_replies_split_across_segments() {
+        let mut redis = conn();
+        assert!(redis.on_send(b"*2\r\n$4\r\nINCR"));
+        assert!(redis.pending.is_empty());
+        assert!(redis.on_send(b"\r\n$3\r\nctr\r\n"));
+        assert_eq!(redis.pending.len(), 1);
+        assert!(redis.on_recv(b":4").unwrap().is_empty());
+        let done = redis.on_recv(b"2\r\n").unwrap();
+        assert_eq!(done[0].command, "INCR");
+        assert_eq!(done[0].reply_type, "integer");
+    }
+
+    #[test]
+    fn test_long_arguments_are_shortened_and_skipped() {
+        let mut redis = conn();
+        let value = vec![b'x'; 1000];
+        let mut cmd = b"*3\r\n$3\r\nSET\r\n$1\r\nk\r\n$1000\r\n".to_vec();
+        cmd.extend_from_slice(&value[..100]);
+        assert!(redis.on_send(&cmd));
+        let mut rest = value[100..].to_vec();
+        rest.extend_from_slice(b"\r\n*1\r\n$4\r\nPING\r\n");
+        assert!(redis.on_send(&rest));
+        let done = redis.on_recv(b"+OK\r\n+PONG\r\n").unwrap();
+        assert_eq!(
+            done[0].args,
+            [format!("{}…", "x".repeat(REDIS_MAX_ARG_LEN))]
+        );
+        assert_eq!(done[1].command, "PING");
+        assert_eq!(done[1].key, None);
+    }
+
+    #[test]
+    fn test_large_bulk_reply_is_skipped_without_buffering() {
+        let mut redis = conn();
+        assert!(redis.on_send(b"*2\r\n$3\r\nGET\r\n$1\r\nk\r\n*1\r\n$4\r\nPING\r\n"));
+        // The reply is complete at its header; its body is only skipped.
+        let done = redis.on_recv(b"$10000\r\n").unwrap();
+        assert_eq!(done[0].command, "GET");
+        assert!(redis.on_recv(&[b'y'; 6000]).unwrap().is_empty());
+        assert!(redis.recv.buf.is_empty());
+        let mut rest = vec![b'y'; 4000];
+        rest.extend_from_slice(b"\r\n+PONG\r\n");
+        let done = redis.on_recv(&rest).unwrap();
+        assert_eq!(done[0].command, "PING");
+    }
+
+    #[test]
+    fn test_error_replies_and_nested_aggregates() {
+        let mut redis = conn();
+        let send = b"*1\r\n$4\r\nEXEC\r\n*2\r\n$4\r\nLPOP\r\n$1\r\nq\r\n*1\r\n$4\r\nPING\r\n";
+        assert!(redis.on_send(send));
+        let done = redis
+            .on_recv(b"*2\r\n*1\r\n:1\r\n$-1\r\n-WRONGTYPE bad key\r\n!3\r\nERR\r\n")
+            .unwrap();
+        assert_eq!(done[0].reply_type, "array");
+        assert_eq!(done[1].reply_type, "error");
+        assert_eq!(done[1].error.as_deref(), Some("WRONGTYPE bad key"));
+        assert_eq!(done[2].reply_type, "error");
+        assert_eq!(done[2].error.as_deref(), Some("ERR"));
+    }
+
+    #[test]
+    fn test_resp3_pushes_and_attributes_answer_no_command() {
+        let mut redis = conn();
+        assert!(redis.on_send(b"*2\r\n$3\r\nGET\r\n$1\r\nk\r\n"));
+        let push = b">3\r\n$10\r\ninvalidate\r\n*1\r\n$1\r\nk\r\n$-1\r\n";
+        assert!(redis.on_recv(push).unwrap().is_empty());
+        let done = redis.on_recv(b"|1\r\n+ttl\r\n:3\r\n$1\r\nv\r\n").unwrap();
+        assert_eq!(done.len(), 1);
+        assert_eq!(done[0].reply_type, "bulk");
+    }
+
+    #[test]
+    fn test_subcommands_and_redacted_arguments() {
+        let mut redis = conn();
+        let send = b"*3\r\n$6\r\nCONFIG\r\n$3\r\nget\r\n$7\r\nmaxmem*\r\n*2\r\n$4\r\nAUTH\r\n$6\r\nsecret\r\nPING\r\n";
+        assert!(redis.on_send(send));
+        let done = redis.on_recv(b"*0\r\n+OK\r\n+PONG\r\n").unwrap();
+        assert_eq!(done[0].command, "CONFIG GET");
+        assert_eq!(done[0].key, None);
+        assert_eq!(done[0].args, ["maxmem*"]);
+        assert_eq!(done[1].command, "AUTH");
+        assert!(done[1].args.is_empty());
+        assert_eq!(done[1].arg_count, 1);
+        // Inline command.
+        assert_eq!(done[2].command, "PING");
+    }
+
+    #[test]
+    fn test_subscribe_stops_queueing_commands() {
+        let mut redis = conn();
+        assert!(redis.on_send(b"*2\r\n$9\r\nSUBSCRIBE\r\n$2\r\nch\r\n"));
+        assert!(redis.streaming);
+        assert!(redis.on_send(b"*1\r\n$4\r\nPING\r\n"));
+        assert!(redis.pending.is_empty());
+        let message = b"*3\r\n$7\r\nmessage\r\n$2\r\nch\r\n$2\r\nhi\r\n";
+        assert!(redis.on_recv(message).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_invalid_resp_stops_tracking() {
+        let mut redis = conn();
+        assert!(!redis.on_send(b"*x\r\n"));
+        let mut redis = conn();
+        assert!(redis.on_send(b"*1\r\n$4\r\nPING\r\n"));
+        assert!(redis.on_recv(b"?what\r\n").is_none());
+    }
+}
@@ -0,0 +1,267 @@
+//! Server-Sent Events: `text/event-stream` responses.
+//!
+//! An event stream never ends on its own, so waiting for the connection to
+//! close would hold every event back and then truncate the body. Instead the
+//! response is emitted as soon as its headers arrive (duration is the time to
+//! headers), and each event that follows is emitted on its own, tagged with
+//! the response's URL and connection.
+
+use std::collections::HashMap;
+use std::time::Instant;
+
+use crate::chunked::ChunkedDecoder;
+use crate::{FdState, Shard, connection_label, max_body, now_ms};
+
+/// True for a response whose body is an event stream.
+pub(crate) fn is_event_stream(headers: Option<&HashMap<String, String>>) -> bool {
+    headers
+        .and_then(|h| h.get("content-type"))
+        .is_some_and(|ct| {
+            ct.split(';')
+                .next()
+                .is_some_and(|mime| mime.trim().eq_ignore_ascii_case("text/event-stream"))
+        })
+}
+
+#[derive(serde::Serialize)]
+pub(crate) struct SseEventMsg {
+    kind: &'static str,
+    pub(crate) url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    event: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    data: String,
+    data_bytes: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    retry_ms: Option<u64>,
+    connection_id: String,
+    /// Time since the request was sent.
+    elapsed_ms: u64,
+    timestamp_ms: u64,
+}
+
+/// Per-connection state for an event-stream response.
+pub(crate) struct SseState {
+    url: String,
+    conn_id: u64,
+    started_at: Instant,
+    /// Present when the stream is sent with chunked transfer coding.
+    chunked: Option<ChunkedDecoder>,
+    /// Undispatched text: a partial line.
+    line: Vec<u8>,
+    /// The previous segment ended in CR; a leading LF belongs to it.
+    after_cr: bool,
+    event: Option<String>,
+    id: Option<String>,
+    data: Vec<u8>,
+    /// Full size of `data`, which is cut at `max_body()`.
+    data_bytes: u64,
+    data_lines: u32,
+    retry_ms: Option<u64>,
+    /// At least one field has been seen since the last dispatch.
+    pending: bool,
+}
+
+impl SseState {
+    pub(crate) fn new(url: String, conn_id: u64, started_at: Instant, chunked: bool) -> Self {
+        Self {
+            url,
+            conn_id,
+            started_at,
+            chunked: chunked.then(ChunkedDecoder::default),
+            line: Vec::new(),
+            after_cr: false,
+            event: None,
+            id: None,
+            data: Vec::new(),
+            data_bytes: 0,
+            data_lines: 0,
+            retry_ms: None,
+            pending: false,
+        }
+    }
+
+    /// Applies one `field: value` line, or dispatches on an empty line.
+    fn on_line(&mut self, out: &mut Vec<SseEventMsg>) {
+        let line = std::mem::take(&mut self.line);
+        if line.is_empty() {
+            if self.pending {
+                out.push(self.dispatch());
+            }
+            return;
+        }
+        if line[0] == b':' {
+            return; // comment / keep-alive
+        }
+        let (field, value) = match line.iter().position(|&b| b == b':') {
+            Some(colon) => {
+                let value = &line[colon + 1..];
+                (&line[..colon], value.strip_prefix(b" ").unwrap_or(value))
+            }
+            None => (&line[..], &[][..]),
+        };
+        let text = || String::from_utf8_lossy(value).into_owned();
+        match field {
+            b"event" => self.event = Some(text()),
+            b"id" => self.id = Some(text()),
+            b"retry" => self.retry_ms = text().parse().ok(),
+            b"data" => {
+                // Multiple data lines join with LF.
+                let joined = self.data_lines > 0;
+                if joined && self.data.len() < max_body() {
+                    self.data.push(b'\n');
+                }
+                let room = max_body().saturating_sub(self.data.len());
+                self.data.extend_from_slice(&value[..value.len().min(room)]);
+                self.data_bytes += value.len() as u64 + u64::from(joined);
+                self.data_lines += 1;
+            }
+            _ => return,
+        }
+        self.pending = true;
+    }
+
+    fn dispatch(&mut self) -> SseEventMsg {
+        self.pending = false;
+        self.data_lines = 0;
+        SseEventMsg {
+            kind: "sse",
+            url: self.url.clone(),
+            event: self.event.take(),
+            // The last event ID persists across events until reset.
+            id: self.id.clone(),
+            data: String::from_utf8_lossy(&std::mem::take(&mut self.data)).into_owned(),
+            data_bytes: std::mem::take(&mut self.data_bytes),
+            retry_ms: self.retry_ms.take(),
+            connection_id: connection_label(self.conn_id),
+            elapsed_ms: self.started_at.elapsed().as_millis() as u64,
+            timestamp_ms: now_ms(),
+        }
+    }
+
+    /// Feeds body bytes. Returns the events completed, and whether the
+    /// stream has ended (last chunk seen), or `None` for malformed framing.
+    fn feed(&mut self, data: &[u8]) -> Option<(Vec<SseEventMsg>, bool)> {
+        let mut decoded = Vec::new();
+        let (body, ended) = match &mut self.chunked {
+            Some(dec) => {
+                let ended = dec.feed(data, &mut decoded, true)?;
+                (decoded.as_slice(), ended)
+            }
+            None => (data, false),
+        };
+        let mut out = Vec::new();
+        let mut rest = body;
+        if std::mem::take(&mut self.after_cr) && rest.first() == Some(&b'\n') {
+            rest = &rest[1..];
+        }
+        while let Some(end) = rest.iter().position(|&b| b == b'\n' || b == b'\r') {
+            if self.line.len() + end <= max_body() {
+                self.line.extend_from_slice(&rest[..end]);
+            }
+            let cr = rest[end] == b'\r';
+            rest = &rest[end + 1..];
+            if cr {
+                match rest.first() {
+                    Some(b'\n') => rest = &rest[1..],
+                    None => self.after_cr = true,
+                    Some(_) => {}
+                }
+            }
+            self.on_line(&mut out);
+        }
+        if self.line.len() + rest.len() <= max_body() {
+            self.line.extend_from_slice(rest);
+        }
+        Some((out, ended))
+    }
+}
+
+/// Feed response bytes to the event-stream tracker for `key`.
+///
+/// Returns `None` when the connection is not an event stream, otherwise the
+/// events completed by this segment. The state is dropped once the stream
+/// ends so the connection can carry the next request.
+pub(crate) fn track_sse(map: &mut Shard, key: usize, data: &[u8]) -> Option<Vec<SseEventMsg>> {
+    let Some(FdState::Sse(sse)) = map.get_mut(&key) else {
+        return None;
+    };
+    match sse.feed(data) {
+        Some((events, false)) => Some(events),
+        Some((events, true)) => {
+            map.remove(&key);
+            Some(events)
+        }
+        None => {
+            map.remove(&key);
+            Some(Vec::new())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(chunked: bool) -> SseState {
+        SseState::new("http://a/events".to_string(), 1, Instant::now(), chunked)
+    }
+
+    #[test]
+    fn test_fields_and_multiline_data() {
+        let mut sse = state(false);
+        let (events, ended) = sse
+            .feed(b": keep-alive\nevent: update\nid: 7\nretry: 500\ndata: a\ndata:b\n\n")
+            .unwrap();
+        assert!(!ended);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event.as_deref(), Some("update"));
+        assert_eq!(events[0].id.as_deref(), Some("7"));
+        assert_eq!(events[0].retry_ms, Some(500));
+        assert_eq!(events[0].data, "a\nb");
+        assert_eq!(events[0].data_bytes, 3);
+        assert_eq!(events[0].url, "http://a/events");
+    }
+
+    #[test]
+    fn test_id_persists_and_comments_do_not_dispatch() {
+        let mut sse = state(false);
+        let (events, _) = sse.feed(b"id: 1\ndata: x\n\n:ping\n\ndata: y\n\n").unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[1].id.as_deref(), Some("1"));
+        assert_eq!(events[1].event, None);
+        assert_eq!(events[1].data, "y");
+    }
+
+    #[test]
+    fn test_crlf_split_across_segments() {
+        let mut sse = state(false);
+        assert!(sse.feed(b"data: one\r").unwrap().0.is_empty());
+        assert_eq!(sse.feed(b"\n\r").unwrap().0.len(), 1);
+        let (events, _) = sse.feed(b"\ndata: two\r\r").unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].data, "two");
+    }
+
+    #[test]
+    fn test_chunked_stream_ends_on_last_chunk() {
+        let mut sse = state(true);
+        let (events, ended) = sse.feed(b"9\r\ndata: x\n\n\r\n").unwrap();
+        assert_eq!((events.len(), ended), (1, false));
+        let (events, ended) = sse.feed(b"0\r\n\r\n").unwrap();
+        assert!(events.is_empty() && ended);
+        assert!(state(true).feed(b"nothex\r\n").is_none());
+    }
+
+    #[test]
+    fn test_event_stream_content_type() {
+        let headers = |ct: &str| HashMap::from([("content-type".to_string(), ct.to_string())]);
+        assert!(is_event_stream(Some(&headers("text/event-stream"))));
+        assert!(is_event_stream(Some(&headers(
+            "Text/Event-Stream; charset=utf-8"
+        ))));
+        assert!(!is_event_stream(Some(&headers("text/plain"))));
+        assert!(!is_event_stream(None));
+    }
+}
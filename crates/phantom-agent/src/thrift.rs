@@ -0,0 +1,318 @@
+//! Apache Thrift calls over the binary or compact protocol, framed or buffered.
+//!
+//! Thrift has no well-known port, so connections are recognised by their
+//! message header: strict TBinaryProtocol starts with version bytes 0x80 0x01,
+//! TCompactProtocol with protocol id 0x82. TFramedTransport prefixes each
+//! message with a 4-byte big-endian length. Calls are matched to replies by
+//! sequence id, in whichever direction the call travelled (client or server).
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::{FdState, Shard, now_ms};
+
+const THRIFT_CALL: u8 = 1;
+const THRIFT_REPLY: u8 = 2;
+const THRIFT_EXCEPTION: u8 = 3;
+const THRIFT_ONEWAY: u8 = 4;
+const THRIFT_BINARY_VERSION: [u8; 2] = [0x80, 0x01];
+const THRIFT_COMPACT_ID: u8 = 0x82;
+const THRIFT_COMPACT_VERSION: u8 = 1;
+/// Longest method name accepted — longer names are treated as a misdetection.
+const THRIFT_MAX_NAME: usize = 256;
+/// Largest frame length accepted when probing for framed transport.
+const THRIFT_MAX_FRAME: usize = 16 * 1024 * 1024;
+/// Maximum outstanding calls tracked per connection.
+const THRIFT_MAX_PENDING: usize = 1024;
+
+struct ThriftHeader {
+    method: String,
+    msg_type: u8,
+    seq_id: i32,
+    compact: bool,
+    framed: bool,
+}
+
+/// Read an unsigned LEB128 varint (compact protocol). Returns `(value, bytes_read)`.
+fn read_varint(buf: &[u8]) -> Option<(u64, usize)> {
+    let mut value = 0u64;
+    for (i, &b) in buf.iter().enumerate().take(10) {
+        value |= ((b & 0x7f) as u64) << (7 * i);
+        if b & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+    None
+}
+
+/// Parse a Thrift message header at the start of `buf`.
+/// Returns `(method, msg_type, seq_id, compact)`.
+fn parse_thrift_message(buf: &[u8]) -> Option<(String, u8, i32, bool)> {
+    let (name, msg_type, seq_id, compact) = if buf.starts_with(&THRIFT_BINARY_VERSION) {
+        // 0x80 0x01 0x00 <type> | i32 name_len | name | i32 seq_id
+        let msg_type = *buf.get(3)? & 0x07;
+        let name_len = i32::from_be_bytes(buf.get(4..8)?.try_into().ok()?);
+        let name_len = usize::try_from(name_len).ok()?;
+        if name_len > THRIFT_MAX_NAME {
+            return None;
+        }
+        let name = buf.get(8..8 + name_len)?;
+        let seq = buf.get(8 + name_len..12 + name_len)?;
+        let seq_id = i32::from_be_bytes(seq.try_into().ok()?);
+        (name, msg_type, seq_id, false)
+    } else if buf.first() == Some(&THRIFT_COMPACT_ID) {
+        // 0x82 | <type:3><version:5> | varint seq_id | varint name_len | name
+        let type_and_version = *buf.get(1)?;
+        if type_and_version & 0x1f != THRIFT_COMPACT_VERSION {
+            return None;
+        }
+        let msg_type = type_and_version >> 5;
+        let (seq_id, n1) = read_varint(buf.get(2..)?)?;
+        let (name_len, n2) = read_varint(buf.get(2 + n1..)?)?;
+        let name_len = usize::try_from(name_len).ok()?;
+        if name_len > THRIFT_MAX_NAME {
+            return None;
+        }
+        let start = 2 + n1 + n2;
+        let name = buf.get(start..start + name_len)?;
+        (name, msg_type, seq_id as i32, true)
+    } else {
+        return None;
+    };
+
+    if !(THRIFT_CALL..=THRIFT_ONEWAY).contains(&msg_type)
+        || name.is_empty()
+        || !name.iter().all(|b| b.is_ascii_graphic())
+    {
+        return None;
+    }
+    let name = String::from_utf8_lossy(name).into_owned();
+    Some((name, msg_type, seq_id, compact))
+}
+
+/// Recognise a Thrift message at the start of a send/recv buffer, with or
+/// without a framed-transport length prefix.
+fn parse_thrift_header(data: &[u8]) -> Option<ThriftHeader> {
+    let (parsed, framed) = match parse_thrift_message(data) {
+        Some(parsed) => (parsed, false),
+        None => {
+            let frame_len = u32::from_be_bytes(data.get(..4)?.try_into().ok()?) as usize;
+            if frame_len == 0 || frame_len > THRIFT_MAX_FRAME {
+                return None;
+            }
+            (parse_thrift_message(&data[4..])?, true)
+        }
+    };
+    let (method, msg_type, seq_id, compact) = parsed;
+    Some(ThriftHeader {
+        method,
+        msg_type,
+        seq_id,
+        compact,
+        framed,
+    })
+}
+
+struct ThriftCall {
+    method: String,
+    started_at: Instant,
+    timestamp_ms: u64,
+}
+
+/// Per-connection state for a Thrift connection.
+pub(crate) struct ThriftConnState {
+    /// Calls awaiting a reply, keyed by sequence id.
+    pending: HashMap<i32, ThriftCall>,
+}
+
+#[derive(serde::Serialize)]
+pub(crate) struct ThriftTraceMsg {
+    kind: &'static str,
+    method: String,
+    seq_id: i32,
+    pub(crate) message_type: &'static str,
+    protocol: &'static str,
+    framed: bool,
+    duration_ms: u64,
+    timestamp_ms: u64,
+}
+
+fn thrift_msg(
+    hdr: &ThriftHeader,
+    method: String,
+    message_type: &'static str,
+    duration: Duration,
+    timestamp_ms: u64,
+) -> ThriftTraceMsg {
+    ThriftTraceMsg {
+        kind: "thrift",
+        method,
+        seq_id: hdr.seq_id,
+        message_type,
+        protocol: if hdr.compact { "compact" } else { "binary" },
+        framed: hdr.framed,
+        duration_ms: duration.as_millis() as u64,
+        timestamp_ms,
+    }
+}
+
+/// Record a Thrift message; returns the finished exchange once a reply (or a
+/// oneway call) completes it.
+fn observe_thrift(conn: &mut ThriftConnState, hdr: ThriftHeader) -> Option<ThriftTraceMsg> {
+    match hdr.msg_type {
+        THRIFT_CALL => {
+            if conn.pending.len() < THRIFT_MAX_PENDING {
+                let call = ThriftCall {
+                    method: hdr.method,
+                    started_at: Instant::now(),
+                    timestamp_ms: now_ms(),
+                };
+                conn.pending.insert(hdr.seq_id, call);
+            }
+            None
+        }
+        THRIFT_ONEWAY => {
+            let method = hdr.method.clone();
+            Some(thrift_msg(&hdr, method, "oneway", Duration::ZERO, now_ms()))
+        }
+        THRIFT_REPLY | THRIFT_EXCEPTION => {
+            let call = conn.pending.remove(&hdr.seq_id)?;
+            let message_type = if hdr.msg_type == THRIFT_EXCEPTION {
+                "exception"
+            } else {
+                "reply"
+            };
+            Some(thrift_msg(
+                &hdr,
+                call.method,
+                message_type,
+                call.started_at.elapsed(),
+                call.timestamp_ms,
+            ))
+        }
+        _ => None,
+    }
+}
+
+/// Feed `data` to the Thrift tracker for `key`.
+///
+/// Returns `None` when the connection is not Thrift (leaving HTTP tracking
+/// untouched), otherwise `Some` of the exchange completed by this message.
+pub(crate) fn track_thrift(
+    map: &mut Shard,
+    key: usize,
+    data: &[u8],
+) -> Option<Option<ThriftTraceMsg>> {
+    let hdr = parse_thrift_header(data);
+    match map.get_mut(&key) {
+        // Continuation segments of a large message carry no header.
+        Some(FdState::Thrift(conn)) => Some(hdr.and_then(|h| observe_thrift(conn, h))),
+        Some(_) => None,
+        None => {
+            let hdr = hdr?;
+            let mut conn = Box::new(ThriftConnState {
+                pending: HashMap::new(),
+            });
+            let done = observe_thrift(&mut conn, hdr);
+            map.insert(key, FdState::Thrift(conn));
+            Some(done)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn binary(msg_type: u8, method: &str, seq_id: i32) -> Vec<u8> {
+        let mut out = vec![0x80, 0x01, 0x00, msg_type];
+        out.extend_from_slice(&(method.len() as i32).to_be_bytes());
+        out.extend_from_slice(method.as_bytes());
+        out.extend_from_slice(&seq_id.to_be_bytes());
+        out.push(0); // STOP: empty struct
+        out
+    }
+
+    fn compact(msg_type: u8, method: &str, seq_id: u8) -> Vec<u8> {
+        let mut out = vec![
+            THRIFT_COMPACT_ID,
+            (msg_type << 5) | THRIFT_COMPACT_VERSION,
+            seq_id,
+        ];
+        out.push(method.len() as u8);
+        out.extend_from_slice(method.as_bytes());
+        out.push(0);
+        out
+    }
+
+    fn framed(msg: Vec<u8>) -> Vec<u8> {
+        let mut out = (msg.len() as u32).to_be_bytes().to_vec();
+        out.extend(msg);
+        out
+    }
+
+    #[test]
+    fn test_parse_binary_and_compact_headers() {
+        let hdr = parse_thrift_header(&binary(THRIFT_CALL, "getUser", 7)).unwrap();
+        assert_eq!(hdr.method, "getUser");
+        assert_eq!(hdr.msg_type, THRIFT_CALL);
+        assert_eq!(hdr.seq_id, 7);
+        assert!(!hdr.compact && !hdr.framed);
+
+        let hdr = parse_thrift_header(&framed(compact(THRIFT_REPLY, "ping", 3))).unwrap();
+        assert_eq!(hdr.method, "ping");
+        assert_eq!(hdr.msg_type, THRIFT_REPLY);
+        assert_eq!(hdr.seq_id, 3);
+        assert!(hdr.compact && hdr.framed);
+    }
+
+    #[test]
+    fn test_rejects_other_protocols() {
+        assert!(parse_thrift_header(b"GET / HTTP/1.1\r\n").is_none());
+        assert!(parse_thrift_header(&binary(7, "x", 1)).is_none());
+        assert!(parse_thrift_header(&binary(THRIFT_CALL, "bad name", 1)).is_none());
+        assert!(parse_thrift_header(&[0, 0, 0, 0, 0x80, 0x01]).is_none());
+    }
+
+    #[test]
+    fn test_read_varint() {
+        assert_eq!(read_varint(&[0x01]), Some((1, 1)));
+        assert_eq!(read_varint(&[0xac, 0x02]), Some((300, 2)));
+        assert_eq!(read_varint(&[0x80]), None);
+    }
+
+    #[test]
+    fn test_calls_match_replies_by_sequence_id() {
+        let mut conn = ThriftConnState {
+            pending: HashMap::new(),
+        };
+        for (method, seq) in [("a", 1), ("b", 2)] {
+            let hdr = parse_thrift_header(&binary(THRIFT_CALL, method, seq)).unwrap();
+            assert!(observe_thrift(&mut conn, hdr).is_none());
+        }
+        let hdr = parse_thrift_header(&binary(THRIFT_EXCEPTION, "b", 2)).unwrap();
+        let done = observe_thrift(&mut conn, hdr).unwrap();
+        assert_eq!(done.method, "b");
+        assert_eq!(done.message_type, "exception");
+        let hdr = parse_thrift_header(&binary(THRIFT_REPLY, "a", 1)).unwrap();
+        assert_eq!(
+            observe_thrift(&mut conn, hdr).unwrap().message_type,
+            "reply"
+        );
+        // A reply nobody waits for is ignored.
+        let hdr = parse_thrift_header(&binary(THRIFT_REPLY, "a", 1)).unwrap();
+        assert!(observe_thrift(&mut conn, hdr).is_none());
+    }
+
+    #[test]
+    fn test_oneway_calls_complete_at_once() {
+        let mut conn = ThriftConnState {
+            pending: HashMap::new(),
+        };
+        let hdr = parse_thrift_header(&compact(THRIFT_ONEWAY, "log", 9)).unwrap();
+        let done = observe_thrift(&mut conn, hdr).unwrap();
+        assert_eq!(done.message_type, "oneway");
+        assert_eq!(done.protocol, "compact");
+        assert!(conn.pending.is_empty());
+    }
+}
@@ -0,0 +1,319 @@
+//! WebSocket (RFC 6455) frames after an HTTP/1.1 `101 Switching Protocols`.
+//!
+//! When a response upgrades the connection to `websocket`, the HTTP/1.x state
+//! is replaced by a frame reader for each direction. Every frame is reported as
+//! soon as its header and the start of its payload are in, so long-lived
+//! sockets stream events instead of waiting for close. Payloads are previewed
+//! (unmasked) and otherwise skipped; with `permessage-deflate` the preview of
+//! a compressed frame is the deflated bytes.
+
+use std::collections::HashMap;
+
+use crate::{FdState, Shard, WireBytes, connection_label, max_buf, now_ms};
+
+/// Payload bytes kept per frame.
+const WS_PREVIEW: usize = 256;
+
+/// One direction of a WebSocket connection.
+#[derive(Default)]
+struct WsStream {
+    buf: Vec<u8>,
+    /// Payload bytes of the current frame still to be discarded.
+    skip: u64,
+}
+
+struct WsFrame {
+    fin: bool,
+    /// RSV1: payload compressed by `permessage-deflate`.
+    compressed: bool,
+    opcode: u8,
+    masked: bool,
+    payload_len: u64,
+    /// First `WS_PREVIEW` payload bytes, unmasked.
+    preview: Vec<u8>,
+}
+
+impl WsStream {
+    /// Appends `data` and returns every frame whose header and preview it
+    /// completes, or `None` when the stream is not valid framing.
+    fn feed(&mut self, mut data: &[u8]) -> Option<Vec<WsFrame>> {
+        if self.skip > 0 {
+            let n = usize::try_from(self.skip)
+                .unwrap_or(usize::MAX)
+                .min(data.len());
+            self.skip -= n as u64;
+            data = &data[n..];
+        }
+        if self.buf.len() + data.len() > max_buf() {
+            return None;
+        }
+        self.buf.extend_from_slice(data);
+
+        let mut out = Vec::new();
+        let mut pos = 0;
+        while let Some(&[b0, b1]) = self.buf.get(pos..pos + 2) {
+            let masked = b1 & 0x80 != 0;
+            let (payload_len, mut header) = match b1 & 0x7f {
+                126 => match self.buf.get(pos + 2..pos + 4) {
+                    Some(b) => (u64::from(u16::from_be_bytes([b[0], b[1]])), 4),
+                    None => break,
+                },
+                127 => match self.buf.get(pos + 2..pos + 10) {
+                    Some(b) => (u64::from_be_bytes(b.try_into().ok()?), 10),
+                    None => break,
+                },
+                n => (u64::from(n), 2),
+            };
+            let mask = if masked {
+                let Some(key) = self.buf.get(pos + header..pos + header + 4) else {
+                    break;
+                };
+                header += 4;
+                Some([key[0], key[1], key[2], key[3]])
+            } else {
+                None
+            };
+            let body = pos + header;
+            let want = usize::try_from(payload_len)
+                .unwrap_or(usize::MAX)
+                .min(WS_PREVIEW);
+            let Some(head) = self.buf.get(body..body + want) else {
+                break;
+            };
+            let mut preview = head.to_vec();
+            if let Some(mask) = mask {
+                for (i, b) in preview.iter_mut().enumerate() {
+                    *b ^= mask[i % 4];
+                }
+            }
+            out.push(WsFrame {
+                fin: b0 & 0x80 != 0,
+                compressed: b0 & 0x40 != 0,
+                opcode: b0 & 0x0f,
+                masked,
+                payload_len,
+                preview,
+            });
+            let available = (self.buf.len() - body) as u64;
+            if available >= payload_len {
+                pos = body + payload_len as usize;
+            } else {
+                self.skip = payload_len - available;
+                pos = self.buf.len();
+            }
+        }
+        self.buf.drain(..pos);
+        Some(out)
+    }
+}
+
+fn ws_opcode_name(opcode: u8) -> &'static str {
+    match opcode {
+        0x0 => "continuation",
+        0x1 => "text",
+        0x2 => "binary",
+        0x8 => "close",
+        0x9 => "ping",
+        0xa => "pong",
+        _ => "reserved",
+    }
+}
+
+#[derive(serde::Serialize)]
+pub(crate) struct WsFrameMsg {
+    kind: &'static str,
+    pub(crate) url: String,
+    direction: &'static str,
+    opcode: &'static str,
+    fin: bool,
+    masked: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    compressed: bool,
+    payload_len: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    preview_b64: Option<WireBytes>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    close_code: Option<u16>,
+    connection_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dest_addr: Option<String>,
+    timestamp_ms: u64,
+}
+
+/// Per-connection state for an upgraded WebSocket connection.
+pub(crate) struct WsConnState {
+    send: WsStream,
+    recv: WsStream,
+    /// URL of the upgrade request.
+    url: String,
+    conn_id: u64,
+    dest_addr: Option<String>,
+}
+
+impl WsConnState {
+    pub(crate) fn new(url: String, conn_id: u64, dest_addr: Option<String>) -> Self {
+        Self {
+            send: WsStream::default(),
+            recv: WsStream::default(),
+            url,
+            conn_id,
+            dest_addr,
+        }
+    }
+
+    fn frame_msgs(&self, frames: Vec<WsFrame>, direction: &'static str) -> Vec<WsFrameMsg> {
+        let timestamp_ms = now_ms();
+        frames
+            .into_iter()
+            .map(|frame| {
+                // A close payload starts with a status code; the rest is the reason.
+                let (close_code, preview) = match (frame.opcode, frame.preview.as_slice()) {
+                    (0x8, [hi, lo, reason @ ..]) => (Some(u16::from_be_bytes([*hi, *lo])), reason),
+                    (_, preview) => (None, preview),
+                };
+                WsFrameMsg {
+                    kind: "websocket",
+                    url: self.url.clone(),
+                    direction,
+                    opcode: ws_opcode_name(frame.opcode),
+                    fin: frame.fin,
+                    masked: frame.masked,
+                    compressed: frame.compressed,
+                    payload_len: frame.payload_len,
+                    preview_b64: (!preview.is_empty()).then(|| WireBytes(preview.to_vec())),
+                    close_code,
+                    connection_id: connection_label(self.conn_id),
+                    dest_addr: self.dest_addr.clone(),
+                    timestamp_ms,
+                }
+            })
+            .collect()
+    }
+}
+
+/// True for a `101 Switching Protocols` response upgrading to WebSocket.
+pub(crate) fn is_websocket_upgrade(
+    status_code: Option<u16>,
+    headers: Option<&HashMap<String, String>>,
+) -> bool {
+    status_code == Some(101)
+        && headers
+            .and_then(|h| h.get("upgrade"))
+            .is_some_and(|v| v.trim().eq_ignore_ascii_case("websocket"))
+}
+
+/// Feed bytes in one direction to the WebSocket tracker for `key`.
+///
+/// Returns `None` when the connection is not a WebSocket, otherwise the
+/// frames started in this segment.
+pub(crate) fn track_websocket(
+    map: &mut Shard,
+    key: usize,
+    data: &[u8],
+    outgoing: bool,
+) -> Option<Vec<WsFrameMsg>> {
+    let Some(FdState::WebSocket(ws)) = map.get_mut(&key) else {
+        return None;
+    };
+    let (stream, direction) = if outgoing {
+        (&mut ws.send, "send")
+    } else {
+        (&mut ws.recv, "recv")
+    };
+    match stream.feed(data) {
+        Some(frames) => Some(ws.frame_msgs(frames, direction)),
+        None => {
+            map.remove(&key);
+            Some(Vec::new())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A client frame masked with a fixed key.
+    fn masked_frame(b0: u8, payload: &[u8]) -> Vec<u8> {
+        let key = [0x12, 0x34, 0x56, 0x78];
+        let mut out = vec![b0];
+        match payload.len() {
+            n @ 0..=125 => out.push(0x80 | n as u8),
+            n => {
+                out.push(0x80 | 126);
+                out.extend_from_slice(&(n as u16).to_be_bytes());
+            }
+        }
+        out.extend_from_slice(&key);
+        out.extend(payload.iter().enumerate().map(|(i, b)| b ^ key[i % 4]));
+        out
+    }
+
+    #[test]
+    fn test_masked_frame_preview_is_unmasked() {
+        let mut stream = WsStream::default();
+        let frames = stream.feed(&masked_frame(0x81, b"hello")).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert!(frames[0].fin && frames[0].masked && !frames[0].compressed);
+        assert_eq!(frames[0].opcode, 0x1);
+        assert_eq!(frames[0].payload_len, 5);
+        assert_eq!(frames[0].preview, b"hello");
+        assert!(stream.buf.is_empty());
+    }
+
+    #[test]
+    fn test_long_frame_is_reported_at_its_preview_and_skipped() {
+        let payload = vec![b'a'; 1000];
+        let mut data = vec![0xc2, 126];
+        data.extend_from_slice(&1000u16.to_be_bytes());
+        data.extend_from_slice(&payload);
+        data.extend_from_slice(&[0x89, 0]); // ping
+
+        let mut stream = WsStream::default();
+        assert!(stream.feed(&data[..3]).unwrap().is_empty());
+        let frames = stream.feed(&data[3..300]).unwrap();
+        assert_eq!(frames[0].payload_len, 1000);
+        assert!(frames[0].compressed);
+        assert_eq!(frames[0].preview.len(), WS_PREVIEW);
+        assert_eq!(stream.skip, 1000 - (300 - 4) as u64);
+        let frames = stream.feed(&data[300..]).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(ws_opcode_name(frames[0].opcode), "ping");
+    }
+
+    #[test]
+    fn test_close_frame_reports_its_status_code() {
+        let ws = WsConnState::new("ws://a/chat".to_string(), 1, None);
+        let mut stream = WsStream::default();
+        let frames = stream
+            .feed(&[0x88, 0x05, 0x03, 0xe8, b'b', b'y', b'e'])
+            .unwrap();
+        let msgs = ws.frame_msgs(frames, "recv");
+        assert_eq!(msgs[0].opcode, "close");
+        assert_eq!(msgs[0].close_code, Some(1000));
+        assert_eq!(msgs[0].preview_b64.as_ref().unwrap().0, b"bye");
+        assert_eq!(msgs[0].url, "ws://a/chat");
+    }
+
+    #[test]
+    fn test_upgrade_detection() {
+        let headers = HashMap::from([("upgrade".to_string(), "WebSocket".to_string())]);
+        assert!(is_websocket_upgrade(Some(101), Some(&headers)));
+        assert!(!is_websocket_upgrade(Some(200), Some(&headers)));
+        let h2c = HashMap::from([("upgrade".to_string(), "h2c".to_string())]);
+        assert!(!is_websocket_upgrade(Some(101), Some(&h2c)));
+    }
+
+    #[test]
+    fn test_track_websocket_by_direction() {
+        let mut map = Shard::default();
+        assert!(track_websocket(&mut map, 3, b"\x81\x00", true).is_none());
+        let ws = WsConnState::new("ws://a/".to_string(), 1, None);
+        map.insert(3, FdState::WebSocket(Box::new(ws)));
+        let sent = track_websocket(&mut map, 3, &masked_frame(0x81, b"hi"), true).unwrap();
+        assert_eq!(sent[0].direction, "send");
+        let received = track_websocket(&mut map, 3, b"\x82\x01x", false).unwrap();
+        assert_eq!(received[0].direction, "recv");
+        assert_eq!(received[0].opcode, "binary");
+    }
+}
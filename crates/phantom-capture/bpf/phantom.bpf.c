@@ -0,0 +1,431 @@
+// SPDX-License-Identifier: GPL-2.0 OR MIT
+//
+// eBPF probes for phantom's `ebpf` capture backend.
+//
+// Socket syscalls (tracepoints), OpenSSL's SSL_read/SSL_write and Go's
+// crypto/tls.(*Conn).Read/Write (uprobes) of the processes in `targets` are
+// copied to the `events` ring buffer; the backend reassembles HTTP/1 from
+// them in user space. Children forked by a
+// target are followed. Only sockets the target connect()ed to an IPv4/IPv6
+// address are reported, so file and pipe I/O stay out of the buffer.
+//
+// Build (needs clang and libbpf headers):
+//
+//   clang -O2 -g -target bpf -D__TARGET_ARCH_x86 \
+//       -c crates/phantom-capture/bpf/phantom.bpf.c -o phantom.bpf.o
+//
+// (use -D__TARGET_ARCH_arm64 on aarch64), then pass `--bpf-object phantom.bpf.o`.
+
+#include <linux/bpf.h>
+#include <linux/types.h>
+#include <asm/ptrace.h>
+#include <bpf/bpf_helpers.h>
+#include <bpf/bpf_tracing.h>
+
+#define MAX_DATA 16384
+// Data events per call; the rest of a longer call is reported as `lost`.
+#define MAX_CHUNKS 4
+
+#define AF_INET 2
+#define AF_INET6 10
+#define EINPROGRESS 115
+
+enum kind { KIND_DATA = 0, KIND_CONNECT = 1, KIND_CLOSE = 2 };
+enum source { SOURCE_SOCKET = 0, SOURCE_SSL = 1, SOURCE_GO_TLS = 2 };
+enum direction { DIR_OUTBOUND = 0, DIR_INBOUND = 1 };
+
+// Mirrored by `EventHeader` in src/ebpf.rs; keep the two in sync.
+struct event {
+    __u64 timestamp_ns; // CLOCK_MONOTONIC
+    __u64 conn;         // fd for sockets, SSL* or *tls.Conn for TLS
+    __u32 pid;          // tgid
+    __u32 tid;
+    __u32 len;          // bytes of `data` in use
+    __u32 lost;         // bytes of the call after `data` that were not copied
+    __u8 kind;
+    __u8 source;
+    __u8 direction;
+    __u8 _pad[5];
+    __u8 data[MAX_DATA]; // payload, or the peer's sockaddr for KIND_CONNECT
+};
+
+struct {
+    __uint(type, BPF_MAP_TYPE_RINGBUF);
+    __uint(max_entries, 16 * 1024 * 1024);
+} events SEC(".maps");
+
+// Processes to trace, filled in by user space and by the fork tracepoint.
+struct {
+    __uint(type, BPF_MAP_TYPE_HASH);
+    __uint(max_entries, 1024);
+    __type(key, __u32);
+    __type(value, __u8);
+} targets SEC(".maps");
+
+// (tgid << 32 | fd) of connected IP sockets.
+struct {
+    __uint(type, BPF_MAP_TYPE_HASH);
+    __uint(max_entries, 65536);
+    __type(key, __u64);
+    __type(value, __u8);
+} sockets SEC(".maps");
+
+// Arguments of calls in progress, keyed by thread, read on return. SSL calls
+// have their own map: SSL_write's own write() runs while it is in progress.
+struct call_args {
+    __u64 conn;
+    __u64 buf;
+};
+
+struct {
+    __uint(type, BPF_MAP_TYPE_HASH);
+    __uint(max_entries, 16384);
+    __type(key, __u64);
+    __type(value, struct call_args);
+} socket_calls SEC(".maps");
+
+struct {
+    __uint(type, BPF_MAP_TYPE_HASH);
+    __uint(max_entries, 16384);
+    __type(key, __u64);
+    __type(value, struct call_args);
+} ssl_calls SEC(".maps");
+
+// Go calls in progress, keyed by goroutine: a goroutine blocked in Read may
+// resume on another thread.
+struct go_call_key {
+    __u64 g;
+    __u32 tgid;
+    __u32 _pad;
+};
+
+struct {
+    __uint(type, BPF_MAP_TYPE_HASH);
+    __uint(max_entries, 16384);
+    __type(key, struct go_call_key);
+    __type(value, struct call_args);
+} go_calls SEC(".maps");
+
+// Go's register ABI (Go 1.18+ on both architectures): arguments and results
+// in RAX, RBX, … (X0, X1, … on arm64), the running goroutine's g in R14
+// (X28).
+#if defined(__TARGET_ARCH_x86)
+#define GO_REG(ctx, x86, arm64) (((struct pt_regs *)(ctx))->x86)
+#elif defined(__TARGET_ARCH_arm64)
+#define GO_REG(ctx, x86, arm64) (((struct user_pt_regs *)(ctx))->arm64)
+#else
+#error "Go probes support x86 and arm64 only"
+#endif
+#define GO_ARG1(ctx) GO_REG(ctx, rax, regs[0])
+#define GO_ARG2(ctx) GO_REG(ctx, rbx, regs[1])
+#define GO_G(ctx) GO_REG(ctx, r14, regs[28])
+
+// Layout of the syscalls:sys_enter_* / sys_exit_* tracepoint records.
+struct sys_enter_ctx {
+    __u64 common;
+    __s32 syscall_nr;
+    __u32 _pad;
+    __u64 args[6];
+};
+
+struct sys_exit_ctx {
+    __u64 common;
+    __s32 syscall_nr;
+    __u32 _pad;
+    __s64 ret;
+};
+
+struct fork_ctx {
+    __u64 common;
+    char parent_comm[16];
+    __u32 parent_pid;
+    char child_comm[16];
+    __u32 child_pid;
+};
+
+static __always_inline int is_target(void)
+{
+    __u32 tgid = bpf_get_current_pid_tgid() >> 32;
+    return bpf_map_lookup_elem(&targets, &tgid) != NULL;
+}
+
+static __always_inline __u64 socket_key(__u64 fd)
+{
+    return (bpf_get_current_pid_tgid() & 0xffffffff00000000ULL) | (fd & 0xffffffff);
+}
+
+static __always_inline struct event *reserve(__u8 kind, __u8 source, __u8 direction, __u64 conn)
+{
+    struct event *e = bpf_ringbuf_reserve(&events, sizeof(*e), 0);
+    if (!e)
+        return NULL;
+    __u64 id = bpf_get_current_pid_tgid();
+    e->timestamp_ns = bpf_ktime_get_ns();
+    e->conn = conn;
+    e->pid = id >> 32;
+    e->tid = (__u32)id;
+    e->len = 0;
+    e->lost = 0;
+    e->kind = kind;
+    e->source = source;
+    e->direction = direction;
+    return e;
+}
+
+static __always_inline void emit_data(__u8 source, __u8 direction, __u64 conn, __u64 buf, __s64 len)
+{
+    for (int i = 0; i < MAX_CHUNKS; i++) {
+        __s64 off = (__s64)i * MAX_DATA;
+        if (off >= len)
+            return;
+        struct event *e = reserve(KIND_DATA, source, direction, conn);
+        if (!e)
+            return;
+        __u32 n = len - off > MAX_DATA ? MAX_DATA : len - off;
+        if (bpf_probe_read_user(e->data, n, (const void *)(buf + off)) != 0) {
+            bpf_ringbuf_discard(e, 0);
+            return;
+        }
+        e->len = n;
+        if (i == MAX_CHUNKS - 1 && len > off + n)
+            e->lost = len - off - n;
+        bpf_ringbuf_submit(e, 0);
+    }
+}
+
+static __always_inline void save_call(void *calls, __u64 conn, __u64 buf)
+{
+    __u64 tid = bpf_get_current_pid_tgid();
+    struct call_args args = { .conn = conn, .buf = buf };
+    bpf_map_update_elem(calls, &tid, &args, BPF_ANY);
+}
+
+static __always_inline int take_call(void *calls, struct call_args *args)
+{
+    __u64 tid = bpf_get_current_pid_tgid();
+    struct call_args *saved = bpf_map_lookup_elem(calls, &tid);
+    if (!saved)
+        return 0;
+    *args = *saved;
+    bpf_map_delete_elem(calls, &tid);
+    return 1;
+}
+
+// ── Processes ───────────────────────────────────────────────────────────────
+
+SEC("tracepoint/sched/sched_process_fork")
+int sched_process_fork(struct fork_ctx *ctx)
+{
+    __u32 parent = ctx->parent_pid;
+    if (!bpf_map_lookup_elem(&targets, &parent))
+        return 0;
+    __u32 child = ctx->child_pid;
+    __u8 one = 1;
+    bpf_map_update_elem(&targets, &child, &one, BPF_ANY);
+    return 0;
+}
+
+// ── Sockets ─────────────────────────────────────────────────────────────────
+
+SEC("tracepoint/syscalls/sys_enter_connect")
+int sys_enter_connect(struct sys_enter_ctx *ctx)
+{
+    if (is_target())
+        save_call(&socket_calls, ctx->args[0], ctx->args[1]);
+    return 0;
+}
+
+SEC("tracepoint/syscalls/sys_exit_connect")
+int sys_exit_connect(struct sys_exit_ctx *ctx)
+{
+    struct call_args args;
+    if (!take_call(&socket_calls, &args))
+        return 0;
+    if (ctx->ret != 0 && ctx->ret != -EINPROGRESS)
+        return 0;
+    __u16 family = 0;
+    bpf_probe_read_user(&family, sizeof(family), (const void *)args.buf);
+    if (family != AF_INET && family != AF_INET6)
+        return 0;
+    __u64 key = socket_key(args.conn);
+    __u8 one = 1;
+    bpf_map_update_elem(&sockets, &key, &one, BPF_ANY);
+
+    struct event *e = reserve(KIND_CONNECT, SOURCE_SOCKET, DIR_OUTBOUND, args.conn);
+    if (!e)
+        return 0;
+    // sockaddr_in6 is the larger of the two.
+    if (bpf_probe_read_user(e->data, 28, (const void *)args.buf) == 0)
+        e->len = 28;
+    bpf_ringbuf_submit(e, 0);
+    return 0;
+}
+
+static __always_inline int enter_io(struct sys_enter_ctx *ctx)
+{
+    if (!is_target())
+        return 0;
+    __u64 key = socket_key(ctx->args[0]);
+    if (bpf_map_lookup_elem(&sockets, &key))
+        save_call(&socket_calls, ctx->args[0], ctx->args[1]);
+    return 0;
+}
+
+static __always_inline int exit_io(struct sys_exit_ctx *ctx, __u8 direction)
+{
+    struct call_args args;
+    if (take_call(&socket_calls, &args) && ctx->ret > 0)
+        emit_data(SOURCE_SOCKET, direction, args.conn, args.buf, ctx->ret);
+    return 0;
+}
+
+SEC("tracepoint/syscalls/sys_enter_write")
+int sys_enter_write(struct sys_enter_ctx *ctx) { return enter_io(ctx); }
+
+SEC("tracepoint/syscalls/sys_exit_write")
+int sys_exit_write(struct sys_exit_ctx *ctx) { return exit_io(ctx, DIR_OUTBOUND); }
+
+SEC("tracepoint/syscalls/sys_enter_sendto")
+int sys_enter_sendto(struct sys_enter_ctx *ctx) { return enter_io(ctx); }
+
+SEC("tracepoint/syscalls/sys_exit_sendto")
+int sys_exit_sendto(struct sys_exit_ctx *ctx) { return exit_io(ctx, DIR_OUTBOUND); }
+
+SEC("tracepoint/syscalls/sys_enter_read")
+int sys_enter_read(struct sys_enter_ctx *ctx) { return enter_io(ctx); }
+
+SEC("tracepoint/syscalls/sys_exit_read")
+int sys_exit_read(struct sys_exit_ctx *ctx) { return exit_io(ctx, DIR_INBOUND); }
+
+SEC("tracepoint/syscalls/sys_enter_recvfrom")
+int sys_enter_recvfrom(struct sys_enter_ctx *ctx) { return enter_io(ctx); }
+
+SEC("tracepoint/syscalls/sys_exit_recvfrom")
+int sys_exit_recvfrom(struct sys_exit_ctx *ctx) { return exit_io(ctx, DIR_INBOUND); }
+
+SEC("tracepoint/syscalls/sys_enter_close")
+int sys_enter_close(struct sys_enter_ctx *ctx)
+{
+    if (!is_target())
+        return 0;
+    __u64 key = socket_key(ctx->args[0]);
+    if (!bpf_map_lookup_elem(&sockets, &key))
+        return 0;
+    bpf_map_delete_elem(&sockets, &key);
+    struct event *e = reserve(KIND_CLOSE, SOURCE_SOCKET, DIR_OUTBOUND, ctx->args[0]);
+    if (e)
+        bpf_ringbuf_submit(e, 0);
+    return 0;
+}
+
+// ── OpenSSL ─────────────────────────────────────────────────────────────────
+
+SEC("uprobe/SSL_write")
+int BPF_UPROBE(ssl_write, void *ssl, const void *buf, int num)
+{
+    if (is_target())
+        save_call(&ssl_calls, (__u64)ssl, (__u64)buf);
+    return 0;
+}
+
+SEC("uretprobe/SSL_write")
+int BPF_URETPROBE(ssl_write_ret, int ret)
+{
+    struct call_args args;
+    if (take_call(&ssl_calls, &args) && ret > 0)
+        emit_data(SOURCE_SSL, DIR_OUTBOUND, args.conn, args.buf, ret);
+    return 0;
+}
+
+SEC("uprobe/SSL_read")
+int BPF_UPROBE(ssl_read, void *ssl, void *buf, int num)
+{
+    if (is_target())
+        save_call(&ssl_calls, (__u64)ssl, (__u64)buf);
+    return 0;
+}
+
+SEC("uretprobe/SSL_read")
+int BPF_URETPROBE(ssl_read_ret, int ret)
+{
+    struct call_args args;
+    if (take_call(&ssl_calls, &args) && ret > 0)
+        emit_data(SOURCE_SSL, DIR_INBOUND, args.conn, args.buf, ret);
+    return 0;
+}
+
+SEC("uprobe/SSL_free")
+int BPF_UPROBE(ssl_free, void *ssl)
+{
+    if (!is_target())
+        return 0;
+    struct event *e = reserve(KIND_CLOSE, SOURCE_SSL, DIR_OUTBOUND, (__u64)ssl);
+    if (e)
+        bpf_ringbuf_submit(e, 0);
+    return 0;
+}
+
+// ── Go crypto/tls ───────────────────────────────────────────────────────────
+//
+// Return probes crash Go programs whose goroutine stacks move, so the
+// results are read by the `_ret` programs, which user space attaches to
+// every RET instruction of the function (see src/gotls.rs).
+
+static __always_inline struct go_call_key go_key(struct pt_regs *ctx)
+{
+    struct go_call_key key = {};
+    key.g = GO_G(ctx);
+    key.tgid = bpf_get_current_pid_tgid() >> 32;
+    return key;
+}
+
+static __always_inline int go_enter(struct pt_regs *ctx)
+{
+    if (!is_target())
+        return 0;
+    struct go_call_key key = go_key(ctx);
+    // Write and Read(b []byte) (int, error) take c, then b's pointer.
+    struct call_args args = { .conn = GO_ARG1(ctx), .buf = GO_ARG2(ctx) };
+    bpf_map_update_elem(&go_calls, &key, &args, BPF_ANY);
+    return 0;
+}
+
+static __always_inline int go_return(struct pt_regs *ctx, __u8 direction)
+{
+    struct go_call_key key = go_key(ctx);
+    struct call_args *args = bpf_map_lookup_elem(&go_calls, &key);
+    if (!args)
+        return 0;
+    // The byte count is the first result.
+    __s64 n = (__s64)GO_ARG1(ctx);
+    if (n > 0)
+        emit_data(SOURCE_GO_TLS, direction, args->conn, args->buf, n);
+    bpf_map_delete_elem(&go_calls, &key);
+    return 0;
+}
+
+SEC("uprobe/go_tls_write")
+int go_tls_write(struct pt_regs *ctx) { return go_enter(ctx); }
+
+SEC("uprobe/go_tls_write_ret")
+int go_tls_write_ret(struct pt_regs *ctx) { return go_return(ctx, DIR_OUTBOUND); }
+
+SEC("uprobe/go_tls_read")
+int go_tls_read(struct pt_regs *ctx) { return go_enter(ctx); }
+
+SEC("uprobe/go_tls_read_ret")
+int go_tls_read_ret(struct pt_regs *ctx) { return go_return(ctx, DIR_INBOUND); }
+
+SEC("uprobe/go_tls_close")
+int go_tls_close(struct pt_regs *ctx)
+{
+    if (!is_target())
+        return 0;
+    struct event *e = reserve(KIND_CLOSE, SOURCE_GO_TLS, DIR_OUTBOUND, GO_ARG1(ctx));
+    if (e)
+        bpf_ringbuf_submit(e, 0);
+    return 0;
+}
+
+// Tracing helpers need a GPL-compatible license.
+char LICENSE[] SEC("license") = "Dual MIT/GPL";
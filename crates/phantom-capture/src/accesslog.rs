@@ -0,0 +1,942 @@
+//! Access-log backend: traces from proxy logs — cross-platform.
+//!
+//! Follows what a proxy already writes instead of intercepting anything, so
+//! a service mesh's traffic (Envoy sidecars, Istio gateways, NGINX ingress)
+//! is seen without touching it. The path is either
+//!
+//! - a log file, followed like `tail -F` from its current end (and from the
+//!   start again when it is truncated or rotated), holding JSON access-log
+//!   entries — Envoy's `json_format`, NGINX's `log_format … escape=json` —
+//!   and/or Envoy tap traces (`http_buffered_trace`); or
+//! - a directory of Envoy `file_per_tap` output, whose new `*.json` files
+//!   are read as they appear.
+//!
+//! Documents may be one per line or pretty-printed. Access-log entries are
+//! matched by the field names Envoy's and NGINX's variables are usually
+//! given (see [`access_log_entry`]); they carry no bodies. Tap traces carry
+//! the full headers and bodies. Text-format logs are not understood.
+
+use std::collections::{HashMap, HashSet};
+use std::io::SeekFrom;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use base64::Engine as _;
+use base64::engine::general_purpose::STANDARD as B64;
+use phantom_core::capture::{CaptureBackend, TraceEvent};
+use phantom_core::error::CaptureError;
+use phantom_core::trace::{
+    BodyEncoding, HttpMethod, HttpTrace, SpanId, TimingPhases, TraceId, parse_traceparent,
+};
+use serde_json::{Map, Value};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio::sync::{mpsc, oneshot};
+use tracing::{debug, warn};
+
+use crate::decode::{MAX_DECODED_SIZE, decompress_body};
+use crate::har::parse_datetime;
+
+/// How often the log is checked for new data.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// An unfinished document larger than this is dropped as garbage.
+const MAX_PENDING: usize = 16 * 1024 * 1024;
+
+pub struct AccessLogCaptureBackend {
+    path: PathBuf,
+    shutdown_tx: Option<oneshot::Sender<()>>,
+    task_handle: Option<tokio::task::JoinHandle<()>>,
+}
+
+impl AccessLogCaptureBackend {
+    /// Create a backend following the log file or tap directory at `path`.
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            shutdown_tx: None,
+            task_handle: None,
+        }
+    }
+}
+
+impl CaptureBackend for AccessLogCaptureBackend {
+    fn start(&mut self) -> Result<mpsc::Receiver<TraceEvent>, CaptureError> {
+        let is_dir = match std::fs::metadata(&self.path) {
+            Ok(meta) => meta.is_dir(),
+            // A log file may only be created once the proxy logs something.
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => false,
+            Err(e) => {
+                return Err(CaptureError::StartFailed(format!(
+                    "{}: {e}",
+                    self.path.display()
+                )));
+            }
+        };
+        let (trace_tx, trace_rx) = mpsc::channel(4096);
+        let (shutdown_tx, shutdown_rx) = oneshot::channel();
+        self.shutdown_tx = Some(shutdown_tx);
+        let path = self.path.clone();
+        self.task_handle = Some(tokio::spawn(async move {
+            tokio::select! {
+                _ = follow(path, is_dir, trace_tx) => {}
+                _ = shutdown_rx => {}
+            }
+        }));
+        Ok(trace_rx)
+    }
+
+    fn stop(&mut self) -> Result<(), CaptureError> {
+        if let Some(tx) = self.shutdown_tx.take() {
+            let _ = tx.send(());
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "accesslog"
+    }
+}
+
+async fn follow(path: PathBuf, is_dir: bool, trace_tx: mpsc::Sender<TraceEvent>) {
+    let mut source = if is_dir {
+        Source::TapDir(TapDir::new(path))
+    } else {
+        Source::Log(LogFile::new(path).await)
+    };
+    loop {
+        let documents = match &mut source {
+            Source::Log(log) => log.poll().await,
+            Source::TapDir(dir) => dir.poll().await,
+        };
+        for document in documents {
+            let Some(trace) = parse_document(&document, SystemTime::now()) else {
+                debug!("access log: skipping a document that is not an HTTP trace");
+                continue;
+            };
+            if trace_tx.send(trace.into()).await.is_err() {
+                return;
+            }
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+enum Source {
+    Log(LogFile),
+    TapDir(TapDir),
+}
+
+// ─── Following a log file ────────────────────────────────────────────────────
+
+/// A log file followed from where it ended when phantom started.
+struct LogFile {
+    path: PathBuf,
+    file: Option<tokio::fs::File>,
+    /// Identity of the open file, to notice rotation.
+    id: Option<u64>,
+    offset: u64,
+    pending: Vec<u8>,
+}
+
+impl LogFile {
+    async fn new(path: PathBuf) -> Self {
+        let mut log = Self {
+            path,
+            file: None,
+            id: None,
+            offset: 0,
+            pending: Vec::new(),
+        };
+        if log.open().await {
+            log.offset = log.seek_end().await;
+        }
+        log
+    }
+
+    async fn open(&mut self) -> bool {
+        let Ok(file) = tokio::fs::File::open(&self.path).await else {
+            return false;
+        };
+        self.id = file.metadata().await.ok().and_then(|m| file_id(&m));
+        self.file = Some(file);
+        self.offset = 0;
+        self.pending.clear();
+        true
+    }
+
+    async fn seek_end(&mut self) -> u64 {
+        match &mut self.file {
+            Some(file) => file.seek(SeekFrom::End(0)).await.unwrap_or(0),
+            None => 0,
+        }
+    }
+
+    /// Documents completed since the last poll.
+    async fn poll(&mut self) -> Vec<Value> {
+        // Start over on a new file at the path, or when it was truncated.
+        let meta = tokio::fs::metadata(&self.path).await.ok();
+        let replaced = meta.as_ref().and_then(file_id) != self.id;
+        let truncated = meta.as_ref().is_some_and(|m| m.len() < self.offset);
+        if self.file.is_none() || replaced || truncated {
+            // Read what is left of a rotated file first.
+            let mut documents = if replaced {
+                self.read().await
+            } else {
+                Vec::new()
+            };
+            if self.open().await {
+                documents.extend(self.read().await);
+            }
+            return documents;
+        }
+        self.read().await
+    }
+
+    async fn read(&mut self) -> Vec<Value> {
+        let Some(file) = &mut self.file else {
+            return Vec::new();
+        };
+        let mut chunk = Vec::new();
+        match file.read_to_end(&mut chunk).await {
+            Ok(n) => self.offset += n as u64,
+            Err(e) => warn!("reading {}: {e}", self.path.display()),
+        }
+        self.pending.extend_from_slice(&chunk);
+        let documents = drain_documents(&mut self.pending);
+        if self.pending.len() > MAX_PENDING {
+            warn!(
+                "{}: unterminated entry over {MAX_PENDING} bytes, dropped",
+                self.path.display()
+            );
+            self.pending.clear();
+        }
+        documents
+    }
+}
+
+#[cfg(unix)]
+fn file_id(meta: &std::fs::Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(meta.ino())
+}
+
+/// Without inode numbers only truncation reveals a rotated file.
+#[cfg(not(unix))]
+fn file_id(_meta: &std::fs::Metadata) -> Option<u64> {
+    None
+}
+
+/// Removes the complete JSON documents at the start of `buf` and returns
+/// them. An unfinished document stays for the next read; a line that is
+/// not JSON is skipped.
+fn drain_documents(buf: &mut Vec<u8>) -> Vec<Value> {
+    let mut documents = Vec::new();
+    let mut consumed = 0;
+    loop {
+        let mut stream = serde_json::Deserializer::from_slice(&buf[consumed..]).into_iter();
+        match stream.next() {
+            Some(Ok(value)) => {
+                documents.push(value);
+                consumed += stream.byte_offset();
+            }
+            Some(Err(e)) if e.is_eof() => break,
+            Some(Err(_)) => {
+                // Skip the offending line (e.g. a text-format entry).
+                let rest = &buf[consumed..];
+                let start = rest
+                    .iter()
+                    .position(|b| !b.is_ascii_whitespace())
+                    .unwrap_or(0);
+                match rest[start..].iter().position(|&b| b == b'\n') {
+                    Some(end) => {
+                        debug!("access log: skipping a line that is not JSON");
+                        consumed += start + end + 1;
+                    }
+                    None => break,
+                }
+            }
+            None => {
+                consumed = buf.len();
+                break;
+            }
+        }
+    }
+    buf.drain(..consumed);
+    documents
+}
+
+// ─── Following a tap directory ───────────────────────────────────────────────
+
+/// A directory Envoy writes one tap file per trace into.
+struct TapDir {
+    path: PathBuf,
+    /// Files already read, or present at start.
+    seen: Option<HashSet<PathBuf>>,
+}
+
+impl TapDir {
+    fn new(path: PathBuf) -> Self {
+        Self { path, seen: None }
+    }
+
+    async fn json_files(&self) -> Vec<PathBuf> {
+        let mut files = Vec::new();
+        let Ok(mut entries) = tokio::fs::read_dir(&self.path).await else {
+            return files;
+        };
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                files.push(path);
+            }
+        }
+        files.sort();
+        files
+    }
+
+    async fn poll(&mut self) -> Vec<Value> {
+        let files = self.json_files().await;
+        let Some(seen) = &mut self.seen else {
+            self.seen = Some(files.into_iter().collect());
+            return Vec::new();
+        };
+        let mut documents = Vec::new();
+        for file in files {
+            if seen.contains(&file) {
+                continue;
+            }
+            let Ok(mut data) = tokio::fs::read(&file).await else {
+                continue;
+            };
+            let parsed = drain_documents(&mut data);
+            // Still being written: try again on the next poll.
+            if parsed.is_empty() && !data.iter().all(u8::is_ascii_whitespace) {
+                continue;
+            }
+            documents.extend(parsed);
+            seen.insert(file);
+        }
+        documents
+    }
+}
+
+// ─── Entries → traces ────────────────────────────────────────────────────────
+
+/// Converts one document into a trace: an Envoy tap trace, or an access-log
+/// entry. `now` stands in for times the document does not carry.
+fn parse_document(document: &Value, now: SystemTime) -> Option<HttpTrace> {
+    let object = document.as_object()?;
+    match object.get("http_buffered_trace") {
+        Some(trace) => tap_trace(trace.as_object()?, now),
+        None => access_log_entry(object, now),
+    }
+}
+
+/// `obj[key]` for the first of `keys` with a value; `null`, `""` and `"-"`
+/// (Envoy's and NGINX's "no value") count as missing.
+fn field<'a>(obj: &'a Map<String, Value>, keys: &[&str]) -> Option<&'a Value> {
+    keys.iter()
+        .filter_map(|key| obj.get(*key))
+        .find(|v| !matches!(v, Value::Null) && !matches!(v.as_str(), Some("" | "-")))
+}
+
+fn text(obj: &Map<String, Value>, keys: &[&str]) -> Option<String> {
+    field(obj, keys).and_then(|v| match v {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    })
+}
+
+/// A number, or a string holding one (NGINX logs every variable as a string).
+fn number(obj: &Map<String, Value>, keys: &[&str]) -> Option<f64> {
+    field(obj, keys).and_then(|v| match v {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    })
+}
+
+/// Log fields copied into request headers, by header name.
+const REQUEST_HEADER_FIELDS: &[(&str, &[&str])] = &[
+    ("user-agent", &["user_agent"]),
+    ("x-forwarded-for", &["x_forwarded_for"]),
+    ("x-request-id", &["request_id", "x_request_id"]),
+    ("referer", &["referer", "referrer"]),
+    ("traceparent", &["traceparent"]),
+    ("content-type", &["content_type"]),
+];
+
+/// Converts an access-log entry, naming fields after Envoy's command
+/// operators (`method`, `path`, `authority`, `response_code`, `duration`
+/// in ms, `start_time`, `bytes_received`, `upstream_host`, …) or NGINX's
+/// variables (`request_method`, `request_uri`, `host`, `status`,
+/// `request_time` in s, `time_iso8601`/`time_local`/`msec`,
+/// `request_length`, `upstream_addr`, `http_*` and `sent_http_*` headers, …).
+/// NGINX logs when a request ends, so its times are moved back by the
+/// duration. Entries without a method and status are not HTTP traces.
+fn access_log_entry(obj: &Map<String, Value>, now: SystemTime) -> Option<HttpTrace> {
+    let method: HttpMethod = text(obj, &["method", "request_method"])?.parse().ok()?;
+    let status_code = number(obj, &["response_code", "status", "status_code"])? as u16;
+
+    let duration = match number(obj, &["duration"]) {
+        Some(ms) => millis(ms),
+        None => number(obj, &["request_time"]).map_or(Duration::ZERO, |s| millis(s * 1000.0)),
+    };
+    let started =
+        text(obj, &["start_time", "timestamp", "@timestamp"]).and_then(|t| parse_datetime(&t));
+    let ended = text(obj, &["time_iso8601", "time"])
+        .and_then(|t| parse_datetime(&t))
+        .or_else(|| text(obj, &["time_local"]).and_then(|t| parse_clf_time(&t)))
+        .or_else(|| {
+            number(obj, &["msec"])
+                .filter(|s| *s >= 0.0)
+                .map(|s| UNIX_EPOCH + Duration::from_secs_f64(s))
+        });
+    let timestamp = started
+        .or_else(|| ended.map(|end| end.checked_sub(duration).unwrap_or(end)))
+        .unwrap_or(now);
+
+    let mut request_headers = HashMap::new();
+    let mut response_headers = HashMap::new();
+    for key in obj.keys() {
+        let Some(value) = text(obj, &[key.as_str()]) else {
+            continue;
+        };
+        if let Some(name) = key.strip_prefix("sent_http_") {
+            response_headers.insert(name.replace('_', "-"), value);
+        } else if let Some(name) = key.strip_prefix("http_") {
+            request_headers.insert(name.replace('_', "-"), value);
+        }
+    }
+    for (header, keys) in REQUEST_HEADER_FIELDS {
+        if let Some(value) = text(obj, keys) {
+            request_headers.insert(header.to_string(), value);
+        }
+    }
+    let host = text(obj, &["authority", "host", "http_host", "server_name"]);
+    if let Some(host) = &host {
+        request_headers.insert("host".to_string(), host.clone());
+    }
+
+    let target = text(obj, &["path", "request_uri", "uri"]).unwrap_or_else(|| "/".to_string());
+    let url = if target.contains("://") {
+        target
+    } else {
+        let scheme = text(
+            obj,
+            &["scheme", "x_forwarded_proto", "http_x_forwarded_proto"],
+        )
+        .unwrap_or_else(|| "http".to_string());
+        format!(
+            "{scheme}://{}{target}",
+            host.as_deref().unwrap_or("unknown")
+        )
+    };
+    let source_addr =
+        text(obj, &["downstream_remote_address", "remote_addr"]).map(|addr| {
+            match text(obj, &["remote_port"]) {
+                Some(port) if !addr.contains(':') => format!("{addr}:{port}"),
+                _ => addr,
+            }
+        });
+    let (trace_id, span_id, parent_span_id) = trace_ids(&request_headers);
+
+    Some(HttpTrace {
+        span_id,
+        trace_id,
+        parent_span_id,
+        method,
+        url,
+        request_headers,
+        request_body: None,
+        status_code,
+        response_headers,
+        response_trailers: HashMap::new(),
+        response_body: None,
+        timestamp,
+        duration,
+        source_addr,
+        dest_addr: text(obj, &["upstream_host", "upstream_addr"]),
+        protocol_version: text(obj, &["protocol", "server_protocol"])
+            .unwrap_or_else(|| "HTTP/1.1".to_string()),
+        request_body_file: None,
+        response_body_file: None,
+        connection_id: text(obj, &["connection_id", "connection"]),
+        process: None,
+        thread_id: None,
+        tls: None,
+        timing: upstream_ttfb(obj).map(|ttfb| TimingPhases {
+            ttfb: Some(ttfb),
+            ..TimingPhases::default()
+        }),
+        request_size: number(obj, &["bytes_received", "request_length"]).map(|n| n as u64),
+        response_size: number(obj, &["bytes_sent"]).map(|n| n as u64),
+        fault: None,
+        replay: None,
+        request_body_encoding: None,
+        response_body_encoding: None,
+        elasticsearch: None,
+        soap: None,
+        graphql: None,
+    })
+}
+
+/// Time until the upstream's response headers: Envoy's
+/// `upstream_service_time` (ms) or NGINX's `upstream_header_time` (s).
+fn upstream_ttfb(obj: &Map<String, Value>) -> Option<Duration> {
+    number(obj, &["upstream_service_time"])
+        .map(millis)
+        .or_else(|| number(obj, &["upstream_header_time"]).map(|s| millis(s * 1000.0)))
+}
+
+/// Converts an Envoy `http_buffered_trace`: headers, bodies and trailers
+/// as the tap filter saw them, and the times headers arrived when Envoy
+/// records them (1.27+).
+fn tap_trace(trace: &Map<String, Value>, now: SystemTime) -> Option<HttpTrace> {
+    let request = trace.get("request")?.as_object()?;
+    let response = trace.get("response")?.as_object()?;
+    let (pseudo, request_headers) = tap_headers(request.get("headers"));
+    let (response_pseudo, response_headers) = tap_headers(response.get("headers"));
+    let (_, response_trailers) = tap_headers(response.get("trailers"));
+
+    let method: HttpMethod = pseudo.get(":method")?.parse().ok()?;
+    let status_code = response_pseudo.get(":status")?.parse().ok()?;
+    let host = pseudo
+        .get(":authority")
+        .or(request_headers.get("host"))
+        .map_or("unknown", String::as_str);
+    let url = format!(
+        "{}://{host}{}",
+        pseudo.get(":scheme").map_or("http", String::as_str),
+        pseudo.get(":path").map_or("/", String::as_str)
+    );
+
+    let received = |message: &Map<String, Value>| {
+        message
+            .get("headers_received_time")
+            .and_then(Value::as_str)
+            .and_then(parse_datetime)
+    };
+    let (timestamp, duration) = match (received(request), received(response)) {
+        (Some(start), end) => (
+            start,
+            end.and_then(|end| end.duration_since(start).ok())
+                .unwrap_or_default(),
+        ),
+        (None, _) => (now, Duration::ZERO),
+    };
+    let (request_body, request_body_encoding) =
+        decompress(&request_headers, tap_body(request.get("body")));
+    let (response_body, response_body_encoding) =
+        decompress(&response_headers, tap_body(response.get("body")));
+    let connection = trace
+        .get("downstream_connection")
+        .and_then(Value::as_object);
+    let address = |key: &str| {
+        let socket = connection?.get(key)?.get("socket_address")?;
+        let ip = socket.get("address")?.as_str()?;
+        let port = socket.get("port_value")?.as_u64()?;
+        Some(if ip.contains(':') {
+            format!("[{ip}]:{port}")
+        } else {
+            format!("{ip}:{port}")
+        })
+    };
+    let (trace_id, span_id, parent_span_id) = trace_ids(&request_headers);
+
+    Some(HttpTrace {
+        span_id,
+        trace_id,
+        parent_span_id,
+        method,
+        url,
+        request_headers,
+        request_body,
+        status_code,
+        response_headers,
+        response_trailers,
+        response_body,
+        timestamp,
+        duration,
+        source_addr: address("remote_address"),
+        dest_addr: address("local_address"),
+        protocol_version: "HTTP/1.1".to_string(),
+        request_body_file: None,
+        response_body_file: None,
+        connection_id: None,
+        process: None,
+        thread_id: None,
+        tls: None,
+        timing: None,
+        request_size: None,
+        response_size: None,
+        fault: None,
+        replay: None,
+        request_body_encoding,
+        response_body_encoding,
+        elasticsearch: None,
+        soap: None,
+        graphql: None,
+    })
+}
+
+/// Splits tap headers (`[{"key": …, "value": …}]`) into pseudo-headers and
+/// lower-cased regular ones, joining repeats with `, `.
+fn tap_headers(headers: Option<&Value>) -> (HashMap<String, String>, HashMap<String, String>) {
+    let mut pseudo = HashMap::new();
+    let mut regular: HashMap<String, String> = HashMap::new();
+    for header in headers.and_then(Value::as_array).into_iter().flatten() {
+        let (Some(key), Some(value)) = (
+            header.get("key").and_then(Value::as_str),
+            header.get("value").and_then(Value::as_str),
+        ) else {
+            continue;
+        };
+        let key = key.to_ascii_lowercase();
+        if key.starts_with(':') {
+            pseudo.insert(key, value.to_string());
+            continue;
+        }
+        regular
+            .entry(key)
+            .and_modify(|existing| {
+                existing.push_str(", ");
+                existing.push_str(value);
+            })
+            .or_insert_with(|| value.to_string());
+    }
+    (pseudo, regular)
+}
+
+/// A tap body: `as_bytes` (base64) or `as_string`, per the tap's format.
+fn tap_body(body: Option<&Value>) -> Option<Vec<u8>> {
+    let body = body?;
+    if let Some(text) = body.get("as_string").and_then(Value::as_str) {
+        return Some(text.as_bytes().to_vec());
+    }
+    B64.decode(body.get("as_bytes")?.as_str()?).ok()
+}
+
+fn decompress(
+    headers: &HashMap<String, String>,
+    body: Option<Vec<u8>>,
+) -> (Option<Vec<u8>>, Option<BodyEncoding>) {
+    let body = body.filter(|b| !b.is_empty());
+    match body
+        .as_deref()
+        .and_then(|b| decompress_body(headers, b, MAX_DECODED_SIZE))
+    {
+        Some((decoded, encoding)) => (Some(decoded), Some(encoding)),
+        None => (body, None),
+    }
+}
+
+/// IDs from the request's `traceparent`, as a child span, or fresh ones.
+fn trace_ids(headers: &HashMap<String, String>) -> (TraceId, SpanId, Option<SpanId>) {
+    match headers
+        .get("traceparent")
+        .and_then(|value| parse_traceparent(value))
+    {
+        Some((trace_id, parent)) => (trace_id, SpanId(rand_bytes::<8>()), Some(parent)),
+        None => (TraceId(rand_bytes::<16>()), SpanId(rand_bytes::<8>()), None),
+    }
+}
+
+fn millis(ms: f64) -> Duration {
+    if ms.is_finite() && ms >= 0.0 {
+        Duration::from_secs_f64(ms / 1000.0)
+    } else {
+        Duration::ZERO
+    }
+}
+
+/// Parses NGINX's `$time_local`, e.g. `10/Oct/2000:13:55:36 -0700`.
+fn parse_clf_time(s: &str) -> Option<SystemTime> {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let (datetime, offset) = s.trim().split_once(' ')?;
+    let mut parts = datetime.splitn(3, '/');
+    let day = parts.next()?;
+    let month = parts.next()?;
+    let month = MONTHS.iter().position(|m| *m == month)? + 1;
+    let (year, time) = parts.next()?.split_once(':')?;
+    parse_datetime(&format!("{year}-{month:02}-{day}T{time}{offset}"))
+}
+
+fn rand_bytes<const N: usize>() -> [u8; N] {
+    let mut buf = [0u8; N];
+    buf.iter_mut().for_each(|b| *b = rand::random());
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use serde_json::json;
+
+    use super::*;
+
+    fn parse(document: Value) -> Option<HttpTrace> {
+        parse_document(&document, UNIX_EPOCH)
+    }
+
+    #[test]
+    fn test_envoy_json_access_log() {
+        let trace = parse(json!({
+            "start_time": "2024-05-01T12:00:00.250Z",
+            "method": "POST",
+            "path": "/v1/orders?x=1",
+            "protocol": "HTTP/2",
+            "response_code": 503,
+            "response_flags": "UF",
+            "bytes_received": 42,
+            "bytes_sent": 91,
+            "duration": 17,
+            "upstream_service_time": "15",
+            "x_forwarded_for": null,
+            "user_agent": "curl/8.0",
+            "request_id": "a1b2",
+            "authority": "orders.default.svc:8080",
+            "upstream_host": "10.0.0.7:8080",
+            "downstream_remote_address": "10.0.0.3:51234",
+            "traceparent": "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01"
+        }))
+        .unwrap();
+        assert_eq!(trace.method, HttpMethod::Post);
+        assert_eq!(trace.url, "http://orders.default.svc:8080/v1/orders?x=1");
+        assert_eq!(trace.status_code, 503);
+        assert_eq!(trace.protocol_version, "HTTP/2");
+        assert_eq!(
+            trace.timestamp,
+            UNIX_EPOCH + Duration::from_millis(1_714_564_800_250)
+        );
+        assert_eq!(trace.duration, Duration::from_millis(17));
+        assert_eq!(trace.timing.unwrap().ttfb, Some(Duration::from_millis(15)));
+        assert_eq!(trace.request_headers["user-agent"], "curl/8.0");
+        assert_eq!(trace.request_headers["x-request-id"], "a1b2");
+        assert_eq!(trace.request_headers["host"], "orders.default.svc:8080");
+        assert!(!trace.request_headers.contains_key("x-forwarded-for"));
+        assert_eq!(trace.source_addr.as_deref(), Some("10.0.0.3:51234"));
+        assert_eq!(trace.dest_addr.as_deref(), Some("10.0.0.7:8080"));
+        assert_eq!(trace.request_size, Some(42));
+        assert_eq!(trace.response_size, Some(91));
+        assert_eq!(
+            trace.trace_id,
+            TraceId::from_hex("0af7651916cd43dd8448eb211c80319c").unwrap()
+        );
+        assert_eq!(trace.parent_span_id, SpanId::from_hex("b7ad6b7169203331"));
+    }
+
+    #[test]
+    fn test_nginx_json_access_log() {
+        let trace = parse(json!({
+            "time_local": "01/May/2024:14:00:01 +0200",
+            "remote_addr": "192.168.1.9",
+            "remote_port": "40000",
+            "request_method": "GET",
+            "request_uri": "/health",
+            "server_protocol": "HTTP/1.1",
+            "status": "200",
+            "request_time": "0.250",
+            "request_length": "80",
+            "bytes_sent": "612",
+            "host": "web.test",
+            "scheme": "https",
+            "http_user_agent": "kube-probe/1.29",
+            "sent_http_content_type": "text/plain",
+            "upstream_addr": "-"
+        }))
+        .unwrap();
+        assert_eq!(trace.method, HttpMethod::Get);
+        assert_eq!(trace.url, "https://web.test/health");
+        assert_eq!(trace.status_code, 200);
+        assert_eq!(trace.duration, Duration::from_millis(250));
+        // Logged at 12:00:01 UTC, when the request ended.
+        assert_eq!(
+            trace.timestamp,
+            UNIX_EPOCH + Duration::from_millis(1_714_564_800_750)
+        );
+        assert_eq!(trace.source_addr.as_deref(), Some("192.168.1.9:40000"));
+        assert_eq!(trace.dest_addr, None);
+        assert_eq!(trace.request_headers["user-agent"], "kube-probe/1.29");
+        assert_eq!(trace.response_headers["content-type"], "text/plain");
+        assert_eq!(trace.request_size, Some(80));
+        assert!(trace.timing.is_none());
+    }
+
+    #[test]
+    fn test_nginx_msec_and_missing_time() {
+        let trace = parse(json!({
+            "request_method": "DELETE", "status": 204, "msec": "1714564801.500",
+            "request_time": "0.5", "request_uri": "/x"
+        }))
+        .unwrap();
+        assert_eq!(
+            trace.timestamp,
+            UNIX_EPOCH + Duration::from_secs(1_714_564_801)
+        );
+        assert_eq!(trace.url, "http://unknown/x");
+
+        let trace = parse(json!({ "method": "GET", "response_code": 200 })).unwrap();
+        assert_eq!(trace.timestamp, UNIX_EPOCH);
+        assert_eq!(trace.url, "http://unknown/");
+    }
+
+    #[test]
+    fn test_non_http_entries_are_skipped() {
+        assert!(parse(json!({ "level": "info", "msg": "started" })).is_none());
+        assert!(parse(json!({ "method": "GET" })).is_none());
+        assert!(parse(json!({ "method": "BREW", "response_code": 418 })).is_none());
+        assert!(parse(json!([1, 2])).is_none());
+    }
+
+    #[test]
+    fn test_envoy_tap_trace() {
+        let trace = parse(json!({
+            "http_buffered_trace": {
+                "request": {
+                    "headers": [
+                        { "key": ":authority", "value": "api.test" },
+                        { "key": ":path", "value": "/users" },
+                        { "key": ":method", "value": "POST" },
+                        { "key": ":scheme", "value": "https" },
+                        { "key": "content-type", "value": "application/json" }
+                    ],
+                    "body": { "truncated": false, "as_string": "{\"name\":\"a\"}" },
+                    "headers_received_time": "2024-05-01T12:00:00Z"
+                },
+                "response": {
+                    "headers": [
+                        { "key": ":status", "value": "201" },
+                        { "key": "Set-Cookie", "value": "a=1" },
+                        { "key": "set-cookie", "value": "b=2" }
+                    ],
+                    "body": { "truncated": false, "as_bytes": "eyJpZCI6MX0=" },
+                    "trailers": [{ "key": "grpc-status", "value": "0" }],
+                    "headers_received_time": "2024-05-01T12:00:00.040Z"
+                },
+                "downstream_connection": {
+                    "local_address": { "socket_address": { "address": "10.0.0.5", "port_value": 8443 } },
+                    "remote_address": { "socket_address": { "address": "::1", "port_value": 5000 } }
+                }
+            }
+        }))
+        .unwrap();
+        assert_eq!(trace.method, HttpMethod::Post);
+        assert_eq!(trace.url, "https://api.test/users");
+        assert_eq!(trace.status_code, 201);
+        assert!(!trace.request_headers.contains_key(":path"));
+        assert_eq!(trace.response_headers["set-cookie"], "a=1, b=2");
+        assert_eq!(trace.response_trailers["grpc-status"], "0");
+        assert_eq!(
+            trace.request_body.as_deref(),
+            Some(&b"{\"name\":\"a\"}"[..])
+        );
+        assert_eq!(trace.response_body.as_deref(), Some(&b"{\"id\":1}"[..]));
+        assert_eq!(
+            trace.timestamp,
+            UNIX_EPOCH + Duration::from_secs(1_714_564_800)
+        );
+        assert_eq!(trace.duration, Duration::from_millis(40));
+        assert_eq!(trace.dest_addr.as_deref(), Some("10.0.0.5:8443"));
+        assert_eq!(trace.source_addr.as_deref(), Some("[::1]:5000"));
+    }
+
+    #[test]
+    fn test_drain_documents() {
+        let mut buf =
+            b"{\"a\":1}\n{\"b\":\n  2}\n[10/Oct/2000] text line\n{\"c\":3}\n{\"d\"".to_vec();
+        let documents = drain_documents(&mut buf);
+        assert_eq!(
+            documents,
+            vec![json!({"a": 1}), json!({"b": 2}), json!({"c": 3})]
+        );
+        assert_eq!(buf.trim_ascii_start(), b"{\"d\"");
+
+        buf.extend_from_slice(b":4}");
+        assert_eq!(drain_documents(&mut buf), vec![json!({"d": 4})]);
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_parse_clf_time() {
+        assert_eq!(
+            parse_clf_time("01/May/2024:12:00:00 +0000"),
+            Some(UNIX_EPOCH + Duration::from_secs(1_714_564_800))
+        );
+        assert_eq!(parse_clf_time("01/Foo/2024:12:00:00 +0000"), None);
+    }
+
+    fn entry(path: &str) -> String {
+        format!(
+            "{}\n",
+            json!({ "method": "GET", "path": path, "response_code": 200 })
+        )
+    }
+
+    async fn next_url(rx: &mut mpsc::Receiver<TraceEvent>) -> String {
+        let event = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .expect("no trace within 5 s")
+            .unwrap();
+        match event {
+            TraceEvent::Http(trace) => trace.url,
+            other => panic!("expected an HTTP trace, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_follows_log_from_its_end_and_across_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("access.log");
+        std::fs::write(&path, entry("/old")).unwrap();
+
+        let mut backend = AccessLogCaptureBackend::new(path.clone());
+        let mut rx = backend.start().unwrap();
+        tokio::time::sleep(POLL_INTERVAL).await;
+
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap();
+        file.write_all(entry("/new").as_bytes()).unwrap();
+        assert_eq!(next_url(&mut rx).await, "http://unknown/new");
+
+        // Rotate: the old file moves away, a new one takes its name.
+        std::fs::rename(&path, dir.path().join("access.log.1")).unwrap();
+        file.write_all(entry("/late").as_bytes()).unwrap();
+        std::fs::write(&path, entry("/rotated")).unwrap();
+        assert_eq!(next_url(&mut rx).await, "http://unknown/late");
+        assert_eq!(next_url(&mut rx).await, "http://unknown/rotated");
+
+        backend.stop().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_reads_new_tap_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let tap = |path: &str| {
+            json!({ "http_buffered_trace": {
+                "request": { "headers": [
+                    { "key": ":method", "value": "GET" },
+                    { "key": ":path", "value": path },
+                    { "key": ":authority", "value": "svc" }
+                ] },
+                "response": { "headers": [{ "key": ":status", "value": "200" }] }
+            } })
+            .to_string()
+        };
+        std::fs::write(dir.path().join("tap_1.json"), tap("/before")).unwrap();
+
+        let mut backend = AccessLogCaptureBackend::new(dir.path().to_path_buf());
+        let mut rx = backend.start().unwrap();
+        tokio::time::sleep(POLL_INTERVAL * 2).await;
+        std::fs::write(dir.path().join("tap_2.pb"), "binary").unwrap();
+        std::fs::write(dir.path().join("tap_3.json"), tap("/after")).unwrap();
+        assert_eq!(next_url(&mut rx).await, "http://svc/after");
+
+        backend.stop().unwrap();
+    }
+}
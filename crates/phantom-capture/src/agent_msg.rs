@@ -0,0 +1,853 @@
+//! Agent message format — cross-platform.
+//!
+//! The messages phantom-agent sends the ldpreload backend, which are also
+//! phantom's ingestion protocol for agents outside this repository (see
+//! `docs/ingest-protocol.md` and the [`ingest`](crate::ingest) backend).
+//! Each message is one JSON object — or MessagePack map — describing an HTTP
+//! exchange; messages with a `kind` tag describe another protocol's call
+//! ([`ProtocolTrace`]), an agent's health ([`AgentStats`]) or a note for the
+//! log. Unknown fields are ignored and unknown kinds skipped, so agents may
+//! send more than phantom reads.
+
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use base64::Engine as _;
+use base64::engine::general_purpose::STANDARD as B64;
+use phantom_core::capture::{AgentStats, TraceEvent};
+use phantom_core::grpc::GrpcTrace;
+use phantom_core::kafka::KafkaTrace;
+use phantom_core::postgres::{PostgresError, PostgresTrace};
+use phantom_core::protocol::ProtocolTrace;
+use phantom_core::redis::{RedisReplyType, RedisTrace};
+use phantom_core::sse::SseEvent;
+use phantom_core::thrift::{ThriftMessageType, ThriftProtocol, ThriftTrace};
+use phantom_core::trace::{
+    BodyEncoding, HttpMethod, HttpTrace, ProcessInfo, SpanId, TimingPhases, TlsInfo, TraceId,
+    parse_traceparent,
+};
+use phantom_core::websocket::{WebSocketDirection, WebSocketFrame, WebSocketOpcode};
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+use tracing::{debug, warn};
+
+use crate::decode::{MAX_DECODED_SIZE, decompress_body};
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Message format (must match phantom-agent's TraceMsg)
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[derive(serde::Deserialize)]
+struct AgentTrace {
+    method: String,
+    url: String,
+    status_code: u16,
+    request_headers: HashMap<String, String>,
+    response_headers: HashMap<String, String>,
+    request_body_b64: Option<WireBytes>,
+    response_body_b64: Option<WireBytes>,
+    #[serde(default)]
+    request_body_file: Option<String>,
+    #[serde(default)]
+    response_body_file: Option<String>,
+    duration_ms: u64,
+    timestamp_ms: u64,
+    dest_addr: Option<String>,
+    #[serde(default)]
+    protocol_version: Option<String>,
+    #[serde(default)]
+    connection_id: Option<String>,
+    #[serde(default)]
+    pid: Option<u32>,
+    #[serde(default)]
+    comm: Option<String>,
+    #[serde(default)]
+    tid: Option<u32>,
+    #[serde(default)]
+    tls: Option<TlsInfo>,
+    #[serde(default)]
+    timing: Option<AgentTiming>,
+    #[serde(default)]
+    request_size: Option<u64>,
+    #[serde(default)]
+    response_size: Option<u64>,
+    /// Ids of a `traceparent` the agent inserted (`PHANTOM_PROPAGATE=1`).
+    #[serde(default)]
+    trace_id: Option<String>,
+    #[serde(default)]
+    span_id: Option<String>,
+    #[serde(default)]
+    response_trailers: HashMap<String, String>,
+}
+
+#[derive(serde::Deserialize)]
+struct AgentTiming {
+    #[serde(default)]
+    connect_ms: Option<u64>,
+    #[serde(default)]
+    tls_handshake_ms: Option<u64>,
+    #[serde(default)]
+    ttfb_ms: Option<u64>,
+}
+
+fn agent_timing(t: AgentTiming) -> TimingPhases {
+    TimingPhases {
+        dns: None,
+        connect: t.connect_ms.map(Duration::from_millis),
+        tls_handshake: t.tls_handshake_ms.map(Duration::from_millis),
+        ttfb: t.ttfb_ms.map(Duration::from_millis),
+    }
+}
+
+/// A note from the agent about its own operation (e.g. evicted connections),
+/// logged rather than stored.
+#[derive(serde::Deserialize)]
+struct AgentDiagnostic {
+    message: String,
+    #[serde(default)]
+    pid: Option<u32>,
+    #[serde(default)]
+    comm: Option<String>,
+}
+
+/// The agent's periodic `agent_stats` heartbeat.
+#[derive(serde::Deserialize)]
+struct AgentStatsMsg {
+    pid: u32,
+    #[serde(default)]
+    comm: String,
+    #[serde(default)]
+    hooks: Vec<String>,
+    sent: u64,
+    dropped: u64,
+    errors: u64,
+    #[serde(default)]
+    connections: u64,
+    timestamp_ms: u64,
+}
+
+/// Just the `kind` tag: absent for HTTP traces, set for other protocols.
+#[derive(serde::Deserialize)]
+struct AgentMsgKind {
+    #[serde(default)]
+    kind: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct AgentThriftTrace {
+    method: String,
+    seq_id: i32,
+    message_type: ThriftMessageType,
+    protocol: ThriftProtocol,
+    framed: bool,
+    duration_ms: u64,
+    timestamp_ms: u64,
+}
+
+#[derive(serde::Deserialize)]
+struct AgentPostgresTrace {
+    query: String,
+    #[serde(default)]
+    command: Option<String>,
+    #[serde(default)]
+    rows: Option<u64>,
+    #[serde(default)]
+    error: Option<PostgresError>,
+    #[serde(default)]
+    database: Option<String>,
+    #[serde(default)]
+    user: Option<String>,
+    #[serde(default)]
+    dest_addr: Option<String>,
+    duration_ms: u64,
+    timestamp_ms: u64,
+}
+
+#[derive(serde::Deserialize)]
+struct AgentRedisTrace {
+    command: String,
+    #[serde(default)]
+    key: Option<String>,
+    #[serde(default)]
+    args: Vec<String>,
+    arg_count: u32,
+    reply_type: RedisReplyType,
+    #[serde(default)]
+    error: Option<String>,
+    #[serde(default)]
+    dest_addr: Option<String>,
+    duration_ms: u64,
+    timestamp_ms: u64,
+}
+
+#[derive(serde::Deserialize)]
+struct AgentGrpcTrace {
+    service: String,
+    method: String,
+    #[serde(default)]
+    authority: Option<String>,
+    #[serde(default)]
+    status: Option<u32>,
+    #[serde(default)]
+    message: Option<String>,
+    #[serde(default)]
+    request_messages: u32,
+    #[serde(default)]
+    response_messages: u32,
+    #[serde(default)]
+    dest_addr: Option<String>,
+    duration_ms: u64,
+    timestamp_ms: u64,
+}
+
+#[derive(serde::Deserialize)]
+struct AgentKafkaTrace {
+    api_key: i16,
+    api: String,
+    api_version: i16,
+    correlation_id: i32,
+    #[serde(default)]
+    client_id: Option<String>,
+    #[serde(default)]
+    topics: Vec<String>,
+    #[serde(default)]
+    error_code: Option<i16>,
+    #[serde(default)]
+    no_response: bool,
+    #[serde(default)]
+    dest_addr: Option<String>,
+    duration_ms: u64,
+    timestamp_ms: u64,
+}
+
+#[derive(serde::Deserialize)]
+struct AgentSseEvent {
+    url: String,
+    #[serde(default)]
+    event: Option<String>,
+    #[serde(default)]
+    id: Option<String>,
+    data: String,
+    data_bytes: u64,
+    #[serde(default)]
+    retry_ms: Option<u64>,
+    #[serde(default)]
+    connection_id: Option<String>,
+    elapsed_ms: u64,
+    timestamp_ms: u64,
+}
+
+#[derive(serde::Deserialize)]
+struct AgentWebSocketFrame {
+    url: String,
+    direction: WebSocketDirection,
+    opcode: WebSocketOpcode,
+    fin: bool,
+    masked: bool,
+    #[serde(default)]
+    compressed: bool,
+    payload_len: u64,
+    #[serde(default)]
+    preview_b64: Option<WireBytes>,
+    #[serde(default)]
+    close_code: Option<u16>,
+    #[serde(default)]
+    connection_id: Option<String>,
+    #[serde(default)]
+    dest_addr: Option<String>,
+    timestamp_ms: u64,
+}
+
+fn parse_method(s: &str) -> HttpMethod {
+    match s.to_uppercase().as_str() {
+        "GET" => HttpMethod::Get,
+        "POST" => HttpMethod::Post,
+        "PUT" => HttpMethod::Put,
+        "DELETE" => HttpMethod::Delete,
+        "PATCH" => HttpMethod::Patch,
+        "HEAD" => HttpMethod::Head,
+        "OPTIONS" => HttpMethod::Options,
+        "TRACE" => HttpMethod::Trace,
+        "CONNECT" => HttpMethod::Connect,
+        _ => HttpMethod::Get,
+    }
+}
+
+/// Body or payload bytes: base64 text in JSON messages, raw bytes in
+/// MessagePack ones (the `_b64` field names predate the binary encoding).
+struct WireBytes(Vec<u8>);
+
+impl<'de> serde::Deserialize<'de> for WireBytes {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl serde::de::Visitor<'_> for Visitor {
+            type Value = WireBytes;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("base64 text or bytes")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<WireBytes, E> {
+                B64.decode(v).map(WireBytes).map_err(E::custom)
+            }
+
+            fn visit_bytes<E: serde::de::Error>(self, v: &[u8]) -> Result<WireBytes, E> {
+                Ok(WireBytes(v.to_vec()))
+            }
+
+            fn visit_byte_buf<E: serde::de::Error>(self, v: Vec<u8>) -> Result<WireBytes, E> {
+                Ok(WireBytes(v))
+            }
+        }
+
+        deserializer.deserialize_any(Visitor)
+    }
+}
+
+fn decode_body(bytes: Option<WireBytes>) -> Option<Vec<u8>> {
+    bytes.map(|b| b.0)
+}
+
+/// Decodes an agent message. JSON messages are objects and start with `{`;
+/// anything else is MessagePack, whose maps never do.
+fn decode<T: serde::de::DeserializeOwned>(data: &[u8]) -> Result<T, String> {
+    if data.first() == Some(&b'{') {
+        serde_json::from_slice(data).map_err(|e| e.to_string())
+    } else {
+        rmp_serde::from_slice(data).map_err(|e| e.to_string())
+    }
+}
+
+/// Decompresses a body per its `Content-Encoding`. The agent caps the
+/// compressed bytes, so a long body decodes to a prefix.
+fn decompress(
+    headers: &HashMap<String, String>,
+    body: Option<Vec<u8>>,
+) -> (Option<Vec<u8>>, Option<BodyEncoding>) {
+    match body
+        .as_deref()
+        .and_then(|b| decompress_body(headers, b, MAX_DECODED_SIZE))
+    {
+        Some((decoded, encoding)) => (Some(decoded), Some(encoding)),
+        None => (body, None),
+    }
+}
+
+fn rand_bytes<const N: usize>() -> [u8; N] {
+    let mut buf = [0u8; N];
+    buf.iter_mut().for_each(|b| *b = rand::random());
+    buf
+}
+
+fn agent_timestamp(timestamp_ms: u64) -> SystemTime {
+    let timestamp = SystemTime::UNIX_EPOCH + Duration::from_millis(timestamp_ms);
+    // Guard against timestamps before UNIX_EPOCH (shouldn't happen but be safe).
+    if timestamp < UNIX_EPOCH {
+        SystemTime::now()
+    } else {
+        timestamp
+    }
+}
+
+/// Trace, span and parent span ids for an agent trace: those of the
+/// `traceparent` the agent inserted, else a child of the one the application
+/// sent itself, else fresh ones.
+fn trace_ids(a: &AgentTrace) -> (TraceId, SpanId, Option<SpanId>) {
+    let inserted = a
+        .trace_id
+        .as_deref()
+        .and_then(TraceId::from_hex)
+        .zip(a.span_id.as_deref().and_then(SpanId::from_hex));
+    if let Some((trace_id, span_id)) = inserted {
+        return (trace_id, span_id, None);
+    }
+    let sent = a
+        .request_headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("traceparent"))
+        .and_then(|(_, value)| parse_traceparent(value));
+    match sent {
+        Some((trace_id, parent)) => (trace_id, SpanId(rand_bytes::<8>()), Some(parent)),
+        None => (TraceId(rand_bytes::<16>()), SpanId(rand_bytes::<8>()), None),
+    }
+}
+
+fn agent_trace_to_http_trace(a: AgentTrace) -> HttpTrace {
+    let (trace_id, span_id, parent_span_id) = trace_ids(&a);
+    let timestamp = agent_timestamp(a.timestamp_ms);
+    let (request_body, request_body_encoding) =
+        decompress(&a.request_headers, decode_body(a.request_body_b64));
+    let (response_body, response_body_encoding) =
+        decompress(&a.response_headers, decode_body(a.response_body_b64));
+
+    HttpTrace {
+        span_id,
+        trace_id,
+        parent_span_id,
+        method: parse_method(&a.method),
+        url: a.url,
+        request_headers: a.request_headers,
+        request_body,
+        status_code: a.status_code,
+        response_headers: a.response_headers,
+        response_body,
+        timestamp,
+        duration: Duration::from_millis(a.duration_ms),
+        source_addr: None,
+        dest_addr: a.dest_addr,
+        protocol_version: a.protocol_version.unwrap_or_else(|| "HTTP/1.1".to_string()),
+        request_body_file: a.request_body_file,
+        response_body_file: a.response_body_file,
+        connection_id: a.connection_id,
+        process: a.pid.map(|pid| ProcessInfo {
+            pid,
+            comm: a.comm.unwrap_or_default(),
+        }),
+        response_trailers: a.response_trailers,
+        request_body_encoding,
+        response_body_encoding,
+        elasticsearch: None,
+        soap: None,
+        graphql: None,
+        thread_id: a.tid,
+        tls: a.tls,
+        timing: a.timing.map(agent_timing),
+        request_size: a.request_size,
+        response_size: a.response_size,
+        fault: None,
+        replay: None,
+    }
+}
+
+fn agent_thrift_to_trace(a: AgentThriftTrace) -> ThriftTrace {
+    ThriftTrace {
+        method: a.method,
+        seq_id: a.seq_id,
+        message_type: a.message_type,
+        protocol: a.protocol,
+        framed: a.framed,
+        timestamp: agent_timestamp(a.timestamp_ms),
+        duration: Duration::from_millis(a.duration_ms),
+        dest_addr: None,
+    }
+}
+
+fn agent_postgres_to_trace(a: AgentPostgresTrace) -> PostgresTrace {
+    PostgresTrace {
+        query: a.query,
+        command: a.command,
+        rows: a.rows,
+        error: a.error,
+        database: a.database,
+        user: a.user,
+        timestamp: agent_timestamp(a.timestamp_ms),
+        duration: Duration::from_millis(a.duration_ms),
+        dest_addr: a.dest_addr,
+    }
+}
+
+fn agent_redis_to_trace(a: AgentRedisTrace) -> RedisTrace {
+    RedisTrace {
+        command: a.command,
+        key: a.key,
+        args: a.args,
+        arg_count: a.arg_count,
+        reply_type: a.reply_type,
+        error: a.error,
+        timestamp: agent_timestamp(a.timestamp_ms),
+        duration: Duration::from_millis(a.duration_ms),
+        dest_addr: a.dest_addr,
+    }
+}
+
+fn agent_grpc_to_trace(a: AgentGrpcTrace) -> GrpcTrace {
+    GrpcTrace {
+        service: a.service,
+        method: a.method,
+        authority: a.authority,
+        status: a.status,
+        message: a.message,
+        request_messages: a.request_messages,
+        response_messages: a.response_messages,
+        timestamp: agent_timestamp(a.timestamp_ms),
+        duration: Duration::from_millis(a.duration_ms),
+        dest_addr: a.dest_addr,
+    }
+}
+
+fn agent_kafka_to_trace(a: AgentKafkaTrace) -> KafkaTrace {
+    KafkaTrace {
+        api_key: a.api_key,
+        api: a.api,
+        api_version: a.api_version,
+        correlation_id: a.correlation_id,
+        client_id: a.client_id,
+        topics: a.topics,
+        error_code: a.error_code,
+        no_response: a.no_response,
+        timestamp: agent_timestamp(a.timestamp_ms),
+        duration: Duration::from_millis(a.duration_ms),
+        dest_addr: a.dest_addr,
+    }
+}
+
+fn agent_websocket_to_frame(a: AgentWebSocketFrame) -> WebSocketFrame {
+    WebSocketFrame {
+        url: a.url,
+        direction: a.direction,
+        opcode: a.opcode,
+        fin: a.fin,
+        masked: a.masked,
+        compressed: a.compressed,
+        payload_len: a.payload_len,
+        preview: decode_body(a.preview_b64).unwrap_or_default(),
+        close_code: a.close_code,
+        timestamp: agent_timestamp(a.timestamp_ms),
+        connection_id: a.connection_id,
+        dest_addr: a.dest_addr,
+    }
+}
+
+fn agent_sse_to_event(a: AgentSseEvent) -> SseEvent {
+    SseEvent {
+        url: a.url,
+        event: a.event,
+        id: a.id,
+        data: a.data,
+        data_bytes: a.data_bytes,
+        retry_ms: a.retry_ms,
+        connection_id: a.connection_id,
+        timestamp: agent_timestamp(a.timestamp_ms),
+        elapsed: Duration::from_millis(a.elapsed_ms),
+    }
+}
+
+fn agent_stats(a: AgentStatsMsg) -> AgentStats {
+    AgentStats {
+        process: ProcessInfo {
+            comm: a.comm,
+            pid: a.pid,
+        },
+        hooks: a.hooks,
+        sent: a.sent,
+        dropped: a.dropped,
+        errors: a.errors,
+        connections: a.connections,
+        timestamp: agent_timestamp(a.timestamp_ms),
+    }
+}
+
+/// Where decoded agent messages go.
+#[derive(Clone)]
+pub(crate) struct AgentSink {
+    /// The backend receiving the messages, for log lines.
+    pub(crate) origin: &'static str,
+    pub(crate) events: mpsc::Sender<TraceEvent>,
+}
+
+/// Decode one agent message and forward it as a [`TraceEvent`].
+pub(crate) fn dispatch_agent_msg(data: &[u8], sink: &AgentSink) -> Result<(), String> {
+    let origin = sink.origin;
+    let kind = decode::<AgentMsgKind>(data)?.kind;
+    let event: TraceEvent = match kind.as_deref() {
+        None => {
+            let trace = agent_trace_to_http_trace(decode(data)?);
+            debug!(url = %trace.url, "captured via {origin}");
+            trace.into()
+        }
+        Some("thrift") => {
+            let trace = agent_thrift_to_trace(decode(data)?);
+            debug!(method = %trace.method, "captured thrift call via {origin}");
+            ProtocolTrace::Thrift(trace).into()
+        }
+        Some("postgres") => {
+            let trace = agent_postgres_to_trace(decode(data)?);
+            debug!(command = ?trace.command, "captured postgres query via {origin}");
+            ProtocolTrace::Postgres(trace).into()
+        }
+        Some("redis") => {
+            let trace = agent_redis_to_trace(decode(data)?);
+            debug!(command = %trace.command, "captured redis command via {origin}");
+            ProtocolTrace::Redis(trace).into()
+        }
+        Some("kafka") => {
+            let trace = agent_kafka_to_trace(decode(data)?);
+            debug!(api = %trace.api, "captured kafka request via {origin}");
+            ProtocolTrace::Kafka(trace).into()
+        }
+        Some("grpc") => {
+            let trace = agent_grpc_to_trace(decode(data)?);
+            debug!(method = %trace.full_method(), "captured grpc call via {origin}");
+            ProtocolTrace::Grpc(trace).into()
+        }
+        Some("websocket") => {
+            let frame = agent_websocket_to_frame(decode(data)?);
+            debug!(url = %frame.url, opcode = ?frame.opcode, "captured websocket frame via {origin}");
+            ProtocolTrace::WebSocket(frame).into()
+        }
+        Some("sse") => {
+            let event = agent_sse_to_event(decode(data)?);
+            debug!(url = %event.url, "captured server-sent event via {origin}");
+            ProtocolTrace::Sse(event).into()
+        }
+        Some("diagnostic") => {
+            let diag: AgentDiagnostic = decode(data)?;
+            warn!(pid = ?diag.pid, comm = ?diag.comm, "{origin} agent: {}", diag.message);
+            return Ok(());
+        }
+        Some("agent_stats") => {
+            let stats = agent_stats(decode(data)?);
+            debug!(
+                pid = stats.process.pid,
+                dropped = stats.dropped,
+                "{origin} agent heartbeat"
+            );
+            stats.into()
+        }
+        Some(other) => {
+            debug!("{origin}: ignoring agent message of kind {other:?}");
+            return Ok(());
+        }
+    };
+    // A closed receiver means the capture is stopping.
+    if let Err(TrySendError::Full(_)) = sink.events.try_send(event) {
+        warn!("{origin} trace channel full, dropping");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Serializes as raw bytes, like the agent's `WireBytes` in MessagePack.
+    struct RawBytes(&'static [u8]);
+
+    impl serde::Serialize for RawBytes {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_bytes(self.0)
+        }
+    }
+
+    #[derive(serde::Serialize)]
+    struct MsgpackTrace {
+        method: &'static str,
+        url: &'static str,
+        status_code: u16,
+        request_headers: HashMap<String, String>,
+        response_headers: HashMap<String, String>,
+        response_body_b64: RawBytes,
+        duration_ms: u64,
+        timestamp_ms: u64,
+    }
+
+    fn make_sink() -> (AgentSink, mpsc::Receiver<TraceEvent>) {
+        let (events, events_rx) = mpsc::channel(8);
+        let sink = AgentSink {
+            origin: "test",
+            events,
+        };
+        (sink, events_rx)
+    }
+
+    /// The HTTP trace dispatched last.
+    fn next_trace(events_rx: &mut mpsc::Receiver<TraceEvent>) -> HttpTrace {
+        match events_rx.try_recv().unwrap() {
+            TraceEvent::Http(trace) => *trace,
+            other => panic!("expected an HTTP trace, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_msgpack_trace_carries_raw_body() {
+        let (sink, mut events_rx) = make_sink();
+        let msg = rmp_serde::to_vec_named(&MsgpackTrace {
+            method: "POST",
+            url: "http://example.com/upload",
+            status_code: 201,
+            request_headers: HashMap::new(),
+            response_headers: HashMap::new(),
+            response_body_b64: RawBytes(b"\x00\xffcreated"),
+            duration_ms: 3,
+            timestamp_ms: 0,
+        })
+        .unwrap();
+
+        dispatch_agent_msg(&msg, &sink).unwrap();
+        let trace = next_trace(&mut events_rx);
+        assert_eq!(trace.method, HttpMethod::Post);
+        assert_eq!(trace.status_code, 201);
+        assert_eq!(
+            trace.response_body.as_deref(),
+            Some(&b"\x00\xffcreated"[..])
+        );
+    }
+
+    #[test]
+    fn test_json_trace_body_is_base64() {
+        let (sink, mut events_rx) = make_sink();
+        let msg = br#"{"method":"GET","url":"http://example.com/","status_code":200,
+            "request_headers":{},"response_headers":{},"response_body_b64":"aGk=",
+            "duration_ms":1,"timestamp_ms":0}"#;
+
+        dispatch_agent_msg(msg, &sink).unwrap();
+        let trace = next_trace(&mut events_rx);
+        assert_eq!(trace.response_body.as_deref(), Some(&b"hi"[..]));
+    }
+
+    #[test]
+    fn test_trace_carries_process_and_thread() {
+        let (sink, mut events_rx) = make_sink();
+        let msg = br#"{"method":"GET","url":"http://example.com/","status_code":200,
+            "request_headers":{},"response_headers":{},"duration_ms":1,"timestamp_ms":0,
+            "pid":42,"tid":43,"comm":"worker"}"#;
+
+        dispatch_agent_msg(msg, &sink).unwrap();
+        let trace = next_trace(&mut events_rx);
+        assert_eq!(
+            trace.process,
+            Some(ProcessInfo {
+                comm: "worker".to_string(),
+                pid: 42,
+            })
+        );
+        assert_eq!(trace.thread_id, Some(43));
+    }
+
+    #[test]
+    fn test_trace_carries_tls_details() {
+        let (sink, mut events_rx) = make_sink();
+        let msg = br#"{"method":"GET","url":"https://example.com/","status_code":200,
+            "request_headers":{},"response_headers":{},"duration_ms":1,"timestamp_ms":0,
+            "tls":{"server_name":"example.com","version":"TLSv1.3","alpn":"h2"}}"#;
+
+        dispatch_agent_msg(msg, &sink).unwrap();
+        let trace = next_trace(&mut events_rx);
+        assert_eq!(
+            trace.tls,
+            Some(TlsInfo {
+                server_name: Some("example.com".to_string()),
+                version: Some("TLSv1.3".to_string()),
+                cipher: None,
+                alpn: Some("h2".to_string()),
+            })
+        );
+    }
+
+    #[test]
+    fn test_trace_uses_propagated_ids() {
+        let (sink, mut events_rx) = make_sink();
+        let msg = br#"{"method":"GET","url":"http://example.com/","status_code":200,
+            "request_headers":{},"response_headers":{},"duration_ms":1,"timestamp_ms":0,
+            "trace_id":"4bf92f3577b34da6a3ce929d0e0e4736","span_id":"00f067aa0ba902b7"}"#;
+
+        dispatch_agent_msg(msg, &sink).unwrap();
+        let trace = next_trace(&mut events_rx);
+        assert_eq!(
+            trace.trace_id.to_string(),
+            "4bf92f3577b34da6a3ce929d0e0e4736"
+        );
+        assert_eq!(trace.span_id.to_string(), "00f067aa0ba902b7");
+        assert_eq!(trace.parent_span_id, None);
+    }
+
+    #[test]
+    fn test_trace_joins_the_applications_traceparent() {
+        let (sink, mut events_rx) = make_sink();
+        let msg = br#"{"method":"GET","url":"http://example.com/","status_code":200,
+            "request_headers":{"Traceparent":"00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"},
+            "response_headers":{},"duration_ms":1,"timestamp_ms":0}"#;
+
+        dispatch_agent_msg(msg, &sink).unwrap();
+        let trace = next_trace(&mut events_rx);
+        assert_eq!(
+            trace.trace_id.to_string(),
+            "4bf92f3577b34da6a3ce929d0e0e4736"
+        );
+        assert_eq!(
+            trace.parent_span_id.map(|id| id.to_string()).as_deref(),
+            Some("00f067aa0ba902b7")
+        );
+        assert_ne!(trace.span_id.to_string(), "00f067aa0ba902b7");
+    }
+
+    #[test]
+    fn test_trace_carries_timing_phases() {
+        let (sink, mut events_rx) = make_sink();
+        let msg = br#"{"method":"GET","url":"https://example.com/","status_code":200,
+            "request_headers":{},"response_headers":{},"duration_ms":50,"timestamp_ms":0,
+            "timing":{"connect_ms":3,"tls_handshake_ms":12,"ttfb_ms":40}}"#;
+
+        dispatch_agent_msg(msg, &sink).unwrap();
+        let trace = next_trace(&mut events_rx);
+        assert_eq!(
+            trace.timing,
+            Some(TimingPhases {
+                dns: None,
+                connect: Some(Duration::from_millis(3)),
+                tls_handshake: Some(Duration::from_millis(12)),
+                ttfb: Some(Duration::from_millis(40)),
+            })
+        );
+    }
+
+    #[test]
+    fn test_trace_carries_wire_sizes() {
+        let (sink, mut events_rx) = make_sink();
+        let msg = br#"{"method":"POST","url":"http://example.com/upload","status_code":201,
+            "request_headers":{},"response_headers":{},"duration_ms":5,"timestamp_ms":0,
+            "request_size":70123,"response_size":98}"#;
+
+        dispatch_agent_msg(msg, &sink).unwrap();
+        let trace = next_trace(&mut events_rx);
+        assert_eq!(trace.request_size, Some(70123));
+        assert_eq!(trace.response_size, Some(98));
+    }
+
+    #[test]
+    fn test_grpc_call_becomes_protocol_trace() {
+        let (sink, mut events_rx) = make_sink();
+        let msg = br#"{"kind":"grpc","service":"helloworld.Greeter","method":"SayHello",
+            "status":5,"message":"no such user","request_messages":1,
+            "duration_ms":8,"timestamp_ms":1000}"#;
+
+        dispatch_agent_msg(msg, &sink).unwrap();
+        let Ok(TraceEvent::Protocol(ProtocolTrace::Grpc(trace))) = events_rx.try_recv() else {
+            panic!("expected a grpc trace");
+        };
+        assert_eq!(trace.full_method(), "helloworld.Greeter/SayHello");
+        assert_eq!(trace.status_name(), Some("NOT_FOUND"));
+        assert_eq!(trace.message.as_deref(), Some("no such user"));
+        assert_eq!(trace.response_messages, 0);
+        assert_eq!(trace.duration, Duration::from_millis(8));
+    }
+
+    #[test]
+    fn test_diagnostics_are_logged_not_forwarded() {
+        let (sink, mut events_rx) = make_sink();
+        let msg = br#"{"kind":"diagnostic","message":"dropped state of 3 connection(s)",
+            "pid":42,"comm":"crawler","timestamp_ms":0}"#;
+
+        dispatch_agent_msg(msg, &sink).unwrap();
+        assert!(events_rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_agent_stats_become_events() {
+        let (sink, mut events_rx) = make_sink();
+        let msg = br#"{"kind":"agent_stats","pid":42,"comm":"crawler","hooks":["socket","openssl"],
+            "sent":10,"dropped":2,"errors":1,"connections":3,"timestamp_ms":1000}"#;
+
+        dispatch_agent_msg(msg, &sink).unwrap();
+        let Ok(TraceEvent::AgentStats(stats)) = events_rx.try_recv() else {
+            panic!("expected agent stats");
+        };
+        assert_eq!(stats.process.pid, 42);
+        assert_eq!(stats.process.comm, "crawler");
+        assert_eq!(stats.hooks, ["socket", "openssl"]);
+        assert_eq!((stats.sent, stats.dropped, stats.errors), (10, 2, 1));
+        assert_eq!(stats.timestamp, UNIX_EPOCH + Duration::from_secs(1));
+        assert!(events_rx.try_recv().is_err());
+    }
+}
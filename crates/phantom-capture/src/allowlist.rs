@@ -0,0 +1,146 @@
+use std::net::IpAddr;
+
+/// An IP network in CIDR notation, e.g. `192.168.1.0/24` or `fd00::/8`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CidrRange {
+    pub addr: IpAddr,
+    pub prefix_len: u8,
+}
+
+impl CidrRange {
+    /// Returns true if `ip` lies inside this network. IPv4-mapped IPv6
+    /// addresses (`::ffff:a.b.c.d`, as seen on a dual-stack `::` listener)
+    /// match IPv4 ranges.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - u32::from(self.prefix_len))
+                    .unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - u32::from(self.prefix_len))
+                    .unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Networks whose clients may use the proxy. Empty allows everyone;
+/// loopback clients are always allowed, since the SOCKS and transparent
+/// front ends reach the proxy listener over loopback.
+#[derive(Clone, Debug, Default)]
+pub struct ClientAllowlist {
+    pub ranges: Vec<CidrRange>,
+}
+
+impl ClientAllowlist {
+    pub fn allows(&self, ip: IpAddr) -> bool {
+        self.ranges.is_empty()
+            || ip.to_canonical().is_loopback()
+            || self.ranges.iter().any(|range| range.contains(ip))
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// CLI spec parsing
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Parse a CIDR range such as `192.168.1.0/24`, `10.0.0.0/8` or `fd00::/8`.
+/// A bare address is a single host (`/32` or `/128`).
+pub fn parse_cidr(s: &str) -> Result<CidrRange, String> {
+    let s = s.trim();
+    let (addr, prefix_len) = match s.split_once('/') {
+        Some((addr, prefix)) => (addr, Some(prefix)),
+        None => (s, None),
+    };
+    let addr: IpAddr = addr
+        .parse()
+        .map_err(|_| format!("invalid IP address {addr:?}"))?;
+    let max_len = if addr.is_ipv4() { 32 } else { 128 };
+    let prefix_len = match prefix_len {
+        Some(prefix) => prefix
+            .parse::<u8>()
+            .ok()
+            .filter(|&len| len <= max_len)
+            .ok_or_else(|| format!("invalid prefix length {prefix:?} (0-{max_len})"))?,
+        None => max_len,
+    };
+    Ok(CidrRange { addr, prefix_len })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_cidr() {
+        assert_eq!(
+            parse_cidr("192.168.1.0/24").unwrap(),
+            CidrRange {
+                addr: ip("192.168.1.0"),
+                prefix_len: 24
+            }
+        );
+        assert_eq!(parse_cidr("10.1.2.3").unwrap().prefix_len, 32);
+        assert_eq!(parse_cidr("fd00::/8").unwrap().prefix_len, 8);
+        assert_eq!(parse_cidr("::1").unwrap().prefix_len, 128);
+    }
+
+    #[test]
+    fn test_parse_cidr_rejects_invalid() {
+        assert!(parse_cidr("192.168.1.0/33").is_err());
+        assert!(parse_cidr("fd00::/129").is_err());
+        assert!(parse_cidr("192.168.1/24").is_err());
+        assert!(parse_cidr("phone.local").is_err());
+        assert!(parse_cidr("10.0.0.0/").is_err());
+    }
+
+    #[test]
+    fn test_cidr_contains() {
+        let lan = parse_cidr("192.168.1.0/24").unwrap();
+        assert!(lan.contains(ip("192.168.1.42")));
+        assert!(!lan.contains(ip("192.168.2.42")));
+        assert!(!lan.contains(ip("fd00::1")));
+
+        let host = parse_cidr("10.0.0.7").unwrap();
+        assert!(host.contains(ip("10.0.0.7")));
+        assert!(!host.contains(ip("10.0.0.8")));
+
+        let any = parse_cidr("0.0.0.0/0").unwrap();
+        assert!(any.contains(ip("203.0.113.9")));
+
+        let ula = parse_cidr("fd00::/8").unwrap();
+        assert!(ula.contains(ip("fd12:3456::1")));
+        assert!(!ula.contains(ip("fe80::1")));
+    }
+
+    #[test]
+    fn test_cidr_contains_ipv4_mapped() {
+        let lan = parse_cidr("192.168.1.0/24").unwrap();
+        assert!(lan.contains(ip("::ffff:192.168.1.42")));
+    }
+
+    #[test]
+    fn test_allowlist() {
+        let empty = ClientAllowlist::default();
+        assert!(empty.allows(ip("203.0.113.9")));
+
+        let allowlist = ClientAllowlist {
+            ranges: vec![parse_cidr("192.168.1.0/24").unwrap()],
+        };
+        assert!(allowlist.allows(ip("192.168.1.42")));
+        assert!(!allowlist.allows(ip("192.168.2.42")));
+        assert!(allowlist.allows(ip("127.0.0.1")));
+        assert!(allowlist.allows(ip("::1")));
+        assert!(allowlist.allows(ip("::ffff:127.0.0.1")));
+    }
+}
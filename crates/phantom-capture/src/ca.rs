@@ -0,0 +1,171 @@
+//! The proxy's MITM certificate authority.
+//!
+//! HTTPS interception only works once clients trust the CA, so it is kept in
+//! the data directory (`ca.pem` and `ca-key.pem`) and reused across runs
+//! instead of being regenerated on every start.
+
+use std::io::Write;
+use std::path::Path;
+
+use hudsucker::certificate_authority::RcgenAuthority;
+use hudsucker::rcgen::{self, Certificate, CertificateParams, KeyPair};
+use phantom_core::error::CaptureError;
+
+/// File name of the CA certificate (PEM) in the data directory.
+pub const CA_CERT_FILE: &str = "ca.pem";
+/// File name of the CA private key (PEM) in the data directory.
+pub const CA_KEY_FILE: &str = "ca-key.pem";
+
+/// Leaf certificates cached by the authority.
+const LEAF_CACHE_SIZE: u64 = 1000;
+
+/// A CA certificate and its private key, both PEM-encoded.
+#[derive(Clone)]
+pub struct ProxyCa {
+    cert_pem: String,
+    key_pem: String,
+}
+
+impl ProxyCa {
+    /// Creates a new self-signed CA that is not stored anywhere.
+    pub fn generate() -> Result<Self, CaptureError> {
+        let mut params = CertificateParams::default();
+        params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        params
+            .distinguished_name
+            .push(rcgen::DnType::CommonName, "Phantom Proxy CA");
+        params
+            .distinguished_name
+            .push(rcgen::DnType::OrganizationName, "Phantom");
+
+        let key_pair = KeyPair::generate()
+            .map_err(|e| CaptureError::Other(format!("failed to generate CA key pair: {e}")))?;
+        let cert = params
+            .self_signed(&key_pair)
+            .map_err(|e| CaptureError::Other(format!("failed to self-sign CA certificate: {e}")))?;
+        Ok(Self {
+            cert_pem: cert.pem(),
+            key_pem: key_pair.serialize_pem(),
+        })
+    }
+
+    /// Loads the CA stored in `dir`, creating and storing one on first use.
+    /// The key file is only readable by its owner.
+    pub fn load_or_create(dir: &Path) -> Result<Self, CaptureError> {
+        let (cert_path, key_path) = (dir.join(CA_CERT_FILE), dir.join(CA_KEY_FILE));
+        if key_path.exists() {
+            // Fail here rather than when the proxy starts.
+            return Self::from_pem_files(&cert_path, &key_path).map_err(|e| {
+                CaptureError::Other(format!(
+                    "{e} (remove {} and {} to create a new CA)",
+                    cert_path.display(),
+                    key_path.display()
+                ))
+            });
+        }
+
+        let ca = Self::generate()?;
+        std::fs::create_dir_all(dir)
+            .map_err(|e| CaptureError::Other(format!("{}: {e}", dir.display())))?;
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        options
+            .open(&key_path)
+            .and_then(|mut file| file.write_all(ca.key_pem.as_bytes()))
+            .map_err(|e| CaptureError::Other(format!("{}: {e}", key_path.display())))?;
+        std::fs::write(&cert_path, &ca.cert_pem)
+            .map_err(|e| CaptureError::Other(format!("{}: {e}", cert_path.display())))?;
+        Ok(ca)
+    }
+
+    /// Loads an existing CA, e.g. an internal one clients already trust.
+    /// Fails unless both files parse; the key must be the certificate's.
+    pub fn from_pem_files(cert_path: &Path, key_path: &Path) -> Result<Self, CaptureError> {
+        let read = |path: &Path| {
+            std::fs::read_to_string(path)
+                .map_err(|e| CaptureError::Other(format!("{}: {e}", path.display())))
+        };
+        let ca = Self {
+            cert_pem: read(cert_path)?,
+            key_pem: read(key_path)?,
+        };
+        ca.authority()?;
+        Ok(ca)
+    }
+
+    /// The PEM-encoded CA certificate, for client trust stores.
+    pub fn cert_pem(&self) -> &str {
+        &self.cert_pem
+    }
+
+    /// The signing authority for leaf certificates.
+    pub(crate) fn authority(&self) -> Result<RcgenAuthority, CaptureError> {
+        let (cert, key_pair) = self.issuer()?;
+        Ok(RcgenAuthority::new(key_pair, cert, LEAF_CACHE_SIZE))
+    }
+
+    /// The CA certificate and key to sign leaves with. The certificate is
+    /// re-signed from the stored one: same subject and key, so leaves chain
+    /// to the certificate clients already trust.
+    pub(crate) fn issuer(&self) -> Result<(Certificate, KeyPair), CaptureError> {
+        let key_pair = KeyPair::from_pem(&self.key_pem)
+            .map_err(|e| CaptureError::Other(format!("invalid CA key: {e}")))?;
+        let cert = CertificateParams::from_ca_cert_pem(&self.cert_pem)
+            .and_then(|params| params.self_signed(&key_pair))
+            .map_err(|e| CaptureError::Other(format!("invalid CA certificate: {e}")))?;
+        Ok((cert, key_pair))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_or_create_reuses_stored_ca() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = ProxyCa::load_or_create(dir.path()).unwrap();
+        let second = ProxyCa::load_or_create(dir.path()).unwrap();
+        assert_eq!(first.cert_pem(), second.cert_pem());
+        assert!(first.cert_pem().contains("BEGIN CERTIFICATE"));
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join(CA_CERT_FILE)).unwrap(),
+            first.cert_pem()
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(dir.path().join(CA_KEY_FILE))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+
+    #[test]
+    fn test_from_pem_files_loads_existing_ca() {
+        let dir = tempfile::tempdir().unwrap();
+        let generated = ProxyCa::generate().unwrap();
+        let (cert, key) = (dir.path().join("corp.crt"), dir.path().join("corp.key"));
+        std::fs::write(&cert, generated.cert_pem()).unwrap();
+        std::fs::write(&key, &generated.key_pem).unwrap();
+
+        let ca = ProxyCa::from_pem_files(&cert, &key).unwrap();
+        assert_eq!(ca.cert_pem(), generated.cert_pem());
+        // Nothing is written to the data directory.
+        assert!(!dir.path().join(CA_CERT_FILE).exists());
+        assert!(ProxyCa::from_pem_files(&cert, &dir.path().join("missing.key")).is_err());
+    }
+
+    #[test]
+    fn test_load_or_create_rejects_corrupt_key() {
+        let dir = tempfile::tempdir().unwrap();
+        ProxyCa::load_or_create(dir.path()).unwrap();
+        std::fs::write(dir.path().join(CA_KEY_FILE), "not a key").unwrap();
+        let err = ProxyCa::load_or_create(dir.path()).err().unwrap();
+        assert!(err.to_string().contains("invalid CA key"), "{err}");
+    }
+}
@@ -0,0 +1,639 @@
+//! HTTP traces from packet capture files.
+//!
+//! A [`CaptureFile`] reads a pcap or pcapng file — as written by `tcpdump -w`
+//! or Wireshark — and runs its packets through the same TCP reassembly and
+//! HTTP/1 parsing as the live packet backends (see [`FlowCollector`]), timed
+//! by the packets' own timestamps. Ethernet (VLAN tags included), Linux
+//! cooked (`tcpdump -i any`), loopback and raw IP link types are understood;
+//! packets of other link types are skipped. Connections whose SYN is not in
+//! the file are ignored, as live.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use phantom_core::error::CaptureError;
+use phantom_core::trace::HttpTrace;
+use tracing::warn;
+
+use crate::flows::FlowCollector;
+use crate::tls::KeyLog;
+
+/// Largest record accepted; anything bigger means the file is corrupt.
+const MAX_RECORD: usize = 16 * 1024 * 1024;
+
+/// How often, in capture time, idle connections are closed.
+const EXPIRY_INTERVAL: Duration = Duration::from_secs(30);
+
+const PCAPNG_SECTION_HEADER: [u8; 4] = [0x0a, 0x0d, 0x0d, 0x0a];
+const PCAPNG_INTERFACE: u32 = 1;
+const PCAPNG_SIMPLE_PACKET: u32 = 3;
+const PCAPNG_ENHANCED_PACKET: u32 = 6;
+const PCAPNG_BYTE_ORDER: u32 = 0x1a2b_3c4d;
+const PCAPNG_OPT_TSRESOL: u16 = 9;
+
+/// How a captured frame wraps its IP packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LinkType {
+    Ethernet,
+    /// BSD loopback: a 4-byte address family.
+    Loopback,
+    /// Linux cooked capture, as from `tcpdump -i any`.
+    LinuxSll,
+    LinuxSll2,
+    Raw,
+}
+
+impl LinkType {
+    fn from_code(code: u32) -> Option<Self> {
+        match code {
+            1 => Some(Self::Ethernet),
+            0 | 108 => Some(Self::Loopback),
+            113 => Some(Self::LinuxSll),
+            276 => Some(Self::LinuxSll2),
+            // LINKTYPE_RAW, the BSDs' DLT_RAW values, LINKTYPE_IPV4/IPV6.
+            101 | 12 | 14 | 228 | 229 => Some(Self::Raw),
+            _ => None,
+        }
+    }
+
+    /// The IP packet in `frame`, if it carries one.
+    fn ip_packet(self, frame: &[u8]) -> Option<&[u8]> {
+        let (ethertype, payload) = match self {
+            Self::Raw => return Some(frame),
+            // The family's values differ between systems; the IP version
+            // nibble tells IPv4 from IPv6 anyway.
+            Self::Loopback => return frame.get(4..),
+            Self::LinuxSll => (frame.get(14..16)?, frame.get(16..)?),
+            Self::LinuxSll2 => (frame.get(0..2)?, frame.get(20..)?),
+            Self::Ethernet => {
+                let mut offset = 12;
+                // 802.1Q and 802.1ad tags.
+                while matches!(frame.get(offset..offset + 2)?, [0x81, 0x00] | [0x88, 0xa8]) {
+                    offset += 4;
+                }
+                (frame.get(offset..offset + 2)?, frame.get(offset + 2..)?)
+            }
+        };
+        matches!(ethertype, [0x08, 0x00] | [0x86, 0xdd]).then_some(payload)
+    }
+}
+
+/// A pcapng interface.
+struct Interface {
+    link: Option<LinkType>,
+    /// Timestamp units per second.
+    units: u64,
+}
+
+enum Format {
+    Pcap {
+        big_endian: bool,
+        /// Units of the sub-second timestamp per second.
+        units: u64,
+        link: LinkType,
+    },
+    PcapNg {
+        big_endian: bool,
+        interfaces: Vec<Interface>,
+    },
+}
+
+/// Reads the packets of a capture file, one at a time.
+struct Packets {
+    reader: Box<dyn Read>,
+    format: Format,
+    buf: Vec<u8>,
+    /// For pcapng simple packets, which carry no timestamp.
+    last_at: SystemTime,
+}
+
+impl Packets {
+    fn new(mut reader: Box<dyn Read>) -> io::Result<Self> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if magic == PCAPNG_SECTION_HEADER {
+            // Put the block type back for `next_pcapng`.
+            let reader = Box::new(io::Cursor::new(magic).chain(reader));
+            return Ok(Self {
+                reader,
+                format: Format::PcapNg {
+                    big_endian: false,
+                    interfaces: Vec::new(),
+                },
+                buf: Vec::new(),
+                last_at: SystemTime::UNIX_EPOCH,
+            });
+        }
+        let (big_endian, units) = match magic {
+            [0xd4, 0xc3, 0xb2, 0xa1] => (false, 1_000_000),
+            [0xa1, 0xb2, 0xc3, 0xd4] => (true, 1_000_000),
+            [0x4d, 0x3c, 0xb2, 0xa1] => (false, 1_000_000_000),
+            [0xa1, 0xb2, 0x3c, 0x4d] => (true, 1_000_000_000),
+            _ => return Err(invalid("not a pcap or pcapng file")),
+        };
+        let mut header = [0u8; 20];
+        reader.read_exact(&mut header)?;
+        // The upper bits may carry FCS details.
+        let code = u32_at(&header, 16, big_endian) & 0xffff;
+        let link = LinkType::from_code(code)
+            .ok_or_else(|| invalid(&format!("unsupported link type {code}")))?;
+        Ok(Self {
+            reader,
+            format: Format::Pcap {
+                big_endian,
+                units,
+                link,
+            },
+            buf: Vec::new(),
+            last_at: SystemTime::UNIX_EPOCH,
+        })
+    }
+
+    /// The next IP packet and when it was captured; `None` at the end.
+    fn read_packet(&mut self) -> io::Result<Option<(SystemTime, &[u8])>> {
+        let found = match self.format {
+            Format::Pcap { .. } => self.next_pcap()?,
+            Format::PcapNg { .. } => self.next_pcapng()?,
+        };
+        Ok(found.map(|(at, link)| {
+            self.last_at = at;
+            (at, link.ip_packet(&self.buf).unwrap_or_default())
+        }))
+    }
+
+    fn next_pcap(&mut self) -> io::Result<Option<(SystemTime, LinkType)>> {
+        let Format::Pcap {
+            big_endian,
+            units,
+            link,
+        } = self.format
+        else {
+            unreachable!()
+        };
+        let mut header = [0u8; 16];
+        if !read_or_eof(&mut self.reader, &mut header)? {
+            return Ok(None);
+        }
+        let secs = u64::from(u32_at(&header, 0, big_endian));
+        let fraction = u64::from(u32_at(&header, 4, big_endian));
+        let len = u32_at(&header, 8, big_endian) as usize;
+        self.read_body(len)?;
+        let at = timestamp(secs * units + fraction, units);
+        Ok(Some((at, link)))
+    }
+
+    fn next_pcapng(&mut self) -> io::Result<Option<(SystemTime, LinkType)>> {
+        loop {
+            let mut header = [0u8; 8];
+            if !read_or_eof(&mut self.reader, &mut header)? {
+                return Ok(None);
+            }
+            let Format::PcapNg {
+                big_endian,
+                interfaces,
+            } = &mut self.format
+            else {
+                unreachable!()
+            };
+            if header[..4] == PCAPNG_SECTION_HEADER {
+                let mut byte_order = [0u8; 4];
+                self.reader.read_exact(&mut byte_order)?;
+                *big_endian = match u32::from_le_bytes(byte_order) {
+                    PCAPNG_BYTE_ORDER => false,
+                    m if m.swap_bytes() == PCAPNG_BYTE_ORDER => true,
+                    _ => return Err(invalid("bad pcapng byte-order magic")),
+                };
+                // Interface numbers start over in each section.
+                interfaces.clear();
+                let len = block_len(&header, *big_endian)?;
+                skip(&mut self.reader, len - 12)?;
+                continue;
+            }
+            let big_endian = *big_endian;
+            let kind = u32_at(&header, 0, big_endian);
+            let len = block_len(&header, big_endian)?;
+            let mut body = vec![0u8; len - 8];
+            self.reader.read_exact(&mut body)?;
+            // Without the trailing copy of the length.
+            let body = &body[..body.len() - 4];
+            match kind {
+                PCAPNG_INTERFACE if body.len() >= 8 => {
+                    let code = u32::from(u16_at(body, 0, big_endian));
+                    let link = LinkType::from_code(code);
+                    if link.is_none() {
+                        warn!(
+                            "skipping packets of capture interface {}: unsupported link type {code}",
+                            interfaces.len()
+                        );
+                    }
+                    interfaces.push(Interface {
+                        link,
+                        units: tsresol(&body[8..], big_endian).unwrap_or(1_000_000),
+                    });
+                }
+                PCAPNG_ENHANCED_PACKET if body.len() >= 20 => {
+                    let interface = u32_at(body, 0, big_endian) as usize;
+                    let Some(Interface {
+                        link: Some(link),
+                        units,
+                    }) = interfaces.get(interface)
+                    else {
+                        continue;
+                    };
+                    let (link, units) = (*link, *units);
+                    let ts = (u64::from(u32_at(body, 4, big_endian)) << 32)
+                        | u64::from(u32_at(body, 8, big_endian));
+                    let captured = u32_at(body, 12, big_endian) as usize;
+                    let data = body.get(20..20 + captured).ok_or_else(corrupt)?;
+                    self.buf.clear();
+                    self.buf.extend_from_slice(data);
+                    return Ok(Some((timestamp(ts, units), link)));
+                }
+                PCAPNG_SIMPLE_PACKET if body.len() >= 4 => {
+                    let Some(Interface {
+                        link: Some(link), ..
+                    }) = interfaces.first()
+                    else {
+                        continue;
+                    };
+                    let link = *link;
+                    // Snapped to the interface's snap length, if it had one.
+                    let original = u32_at(body, 0, big_endian) as usize;
+                    let data = &body[4..];
+                    self.buf.clear();
+                    self.buf
+                        .extend_from_slice(&data[..original.min(data.len())]);
+                    return Ok(Some((self.last_at, link)));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Reads a record body of `len` bytes into `buf`.
+    fn read_body(&mut self, len: usize) -> io::Result<()> {
+        if len > MAX_RECORD {
+            return Err(corrupt());
+        }
+        self.buf.resize(len, 0);
+        self.reader.read_exact(&mut self.buf)
+    }
+}
+
+/// HTTP traces from a pcap or pcapng file, in the order their exchanges
+/// completed.
+pub struct CaptureFile {
+    packets: Packets,
+    collector: FlowCollector,
+    traces: VecDeque<HttpTrace>,
+    packet_count: u64,
+    next_expiry: Option<SystemTime>,
+    done: bool,
+}
+
+impl CaptureFile {
+    /// Open a pcap or pcapng file.
+    pub fn open(path: &Path) -> Result<Self, CaptureError> {
+        let file = File::open(path)
+            .map_err(|e| CaptureError::Other(format!("{}: {e}", path.display())))?;
+        Self::from_reader(BufReader::new(file))
+            .map_err(|e| CaptureError::Other(format!("{}: {e}", path.display())))
+    }
+
+    /// Read a capture from `reader`, starting at the file header.
+    pub fn from_reader(reader: impl Read + 'static) -> Result<Self, CaptureError> {
+        let packets = Packets::new(Box::new(reader)).map_err(read_error)?;
+        Ok(Self {
+            packets,
+            collector: FlowCollector::plaintext(),
+            traces: VecDeque::new(),
+            packet_count: 0,
+            next_expiry: None,
+            done: false,
+        })
+    }
+
+    /// Decrypt TLS connections with the secrets in this `SSLKEYLOGFILE`
+    /// (builder pattern). Plain HTTP connections are then skipped, as by the
+    /// keylog backend.
+    pub fn with_keylog(mut self, path: PathBuf) -> Self {
+        self.collector = FlowCollector::decrypting(KeyLog::new(path));
+        self
+    }
+
+    /// Packets read so far.
+    pub fn packet_count(&self) -> u64 {
+        self.packet_count
+    }
+
+    /// Reads packets until some complete exchanges or the file ends.
+    fn fill(&mut self) -> Result<(), CaptureError> {
+        while self.traces.is_empty() {
+            let (at, packet) = match self.packets.read_packet() {
+                Ok(Some(next)) => next,
+                Ok(None) => {
+                    self.done = true;
+                    break;
+                }
+                // Typical of a capture cut short by killing tcpdump.
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                    warn!("capture file ends mid-packet; ignoring the rest");
+                    self.done = true;
+                    break;
+                }
+                Err(e) => {
+                    self.done = true;
+                    return Err(read_error(e));
+                }
+            };
+            self.packet_count += 1;
+            self.traces.extend(self.collector.packet(packet, at));
+            let next_expiry = *self.next_expiry.get_or_insert(at + EXPIRY_INTERVAL);
+            if at >= next_expiry {
+                self.traces.extend(self.collector.expire(at));
+                self.next_expiry = Some(at + EXPIRY_INTERVAL);
+            }
+        }
+        if self.done {
+            let end = self.packets.last_at;
+            self.traces.extend(self.collector.flush(end));
+        }
+        Ok(())
+    }
+}
+
+impl Iterator for CaptureFile {
+    type Item = Result<HttpTrace, CaptureError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.traces.is_empty()
+            && !self.done
+            && let Err(e) = self.fill()
+        {
+            return Some(Err(e));
+        }
+        self.traces.pop_front().map(Ok)
+    }
+}
+
+fn read_error(e: io::Error) -> CaptureError {
+    CaptureError::Other(format!("reading capture file: {e}"))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn corrupt() -> io::Error {
+    invalid("corrupt capture file")
+}
+
+/// Fills `buf`, or returns `false` if the reader is already at its end.
+fn read_or_eof(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<bool> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
+
+fn skip(reader: &mut impl Read, len: usize) -> io::Result<()> {
+    let skipped = io::copy(&mut reader.take(len as u64), &mut io::sink())?;
+    if skipped < len as u64 {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(())
+}
+
+/// The total length of a pcapng block, from its header.
+fn block_len(header: &[u8], big_endian: bool) -> io::Result<usize> {
+    let len = u32_at(header, 4, big_endian) as usize;
+    if len < 12 || !len.is_multiple_of(4) || len > MAX_RECORD {
+        return Err(corrupt());
+    }
+    Ok(len)
+}
+
+/// Units per second from an interface's `if_tsresol` option.
+fn tsresol(mut options: &[u8], big_endian: bool) -> Option<u64> {
+    while options.len() >= 4 {
+        let code = u16_at(options, 0, big_endian);
+        let len = usize::from(u16_at(options, 2, big_endian));
+        let value = options.get(4..4 + len)?;
+        if code == PCAPNG_OPT_TSRESOL {
+            let resolution = *value.first()?;
+            let exponent = u32::from(resolution & 0x7f);
+            return if resolution & 0x80 == 0 {
+                10u64.checked_pow(exponent)
+            } else {
+                2u64.checked_pow(exponent)
+            };
+        }
+        if code == 0 {
+            break;
+        }
+        options = options.get(4 + len.next_multiple_of(4)..)?;
+    }
+    None
+}
+
+/// `ts` units of `units` per second since the epoch.
+fn timestamp(ts: u64, units: u64) -> SystemTime {
+    let nanos = u128::from(ts % units) * 1_000_000_000 / u128::from(units);
+    SystemTime::UNIX_EPOCH + Duration::from_secs(ts / units) + Duration::from_nanos(nanos as u64)
+}
+
+fn u16_at(bytes: &[u8], offset: usize, big_endian: bool) -> u16 {
+    let b = [bytes[offset], bytes[offset + 1]];
+    if big_endian {
+        u16::from_be_bytes(b)
+    } else {
+        u16::from_le_bytes(b)
+    }
+}
+
+fn u32_at(bytes: &[u8], offset: usize, big_endian: bool) -> u32 {
+    let b = [
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ];
+    if big_endian {
+        u32::from_be_bytes(b)
+    } else {
+        u32::from_le_bytes(b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SYN: u8 = 0x02;
+    const ACK: u8 = 0x10;
+
+    /// An IPv4 TCP packet from `src` to `dst` ports on 127.0.0.1.
+    fn packet(src: u16, dst: u16, seq: u32, flags: u8, payload: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0x45, 0, 0, 0, 0, 0, 0x40, 0, 64, 6, 0, 0];
+        packet.extend_from_slice(&[127, 0, 0, 1, 127, 0, 0, 1]);
+        packet.extend_from_slice(&src.to_be_bytes());
+        packet.extend_from_slice(&dst.to_be_bytes());
+        packet.extend_from_slice(&seq.to_be_bytes());
+        packet.extend_from_slice(&[0, 0, 0, 0, 0x50, flags, 0, 0, 0, 0, 0, 0]);
+        packet.extend_from_slice(payload);
+        let len = packet.len() as u16;
+        packet[2..4].copy_from_slice(&len.to_be_bytes());
+        packet
+    }
+
+    /// A GET and its response, as IP packets.
+    fn exchange() -> Vec<Vec<u8>> {
+        vec![
+            packet(50000, 8080, 9, SYN, b""),
+            packet(8080, 50000, 99, SYN | ACK, b""),
+            packet(
+                50000,
+                8080,
+                10,
+                ACK,
+                b"GET /items HTTP/1.1\r\nHost: localhost:8080\r\n\r\n",
+            ),
+            packet(
+                8080,
+                50000,
+                100,
+                ACK,
+                b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok",
+            ),
+        ]
+    }
+
+    fn ethernet(ip: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0; 12];
+        frame.extend_from_slice(&[0x08, 0x00]);
+        frame.extend_from_slice(ip);
+        frame
+    }
+
+    fn traces(file: Vec<u8>) -> Vec<HttpTrace> {
+        CaptureFile::from_reader(io::Cursor::new(file))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_pcap_ethernet() {
+        let mut file = vec![0xd4, 0xc3, 0xb2, 0xa1, 2, 0, 4, 0];
+        file.extend_from_slice(&[0; 8]);
+        file.extend_from_slice(&65535u32.to_le_bytes());
+        file.extend_from_slice(&1u32.to_le_bytes());
+        for (i, ip) in exchange().iter().enumerate() {
+            let frame = ethernet(ip);
+            file.extend_from_slice(&1_700_000_000u32.to_le_bytes());
+            file.extend_from_slice(&(i as u32 * 1000).to_le_bytes());
+            file.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+            file.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+            file.extend_from_slice(&frame);
+        }
+        let traces = traces(file);
+        assert_eq!(traces.len(), 1);
+        assert_eq!(traces[0].url, "http://localhost:8080/items");
+        assert_eq!(traces[0].status_code, 200);
+        assert_eq!(
+            traces[0].timestamp,
+            SystemTime::UNIX_EPOCH + Duration::from_millis(1_700_000_000_002)
+        );
+    }
+
+    #[test]
+    fn test_pcapng_big_endian_nanoseconds() {
+        fn block(kind: u32, body: &[u8]) -> Vec<u8> {
+            let len = (12 + body.len().next_multiple_of(4)) as u32;
+            let mut block = kind.to_be_bytes().to_vec();
+            block.extend_from_slice(&len.to_be_bytes());
+            block.extend_from_slice(body);
+            block.resize(len as usize - 4, 0);
+            block.extend_from_slice(&len.to_be_bytes());
+            block
+        }
+        let mut file = block(
+            0x0a0d0d0a,
+            &[
+                0x1a, 0x2b, 0x3c, 0x4d, 0, 1, 0, 0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+            ],
+        );
+        // Raw IP, if_tsresol 10^-9.
+        file.extend(block(
+            PCAPNG_INTERFACE,
+            &[0, 101, 0, 0, 0, 0, 0, 0, 0, 9, 0, 1, 9, 0, 0, 0, 0, 0, 0, 0],
+        ));
+        for ip in exchange() {
+            let ts: u64 = 1_700_000_000_000_000_000;
+            let mut body = 0u32.to_be_bytes().to_vec();
+            body.extend_from_slice(&((ts >> 32) as u32).to_be_bytes());
+            body.extend_from_slice(&(ts as u32).to_be_bytes());
+            body.extend_from_slice(&(ip.len() as u32).to_be_bytes());
+            body.extend_from_slice(&(ip.len() as u32).to_be_bytes());
+            body.extend_from_slice(&ip);
+            file.extend(block(PCAPNG_ENHANCED_PACKET, &body));
+        }
+        let traces = traces(file);
+        assert_eq!(traces.len(), 1);
+        assert_eq!(traces[0].url, "http://localhost:8080/items");
+        assert_eq!(
+            traces[0].timestamp,
+            SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000)
+        );
+    }
+
+    #[test]
+    fn test_link_types() {
+        let ip = [0x45, 1, 2, 3];
+        let mut vlan = vec![0; 12];
+        vlan.extend_from_slice(&[0x81, 0x00, 0, 5, 0x08, 0x00]);
+        vlan.extend_from_slice(&ip);
+        assert_eq!(LinkType::Ethernet.ip_packet(&vlan), Some(&ip[..]));
+        let mut arp = vec![0; 12];
+        arp.extend_from_slice(&[0x08, 0x06, 0, 1]);
+        assert_eq!(LinkType::Ethernet.ip_packet(&arp), None);
+        let mut sll = vec![0; 14];
+        sll.extend_from_slice(&[0x86, 0xdd]);
+        sll.extend_from_slice(&ip);
+        assert_eq!(LinkType::LinuxSll.ip_packet(&sll), Some(&ip[..]));
+        let mut loopback = 2u32.to_le_bytes().to_vec();
+        loopback.extend_from_slice(&ip);
+        assert_eq!(LinkType::Loopback.ip_packet(&loopback), Some(&ip[..]));
+    }
+
+    #[test]
+    fn test_truncated_file_keeps_earlier_packets() {
+        let mut file = vec![0xa1, 0xb2, 0xc3, 0xd4, 0, 2, 0, 4];
+        file.extend_from_slice(&[0; 8]);
+        file.extend_from_slice(&65535u32.to_be_bytes());
+        file.extend_from_slice(&101u32.to_be_bytes());
+        for ip in exchange() {
+            file.extend_from_slice(&[0; 8]);
+            file.extend_from_slice(&(ip.len() as u32).to_be_bytes());
+            file.extend_from_slice(&(ip.len() as u32).to_be_bytes());
+            file.extend_from_slice(&ip);
+        }
+        file.extend_from_slice(&[0; 10]);
+        assert_eq!(traces(file).len(), 1);
+    }
+
+    #[test]
+    fn test_rejects_other_files() {
+        assert!(CaptureFile::from_reader(io::Cursor::new(b"GET / HTTP/1.1\r\n".to_vec())).is_err());
+    }
+}
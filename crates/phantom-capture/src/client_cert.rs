@@ -0,0 +1,248 @@
+//! Client certificates (mTLS) the proxy presents to upstream servers.
+//!
+//! The TLS client behind the proxy is otherwise shared by all destinations;
+//! with `--client-cert` rules each matching host gets its own rustls config
+//! carrying that host's certificate chain and key.
+
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use http::Uri;
+use hyper_rustls::HttpsConnector;
+use rustls::client::WantsClientCert;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::{ClientConfig, ConfigBuilder};
+use tower_service::Service;
+
+use crate::shaping::host_matches;
+
+/// A client certificate presented to one host (or its subdomains).
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClientCertRule {
+    /// Lowercase host name; `*.example.com` also matches subdomains.
+    pub host_pattern: String,
+    /// PEM certificate chain, leaf first.
+    pub cert_path: PathBuf,
+    /// PEM private key (PKCS#8, PKCS#1 or SEC1). If None, the key is read
+    /// from `cert_path`.
+    pub key_path: Option<PathBuf>,
+}
+
+/// Client certificate rules; the first one matching a destination host
+/// applies, other hosts get no client certificate.
+#[derive(Clone, Debug, Default)]
+pub struct ClientCertConfig {
+    pub rules: Vec<ClientCertRule>,
+}
+
+impl ClientCertConfig {
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// CLI spec parsing
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Parse a client certificate specification string into a `ClientCertRule`.
+///
+///   api.internal.example.com=client.pem,client-key.pem
+///   *.corp.example.com=corp.pem       (key in the same PEM file)
+pub fn parse_client_cert_spec(s: &str) -> Result<ClientCertRule, String> {
+    let (host, files) = s
+        .split_once('=')
+        .ok_or_else(|| format!("invalid client cert spec {s:?}: expected HOST=CERT[,KEY]"))?;
+    let host_pattern = host.trim().to_ascii_lowercase();
+    if host_pattern.is_empty() {
+        return Err(format!("invalid client cert spec {s:?}: empty host"));
+    }
+    let (cert_path, key_path) = match files.split_once(',') {
+        Some((cert, key)) => (cert.trim(), Some(key.trim())),
+        None => (files.trim(), None),
+    };
+    if cert_path.is_empty() || key_path.is_some_and(str::is_empty) {
+        return Err(format!("invalid client cert spec {s:?}: empty file path"));
+    }
+    Ok(ClientCertRule {
+        host_pattern,
+        cert_path: cert_path.into(),
+        key_path: key_path.map(PathBuf::from),
+    })
+}
+
+/// Reads a rule's certificate chain and private key.
+fn load_identity(
+    rule: &ClientCertRule,
+) -> Result<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>), String> {
+    let read = |path: &Path| {
+        std::fs::read(path).map_err(|e| format!("failed to read {}: {e}", path.display()))
+    };
+    let cert_pem = read(&rule.cert_path)?;
+    let certs = rustls_pemfile::certs(&mut cert_pem.as_slice())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("invalid certificate {}: {e}", rule.cert_path.display()))?;
+    if certs.is_empty() {
+        return Err(format!("no certificate in {}", rule.cert_path.display()));
+    }
+    let key_path = rule.key_path.as_deref().unwrap_or(&rule.cert_path);
+    let key_pem = match &rule.key_path {
+        Some(path) => read(path)?,
+        None => cert_pem,
+    };
+    let key = rustls_pemfile::private_key(&mut key_pem.as_slice())
+        .map_err(|e| format!("invalid private key {}: {e}", key_path.display()))?
+        .ok_or_else(|| format!("no private key in {}", key_path.display()))?;
+    Ok((certs, key))
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Connector
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Connector for the proxy's HTTP client that picks a TLS config by
+/// destination host: one with the host's client certificate if a rule
+/// matches, else the shared one without.
+#[derive(Clone)]
+pub(crate) struct ClientCertConnector<C> {
+    default: HttpsConnector<C>,
+    per_host: Arc<Vec<(String, HttpsConnector<C>)>>,
+}
+
+impl<C: Clone> ClientCertConnector<C> {
+    /// Wraps `inner` (the TCP connector) once per rule. `tls` yields the
+    /// server verification settings shared by every config.
+    pub(crate) fn new(
+        inner: C,
+        config: &ClientCertConfig,
+        tls: impl Fn() -> ConfigBuilder<ClientConfig, WantsClientCert>,
+    ) -> Result<Self, String> {
+        let wrap = |tls_config: ClientConfig| {
+            hyper_rustls::HttpsConnectorBuilder::new()
+                .with_tls_config(tls_config)
+                .https_or_http()
+                .enable_http1()
+                .wrap_connector(inner.clone())
+        };
+        let mut per_host = Vec::new();
+        for rule in &config.rules {
+            let (certs, key) = load_identity(rule)?;
+            let tls_config = tls()
+                .with_client_auth_cert(certs, key)
+                .map_err(|e| format!("client certificate {}: {e}", rule.cert_path.display()))?;
+            per_host.push((rule.host_pattern.clone(), wrap(tls_config)));
+        }
+        Ok(Self {
+            default: wrap(tls().with_no_client_auth()),
+            per_host: Arc::new(per_host),
+        })
+    }
+
+    fn connector_for(&self, host: &str) -> &HttpsConnector<C> {
+        self.per_host
+            .iter()
+            .find(|(pattern, _)| host_matches(pattern, host))
+            .map_or(&self.default, |(_, connector)| connector)
+    }
+}
+
+impl<C> Service<Uri> for ClientCertConnector<C>
+where
+    C: Clone,
+    HttpsConnector<C>: Service<Uri> + Send + 'static,
+    <HttpsConnector<C> as Service<Uri>>::Future: Send,
+{
+    type Response = <HttpsConnector<C> as Service<Uri>>::Response;
+    type Error = <HttpsConnector<C> as Service<Uri>>::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, dst: Uri) -> Self::Future {
+        // IPv6 hosts keep their brackets in the URI.
+        let host = dst.host().unwrap_or_default();
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let mut connector = self.connector_for(host).clone();
+        Box::pin(async move {
+            futures_util::future::poll_fn(|cx| connector.poll_ready(cx)).await?;
+            connector.call(dst).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hudsucker::rcgen;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_client_cert_spec() {
+        let rule = parse_client_cert_spec("API.internal=client.pem,client-key.pem").unwrap();
+        assert_eq!(rule.host_pattern, "api.internal");
+        assert_eq!(rule.cert_path, PathBuf::from("client.pem"));
+        assert_eq!(rule.key_path, Some(PathBuf::from("client-key.pem")));
+
+        let rule = parse_client_cert_spec("*.corp.example.com=corp.pem").unwrap();
+        assert_eq!(rule.host_pattern, "*.corp.example.com");
+        assert_eq!(rule.key_path, None);
+    }
+
+    #[test]
+    fn test_parse_client_cert_spec_rejects_invalid() {
+        assert!(parse_client_cert_spec("client.pem").is_err());
+        assert!(parse_client_cert_spec("=client.pem").is_err());
+        assert!(parse_client_cert_spec("api.internal=").is_err());
+        assert!(parse_client_cert_spec("api.internal=client.pem,").is_err());
+    }
+
+    #[test]
+    fn test_load_identity_from_combined_pem() {
+        let key_pair = rcgen::KeyPair::generate().unwrap();
+        let cert = rcgen::CertificateParams::new(vec!["client.test".to_string()])
+            .unwrap()
+            .self_signed(&key_pair)
+            .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let combined = dir.path().join("client.pem");
+        std::fs::write(
+            &combined,
+            format!("{}{}", cert.pem(), key_pair.serialize_pem()),
+        )
+        .unwrap();
+
+        let rule = ClientCertRule {
+            host_pattern: "api.internal".to_string(),
+            cert_path: combined,
+            key_path: None,
+        };
+        let (certs, _key) = load_identity(&rule).unwrap();
+        assert_eq!(certs.len(), 1);
+        assert_eq!(certs[0].as_ref(), cert.der().as_ref());
+    }
+
+    #[test]
+    fn test_load_identity_requires_key() {
+        let key_pair = rcgen::KeyPair::generate().unwrap();
+        let cert = rcgen::CertificateParams::new(vec!["client.test".to_string()])
+            .unwrap()
+            .self_signed(&key_pair)
+            .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let cert_path = dir.path().join("client.pem");
+        std::fs::write(&cert_path, cert.pem()).unwrap();
+
+        let rule = ClientCertRule {
+            host_pattern: "api.internal".to_string(),
+            cert_path,
+            key_path: None,
+        };
+        let err = load_identity(&rule).unwrap_err();
+        assert!(err.contains("no private key"), "{err}");
+    }
+}
@@ -0,0 +1,142 @@
+//! `Content-Encoding` decoding for captured bodies.
+//!
+//! Both backends store bodies decompressed so they read as text in the TUI
+//! and the query output. Decoding is best-effort: a body cut short at capture
+//! time decodes as far as its bytes go.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io::Read;
+
+use phantom_core::trace::BodyEncoding;
+
+/// Decoded bytes kept for bodies whose capture limit applies to the
+/// compressed form (the LD_PRELOAD agent's datagrams).
+pub(crate) const MAX_DECODED_SIZE: usize = 1024 * 1024;
+
+/// Decodes `body` per the `content-encoding` header in `headers`, keeping at
+/// most `limit` decoded bytes.
+///
+/// Returns `None` when the body is not encoded, uses an unsupported coding
+/// or does not decode at all; the captured bytes should then be kept as-is.
+pub(crate) fn decompress_body(
+    headers: &HashMap<String, String>,
+    body: &[u8],
+    limit: usize,
+) -> Option<(Vec<u8>, BodyEncoding)> {
+    let header = headers.get("content-encoding")?;
+    let codings: Vec<String> = header
+        .split(',')
+        .map(|c| c.trim().to_ascii_lowercase())
+        .filter(|c| !c.is_empty() && c != "identity")
+        .collect();
+    if codings.is_empty() {
+        return None;
+    }
+
+    // Codings are listed in the order they were applied; undo them in reverse.
+    let mut data = Cow::Borrowed(body);
+    let mut truncated = false;
+    for coding in codings.iter().rev() {
+        let (decoded, cut) = decode_one(coding, &data, limit)?;
+        data = Cow::Owned(decoded);
+        truncated |= cut;
+    }
+    let encoding = BodyEncoding {
+        encoding: header.trim().to_string(),
+        truncated,
+    };
+    Some((data.into_owned(), encoding))
+}
+
+fn decode_one(coding: &str, data: &[u8], limit: usize) -> Option<(Vec<u8>, bool)> {
+    match coding {
+        "gzip" | "x-gzip" => read_limited(flate2::read::MultiGzDecoder::new(data), limit),
+        // `deflate` means zlib-wrapped, but some servers send raw deflate.
+        "deflate" => read_limited(flate2::read::ZlibDecoder::new(data), limit)
+            .or_else(|| read_limited(flate2::read::DeflateDecoder::new(data), limit)),
+        "br" => read_limited(brotli::Decompressor::new(data, 4096), limit),
+        _ => None,
+    }
+}
+
+/// Reads `reader` to its end, or to `limit` bytes. A read error after some
+/// output (typically a stream cut short) keeps what was decoded; the flag
+/// reports that decoding stopped early.
+fn read_limited(reader: impl Read, limit: usize) -> Option<(Vec<u8>, bool)> {
+    let mut out = Vec::new();
+    match reader.take(limit as u64 + 1).read_to_end(&mut out) {
+        Ok(_) if out.len() > limit => {
+            out.truncate(limit);
+            Some((out, true))
+        }
+        Ok(_) => Some((out, false)),
+        Err(_) if !out.is_empty() => Some((out, true)),
+        Err(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    fn make_headers(encoding: &str) -> HashMap<String, String> {
+        let mut headers = HashMap::new();
+        headers.insert("content-encoding".to_string(), encoding.to_string());
+        headers
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut enc = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        enc.write_all(data).unwrap();
+        enc.finish().unwrap()
+    }
+
+    #[test]
+    fn test_decompress_gzip() {
+        let body = gzip(b"{\"ok\":true}");
+        let (decoded, encoding) = decompress_body(&make_headers("gzip"), &body, 1024).unwrap();
+        assert_eq!(decoded, b"{\"ok\":true}");
+        assert_eq!(encoding.encoding, "gzip");
+        assert!(!encoding.truncated);
+    }
+
+    #[test]
+    fn test_decompress_raw_deflate_fallback() {
+        let mut enc =
+            flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+        enc.write_all(b"hello").unwrap();
+        let body = enc.finish().unwrap();
+        let (decoded, _) = decompress_body(&make_headers("deflate"), &body, 1024).unwrap();
+        assert_eq!(decoded, b"hello");
+    }
+
+    #[test]
+    fn test_decompress_cut_short_keeps_prefix() {
+        let text: String = (0..5000).map(|i| i.to_string()).collect();
+        let body = gzip(text.as_bytes());
+        let (decoded, encoding) =
+            decompress_body(&make_headers("gzip"), &body[..body.len() - 10], 1 << 20).unwrap();
+        assert!(encoding.truncated);
+        assert!(text.as_bytes().starts_with(&decoded));
+    }
+
+    #[test]
+    fn test_decompress_limit_truncates() {
+        let body = gzip(&[b'x'; 100]);
+        let (decoded, encoding) = decompress_body(&make_headers("gzip"), &body, 10).unwrap();
+        assert_eq!(decoded.len(), 10);
+        assert!(encoding.truncated);
+    }
+
+    #[test]
+    fn test_decompress_skips_identity_and_unknown() {
+        assert!(decompress_body(&make_headers("identity"), b"plain", 1024).is_none());
+        assert!(decompress_body(&make_headers("zstd"), b"plain", 1024).is_none());
+        assert!(decompress_body(&HashMap::new(), b"plain", 1024).is_none());
+        // Not actually gzip: keep the bytes as captured.
+        assert!(decompress_body(&make_headers("gzip"), b"plain", 1024).is_none());
+    }
+}
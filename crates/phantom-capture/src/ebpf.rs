@@ -0,0 +1,667 @@
+//! eBPF capture backend — Linux only.
+//!
+//! Loads the probes built from `bpf/phantom.bpf.c`: tracepoints on the
+//! socket syscalls (`connect`, `read`/`write`, `recvfrom`/`sendto`, `close`)
+//! and uprobes on OpenSSL's `SSL_read`/`SSL_write` and Go's
+//! `crypto/tls.(*Conn).Read`/`Write`. They copy the traffic of the traced
+//! processes, and of the children they fork, to a ring buffer; each
+//! connection is reassembled as HTTP/1 here (see [`Http1Connection`]).
+//! Plain-HTTP traffic is seen at the syscalls, so Go and other statically
+//! linked programs are captured without `LD_PRELOAD` or proxy settings.
+//! HTTPS needs an OpenSSL the uprobes are attached to — a shared `libssl`
+//! or the target binary itself, when it links OpenSSL statically — or a Go
+//! binary, whose `crypto/tls` is probed at the offsets [`gotls`] finds.
+//!
+//! Loading the probes needs root, or `CAP_BPF` and `CAP_PERFMON`. HTTP/2 is
+//! not reassembled, so spawned Go programs are switched to HTTP/1 through
+//! `GODEBUG`.
+
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+use std::time::{Duration, SystemTime};
+
+use aya::Ebpf;
+use aya::maps::{MapData, RingBuf};
+use aya::programs::{TracePoint, UProbe};
+use phantom_core::capture::{CaptureBackend, TraceEvent};
+use phantom_core::error::CaptureError;
+use phantom_core::trace::{HttpTrace, ProcessInfo};
+use tokio::io::unix::AsyncFd;
+use tokio::sync::{mpsc, oneshot};
+use tracing::{debug, warn};
+
+use crate::gotls;
+use crate::http1::{Direction, Http1Connection};
+
+/// Tracepoint programs and the `category/name` events they attach to.
+const TRACEPOINTS: &[(&str, &str, &str)] = &[
+    ("sched_process_fork", "sched", "sched_process_fork"),
+    ("sys_enter_connect", "syscalls", "sys_enter_connect"),
+    ("sys_exit_connect", "syscalls", "sys_exit_connect"),
+    ("sys_enter_write", "syscalls", "sys_enter_write"),
+    ("sys_exit_write", "syscalls", "sys_exit_write"),
+    ("sys_enter_sendto", "syscalls", "sys_enter_sendto"),
+    ("sys_exit_sendto", "syscalls", "sys_exit_sendto"),
+    ("sys_enter_read", "syscalls", "sys_enter_read"),
+    ("sys_exit_read", "syscalls", "sys_exit_read"),
+    ("sys_enter_recvfrom", "syscalls", "sys_enter_recvfrom"),
+    ("sys_exit_recvfrom", "syscalls", "sys_exit_recvfrom"),
+    ("sys_enter_close", "syscalls", "sys_enter_close"),
+];
+
+/// Uprobe programs and the OpenSSL functions they attach to.
+const SSL_UPROBES: &[(&str, &str)] = &[
+    ("ssl_write", "SSL_write"),
+    ("ssl_write_ret", "SSL_write"),
+    ("ssl_read", "SSL_read"),
+    ("ssl_read_ret", "SSL_read"),
+    ("ssl_free", "SSL_free"),
+];
+
+/// Uprobe programs for Go's `crypto/tls`, attached at the offsets
+/// [`gotls::find_probes`] returns.
+const GO_TLS_PROGRAMS: &[&str] = &[
+    "go_tls_write",
+    "go_tls_write_ret",
+    "go_tls_read",
+    "go_tls_read_ret",
+    "go_tls_close",
+];
+
+/// Where distributions install OpenSSL, tried when no library is given.
+const DEFAULT_SSL_LIBS: &[&str] = &[
+    "/usr/lib/x86_64-linux-gnu/libssl.so.3",
+    "/usr/lib/aarch64-linux-gnu/libssl.so.3",
+    "/usr/lib64/libssl.so.3",
+    "/usr/lib/libssl.so.3",
+    "/lib/libssl.so.3",
+    "/usr/lib/x86_64-linux-gnu/libssl.so.1.1",
+    "/usr/lib/aarch64-linux-gnu/libssl.so.1.1",
+    "/usr/lib64/libssl.so.1.1",
+    "/usr/lib/libssl.so.1.1",
+];
+
+pub struct EbpfCaptureBackend {
+    object: PathBuf,
+    ssl_libs: Vec<PathBuf>,
+    bpf: Option<Ebpf>,
+    shutdown_tx: Option<oneshot::Sender<()>>,
+    task_handle: Option<tokio::task::JoinHandle<()>>,
+}
+
+impl EbpfCaptureBackend {
+    /// Create a backend loading the compiled probes from `object`.
+    pub fn new(object: PathBuf) -> Self {
+        Self {
+            object,
+            ssl_libs: Vec::new(),
+            bpf: None,
+            shutdown_tx: None,
+            task_handle: None,
+        }
+    }
+
+    /// Attach the OpenSSL uprobes to these libraries or binaries (builder
+    /// pattern) instead of the first `libssl` found in the usual places. Go
+    /// binaries among them get the `crypto/tls` uprobes.
+    pub fn with_ssl_libs(mut self, libs: Vec<PathBuf>) -> Self {
+        self.ssl_libs = libs;
+        self
+    }
+
+    /// Traces process `pid` and the children it forks from now on.
+    pub fn trace_pid(&mut self, pid: u32) -> Result<(), CaptureError> {
+        let bpf = self
+            .bpf
+            .as_mut()
+            .ok_or_else(|| CaptureError::Other("eBPF backend not started".to_string()))?;
+        let map = bpf
+            .map_mut("targets")
+            .ok_or_else(|| CaptureError::Other("probes lack the targets map".to_string()))?;
+        let mut targets: aya::maps::HashMap<_, u32, u8> =
+            aya::maps::HashMap::try_from(map).map_err(|e| CaptureError::Other(e.to_string()))?;
+        targets
+            .insert(pid, 1, 0)
+            .map_err(|e| CaptureError::Other(format!("tracing PID {pid}: {e}")))
+    }
+
+    /// Spawns `command` traced from its first instruction: the child waits
+    /// before `exec` until its PID is in the probes' target map. Its binary
+    /// also gets the OpenSSL uprobes, in case it links OpenSSL statically,
+    /// or the `crypto/tls` ones if it is a Go program; Go's HTTP client and
+    /// server are kept to HTTP/1.
+    pub fn spawn(&mut self, command: &mut Command) -> Result<Child, CaptureError> {
+        if let Some(binary) = resolve_program(command.get_program()) {
+            self.attach_ssl(&binary);
+            self.attach_go_tls(&binary);
+        }
+        let godebug = match std::env::var("GODEBUG") {
+            Ok(existing) if !existing.is_empty() => {
+                format!("{existing},http2client=0,http2server=0")
+            }
+            _ => "http2client=0,http2server=0".to_string(),
+        };
+        command.env("GODEBUG", godebug);
+        let mut fds = [0; 2];
+        // SAFETY: `fds` has room for the two descriptors pipe2 writes.
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+            return Err(CaptureError::Other(format!(
+                "pipe: {}",
+                std::io::Error::last_os_error()
+            )));
+        }
+        let [gate_read, gate_write] = fds;
+        // SAFETY: only async-signal-safe calls between fork and exec. The
+        // child drops its copy of the write end, then blocks until the
+        // parent closes its own.
+        unsafe {
+            command.pre_exec(move || {
+                libc::close(gate_write);
+                let mut byte = 0u8;
+                libc::read(gate_read, (&raw mut byte).cast(), 1);
+                Ok(())
+            });
+        }
+        let spawned = command.spawn();
+        // SAFETY: the parent's copy of the read end is no longer needed.
+        unsafe { libc::close(gate_read) };
+        let traced = spawned
+            .map_err(|e| CaptureError::Other(format!("spawn: {e}")))
+            .and_then(|mut child| match self.trace_pid(child.id()) {
+                Ok(()) => Ok(child),
+                Err(e) => {
+                    let _ = child.kill();
+                    Err(e)
+                }
+            });
+        // SAFETY: closing the write end releases the child.
+        unsafe { libc::close(gate_write) };
+        traced
+    }
+
+    /// Attaches the OpenSSL uprobes to `target`. Binaries without OpenSSL
+    /// are skipped.
+    fn attach_ssl(&mut self, target: &Path) {
+        let Some(bpf) = self.bpf.as_mut() else {
+            return;
+        };
+        for (program, function) in SSL_UPROBES {
+            let attached = bpf
+                .program_mut(program)
+                .ok_or_else(|| format!("no program {program}"))
+                .and_then(|p| <&mut UProbe>::try_from(p).map_err(|e| e.to_string()))
+                .and_then(|p| {
+                    p.attach(Some(*function), 0, target, None)
+                        .map_err(|e| e.to_string())
+                });
+            if let Err(e) = attached {
+                debug!("{function} uprobe on {}: {e}", target.display());
+                return;
+            }
+        }
+        debug!("OpenSSL uprobes attached to {}", target.display());
+    }
+
+    /// Attaches the `crypto/tls` uprobes to `target` when it is a Go
+    /// binary they can be placed in.
+    fn attach_go_tls(&mut self, target: &Path) {
+        let Some(bpf) = self.bpf.as_mut() else {
+            return;
+        };
+        let probes = match std::fs::read(target)
+            .map_err(|e| e.to_string())
+            .and_then(|elf| gotls::find_probes(&elf))
+        {
+            Ok(probes) => probes,
+            Err(e) => {
+                debug!("no Go TLS uprobes on {}: {e}", target.display());
+                return;
+            }
+        };
+        for (program, offset) in probes.attach_points() {
+            let attached = bpf
+                .program_mut(program)
+                .ok_or_else(|| format!("no program {program}"))
+                .and_then(|p| <&mut UProbe>::try_from(p).map_err(|e| e.to_string()))
+                .and_then(|p| {
+                    p.attach(None, offset, target, None)
+                        .map_err(|e| e.to_string())
+                });
+            if let Err(e) = attached {
+                warn!(
+                    "{program} uprobe on {} at {offset:#x}: {e}",
+                    target.display()
+                );
+                return;
+            }
+        }
+        debug!("Go crypto/tls uprobes attached to {}", target.display());
+    }
+}
+
+impl CaptureBackend for EbpfCaptureBackend {
+    fn start(&mut self) -> Result<mpsc::Receiver<TraceEvent>, CaptureError> {
+        let mut bpf = Ebpf::load_file(&self.object).map_err(|e| {
+            CaptureError::StartFailed(format!(
+                "loading {}: {e} (needs root, or CAP_BPF and CAP_PERFMON)",
+                self.object.display()
+            ))
+        })?;
+        for (program, category, name) in TRACEPOINTS {
+            let tracepoint: &mut TracePoint = bpf
+                .program_mut(program)
+                .ok_or_else(|| CaptureError::StartFailed(format!("probes lack program {program}")))?
+                .try_into()
+                .map_err(|e| CaptureError::StartFailed(format!("{program}: {e}")))?;
+            tracepoint
+                .load()
+                .map_err(|e| CaptureError::StartFailed(format!("loading {program}: {e}")))?;
+            tracepoint.attach(category, name).map_err(|e| {
+                CaptureError::StartFailed(format!("attaching {category}/{name}: {e}"))
+            })?;
+        }
+        let uprobes = SSL_UPROBES.iter().map(|(program, _)| program);
+        for program in uprobes.chain(GO_TLS_PROGRAMS) {
+            let uprobe: &mut UProbe = bpf
+                .program_mut(program)
+                .ok_or_else(|| CaptureError::StartFailed(format!("probes lack program {program}")))?
+                .try_into()
+                .map_err(|e| CaptureError::StartFailed(format!("{program}: {e}")))?;
+            uprobe
+                .load()
+                .map_err(|e| CaptureError::StartFailed(format!("loading {program}: {e}")))?;
+        }
+        let events = bpf
+            .take_map("events")
+            .ok_or_else(|| CaptureError::StartFailed("probes lack the events map".to_string()))?;
+        let ring =
+            RingBuf::try_from(events).map_err(|e| CaptureError::StartFailed(e.to_string()))?;
+        let ring = AsyncFd::new(ring).map_err(|e| CaptureError::StartFailed(e.to_string()))?;
+        self.bpf = Some(bpf);
+
+        let ssl_libs = if self.ssl_libs.is_empty() {
+            DEFAULT_SSL_LIBS
+                .iter()
+                .map(PathBuf::from)
+                .find(|lib| lib.exists())
+                .into_iter()
+                .collect()
+        } else {
+            self.ssl_libs.clone()
+        };
+        if ssl_libs.is_empty() {
+            warn!("no libssl found; HTTPS is only captured from binaries linking OpenSSL");
+        }
+        for lib in &ssl_libs {
+            self.attach_ssl(lib);
+            self.attach_go_tls(lib);
+        }
+
+        let (trace_tx, trace_rx) = mpsc::channel(4096);
+        let (shutdown_tx, shutdown_rx) = oneshot::channel();
+        let collector = Collector::new(monotonic_epoch());
+        self.shutdown_tx = Some(shutdown_tx);
+        self.task_handle = Some(tokio::spawn(read_events(
+            ring,
+            collector,
+            trace_tx,
+            shutdown_rx,
+        )));
+        Ok(trace_rx)
+    }
+
+    fn stop(&mut self) -> Result<(), CaptureError> {
+        if let Some(tx) = self.shutdown_tx.take() {
+            let _ = tx.send(());
+        }
+        // Dropping the programs detaches them.
+        self.bpf = None;
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "ebpf"
+    }
+}
+
+async fn read_events(
+    mut ring: AsyncFd<RingBuf<MapData>>,
+    mut collector: Collector,
+    trace_tx: mpsc::Sender<TraceEvent>,
+    mut shutdown_rx: oneshot::Receiver<()>,
+) {
+    loop {
+        tokio::select! {
+            _ = &mut shutdown_rx => break,
+            guard = ring.readable_mut() => {
+                let mut guard = match guard {
+                    Ok(guard) => guard,
+                    Err(e) => {
+                        warn!("eBPF ring buffer: {e}");
+                        break;
+                    }
+                };
+                let events = guard.get_inner_mut();
+                while let Some(item) = events.next() {
+                    match parse_event(&item) {
+                        Some(event) => send_traces(&trace_tx, collector.handle(event)),
+                        None => debug!("malformed eBPF event ({} bytes)", item.len()),
+                    }
+                }
+                guard.clear_ready();
+            }
+        }
+    }
+    send_traces(&trace_tx, collector.flush());
+}
+
+fn send_traces(trace_tx: &mpsc::Sender<TraceEvent>, traces: Vec<HttpTrace>) {
+    for trace in traces {
+        if trace_tx.try_send(trace.into()).is_err() {
+            warn!("Trace channel full, dropping trace");
+        }
+    }
+}
+
+/// Wall-clock time at `CLOCK_MONOTONIC` zero, which `bpf_ktime_get_ns`
+/// counts from.
+fn monotonic_epoch() -> SystemTime {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // SAFETY: `ts` is a valid timespec to write to.
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) };
+    let since_boot = Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32);
+    SystemTime::now() - since_boot
+}
+
+/// Finds `program` the way `exec` would, through `PATH` unless it has a `/`.
+fn resolve_program(program: &OsStr) -> Option<PathBuf> {
+    let path = Path::new(program);
+    if path.components().count() > 1 {
+        return Some(path.to_path_buf());
+    }
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(program))
+        .find(|candidate| candidate.is_file())
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Events
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Bytes before the payload of a `struct event` (see `phantom.bpf.c`).
+const HEADER_LEN: usize = 40;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Source {
+    Socket,
+    Ssl,
+    GoTls,
+}
+
+#[derive(Debug, PartialEq)]
+enum EventKind {
+    Data,
+    /// The payload is the peer's `sockaddr`.
+    Connect,
+    Close,
+}
+
+#[derive(Debug, PartialEq)]
+struct Event<'a> {
+    timestamp_ns: u64,
+    /// File descriptor for sockets, `SSL*` or `*tls.Conn` for TLS.
+    conn: u64,
+    pid: u32,
+    lost: u32,
+    kind: EventKind,
+    source: Source,
+    direction: Direction,
+    data: &'a [u8],
+}
+
+fn parse_event(bytes: &[u8]) -> Option<Event<'_>> {
+    let u64_at = |at: usize| Some(u64::from_ne_bytes(bytes.get(at..at + 8)?.try_into().ok()?));
+    let u32_at = |at: usize| Some(u32::from_ne_bytes(bytes.get(at..at + 4)?.try_into().ok()?));
+    let len = u32_at(24)? as usize;
+    Some(Event {
+        timestamp_ns: u64_at(0)?,
+        conn: u64_at(8)?,
+        pid: u32_at(16)?,
+        lost: u32_at(28)?,
+        kind: match bytes.get(32)? {
+            0 => EventKind::Data,
+            1 => EventKind::Connect,
+            2 => EventKind::Close,
+            _ => return None,
+        },
+        source: match bytes.get(33)? {
+            0 => Source::Socket,
+            1 => Source::Ssl,
+            2 => Source::GoTls,
+            _ => return None,
+        },
+        direction: match bytes.get(34)? {
+            0 => Direction::Outbound,
+            1 => Direction::Inbound,
+            _ => return None,
+        },
+        data: bytes.get(HEADER_LEN..HEADER_LEN + len)?,
+    })
+}
+
+/// Formats an IPv4 or IPv6 `sockaddr` as `ip:port`.
+fn format_sockaddr(addr: &[u8]) -> Option<String> {
+    let family = u16::from_ne_bytes(addr.get(0..2)?.try_into().ok()?);
+    let port = u16::from_be_bytes(addr.get(2..4)?.try_into().ok()?);
+    let ip: std::net::IpAddr = match family as i32 {
+        libc::AF_INET => <[u8; 4]>::try_from(addr.get(4..8)?).ok()?.into(),
+        libc::AF_INET6 => <[u8; 16]>::try_from(addr.get(8..24)?).ok()?.into(),
+        _ => return None,
+    };
+    Some(std::net::SocketAddr::new(ip, port).to_string())
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Reassembly
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Routes events to their connections.
+struct Collector {
+    /// Wall-clock time of event timestamp 0.
+    epoch: SystemTime,
+    conns: HashMap<(u32, Source, u64), Http1Connection>,
+    comms: HashMap<u32, String>,
+}
+
+impl Collector {
+    fn new(epoch: SystemTime) -> Self {
+        Self {
+            epoch,
+            conns: HashMap::new(),
+            comms: HashMap::new(),
+        }
+    }
+
+    fn handle(&mut self, event: Event<'_>) -> Vec<HttpTrace> {
+        let at = self.epoch + Duration::from_nanos(event.timestamp_ns);
+        let key = (event.pid, event.source, event.conn);
+        match event.kind {
+            EventKind::Connect => {
+                // A reused descriptor: whatever was on it has closed.
+                let traces = self
+                    .conns
+                    .remove(&key)
+                    .map(|mut conn| conn.close(at))
+                    .unwrap_or_default();
+                let conn = self.connection(key);
+                if let Some(addr) = format_sockaddr(event.data) {
+                    conn.set_dest_addr(addr);
+                }
+                traces
+            }
+            EventKind::Close => self
+                .conns
+                .remove(&key)
+                .map(|mut conn| conn.close(at))
+                .unwrap_or_default(),
+            EventKind::Data => {
+                let conn = self.connection(key);
+                let mut traces = conn.push(event.direction, event.data, at);
+                if event.lost > 0 {
+                    traces.extend(conn.skip(event.direction, event.lost as usize, at));
+                }
+                traces
+            }
+        }
+    }
+
+    /// Ends every connection, as at shutdown.
+    fn flush(&mut self) -> Vec<HttpTrace> {
+        let now = SystemTime::now();
+        self.conns
+            .drain()
+            .flat_map(|(_, mut conn)| conn.close(now))
+            .collect()
+    }
+
+    fn connection(&mut self, (pid, source, conn): (u32, Source, u64)) -> &mut Http1Connection {
+        let comm = self.comms.entry(pid).or_insert_with(|| {
+            std::fs::read_to_string(format!("/proc/{pid}/comm"))
+                .map(|comm| comm.trim_end().to_string())
+                .unwrap_or_default()
+        });
+        self.conns.entry((pid, source, conn)).or_insert_with(|| {
+            let (scheme, id) = match source {
+                Source::Socket => ("http", format!("{pid}:fd{conn}")),
+                Source::Ssl => ("https", format!("{pid}:ssl{conn:x}")),
+                Source::GoTls => ("https", format!("{pid}:gotls{conn:x}")),
+            };
+            let mut http = Http1Connection::new(scheme);
+            http.set_connection_id(id);
+            http.set_process(ProcessInfo {
+                comm: comm.clone(),
+                pid,
+            });
+            http
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event_bytes(kind: u8, source: u8, direction: u8, conn: u64, data: &[u8]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&1_000_000u64.to_ne_bytes());
+        bytes.extend_from_slice(&conn.to_ne_bytes());
+        bytes.extend_from_slice(&42u32.to_ne_bytes());
+        bytes.extend_from_slice(&43u32.to_ne_bytes());
+        bytes.extend_from_slice(&(data.len() as u32).to_ne_bytes());
+        bytes.extend_from_slice(&0u32.to_ne_bytes());
+        bytes.extend_from_slice(&[kind, source, direction, 0, 0, 0, 0, 0]);
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    fn sockaddr_in(ip: [u8; 4], port: u16) -> Vec<u8> {
+        let mut addr = (libc::AF_INET as u16).to_ne_bytes().to_vec();
+        addr.extend_from_slice(&port.to_be_bytes());
+        addr.extend_from_slice(&ip);
+        addr.resize(28, 0);
+        addr
+    }
+
+    #[test]
+    fn test_parse_event() {
+        let bytes = event_bytes(0, 1, 1, 0xdead, b"hi");
+        let event = parse_event(&bytes).unwrap();
+        assert_eq!(event.timestamp_ns, 1_000_000);
+        assert_eq!(event.conn, 0xdead);
+        assert_eq!(event.pid, 42);
+        assert_eq!(event.kind, EventKind::Data);
+        assert_eq!(event.source, Source::Ssl);
+        assert_eq!(event.direction, Direction::Inbound);
+        assert_eq!(event.data, b"hi");
+
+        let go = event_bytes(2, 2, 0, 0xc000_1234, b"");
+        assert_eq!(parse_event(&go).unwrap().source, Source::GoTls);
+        assert!(parse_event(&event_bytes(2, 3, 0, 1, b"")).is_none());
+        assert!(parse_event(&bytes[..HEADER_LEN]).is_none());
+        assert!(parse_event(&event_bytes(9, 0, 0, 1, b"")).is_none());
+    }
+
+    #[test]
+    fn test_format_sockaddr() {
+        assert_eq!(
+            format_sockaddr(&sockaddr_in([10, 0, 0, 7], 8080)).as_deref(),
+            Some("10.0.0.7:8080")
+        );
+        let mut v6 = (libc::AF_INET6 as u16).to_ne_bytes().to_vec();
+        v6.extend_from_slice(&443u16.to_be_bytes());
+        v6.extend_from_slice(&[0; 4]);
+        v6.extend_from_slice(&std::net::Ipv6Addr::LOCALHOST.octets());
+        v6.extend_from_slice(&[0; 4]);
+        assert_eq!(format_sockaddr(&v6).as_deref(), Some("[::1]:443"));
+        assert_eq!(format_sockaddr(&[1, 0, 0, 0]), None);
+    }
+
+    #[test]
+    fn test_collector_reassembles_socket_exchange() {
+        let mut collector = Collector::new(SystemTime::UNIX_EPOCH);
+        let events = [
+            event_bytes(1, 0, 0, 5, &sockaddr_in([127, 0, 0, 1], 3000)),
+            event_bytes(0, 0, 0, 5, b"GET /health HTTP/1.1\r\n\r\n"),
+            event_bytes(0, 0, 1, 5, b"HTTP/1.1 200 OK\r\n\r\nok"),
+        ];
+        for bytes in &events {
+            assert!(collector.handle(parse_event(bytes).unwrap()).is_empty());
+        }
+        let traces = collector.handle(parse_event(&event_bytes(2, 0, 0, 5, b"")).unwrap());
+        assert_eq!(traces.len(), 1);
+        assert_eq!(traces[0].url, "http://127.0.0.1:3000/health");
+        assert_eq!(traces[0].response_body.as_deref(), Some(&b"ok"[..]));
+        assert_eq!(traces[0].connection_id.as_deref(), Some("42:fd5"));
+        assert_eq!(traces[0].process.as_ref().map(|p| p.pid), Some(42));
+        assert!(collector.conns.is_empty());
+    }
+
+    #[test]
+    fn test_collector_flushes_open_ssl_connections() {
+        let mut collector = Collector::new(SystemTime::UNIX_EPOCH);
+        let request = event_bytes(0, 1, 0, 0x10, b"GET / HTTP/1.1\r\nHost: a.test\r\n\r\n");
+        let response = event_bytes(0, 1, 1, 0x10, b"HTTP/1.1 200 OK\r\n\r\nstream");
+        collector.handle(parse_event(&request).unwrap());
+        collector.handle(parse_event(&response).unwrap());
+        let traces = collector.flush();
+        assert_eq!(traces.len(), 1);
+        assert_eq!(traces[0].url, "https://a.test/");
+        assert_eq!(traces[0].response_body.as_deref(), Some(&b"stream"[..]));
+    }
+
+    #[test]
+    fn test_collector_reassembles_go_tls_exchange() {
+        let mut collector = Collector::new(SystemTime::UNIX_EPOCH);
+        let conn = 0xc000_1234;
+        let events = [
+            event_bytes(0, 2, 0, conn, b"GET /v1 HTTP/1.1\r\nHost: api.test\r\n\r\n"),
+            event_bytes(0, 2, 1, conn, b"HTTP/1.1 204 No Content\r\n\r\n"),
+        ];
+        let mut traces = Vec::new();
+        for bytes in &events {
+            traces.extend(collector.handle(parse_event(bytes).unwrap()));
+        }
+        traces.extend(collector.handle(parse_event(&event_bytes(2, 2, 0, conn, b"")).unwrap()));
+        assert_eq!(traces.len(), 1);
+        assert_eq!(traces[0].url, "https://api.test/v1");
+        assert_eq!(traces[0].status_code, 204);
+        assert_eq!(traces[0].connection_id.as_deref(), Some("42:gotlsc0001234"));
+        assert!(collector.conns.is_empty());
+    }
+}
@@ -16,14 +16,36 @@ pub enum FaultRule {
         /// If Some, only applies when the URL contains this substring.
         url_pattern: Option<String>,
     },
+    /// Drop the connection instead of forwarding: the client gets a
+    /// response head promising a body that never arrives.
+    Reset {
+        probability: f64,
+        url_pattern: Option<String>,
+    },
+    /// Forward the request, then cut the response body off halfway and
+    /// close the connection.
+    Truncate {
+        probability: f64,
+        url_pattern: Option<String>,
+    },
+    /// Hold the request for `after_ms` without forwarding it, then answer
+    /// 504 Gateway Timeout (if the client is still waiting).
+    Timeout {
+        after_ms: u64,
+        probability: f64,
+        url_pattern: Option<String>,
+    },
 }
 
 impl FaultRule {
     /// Returns true if this rule applies to the given URL.
     pub fn matches_url(&self, url: &str) -> bool {
         let pattern = match self {
-            FaultRule::Delay { url_pattern, .. } => url_pattern,
-            FaultRule::Error { url_pattern, .. } => url_pattern,
+            FaultRule::Delay { url_pattern, .. }
+            | FaultRule::Error { url_pattern, .. }
+            | FaultRule::Reset { url_pattern, .. }
+            | FaultRule::Truncate { url_pattern, .. }
+            | FaultRule::Timeout { url_pattern, .. } => url_pattern,
         };
         pattern
             .as_ref()
@@ -53,15 +75,44 @@ pub struct FaultConfig {
 ///   error:503:0.5               return HTTP 503 with 50% probability
 ///   error:503:/api              always return 503 for URLs containing "/api"
 ///   error:503:0.5:/api          probability + URL filter
+///   reset                       drop every connection
+///   reset:0.1:/api              10% of "/api" requests
+///   truncate:0.5                cut half the response bodies short
+///   timeout:30s                 hold requests 30s, then answer 504
+///   timeout:10s:0.2:/api        probability + URL filter
 pub fn parse_fault_spec(s: &str) -> Result<FaultRule, String> {
-    let (kind, rest) = s
-        .split_once(':')
-        .ok_or_else(|| format!("invalid fault spec {s:?}: expected 'delay:…' or 'error:…'"))?;
+    let (kind, rest) = s.split_once(':').unwrap_or((s, ""));
     match kind {
+        "delay" | "error" | "timeout" if rest.is_empty() => {
+            Err(format!("invalid fault spec {s:?}: expected '{kind}:…'"))
+        }
         "delay" => parse_delay(rest),
         "error" => parse_error(rest),
+        "reset" => {
+            let (probability, url_pattern) = parse_chance(rest)?;
+            Ok(FaultRule::Reset {
+                probability,
+                url_pattern,
+            })
+        }
+        "truncate" => {
+            let (probability, url_pattern) = parse_chance(rest)?;
+            Ok(FaultRule::Truncate {
+                probability,
+                url_pattern,
+            })
+        }
+        "timeout" => {
+            let (after, chance) = rest.split_once(':').unwrap_or((rest, ""));
+            let (probability, url_pattern) = parse_chance(chance)?;
+            Ok(FaultRule::Timeout {
+                after_ms: parse_ms(after)?,
+                probability,
+                url_pattern,
+            })
+        }
         _ => Err(format!(
-            "unknown fault type {kind:?} in {s:?}; expected 'delay' or 'error'"
+            "unknown fault type {kind:?} in {s:?}; expected 'delay', 'error', 'reset', 'truncate' or 'timeout'"
         )),
     }
 }
@@ -92,42 +143,38 @@ fn parse_delay(rest: &str) -> Result<FaultRule, String> {
 }
 
 fn parse_error(rest: &str) -> Result<FaultRule, String> {
-    // Optional URL pattern is the last colon-segment starting with '/'.
-    let (url_pattern, non_url) = if let Some(pos) = rest.rfind(':') {
-        if rest[pos + 1..].starts_with('/') {
-            (Some(rest[pos + 1..].to_string()), &rest[..pos])
-        } else {
-            (None, rest)
-        }
-    } else {
-        (None, rest)
-    };
-
-    let parts: Vec<&str> = non_url.splitn(2, ':').collect();
-    let status_code: u16 = parts[0]
+    let (code, chance) = rest.split_once(':').unwrap_or((rest, ""));
+    let status_code: u16 = code
         .parse()
-        .map_err(|_| format!("invalid HTTP status code {:?}", parts[0]))?;
+        .map_err(|_| format!("invalid HTTP status code {code:?}"))?;
     if !(100..=599).contains(&status_code) {
         return Err(format!("status code {status_code} is out of range 100–599"));
     }
-    let probability: f64 = if parts.len() == 2 {
-        parts[1].parse().map_err(|_| {
-            format!(
-                "invalid probability {:?}; expected a float like 0.5",
-                parts[1]
-            )
-        })?
+    let (probability, url_pattern) = parse_chance(chance)?;
+    Ok(FaultRule::Error {
+        status_code,
+        probability,
+        url_pattern,
+    })
+}
+
+/// Parse the optional `[PROBABILITY][:/URL]` tail of a fault spec.
+fn parse_chance(rest: &str) -> Result<(f64, Option<String>), String> {
+    let (rest, url_pattern) = if rest.starts_with('/') {
+        ("", Some(rest.to_string()))
     } else {
+        split_url_suffix(rest)
+    };
+    let probability: f64 = if rest.is_empty() {
         1.0
+    } else {
+        rest.parse()
+            .map_err(|_| format!("invalid probability {rest:?}; expected a float like 0.5"))?
     };
     if !(0.0..=1.0).contains(&probability) {
         return Err(format!("probability {probability} is out of range 0.0–1.0"));
     }
-    Ok(FaultRule::Error {
-        status_code,
-        probability,
-        url_pattern,
-    })
+    Ok((probability, url_pattern))
 }
 
 /// Split a trailing URL pattern (`:/<path>`) from the rest of a spec segment.
@@ -253,6 +300,52 @@ mod tests {
         assert!(matches!(r, FaultRule::Delay { min_ms: 2000, .. }));
     }
 
+    #[test]
+    fn parse_reset_and_truncate() {
+        assert!(matches!(
+            parse_fault_spec("reset").unwrap(),
+            FaultRule::Reset {
+                url_pattern: None,
+                ..
+            }
+        ));
+        match parse_fault_spec("truncate:0.25:/api").unwrap() {
+            FaultRule::Truncate {
+                probability,
+                url_pattern: Some(p),
+            } => {
+                assert!((probability - 0.25).abs() < 1e-9);
+                assert_eq!(p, "/api");
+            }
+            _ => panic!("unexpected rule"),
+        }
+        assert!(matches!(
+            parse_fault_spec("reset:/api").unwrap(),
+            FaultRule::Reset {
+                probability: 1.0,
+                url_pattern: Some(_),
+            }
+        ));
+    }
+
+    #[test]
+    fn parse_timeout() {
+        match parse_fault_spec("timeout:10s:0.2:/api").unwrap() {
+            FaultRule::Timeout {
+                after_ms: 10_000,
+                probability,
+                url_pattern: Some(p),
+            } => {
+                assert!((probability - 0.2).abs() < 1e-9);
+                assert_eq!(p, "/api");
+            }
+            _ => panic!("unexpected rule"),
+        }
+        assert!(parse_fault_spec("timeout").is_err());
+        assert!(parse_fault_spec("reset:2").is_err());
+        assert!(parse_fault_spec("error").is_err());
+    }
+
     #[test]
     fn url_pattern_matching() {
         let rule = FaultRule::Delay {
@@ -0,0 +1,181 @@
+//! HTTP traces from captured packets.
+//!
+//! A [`FlowCollector`] runs IP packets through the TCP reassembler and each
+//! connection's bytes through an [`Http1Connection`] — after decrypting them
+//! with a [`TlsSession`] when it was given a key log. The packet backends
+//! share it.
+
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+use phantom_core::trace::HttpTrace;
+
+use crate::http1::Http1Connection;
+use crate::tcp::{FlowKey, StreamEvent, TcpReassembler};
+use crate::tls::{KeyLog, TlsSession};
+
+/// Connections without packets for this long are closed.
+pub(crate) const IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Turns packets into traces.
+pub(crate) struct FlowCollector {
+    /// Present when only TLS decrypted with its secrets is wanted.
+    keylog: Option<KeyLog>,
+    tcp: TcpReassembler,
+    conns: HashMap<FlowKey, Connection>,
+}
+
+struct Connection {
+    tls: Option<TlsSession>,
+    http: Http1Connection,
+    /// Whether the negotiated TLS details were handed to `http`.
+    has_tls_info: bool,
+}
+
+impl FlowCollector {
+    /// Parses connections as plain HTTP/1.
+    pub(crate) fn plaintext() -> Self {
+        Self {
+            keylog: None,
+            tcp: TcpReassembler::default(),
+            conns: HashMap::new(),
+        }
+    }
+
+    /// Decrypts connections as TLS with secrets from `keylog`; others are
+    /// ignored.
+    pub(crate) fn decrypting(keylog: KeyLog) -> Self {
+        Self {
+            keylog: Some(keylog),
+            ..Self::plaintext()
+        }
+    }
+
+    /// Feeds one IP packet captured at `at`.
+    pub(crate) fn packet(&mut self, packet: &[u8], at: SystemTime) -> Vec<HttpTrace> {
+        let events = self.tcp.push(packet, at);
+        self.stream_events(events, at)
+    }
+
+    /// Closes connections idle for [`IDLE_TIMEOUT`] as of `now`.
+    pub(crate) fn expire(&mut self, now: SystemTime) -> Vec<HttpTrace> {
+        let events = self.tcp.expire(IDLE_TIMEOUT, now);
+        self.stream_events(events, now)
+    }
+
+    /// Ends every connection at `at`, as at shutdown or the end of a file.
+    pub(crate) fn flush(&mut self, at: SystemTime) -> Vec<HttpTrace> {
+        let events = self.tcp.close_all();
+        self.stream_events(events, at)
+    }
+
+    fn stream_events(&mut self, events: Vec<StreamEvent>, at: SystemTime) -> Vec<HttpTrace> {
+        let mut traces = Vec::new();
+        for event in events {
+            match event {
+                StreamEvent::Data {
+                    flow,
+                    direction,
+                    data,
+                } => {
+                    let decrypting = self.keylog.is_some();
+                    let conn = self
+                        .conns
+                        .entry(flow)
+                        .or_insert_with(|| Connection::new(flow, decrypting));
+                    let (Some(tls), Some(keylog)) = (conn.tls.as_mut(), self.keylog.as_mut())
+                    else {
+                        traces.extend(conn.http.push(direction, &data, at));
+                        continue;
+                    };
+                    let plaintext = tls.push(direction, &data, keylog, at);
+                    if !plaintext.is_empty() && !conn.has_tls_info {
+                        conn.http.set_tls(tls.info().clone());
+                        conn.has_tls_info = true;
+                    }
+                    for (direction, bytes) in plaintext {
+                        traces.extend(conn.http.push(direction, &bytes, at));
+                    }
+                }
+                StreamEvent::Gap {
+                    flow,
+                    direction,
+                    len,
+                } => {
+                    let Some(conn) = self.conns.get_mut(&flow) else {
+                        continue;
+                    };
+                    match conn.tls.as_mut() {
+                        // Records can't be framed past missing ciphertext.
+                        Some(tls) => tls.ignore(),
+                        None => traces.extend(conn.http.skip(direction, len, at)),
+                    }
+                }
+                StreamEvent::Closed { flow } => {
+                    if let Some(mut conn) = self.conns.remove(&flow) {
+                        traces.extend(conn.http.close(at));
+                    }
+                }
+            }
+        }
+        traces
+    }
+}
+
+impl Connection {
+    fn new(flow: FlowKey, decrypting: bool) -> Self {
+        let mut http = Http1Connection::new(if decrypting { "https" } else { "http" });
+        http.set_source_addr(flow.client.to_string());
+        http.set_dest_addr(flow.server.to_string());
+        http.set_connection_id(flow.client.to_string());
+        Self {
+            tls: decrypting.then(TlsSession::default),
+            http,
+            has_tls_info: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An IPv4 TCP packet from `src` to `dst` ports on 127.0.0.1.
+    fn packet(src: u16, dst: u16, seq: u32, flags: u8, payload: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0x45, 0, 0, 0, 0, 0, 0x40, 0, 64, 6, 0, 0];
+        packet.extend_from_slice(&[127, 0, 0, 1, 127, 0, 0, 1]);
+        packet.extend_from_slice(&src.to_be_bytes());
+        packet.extend_from_slice(&dst.to_be_bytes());
+        packet.extend_from_slice(&seq.to_be_bytes());
+        packet.extend_from_slice(&[0, 0, 0, 0, 0x50, flags, 0, 0, 0, 0, 0, 0]);
+        packet.extend_from_slice(payload);
+        let len = packet.len() as u16;
+        packet[2..4].copy_from_slice(&len.to_be_bytes());
+        packet
+    }
+
+    #[test]
+    fn test_plaintext_exchange_from_packets() {
+        const SYN: u8 = 0x02;
+        const ACK: u8 = 0x10;
+        let mut collector = FlowCollector::plaintext();
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1);
+        let request = b"GET /items HTTP/1.1\r\nHost: localhost:8080\r\n\r\n";
+        let response = b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok";
+        for packet in [
+            packet(50000, 8080, 9, SYN, b""),
+            packet(8080, 50000, 99, SYN | ACK, b""),
+            packet(50000, 8080, 10, ACK, request),
+        ] {
+            assert!(collector.packet(&packet, now).is_empty());
+        }
+        let traces = collector.packet(&packet(8080, 50000, 100, ACK, response), now);
+        assert_eq!(traces.len(), 1);
+        let trace = &traces[0];
+        assert_eq!(trace.url, "http://localhost:8080/items");
+        assert_eq!(trace.status_code, 200);
+        assert_eq!(trace.response_body.as_deref(), Some(&b"ok"[..]));
+        assert_eq!(trace.source_addr.as_deref(), Some("127.0.0.1:50000"));
+        assert_eq!(trace.dest_addr.as_deref(), Some("127.0.0.1:8080"));
+    }
+}
@@ -0,0 +1,579 @@
+//! Probe points for Go's `crypto/tls` — Linux only.
+//!
+//! Go programs don't use OpenSSL: TLS is `crypto/tls`, compiled into the
+//! binary, so the eBPF backend reads the plaintext at `(*Conn).Write` and
+//! `(*Conn).Read` instead. Return probes can't be used on Go code — the
+//! kernel's return trampoline breaks when a goroutine's stack is moved — so
+//! results are read by plain uprobes on each `RET` of the two functions,
+//! found here by disassembling them.
+//!
+//! Functions are looked up in the ELF symbol table or, when the binary is
+//! stripped, in Go's own `.gopclntab`. Only Go 1.18 and later (per
+//! `.go.buildinfo`) on amd64 and arm64 are supported: arguments and results
+//! are then passed in registers, where the probes read them.
+
+use iced_x86::{Decoder, DecoderOptions, Instruction, Mnemonic};
+
+const WRITE: &[u8] = b"crypto/tls.(*Conn).Write";
+const READ: &[u8] = b"crypto/tls.(*Conn).Read";
+const CLOSE: &[u8] = b"crypto/tls.(*Conn).Close";
+
+const EM_X86_64: u16 = 62;
+const EM_AARCH64: u16 = 183;
+const SHT_SYMTAB: u32 = 2;
+const SHT_NOBITS: u32 = 8;
+const STT_FUNC: u8 = 2;
+/// `ret` (to x30) on arm64.
+const ARM64_RET: u32 = 0xd65f_03c0;
+/// `.gopclntab` header magics of Go 1.18–1.19 and 1.20+.
+const PCLNTAB_MAGICS: [u32; 2] = [0xffff_fff0, 0xffff_fff1];
+const BUILDINFO_MAGIC: &[u8] = b"\xff Go buildinf:";
+
+/// File offsets of the `crypto/tls` probe points in one binary.
+#[derive(Debug, PartialEq)]
+pub(crate) struct GoTlsProbes {
+    pub write: u64,
+    pub write_returns: Vec<u64>,
+    pub read: u64,
+    pub read_returns: Vec<u64>,
+    pub close: u64,
+}
+
+impl GoTlsProbes {
+    /// `(program, file offset)` pairs, by the program names in
+    /// `phantom.bpf.c`.
+    pub(crate) fn attach_points(&self) -> Vec<(&'static str, u64)> {
+        let mut points = vec![
+            ("go_tls_write", self.write),
+            ("go_tls_read", self.read),
+            ("go_tls_close", self.close),
+        ];
+        points.extend(
+            self.write_returns
+                .iter()
+                .map(|&at| ("go_tls_write_ret", at)),
+        );
+        points.extend(self.read_returns.iter().map(|&at| ("go_tls_read_ret", at)));
+        points
+    }
+}
+
+/// Finds the probe points in the ELF file `elf`, or says why there are none.
+pub(crate) fn find_probes(elf: &[u8]) -> Result<GoTlsProbes, String> {
+    let elf = Elf::parse(elf).ok_or("not a 64-bit little-endian ELF file")?;
+    if elf.machine != EM_X86_64 && elf.machine != EM_AARCH64 {
+        return Err(format!("unsupported machine {}", elf.machine));
+    }
+    let version = elf.go_version().ok_or("not a Go 1.18+ binary")?;
+    if !register_abi(version) {
+        return Err(format!("built with {version}; Go 1.18 or later is needed"));
+    }
+    let mut functions = elf.symbols(&[WRITE, READ, CLOSE]);
+    if functions.iter().any(Option::is_none) {
+        functions = elf
+            .pclntab_functions(&[WRITE, READ, CLOSE])
+            .ok_or("no symbol table and no readable .gopclntab")?;
+    }
+    let [write, read, close] = functions;
+    let missing = |name: &[u8]| format!("no {}", String::from_utf8_lossy(name));
+    let (write, write_returns) = elf.function(write.ok_or_else(|| missing(WRITE))?)?;
+    let (read, read_returns) = elf.function(read.ok_or_else(|| missing(READ))?)?;
+    let (close, _) = elf.function(close.ok_or_else(|| missing(CLOSE))?)?;
+    Ok(GoTlsProbes {
+        write,
+        write_returns,
+        read,
+        read_returns,
+        close,
+    })
+}
+
+/// Whether a `.go.buildinfo` version such as `go1.22.4` passes arguments
+/// in registers on amd64 and arm64.
+fn register_abi(version: &str) -> bool {
+    if version.starts_with("devel") {
+        return true;
+    }
+    let mut parts = version.trim_start_matches("go").split(['.', '-', ' ']);
+    match (
+        parts.next(),
+        parts.next().and_then(|m| m.parse::<u32>().ok()),
+    ) {
+        (Some("1"), Some(minor)) => minor >= 18,
+        (Some(major), _) => major.parse::<u32>().is_ok_and(|major| major > 1),
+        _ => false,
+    }
+}
+
+/// Offsets of the `RET` instructions in a function's machine code.
+fn return_offsets(machine: u16, code: &[u8]) -> Vec<usize> {
+    match machine {
+        EM_X86_64 => {
+            let mut decoder = Decoder::new(64, code, DecoderOptions::NONE);
+            let mut instruction = Instruction::default();
+            let mut offsets = Vec::new();
+            while decoder.can_decode() {
+                let at = decoder.position();
+                decoder.decode_out(&mut instruction);
+                if instruction.mnemonic() == Mnemonic::Ret {
+                    offsets.push(at);
+                }
+            }
+            offsets
+        }
+        EM_AARCH64 => code
+            .chunks_exact(4)
+            .enumerate()
+            .filter(|(_, word)| word == &ARM64_RET.to_le_bytes())
+            .map(|(i, _)| i * 4)
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+struct Section<'a> {
+    name: &'a [u8],
+    kind: u32,
+    addr: u64,
+    offset: usize,
+    size: usize,
+    link: usize,
+}
+
+/// The parts of an ELF64 file the lookup needs.
+struct Elf<'a> {
+    data: &'a [u8],
+    machine: u16,
+    sections: Vec<Section<'a>>,
+}
+
+impl<'a> Elf<'a> {
+    fn parse(data: &'a [u8]) -> Option<Self> {
+        if data.get(..6)? != b"\x7fELF\x02\x01" {
+            return None;
+        }
+        let shoff = usize::try_from(u64_at(data, 0x28)?).ok()?;
+        let shentsize = usize::from(u16_at(data, 0x3a)?);
+        let shnum = usize::from(u16_at(data, 0x3c)?);
+        let shstrndx = usize::from(u16_at(data, 0x3e)?);
+        let header = |i: usize| {
+            let sh = shoff + i * shentsize;
+            Some((
+                usize::try_from(u32_at(data, sh)?).ok()?,
+                u32_at(data, sh + 4)?,
+                u64_at(data, sh + 0x10)?,
+                usize::try_from(u64_at(data, sh + 0x18)?).ok()?,
+                usize::try_from(u64_at(data, sh + 0x20)?).ok()?,
+                usize::try_from(u32_at(data, sh + 0x28)?).ok()?,
+            ))
+        };
+        let (_, _, _, names, _, _) = header(shstrndx)?;
+        let sections = (0..shnum)
+            .map(|i| {
+                let (name, kind, addr, offset, size, link) = header(i)?;
+                Some(Section {
+                    name: c_string(data, names.checked_add(name)?)?,
+                    kind,
+                    addr,
+                    offset,
+                    size,
+                    link,
+                })
+            })
+            .collect::<Option<_>>()?;
+        Some(Self {
+            data,
+            machine: u16_at(data, 0x12)?,
+            sections,
+        })
+    }
+
+    fn contents(&self, section: &Section<'_>) -> Option<&'a [u8]> {
+        if section.kind == SHT_NOBITS {
+            return None;
+        }
+        self.data
+            .get(section.offset..section.offset.checked_add(section.size)?)
+    }
+
+    fn section(&self, name: &[u8]) -> Option<&'a [u8]> {
+        let section = self.sections.iter().find(|s| s.name == name)?;
+        self.contents(section)
+    }
+
+    /// The Go version recorded in `.go.buildinfo` (Go 1.18+ layout).
+    fn go_version(&self) -> Option<&'a str> {
+        let info = self.section(b".go.buildinfo")?;
+        // The version is stored inline (flag 2) from Go 1.18 on; earlier
+        // releases point to it instead.
+        if !info.starts_with(BUILDINFO_MAGIC) || info.get(15)? & 2 == 0 {
+            return None;
+        }
+        let (len, used) = uvarint(info.get(32..)?)?;
+        let start = 32 + used;
+        std::str::from_utf8(info.get(start..start.checked_add(usize::try_from(len).ok()?)?)?).ok()
+    }
+
+    /// `(address, size)` of each of `names` in the symbol table.
+    fn symbols<const N: usize>(&self, names: &[&[u8]; N]) -> [Option<(u64, u64)>; N] {
+        let mut found = [None; N];
+        let Some(symtab) = self.sections.iter().find(|s| s.kind == SHT_SYMTAB) else {
+            return found;
+        };
+        let (Some(symbols), Some(strings)) = (
+            self.contents(symtab),
+            self.sections
+                .get(symtab.link)
+                .and_then(|s| self.contents(s)),
+        ) else {
+            return found;
+        };
+        for sym in symbols.chunks_exact(24) {
+            if sym[4] & 0xf != STT_FUNC {
+                continue;
+            }
+            let Some(name) = u32_at(sym, 0)
+                .and_then(|at| usize::try_from(at).ok())
+                .and_then(|at| c_string(strings, at))
+            else {
+                continue;
+            };
+            if let Some(i) = names.iter().position(|n| *n == name) {
+                found[i] = u64_at(sym, 8).zip(u64_at(sym, 16));
+            }
+        }
+        found
+    }
+
+    /// `(address, size)` of each of `names` in Go's function table, the
+    /// `.gopclntab` section (Go 1.18+ layout). A function ends where the
+    /// next one starts.
+    fn pclntab_functions<const N: usize>(
+        &self,
+        names: &[&[u8]; N],
+    ) -> Option<[Option<(u64, u64)>; N]> {
+        let table = self.section(b".gopclntab")?;
+        if !PCLNTAB_MAGICS.contains(&u32_at(table, 0)?) || *table.get(7)? != 8 {
+            return None;
+        }
+        let nfunc = usize::try_from(u64_at(table, 8)?).ok()?;
+        let mut text_start = u64_at(table, 24)?;
+        let funcnames = usize::try_from(u64_at(table, 32)?).ok()?;
+        let pcln = usize::try_from(u64_at(table, 64)?).ok()?;
+        if text_start == 0 {
+            // Left to a relocation in position-independent executables.
+            text_start = self.sections.iter().find(|s| s.name == b".text")?.addr;
+        }
+
+        let mut found = [None; N];
+        for i in 0..nfunc {
+            let entry = pcln + i * 8;
+            let start = u64::from(u32_at(table, entry)?);
+            let end = u64::from(u32_at(table, entry + 8)?);
+            let func = pcln.checked_add(usize::try_from(u32_at(table, entry + 4)?).ok()?)?;
+            let name_offset = usize::try_from(u32_at(table, func + 4)?).ok()?;
+            let Some(name) = c_string(table, funcnames + name_offset) else {
+                continue;
+            };
+            if let Some(i) = names.iter().position(|n| *n == name) {
+                found[i] = Some((text_start + start, end.saturating_sub(start)));
+            }
+        }
+        Some(found)
+    }
+
+    /// The file offsets of the function at `(address, size)` and of its
+    /// `RET`s.
+    fn function(&self, (addr, size): (u64, u64)) -> Result<(u64, Vec<u64>), String> {
+        let section = self
+            .sections
+            .iter()
+            .find(|s| {
+                s.kind != SHT_NOBITS
+                    && s.addr != 0
+                    && (s.addr..s.addr + s.size as u64).contains(&addr)
+            })
+            .ok_or_else(|| format!("address {addr:#x} is in no section"))?;
+        let offset = section.offset as u64 + (addr - section.addr);
+        let code = usize::try_from(offset)
+            .ok()
+            .zip(usize::try_from(size).ok())
+            .and_then(|(start, len)| self.data.get(start..start.checked_add(len)?))
+            .ok_or_else(|| format!("function at {addr:#x} runs past the end of the file"))?;
+        let returns: Vec<u64> = return_offsets(self.machine, code)
+            .into_iter()
+            .map(|at| offset + at as u64)
+            .collect();
+        if returns.is_empty() {
+            return Err(format!("no RET in the function at {addr:#x}"));
+        }
+        Ok((offset, returns))
+    }
+}
+
+/// The NUL-terminated string at `at` in `bytes`.
+fn c_string(bytes: &[u8], at: usize) -> Option<&[u8]> {
+    let rest = bytes.get(at..)?;
+    Some(&rest[..rest.iter().position(|&b| b == 0)?])
+}
+
+/// A Go/protobuf unsigned varint and the number of bytes it took.
+fn uvarint(bytes: &[u8]) -> Option<(u64, usize)> {
+    let mut value = 0u64;
+    for (i, &b) in bytes.iter().enumerate().take(10) {
+        value |= u64::from(b & 0x7f) << (7 * i);
+        if b & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+    None
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        bytes.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        bytes.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn u64_at(bytes: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(
+        bytes.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT_ADDR: u64 = 0x40_1000;
+    /// `nop; ret; int3; int3`
+    const WRITE_CODE: &[u8] = &[0x90, 0xc3, 0xcc, 0xcc];
+    /// `mov eax, 0xc3; ret; xor eax, eax; ret` — the immediate is no `RET`.
+    const READ_CODE: &[u8] = &[0xb8, 0xc3, 0, 0, 0, 0xc3, 0x31, 0xc0, 0xc3];
+    /// `ret`
+    const CLOSE_CODE: &[u8] = &[0xc3];
+
+    struct TestSection {
+        name: &'static str,
+        kind: u32,
+        addr: u64,
+        data: Vec<u8>,
+        link: u32,
+    }
+
+    fn section(name: &'static str, kind: u32, addr: u64, data: Vec<u8>) -> TestSection {
+        TestSection {
+            name,
+            kind,
+            addr,
+            data,
+            link: 0,
+        }
+    }
+
+    /// An ELF64 file with `sections` (numbered from 1) and their names.
+    fn elf(machine: u16, mut sections: Vec<TestSection>) -> Vec<u8> {
+        let mut names = vec![0u8];
+        for s in &sections {
+            names.extend_from_slice(s.name.as_bytes());
+            names.push(0);
+        }
+        let names_at = names.len();
+        names.extend_from_slice(b".shstrtab\0");
+        sections.push(section("", 3, 0, names));
+
+        let mut out = vec![0u8; 64];
+        let mut headers = vec![0u8; 64];
+        let mut name = 1;
+        for (i, s) in sections.iter().enumerate() {
+            let name_offset = if i == sections.len() - 1 {
+                names_at
+            } else {
+                name
+            };
+            name += s.name.len() + 1;
+            let offset = out.len() as u64;
+            out.extend_from_slice(&s.data);
+            let mut header = vec![0u8; 64];
+            header[..4].copy_from_slice(&(name_offset as u32).to_le_bytes());
+            header[4..8].copy_from_slice(&s.kind.to_le_bytes());
+            header[0x10..0x18].copy_from_slice(&s.addr.to_le_bytes());
+            header[0x18..0x20].copy_from_slice(&offset.to_le_bytes());
+            header[0x20..0x28].copy_from_slice(&(s.data.len() as u64).to_le_bytes());
+            header[0x28..0x2c].copy_from_slice(&s.link.to_le_bytes());
+            headers.extend_from_slice(&header);
+        }
+        let shoff = out.len() as u64;
+        out.extend_from_slice(&headers);
+        out[..6].copy_from_slice(b"\x7fELF\x02\x01");
+        out[0x12..0x14].copy_from_slice(&machine.to_le_bytes());
+        out[0x28..0x30].copy_from_slice(&shoff.to_le_bytes());
+        out[0x3a..0x3c].copy_from_slice(&64u16.to_le_bytes());
+        out[0x3c..0x3e].copy_from_slice(&(sections.len() as u16 + 1).to_le_bytes());
+        out[0x3e..0x40].copy_from_slice(&(sections.len() as u16).to_le_bytes());
+        out
+    }
+
+    fn buildinfo(version: &str) -> Vec<u8> {
+        let mut info = BUILDINFO_MAGIC.to_vec();
+        info.extend_from_slice(&[8, 2]);
+        info.resize(32, 0);
+        info.push(version.len() as u8);
+        info.extend_from_slice(version.as_bytes());
+        info
+    }
+
+    /// `.text` holding Write, Read and Close back to back.
+    fn text() -> Vec<u8> {
+        [WRITE_CODE, READ_CODE, CLOSE_CODE].concat()
+    }
+
+    fn addresses() -> [u64; 3] {
+        let write = TEXT_ADDR;
+        let read = write + WRITE_CODE.len() as u64;
+        [write, read, read + READ_CODE.len() as u64]
+    }
+
+    fn symtab_sections() -> Vec<TestSection> {
+        let mut strings = vec![0u8];
+        let mut symbols = vec![0u8; 24];
+        for (name, (addr, code)) in [WRITE, READ, CLOSE].iter().zip(
+            addresses()
+                .into_iter()
+                .zip([WRITE_CODE, READ_CODE, CLOSE_CODE]),
+        ) {
+            let mut sym = vec![0u8; 24];
+            sym[..4].copy_from_slice(&(strings.len() as u32).to_le_bytes());
+            sym[4] = STT_FUNC;
+            sym[8..16].copy_from_slice(&addr.to_le_bytes());
+            sym[16..24].copy_from_slice(&(code.len() as u64).to_le_bytes());
+            symbols.extend_from_slice(&sym);
+            strings.extend_from_slice(name);
+            strings.push(0);
+        }
+        let mut symtab = section(".symtab", SHT_SYMTAB, 0, symbols);
+        symtab.link = 4;
+        vec![symtab, section(".strtab", 3, 0, strings)]
+    }
+
+    fn pclntab() -> Vec<u8> {
+        let names = [WRITE, READ, CLOSE];
+        let mut funcnames = Vec::new();
+        let mut name_offsets = Vec::new();
+        for name in names {
+            name_offsets.push(funcnames.len() as u32);
+            funcnames.extend_from_slice(name);
+            funcnames.push(0);
+        }
+        let funcnames_at = 72u64;
+        let pcln_at = funcnames_at + funcnames.len() as u64;
+
+        let mut table = Vec::new();
+        table.extend_from_slice(&0xffff_fff1u32.to_le_bytes());
+        table.extend_from_slice(&[0, 0, 1, 8]);
+        table.extend_from_slice(&3u64.to_le_bytes());
+        table.extend_from_slice(&0u64.to_le_bytes());
+        table.extend_from_slice(&TEXT_ADDR.to_le_bytes());
+        table.extend_from_slice(&funcnames_at.to_le_bytes());
+        table.extend_from_slice(&[0; 24]);
+        table.extend_from_slice(&pcln_at.to_le_bytes());
+        table.extend_from_slice(&funcnames);
+
+        // The function table, its end-of-text sentinel, then the _func records.
+        let entries = addresses().map(|addr| (addr - TEXT_ADDR) as u32);
+        let funcs_at = 4 * 8;
+        for (i, entry) in entries.iter().enumerate() {
+            table.extend_from_slice(&entry.to_le_bytes());
+            table.extend_from_slice(&(funcs_at + i as u32 * 8).to_le_bytes());
+        }
+        table.extend_from_slice(&(text().len() as u32).to_le_bytes());
+        table.extend_from_slice(&0u32.to_le_bytes());
+        for (entry, name_offset) in entries.iter().zip(name_offsets) {
+            table.extend_from_slice(&entry.to_le_bytes());
+            table.extend_from_slice(&name_offset.to_le_bytes());
+        }
+        table
+    }
+
+    fn expected(text_offset: u64) -> GoTlsProbes {
+        GoTlsProbes {
+            write: text_offset,
+            write_returns: vec![text_offset + 1],
+            read: text_offset + 4,
+            read_returns: vec![text_offset + 9, text_offset + 12],
+            close: text_offset + 13,
+        }
+    }
+
+    #[test]
+    fn test_finds_probes_through_the_symbol_table() {
+        let mut sections = vec![
+            section(".text", 1, TEXT_ADDR, text()),
+            section(".go.buildinfo", 1, 0x50_0000, buildinfo("go1.22.4")),
+        ];
+        sections.extend(symtab_sections());
+        let probes = find_probes(&elf(EM_X86_64, sections)).unwrap();
+        assert_eq!(probes, expected(64));
+        assert_eq!(probes.attach_points().len(), 6);
+    }
+
+    #[test]
+    fn test_finds_probes_of_stripped_binaries_through_gopclntab() {
+        let sections = vec![
+            section(".text", 1, TEXT_ADDR, text()),
+            section(".gopclntab", 1, 0x48_0000, pclntab()),
+            section(".go.buildinfo", 1, 0x50_0000, buildinfo("go1.24.3")),
+        ];
+        assert_eq!(
+            find_probes(&elf(EM_X86_64, sections)).unwrap(),
+            expected(64)
+        );
+    }
+
+    #[test]
+    fn test_rejects_other_binaries() {
+        let old_go = vec![
+            section(".text", 1, TEXT_ADDR, text()),
+            section(".go.buildinfo", 1, 0x50_0000, buildinfo("go1.17.13")),
+        ];
+        assert!(
+            find_probes(&elf(EM_X86_64, old_go))
+                .unwrap_err()
+                .contains("1.18")
+        );
+        let not_go = vec![section(".text", 1, TEXT_ADDR, text())];
+        assert!(find_probes(&elf(EM_X86_64, not_go)).is_err());
+        let no_tls = vec![
+            section(".text", 1, TEXT_ADDR, text()),
+            section(".go.buildinfo", 1, 0x50_0000, buildinfo("go1.22.4")),
+        ];
+        assert!(find_probes(&elf(EM_X86_64, no_tls)).is_err());
+        assert!(find_probes(b"#!/bin/sh\n").is_err());
+    }
+
+    #[test]
+    fn test_return_offsets() {
+        assert_eq!(return_offsets(EM_X86_64, READ_CODE), vec![5, 8]);
+        let arm64: Vec<u8> = [0xd503_201f, ARM64_RET, 0xd503_201f, ARM64_RET]
+            .iter()
+            .flat_map(|word: &u32| word.to_le_bytes())
+            .collect();
+        assert_eq!(return_offsets(EM_AARCH64, &arm64), vec![4, 12]);
+    }
+
+    #[test]
+    fn test_register_abi() {
+        assert!(register_abi("go1.18"));
+        assert!(register_abi("go1.23.8"));
+        assert!(register_abi("devel go1.25-abcdef"));
+        assert!(!register_abi("go1.17.13"));
+        assert!(!register_abi("go1.9"));
+        assert!(!register_abi("nonsense"));
+    }
+}
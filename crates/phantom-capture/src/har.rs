@@ -0,0 +1,610 @@
+//! HTTP traces from HAR (HTTP Archive) files.
+//!
+//! A [`HarFile`] reads the JSON a browser's developer tools (or a proxy such
+//! as Charles or mitmproxy) export and turns each `log.entries` item into an
+//! [`HttpTrace`]. HAR stores bodies already decoded, so they are kept as the
+//! live backends keep theirs: decompressed, with the original
+//! `Content-Encoding` recorded, and cut at [`MAX_BODY_SIZE`]. HTTP/2 pseudo
+//! headers (`:authority`, …) are dropped. Entries whose method phantom does
+//! not model (e.g. WebDAV's `PROPFIND`) are skipped.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use base64::Engine as _;
+use base64::engine::general_purpose::STANDARD as B64;
+use phantom_core::error::CaptureError;
+use phantom_core::trace::{
+    BodyEncoding, HttpMethod, HttpTrace, SpanId, TimingPhases, TraceId, parse_traceparent,
+};
+use serde::Deserialize;
+use tracing::warn;
+
+/// Bytes kept per body, as by the packet backends.
+const MAX_BODY_SIZE: usize = 1024 * 1024;
+
+#[derive(Deserialize)]
+struct Har {
+    log: Log,
+}
+
+#[derive(Deserialize)]
+struct Log {
+    #[serde(default)]
+    entries: Vec<Entry>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Entry {
+    started_date_time: String,
+    /// Total elapsed time in milliseconds.
+    #[serde(default)]
+    time: f64,
+    request: Request,
+    response: Response,
+    #[serde(default)]
+    timings: Option<Timings>,
+    #[serde(default, rename = "serverIPAddress")]
+    server_ip_address: Option<String>,
+    /// Connection identifier, e.g. Chrome's socket ID. A string per the
+    /// spec, but some tools write a number.
+    #[serde(default)]
+    connection: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Request {
+    method: String,
+    url: String,
+    #[serde(default)]
+    http_version: String,
+    #[serde(default)]
+    headers: Vec<Header>,
+    #[serde(default)]
+    post_data: Option<PostData>,
+    #[serde(default = "unknown_size")]
+    headers_size: i64,
+    #[serde(default = "unknown_size")]
+    body_size: i64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Response {
+    #[serde(default)]
+    status: u16,
+    #[serde(default)]
+    http_version: String,
+    #[serde(default)]
+    headers: Vec<Header>,
+    #[serde(default)]
+    content: Option<Content>,
+    #[serde(default = "unknown_size")]
+    headers_size: i64,
+    #[serde(default = "unknown_size")]
+    body_size: i64,
+}
+
+#[derive(Deserialize)]
+struct Header {
+    name: String,
+    value: String,
+}
+
+#[derive(Deserialize)]
+struct PostData {
+    #[serde(default)]
+    text: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Content {
+    #[serde(default)]
+    text: Option<String>,
+    /// `"base64"` for binary bodies.
+    #[serde(default)]
+    encoding: Option<String>,
+}
+
+/// Phase durations in milliseconds; `-1` means not applicable.
+#[derive(Deserialize)]
+struct Timings {
+    #[serde(default = "not_applicable")]
+    dns: f64,
+    /// Includes `ssl`.
+    #[serde(default = "not_applicable")]
+    connect: f64,
+    #[serde(default = "not_applicable")]
+    ssl: f64,
+    #[serde(default = "not_applicable")]
+    send: f64,
+    #[serde(default = "not_applicable")]
+    wait: f64,
+}
+
+fn unknown_size() -> i64 {
+    -1
+}
+
+fn not_applicable() -> f64 {
+    -1.0
+}
+
+/// HTTP traces from a HAR file, in the file's entry order.
+pub struct HarFile {
+    entries: std::vec::IntoIter<Entry>,
+    entry_count: u64,
+}
+
+impl HarFile {
+    /// Open and parse a HAR file.
+    pub fn open(path: &Path) -> Result<Self, CaptureError> {
+        let file = File::open(path)
+            .map_err(|e| CaptureError::Other(format!("{}: {e}", path.display())))?;
+        Self::from_reader(file).map_err(|e| CaptureError::Other(format!("{}: {e}", path.display())))
+    }
+
+    /// Parse a HAR document from `reader`.
+    pub fn from_reader(mut reader: impl Read) -> Result<Self, CaptureError> {
+        let mut data = Vec::new();
+        reader
+            .read_to_end(&mut data)
+            .map_err(|e| CaptureError::Other(format!("reading HAR file: {e}")))?;
+        // Some Windows tools write a byte order mark.
+        let json = data.strip_prefix(b"\xef\xbb\xbf").unwrap_or(&data);
+        let har: Har = serde_json::from_slice(json)
+            .map_err(|e| CaptureError::Other(format!("not a HAR file: {e}")))?;
+        Ok(Self {
+            entry_count: har.log.entries.len() as u64,
+            entries: har.log.entries.into_iter(),
+        })
+    }
+
+    /// Entries in the file, including skipped ones.
+    pub fn entry_count(&self) -> u64 {
+        self.entry_count
+    }
+}
+
+impl Iterator for HarFile {
+    type Item = Result<HttpTrace, CaptureError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for entry in self.entries.by_ref() {
+            match entry_to_trace(entry) {
+                Ok(trace) => return Some(Ok(trace)),
+                Err(e) => warn!("skipping HAR entry: {e}"),
+            }
+        }
+        None
+    }
+}
+
+fn entry_to_trace(entry: Entry) -> Result<HttpTrace, String> {
+    let Entry {
+        started_date_time,
+        time,
+        request,
+        response,
+        timings,
+        server_ip_address,
+        connection,
+    } = entry;
+    let method: HttpMethod = request
+        .method
+        .parse()
+        .map_err(|e| format!("{} {}: {e}", request.method, request.url))?;
+    let timestamp = parse_datetime(&started_date_time).ok_or_else(|| {
+        format!(
+            "{} {}: invalid startedDateTime {started_date_time:?}",
+            request.method, request.url
+        )
+    })?;
+
+    let request_headers = collect_headers(request.headers);
+    let response_headers = collect_headers(response.headers);
+    let request_body = request
+        .post_data
+        .and_then(|p| p.text)
+        .map(String::into_bytes);
+    let response_body = response.content.and_then(|c| match (c.text, c.encoding) {
+        (Some(text), Some(encoding)) if encoding.eq_ignore_ascii_case("base64") => {
+            Some(B64.decode(&text).unwrap_or_else(|_| text.into_bytes()))
+        }
+        (text, _) => text.map(String::into_bytes),
+    });
+    let (request_body, request_body_encoding) = keep_body(&request_headers, request_body);
+    let (response_body, response_body_encoding) = keep_body(&response_headers, response_body);
+
+    let (trace_id, span_id, parent_span_id) = match request_headers
+        .get("traceparent")
+        .and_then(|value| parse_traceparent(value))
+    {
+        Some((trace_id, parent)) => (trace_id, SpanId(rand_bytes::<8>()), Some(parent)),
+        None => (TraceId(rand_bytes::<16>()), SpanId(rand_bytes::<8>()), None),
+    };
+    let version = if response.http_version.is_empty() {
+        &request.http_version
+    } else {
+        &response.http_version
+    };
+
+    Ok(HttpTrace {
+        span_id,
+        trace_id,
+        parent_span_id,
+        method,
+        dest_addr: server_ip_address
+            .filter(|ip| !ip.is_empty())
+            .map(|ip| dest_addr(&ip, &request.url)),
+        url: request.url,
+        request_headers,
+        request_body,
+        status_code: response.status,
+        response_headers,
+        response_trailers: HashMap::new(),
+        response_body,
+        timestamp,
+        duration: millis(time).unwrap_or_default(),
+        source_addr: None,
+        protocol_version: protocol_version(version),
+        request_body_file: None,
+        response_body_file: None,
+        connection_id: connection.and_then(|c| match c {
+            serde_json::Value::String(s) if !s.is_empty() => Some(s),
+            serde_json::Value::Number(n) => Some(n.to_string()),
+            _ => None,
+        }),
+        process: None,
+        thread_id: None,
+        tls: None,
+        timing: timings.map(timing_phases),
+        request_size: wire_size(request.headers_size, request.body_size),
+        response_size: wire_size(response.headers_size, response.body_size),
+        fault: None,
+        replay: None,
+        request_body_encoding,
+        response_body_encoding,
+        elasticsearch: None,
+        soap: None,
+        graphql: None,
+    })
+}
+
+/// Lower-cased headers, repeated ones joined with `, ` as on the wire.
+fn collect_headers(headers: Vec<Header>) -> HashMap<String, String> {
+    let mut map: HashMap<String, String> = HashMap::new();
+    for header in headers {
+        if header.name.starts_with(':') {
+            continue;
+        }
+        map.entry(header.name.to_ascii_lowercase())
+            .and_modify(|existing| {
+                existing.push_str(", ");
+                existing.push_str(&header.value);
+            })
+            .or_insert(header.value);
+    }
+    map
+}
+
+/// Cuts a body to [`MAX_BODY_SIZE`]. HAR bodies are stored decoded, so a
+/// `Content-Encoding` header only records how the body was sent.
+fn keep_body(
+    headers: &HashMap<String, String>,
+    body: Option<Vec<u8>>,
+) -> (Option<Vec<u8>>, Option<BodyEncoding>) {
+    let Some(mut body) = body.filter(|b| !b.is_empty()) else {
+        return (None, None);
+    };
+    let truncated = body.len() > MAX_BODY_SIZE;
+    body.truncate(MAX_BODY_SIZE);
+    let encoding = headers
+        .get("content-encoding")
+        .map(|e| e.trim())
+        .filter(|e| !e.is_empty() && !e.eq_ignore_ascii_case("identity"))
+        .map(|e| BodyEncoding {
+            encoding: e.to_string(),
+            truncated,
+        });
+    (Some(body), encoding)
+}
+
+/// `IP:PORT` of the server, with the port from the URL.
+fn dest_addr(ip: &str, url: &str) -> String {
+    let (scheme, rest) = url.split_once("://").unwrap_or(("http", url));
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or("");
+    let authority = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    let port = authority
+        .rsplit_once(':')
+        .and_then(|(_, port)| port.parse::<u16>().ok())
+        .unwrap_or(if scheme.eq_ignore_ascii_case("https") {
+            443
+        } else {
+            80
+        });
+    // Firefox writes IPv6 addresses bracketed, Chrome bare.
+    let ip = ip.trim_start_matches('[').trim_end_matches(']');
+    if ip.contains(':') {
+        format!("[{ip}]:{port}")
+    } else {
+        format!("{ip}:{port}")
+    }
+}
+
+/// Phantom's spelling of a HAR `httpVersion` (`http/2.0`, `h3`, …).
+fn protocol_version(version: &str) -> String {
+    match version.to_ascii_uppercase().as_str() {
+        "" => "HTTP/1.1".to_string(),
+        "H2" | "HTTP/2" | "HTTP/2.0" => "HTTP/2".to_string(),
+        "H3" | "HTTP/3" | "HTTP/3.0" => "HTTP/3".to_string(),
+        other => other.to_string(),
+    }
+}
+
+fn timing_phases(t: Timings) -> TimingPhases {
+    let ssl = millis(t.ssl);
+    // HAR's connect includes the TLS handshake; phantom's does not.
+    let connect = millis(t.connect).map(|c| c.saturating_sub(ssl.unwrap_or_default()));
+    let ttfb = millis(t.wait).map(|wait| {
+        [t.dns, t.connect, t.send]
+            .into_iter()
+            .filter_map(millis)
+            .sum::<Duration>()
+            + wait
+    });
+    TimingPhases {
+        dns: millis(t.dns),
+        connect,
+        tls_handshake: ssl,
+        ttfb,
+    }
+}
+
+/// A HAR millisecond value; negative means unknown.
+fn millis(ms: f64) -> Option<Duration> {
+    (ms.is_finite() && ms >= 0.0).then(|| Duration::from_secs_f64(ms / 1000.0))
+}
+
+fn wire_size(headers: i64, body: i64) -> Option<u64> {
+    (headers >= 0 && body >= 0).then(|| (headers + body) as u64)
+}
+
+/// Parses an ISO 8601 date-time as HAR files and proxy logs write it, e.g.
+/// `2024-05-01T12:34:56.789Z` or `2024-05-01T14:34:56.789+02:00`.
+pub(crate) fn parse_datetime(s: &str) -> Option<SystemTime> {
+    let (date, rest) = s.split_once(['T', 't', ' '])?;
+    let mut parts = date.splitn(3, '-');
+    let year: i64 = parts.next()?.parse().ok()?;
+    let month: u32 = parts.next()?.parse().ok()?;
+    let day: u32 = parts.next()?.parse().ok()?;
+
+    let (time, offset) = match rest.strip_suffix(['Z', 'z']) {
+        Some(time) => (time, 0),
+        None => {
+            let i = rest.rfind(['+', '-'])?;
+            let (time, offset) = rest.split_at(i);
+            let (hours, minutes) = match offset[1..].split_once(':') {
+                Some((h, m)) => (h, m),
+                None => offset[1..].split_at_checked(2)?,
+            };
+            let secs = hours.parse::<i64>().ok()? * 3600 + minutes.parse::<i64>().ok()? * 60;
+            (time, if offset.starts_with('-') { -secs } else { secs })
+        }
+    };
+    let (hms, fraction) = time.split_once('.').unwrap_or((time, ""));
+    let mut parts = hms.splitn(3, ':');
+    let hour: i64 = parts.next()?.parse().ok()?;
+    let minute: i64 = parts.next()?.parse().ok()?;
+    let second: i64 = parts.next()?.parse().ok()?;
+    if !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 60
+        || !fraction.bytes().all(|b| b.is_ascii_digit())
+    {
+        return None;
+    }
+    let nanos = format!("{:0<9}", &fraction[..fraction.len().min(9)])
+        .parse::<u32>()
+        .ok()?;
+
+    let secs =
+        days_from_civil(year, month, day) * 86_400 + hour * 3600 + minute * 60 + second - offset;
+    u64::try_from(secs)
+        .ok()
+        .map(|secs| UNIX_EPOCH + Duration::new(secs, nanos))
+}
+
+/// (year, month, day) → days since 1970-01-01. Howard Hinnant's algorithm.
+fn days_from_civil(y: i64, m: u32, d: u32) -> i64 {
+    let y = y - i64::from(m <= 2);
+    let era = y.div_euclid(400);
+    let yoe = y.rem_euclid(400);
+    let m = i64::from(m);
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + i64::from(d) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+fn rand_bytes<const N: usize>() -> [u8; N] {
+    let mut buf = [0u8; N];
+    buf.iter_mut().for_each(|b| *b = rand::random());
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HAR: &str = r#"{
+      "log": {
+        "version": "1.2",
+        "creator": { "name": "WebInspector", "version": "537.36" },
+        "entries": [
+          {
+            "startedDateTime": "2024-05-01T12:00:00.250Z",
+            "time": 120.5,
+            "request": {
+              "method": "POST",
+              "url": "https://api.example.com:8443/v1/orders?x=1",
+              "httpVersion": "http/2.0",
+              "headers": [
+                { "name": ":authority", "value": "api.example.com:8443" },
+                { "name": "Content-Type", "value": "application/json" },
+                { "name": "traceparent",
+                  "value": "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01" }
+              ],
+              "postData": { "mimeType": "application/json", "text": "{\"qty\":2}" },
+              "headersSize": -1,
+              "bodySize": 9
+            },
+            "response": {
+              "status": 201,
+              "httpVersion": "http/2.0",
+              "headers": [
+                { "name": "Content-Encoding", "value": "gzip" },
+                { "name": "Set-Cookie", "value": "a=1" },
+                { "name": "set-cookie", "value": "b=2" }
+              ],
+              "content": { "size": 11, "mimeType": "application/json", "text": "{\"id\":\"7\"}" },
+              "headersSize": 120,
+              "bodySize": 40
+            },
+            "timings": { "blocked": 1, "dns": 10, "connect": 30, "ssl": 20,
+                         "send": 0.5, "wait": 60, "receive": 19 },
+            "serverIPAddress": "93.184.216.34",
+            "connection": "4711"
+          },
+          {
+            "startedDateTime": "2024-05-01T14:00:01+02:00",
+            "time": 5,
+            "request": { "method": "PROPFIND", "url": "http://dav.test/", "headers": [] },
+            "response": { "status": 207, "headers": [] }
+          },
+          {
+            "startedDateTime": "2024-05-01T14:00:01.5+02:00",
+            "time": -1,
+            "request": { "method": "GET", "url": "http://[::1]/logo.png",
+                         "httpVersion": "HTTP/1.1", "headers": [] },
+            "response": {
+              "status": 200, "httpVersion": "HTTP/1.1", "headers": [],
+              "content": { "size": 3, "mimeType": "image/png",
+                           "text": "iVBO", "encoding": "base64" }
+            },
+            "serverIPAddress": "[::1]",
+            "connection": 12
+          }
+        ]
+      }
+    }"#;
+
+    fn read(har: &str) -> (Vec<HttpTrace>, u64) {
+        let mut file = HarFile::from_reader(har.as_bytes()).unwrap();
+        let traces = file.by_ref().collect::<Result<Vec<_>, _>>().unwrap();
+        (traces, file.entry_count())
+    }
+
+    #[test]
+    fn test_converts_entries() {
+        let (traces, entries) = read(HAR);
+        assert_eq!(entries, 3);
+        assert_eq!(traces.len(), 2, "PROPFIND is skipped");
+
+        let t = &traces[0];
+        assert_eq!(t.method, HttpMethod::Post);
+        assert_eq!(t.url, "https://api.example.com:8443/v1/orders?x=1");
+        assert_eq!(t.status_code, 201);
+        assert_eq!(t.protocol_version, "HTTP/2");
+        assert!(!t.request_headers.contains_key(":authority"));
+        assert_eq!(t.request_headers["content-type"], "application/json");
+        assert_eq!(t.response_headers["set-cookie"], "a=1, b=2");
+        assert_eq!(t.request_body.as_deref(), Some(&b"{\"qty\":2}"[..]));
+        assert_eq!(t.response_body.as_deref(), Some(&b"{\"id\":\"7\"}"[..]));
+        assert_eq!(
+            t.response_body_encoding,
+            Some(BodyEncoding {
+                encoding: "gzip".into(),
+                truncated: false
+            })
+        );
+        assert_eq!(
+            t.timestamp,
+            UNIX_EPOCH + Duration::from_millis(1_714_564_800_250)
+        );
+        assert_eq!(t.duration, Duration::from_micros(120_500));
+        assert_eq!(t.dest_addr.as_deref(), Some("93.184.216.34:8443"));
+        assert_eq!(t.connection_id.as_deref(), Some("4711"));
+        assert_eq!(t.request_size, None);
+        assert_eq!(t.response_size, Some(160));
+        assert_eq!(
+            t.trace_id,
+            TraceId::from_hex("0af7651916cd43dd8448eb211c80319c").unwrap()
+        );
+        assert_eq!(t.parent_span_id, SpanId::from_hex("b7ad6b7169203331"));
+
+        let timing = t.timing.unwrap();
+        assert_eq!(timing.dns, Some(Duration::from_millis(10)));
+        assert_eq!(timing.connect, Some(Duration::from_millis(10)));
+        assert_eq!(timing.tls_handshake, Some(Duration::from_millis(20)));
+        assert_eq!(timing.ttfb, Some(Duration::from_micros(100_500)));
+    }
+
+    #[test]
+    fn test_base64_body_and_ipv6_server() {
+        let (traces, _) = read(HAR);
+        let t = &traces[1];
+        assert_eq!(t.response_body.as_deref(), Some(&[0x89, b'P', b'N'][..]));
+        assert_eq!(t.response_body_encoding, None);
+        assert_eq!(t.dest_addr.as_deref(), Some("[::1]:80"));
+        assert_eq!(t.connection_id.as_deref(), Some("12"));
+        assert_eq!(t.duration, Duration::ZERO);
+        assert!(t.timing.is_none());
+        assert_eq!(
+            t.timestamp,
+            UNIX_EPOCH + Duration::from_millis(1_714_564_801_500)
+        );
+    }
+
+    #[test]
+    fn test_parse_datetime() {
+        let at = |ms: u64| Some(UNIX_EPOCH + Duration::from_millis(ms));
+        assert_eq!(parse_datetime("1970-01-01T00:00:00Z"), at(0));
+        assert_eq!(
+            parse_datetime("2024-02-29T23:59:59.999Z"),
+            at(1_709_251_199_999)
+        );
+        assert_eq!(
+            parse_datetime("2024-03-01T01:00:00+0100"),
+            at(1_709_251_200_000)
+        );
+        assert_eq!(
+            parse_datetime("2024-02-29T19:00:00-05:00"),
+            at(1_709_251_200_000)
+        );
+        assert_eq!(
+            parse_datetime("2024-05-01T12:00:00.123456789Z"),
+            Some(UNIX_EPOCH + Duration::new(1_714_564_800, 123_456_789))
+        );
+        assert_eq!(parse_datetime("2024-05-01"), None);
+        assert_eq!(parse_datetime("2024-13-01T00:00:00Z"), None);
+        assert_eq!(parse_datetime("2024-05-01T00:00:00"), None);
+        assert_eq!(parse_datetime("2024-05-01T00:00:00.x1Z"), None);
+    }
+
+    #[test]
+    fn test_rejects_non_har() {
+        assert!(HarFile::from_reader(&b"{\"foo\": 1}"[..]).is_err());
+        assert!(HarFile::from_reader(&b"\xd4\xc3\xb2\xa1"[..]).is_err());
+        let bom = b"\xef\xbb\xbf{\"log\": {\"entries\": []}}";
+        assert_eq!(HarFile::from_reader(&bom[..]).unwrap().entry_count(), 0);
+    }
+}
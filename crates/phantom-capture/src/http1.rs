@@ -0,0 +1,744 @@
+//! HTTP/1.x reassembly from raw connection bytes.
+//!
+//! Backends that observe a connection's bytes rather than whole exchanges
+//! (the eBPF probes, packet captures and capture files) feed both directions
+//! of each connection to an [`Http1Connection`]. It parses requests and
+//! responses, including pipelined ones, and pairs them into [`HttpTrace`]s.
+//! Bodies are de-chunked, kept up to [`MAX_BODY_SIZE`] and decoded per their
+//! `Content-Encoding`; sizes count the bytes as sent.
+//!
+//! A direction whose bytes don't parse as HTTP/1 (TLS records, HTTP/2, a
+//! protocol upgrade) is ignored from then on.
+
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, SystemTime};
+
+use phantom_core::trace::{
+    BodyEncoding, HttpMethod, HttpTrace, ProcessInfo, SpanId, TimingPhases, TlsInfo, TraceId,
+};
+
+use crate::decode::{MAX_DECODED_SIZE, decompress_body};
+
+/// Body bytes kept per message (1 MB), as in the proxy.
+const MAX_BODY_SIZE: usize = 1024 * 1024;
+
+/// A message head longer than this is taken for something other than HTTP.
+const MAX_HEAD_SIZE: usize = 64 * 1024;
+
+const MAX_HEADERS: usize = 128;
+
+/// Which way bytes travelled, seen from the client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Direction {
+    /// Client to server: requests.
+    Outbound,
+    /// Server to client: responses.
+    Inbound,
+}
+
+/// One client connection being reassembled.
+pub(crate) struct Http1Connection {
+    scheme: &'static str,
+    source_addr: Option<String>,
+    dest_addr: Option<String>,
+    connection_id: Option<String>,
+    process: Option<ProcessInfo>,
+    tls: Option<TlsInfo>,
+    requests: Reader,
+    responses: Reader,
+    /// Requests waiting for their responses, oldest first.
+    pending: VecDeque<Message>,
+}
+
+impl Http1Connection {
+    /// A connection carrying `http` or, for decrypted TLS, `https`.
+    pub(crate) fn new(scheme: &'static str) -> Self {
+        Self {
+            scheme,
+            source_addr: None,
+            dest_addr: None,
+            connection_id: None,
+            process: None,
+            tls: None,
+            requests: Reader::new(false),
+            responses: Reader::new(true),
+            pending: VecDeque::new(),
+        }
+    }
+
+    pub(crate) fn set_source_addr(&mut self, addr: String) {
+        self.source_addr = Some(addr);
+    }
+
+    /// The server's address, used for the URL when requests lack `Host`.
+    pub(crate) fn set_dest_addr(&mut self, addr: String) {
+        self.dest_addr = Some(addr);
+    }
+
+    pub(crate) fn set_connection_id(&mut self, id: String) {
+        self.connection_id = Some(id);
+    }
+
+    #[cfg(target_os = "linux")]
+    pub(crate) fn set_process(&mut self, process: ProcessInfo) {
+        self.process = Some(process);
+    }
+
+    pub(crate) fn set_tls(&mut self, tls: TlsInfo) {
+        self.tls = Some(tls);
+    }
+
+    /// Feeds bytes seen at `at`, returning the exchanges they completed.
+    pub(crate) fn push(
+        &mut self,
+        direction: Direction,
+        data: &[u8],
+        at: SystemTime,
+    ) -> Vec<HttpTrace> {
+        match direction {
+            Direction::Outbound => {
+                self.requests.feed(data, at);
+                self.read_requests();
+                Vec::new()
+            }
+            Direction::Inbound => {
+                self.responses.feed(data, at);
+                self.read_responses(false)
+            }
+        }
+    }
+
+    /// Accounts for `len` bytes that went by without being seen (cut off
+    /// by the capture). Within a body they count towards its size; anywhere
+    /// else the direction can no longer be followed.
+    pub(crate) fn skip(
+        &mut self,
+        direction: Direction,
+        len: usize,
+        at: SystemTime,
+    ) -> Vec<HttpTrace> {
+        match direction {
+            Direction::Outbound => {
+                self.requests.skip(len, at);
+                self.read_requests();
+                Vec::new()
+            }
+            Direction::Inbound => {
+                self.responses.skip(len, at);
+                self.read_responses(false)
+            }
+        }
+    }
+
+    /// The connection closed at `at`: a response delimited by the close is
+    /// complete. Anything else still in flight is dropped.
+    pub(crate) fn close(&mut self, at: SystemTime) -> Vec<HttpTrace> {
+        self.responses.last_seen = Some(at);
+        self.read_responses(true)
+    }
+
+    fn read_requests(&mut self) {
+        while let Some(request) = self.requests.next_message(false, false) {
+            self.pending.push_back(request);
+        }
+    }
+
+    fn read_responses(&mut self, closed: bool) -> Vec<HttpTrace> {
+        let mut traces = Vec::new();
+        loop {
+            let is_head = self
+                .pending
+                .front()
+                .is_some_and(|request| request.method() == Some(HttpMethod::Head));
+            let Some(response) = self.responses.next_message(is_head, closed) else {
+                break;
+            };
+            let status = response.status().unwrap_or(0);
+            // Interim responses (100 Continue, 103 Early Hints) precede the
+            // real one.
+            if (100..200).contains(&status) && status != 101 {
+                continue;
+            }
+            let Some(request) = self.pending.pop_front() else {
+                // Its request was missed; nothing to pair it with.
+                continue;
+            };
+            traces.push(self.trace(request, response));
+            if status == 101 {
+                // Switched to another protocol.
+                self.requests.broken = true;
+                self.responses.broken = true;
+                self.pending.clear();
+                break;
+            }
+        }
+        traces
+    }
+
+    fn trace(&self, request: Message, response: Message) -> HttpTrace {
+        let method = request.method().unwrap_or(HttpMethod::Get);
+        let status_code = response.status().unwrap_or(0);
+        let target = match &request.start {
+            Start::Request { target, .. } => target.as_str(),
+            Start::Response { .. } => "/",
+        };
+        let url = if target.contains("://") {
+            target.to_string()
+        } else {
+            let host = request
+                .headers
+                .get("host")
+                .or(self.dest_addr.as_ref())
+                .map_or("unknown", String::as_str);
+            format!("{}://{host}{target}", self.scheme)
+        };
+        let (request_body, request_body_encoding) = decompress(&request.headers, request.body);
+        let (response_body, response_body_encoding) = decompress(&response.headers, response.body);
+        HttpTrace {
+            span_id: SpanId(rand_bytes::<8>()),
+            trace_id: TraceId(rand_bytes::<16>()),
+            parent_span_id: None,
+            method,
+            url,
+            request_headers: request.headers,
+            request_body,
+            status_code,
+            response_headers: response.headers,
+            response_trailers: response.trailers,
+            response_body,
+            timestamp: request.started,
+            duration: elapsed(request.started, response.finished),
+            source_addr: self.source_addr.clone(),
+            dest_addr: self.dest_addr.clone(),
+            protocol_version: response.version.to_string(),
+            request_body_file: None,
+            response_body_file: None,
+            connection_id: self.connection_id.clone(),
+            process: self.process.clone(),
+            thread_id: None,
+            tls: self.tls.clone(),
+            timing: Some(TimingPhases {
+                ttfb: Some(elapsed(request.started, response.started)),
+                ..TimingPhases::default()
+            }),
+            request_size: Some(request.wire_size),
+            response_size: Some(response.wire_size),
+            fault: None,
+            replay: None,
+            request_body_encoding,
+            response_body_encoding,
+            elasticsearch: None,
+            soap: None,
+            graphql: None,
+        }
+    }
+}
+
+fn rand_bytes<const N: usize>() -> [u8; N] {
+    let mut buf = [0u8; N];
+    buf.iter_mut().for_each(|b| *b = rand::random());
+    buf
+}
+
+fn elapsed(from: SystemTime, to: SystemTime) -> Duration {
+    to.duration_since(from).unwrap_or_default()
+}
+
+/// Decodes a captured body per its `Content-Encoding`; an empty body is
+/// `None`.
+fn decompress(
+    headers: &HashMap<String, String>,
+    body: Vec<u8>,
+) -> (Option<Vec<u8>>, Option<BodyEncoding>) {
+    if body.is_empty() {
+        return (None, None);
+    }
+    match decompress_body(headers, &body, MAX_DECODED_SIZE) {
+        Some((decoded, encoding)) => (Some(decoded), Some(encoding)),
+        None => (Some(body), None),
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Messages
+// ─────────────────────────────────────────────────────────────────────────────
+
+enum Start {
+    Request { method: String, target: String },
+    Response { status: u16 },
+}
+
+struct Message {
+    start: Start,
+    version: &'static str,
+    /// Lower-cased names; repeated headers are joined with `, `.
+    headers: HashMap<String, String>,
+    trailers: HashMap<String, String>,
+    /// De-chunked body, up to [`MAX_BODY_SIZE`].
+    body: Vec<u8>,
+    wire_size: u64,
+    /// When the first and last bytes were seen.
+    started: SystemTime,
+    finished: SystemTime,
+}
+
+impl Message {
+    fn method(&self) -> Option<HttpMethod> {
+        match &self.start {
+            Start::Request { method, .. } => method.parse().ok(),
+            Start::Response { .. } => None,
+        }
+    }
+
+    fn status(&self) -> Option<u16> {
+        match self.start {
+            Start::Response { status } => Some(status),
+            Start::Request { .. } => None,
+        }
+    }
+
+    fn keep(&mut self, data: &[u8]) {
+        let room = MAX_BODY_SIZE.saturating_sub(self.body.len());
+        self.body.extend_from_slice(&data[..data.len().min(room)]);
+    }
+
+    /// How the body is delimited (RFC 9112 §6.3). `bodiless` is set for
+    /// responses to `HEAD`.
+    fn framing(&self, bodiless: bool) -> Framing {
+        let status = self.status();
+        if bodiless || status.is_some_and(|s| (100..200).contains(&s) || s == 204 || s == 304) {
+            return Framing::Length(0);
+        }
+        let chunked = self
+            .headers
+            .get("transfer-encoding")
+            .is_some_and(|te| te.to_ascii_lowercase().trim_end().ends_with("chunked"));
+        if chunked {
+            return Framing::Chunked(Chunk::Size);
+        }
+        match self
+            .headers
+            .get("content-length")
+            .and_then(|len| len.split(',').next()?.trim().parse().ok())
+        {
+            Some(len) => Framing::Length(len),
+            // A request without a length has no body; a response runs until
+            // the connection closes.
+            None if status.is_none() => Framing::Length(0),
+            None => Framing::UntilClose,
+        }
+    }
+}
+
+enum Framing {
+    /// Body bytes still to come.
+    Length(u64),
+    Chunked(Chunk),
+    UntilClose,
+}
+
+enum Chunk {
+    /// Expecting a chunk-size line.
+    Size,
+    /// Data bytes of the current chunk still to come.
+    Data(u64),
+    /// Expecting the CRLF after a chunk's data.
+    DataEnd,
+    /// Reading trailer lines after the last chunk.
+    Trailers,
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// One direction of a connection
+// ─────────────────────────────────────────────────────────────────────────────
+
+struct Reader {
+    /// Whether this direction carries responses rather than requests.
+    response: bool,
+    /// Bytes not yet parsed.
+    buf: Vec<u8>,
+    /// The message whose body is being read, and how it ends.
+    current: Option<(Message, Framing)>,
+    /// When bytes were last fed, the time a message being read ends at.
+    last_seen: Option<SystemTime>,
+    /// When the unparsed bytes in `buf` started arriving.
+    buf_since: Option<SystemTime>,
+    /// Set once the bytes stop making sense as HTTP/1.
+    broken: bool,
+}
+
+impl Reader {
+    fn new(response: bool) -> Self {
+        Self {
+            response,
+            buf: Vec::new(),
+            current: None,
+            last_seen: None,
+            buf_since: None,
+            broken: false,
+        }
+    }
+
+    fn feed(&mut self, data: &[u8], at: SystemTime) {
+        if self.broken {
+            return;
+        }
+        if self.buf.is_empty() {
+            self.buf_since = Some(at);
+        }
+        self.buf.extend_from_slice(data);
+        self.last_seen = Some(at);
+    }
+
+    fn skip(&mut self, len: usize, at: SystemTime) {
+        self.last_seen = Some(at);
+        let len = len as u64;
+        let skippable = match &mut self.current {
+            _ if !self.buf.is_empty() => false,
+            Some((_, Framing::Length(remaining))) if *remaining >= len => {
+                *remaining -= len;
+                true
+            }
+            Some((_, Framing::Chunked(chunk))) => match *chunk {
+                Chunk::Data(remaining) if remaining > len => {
+                    *chunk = Chunk::Data(remaining - len);
+                    true
+                }
+                Chunk::Data(remaining) if remaining == len => {
+                    *chunk = Chunk::DataEnd;
+                    true
+                }
+                _ => false,
+            },
+            Some((_, Framing::UntilClose)) => true,
+            _ => false,
+        };
+        if let Some((message, _)) = self.current.as_mut().filter(|_| skippable) {
+            message.wire_size += len;
+        } else {
+            self.broken = true;
+            self.buf.clear();
+            self.current = None;
+        }
+    }
+
+    /// Parses the next complete message from the buffered bytes, if there
+    /// is one. `bodiless` is set when a response has no body whatever its
+    /// headers say; `closed` once the connection has closed.
+    fn next_message(&mut self, bodiless: bool, closed: bool) -> Option<Message> {
+        if self.broken {
+            return None;
+        }
+        if self.current.is_none() {
+            let message = self.parse_head()?;
+            let framing = message.framing(bodiless);
+            self.current = Some((message, framing));
+        }
+        let done = self.read_body(closed);
+        if !done {
+            return None;
+        }
+        let (mut message, _) = self.current.take()?;
+        message.finished = self.last_seen.unwrap_or(message.started);
+        Some(message)
+    }
+
+    fn parse_head(&mut self) -> Option<Message> {
+        let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+        let (parsed, start, version, fields) = if self.response {
+            let mut res = httparse::Response::new(&mut headers);
+            let parsed = res.parse(&self.buf);
+            let start = Start::Response {
+                status: res.code.unwrap_or(0),
+            };
+            (parsed, start, res.version, collect_headers(res.headers))
+        } else {
+            let mut req = httparse::Request::new(&mut headers);
+            let parsed = req.parse(&self.buf);
+            let start = Start::Request {
+                method: req.method.unwrap_or_default().to_string(),
+                target: req.path.unwrap_or_default().to_string(),
+            };
+            (parsed, start, req.version, collect_headers(req.headers))
+        };
+        let head_len = match parsed {
+            Ok(httparse::Status::Complete(len)) => len,
+            Ok(httparse::Status::Partial) if self.buf.len() <= MAX_HEAD_SIZE => return None,
+            _ => {
+                self.broken = true;
+                self.buf.clear();
+                return None;
+            }
+        };
+        self.buf.drain(..head_len);
+        let started = self
+            .buf_since
+            .or(self.last_seen)
+            .unwrap_or_else(SystemTime::now);
+        Some(Message {
+            start,
+            version: if version == Some(0) {
+                "HTTP/1.0"
+            } else {
+                "HTTP/1.1"
+            },
+            headers: fields,
+            trailers: HashMap::new(),
+            body: Vec::new(),
+            wire_size: head_len as u64,
+            started,
+            finished: started,
+        })
+    }
+
+    /// Consumes body bytes of the current message; true once it is done.
+    fn read_body(&mut self, closed: bool) -> bool {
+        let Some((message, framing)) = &mut self.current else {
+            return false;
+        };
+        let mut used = 0;
+        let done = loop {
+            let rest = &self.buf[used..];
+            match framing {
+                Framing::Length(remaining) => {
+                    let n = rest.len().min(*remaining as usize);
+                    message.keep(&rest[..n]);
+                    used += n;
+                    *remaining -= n as u64;
+                    break *remaining == 0;
+                }
+                Framing::UntilClose => {
+                    message.keep(rest);
+                    used += rest.len();
+                    break closed;
+                }
+                Framing::Chunked(chunk) => match chunk {
+                    Chunk::Size => {
+                        let Some(line) = take_line(rest) else {
+                            break false;
+                        };
+                        let size = std::str::from_utf8(&rest[..line - 2]).ok().and_then(|l| {
+                            u64::from_str_radix(l.split(';').next()?.trim(), 16).ok()
+                        });
+                        let Some(size) = size else {
+                            self.broken = true;
+                            break false;
+                        };
+                        used += line;
+                        *chunk = if size == 0 {
+                            Chunk::Trailers
+                        } else {
+                            Chunk::Data(size)
+                        };
+                    }
+                    Chunk::Data(remaining) => {
+                        if rest.is_empty() {
+                            break false;
+                        }
+                        let n = rest.len().min(*remaining as usize);
+                        message.keep(&rest[..n]);
+                        used += n;
+                        *remaining -= n as u64;
+                        if *remaining == 0 {
+                            *chunk = Chunk::DataEnd;
+                        }
+                    }
+                    Chunk::DataEnd => {
+                        let Some(line) = take_line(rest) else {
+                            break false;
+                        };
+                        used += line;
+                        *chunk = Chunk::Size;
+                    }
+                    Chunk::Trailers => {
+                        let Some(line) = take_line(rest) else {
+                            break false;
+                        };
+                        used += line;
+                        if line == 2 {
+                            break true;
+                        }
+                        if let Some((name, value)) = std::str::from_utf8(&rest[..line - 2])
+                            .ok()
+                            .and_then(|l| l.split_once(':'))
+                        {
+                            message
+                                .trailers
+                                .insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+                        }
+                    }
+                },
+            }
+        };
+        message.wire_size += used as u64;
+        self.buf.drain(..used);
+        if self.broken {
+            self.buf.clear();
+            self.current = None;
+            return false;
+        }
+        if done && !self.buf.is_empty() {
+            // A pipelined message follows.
+            self.buf_since = self.last_seen;
+        }
+        done
+    }
+}
+
+/// Length of the CRLF-terminated line at the start of `buf`, with the CRLF.
+fn take_line(buf: &[u8]) -> Option<usize> {
+    buf.windows(2).position(|w| w == b"\r\n").map(|i| i + 2)
+}
+
+fn collect_headers(headers: &[httparse::Header<'_>]) -> HashMap<String, String> {
+    let mut map: HashMap<String, String> = HashMap::new();
+    for header in headers {
+        let value = String::from_utf8_lossy(header.value).into_owned();
+        map.entry(header.name.to_ascii_lowercase())
+            .and_modify(|existing| {
+                existing.push_str(", ");
+                existing.push_str(&value);
+            })
+            .or_insert(value);
+    }
+    map
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(ms: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn test_request_and_response_with_length() {
+        let mut conn = Http1Connection::new("http");
+        conn.set_dest_addr("10.0.0.1:80".to_string());
+        let request =
+            b"POST /users HTTP/1.1\r\nHost: api.test\r\nContent-Length: 7\r\n\r\n{\"a\":1}";
+        assert!(conn.push(Direction::Outbound, request, at(0)).is_empty());
+        assert!(
+            conn.push(
+                Direction::Inbound,
+                b"HTTP/1.1 201 Created\r\nContent-Length: 2\r\n\r\n",
+                at(30)
+            )
+            .is_empty()
+        );
+        let traces = conn.push(Direction::Inbound, b"ok", at(40));
+        assert_eq!(traces.len(), 1);
+        let trace = &traces[0];
+        assert_eq!(trace.method, HttpMethod::Post);
+        assert_eq!(trace.url, "http://api.test/users");
+        assert_eq!(trace.request_body.as_deref(), Some(&b"{\"a\":1}"[..]));
+        assert_eq!(trace.status_code, 201);
+        assert_eq!(trace.response_body.as_deref(), Some(&b"ok"[..]));
+        assert_eq!(trace.duration, Duration::from_millis(40));
+        assert_eq!(trace.timing.unwrap().ttfb, Some(Duration::from_millis(30)));
+        assert_eq!(trace.request_size, Some(request.len() as u64));
+        assert_eq!(trace.dest_addr.as_deref(), Some("10.0.0.1:80"));
+        assert_eq!(trace.protocol_version, "HTTP/1.1");
+    }
+
+    #[test]
+    fn test_chunked_response_split_across_reads() {
+        let mut conn = Http1Connection::new("https");
+        conn.push(
+            Direction::Outbound,
+            b"GET /s HTTP/1.1\r\nHost: a.test\r\n\r\n",
+            at(0),
+        );
+        let response = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n6;x=1\r\n world\r\n0\r\nGrpc-Status: 0\r\n\r\n";
+        let mut traces = Vec::new();
+        for (i, byte) in response.iter().enumerate() {
+            traces.extend(conn.push(Direction::Inbound, &[*byte], at(i as u64)));
+        }
+        assert_eq!(traces.len(), 1);
+        assert_eq!(traces[0].url, "https://a.test/s");
+        assert_eq!(
+            traces[0].response_body.as_deref(),
+            Some(&b"hello world"[..])
+        );
+        assert_eq!(traces[0].response_trailers["grpc-status"], "0");
+        assert_eq!(traces[0].response_size, Some(response.len() as u64));
+    }
+
+    #[test]
+    fn test_pipelined_requests_pair_in_order() {
+        let mut conn = Http1Connection::new("http");
+        conn.push(
+            Direction::Outbound,
+            b"HEAD /a HTTP/1.1\r\nHost: h\r\n\r\nGET /b HTTP/1.1\r\nHost: h\r\n\r\n",
+            at(0),
+        );
+        let traces = conn.push(
+            Direction::Inbound,
+            b"HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 200 OK\r\nContent-Length: 100\r\n\r\nHTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n",
+            at(5),
+        );
+        let urls: Vec<_> = traces
+            .iter()
+            .map(|t| (t.url.as_str(), t.status_code))
+            .collect();
+        assert_eq!(urls, [("http://h/a", 200), ("http://h/b", 404)]);
+        assert_eq!(traces[0].response_body, None);
+    }
+
+    #[test]
+    fn test_response_until_close() {
+        let mut conn = Http1Connection::new("http");
+        conn.push(Direction::Outbound, b"GET / HTTP/1.0\r\n\r\n", at(0));
+        assert!(
+            conn.push(Direction::Inbound, b"HTTP/1.0 200 OK\r\n\r\nbody", at(1))
+                .is_empty()
+        );
+        let traces = conn.close(at(2));
+        assert_eq!(traces.len(), 1);
+        assert_eq!(traces[0].url, "http://unknown/");
+        assert_eq!(traces[0].response_body.as_deref(), Some(&b"body"[..]));
+        assert_eq!(traces[0].protocol_version, "HTTP/1.0");
+    }
+
+    #[test]
+    fn test_skipped_body_bytes_count_towards_size() {
+        let mut conn = Http1Connection::new("http");
+        conn.push(
+            Direction::Outbound,
+            b"GET / HTTP/1.1\r\nHost: h\r\n\r\n",
+            at(0),
+        );
+        let head = b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\n";
+        conn.push(Direction::Inbound, head, at(1));
+        conn.push(Direction::Inbound, b"abc", at(1));
+        assert!(conn.skip(Direction::Inbound, 5, at(2)).is_empty());
+        let traces = conn.push(Direction::Inbound, b"xy", at(3));
+        assert_eq!(traces[0].response_body.as_deref(), Some(&b"abcxy"[..]));
+        assert_eq!(traces[0].response_size, Some(head.len() as u64 + 10));
+
+        // Outside a body, skipped bytes lose the thread.
+        conn.push(
+            Direction::Outbound,
+            b"GET / HTTP/1.1\r\nHost: h\r\n\r\n",
+            at(4),
+        );
+        conn.skip(Direction::Inbound, 10, at(5));
+        assert!(conn.push(Direction::Inbound, head, at(6)).is_empty());
+    }
+
+    #[test]
+    fn test_non_http_bytes_are_ignored() {
+        let mut conn = Http1Connection::new("http");
+        conn.push(Direction::Outbound, &[0x16, 0x03, 0x01, 0x02, 0x00], at(0));
+        conn.push(Direction::Outbound, b"GET / HTTP/1.1\r\n\r\n", at(1));
+        assert!(
+            conn.push(
+                Direction::Inbound,
+                b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n",
+                at(2)
+            )
+            .is_empty()
+        );
+    }
+}
@@ -0,0 +1,368 @@
+//! Ingest backend: traces sent by agents outside this repository —
+//! cross-platform.
+//!
+//! Listens on a TCP port for the messages phantom-agent sends the ldpreload
+//! backend ([`agent_msg`](crate::agent_msg)), so an agent written for
+//! another runtime (a Java agent, a Node.js hook) can feed phantom without
+//! being built with it. The protocol is documented for agent authors in
+//! `docs/ingest-protocol.md`. Each connection is either:
+//!
+//! - a stream of JSON messages, one per line, or
+//! - HTTP/1.1 requests `POST /v1/traces` whose body holds JSON messages
+//!   (one object, several separated by newlines, or an array of them) or a
+//!   single MessagePack message.
+//!
+//! The first byte tells the two apart: a message starts with `{`, a request
+//! with its method. Messages are dropped when phantom falls behind, as with
+//! the ldpreload datagram socket. There is no authentication; bind to
+//! loopback or a private network.
+
+use std::convert::Infallible;
+use std::net::SocketAddr;
+
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full, LengthLimitError, Limited};
+use hyper::body::Incoming;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use phantom_core::capture::{CaptureBackend, TraceEvent};
+use phantom_core::error::CaptureError;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinSet;
+use tracing::{debug, info, warn};
+
+use crate::agent_msg::{AgentSink, dispatch_agent_msg};
+
+/// The HTTP path agents post messages to.
+pub const INGEST_PATH: &str = "/v1/traces";
+
+/// Largest message line or request body accepted.
+const MAX_MESSAGE: usize = 64 * 1024 * 1024;
+
+pub struct IngestCaptureBackend {
+    addr: String,
+    local_addr: Option<SocketAddr>,
+    shutdown_tx: Option<oneshot::Sender<()>>,
+    task_handle: Option<tokio::task::JoinHandle<()>>,
+}
+
+impl IngestCaptureBackend {
+    /// Create a backend that will listen on `addr` (`HOST:PORT`).
+    pub fn new(addr: String) -> Self {
+        Self {
+            addr,
+            local_addr: None,
+            shutdown_tx: None,
+            task_handle: None,
+        }
+    }
+
+    /// The address agents must send to, once the backend is started; port 0
+    /// is resolved to the port picked.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr
+    }
+}
+
+/// Accepts agent connections until shutdown; dropping `conns` at the end
+/// aborts the readers still running.
+async fn serve(listener: TcpListener, mut shutdown_rx: oneshot::Receiver<()>, sink: AgentSink) {
+    let mut conns = JoinSet::new();
+    loop {
+        tokio::select! {
+            _ = &mut shutdown_rx => break,
+            Some(_) = conns.join_next(), if !conns.is_empty() => {}
+            result = listener.accept() => {
+                match result {
+                    Ok((conn, peer)) => {
+                        conns.spawn(read_connection(conn, peer, sink.clone()));
+                    }
+                    Err(e) => {
+                        warn!("ingest accept error: {e}");
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+async fn read_connection(conn: TcpStream, peer: SocketAddr, sink: AgentSink) {
+    let mut conn = BufReader::new(conn);
+    let first = match conn.fill_buf().await {
+        Ok([first, ..]) => *first,
+        _ => return,
+    };
+    if first.is_ascii_alphabetic() {
+        serve_http(conn, peer, sink).await;
+    } else {
+        debug!("ingest: {peer} streams messages");
+        read_lines(conn, peer, sink).await;
+    }
+}
+
+async fn read_lines(mut conn: BufReader<TcpStream>, peer: SocketAddr, sink: AgentSink) {
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        let read = (&mut conn)
+            .take(MAX_MESSAGE as u64)
+            .read_until(b'\n', &mut line)
+            .await;
+        match read {
+            Ok(0) => break,
+            Ok(n) if n == MAX_MESSAGE && line.last() != Some(&b'\n') => {
+                warn!("ingest: message from {peer} exceeds {MAX_MESSAGE} bytes, closing");
+                break;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                debug!("ingest: reading from {peer}: {e}");
+                break;
+            }
+        }
+        let msg = line.trim_ascii();
+        if msg.is_empty() {
+            continue;
+        }
+        if let Err(e) = dispatch_agent_msg(msg, &sink) {
+            warn!("ingest: failed to parse message from {peer}: {e}");
+        }
+    }
+}
+
+async fn serve_http(conn: BufReader<TcpStream>, peer: SocketAddr, sink: AgentSink) {
+    let service = service_fn(move |req| {
+        let sink = sink.clone();
+        async move { Ok::<_, Infallible>(answer(req, &sink).await) }
+    });
+    if let Err(e) = http1::Builder::new()
+        .serve_connection(TokioIo::new(conn), service)
+        .await
+    {
+        debug!("ingest connection from {peer}: {e}");
+    }
+}
+
+fn reply(status: StatusCode, text: String) -> Response<Full<Bytes>> {
+    Response::builder()
+        .status(status)
+        .header("content-type", "text/plain; charset=utf-8")
+        .body(Full::new(Bytes::from(text)))
+        .expect("valid ingest response")
+}
+
+async fn answer(req: Request<Incoming>, sink: &AgentSink) -> Response<Full<Bytes>> {
+    if req.uri().path() != INGEST_PATH {
+        return reply(
+            StatusCode::NOT_FOUND,
+            format!("phantom ingest: POST messages to {INGEST_PATH}\n"),
+        );
+    }
+    if req.method() != Method::POST {
+        return Response::builder()
+            .status(StatusCode::METHOD_NOT_ALLOWED)
+            .header("allow", "POST")
+            .body(Full::new(Bytes::new()))
+            .expect("valid ingest response");
+    }
+    let body = match Limited::new(req.into_body(), MAX_MESSAGE).collect().await {
+        Ok(collected) => collected.to_bytes(),
+        Err(e) if e.is::<LengthLimitError>() => {
+            return reply(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                format!("phantom ingest: body exceeds {MAX_MESSAGE} bytes\n"),
+            );
+        }
+        Err(e) => {
+            return reply(
+                StatusCode::BAD_REQUEST,
+                format!("phantom ingest: reading body: {e}\n"),
+            );
+        }
+    };
+    match ingest_body(&body, sink) {
+        Ok(n) => {
+            debug!("ingest: took {n} message(s) over HTTP");
+            reply(StatusCode::ACCEPTED, String::new())
+        }
+        Err(e) => {
+            info!("ingest: rejected request: {e}");
+            reply(StatusCode::BAD_REQUEST, format!("phantom ingest: {e}\n"))
+        }
+    }
+}
+
+/// Dispatches the messages in an HTTP request body and returns how many
+/// there were. Messages after a malformed one are still dispatched; the
+/// first error is returned.
+fn ingest_body(body: &[u8], sink: &AgentSink) -> Result<usize, String> {
+    let body = body.trim_ascii();
+    if !body.starts_with(b"{") && !body.starts_with(b"[") {
+        // Anything else is a single MessagePack message.
+        return dispatch_agent_msg(body, sink).map(|()| 1);
+    }
+
+    let mut count = 0;
+    let mut first_error = None;
+    for value in serde_json::Deserializer::from_slice(body).into_iter::<serde_json::Value>() {
+        let value = value.map_err(|e| format!("invalid JSON: {e}"))?;
+        let msgs = match value {
+            serde_json::Value::Array(msgs) => msgs,
+            msg => vec![msg],
+        };
+        for msg in msgs {
+            count += 1;
+            let result = serde_json::to_vec(&msg)
+                .map_err(|e| e.to_string())
+                .and_then(|data| dispatch_agent_msg(&data, sink));
+            if let Err(e) = result {
+                first_error.get_or_insert(format!("message {count}: {e}"));
+            }
+        }
+    }
+    match first_error {
+        Some(e) => Err(e),
+        None => Ok(count),
+    }
+}
+
+impl CaptureBackend for IngestCaptureBackend {
+    fn start(&mut self) -> Result<mpsc::Receiver<TraceEvent>, CaptureError> {
+        let start_failed = |e: std::io::Error| {
+            CaptureError::StartFailed(format!("listening on {}: {e}", self.addr))
+        };
+        let listener = std::net::TcpListener::bind(&self.addr).map_err(start_failed)?;
+        listener.set_nonblocking(true).map_err(start_failed)?;
+        let local_addr = listener.local_addr().map_err(start_failed)?;
+        let listener = TcpListener::from_std(listener).map_err(start_failed)?;
+
+        let (events, events_rx) = mpsc::channel(4096);
+        let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+        let sink = AgentSink {
+            origin: "ingest",
+            events,
+        };
+
+        self.local_addr = Some(local_addr);
+        self.shutdown_tx = Some(shutdown_tx);
+        self.task_handle = Some(tokio::spawn(serve(listener, shutdown_rx, sink)));
+        Ok(events_rx)
+    }
+
+    fn stop(&mut self) -> Result<(), CaptureError> {
+        if let Some(tx) = self.shutdown_tx.take() {
+            let _ = tx.send(());
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "ingest"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use phantom_core::protocol::ProtocolTrace;
+    use tokio::io::AsyncWriteExt;
+
+    use super::*;
+
+    const TRACE: &str = r#"{"method":"GET","url":"http://svc/orders","status_code":200,"request_headers":{},"response_headers":{},"duration_ms":4,"timestamp_ms":0}"#;
+
+    fn started() -> (IngestCaptureBackend, mpsc::Receiver<TraceEvent>) {
+        let mut backend = IngestCaptureBackend::new("127.0.0.1:0".into());
+        let events_rx = backend.start().unwrap();
+        (backend, events_rx)
+    }
+
+    async fn next_url(events_rx: &mut mpsc::Receiver<TraceEvent>) -> String {
+        match events_rx.recv().await.unwrap() {
+            TraceEvent::Http(trace) => trace.url,
+            other => panic!("expected an HTTP trace, got {other:?}"),
+        }
+    }
+
+    /// Sends a raw HTTP request and returns the response's status line.
+    async fn post(addr: SocketAddr, path: &str, body: &str) -> String {
+        let mut conn = TcpStream::connect(addr).await.unwrap();
+        let request = format!(
+            "POST {path} HTTP/1.1\r\nhost: phantom\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+            body.len()
+        );
+        conn.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        conn.read_to_string(&mut response).await.unwrap();
+        response.lines().next().unwrap_or_default().to_string()
+    }
+
+    #[tokio::test]
+    async fn test_reads_one_message_per_line() {
+        let (mut backend, mut events_rx) = started();
+        let mut conn = TcpStream::connect(backend.local_addr().unwrap())
+            .await
+            .unwrap();
+        let redis = r#"{"kind":"redis","command":"GET","key":"user:1","arg_count":1,"reply_type":"bulk","duration_ms":1,"timestamp_ms":0}"#;
+        let payload = format!("{TRACE}\n\nnot json\n{redis}\n");
+        conn.write_all(payload.as_bytes()).await.unwrap();
+
+        let Some(TraceEvent::Http(trace)) = events_rx.recv().await else {
+            panic!("expected an HTTP trace");
+        };
+        assert_eq!(trace.url, "http://svc/orders");
+        assert_eq!(trace.status_code, 200);
+        match events_rx.recv().await.unwrap() {
+            TraceEvent::Protocol(ProtocolTrace::Redis(redis)) => {
+                assert_eq!(redis.key.as_deref(), Some("user:1"))
+            }
+            other => panic!("unexpected event: {other:?}"),
+        }
+        backend.stop().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_accepts_posted_messages() {
+        let (mut backend, mut events_rx) = started();
+        let addr = backend.local_addr().unwrap();
+
+        let status = post(addr, INGEST_PATH, TRACE).await;
+        assert_eq!(status, "HTTP/1.1 202 Accepted");
+        assert_eq!(next_url(&mut events_rx).await, "http://svc/orders");
+
+        let array = format!("[{TRACE},{}]", TRACE.replace("/orders", "/users"));
+        assert_eq!(
+            post(addr, INGEST_PATH, &array).await,
+            "HTTP/1.1 202 Accepted"
+        );
+        assert_eq!(next_url(&mut events_rx).await, "http://svc/orders");
+        assert_eq!(next_url(&mut events_rx).await, "http://svc/users");
+        backend.stop().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rejects_bad_requests() {
+        let (mut backend, mut events_rx) = started();
+        let addr = backend.local_addr().unwrap();
+
+        assert_eq!(post(addr, "/traces", TRACE).await, "HTTP/1.1 404 Not Found");
+        // The valid message is kept; the request still reports the other.
+        let body = format!("{TRACE}\n{{\"method\":\"GET\"}}");
+        assert_eq!(
+            post(addr, INGEST_PATH, &body).await,
+            "HTTP/1.1 400 Bad Request"
+        );
+        assert_eq!(next_url(&mut events_rx).await, "http://svc/orders");
+        backend.stop().unwrap();
+    }
+
+    #[test]
+    fn test_rejects_unusable_address() {
+        let mut backend = IngestCaptureBackend::new("localhost".into());
+        assert!(backend.start().is_err());
+    }
+}
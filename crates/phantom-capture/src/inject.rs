@@ -0,0 +1,556 @@
+//! Loading the agent into a running process (`phantom attach`) — Linux
+//! x86_64 only.
+//!
+//! The process's main thread is stopped with `ptrace` and made to call, in
+//! turn, `setenv` for the agent's settings, `dlopen` on the agent and the
+//! agent's `phantom_attach`. A library loaded that late is not interposed the
+//! way `LD_PRELOAD` is, so `phantom_attach` points the process's GOT entries
+//! for the hooked functions at the agent, and reports how many it changed.
+//! The functions' addresses come from the dynamic symbol tables of the libc
+//! (or libdl) the process has mapped. Each call returns to address 0; the
+//! fault that raises hands the thread back, and once done its registers are
+//! restored and it resumes where it was stopped — an interrupted syscall is
+//! restarted. Signals arriving meanwhile are sent again after detaching.
+//!
+//! Needs `CAP_SYS_PTRACE` (root), or the same user with Yama's
+//! `ptrace_scope` at 0. The process must see the agent and phantom's socket
+//! at the same paths, so processes in other mount namespaces (containers)
+//! are refused.
+
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{FileExt, MetadataExt};
+use std::path::{Path, PathBuf};
+
+/// The agent's entry point once loaded.
+const ATTACH_SYMBOL: &[u8] = b"phantom_attach";
+
+const SHT_DYNSYM: u32 = 11;
+const SHT_GNU_VERSYM: u32 = 0x6fff_ffff;
+/// Marks a symbol version other than the default one.
+const VERSYM_HIDDEN: u16 = 0x8000;
+const STT_FUNC: u8 = 2;
+
+/// Loads `agent_lib` into process `pid` with `env` set in its environment,
+/// returning the number of call sites the agent hooked.
+pub(crate) fn inject_agent(pid: u32, agent_lib: &Path, env: &[(&str, &OsStr)]) -> io::Result<u32> {
+    if !cfg!(target_arch = "x86_64") {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "attaching is only supported on x86_64",
+        ));
+    }
+    let ours = std::fs::metadata("/")?;
+    let theirs = std::fs::metadata(format!("/proc/{pid}/root"))
+        .map_err(|e| io::Error::new(e.kind(), format!("process {pid}: {e}")))?;
+    if (ours.dev(), ours.ino()) != (theirs.dev(), theirs.ino()) {
+        return Err(io::Error::other(format!(
+            "process {pid} has another root directory (a container?); attaching needs it to share phantom's"
+        )));
+    }
+    let agent_lib = std::fs::canonicalize(agent_lib)
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", agent_lib.display())))?;
+
+    let libs = mapped_libraries(pid)?;
+    let lookup = |names: &[(&str, &str)]| {
+        names
+            .iter()
+            .find_map(|(lib, symbol)| find_function(&libs, lib, symbol))
+    };
+    let setenv = lookup(&[("libc", "setenv")]);
+    // glibc before 2.34 has dlopen in libdl, which not every process maps,
+    // and a private copy in libc.
+    let dlopen = lookup(&[
+        ("libc", "dlopen"),
+        ("libdl", "dlopen"),
+        ("libc", "__libc_dlopen_mode"),
+    ]);
+    let dlsym = lookup(&[
+        ("libc", "dlsym"),
+        ("libdl", "dlsym"),
+        ("libc", "__libc_dlsym"),
+    ]);
+    let dlerror = lookup(&[("libc", "dlerror"), ("libdl", "dlerror")]);
+    let (Some(setenv), Some(dlopen), Some(dlsym)) = (setenv, dlopen, dlsym) else {
+        return Err(io::Error::other(format!(
+            "process {pid}: setenv/dlopen/dlsym not found in its libc"
+        )));
+    };
+
+    let mut tracee = arch::Tracee::seize(pid)?;
+    for (name, value) in env {
+        let result = tracee.call(
+            setenv,
+            &[
+                Arg::Str(name.as_bytes()),
+                Arg::Str(value.as_bytes()),
+                Arg::Int(1),
+            ],
+        )?;
+        // An `int`: the upper half of the register is undefined.
+        if result as i32 != 0 {
+            return Err(io::Error::other(format!(
+                "setenv {name} failed in process {pid}"
+            )));
+        }
+    }
+    let path = agent_lib.as_os_str().as_bytes();
+    let handle = tracee.call(dlopen, &[Arg::Str(path), Arg::Int(libc::RTLD_NOW as u64)])?;
+    if handle == 0 {
+        let reason = match dlerror {
+            Some(dlerror) => {
+                let message = tracee.call(dlerror, &[])?;
+                tracee.read_c_string(message)?
+            }
+            None => String::new(),
+        };
+        return Err(io::Error::other(format!(
+            "loading {} into process {pid} failed: {reason}",
+            agent_lib.display()
+        )));
+    }
+    let attach = tracee.call(dlsym, &[Arg::Int(handle), Arg::Str(ATTACH_SYMBOL)])?;
+    if attach == 0 {
+        return Err(io::Error::other(format!(
+            "{} has no phantom_attach; rebuild the agent",
+            agent_lib.display()
+        )));
+    }
+    // An `int` again; negative when the agent cannot find itself.
+    let hooked = tracee.call(attach, &[])? as i32;
+    u32::try_from(hooked)
+        .map_err(|_| io::Error::other(format!("phantom_attach failed in process {pid}")))
+}
+
+/// An argument of a function called in the tracee.
+enum Arg<'a> {
+    Int(u64),
+    /// Copied into the tracee, NUL-terminated; passed as a pointer.
+    Str(&'a [u8]),
+}
+
+/// A shared object the process has mapped.
+struct MappedLibrary {
+    path: PathBuf,
+    /// Where its first page is mapped.
+    start: u64,
+}
+
+/// The libraries in `/proc/<pid>/maps`, by the mapping of their start.
+fn mapped_libraries(pid: u32) -> io::Result<Vec<MappedLibrary>> {
+    let maps = std::fs::read_to_string(format!("/proc/{pid}/maps"))?;
+    let mut libs = Vec::new();
+    for line in maps.lines() {
+        let mut fields = line.split_whitespace();
+        let (Some(range), Some(_perms), Some(offset), Some(_dev), Some(_inode), Some(path)) = (
+            fields.next(),
+            fields.next(),
+            fields.next(),
+            fields.next(),
+            fields.next(),
+            fields.next(),
+        ) else {
+            continue;
+        };
+        if offset.trim_start_matches('0').is_empty() && path.starts_with('/') {
+            let Some(start) = range
+                .split('-')
+                .next()
+                .and_then(|s| u64::from_str_radix(s, 16).ok())
+            else {
+                continue;
+            };
+            libs.push(MappedLibrary {
+                path: PathBuf::from(path),
+                start,
+            });
+        }
+    }
+    Ok(libs)
+}
+
+/// Whether `path` names the library `lib` (`libc` matches `libc.so.6`,
+/// `libc-2.31.so` and musl's `ld-musl-x86_64.so.1`).
+fn is_library(path: &Path, lib: &str) -> bool {
+    let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+        return false;
+    };
+    let versioned = name
+        .strip_prefix(lib)
+        .is_some_and(|rest| rest.starts_with(".so") || rest.starts_with('-'));
+    versioned || (lib == "libc" && name.starts_with("ld-musl-"))
+}
+
+/// The address of function `symbol` in the first mapped library `lib`
+/// defining it.
+fn find_function(libs: &[MappedLibrary], lib: &str, symbol: &str) -> Option<u64> {
+    libs.iter()
+        .filter(|l| is_library(&l.path, lib))
+        .find_map(|l| {
+            let elf = std::fs::read(&l.path).ok()?;
+            let value = elf_symbol(&elf, symbol.as_bytes())?;
+            Some(l.start - first_load_address(&elf)? + value)
+        })
+}
+
+/// The page-aligned virtual address of an ELF64 file's first `PT_LOAD`.
+fn first_load_address(elf: &[u8]) -> Option<u64> {
+    let phoff = usize::try_from(u64_at(elf, 0x20)?).ok()?;
+    let phentsize = usize::from(u16_at(elf, 0x36)?);
+    let phnum = usize::from(u16_at(elf, 0x38)?);
+    (0..phnum)
+        .map(|i| phoff + i * phentsize)
+        .find(|&ph| u32_at(elf, ph) == Some(libc::PT_LOAD))
+        .and_then(|ph| u64_at(elf, ph + 0x10))
+        .map(|vaddr| vaddr & !0xfff)
+}
+
+/// The value of the function `name` in an ELF64 file's dynamic symbol
+/// table, preferring its default version.
+fn elf_symbol(elf: &[u8], name: &[u8]) -> Option<u64> {
+    if elf.get(..5)? != b"\x7fELF\x02" {
+        return None;
+    }
+    let shoff = usize::try_from(u64_at(elf, 0x28)?).ok()?;
+    let shentsize = usize::from(u16_at(elf, 0x3a)?);
+    let shnum = usize::from(u16_at(elf, 0x3c)?);
+    let section = |i: usize| {
+        let sh = shoff + i * shentsize;
+        Some((
+            u32_at(elf, sh + 4)?,
+            usize::try_from(u64_at(elf, sh + 0x18)?).ok()?,
+            usize::try_from(u64_at(elf, sh + 0x20)?).ok()?,
+            usize::try_from(u32_at(elf, sh + 0x28)?).ok()?,
+        ))
+    };
+    let sections: Vec<_> = (0..shnum).filter_map(section).collect();
+    let (_, symtab, symtab_size, strtab_index) = *sections.iter().find(|s| s.0 == SHT_DYNSYM)?;
+    let (_, strtab, _, _) = *sections.get(strtab_index)?;
+    let versym = sections.iter().find(|s| s.0 == SHT_GNU_VERSYM).map(|s| s.1);
+
+    let mut found = None;
+    for index in 0..symtab_size / 24 {
+        let sym = symtab + index * 24;
+        let name_offset = usize::try_from(u32_at(elf, sym)?).ok()?;
+        let info = *elf.get(sym + 4)?;
+        let defined = u16_at(elf, sym + 6)? != 0;
+        if info & 0xf != STT_FUNC || !defined {
+            continue;
+        }
+        let start = strtab + name_offset;
+        let end = start + elf.get(start..)?.iter().position(|&b| b == 0)?;
+        if &elf[start..end] != name {
+            continue;
+        }
+        let value = u64_at(elf, sym + 8)?;
+        let hidden = versym
+            .and_then(|v| u16_at(elf, v + index * 2))
+            .is_some_and(|v| v & VERSYM_HIDDEN != 0);
+        if !hidden {
+            return Some(value);
+        }
+        found.get_or_insert(value);
+    }
+    found
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        bytes.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        bytes.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn u64_at(bytes: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(
+        bytes.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
+
+#[cfg(target_arch = "x86_64")]
+mod arch {
+    use super::*;
+
+    /// `NT_X86_XSTATE`: the FPU, SSE and AVX registers.
+    const NT_X86_XSTATE: libc::c_int = 0x202;
+    /// Room for the largest XSAVE area (AMX included).
+    const XSTATE_SIZE: usize = 16 * 1024;
+    /// Skipped below the stack pointer: the System V red zone, and then some.
+    const STACK_GAP: u64 = 1024;
+
+    /// A process whose main thread is stopped under `ptrace`; dropping it
+    /// restores the thread's registers and lets it go.
+    pub(super) struct Tracee {
+        pid: libc::pid_t,
+        mem: File,
+        regs: libc::user_regs_struct,
+        xstate: Vec<u8>,
+        /// A signal the thread was stopped for, handed back on detach.
+        stop_signal: libc::c_int,
+        /// Signals that arrived during calls, sent again after detaching.
+        deferred: Vec<libc::c_int>,
+    }
+
+    impl Tracee {
+        pub(super) fn seize(pid: u32) -> io::Result<Self> {
+            let pid = libc::pid_t::try_from(pid)
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "bad PID"))?;
+            let mem = File::options()
+                .read(true)
+                .write(true)
+                .open(format!("/proc/{pid}/mem"))
+                .map_err(|e| {
+                    io::Error::new(
+                        e.kind(),
+                        format!("process {pid}: {e} (needs root or CAP_SYS_PTRACE)"),
+                    )
+                })?;
+            // SAFETY: plain ptrace(2) requests on `pid`; results are checked.
+            unsafe {
+                if libc::ptrace(libc::PTRACE_SEIZE, pid, 0, 0) != 0 {
+                    let e = io::Error::last_os_error();
+                    return Err(io::Error::new(
+                        e.kind(),
+                        format!("ptrace({pid}): {e} (needs root or CAP_SYS_PTRACE)"),
+                    ));
+                }
+                if libc::ptrace(libc::PTRACE_INTERRUPT, pid, 0, 0) != 0 {
+                    let e = io::Error::last_os_error();
+                    libc::ptrace(libc::PTRACE_DETACH, pid, 0, 0);
+                    return Err(e);
+                }
+            }
+            let mut tracee = Self {
+                pid,
+                mem,
+                // SAFETY: all-zero registers are a valid value, overwritten below.
+                regs: unsafe { std::mem::zeroed() },
+                xstate: vec![0; XSTATE_SIZE],
+                stop_signal: 0,
+                deferred: Vec::new(),
+            };
+            let status = tracee.wait()?;
+            if status >> 16 == 0 {
+                // Stopped for a signal before the interrupt.
+                tracee.stop_signal = libc::WSTOPSIG(status);
+            }
+            tracee.regs = tracee.get_regs()?;
+            let mut iov = libc::iovec {
+                iov_base: tracee.xstate.as_mut_ptr().cast(),
+                iov_len: tracee.xstate.len(),
+            };
+            // SAFETY: `iov` describes the writable `xstate` buffer.
+            if unsafe { libc::ptrace(libc::PTRACE_GETREGSET, pid, NT_X86_XSTATE, &raw mut iov) }
+                != 0
+            {
+                return Err(io::Error::last_os_error());
+            }
+            tracee.xstate.truncate(iov.iov_len);
+            Ok(tracee)
+        }
+
+        /// Calls `function` with `args` in the stopped thread and returns
+        /// what it returned.
+        pub(super) fn call(&mut self, function: u64, args: &[Arg<'_>]) -> io::Result<u64> {
+            let mut regs = self.regs;
+            let mut scratch = regs.rsp - STACK_GAP;
+            let mut values = Vec::with_capacity(args.len());
+            for arg in args {
+                values.push(match arg {
+                    Arg::Int(value) => *value,
+                    Arg::Str(bytes) => {
+                        scratch = (scratch - bytes.len() as u64 - 1) & !0xf;
+                        let mut copy = bytes.to_vec();
+                        copy.push(0);
+                        self.mem.write_all_at(&copy, scratch)?;
+                        scratch
+                    }
+                });
+            }
+            // 16-byte aligned at the call, then the return address: 0.
+            regs.rsp = (scratch - 256) & !0xf;
+            regs.rsp -= 8;
+            self.mem.write_all_at(&0u64.to_ne_bytes(), regs.rsp)?;
+            for (register, value) in [&mut regs.rdi, &mut regs.rsi, &mut regs.rdx]
+                .into_iter()
+                .zip(values)
+            {
+                *register = value;
+            }
+            regs.rip = function;
+            regs.rax = 0;
+            // Keeps the kernel from restarting the syscall the thread was
+            // stopped in at our function instead.
+            regs.orig_rax = u64::MAX;
+            self.set_regs(&regs)?;
+            loop {
+                self.resume(0)?;
+                let status = self.wait()?;
+                let signal = libc::WSTOPSIG(status);
+                if status >> 16 != 0 {
+                    // Interrupt or group stop: keep going.
+                    continue;
+                }
+                if signal != libc::SIGSEGV {
+                    self.deferred.push(signal);
+                    continue;
+                }
+                let after = self.get_regs()?;
+                if after.rip != 0 {
+                    return Err(io::Error::other(format!(
+                        "process {} faulted at {:#x} during the call",
+                        self.pid, after.rip
+                    )));
+                }
+                return Ok(after.rax);
+            }
+        }
+
+        /// The NUL-terminated string at `address`, up to 512 bytes of it.
+        pub(super) fn read_c_string(&self, address: u64) -> io::Result<String> {
+            if address == 0 {
+                return Ok(String::new());
+            }
+            let mut buf = vec![0u8; 512];
+            let len = self.mem.read_at(&mut buf, address)?;
+            buf.truncate(len);
+            if let Some(end) = buf.iter().position(|&b| b == 0) {
+                buf.truncate(end);
+            }
+            Ok(String::from_utf8_lossy(&buf).into_owned())
+        }
+
+        fn wait(&self) -> io::Result<libc::c_int> {
+            let mut status = 0;
+            loop {
+                // SAFETY: waitpid(2) writes the status into `status`.
+                if unsafe { libc::waitpid(self.pid, &mut status, libc::__WALL) } < 0 {
+                    let e = io::Error::last_os_error();
+                    if e.kind() == io::ErrorKind::Interrupted {
+                        continue;
+                    }
+                    return Err(e);
+                }
+                if libc::WIFSTOPPED(status) {
+                    return Ok(status);
+                }
+                if libc::WIFEXITED(status) || libc::WIFSIGNALED(status) {
+                    return Err(io::Error::other(format!("process {} exited", self.pid)));
+                }
+            }
+        }
+
+        fn resume(&self, signal: libc::c_int) -> io::Result<()> {
+            // SAFETY: PTRACE_CONT on our stopped tracee.
+            if unsafe { libc::ptrace(libc::PTRACE_CONT, self.pid, 0, signal) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        }
+
+        fn get_regs(&self) -> io::Result<libc::user_regs_struct> {
+            // SAFETY: all-zero registers are a valid value for the kernel to fill.
+            let mut regs: libc::user_regs_struct = unsafe { std::mem::zeroed() };
+            // SAFETY: PTRACE_GETREGS writes a user_regs_struct into `regs`.
+            if unsafe { libc::ptrace(libc::PTRACE_GETREGS, self.pid, 0, &raw mut regs) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(regs)
+        }
+
+        fn set_regs(&self, regs: &libc::user_regs_struct) -> io::Result<()> {
+            // SAFETY: PTRACE_SETREGS reads a user_regs_struct from `regs`.
+            if unsafe { libc::ptrace(libc::PTRACE_SETREGS, self.pid, 0, regs as *const _) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        }
+    }
+
+    impl Drop for Tracee {
+        fn drop(&mut self) {
+            let _ = self.set_regs(&self.regs);
+            let mut iov = libc::iovec {
+                iov_base: self.xstate.as_mut_ptr().cast(),
+                iov_len: self.xstate.len(),
+            };
+            // SAFETY: `iov` describes the saved XSAVE area; ptrace requests
+            // on our stopped tracee, whose failure leaves nothing to undo.
+            unsafe {
+                libc::ptrace(
+                    libc::PTRACE_SETREGSET,
+                    self.pid,
+                    NT_X86_XSTATE,
+                    &raw mut iov,
+                );
+                libc::ptrace(libc::PTRACE_DETACH, self.pid, 0, self.stop_signal);
+                for &signal in &self.deferred {
+                    libc::kill(self.pid, signal);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+mod arch {
+    use super::*;
+
+    /// Refused in [`inject_agent`] before one is made.
+    pub(super) enum Tracee {}
+
+    impl Tracee {
+        pub(super) fn seize(_pid: u32) -> io::Result<Self> {
+            Err(io::ErrorKind::Unsupported.into())
+        }
+
+        pub(super) fn call(&mut self, _function: u64, _args: &[Arg<'_>]) -> io::Result<u64> {
+            match *self {}
+        }
+
+        pub(super) fn read_c_string(&self, _address: u64) -> io::Result<String> {
+            match *self {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_library() {
+        assert!(is_library(
+            Path::new("/usr/lib/x86_64-linux-gnu/libc.so.6"),
+            "libc"
+        ));
+        assert!(is_library(
+            Path::new("/lib/x86_64-linux-gnu/libc-2.31.so"),
+            "libc"
+        ));
+        assert!(is_library(Path::new("/lib/ld-musl-x86_64.so.1"), "libc"));
+        assert!(is_library(Path::new("/lib/libdl.so.2"), "libdl"));
+        assert!(!is_library(Path::new("/usr/lib/libcurl.so.4"), "libc"));
+        assert!(!is_library(Path::new("/usr/lib/libcrypto.so.3"), "libc"));
+    }
+
+    #[test]
+    fn test_finds_own_libc_functions() {
+        let libs = mapped_libraries(std::process::id()).unwrap();
+        for name in ["setenv", "dlsym"] {
+            let found =
+                find_function(&libs, "libc", name).or_else(|| find_function(&libs, "libdl", name));
+            let c_name = std::ffi::CString::new(name).unwrap();
+            // SAFETY: dlsym with a NUL-terminated name.
+            let expected = unsafe { libc::dlsym(libc::RTLD_DEFAULT, c_name.as_ptr()) };
+            assert_eq!(found, Some(expected as u64), "{name}");
+        }
+    }
+}
@@ -0,0 +1,119 @@
+//! Rules choosing which proxied requests `--intercept` holds for the user.
+
+use phantom_core::slo::wildcard_contains;
+use phantom_core::trace::HttpMethod;
+
+/// Holds requests with this method (any, if None) whose URL matches.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InterceptRule {
+    pub method: Option<HttpMethod>,
+    /// URL pattern; `*` matches any run of characters, empty matches all.
+    pub url_pattern: String,
+}
+
+impl InterceptRule {
+    pub fn matches(&self, method: HttpMethod, url: &str) -> bool {
+        self.method.is_none_or(|m| m == method) && wildcard_contains(url, &self.url_pattern)
+    }
+}
+
+/// Intercept rules; a request matching any of them is held.
+#[derive(Clone, Debug, Default)]
+pub struct InterceptConfig {
+    pub rules: Vec<InterceptRule>,
+}
+
+impl InterceptConfig {
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Returns true if the request should be held. `CONNECT` tunnels are
+    /// never held; the requests inside them are.
+    pub fn matches(&self, method: HttpMethod, url: &str) -> bool {
+        method != HttpMethod::Connect && self.rules.iter().any(|r| r.matches(method, url))
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// CLI spec parsing
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Parse an intercept specification string into an `InterceptRule`.
+///
+/// ```text
+/// /api/orders             URLs containing "/api/orders"
+/// POST /api/*/orders      POSTs to matching URLs
+/// DELETE                  every DELETE
+/// *                       every request
+/// ```
+pub fn parse_intercept_spec(s: &str) -> Result<InterceptRule, String> {
+    let s = s.trim();
+    if s.is_empty() {
+        return Err("empty intercept spec; use '*' to hold every request".to_string());
+    }
+    let (first, rest) = s.split_once(char::is_whitespace).unwrap_or((s, ""));
+    match first.parse::<HttpMethod>() {
+        Ok(method) => Ok(InterceptRule {
+            method: Some(method),
+            url_pattern: rest.trim().to_string(),
+        }),
+        Err(_) if rest.trim().is_empty() => Ok(InterceptRule {
+            method: None,
+            url_pattern: s.to_string(),
+        }),
+        Err(e) => Err(format!("invalid intercept spec {s:?}: {e}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_intercept_spec() {
+        assert_eq!(
+            parse_intercept_spec("/api/orders").unwrap(),
+            InterceptRule {
+                method: None,
+                url_pattern: "/api/orders".to_string()
+            }
+        );
+        assert_eq!(
+            parse_intercept_spec("post /api/*/orders").unwrap(),
+            InterceptRule {
+                method: Some(HttpMethod::Post),
+                url_pattern: "/api/*/orders".to_string()
+            }
+        );
+        let delete = parse_intercept_spec("DELETE").unwrap();
+        assert_eq!(delete.method, Some(HttpMethod::Delete));
+        assert_eq!(delete.url_pattern, "");
+    }
+
+    #[test]
+    fn test_parse_intercept_spec_rejects_invalid() {
+        assert!(parse_intercept_spec("").is_err());
+        assert!(parse_intercept_spec("FETCH /api").is_err());
+    }
+
+    #[test]
+    fn test_intercept_matches() {
+        let config = InterceptConfig {
+            rules: vec![
+                parse_intercept_spec("POST /api/*/orders").unwrap(),
+                parse_intercept_spec("/admin").unwrap(),
+            ],
+        };
+        assert!(config.matches(HttpMethod::Post, "http://shop.test/api/v2/orders"));
+        assert!(!config.matches(HttpMethod::Get, "http://shop.test/api/v2/orders"));
+        assert!(config.matches(HttpMethod::Get, "http://shop.test/admin/users"));
+        assert!(!config.matches(HttpMethod::Get, "http://shop.test/"));
+
+        let all = InterceptConfig {
+            rules: vec![parse_intercept_spec("*").unwrap()],
+        };
+        assert!(all.matches(HttpMethod::Get, "http://shop.test/"));
+        assert!(!all.matches(HttpMethod::Connect, "shop.test:443"));
+    }
+}
@@ -0,0 +1,122 @@
+//! SSLKEYLOGFILE capture backend — Linux only.
+//!
+//! Runs the traced command with `SSLKEYLOGFILE` pointing at a file phantom
+//! reads, captures TCP packets on an `AF_PACKET` socket (see [`packet`]),
+//! reassembles each connection and decrypts its TLS with the secrets the
+//! command logged (see [`FlowCollector`]), then parses the plaintext as
+//! HTTP/1. Nothing is injected into the target and its certificate checks
+//! are untouched, so statically linked rustls and Go programs and pinned
+//! clients are captured too — provided their TLS library honours
+//! `SSLKEYLOGFILE` (curl, NSS, Go and rustls programs that wire it to
+//! `KeyLogWriter` / `KeyLogFile`).
+//!
+//! Only connections whose secrets show up in the key log are decrypted, so
+//! other processes' traffic on the interface stays out. Plain HTTP cannot be
+//! attributed to the command and is not captured. Go's HTTP/2 client is
+//! turned off through `GODEBUG`, as HTTP/2 is not reassembled. Capturing
+//! needs root or `CAP_NET_RAW`.
+//!
+//! [`packet`]: crate::packet
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use phantom_core::capture::{CaptureBackend, TraceEvent};
+use phantom_core::error::CaptureError;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::flows::FlowCollector;
+use crate::packet::{PacketSocket, capture};
+use crate::tls::KeyLog;
+
+pub struct KeyLogCaptureBackend {
+    keylog_path: PathBuf,
+    interface: Option<String>,
+    shutdown_tx: Option<oneshot::Sender<()>>,
+    task_handle: Option<tokio::task::JoinHandle<()>>,
+}
+
+impl KeyLogCaptureBackend {
+    /// Create a backend reading TLS secrets from `keylog_path`, which
+    /// `start` creates (or empties).
+    pub fn new(keylog_path: PathBuf) -> Self {
+        Self {
+            keylog_path,
+            interface: None,
+            shutdown_tx: None,
+            task_handle: None,
+        }
+    }
+
+    /// Capture on this network interface only (builder pattern) rather than
+    /// on all of them.
+    pub fn with_interface(mut self, interface: String) -> Self {
+        self.interface = Some(interface);
+        self
+    }
+
+    pub fn keylog_path(&self) -> &Path {
+        &self.keylog_path
+    }
+
+    /// Sets up `command`'s environment: `SSLKEYLOGFILE`, and HTTP/1 for Go's
+    /// HTTP client.
+    pub fn prepare(&self, command: &mut Command) {
+        let godebug = match std::env::var("GODEBUG") {
+            Ok(existing) if !existing.is_empty() => format!("{existing},http2client=0"),
+            _ => "http2client=0".to_string(),
+        };
+        command
+            .env("SSLKEYLOGFILE", &self.keylog_path)
+            .env("GODEBUG", godebug);
+    }
+}
+
+impl CaptureBackend for KeyLogCaptureBackend {
+    fn start(&mut self) -> Result<mpsc::Receiver<TraceEvent>, CaptureError> {
+        create_keylog(&self.keylog_path).map_err(|e| {
+            CaptureError::StartFailed(format!("{}: {e}", self.keylog_path.display()))
+        })?;
+        let socket = PacketSocket::open(self.interface.as_deref()).map_err(|e| {
+            CaptureError::StartFailed(format!(
+                "opening packet capture: {e} (needs root or CAP_NET_RAW)"
+            ))
+        })?;
+
+        let (trace_tx, trace_rx) = mpsc::channel(4096);
+        let (shutdown_tx, shutdown_rx) = oneshot::channel();
+        let collector = FlowCollector::decrypting(KeyLog::new(self.keylog_path.clone()));
+        self.shutdown_tx = Some(shutdown_tx);
+        self.task_handle = Some(tokio::spawn(capture(
+            socket,
+            collector,
+            trace_tx,
+            shutdown_rx,
+        )));
+        Ok(trace_rx)
+    }
+
+    fn stop(&mut self) -> Result<(), CaptureError> {
+        if let Some(tx) = self.shutdown_tx.take() {
+            let _ = tx.send(());
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "keylog"
+    }
+}
+
+/// Creates an empty key log readable by its owner only: it holds the
+/// secrets to every connection in it.
+fn create_keylog(path: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::OpenOptionsExt;
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)
+        .map(drop)
+}
@@ -1,92 +1,322 @@
 //! LD_PRELOAD capture backend — Linux only.
 //!
-//! Listens on a Unix datagram socket for [`TraceMsg`] JSON messages emitted
+//! Listens on a Unix socket for [`TraceMsg`] messages emitted
 //! by the phantom-agent dylib injected into a target process, and converts
-//! them into [`HttpTrace`] objects.
+//! them into [`TraceEvent`]s (see [`agent_msg`](crate::agent_msg) for the
+//! format): HTTP traces, and traces of other protocols and agent health
+//! reports from messages with a `kind` tag. Messages too large for one
+//! datagram arrive in fragments and are reassembled first. Agents encode
+//! messages as JSON, or as MessagePack with `PHANTOM_IPC_FORMAT=msgpack`;
+//! both are accepted. Agents batching small messages (`PHANTOM_BATCH_MS`)
+//! send several per datagram.
+//!
+//! The socket is a datagram socket by default; [`IpcMode`] selects a
+//! `SOCK_SEQPACKET` or length-prefixed `SOCK_STREAM` listener instead, which
+//! never drops messages when phantom falls behind.
 
 use std::collections::HashMap;
+use std::ffi::OsStr;
 use std::path::{Path, PathBuf};
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 
-use base64::Engine as _;
-use base64::engine::general_purpose::STANDARD as B64;
-use phantom_core::capture::CaptureBackend;
+use phantom_core::capture::{CaptureBackend, TraceEvent};
 use phantom_core::error::CaptureError;
-use phantom_core::trace::{HttpMethod, HttpTrace, SpanId, TraceId};
-use tokio::net::UnixDatagram;
+use tokio::io::AsyncReadExt;
+use tokio::net::{UnixDatagram, UnixListener, UnixStream};
 use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinSet;
+use tokio_seqpacket::{UnixSeqpacket, UnixSeqpacketListener};
 use tracing::{debug, warn};
 
+use crate::agent_msg::{AgentSink, dispatch_agent_msg};
+
 // ─────────────────────────────────────────────────────────────────────────────
-// IPC message format (must match phantom-agent's TraceMsg)
+// Fragment reassembly (must match phantom-agent's send_fragmented)
+//
+// A fragment is `magic | message id: u64 LE | index: u16 LE | total: u16 LE`
+// followed by its slice of the encoded message. Fragments of one message arrive
+// in order from a single agent thread, but other messages may interleave.
 // ─────────────────────────────────────────────────────────────────────────────
 
-#[derive(serde::Deserialize)]
-struct AgentTrace {
-    method: String,
-    url: String,
-    status_code: u16,
-    request_headers: HashMap<String, String>,
-    response_headers: HashMap<String, String>,
-    request_body_b64: Option<String>,
-    response_body_b64: Option<String>,
-    duration_ms: u64,
-    timestamp_ms: u64,
-    dest_addr: Option<String>,
-    #[serde(default)]
-    protocol_version: Option<String>,
-}
-
-fn parse_method(s: &str) -> HttpMethod {
-    match s.to_uppercase().as_str() {
-        "GET" => HttpMethod::Get,
-        "POST" => HttpMethod::Post,
-        "PUT" => HttpMethod::Put,
-        "DELETE" => HttpMethod::Delete,
-        "PATCH" => HttpMethod::Patch,
-        "HEAD" => HttpMethod::Head,
-        "OPTIONS" => HttpMethod::Options,
-        "TRACE" => HttpMethod::Trace,
-        "CONNECT" => HttpMethod::Connect,
-        _ => HttpMethod::Get,
-    }
-}
-
-fn decode_body(b64: Option<String>) -> Option<Vec<u8>> {
-    b64.and_then(|s| B64.decode(s).ok())
-}
-
-fn rand_bytes<const N: usize>() -> [u8; N] {
-    let mut buf = [0u8; N];
-    buf.iter_mut().for_each(|b| *b = rand::random());
-    buf
-}
-
-fn agent_trace_to_http_trace(a: AgentTrace) -> HttpTrace {
-    let timestamp = SystemTime::UNIX_EPOCH + Duration::from_millis(a.timestamp_ms);
-    // Guard against timestamps before UNIX_EPOCH (shouldn't happen but be safe).
-    let timestamp = if timestamp < UNIX_EPOCH {
-        SystemTime::now()
+/// First bytes of a fragment; JSON messages never start with NUL.
+const FRAGMENT_MAGIC: &[u8; 4] = b"\0PHF";
+const FRAGMENT_HEADER: usize = 16;
+/// Most fragments the agent splits one message into.
+const MAX_FRAGMENTS: usize = 64;
+/// Incomplete messages are dropped after this long (a fragment was lost).
+const FRAGMENT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Most messages being reassembled at once.
+const MAX_PARTIAL: usize = 256;
+
+fn is_fragment(data: &[u8]) -> bool {
+    data.starts_with(FRAGMENT_MAGIC)
+}
+
+struct Partial {
+    parts: Vec<Option<Vec<u8>>>,
+    missing: usize,
+    started: Instant,
+}
+
+#[derive(Default)]
+struct Reassembler {
+    partial: HashMap<u64, Partial>,
+}
+
+impl Reassembler {
+    /// Stores one fragment and returns the whole message once its last
+    /// fragment is in. Malformed fragments are ignored.
+    fn push(&mut self, data: &[u8], now: Instant) -> Option<Vec<u8>> {
+        if data.len() < FRAGMENT_HEADER || !is_fragment(data) {
+            return None;
+        }
+        let id = u64::from_le_bytes(data[4..12].try_into().ok()?);
+        let index = usize::from(u16::from_le_bytes(data[12..14].try_into().ok()?));
+        let total = usize::from(u16::from_le_bytes(data[14..16].try_into().ok()?));
+        if total == 0 || total > MAX_FRAGMENTS || index >= total {
+            return None;
+        }
+
+        self.partial
+            .retain(|_, p| now.duration_since(p.started) < FRAGMENT_TIMEOUT);
+        if !self.partial.contains_key(&id) && self.partial.len() >= MAX_PARTIAL {
+            warn!("ldpreload: too many fragmented messages in flight, dropping one");
+            return None;
+        }
+        let partial = self.partial.entry(id).or_insert_with(|| Partial {
+            parts: vec![None; total],
+            missing: total,
+            started: now,
+        });
+        if partial.parts.len() != total {
+            self.partial.remove(&id);
+            return None;
+        }
+        if partial.parts[index].is_none() {
+            partial.parts[index] = Some(data[FRAGMENT_HEADER..].to_vec());
+            partial.missing -= 1;
+        }
+        if partial.missing > 0 {
+            return None;
+        }
+        let partial = self.partial.remove(&id)?;
+        Some(partial.parts.into_iter().flatten().flatten().collect())
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Batches (must match phantom-agent's ipc_emit)
+//
+// With `PHANTOM_BATCH_MS` set, the agent sends several messages in one frame:
+// `magic | (length: u32 LE | message)*`.
+// ─────────────────────────────────────────────────────────────────────────────
+
+const BATCH_MAGIC: &[u8; 4] = b"\0PHB";
+
+/// The messages in a batch frame. A truncated last entry is dropped.
+fn split_batch(data: &[u8]) -> Vec<&[u8]> {
+    let mut rest = data.strip_prefix(BATCH_MAGIC).unwrap_or_default();
+    let mut msgs = Vec::new();
+    while let Some((len, tail)) = rest.split_first_chunk::<4>() {
+        let len = u32::from_le_bytes(*len) as usize;
+        if tail.len() < len {
+            break;
+        }
+        let (msg, tail) = tail.split_at(len);
+        msgs.push(msg);
+        rest = tail;
+    }
+    msgs
+}
+
+/// Dispatches one complete message or batch of messages.
+fn receive_message(data: &[u8], sink: &AgentSink) {
+    let msgs = if data.starts_with(BATCH_MAGIC) {
+        split_batch(data)
+    } else {
+        vec![data]
+    };
+    for msg in msgs {
+        if let Err(e) = dispatch_agent_msg(msg, sink) {
+            warn!("ldpreload: failed to parse agent message: {e}");
+        }
+    }
+}
+
+/// Dispatches one datagram or packet from an agent, holding fragments back
+/// until their message is complete.
+fn receive_frame(frame: &[u8], fragments: &mut Reassembler, sink: &AgentSink) {
+    let whole;
+    let msg = if is_fragment(frame) {
+        match fragments.push(frame, Instant::now()) {
+            Some(joined) => {
+                whole = joined;
+                &whole[..]
+            }
+            None => return,
+        }
     } else {
-        timestamp
+        frame
     };
+    receive_message(msg, sink);
+}
 
-    HttpTrace {
-        span_id: SpanId(rand_bytes::<8>()),
-        trace_id: TraceId(rand_bytes::<16>()),
-        parent_span_id: None,
-        method: parse_method(&a.method),
-        url: a.url,
-        request_headers: a.request_headers,
-        request_body: decode_body(a.request_body_b64),
-        status_code: a.status_code,
-        response_headers: a.response_headers,
-        response_body: decode_body(a.response_body_b64),
-        timestamp,
-        duration: Duration::from_millis(a.duration_ms),
-        source_addr: None,
-        dest_addr: a.dest_addr,
-        protocol_version: a.protocol_version.unwrap_or_else(|| "HTTP/1.1".to_string()),
+// ─────────────────────────────────────────────────────────────────────────────
+// IPC transports
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Largest message accepted on a stream connection; a longer length prefix
+/// means the stream is out of sync.
+const MAX_STREAM_MESSAGE: usize = 64 * 1024 * 1024;
+
+/// How agents deliver messages. Passed to the target process as
+/// `PHANTOM_IPC_MODE`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IpcMode {
+    /// Unix datagrams. Messages are dropped when phantom falls behind.
+    #[default]
+    Datagram,
+    /// `SOCK_SEQPACKET` connections: ordered, reliable, message boundaries
+    /// kept. Agents block while phantom catches up.
+    SeqPacket,
+    /// `SOCK_STREAM` connections carrying messages prefixed with their length
+    /// (u32 LE). Reliable, and messages are never fragmented.
+    Stream,
+}
+
+impl IpcMode {
+    /// The `PHANTOM_IPC_MODE` value for this mode.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Datagram => "dgram",
+            Self::SeqPacket => "seqpacket",
+            Self::Stream => "stream",
+        }
+    }
+}
+
+impl FromStr for IpcMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "dgram" | "datagram" => Ok(Self::Datagram),
+            "seqpacket" => Ok(Self::SeqPacket),
+            "stream" => Ok(Self::Stream),
+            _ => Err(format!(
+                "unknown IPC mode {s:?} (expected dgram, seqpacket or stream)"
+            )),
+        }
+    }
+}
+
+async fn serve_datagrams(
+    socket: UnixDatagram,
+    mut shutdown_rx: oneshot::Receiver<()>,
+    sink: AgentSink,
+) {
+    let mut buf = vec![0u8; 65536];
+    let mut fragments = Reassembler::default();
+    loop {
+        tokio::select! {
+            _ = &mut shutdown_rx => break,
+            result = socket.recv_from(&mut buf) => {
+                match result {
+                    Ok((n, _from)) => receive_frame(&buf[..n], &mut fragments, &sink),
+                    Err(e) => {
+                        warn!("ldpreload socket recv error: {e}");
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Accepts agent connections until shutdown; dropping `conns` at the end
+/// aborts the readers still running.
+async fn serve_seqpacket(
+    mut listener: UnixSeqpacketListener,
+    mut shutdown_rx: oneshot::Receiver<()>,
+    sink: AgentSink,
+) {
+    let mut conns = JoinSet::new();
+    loop {
+        tokio::select! {
+            _ = &mut shutdown_rx => break,
+            Some(_) = conns.join_next(), if !conns.is_empty() => {}
+            result = listener.accept() => {
+                match result {
+                    Ok(conn) => {
+                        conns.spawn(read_seqpacket(conn, sink.clone()));
+                    }
+                    Err(e) => {
+                        warn!("ldpreload socket accept error: {e}");
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+async fn read_seqpacket(conn: UnixSeqpacket, sink: AgentSink) {
+    let mut buf = vec![0u8; 65536];
+    let mut fragments = Reassembler::default();
+    loop {
+        match conn.recv(&mut buf).await {
+            Ok(0) => break,
+            Ok(n) => receive_frame(&buf[..n], &mut fragments, &sink),
+            Err(e) => {
+                debug!("ldpreload connection recv error: {e}");
+                break;
+            }
+        }
+    }
+}
+
+/// Accepts agent connections until shutdown; dropping `conns` at the end
+/// aborts the readers still running.
+async fn serve_stream(
+    listener: UnixListener,
+    mut shutdown_rx: oneshot::Receiver<()>,
+    sink: AgentSink,
+) {
+    let mut conns = JoinSet::new();
+    loop {
+        tokio::select! {
+            _ = &mut shutdown_rx => break,
+            Some(_) = conns.join_next(), if !conns.is_empty() => {}
+            result = listener.accept() => {
+                match result {
+                    Ok((conn, _addr)) => {
+                        conns.spawn(read_stream(conn, sink.clone()));
+                    }
+                    Err(e) => {
+                        warn!("ldpreload socket accept error: {e}");
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+async fn read_stream(mut conn: UnixStream, sink: AgentSink) {
+    let mut buf = Vec::new();
+    // Ends at EOF, which also covers an agent exiting mid-message.
+    while let Ok(len) = conn.read_u32_le().await {
+        let len = len as usize;
+        if len > MAX_STREAM_MESSAGE {
+            warn!("ldpreload: {len}-byte message on stream connection, closing it");
+            break;
+        }
+        buf.resize(len, 0);
+        if conn.read_exact(&mut buf).await.is_err() {
+            break;
+        }
+        receive_message(&buf, &sink);
     }
 }
 
@@ -96,6 +326,7 @@ fn agent_trace_to_http_trace(a: AgentTrace) -> HttpTrace {
 
 pub struct LdPreloadCaptureBackend {
     socket_path: PathBuf,
+    ipc_mode: IpcMode,
     shutdown_tx: Option<oneshot::Sender<()>>,
     task_handle: Option<tokio::task::JoinHandle<()>>,
 }
@@ -108,62 +339,94 @@ impl LdPreloadCaptureBackend {
     pub fn new(socket_path: PathBuf) -> Self {
         Self {
             socket_path,
+            ipc_mode: IpcMode::default(),
             shutdown_tx: None,
             task_handle: None,
         }
     }
 
+    /// Listen with `mode` instead of datagrams (builder pattern). The target
+    /// process must get the same mode in `PHANTOM_IPC_MODE`.
+    pub fn with_ipc_mode(mut self, mode: IpcMode) -> Self {
+        self.ipc_mode = mode;
+        self
+    }
+
     /// The Unix socket path agents must write to (`PHANTOM_SOCKET` env var).
     pub fn socket_path(&self) -> &Path {
         &self.socket_path
     }
+
+    /// The transport agents must use (`PHANTOM_IPC_MODE` env var).
+    pub fn ipc_mode(&self) -> IpcMode {
+        self.ipc_mode
+    }
+
+    /// Loads the agent at `agent_lib` into the running process `pid`, set up
+    /// to report to this backend, which must be started. Returns the number
+    /// of call sites the agent hooked. Needs `CAP_SYS_PTRACE`, or the
+    /// process's own user with Yama's `ptrace_scope` at 0.
+    pub fn attach(&self, pid: u32, agent_lib: &Path) -> Result<u32, CaptureError> {
+        if self.shutdown_tx.is_none() {
+            return Err(CaptureError::Other(
+                "ldpreload backend not started".to_string(),
+            ));
+        }
+        // The process may run as another user, who must reach the socket.
+        let status = std::fs::read_to_string(format!("/proc/{pid}/status"))
+            .map_err(|e| CaptureError::Other(format!("process {pid}: {e}")))?;
+        let uid = status
+            .lines()
+            .find_map(|line| line.strip_prefix("Uid:"))
+            .and_then(|ids| ids.split_whitespace().nth(1))
+            .and_then(|uid| uid.parse().ok());
+        // SAFETY: geteuid(2) has no preconditions and cannot fail.
+        let euid = unsafe { libc::geteuid() };
+        if uid.is_some_and(|uid| uid != euid) {
+            std::os::unix::fs::chown(&self.socket_path, uid, None)
+                .map_err(|e| CaptureError::Other(format!("{}: {e}", self.socket_path.display())))?;
+        }
+        let env = [
+            ("PHANTOM_SOCKET", self.socket_path.as_os_str()),
+            ("PHANTOM_IPC_MODE", OsStr::new(self.ipc_mode.as_str())),
+        ];
+        crate::inject::inject_agent(pid, agent_lib, &env)
+            .map_err(|e| CaptureError::Other(format!("attaching to process {pid}: {e}")))
+    }
 }
 
 impl CaptureBackend for LdPreloadCaptureBackend {
-    fn start(&mut self) -> Result<mpsc::Receiver<HttpTrace>, CaptureError> {
+    fn start(&mut self) -> Result<mpsc::Receiver<TraceEvent>, CaptureError> {
         // Remove stale socket file if it exists.
         let _ = std::fs::remove_file(&self.socket_path);
 
-        let socket = UnixDatagram::bind(&self.socket_path)
-            .map_err(|e| CaptureError::StartFailed(e.to_string()))?;
-
-        let (trace_tx, trace_rx) = mpsc::channel(4096);
-        let (shutdown_tx, mut shutdown_rx) = oneshot::channel::<()>();
-
-        let task_handle = tokio::spawn(async move {
-            let mut buf = vec![0u8; 65536];
-            loop {
-                tokio::select! {
-                    _ = &mut shutdown_rx => break,
-                    result = socket.recv_from(&mut buf) => {
-                        match result {
-                            Ok((n, _from)) => {
-                                match serde_json::from_slice::<AgentTrace>(&buf[..n]) {
-                                    Ok(agent_trace) => {
-                                        let trace = agent_trace_to_http_trace(agent_trace);
-                                        debug!(url = %trace.url, "captured via ldpreload");
-                                        if trace_tx.try_send(trace).is_err() {
-                                            warn!("ldpreload trace channel full, dropping");
-                                        }
-                                    }
-                                    Err(e) => {
-                                        warn!("ldpreload: failed to parse agent message: {e}");
-                                    }
-                                }
-                            }
-                            Err(e) => {
-                                warn!("ldpreload socket recv error: {e}");
-                                break;
-                            }
-                        }
-                    }
-                }
+        let start_failed = |e: std::io::Error| CaptureError::StartFailed(e.to_string());
+        let (events, events_rx) = mpsc::channel(4096);
+        let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+        let sink = AgentSink {
+            origin: "ldpreload",
+            events,
+        };
+
+        let task_handle = match self.ipc_mode {
+            IpcMode::Datagram => {
+                let socket = UnixDatagram::bind(&self.socket_path).map_err(start_failed)?;
+                tokio::spawn(serve_datagrams(socket, shutdown_rx, sink))
             }
-        });
+            IpcMode::SeqPacket => {
+                let listener =
+                    UnixSeqpacketListener::bind(&self.socket_path).map_err(start_failed)?;
+                tokio::spawn(serve_seqpacket(listener, shutdown_rx, sink))
+            }
+            IpcMode::Stream => {
+                let listener = UnixListener::bind(&self.socket_path).map_err(start_failed)?;
+                tokio::spawn(serve_stream(listener, shutdown_rx, sink))
+            }
+        };
 
         self.shutdown_tx = Some(shutdown_tx);
         self.task_handle = Some(task_handle);
-        Ok(trace_rx)
+        Ok(events_rx)
     }
 
     fn stop(&mut self) -> Result<(), CaptureError> {
@@ -178,3 +441,157 @@ impl CaptureBackend for LdPreloadCaptureBackend {
         "ldpreload"
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_fragment(id: u64, index: u16, total: u16, payload: &[u8]) -> Vec<u8> {
+        let mut frame = FRAGMENT_MAGIC.to_vec();
+        frame.extend_from_slice(&id.to_le_bytes());
+        frame.extend_from_slice(&index.to_le_bytes());
+        frame.extend_from_slice(&total.to_le_bytes());
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    #[test]
+    fn test_reassembles_interleaved_fragments() {
+        let mut r = Reassembler::default();
+        let now = Instant::now();
+        assert_eq!(r.push(&make_fragment(1, 0, 2, b"{\"a\":"), now), None);
+        assert_eq!(r.push(&make_fragment(2, 1, 2, b"2}"), now), None);
+        assert_eq!(
+            r.push(&make_fragment(1, 1, 2, b"1}"), now).as_deref(),
+            Some(&b"{\"a\":1}"[..])
+        );
+        assert_eq!(
+            r.push(&make_fragment(2, 0, 2, b"{\"b\":"), now).as_deref(),
+            Some(&b"{\"b\":2}"[..])
+        );
+        assert!(r.partial.is_empty());
+    }
+
+    #[test]
+    fn test_fragments_are_told_from_json() {
+        assert!(is_fragment(&make_fragment(1, 0, 1, b"{}")));
+        assert!(!is_fragment(b"{\"method\":\"GET\"}"));
+    }
+
+    #[test]
+    fn test_incomplete_messages_expire() {
+        let mut r = Reassembler::default();
+        let start = Instant::now();
+        assert_eq!(r.push(&make_fragment(7, 0, 2, b"{\"a\":"), start), None);
+        let later = start + FRAGMENT_TIMEOUT;
+        assert_eq!(r.push(&make_fragment(7, 1, 2, b"1}"), later), None);
+        assert_eq!(r.partial.len(), 1, "the late fragment starts a new message");
+    }
+
+    fn make_sink() -> (AgentSink, mpsc::Receiver<TraceEvent>) {
+        let (events, events_rx) = mpsc::channel(8);
+        let sink = AgentSink {
+            origin: "ldpreload",
+            events,
+        };
+        (sink, events_rx)
+    }
+
+    fn trace_url(event: TraceEvent) -> String {
+        match event {
+            TraceEvent::Http(trace) => trace.url,
+            other => panic!("expected an HTTP trace, got {other:?}"),
+        }
+    }
+
+    fn make_batch(msgs: &[&[u8]]) -> Vec<u8> {
+        let mut batch = BATCH_MAGIC.to_vec();
+        for msg in msgs {
+            batch.extend_from_slice(&(msg.len() as u32).to_le_bytes());
+            batch.extend_from_slice(msg);
+        }
+        batch
+    }
+
+    #[test]
+    fn test_split_batch() {
+        let batch = make_batch(&[b"{\"a\":1}", b"", b"{\"b\":2}"]);
+        assert_eq!(
+            split_batch(&batch),
+            vec![&b"{\"a\":1}"[..], b"", b"{\"b\":2}"]
+        );
+        assert_eq!(
+            split_batch(&batch[..batch.len() - 1]),
+            vec![&b"{\"a\":1}"[..], b""]
+        );
+        assert!(split_batch(BATCH_MAGIC).is_empty());
+    }
+
+    #[test]
+    fn test_batched_traces_are_all_dispatched() {
+        let (sink, mut events_rx) = make_sink();
+        let get = br#"{"method":"GET","url":"http://example.com/a","status_code":200,
+            "request_headers":{},"response_headers":{},"duration_ms":1,"timestamp_ms":0}"#;
+        let delete = br#"{"method":"DELETE","url":"http://example.com/b","status_code":204,
+            "request_headers":{},"response_headers":{},"duration_ms":1,"timestamp_ms":0}"#;
+
+        receive_message(&make_batch(&[get, delete]), &sink);
+        assert_eq!(
+            trace_url(events_rx.try_recv().unwrap()),
+            "http://example.com/a"
+        );
+        assert_eq!(
+            trace_url(events_rx.try_recv().unwrap()),
+            "http://example.com/b"
+        );
+        assert!(events_rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_ipc_mode_round_trips_through_env_value() {
+        for mode in [IpcMode::Datagram, IpcMode::SeqPacket, IpcMode::Stream] {
+            assert_eq!(mode.as_str().parse::<IpcMode>(), Ok(mode));
+        }
+        assert_eq!("STREAM".parse::<IpcMode>(), Ok(IpcMode::Stream));
+        assert!("udp".parse::<IpcMode>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_stream_mode_reads_length_prefixed_messages() {
+        use tokio::io::AsyncWriteExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("agent.sock");
+        let mut backend = LdPreloadCaptureBackend::new(path.clone()).with_ipc_mode(IpcMode::Stream);
+        let mut events_rx = backend.start().unwrap();
+
+        let msg = br#"{"method":"GET","url":"http://example.com/","status_code":200,
+            "request_headers":{},"response_headers":{},"duration_ms":1,"timestamp_ms":0}"#;
+        let mut conn = UnixStream::connect(&path).await.unwrap();
+        for _ in 0..2 {
+            conn.write_all(&(msg.len() as u32).to_le_bytes())
+                .await
+                .unwrap();
+            conn.write_all(msg).await.unwrap();
+        }
+
+        for _ in 0..2 {
+            let Some(TraceEvent::Http(trace)) = events_rx.recv().await else {
+                panic!("expected an HTTP trace");
+            };
+            assert_eq!(trace.url, "http://example.com/");
+            assert_eq!(trace.status_code, 200);
+        }
+        backend.stop().unwrap();
+    }
+
+    #[test]
+    fn test_malformed_fragments_are_ignored() {
+        let mut r = Reassembler::default();
+        let now = Instant::now();
+        assert_eq!(r.push(&make_fragment(1, 2, 2, b"x"), now), None);
+        assert_eq!(r.push(&make_fragment(1, 0, 0, b"x"), now), None);
+        assert_eq!(r.push(&FRAGMENT_MAGIC[..], now), None);
+        assert!(r.partial.is_empty());
+    }
+}
@@ -1,11 +1,73 @@
+mod accesslog;
+mod agent_msg;
+pub mod allowlist;
+pub mod ca;
+mod capture_file;
+pub mod client_cert;
+mod decode;
 pub mod fault;
+mod flows;
+mod har;
+mod http1;
+mod ingest;
+pub mod intercept;
+pub mod mock;
+pub mod policy;
 mod proxy;
+mod quic;
+mod remote;
+pub mod rewrite;
+pub mod shaping;
+mod socks;
+mod tcp;
+mod tee;
+mod timing;
+mod tls;
+mod upstream;
 
+#[cfg(target_os = "linux")]
+mod ebpf;
+#[cfg(target_os = "linux")]
+mod gotls;
+#[cfg(target_os = "linux")]
+mod inject;
+#[cfg(target_os = "linux")]
+mod keylog;
 #[cfg(target_os = "linux")]
 mod ldpreload;
+#[cfg(target_os = "linux")]
+mod packet;
+#[cfg(target_os = "linux")]
+mod pcap;
+#[cfg(target_os = "linux")]
+mod transparent;
+#[cfg(windows)]
+mod windivert;
 
+pub use accesslog::AccessLogCaptureBackend;
+pub use allowlist::{ClientAllowlist, parse_cidr};
+pub use ca::ProxyCa;
+pub use capture_file::CaptureFile;
+pub use client_cert::{ClientCertConfig, ClientCertRule, parse_client_cert_spec};
 pub use fault::{FaultConfig, FaultRule, parse_fault_spec};
+pub use har::HarFile;
+pub use ingest::{INGEST_PATH, IngestCaptureBackend};
+pub use intercept::{InterceptConfig, InterceptRule, parse_intercept_spec};
+pub use mock::{MockRoutes, serve_mock};
+pub use policy::{CapturePolicies, HostPolicy};
 pub use proxy::ProxyCaptureBackend;
+pub use remote::RemoteCaptureBackend;
+pub use rewrite::RewriteConfig;
+pub use shaping::{ShapingConfig, ShapingRule, parse_shaping_spec};
+pub use upstream::UpstreamProxy;
 
 #[cfg(target_os = "linux")]
-pub use ldpreload::LdPreloadCaptureBackend;
+pub use ebpf::EbpfCaptureBackend;
+#[cfg(target_os = "linux")]
+pub use keylog::KeyLogCaptureBackend;
+#[cfg(target_os = "linux")]
+pub use ldpreload::{IpcMode, LdPreloadCaptureBackend};
+#[cfg(target_os = "linux")]
+pub use pcap::PcapCaptureBackend;
+#[cfg(windows)]
+pub use windivert::WinDivertCaptureBackend;
@@ -0,0 +1,534 @@
+//! Mock server answering requests from recorded traces (`phantom mock`).
+//!
+//! Requests are matched on method and path (including the query string);
+//! with body matching enabled, a hash of the request body must match too.
+//! The host is ignored, so one mock server stands in for every origin a
+//! capture talked to. When several traces share a key their responses are
+//! served in recorded order, the last one repeating, so a test that polls an
+//! endpoint sees the same sequence the capture did.
+//!
+//! The proxy's `--replay` mode uses the same index with stricter
+//! [`MatchOptions`]: origin and body always count, and queries must match.
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+
+use bytes::Bytes;
+use http::header::{HeaderName, HeaderValue};
+use http_body_util::{BodyExt, Full};
+use hyper::body::Incoming;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Request, Response};
+use hyper_util::rt::TokioIo;
+use phantom_core::trace::{HttpMethod, HttpTrace};
+use tokio::net::TcpListener;
+use tracing::{debug, info, warn};
+
+/// Response headers that describe the recorded connection or framing rather
+/// than the response itself; hyper sets them afresh.
+const HOP_HEADERS: &[&str] = &[
+    "connection",
+    "content-length",
+    "keep-alive",
+    "transfer-encoding",
+];
+
+/// Which parts of a request beyond method and path select a recording.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MatchOptions {
+    /// Match a hash of the request body.
+    pub body: bool,
+    /// Match scheme and host too, for recordings spanning several origins.
+    pub origin: bool,
+    /// Answer an unknown query string with a recording of the bare path.
+    pub query_fallback: bool,
+}
+
+/// What a request is matched on.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RequestKey {
+    pub method: HttpMethod,
+    /// Path and query, e.g. `/v1/users?page=2`; the whole URL when origins
+    /// are matched.
+    pub path: String,
+    /// FNV-1a hash of the request body, when bodies are matched.
+    pub body_hash: Option<u64>,
+}
+
+impl RequestKey {
+    /// Builds the key for a request to `url` (absolute or origin-form).
+    /// A missing body hashes like an empty one.
+    pub fn new(method: HttpMethod, url: &str, body: Option<&[u8]>, options: MatchOptions) -> Self {
+        let path = if options.origin { url } else { path_of(url) };
+        Self {
+            method,
+            path: path.to_string(),
+            body_hash: options.body.then(|| fnv1a(body.unwrap_or_default())),
+        }
+    }
+
+    /// The path without its query string.
+    fn bare_path(&self) -> &str {
+        self.path.split('?').next().unwrap_or(&self.path)
+    }
+}
+
+/// A recorded response, ready to be served again.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MockResponse {
+    pub status_code: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl MockResponse {
+    /// The response recorded on `trace`. The full body is read back from
+    /// `response_body_file` when the capture had to truncate it; otherwise
+    /// the stored body is used, minus its `content-encoding` header when it
+    /// was stored decompressed. Headers that cannot be sent again are dropped.
+    pub fn from_trace(trace: &HttpTrace) -> Self {
+        let full_body = trace.response_body_file.as_ref().and_then(|path| {
+            std::fs::read(path)
+                .map_err(|e| warn!("mock: overflow body {path}: {e}"))
+                .ok()
+        });
+        let decoded = full_body.is_none() && trace.response_body_encoding.is_some();
+        let mut headers: Vec<(String, String)> = trace
+            .response_headers
+            .iter()
+            .filter(|(name, _)| !HOP_HEADERS.contains(&name.as_str()))
+            .filter(|(name, _)| !(decoded && *name == "content-encoding"))
+            .filter(|(name, value)| {
+                HeaderName::from_bytes(name.as_bytes()).is_ok()
+                    && HeaderValue::from_str(value).is_ok()
+            })
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+        headers.sort();
+        Self {
+            status_code: trace.status_code,
+            headers,
+            body: full_body
+                .or_else(|| trace.response_body.clone())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Recorded responses of one request key, served in order.
+#[derive(Debug)]
+struct Route {
+    responses: Vec<MockResponse>,
+    next: usize,
+}
+
+/// Recorded responses indexed by [`RequestKey`].
+#[derive(Debug, Default)]
+pub struct MockRoutes {
+    routes: HashMap<RequestKey, Route>,
+    options: MatchOptions,
+}
+
+impl MockRoutes {
+    /// Indexes `traces`, which must be oldest first.
+    pub fn from_traces<'a>(
+        traces: impl IntoIterator<Item = &'a HttpTrace>,
+        options: MatchOptions,
+    ) -> Self {
+        let mut routes = Self {
+            routes: HashMap::new(),
+            options,
+        };
+        for trace in traces {
+            routes.insert(trace);
+        }
+        routes
+    }
+
+    /// Adds `trace`'s response after those already recorded for its key.
+    pub fn insert(&mut self, trace: &HttpTrace) {
+        let key = RequestKey::new(
+            trace.method,
+            &trace.url,
+            trace.request_body.as_deref(),
+            self.options,
+        );
+        self.routes
+            .entry(key)
+            .or_insert_with(|| Route {
+                responses: Vec::new(),
+                next: 0,
+            })
+            .responses
+            .push(MockResponse::from_trace(trace));
+    }
+
+    /// Number of distinct request keys.
+    pub fn len(&self) -> usize {
+        self.routes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+
+    /// The next recorded response for a request, or `None` if nothing was
+    /// recorded for it. With [`MatchOptions::query_fallback`], a recording of
+    /// the same path with a different query string answers an unknown one.
+    pub fn next_response(
+        &mut self,
+        method: HttpMethod,
+        url: &str,
+        body: Option<&[u8]>,
+    ) -> Option<MockResponse> {
+        let key = RequestKey::new(method, url, body, self.options);
+        let route = if self.routes.contains_key(&key) {
+            self.routes.get_mut(&key)
+        } else if self.options.query_fallback {
+            // Pick the lowest key so the fallback does not depend on
+            // HashMap order.
+            let fallback = self
+                .routes
+                .keys()
+                .filter(|k| {
+                    k.method == key.method
+                        && k.body_hash == key.body_hash
+                        && k.bare_path() == key.bare_path()
+                })
+                .min_by(|a, b| a.path.cmp(&b.path))
+                .cloned()?;
+            self.routes.get_mut(&fallback)
+        } else {
+            None
+        }?;
+        let response = route.responses.get(route.next)?.clone();
+        if route.next + 1 < route.responses.len() {
+            route.next += 1;
+        }
+        Some(response)
+    }
+}
+
+/// Serves `routes` over HTTP/1.1 on `listener` until the task is dropped.
+/// Unknown requests get a 404; every answer carries `x-phantom-mock: hit`
+/// or `miss`.
+pub async fn serve_mock(listener: TcpListener, routes: MockRoutes) -> std::io::Result<()> {
+    let routes = Arc::new(Mutex::new(routes));
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let routes = Arc::clone(&routes);
+        tokio::spawn(async move {
+            let service = service_fn(move |req| {
+                let routes = Arc::clone(&routes);
+                async move { Ok::<_, Infallible>(answer(&routes, req).await) }
+            });
+            if let Err(e) = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                debug!("mock connection from {peer}: {e}");
+            }
+        });
+    }
+}
+
+async fn answer(routes: &Mutex<MockRoutes>, req: Request<Incoming>) -> Response<Full<Bytes>> {
+    let method = req.method().clone();
+    let path = req
+        .uri()
+        .path_and_query()
+        .map_or("/", |pq| pq.as_str())
+        .to_string();
+    let body = match req.into_body().collect().await {
+        Ok(collected) => collected.to_bytes(),
+        Err(e) => {
+            debug!("mock: reading request body: {e}");
+            Bytes::new()
+        }
+    };
+    let recorded = method.as_str().parse::<HttpMethod>().ok().and_then(|m| {
+        let mut routes = routes.lock().unwrap_or_else(|e| e.into_inner());
+        routes.next_response(m, &path, (!body.is_empty()).then_some(&body[..]))
+    });
+
+    let Some(recorded) = recorded else {
+        info!("mock miss: {method} {path}");
+        return Response::builder()
+            .status(404)
+            .header("content-type", "text/plain; charset=utf-8")
+            .header("x-phantom-mock", "miss")
+            .body(Full::new(Bytes::from(format!(
+                "phantom mock: no recorded response for {method} {path}\n"
+            ))))
+            .expect("valid miss response");
+    };
+    info!("mock hit: {method} {path} → {}", recorded.status_code);
+    let mut response = Response::builder().status(recorded.status_code);
+    for (name, value) in &recorded.headers {
+        response = response.header(name.as_str(), value.as_str());
+    }
+    response
+        .header("x-phantom-mock", "hit")
+        .body(Full::new(Bytes::from(recorded.body)))
+        .unwrap_or_else(|e| {
+            warn!("mock: recorded response for {method} {path} is invalid: {e}");
+            let mut response = Response::new(Full::new(Bytes::new()));
+            *response.status_mut() = http::StatusCode::BAD_GATEWAY;
+            response
+        })
+}
+
+/// Path and query of an absolute or origin-form URL.
+fn path_of(url: &str) -> &str {
+    let Some((_, rest)) = url.split_once("://") else {
+        return url;
+    };
+    rest.find('/').map_or("/", |i| &rest[i..])
+}
+
+/// 64-bit FNV-1a, stable across runs (unlike `DefaultHasher`).
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use phantom_core::trace::{BodyEncoding, SpanId, TraceId};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+
+    /// The options `phantom mock` serves with.
+    const MOCK: MatchOptions = MatchOptions {
+        body: false,
+        origin: false,
+        query_fallback: true,
+    };
+
+    fn make_trace(
+        method: HttpMethod,
+        url: &str,
+        request_body: Option<&str>,
+        status: u16,
+    ) -> HttpTrace {
+        HttpTrace {
+            span_id: SpanId([1; 8]),
+            trace_id: TraceId([2; 16]),
+            parent_span_id: None,
+            method,
+            url: url.to_string(),
+            request_headers: HashMap::new(),
+            request_body: request_body.map(|b| b.as_bytes().to_vec()),
+            status_code: status,
+            response_headers: HashMap::from([
+                ("content-type".to_string(), "application/json".to_string()),
+                ("content-length".to_string(), "2".to_string()),
+            ]),
+            response_trailers: HashMap::new(),
+            response_body: Some(format!("{{\"status\":{status}}}").into_bytes()),
+            timestamp: SystemTime::now(),
+            duration: Duration::from_millis(5),
+            source_addr: None,
+            dest_addr: None,
+            protocol_version: "HTTP/1.1".to_string(),
+            request_body_file: None,
+            response_body_file: None,
+            connection_id: None,
+            process: None,
+            thread_id: None,
+            tls: None,
+            timing: None,
+            request_size: None,
+            response_size: None,
+            fault: None,
+            replay: None,
+            request_body_encoding: None,
+            response_body_encoding: None,
+            elasticsearch: None,
+            soap: None,
+            graphql: None,
+        }
+    }
+
+    #[test]
+    fn test_path_of() {
+        assert_eq!(
+            path_of("http://api.example.com/v1/users?page=2"),
+            "/v1/users?page=2"
+        );
+        assert_eq!(path_of("https://api.example.com"), "/");
+        assert_eq!(path_of("/health"), "/health");
+    }
+
+    #[test]
+    fn test_matches_method_and_path_ignoring_host() {
+        let traces = [
+            make_trace(HttpMethod::Get, "http://a.example/users", None, 200),
+            make_trace(HttpMethod::Post, "http://a.example/users", None, 201),
+        ];
+        let mut routes = MockRoutes::from_traces(&traces, MOCK);
+        assert_eq!(routes.len(), 2);
+        let hit = routes
+            .next_response(HttpMethod::Post, "/users", Some(b"{}"))
+            .unwrap();
+        assert_eq!(hit.status_code, 201);
+        assert_eq!(
+            hit.headers,
+            vec![("content-type".to_string(), "application/json".to_string())]
+        );
+        assert!(
+            routes
+                .next_response(HttpMethod::Delete, "/users", None)
+                .is_none()
+        );
+        assert!(
+            routes
+                .next_response(HttpMethod::Get, "/other", None)
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_repeated_requests_replay_in_order() {
+        let traces = [
+            make_trace(HttpMethod::Get, "http://a.example/job", None, 202),
+            make_trace(HttpMethod::Get, "http://a.example/job", None, 200),
+        ];
+        let mut routes = MockRoutes::from_traces(&traces, MOCK);
+        let statuses: Vec<u16> = (0..3)
+            .map(|_| {
+                routes
+                    .next_response(HttpMethod::Get, "/job", None)
+                    .unwrap()
+                    .status_code
+            })
+            .collect();
+        assert_eq!(statuses, [202, 200, 200]);
+    }
+
+    #[test]
+    fn test_body_matching() {
+        let traces = [
+            make_trace(HttpMethod::Post, "http://a.example/q", Some("a"), 200),
+            make_trace(HttpMethod::Post, "http://a.example/q", Some("b"), 400),
+        ];
+        let mut routes = MockRoutes::from_traces(&traces, MatchOptions { body: true, ..MOCK });
+        let b = routes
+            .next_response(HttpMethod::Post, "/q", Some(b"b"))
+            .unwrap();
+        assert_eq!(b.status_code, 400);
+        assert!(
+            routes
+                .next_response(HttpMethod::Post, "/q", Some(b"c"))
+                .is_none()
+        );
+        assert!(routes.next_response(HttpMethod::Post, "/q", None).is_none());
+    }
+
+    #[test]
+    fn test_query_fallback() {
+        let traces = [make_trace(
+            HttpMethod::Get,
+            "http://a.example/search?q=x&t=1",
+            None,
+            200,
+        )];
+        let mut routes = MockRoutes::from_traces(&traces, MOCK);
+        assert!(
+            routes
+                .next_response(HttpMethod::Get, "/search?q=x&t=2", None)
+                .is_some()
+        );
+        assert!(
+            routes
+                .next_response(HttpMethod::Get, "/search", None)
+                .is_some()
+        );
+        assert!(
+            routes
+                .next_response(HttpMethod::Get, "/searches", None)
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_strict_matching_for_replay() {
+        let traces = [make_trace(
+            HttpMethod::Get,
+            "https://a.example/search?q=x",
+            None,
+            200,
+        )];
+        let options = MatchOptions {
+            body: true,
+            origin: true,
+            query_fallback: false,
+        };
+        let mut routes = MockRoutes::from_traces(&traces, options);
+        assert!(
+            routes
+                .next_response(HttpMethod::Get, "https://a.example/search?q=x", None)
+                .is_some()
+        );
+        assert!(
+            routes
+                .next_response(HttpMethod::Get, "https://b.example/search?q=x", None)
+                .is_none()
+        );
+        assert!(
+            routes
+                .next_response(HttpMethod::Get, "https://a.example/search?q=y", None)
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_decompressed_body_drops_content_encoding() {
+        let mut trace = make_trace(HttpMethod::Get, "http://a.example/", None, 200);
+        trace
+            .response_headers
+            .insert("content-encoding".to_string(), "gzip".to_string());
+        trace.response_body_encoding = Some(BodyEncoding {
+            encoding: "gzip".to_string(),
+            truncated: false,
+        });
+        let response = MockResponse::from_trace(&trace);
+        assert!(
+            response
+                .headers
+                .iter()
+                .all(|(name, _)| name != "content-encoding")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_serve_mock_answers_over_http() {
+        let traces = [make_trace(
+            HttpMethod::Get,
+            "http://a.example/health",
+            None,
+            200,
+        )];
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(serve_mock(listener, MockRoutes::from_traces(&traces, MOCK)));
+
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET /health HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+        assert!(response.contains("x-phantom-mock: hit"));
+        assert!(response.ends_with("{\"status\":200}"));
+        server.abort();
+    }
+}
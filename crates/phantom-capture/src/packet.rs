@@ -0,0 +1,135 @@
+//! Packet capture through an `AF_PACKET` socket (Linux).
+//!
+//! The socket is opened in cooked (`SOCK_DGRAM`) mode, so packets arrive
+//! without their link-layer header whatever the interface: each one is an
+//! IPv4 or IPv6 packet, ready for the TCP reassembler. Opening it needs root
+//! or `CAP_NET_RAW`. On loopback every packet is seen twice, going out and
+//! coming in; the reassembler drops the second copy.
+
+use std::ffi::CString;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::time::{Duration, SystemTime};
+
+use phantom_core::capture::TraceEvent;
+use phantom_core::trace::HttpTrace;
+use tokio::io::unix::AsyncFd;
+use tokio::sync::{mpsc, oneshot};
+use tracing::warn;
+
+use crate::flows::FlowCollector;
+
+/// Room for the largest packet the kernel hands over, offloads included.
+const MAX_PACKET: usize = 256 * 1024;
+
+pub(crate) struct PacketSocket {
+    fd: AsyncFd<OwnedFd>,
+}
+
+impl PacketSocket {
+    /// Captures on the interface named `interface`, or on all of them.
+    pub(crate) fn open(interface: Option<&str>) -> io::Result<Self> {
+        let protocol = (libc::ETH_P_ALL as u16).to_be();
+        // SAFETY: plain socket(2) call; the result is checked below.
+        let raw = unsafe {
+            libc::socket(
+                libc::AF_PACKET,
+                libc::SOCK_DGRAM | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC,
+                i32::from(protocol),
+            )
+        };
+        if raw < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: `raw` is a descriptor we just opened and own.
+        let fd = unsafe { OwnedFd::from_raw_fd(raw) };
+        if let Some(name) = interface {
+            let c_name = CString::new(name)
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "bad interface name"))?;
+            // SAFETY: `c_name` is a valid NUL-terminated string.
+            let index = unsafe { libc::if_nametoindex(c_name.as_ptr()) };
+            if index == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("no interface {name}"),
+                ));
+            }
+            // SAFETY: an all-zero sockaddr_ll is a valid (empty) address.
+            let mut addr: libc::sockaddr_ll = unsafe { std::mem::zeroed() };
+            addr.sll_family = libc::AF_PACKET as u16;
+            addr.sll_protocol = protocol;
+            addr.sll_ifindex = index as i32;
+            // SAFETY: `addr` is a sockaddr_ll of the length passed.
+            let rc = unsafe {
+                libc::bind(
+                    fd.as_raw_fd(),
+                    (&raw const addr).cast(),
+                    std::mem::size_of::<libc::sockaddr_ll>() as libc::socklen_t,
+                )
+            };
+            if rc != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(Self {
+            fd: AsyncFd::new(fd)?,
+        })
+    }
+
+    /// Waits for the next packet and copies it into `buf`, returning its
+    /// length. Packets longer than `buf` are cut short.
+    pub(crate) async fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let mut guard = self.fd.readable().await?;
+            let received = guard.try_io(|fd| {
+                // SAFETY: `buf` is writable for `buf.len()` bytes.
+                let n =
+                    unsafe { libc::recv(fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len(), 0) };
+                if n < 0 {
+                    Err(io::Error::last_os_error())
+                } else {
+                    Ok(n as usize)
+                }
+            });
+            if let Ok(result) = received {
+                return result;
+            }
+        }
+    }
+}
+
+/// Feeds packets from `socket` to `collector` until shutdown, sending the
+/// traces it completes. Open connections are flushed at the end.
+pub(crate) async fn capture(
+    socket: PacketSocket,
+    mut collector: FlowCollector,
+    trace_tx: mpsc::Sender<TraceEvent>,
+    mut shutdown_rx: oneshot::Receiver<()>,
+) {
+    let mut buf = vec![0u8; MAX_PACKET];
+    let mut expiry = tokio::time::interval(Duration::from_secs(30));
+    loop {
+        tokio::select! {
+            _ = &mut shutdown_rx => break,
+            _ = expiry.tick() => {
+                send_traces(&trace_tx, collector.expire(SystemTime::now()));
+            }
+            received = socket.recv(&mut buf) => match received {
+                Ok(len) => send_traces(&trace_tx, collector.packet(&buf[..len], SystemTime::now())),
+                Err(e) => {
+                    warn!("packet capture: {e}");
+                    break;
+                }
+            },
+        }
+    }
+    send_traces(&trace_tx, collector.flush(SystemTime::now()));
+}
+
+fn send_traces(trace_tx: &mpsc::Sender<TraceEvent>, traces: Vec<HttpTrace>) {
+    for trace in traces {
+        if trace_tx.try_send(trace.into()).is_err() {
+            warn!("Trace channel full, dropping trace");
+        }
+    }
+}
@@ -0,0 +1,70 @@
+//! Live packet-capture backend — Linux only.
+//!
+//! Captures TCP packets on an `AF_PACKET` socket (see [`packet`]), on one
+//! interface or all of them, and parses every connection seen from its SYN
+//! as plain HTTP/1 (see [`FlowCollector`]). Nothing is spawned or injected,
+//! so traffic of processes phantom didn't start — other services, containers
+//! on a bridge — is observed as it passes. Connections that turn out not to
+//! be HTTP/1 (TLS, HTTP/2, other protocols) are ignored, and traces carry no
+//! process. Capturing needs root or `CAP_NET_RAW`.
+//!
+//! [`packet`]: crate::packet
+
+use phantom_core::capture::{CaptureBackend, TraceEvent};
+use phantom_core::error::CaptureError;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::flows::FlowCollector;
+use crate::packet::{PacketSocket, capture};
+
+#[derive(Default)]
+pub struct PcapCaptureBackend {
+    interface: Option<String>,
+    shutdown_tx: Option<oneshot::Sender<()>>,
+    task_handle: Option<tokio::task::JoinHandle<()>>,
+}
+
+impl PcapCaptureBackend {
+    /// Create a backend capturing on every interface.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Capture on this network interface only (builder pattern), e.g. `lo`.
+    pub fn with_interface(mut self, interface: String) -> Self {
+        self.interface = Some(interface);
+        self
+    }
+}
+
+impl CaptureBackend for PcapCaptureBackend {
+    fn start(&mut self) -> Result<mpsc::Receiver<TraceEvent>, CaptureError> {
+        let socket = PacketSocket::open(self.interface.as_deref()).map_err(|e| {
+            CaptureError::StartFailed(format!(
+                "opening packet capture: {e} (needs root or CAP_NET_RAW)"
+            ))
+        })?;
+
+        let (trace_tx, trace_rx) = mpsc::channel(4096);
+        let (shutdown_tx, shutdown_rx) = oneshot::channel();
+        self.shutdown_tx = Some(shutdown_tx);
+        self.task_handle = Some(tokio::spawn(capture(
+            socket,
+            FlowCollector::plaintext(),
+            trace_tx,
+            shutdown_rx,
+        )));
+        Ok(trace_rx)
+    }
+
+    fn stop(&mut self) -> Result<(), CaptureError> {
+        if let Some(tx) = self.shutdown_tx.take() {
+            let _ = tx.send(());
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "pcap"
+    }
+}
@@ -0,0 +1,155 @@
+//! Per-host capture policies.
+//!
+//! Loaded from a TOML file; the first entry whose pattern matches a
+//! request's host decides how much of it the proxy captures:
+//!
+//! ```toml
+//! [[host]]
+//! match = "*.cdn.example.com"   # exact host, or `*.` for subdomains too
+//! skip = true                   # forward untouched, no trace
+//!
+//! [[host]]
+//! match = "images.example.com"
+//! skip_bodies = true            # trace headers only
+//!
+//! [[host]]
+//! match = "*.example.com"
+//! max_body_size = 65536         # bytes kept per body, no overflow file
+//!
+//! [[host]]
+//! match = "bank.example.com"
+//! no_tls_intercept = true       # tunnel HTTPS without decrypting it
+//! ```
+//!
+//! Hosts without a matching entry are captured in full.
+
+use std::path::Path;
+
+use phantom_core::error::CaptureError;
+use serde::Deserialize;
+
+use crate::shaping::host_matches;
+
+/// Capture policies, first match wins.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CapturePolicies {
+    #[serde(default, rename = "host")]
+    pub hosts: Vec<HostPolicy>,
+}
+
+/// How the proxy captures traffic to hosts matching `host_pattern`.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HostPolicy {
+    /// Lowercase host name; `*.example.com` also matches subdomains.
+    #[serde(rename = "match")]
+    pub host_pattern: String,
+    /// Forward requests without rewrites, faults, shaping or a trace.
+    #[serde(default)]
+    pub skip: bool,
+    /// Trace requests without their bodies.
+    #[serde(default)]
+    pub skip_bodies: bool,
+    /// Bytes of each body kept on the trace, in place of the 1 MB limit.
+    /// Longer bodies are truncated without an overflow file.
+    #[serde(default)]
+    pub max_body_size: Option<usize>,
+    /// Tunnel `CONNECT`s to these hosts without TLS interception, so
+    /// certificate-pinning clients keep working. Nothing inside is traced.
+    #[serde(default)]
+    pub no_tls_intercept: bool,
+}
+
+impl CapturePolicies {
+    /// Loads and validates a policy file.
+    pub fn load(path: &Path) -> Result<Self, CaptureError> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| CaptureError::Other(format!("{}: {e}", path.display())))?;
+        Self::parse(&text).map_err(|e| CaptureError::Other(format!("{}: {e}", path.display())))
+    }
+
+    /// Parses and validates policies from TOML text.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut config: Self = toml::from_str(text).map_err(|e| e.to_string())?;
+        for (i, policy) in config.hosts.iter_mut().enumerate() {
+            policy.host_pattern = policy.host_pattern.trim().to_ascii_lowercase();
+            if policy.host_pattern.is_empty() {
+                return Err(format!("host {}: empty match", i + 1));
+            }
+        }
+        Ok(config)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hosts.is_empty()
+    }
+
+    pub fn policy_for(&self, host: &str) -> Option<&HostPolicy> {
+        self.hosts
+            .iter()
+            .find(|policy| host_matches(&policy.host_pattern, host))
+    }
+}
+
+impl HostPolicy {
+    /// Bytes of each body to keep, if the policy caps them.
+    pub fn body_limit(&self) -> Option<usize> {
+        if self.skip_bodies {
+            Some(0)
+        } else {
+            self.max_body_size
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_policies_in_order() {
+        let config = CapturePolicies::parse(
+            r#"
+            [[host]]
+            match = "static.example.com"
+            skip = true
+
+            [[host]]
+            match = "*.Example.com"
+            max_body_size = 4096
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.hosts.len(), 2);
+        assert!(config.policy_for("static.example.com").unwrap().skip);
+        let api = config.policy_for("api.example.com").unwrap();
+        assert_eq!(api.host_pattern, "*.example.com");
+        assert_eq!(api.body_limit(), Some(4096));
+        assert!(config.policy_for("example.org").is_none());
+    }
+
+    #[test]
+    fn test_parse_rejects_invalid_policies() {
+        for toml in [
+            "[[host]]\nskip = true",
+            "[[host]]\nmatch = \"\"",
+            "[[host]]\nmatch = \"a.test\"\nskip_body = true",
+            "[[host]]\nmatch = \"a.test\"\nmax_body_size = -1",
+        ] {
+            assert!(CapturePolicies::parse(toml).is_err(), "{toml}");
+        }
+    }
+
+    #[test]
+    fn test_body_limit() {
+        let policy = HostPolicy {
+            host_pattern: "a.test".to_string(),
+            skip_bodies: true,
+            max_body_size: Some(10),
+            ..HostPolicy::default()
+        };
+        assert_eq!(policy.body_limit(), Some(0));
+        assert_eq!(HostPolicy::default().body_limit(), None);
+    }
+}
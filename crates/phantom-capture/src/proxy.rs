@@ -1,20 +1,46 @@
 use std::collections::HashMap;
+use std::future::Future;
 use std::net::{IpAddr, SocketAddr};
-use std::sync::Arc;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant, SystemTime};
 
 use http::uri::Scheme;
 use hudsucker::certificate_authority::RcgenAuthority;
 use hudsucker::hyper::{Request, Response};
-use hudsucker::rcgen::{CertificateParams, KeyPair};
 use hudsucker::{Body, HttpContext, HttpHandler, Proxy, RequestOrResponse};
-use phantom_core::capture::CaptureBackend;
+use hyper::body::Body as HttpBody;
+use hyper_rustls::HttpsConnector;
+use hyper_util::client::legacy::Client;
+use hyper_util::client::legacy::connect::{Connect, HttpConnector, HttpInfo};
+use hyper_util::rt::TokioExecutor;
+use phantom_core::capture::{CaptureBackend, TraceEvent};
 use phantom_core::error::CaptureError;
-use phantom_core::trace::{HttpMethod, HttpTrace, SpanId, TraceId};
+use phantom_core::intercept::{InterceptDecision, InterceptedRequest};
+use phantom_core::trace::{
+    BodyEncoding, HttpMethod, HttpTrace, ReplayOutcome, SpanId, TimingPhases, TraceId,
+};
+use rustls::client::WantsClientCert;
 use tokio::sync::{mpsc, oneshot};
-use tracing::{info, warn};
+use tracing::{debug, info, warn};
 
+use crate::allowlist::ClientAllowlist;
+use crate::ca::ProxyCa;
+use crate::client_cert::{ClientCertConfig, ClientCertConnector};
+use crate::decode::decompress_body;
 use crate::fault::{FaultConfig, FaultRule};
+use crate::intercept::InterceptConfig;
+use crate::mock::MockRoutes;
+use crate::policy::{CapturePolicies, HostPolicy};
+use crate::quic::{self, H3_CLIENT_HEADER};
+use crate::rewrite::{RewriteConfig, content_type_for};
+use crate::shaping::{ShapingConfig, transfer_time};
+use crate::socks;
+use crate::tee::{BodyCapture, TeeStream};
+use crate::timing::{ConnectTiming, MarkTcp, TimedConnector, TimedResolver};
+#[cfg(target_os = "linux")]
+use crate::transparent;
+use crate::upstream::{UpstreamConnector, UpstreamProxy};
 
 /// Maximum body size to capture (1 MB).
 const MAX_BODY_SIZE: usize = 1024 * 1024;
@@ -24,9 +50,25 @@ pub struct ProxyCaptureBackend {
     listen_port: u16,
     insecure: bool,
     fault_config: FaultConfig,
+    rewrites: RewriteConfig,
+    shaping: ShapingConfig,
+    body_dir: Option<PathBuf>,
+    replay: Option<MockRoutes>,
+    allowlist: ClientAllowlist,
+    ca: Option<ProxyCa>,
+    upstream: Option<UpstreamProxy>,
+    client_certs: ClientCertConfig,
+    intercepts: InterceptConfig,
+    intercept_rx: Option<mpsc::Receiver<InterceptedRequest>>,
+    policies: CapturePolicies,
+    socks_port: Option<u16>,
+    transparent_port: Option<u16>,
+    h3_port: Option<u16>,
+    block_quic: bool,
     shutdown_tx: Option<oneshot::Sender<()>>,
     task_handle: Option<tokio::task::JoinHandle<()>>,
-    ca_cert_pem: Arc<std::sync::Mutex<Option<String>>>,
+    /// SOCKS / transparent front ends, aborted on stop.
+    front_end_handles: Vec<tokio::task::JoinHandle<()>>,
 }
 
 impl ProxyCaptureBackend {
@@ -36,9 +78,24 @@ impl ProxyCaptureBackend {
             listen_port,
             insecure,
             fault_config: FaultConfig::default(),
+            rewrites: RewriteConfig::default(),
+            shaping: ShapingConfig::default(),
+            body_dir: None,
+            replay: None,
+            allowlist: ClientAllowlist::default(),
+            ca: None,
+            upstream: None,
+            client_certs: ClientCertConfig::default(),
+            intercepts: InterceptConfig::default(),
+            intercept_rx: None,
+            policies: CapturePolicies::default(),
+            socks_port: None,
+            transparent_port: None,
+            h3_port: None,
+            block_quic: false,
             shutdown_tx: None,
             task_handle: None,
-            ca_cert_pem: Arc::new(std::sync::Mutex::new(None)),
+            front_end_handles: Vec::new(),
         }
     }
 
@@ -48,71 +105,305 @@ impl ProxyCaptureBackend {
         self
     }
 
-    /// Returns the PEM-encoded MITM CA certificate once the proxy has started.
+    /// Apply map-local / map-remote and header rewrite rules (builder
+    /// pattern), before fault injection.
+    pub fn with_rewrites(mut self, config: RewriteConfig) -> Self {
+        self.rewrites = config;
+        self
+    }
+
+    /// Throttle requests per host (builder pattern): added latency and
+    /// body bandwidth caps, applied after rewrites and before faults.
+    pub fn with_shaping(mut self, config: ShapingConfig) -> Self {
+        self.shaping = config;
+        self
+    }
+
+    /// Write bodies larger than the capture limit in full to files in `dir`
+    /// (builder pattern). Traces keep the truncated body and reference the
+    /// file via `request_body_file` / `response_body_file`.
+    pub fn with_body_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.body_dir = Some(dir.into());
+        self
+    }
+
+    /// Answer requests found in `recording` from it instead of forwarding
+    /// them (builder pattern). Traces are marked with their
+    /// [`ReplayOutcome`]; unknown requests go upstream as usual.
+    pub fn with_replay(mut self, recording: MockRoutes) -> Self {
+        self.replay = Some(recording);
+        self
+    }
+
+    /// Only serve clients in `allowlist` (builder pattern); others get a
+    /// 403 from the proxy and are disconnected by the SOCKS and transparent
+    /// front ends, without being traced.
+    pub fn with_client_allowlist(mut self, allowlist: ClientAllowlist) -> Self {
+        self.allowlist = allowlist;
+        self
+    }
+
+    /// Sign intercepted HTTPS connections with `ca` (builder pattern), e.g.
+    /// one persisted with [`ProxyCa::load_or_create`]. Without it a throwaway
+    /// CA is generated on every start.
+    pub fn with_ca(mut self, ca: ProxyCa) -> Self {
+        self.ca = Some(ca);
+        self
+    }
+
+    /// Forward outbound connections through `upstream` (builder pattern)
+    /// instead of connecting to destinations directly.
+    pub fn with_upstream_proxy(mut self, upstream: UpstreamProxy) -> Self {
+        self.upstream = Some(upstream);
+        self
+    }
+
+    /// Present client certificates to matching upstream hosts (builder
+    /// pattern), for servers that require mutual TLS.
+    pub fn with_client_certs(mut self, config: ClientCertConfig) -> Self {
+        self.client_certs = config;
+        self
+    }
+
+    /// Hold requests matching `config` until the user decides on them
+    /// (builder pattern); see [`take_intercepted`](Self::take_intercepted).
+    pub fn with_intercepts(mut self, config: InterceptConfig) -> Self {
+        self.intercepts = config;
+        self
+    }
+
+    /// Requests held by the intercept rules, available once after the
+    /// backend is started. Each waits until it is forwarded or dropped;
+    /// without a taker they are forwarded unchanged.
+    pub fn take_intercepted(&mut self) -> Option<mpsc::Receiver<InterceptedRequest>> {
+        self.intercept_rx.take()
+    }
+
+    /// Limit or skip capture per host (builder pattern): matching hosts can
+    /// have smaller body limits, header-only traces, no trace at all, or
+    /// their HTTPS tunnelled without interception.
+    pub fn with_capture_policies(mut self, policies: CapturePolicies) -> Self {
+        self.policies = policies;
+        self
+    }
+
+    /// Also accept SOCKS5 clients on `port` (builder pattern). Their
+    /// connections are captured exactly like HTTP proxy ones.
+    pub fn with_socks_port(mut self, port: u16) -> Self {
+        self.socks_port = Some(port);
+        self
+    }
+
+    /// Also accept connections iptables `REDIRECT`ed to `port` (builder
+    /// pattern), intercepting them at their `SO_ORIGINAL_DST` destination.
+    #[cfg(target_os = "linux")]
+    pub fn with_transparent_port(mut self, port: u16) -> Self {
+        self.transparent_port = Some(port);
+        self
+    }
+
+    /// Also accept HTTP/3 clients on UDP `port` (builder pattern). Their
+    /// requests are relayed through the proxy and captured like proxied
+    /// ones, recorded as `HTTP/3.0`.
+    pub fn with_h3_port(mut self, port: u16) -> Self {
+        self.h3_port = Some(port);
+        self
+    }
+
+    /// Strip HTTP/3 advertisements from `Alt-Svc` response headers (builder
+    /// pattern), so clients stay on TCP connections the proxy sees instead
+    /// of moving to QUIC.
+    pub fn with_block_quic(mut self) -> Self {
+        self.block_quic = true;
+        self
+    }
+
+    /// Returns the PEM-encoded MITM CA certificate.
     ///
-    /// `None` until `start()` has generated the CA (which happens before the
-    /// proxy begins listening, so it is always available once the proxy port
-    /// is confirmed to be accepting connections).
+    /// Without [`with_ca`](Self::with_ca), `None` until `start()` has
+    /// generated the CA (which happens before the proxy begins listening, so
+    /// it is always available once the proxy port accepts connections).
     pub fn ca_cert_pem(&self) -> Option<String> {
-        self.ca_cert_pem.lock().unwrap().clone()
+        self.ca.as_ref().map(|ca| ca.cert_pem().to_string())
     }
 }
 
 impl CaptureBackend for ProxyCaptureBackend {
-    fn start(&mut self) -> Result<mpsc::Receiver<HttpTrace>, CaptureError> {
+    fn start(&mut self) -> Result<mpsc::Receiver<TraceEvent>, CaptureError> {
+        let ca = match &self.ca {
+            Some(ca) => ca.authority(),
+            None => ProxyCa::generate().and_then(|ca| {
+                let authority = ca.authority();
+                self.ca = Some(ca);
+                authority
+            }),
+        }
+        .map_err(|e| CaptureError::StartFailed(e.to_string()))?;
+
+        let client = if !self.client_certs.is_empty() {
+            // Each certificate needs its own TLS config, so the client-cert
+            // connector replaces the TLS layer of the other clients.
+            let tls = client_cert_tls(self.insecure)?;
+            let start_failed = |e: String| CaptureError::StartFailed(format!("--client-cert: {e}"));
+            match self.upstream.clone() {
+                Some(upstream) => {
+                    let (host, port) = upstream.addr();
+                    info!("Forwarding through upstream proxy {host}:{port}");
+                    let connector = ClientCertConnector::new(
+                        MarkTcp(UpstreamConnector::new(upstream)),
+                        &self.client_certs,
+                        tls,
+                    )
+                    .map_err(start_failed)?;
+                    OutboundClient::UpstreamClientCert(timed_client(connector))
+                }
+                None => {
+                    let connector = ClientCertConnector::new(direct_tcp(), &self.client_certs, tls)
+                        .map_err(start_failed)?;
+                    OutboundClient::ClientCert(timed_client(connector))
+                }
+            }
+        } else {
+            let tls = hyper_rustls::HttpsConnectorBuilder::new();
+            let tls = if self.insecure {
+                tls.with_tls_config(insecure_tls_config())
+            } else if self.upstream.is_some() {
+                tls.with_native_roots().map_err(|e| {
+                    CaptureError::StartFailed(format!("failed to load root certificates: {e}"))
+                })?
+            } else {
+                // The roots hudsucker's built-in client trusted.
+                tls.with_webpki_roots()
+            };
+            let tls = tls.https_or_http().enable_http1();
+            match self.upstream.clone() {
+                Some(upstream) => {
+                    let (host, port) = upstream.addr();
+                    info!("Forwarding through upstream proxy {host}:{port}");
+                    OutboundClient::Upstream(timed_client(
+                        tls.wrap_connector(MarkTcp(UpstreamConnector::new(upstream))),
+                    ))
+                }
+                None => OutboundClient::Direct(timed_client(tls.wrap_connector(direct_tcp()))),
+            }
+        };
+
+        // Bind now so a busy port fails start() rather than the task.
+        let socks_listener = self
+            .socks_port
+            .map(|port| bind_front_end(self.bind_ip, port, "SOCKS"))
+            .transpose()?;
+        let transparent_listener = self
+            .transparent_port
+            .map(|port| bind_front_end(self.bind_ip, port, "transparent"))
+            .transpose()?;
+        let h3_endpoint = match (self.h3_port, &self.ca) {
+            (Some(port), Some(ca)) => Some(quic::endpoint(self.bind_ip, port, ca)?),
+            _ => None,
+        };
+
         let (trace_tx, trace_rx) = mpsc::channel(4096);
         let (shutdown_tx, shutdown_rx) = oneshot::channel();
+        let allowlist = Arc::new(self.allowlist.clone());
+        let intercept_tx = (!self.intercepts.is_empty()).then(|| {
+            let (tx, rx) = mpsc::channel(64);
+            self.intercept_rx = Some(rx);
+            tx
+        });
 
         let handler = TraceHandler {
             trace_tx,
             pending: None,
             fault_config: Arc::new(self.fault_config.clone()),
+            rewrites: Arc::new(self.rewrites.clone()),
+            shaping: Arc::new(self.shaping.clone()),
+            body_dir: self.body_dir.clone().map(Arc::from),
+            allowlist: Arc::clone(&allowlist),
+            intercepts: Arc::new(self.intercepts.clone()),
+            intercept_tx,
+            policies: Arc::new(self.policies.clone()),
+            block_quic: self.block_quic,
+            replay: self
+                .replay
+                .take()
+                .map(|routes| Arc::new(Mutex::new(routes))),
         };
 
         let bind_ip = self.bind_ip;
         let port = self.listen_port;
         let insecure = self.insecure;
-        let ca_cert_pem = Arc::clone(&self.ca_cert_pem);
 
         let task_handle = tokio::spawn(async move {
-            let (key_pair, ca_cert) = generate_ca();
-            *ca_cert_pem.lock().unwrap() = Some(ca_cert.pem());
-            let ca = RcgenAuthority::new(key_pair, ca_cert, 1000);
-
             let addr = SocketAddr::new(bind_ip, port);
             info!("Starting proxy on {addr}");
 
             if insecure {
                 info!("TLS verification disabled (--insecure)");
-                let client = build_insecure_client();
-                let proxy = Proxy::builder()
-                    .with_addr(addr)
-                    .with_client(client)
-                    .with_ca(ca)
-                    .with_http_handler(handler)
-                    .with_graceful_shutdown(async {
-                        shutdown_rx.await.ok();
-                    })
-                    .build();
-                if let Err(e) = proxy.start().await {
-                    warn!("Proxy error: {e}");
+            }
+            let shutdown = async {
+                shutdown_rx.await.ok();
+            };
+            let result = match client {
+                OutboundClient::Direct(client) => {
+                    serve_with_client(addr, client, ca, handler, shutdown).await
                 }
-            } else {
-                let proxy = Proxy::builder()
-                    .with_addr(addr)
-                    .with_rustls_client()
-                    .with_ca(ca)
-                    .with_http_handler(handler)
-                    .with_graceful_shutdown(async {
-                        shutdown_rx.await.ok();
-                    })
-                    .build();
-                if let Err(e) = proxy.start().await {
-                    warn!("Proxy error: {e}");
+                OutboundClient::Upstream(client) => {
+                    serve_with_client(addr, client, ca, handler, shutdown).await
+                }
+                OutboundClient::ClientCert(client) => {
+                    serve_with_client(addr, client, ca, handler, shutdown).await
                 }
+                OutboundClient::UpstreamClientCert(client) => {
+                    serve_with_client(addr, client, ca, handler, shutdown).await
+                }
+            };
+            if let Err(e) = result {
+                warn!("Proxy error: {e}");
             }
         });
 
+        // Front ends hand their tunnels to the proxy listener over loopback.
+        let proxy_ip = if bind_ip.is_unspecified() {
+            match bind_ip {
+                IpAddr::V4(_) => IpAddr::V4(std::net::Ipv4Addr::LOCALHOST),
+                IpAddr::V6(_) => IpAddr::V6(std::net::Ipv6Addr::LOCALHOST),
+            }
+        } else {
+            bind_ip
+        };
+        let proxy_addr = SocketAddr::new(proxy_ip, port);
+        if let Some(listener) = socks_listener {
+            if let Ok(addr) = listener.local_addr() {
+                info!("Starting SOCKS5 listener on {addr}");
+            }
+            self.front_end_handles.push(tokio::spawn(socks::serve(
+                listener,
+                proxy_addr,
+                Arc::clone(&allowlist),
+            )));
+        }
+        if let Some(listener) = transparent_listener {
+            if let Ok(addr) = listener.local_addr() {
+                info!("Starting transparent listener on {addr}");
+            }
+            #[cfg(target_os = "linux")]
+            self.front_end_handles.push(tokio::spawn(transparent::serve(
+                listener,
+                proxy_addr,
+                Arc::clone(&allowlist),
+            )));
+        }
+        if let Some(endpoint) = h3_endpoint {
+            if let Ok(addr) = endpoint.local_addr() {
+                info!("Starting HTTP/3 listener on udp/{addr}");
+            }
+            self.front_end_handles.push(tokio::spawn(quic::serve(
+                endpoint,
+                proxy_addr,
+                Arc::clone(&allowlist),
+            )));
+        }
+
         self.shutdown_tx = Some(shutdown_tx);
         self.task_handle = Some(task_handle);
 
@@ -123,6 +414,9 @@ impl CaptureBackend for ProxyCaptureBackend {
         if let Some(tx) = self.shutdown_tx.take() {
             let _ = tx.send(());
         }
+        for handle in self.front_end_handles.drain(..) {
+            handle.abort();
+        }
         Ok(())
     }
 
@@ -131,36 +425,27 @@ impl CaptureBackend for ProxyCaptureBackend {
     }
 }
 
-/// Generate a self-signed CA certificate for HTTPS interception.
-fn generate_ca() -> (KeyPair, hudsucker::rcgen::Certificate) {
-    use hudsucker::rcgen;
-
-    let mut params = CertificateParams::default();
-    params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
-    params
-        .distinguished_name
-        .push(rcgen::DnType::CommonName, "Phantom Proxy CA");
-    params
-        .distinguished_name
-        .push(rcgen::DnType::OrganizationName, "Phantom");
-
-    let key_pair = KeyPair::generate().expect("Failed to generate CA key pair");
-    let ca_cert = params
-        .self_signed(&key_pair)
-        .expect("Failed to self-sign CA certificate");
-
-    (key_pair, ca_cert)
-}
-
 /// Handler is cloned per-connection by hudsucker. Within a single connection,
 /// `handle_request` is always called before the corresponding `handle_response`,
 /// so we store the pending request info directly on `self`.
 #[derive(Clone)]
 struct TraceHandler {
-    trace_tx: mpsc::Sender<HttpTrace>,
+    trace_tx: mpsc::Sender<TraceEvent>,
     /// Pending request info, set in handle_request, consumed in handle_response.
     pending: Option<PendingRequest>,
     fault_config: Arc<FaultConfig>,
+    rewrites: Arc<RewriteConfig>,
+    shaping: Arc<ShapingConfig>,
+    body_dir: Option<Arc<Path>>,
+    allowlist: Arc<ClientAllowlist>,
+    intercepts: Arc<InterceptConfig>,
+    /// Where held requests go; None without intercept rules.
+    intercept_tx: Option<mpsc::Sender<InterceptedRequest>>,
+    policies: Arc<CapturePolicies>,
+    /// Strip `h3` entries from `Alt-Svc` (`--block-quic`).
+    block_quic: bool,
+    /// Recorded responses for `--replay`, shared by all connections.
+    replay: Option<Arc<Mutex<MockRoutes>>>,
 }
 
 #[derive(Clone)]
@@ -169,46 +454,374 @@ struct PendingRequest {
     url: String,
     request_headers: HashMap<String, String>,
     request_body: Option<Vec<u8>>,
+    request_body_file: Option<String>,
+    request_body_encoding: Option<BodyEncoding>,
     source_addr: Option<String>,
     timestamp: SystemTime,
     started_at: Instant,
     span_id: SpanId,
     trace_id: TraceId,
     protocol_version: String,
+    /// Indices of the rewrite rules matching this request.
+    rewrites: Vec<usize>,
+    /// Body capture limit of the host's capture policy, if it sets one.
+    body_limit: Option<usize>,
+    /// Response body cap of the shaping rule for this request's host.
+    down_bytes_per_sec: Option<u64>,
+    /// Faults injected so far, recorded on the trace.
+    faults: Vec<String>,
+    /// Set by a `truncate` fault: cut the response body short.
+    truncate: bool,
+    /// Whether `--replay` answered this request from the recording.
+    replay: Option<ReplayOutcome>,
+    /// When the request was handed to the outbound client, after any
+    /// injected delay; the start of TTFB.
+    forwarded_at: Option<Instant>,
+    /// Capture of a request body streamed upstream rather than buffered;
+    /// the `request_body*` fields are filled from it once it has ended.
+    streamed_body: Option<StreamedBody>,
+}
+
+impl PendingRequest {
+    /// Records the capture of a streamed request body, decoded from the
+    /// captured bytes like a streamed response body.
+    fn set_request_body(&mut self, capture: BodyCapture) {
+        let (body, file) = capture.finish();
+        let limit = self.body_limit.unwrap_or(MAX_BODY_SIZE);
+        (self.request_body, self.request_body_encoding) = match body
+            .as_deref()
+            .and_then(|body| decompress_body(&self.request_headers, body, limit))
+        {
+            Some((decoded, encoding)) => (Some(decoded), Some(encoding)),
+            None => (body, None),
+        };
+        self.request_body_file = file;
+    }
+}
+
+/// The capture of a request body streamed upstream. Its end and the
+/// response's can come in either order (a server may answer before the
+/// upload is done), so the trace is completed by whichever is last.
+#[derive(Clone, Default)]
+struct StreamedBody(Arc<Mutex<StreamedBodyState>>);
+
+#[derive(Default)]
+enum StreamedBodyState {
+    #[default]
+    Streaming,
+    Done(BodyCapture),
+    /// The response ended first and waits for the capture.
+    Waiting(Box<dyn FnOnce(BodyCapture) + Send>),
+}
+
+impl StreamedBody {
+    /// Called when the request body has ended, failed, or been dropped.
+    fn finish(&self, capture: BodyCapture) {
+        let mut state = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        match std::mem::take(&mut *state) {
+            StreamedBodyState::Waiting(then) => {
+                drop(state);
+                then(capture);
+            }
+            _ => *state = StreamedBodyState::Done(capture),
+        }
+    }
+
+    /// Runs `then` with the capture: now if the body has ended, otherwise
+    /// when it does.
+    fn then(&self, then: impl FnOnce(BodyCapture) + Send + 'static) {
+        let mut state = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        match std::mem::take(&mut *state) {
+            StreamedBodyState::Done(capture) => {
+                drop(state);
+                then(capture);
+            }
+            _ => *state = StreamedBodyState::Waiting(Box::new(then)),
+        }
+    }
 }
 
 impl HttpHandler for TraceHandler {
-    async fn handle_request(&mut self, ctx: &HttpContext, req: Request<Body>) -> RequestOrResponse {
+    /// Tunnels `CONNECT`s to hosts whose policy sets `no_tls_intercept`
+    /// instead of decrypting them.
+    async fn should_intercept(&mut self, _ctx: &HttpContext, req: &Request<Body>) -> bool {
+        let no_tls_intercept = request_host(req)
+            .map(str::to_ascii_lowercase)
+            .is_some_and(|host| {
+                self.policies
+                    .policy_for(&host)
+                    .is_some_and(|policy| policy.no_tls_intercept)
+            });
+        if no_tls_intercept {
+            debug!(
+                "Tunnelling {} without interception (capture policy)",
+                req.uri()
+            );
+        }
+        !no_tls_intercept
+    }
+
+    async fn handle_request(
+        &mut self,
+        ctx: &HttpContext,
+        mut req: Request<Body>,
+    ) -> RequestOrResponse {
+        if !self.allowlist.allows(ctx.client_addr.ip()) {
+            debug!("Refusing client {} (not in allowlist)", ctx.client_addr);
+            let mut res = Response::new(Body::from("phantom: client not allowed\n"));
+            *res.status_mut() = http::StatusCode::FORBIDDEN;
+            return RequestOrResponse::Response(res);
+        }
+        // Requests relayed by the HTTP/3 front end name their client. Only
+        // loopback peers (the front end) are believed.
+        let h3_client = req
+            .headers_mut()
+            .remove(H3_CLIENT_HEADER)
+            .filter(|_| ctx.client_addr.ip().is_loopback())
+            .and_then(|value| value.to_str().ok().map(str::to_string));
         let method = parse_method(req.method());
         let url = reconstruct_url(&req);
-        let version = format!("{:?}", req.version());
+        let version = match h3_client {
+            Some(_) => format!("{:?}", http::Version::HTTP_3),
+            None => format!("{:?}", req.version()),
+        };
         let headers = extract_headers(req.headers());
+        let host = request_host(&req).map(str::to_ascii_lowercase);
+        let policy = host
+            .as_deref()
+            .and_then(|host| self.policies.policy_for(host));
+        // Skipped hosts pass straight through: no rewrites, faults or trace.
+        if policy.is_some_and(|policy| policy.skip) {
+            self.pending = None;
+            return RequestOrResponse::Request(req);
+        }
+        let body_limit = policy.and_then(HostPolicy::body_limit);
+        let rewrites = self.rewrites.matching(req.method(), &url);
+        let shaping = host
+            .as_deref()
+            .and_then(|host| self.shaping.rule_for(host))
+            .cloned();
 
-        let (parts, body) = req.into_parts();
-        let body_bytes = collect_body(body).await;
+        // Request bodies stream upstream through a capture tee, like
+        // responses. They are read in full first only when something needs
+        // the whole body before forwarding: an intercept, the replay
+        // fingerprint, map_local, or a fault answering locally (whose trace
+        // would otherwise miss it).
+        let buffer_body = (self.intercept_tx.is_some() && self.intercepts.matches(method, &url))
+            || (self.replay.is_some() && method != HttpMethod::Connect)
+            || rewrites
+                .iter()
+                .any(|&i| self.rewrites.rules[i].map_local.is_some())
+            || self.fault_config.rules.iter().any(|rule| {
+                rule.matches_url(&url)
+                    && !matches!(rule, FaultRule::Delay { .. } | FaultRule::Truncate { .. })
+            });
+
+        let (mut parts, body) = req.into_parts();
+        let span_id = SpanId(rand_bytes::<8>());
+        let mut body_bytes = None;
+        let mut streamed_body = None;
+        let tee_body = if buffer_body {
+            match collect_body(body).await {
+                Ok(bytes) => body_bytes = bytes,
+                Err(e) => {
+                    // Forwarding what arrived would pass a cut-short upload
+                    // off as complete.
+                    warn!("{} {url}: request body failed: {e}", parts.method);
+                    self.pending = None;
+                    let mut res =
+                        Response::new(Body::from(format!("phantom: request body failed: {e}\n")));
+                    *res.status_mut() = http::StatusCode::BAD_REQUEST;
+                    return RequestOrResponse::Response(res);
+                }
+            }
+            None
+        } else if body.is_end_stream() {
+            None
+        } else {
+            // A failing upload fails the tee, and with it the upstream request.
+            let streamed = StreamedBody::default();
+            let on_end = {
+                let streamed = streamed.clone();
+                move |capture| streamed.finish(capture)
+            };
+            let capture = self.body_capture(body_limit, &span_id, "request");
+            let up_bytes_per_sec = shaping.as_ref().and_then(|rule| rule.up_bytes_per_sec);
+            streamed_body = Some(streamed);
+            Some(Body::from_stream(TeeStream::new(
+                body,
+                capture,
+                up_bytes_per_sec,
+                on_end,
+            )))
+        };
+        let (request_body, request_body_file) = capture_body(
+            body_bytes.as_ref(),
+            body_limit,
+            self.body_dir.as_deref(),
+            &span_id,
+            "request",
+        );
+        let (request_body, request_body_encoding) =
+            decompress_captured(&headers, body_bytes.as_ref(), request_body, body_limit);
 
         self.pending = Some(PendingRequest {
             method,
-            url,
+            url: url.clone(),
             request_headers: headers,
-            request_body: body_bytes.clone(),
-            source_addr: Some(ctx.client_addr.to_string()),
+            request_body,
+            request_body_file,
+            request_body_encoding,
+            source_addr: Some(h3_client.unwrap_or_else(|| ctx.client_addr.to_string())),
             timestamp: SystemTime::now(),
             started_at: Instant::now(),
-            span_id: SpanId(rand_bytes::<8>()),
+            span_id,
             trace_id: TraceId(rand_bytes::<16>()),
             protocol_version: version,
+            rewrites: rewrites.clone(),
+            body_limit,
+            down_bytes_per_sec: shaping.as_ref().and_then(|rule| rule.down_bytes_per_sec),
+            faults: Vec::new(),
+            truncate: false,
+            replay: None,
+            forwarded_at: None,
+            streamed_body,
         });
 
-        let rebuilt = Request::from_parts(parts, body_to_body(body_bytes));
+        // Apply rewrite rules in order; the first map_local one answers the
+        // request itself.
+        let mut map_local = None;
+        for &i in &rewrites {
+            let rule = &self.rewrites.rules[i];
+            rule.apply_request_headers(&mut parts.headers);
+            if let Some(uri) = rule.map_remote_uri(&parts.uri) {
+                if let Some(host) = uri
+                    .authority()
+                    .and_then(|a| http::HeaderValue::from_str(a.as_str()).ok())
+                {
+                    parts.headers.insert(http::header::HOST, host);
+                }
+                parts.uri = uri;
+            }
+            if map_local.is_none() {
+                map_local = rule
+                    .map_local
+                    .clone()
+                    .map(|file| (file, rule.status.unwrap_or(200)));
+            }
+        }
+        if let Some((file, status_code)) = map_local {
+            let (status_code, content_type, body) = match tokio::fs::read(&file).await {
+                Ok(body) => (status_code, content_type_for(&file), body),
+                Err(e) => {
+                    warn!("map_local {}: {e}", file.display());
+                    (
+                        502,
+                        "text/plain; charset=utf-8",
+                        format!("phantom map_local {}: {e}\n", file.display()).into_bytes(),
+                    )
+                }
+            };
+            let response = self.respond_locally(
+                ctx,
+                status_code,
+                &[
+                    ("content-type", content_type),
+                    ("x-phantom-map-local", "phantom"),
+                ],
+                body,
+            );
+            return RequestOrResponse::Response(response);
+        }
 
-        // Apply fault injection rules in order.
-        let url = self
-            .pending
-            .as_ref()
-            .map(|p| p.url.clone())
-            .unwrap_or_default();
-        for rule in &self.fault_config.rules {
+        // Replay mode: answer from the recording when it has this request,
+        // otherwise forward it and mark the trace as a miss.
+        if let Some(replay) = &self.replay
+            && method != HttpMethod::Connect
+        {
+            let recorded = replay
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .next_response(method, &url, body_bytes.as_deref());
+            let outcome = if recorded.is_some() {
+                ReplayOutcome::Hit
+            } else {
+                ReplayOutcome::Miss
+            };
+            if let Some(info) = self.pending.as_mut() {
+                info.replay = Some(outcome);
+            }
+            if let Some(recorded) = recorded {
+                let mut headers: Vec<(&str, &str)> = recorded
+                    .headers
+                    .iter()
+                    .map(|(name, value)| (name.as_str(), value.as_str()))
+                    .collect();
+                headers.push(("x-phantom-replay", "hit"));
+                let response =
+                    self.respond_locally(ctx, recorded.status_code, &headers, recorded.body);
+                return RequestOrResponse::Response(response);
+            }
+        }
+
+        // Intercept: hold the request, as rewritten, until the user forwards
+        // it (perhaps edited) or drops it.
+        if let Some(intercept_tx) = &self.intercept_tx
+            && self.intercepts.matches(method, &url)
+        {
+            let headers = parts
+                .headers
+                .iter()
+                .map(|(name, value)| {
+                    let value = String::from_utf8_lossy(value.as_bytes()).into_owned();
+                    (name.as_str().to_string(), value)
+                })
+                .collect();
+            let body = body_bytes.as_deref().unwrap_or_default().to_vec();
+            let (held, decision) = InterceptedRequest::new(method, url.clone(), headers, body);
+            // A failed send drops `held`, which forwards it unchanged.
+            let _ = intercept_tx.send(held).await;
+            match decision.await {
+                Ok(InterceptDecision::Drop) => {
+                    self.note_fault("intercept:drop".to_string());
+                    let mut response = self.respond_locally(
+                        ctx,
+                        502,
+                        &[("x-phantom-intercept", "dropped")],
+                        Vec::new(),
+                    );
+                    *response.body_mut() = aborted_body(bytes::Bytes::new());
+                    return RequestOrResponse::Response(response);
+                }
+                Ok(InterceptDecision::Forward {
+                    headers,
+                    body,
+                    edited: true,
+                }) => {
+                    self.note_fault("intercept:edit".to_string());
+                    parts.headers = edited_headers(&headers);
+                    body_bytes = (!body.is_empty()).then(|| bytes::Bytes::from(body));
+                }
+                Ok(InterceptDecision::Forward { .. }) | Err(_) => {}
+            }
+        }
+
+        // Network shaping: added latency plus a buffered request body's
+        // transfer time at the upload cap; a streamed one is paced by its tee.
+        if let Some(rule) = &shaping {
+            let body_len = body_bytes.as_ref().map_or(0, |b| b.len());
+            let delay = Duration::from_millis(rule.latency_ms)
+                + transfer_time(body_len, rule.up_bytes_per_sec);
+            tokio::time::sleep(delay).await;
+        }
+
+        let rebuilt =
+            Request::from_parts(parts, tee_body.unwrap_or_else(|| body_to_body(body_bytes)));
+
+        // Apply fault injection rules in order. The Arc is cloned so that
+        // answering locally can borrow the handler mutably.
+        let fault_config = Arc::clone(&self.fault_config);
+        for rule in &fault_config.rules {
             if !rule.matches_url(&url) {
                 continue;
             }
@@ -220,6 +833,7 @@ impl HttpHandler for TraceHandler {
                         min_ms + rand::random::<u64>() % (max_ms - min_ms + 1)
                     };
                     tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+                    self.note_fault(format!("delay:{delay_ms}ms"));
                 }
                 FaultRule::Error {
                     status_code,
@@ -227,64 +841,278 @@ impl HttpHandler for TraceHandler {
                     ..
                 } => {
                     if rand::random::<f64>() < *probability {
-                        // Emit a trace immediately — handle_response won't be called.
-                        if let Some(info) = self.pending.take() {
-                            let fault_body = b"{\"fault\":\"injected\"}".to_vec();
-                            let trace = HttpTrace {
-                                span_id: info.span_id,
-                                trace_id: info.trace_id,
-                                parent_span_id: None,
-                                method: info.method,
-                                url: info.url,
-                                request_headers: info.request_headers,
-                                request_body: info.request_body,
-                                status_code: *status_code,
-                                response_headers: {
-                                    let mut h = HashMap::new();
-                                    h.insert(
-                                        "content-type".to_string(),
-                                        "application/json".to_string(),
-                                    );
-                                    h.insert("x-fault-injected".to_string(), "phantom".to_string());
-                                    h
-                                },
-                                response_body: Some(fault_body),
-                                timestamp: info.timestamp,
-                                duration: info.started_at.elapsed(),
-                                source_addr: info.source_addr,
-                                dest_addr: None,
-                                protocol_version: info.protocol_version,
-                            };
-                            if self.trace_tx.try_send(trace).is_err() {
-                                warn!("Trace channel full, dropping fault-injected trace");
-                            }
-                        }
-                        let body_bytes: bytes::Bytes = b"{\"fault\":\"injected\"}".as_ref().into();
-                        let response = Response::builder()
-                            .status(*status_code)
-                            .header("content-type", "application/json")
-                            .header("x-fault-injected", "phantom")
-                            .body(Body::from(http_body_util::Full::new(body_bytes)))
-                            .expect("valid fault response");
+                        self.note_fault(format!("error:{status_code}"));
+                        let response = self.respond_locally(
+                            ctx,
+                            *status_code,
+                            &[
+                                ("content-type", "application/json"),
+                                ("x-fault-injected", "phantom"),
+                            ],
+                            b"{\"fault\":\"injected\"}".to_vec(),
+                        );
+                        return RequestOrResponse::Response(response);
+                    }
+                }
+                FaultRule::Reset { probability, .. } => {
+                    if rand::random::<f64>() < *probability {
+                        self.note_fault("reset".to_string());
+                        let mut response = self.respond_locally(
+                            ctx,
+                            502,
+                            &[("x-fault-injected", "phantom")],
+                            Vec::new(),
+                        );
+                        *response.body_mut() = aborted_body(bytes::Bytes::new());
+                        return RequestOrResponse::Response(response);
+                    }
+                }
+                FaultRule::Truncate { probability, .. } => {
+                    if rand::random::<f64>() < *probability
+                        && let Some(info) = self.pending.as_mut()
+                    {
+                        info.truncate = true;
+                    }
+                }
+                FaultRule::Timeout {
+                    after_ms,
+                    probability,
+                    ..
+                } => {
+                    if rand::random::<f64>() < *probability {
+                        tokio::time::sleep(Duration::from_millis(*after_ms)).await;
+                        self.note_fault(format!("timeout:{after_ms}ms"));
+                        let response = self.respond_locally(
+                            ctx,
+                            504,
+                            &[
+                                ("content-type", "text/plain; charset=utf-8"),
+                                ("x-fault-injected", "phantom"),
+                            ],
+                            b"phantom: injected timeout\n".to_vec(),
+                        );
                         return RequestOrResponse::Response(response);
                     }
                 }
             }
         }
 
+        if let Some(info) = self.pending.as_mut() {
+            info.forwarded_at = Some(Instant::now());
+        }
         RequestOrResponse::Request(rebuilt)
     }
 
-    async fn handle_response(&mut self, _ctx: &HttpContext, res: Response<Body>) -> Response<Body> {
-        let (parts, body) = res.into_parts();
+    async fn handle_response(&mut self, ctx: &HttpContext, res: Response<Body>) -> Response<Body> {
+        let (mut parts, body) = res.into_parts();
         let response_headers = extract_headers(&parts.headers);
+        // The client's connector records the socket it dialled: the resolved
+        // server address, or the upstream proxy's when tunnelling through one.
+        let dest_addr = parts
+            .extensions
+            .get::<HttpInfo>()
+            .map(|http| http.remote_addr().to_string());
+        // Only the first response on a new connection gets its phases.
+        let connect_timing = parts
+            .extensions
+            .get::<ConnectTiming>()
+            .and_then(ConnectTiming::claim);
+        if self.block_quic {
+            strip_h3_alt_svc(&mut parts.headers);
+        }
+        if let Some(info) = &self.pending {
+            for &i in &info.rewrites {
+                self.rewrites.rules[i].apply_response_headers(&mut parts.headers);
+            }
+            if info.replay == Some(ReplayOutcome::Miss) {
+                parts
+                    .headers
+                    .insert("x-phantom-replay", http::HeaderValue::from_static("miss"));
+            }
+        }
         let status_code = parts.status.as_u16();
-        let response_body = collect_body(body).await;
+        let Some(mut info) = self.pending.take() else {
+            return Response::from_parts(parts, body);
+        };
+        let timing = TimingPhases {
+            ttfb: info.forwarded_at.map(|at| at.elapsed()),
+            ..connect_timing.unwrap_or_default()
+        };
+        let mut capture = self.body_capture(info.body_limit, &info.span_id, "response");
+        let connection_id = ctx.client_addr.to_string();
+
+        // A truncate fault sends half the body, then aborts the connection.
+        // It needs the whole body first to know where half is.
+        if info.truncate {
+            // A failed body is aborted all the same, after what arrived of it.
+            let body_bytes = collect_body(body)
+                .await
+                .unwrap_or_else(|e| {
+                    warn!("{}: response body failed: {e}", info.url);
+                    None
+                })
+                .unwrap_or_default();
+            let full = body_bytes.len();
+            let half = body_bytes.slice(..full / 2);
+            info.faults.push(format!("truncate:{}/{full}B", full / 2));
+            tokio::time::sleep(transfer_time(half.len(), info.down_bytes_per_sec)).await;
+            capture.push(&half);
+            send_when_complete(self.trace_tx.clone(), info, move |info| {
+                response_trace(
+                    info,
+                    status_code,
+                    response_headers,
+                    dest_addr,
+                    timing,
+                    connection_id,
+                    capture,
+                )
+            });
+            return Response::from_parts(parts, aborted_body(half));
+        }
 
-        let rebuilt = Response::from_parts(parts, body_to_body(response_body.clone()));
+        // Everything else streams through: chunks reach the client as they
+        // arrive (paced to the download cap, whose transfer time counts
+        // towards the traced duration), and the trace is sent once the body
+        // has ended, and a streamed request body too.
+        let trace_tx = self.trace_tx.clone();
+        let down_bytes_per_sec = info.down_bytes_per_sec;
+        let tee = TeeStream::new(body, capture, down_bytes_per_sec, move |capture| {
+            send_when_complete(trace_tx, info, move |info| {
+                response_trace(
+                    info,
+                    status_code,
+                    response_headers,
+                    dest_addr,
+                    timing,
+                    connection_id,
+                    capture,
+                )
+            });
+        });
+        Response::from_parts(parts, Body::from_stream(tee))
+    }
+}
 
+/// Sends the trace built by `trace` once the request body has ended too,
+/// when it was streamed upstream.
+fn send_when_complete(
+    trace_tx: mpsc::Sender<TraceEvent>,
+    mut info: PendingRequest,
+    trace: impl FnOnce(PendingRequest) -> HttpTrace + Send + 'static,
+) {
+    let send = move |info| {
+        if trace_tx.try_send(trace(info).into()).is_err() {
+            warn!("Trace channel full, dropping trace");
+        }
+    };
+    match info.streamed_body.take() {
+        Some(streamed) => streamed.then(move |capture| {
+            info.set_request_body(capture);
+            send(info);
+        }),
+        None => send(info),
+    }
+}
+
+/// Builds the trace of a forwarded exchange once its response body is done.
+fn response_trace(
+    info: PendingRequest,
+    status_code: u16,
+    response_headers: HashMap<String, String>,
+    dest_addr: Option<String>,
+    timing: TimingPhases,
+    connection_id: String,
+    capture: BodyCapture,
+) -> HttpTrace {
+    let (response_body, response_body_file) = capture.finish();
+    // Decoded from the captured bytes, so a body over the capture limit
+    // decodes as far as its start goes; overflow files keep the bytes as sent.
+    let (response_body, response_body_encoding) = match response_body.as_deref().and_then(|body| {
+        let limit = info.body_limit.unwrap_or(MAX_BODY_SIZE);
+        decompress_body(&response_headers, body, limit)
+    }) {
+        Some((decoded, encoding)) => (Some(decoded), Some(encoding)),
+        None => (response_body, None),
+    };
+    HttpTrace {
+        span_id: info.span_id,
+        trace_id: info.trace_id,
+        parent_span_id: None,
+        method: info.method,
+        url: info.url,
+        request_headers: info.request_headers,
+        request_body: info.request_body,
+        status_code,
+        response_headers,
+        response_body,
+        timestamp: info.timestamp,
+        duration: info.started_at.elapsed(),
+        source_addr: info.source_addr,
+        dest_addr,
+        protocol_version: info.protocol_version,
+        request_body_file: info.request_body_file,
+        response_body_file,
+        // hudsucker serves each client TCP connection (including CONNECT
+        // tunnels) from one address, so it names the connection.
+        connection_id: Some(connection_id),
+        process: None,
+        response_trailers: HashMap::new(),
+        request_body_encoding: info.request_body_encoding,
+        response_body_encoding,
+        elasticsearch: None,
+        soap: None,
+        graphql: None,
+        thread_id: None,
+        tls: None,
+        timing: Some(timing),
+        request_size: None,
+        response_size: None,
+        fault: (!info.faults.is_empty()).then(|| info.faults.join(", ")),
+        replay: info.replay,
+    }
+}
+
+impl TraceHandler {
+    /// Capture of a streamed body. A policy's limit is deliberate, so
+    /// nothing goes to an overflow file.
+    fn body_capture(&self, limit: Option<usize>, span_id: &SpanId, side: &str) -> BodyCapture {
+        match limit {
+            Some(limit) => BodyCapture::new(limit, None),
+            None => {
+                let overflow_file = self
+                    .body_dir
+                    .as_deref()
+                    .map(|dir| overflow_path(dir, span_id, side));
+                BodyCapture::new(MAX_BODY_SIZE, overflow_file)
+            }
+        }
+    }
+
+    fn note_fault(&mut self, fault: String) {
+        if let Some(info) = self.pending.as_mut() {
+            info.faults.push(fault);
+        }
+    }
+
+    /// Answers the pending request without forwarding it. Its trace is
+    /// emitted at once, since `handle_response` won't be called.
+    fn respond_locally(
+        &mut self,
+        ctx: &HttpContext,
+        status_code: u16,
+        headers: &[(&str, &str)],
+        body: Vec<u8>,
+    ) -> Response<Body> {
+        let body = bytes::Bytes::from(body);
         if let Some(info) = self.pending.take() {
-            let duration = info.started_at.elapsed();
+            let (response_body, response_body_file) = capture_body(
+                Some(&body),
+                info.body_limit,
+                self.body_dir.as_deref(),
+                &info.span_id,
+                "response",
+            );
             let trace = HttpTrace {
                 span_id: info.span_id,
                 trace_id: info.trace_id,
@@ -294,21 +1122,45 @@ impl HttpHandler for TraceHandler {
                 request_headers: info.request_headers,
                 request_body: info.request_body,
                 status_code,
-                response_headers,
+                response_headers: headers
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), value.to_string()))
+                    .collect(),
                 response_body,
                 timestamp: info.timestamp,
-                duration,
+                duration: info.started_at.elapsed(),
                 source_addr: info.source_addr,
                 dest_addr: None,
                 protocol_version: info.protocol_version,
+                request_body_file: info.request_body_file,
+                response_body_file,
+                connection_id: Some(ctx.client_addr.to_string()),
+                process: None,
+                response_trailers: HashMap::new(),
+                request_body_encoding: info.request_body_encoding,
+                response_body_encoding: None,
+                elasticsearch: None,
+                soap: None,
+                graphql: None,
+                thread_id: None,
+                tls: None,
+                timing: None,
+                request_size: None,
+                response_size: None,
+                fault: (!info.faults.is_empty()).then(|| info.faults.join(", ")),
+                replay: info.replay,
             };
-
-            if self.trace_tx.try_send(trace).is_err() {
-                warn!("Trace channel full, dropping trace");
+            if self.trace_tx.try_send(trace.into()).is_err() {
+                warn!("Trace channel full, dropping locally answered trace");
             }
         }
-
-        rebuilt
+        let mut response = Response::builder().status(status_code);
+        for (name, value) in headers {
+            response = response.header(*name, *value);
+        }
+        response
+            .body(Body::from(http_body_util::Full::new(body)))
+            .expect("valid local response")
     }
 }
 
@@ -327,6 +1179,15 @@ fn parse_method(method: &http::Method) -> HttpMethod {
     }
 }
 
+/// The host a request is for, from its URI or else its `Host` header,
+/// without the port.
+fn request_host(req: &Request<Body>) -> Option<&str> {
+    req.uri()
+        .host()
+        .or_else(|| req.headers().get("host").and_then(|h| h.to_str().ok()))
+        .map(|host| host.split(':').next().unwrap_or(host))
+}
+
 fn reconstruct_url(req: &Request<Body>) -> String {
     let uri = req.uri();
     if uri.scheme().is_some() {
@@ -347,6 +1208,48 @@ fn reconstruct_url(req: &Request<Body>) -> String {
     format!("{scheme}://{host}{path}")
 }
 
+/// Removes HTTP/3 (QUIC) advertisements from `Alt-Svc` response headers.
+///
+/// A client that sees `Alt-Svc: h3=":443"` switches its next requests to QUIC
+/// straight to the origin, bypassing the proxy, and those requests are never
+/// captured. Keeping clients on TCP keeps them visible. Non-QUIC alternatives
+/// are preserved; the header is dropped when nothing else remains. The stored
+/// trace still records the server's original header.
+fn strip_h3_alt_svc(headers: &mut http::HeaderMap) {
+    let Some(value) = headers
+        .get(http::header::ALT_SVC)
+        .and_then(|v| v.to_str().ok())
+    else {
+        return;
+    };
+    match filter_alt_svc(value) {
+        Some(kept) if kept == value => {}
+        Some(kept) => {
+            if let Ok(v) = http::HeaderValue::from_str(&kept) {
+                headers.insert(http::header::ALT_SVC, v);
+            }
+        }
+        None => {
+            headers.remove(http::header::ALT_SVC);
+        }
+    }
+}
+
+/// Returns the `Alt-Svc` value without its `h3`/`h3-*`/`quic` entries, or
+/// `None` when no entry is left.
+fn filter_alt_svc(value: &str) -> Option<String> {
+    let kept: Vec<&str> = value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| {
+            let protocol = entry.split('=').next().unwrap_or("").trim();
+            !(protocol == "h3" || protocol.starts_with("h3-") || protocol == "quic")
+        })
+        .filter(|entry| !entry.is_empty())
+        .collect();
+    (!kept.is_empty()).then(|| kept.join(", "))
+}
+
 fn extract_headers(headers: &http::HeaderMap) -> HashMap<String, String> {
     headers
         .iter()
@@ -359,30 +1262,115 @@ fn extract_headers(headers: &http::HeaderMap) -> HashMap<String, String> {
         .collect()
 }
 
-async fn collect_body(body: Body) -> Option<Vec<u8>> {
+/// Reads a whole body, `None` when it is empty. The full bytes are
+/// forwarded unchanged; only the captured copy is limited (see
+/// [`capture_body`]).
+async fn collect_body(body: Body) -> Result<Option<bytes::Bytes>, hudsucker::Error> {
     use http_body_util::BodyExt;
-    match body.collect().await {
-        Ok(collected) => {
-            let bytes = collected.to_bytes();
-            if bytes.is_empty() {
-                None
-            } else if bytes.len() > MAX_BODY_SIZE {
-                // Truncate large bodies
-                Some(bytes[..MAX_BODY_SIZE].to_vec())
-            } else {
-                Some(bytes.to_vec())
+    let bytes = body.collect().await?.to_bytes();
+    Ok((!bytes.is_empty()).then_some(bytes))
+}
+
+/// Returns the body to store on the trace, truncated to [`MAX_BODY_SIZE`],
+/// plus the path of the overflow file holding the full body when it was
+/// truncated and `body_dir` is set. Overflow files are named
+/// `<span_id>.<side>.bin`.
+///
+/// A capture policy's `limit` replaces [`MAX_BODY_SIZE`] and never writes
+/// an overflow file; a limit of 0 drops the body.
+fn capture_body(
+    body: Option<&bytes::Bytes>,
+    limit: Option<usize>,
+    body_dir: Option<&Path>,
+    span_id: &SpanId,
+    side: &str,
+) -> (Option<Vec<u8>>, Option<String>) {
+    let Some(bytes) = body else {
+        return (None, None);
+    };
+    let max = limit.unwrap_or(MAX_BODY_SIZE);
+    if max == 0 {
+        return (None, None);
+    }
+    if bytes.len() <= max {
+        return (Some(bytes.to_vec()), None);
+    }
+    let truncated = Some(bytes[..max].to_vec());
+    let Some(dir) = body_dir.filter(|_| limit.is_none()) else {
+        return (truncated, None);
+    };
+    let path = overflow_path(dir, span_id, side);
+    let written = std::fs::create_dir_all(dir).and_then(|()| std::fs::write(&path, bytes));
+    match written {
+        Ok(()) => (truncated, Some(path.display().to_string())),
+        Err(e) => {
+            warn!("failed to write overflow body {}: {e}", path.display());
+            (truncated, None)
+        }
+    }
+}
+
+/// Path of the file holding a body over the capture limit in full.
+fn overflow_path(dir: &Path, span_id: &SpanId, side: &str) -> PathBuf {
+    dir.join(format!("{span_id}.{side}.bin"))
+}
+
+/// Swaps a captured body for its decompressed form when the headers name a
+/// `Content-Encoding`. Decodes from the full bytes, so a body over the
+/// capture limit still decodes from its start; overflow files keep the
+/// bytes as sent.
+fn decompress_captured(
+    headers: &HashMap<String, String>,
+    full: Option<&bytes::Bytes>,
+    captured: Option<Vec<u8>>,
+    limit: Option<usize>,
+) -> (Option<Vec<u8>>, Option<BodyEncoding>) {
+    // A dropped body stays dropped.
+    if captured.is_none() {
+        return (None, None);
+    }
+    let limit = limit.unwrap_or(MAX_BODY_SIZE);
+    match full.and_then(|b| decompress_body(headers, b, limit)) {
+        Some((decoded, encoding)) => (Some(decoded), Some(encoding)),
+        None => (captured, None),
+    }
+}
+
+/// A body that sends `data` and then fails, so the connection is aborted
+/// instead of the response completing.
+fn aborted_body(data: bytes::Bytes) -> Body {
+    let chunks: Vec<Result<bytes::Bytes, std::io::Error>> = vec![
+        Ok(data),
+        Err(std::io::Error::new(
+            std::io::ErrorKind::ConnectionReset,
+            "fault injected by phantom",
+        )),
+    ];
+    Body::from_stream(futures_util::stream::iter(chunks))
+}
+
+/// Header map of an edited intercepted request. `Content-Length` is left
+/// for hyper to set from the edited body; invalid lines are skipped.
+fn edited_headers(headers: &[(String, String)]) -> http::HeaderMap {
+    let mut map = http::HeaderMap::new();
+    for (name, value) in headers {
+        match (
+            http::HeaderName::from_bytes(name.as_bytes()),
+            http::HeaderValue::from_str(value),
+        ) {
+            (Ok(name), _) if name == http::header::CONTENT_LENGTH => {}
+            (Ok(name), Ok(value)) => {
+                map.append(name, value);
             }
+            _ => warn!("intercept: skipping invalid header {name}: {value}"),
         }
-        Err(_) => None,
     }
+    map
 }
 
-fn body_to_body(data: Option<Vec<u8>>) -> Body {
+fn body_to_body(data: Option<bytes::Bytes>) -> Body {
     match data {
-        Some(bytes) => {
-            let b: bytes::Bytes = bytes.into();
-            Body::from(http_body_util::Full::new(b))
-        }
+        Some(bytes) => Body::from(http_body_util::Full::new(bytes)),
         None => Body::empty(),
     }
 }
@@ -393,28 +1381,110 @@ fn rand_bytes<const N: usize>() -> [u8; N] {
     buf
 }
 
+/// Binds the listener of a SOCKS or transparent front end.
+fn bind_front_end(
+    ip: IpAddr,
+    port: u16,
+    kind: &str,
+) -> Result<tokio::net::TcpListener, CaptureError> {
+    std::net::TcpListener::bind((ip, port))
+        .and_then(|listener| {
+            listener.set_nonblocking(true)?;
+            tokio::net::TcpListener::from_std(listener)
+        })
+        .map_err(|e| CaptureError::StartFailed(format!("{kind} listener on {ip}:{port}: {e}")))
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Outbound clients
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// The HTTP client the proxy forwards requests with. Each connector is its
+/// own type, so each gets a variant.
+enum OutboundClient {
+    Direct(Client<TimedConnector<HttpsConnector<DirectTcp>>, Body>),
+    Upstream(Client<TimedConnector<HttpsConnector<MarkTcp<UpstreamConnector>>>, Body>),
+    ClientCert(Client<TimedConnector<ClientCertConnector<DirectTcp>>, Body>),
+    UpstreamClientCert(
+        Client<TimedConnector<ClientCertConnector<MarkTcp<UpstreamConnector>>>, Body>,
+    ),
+}
+
+/// TCP connector for destinations reached directly, with timed lookups.
+type DirectTcp = MarkTcp<HttpConnector<TimedResolver>>;
+
+fn direct_tcp() -> DirectTcp {
+    let mut http = HttpConnector::new_with_resolver(TimedResolver::default());
+    http.enforce_http(false);
+    MarkTcp(http)
+}
+
+/// HTTP client over `connector` whose new connections carry their
+/// [`ConnectTiming`].
+fn timed_client<C>(connector: C) -> Client<TimedConnector<C>, Body>
+where
+    TimedConnector<C>: Connect + Clone,
+{
+    Client::builder(TokioExecutor::new()).build(TimedConnector(connector))
+}
+
+/// Runs the proxy with `client` for outbound requests until `shutdown`.
+async fn serve_with_client<C>(
+    addr: SocketAddr,
+    client: Client<C, Body>,
+    ca: RcgenAuthority,
+    handler: TraceHandler,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> Result<(), hudsucker::Error>
+where
+    C: Connect + Clone + Send + Sync + 'static,
+{
+    Proxy::builder()
+        .with_addr(addr)
+        .with_client(client)
+        .with_ca(ca)
+        .with_http_handler(handler)
+        .with_graceful_shutdown(shutdown)
+        .build()
+        .start()
+        .await
+}
+
+/// Server verification half of the per-host `--client-cert` TLS configs:
+/// the system roots, or no verification with `--insecure`.
+fn client_cert_tls(
+    insecure: bool,
+) -> Result<impl Fn() -> rustls::ConfigBuilder<rustls::ClientConfig, WantsClientCert>, CaptureError>
+{
+    let mut roots = rustls::RootCertStore::empty();
+    if !insecure {
+        let certs = rustls_native_certs::load_native_certs().map_err(|e| {
+            CaptureError::StartFailed(format!("failed to load root certificates: {e}"))
+        })?;
+        roots.add_parsable_certificates(certs);
+    }
+    let roots = Arc::new(roots);
+    Ok(move || {
+        if insecure {
+            rustls::ClientConfig::builder()
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(NoCertVerifier))
+        } else {
+            rustls::ClientConfig::builder().with_root_certificates(Arc::clone(&roots))
+        }
+    })
+}
+
 // ─────────────────────────────────────────────────────────────────────────────
 // Insecure TLS client (--insecure mode)
 // ─────────────────────────────────────────────────────────────────────────────
 
-/// Build a hyper client that skips all TLS certificate verification.
-/// Used with `--insecure` for testing against backends with self-signed certs.
-fn build_insecure_client() -> hyper_util::client::legacy::Client<
-    hyper_rustls::HttpsConnector<hyper_util::client::legacy::connect::HttpConnector>,
-    Body,
-> {
-    let tls_config = rustls::ClientConfig::builder()
+/// TLS client configuration that skips all certificate verification.
+fn insecure_tls_config() -> rustls::ClientConfig {
+    rustls::ClientConfig::builder()
         .dangerous()
         .with_custom_certificate_verifier(std::sync::Arc::new(NoCertVerifier))
-        .with_no_client_auth();
-
-    let https = hyper_rustls::HttpsConnectorBuilder::new()
-        .with_tls_config(tls_config)
-        .https_or_http()
-        .enable_http1()
-        .build();
-
-    hyper_util::client::legacy::Client::builder(hyper_util::rt::TokioExecutor::new()).build(https)
+        .with_no_client_auth()
 }
 
 /// A [`rustls::client::danger::ServerCertVerifier`] that accepts any certificate.
@@ -466,3 +1536,220 @@ impl rustls::client::danger::ServerCertVerifier for NoCertVerifier {
         ]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use futures_util::StreamExt;
+
+    use super::*;
+
+    fn make_pending() -> PendingRequest {
+        PendingRequest {
+            method: HttpMethod::Get,
+            url: "https://api.example.com/items".to_string(),
+            request_headers: HashMap::new(),
+            request_body: None,
+            request_body_file: None,
+            request_body_encoding: None,
+            source_addr: None,
+            timestamp: SystemTime::now(),
+            started_at: Instant::now(),
+            span_id: SpanId([1; 8]),
+            trace_id: TraceId([2; 16]),
+            protocol_version: "HTTP/1.1".to_string(),
+            rewrites: Vec::new(),
+            body_limit: None,
+            down_bytes_per_sec: None,
+            faults: Vec::new(),
+            truncate: false,
+            replay: None,
+            forwarded_at: None,
+            streamed_body: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_streamed_gzip_response_is_stored_decoded() {
+        let json = br#"{"items":[1,2,3]}"#;
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(json).unwrap();
+        let gzipped = bytes::Bytes::from(encoder.finish().unwrap());
+        let (first, rest) = gzipped.split_at(gzipped.len() / 2);
+        let frames: Vec<Result<hyper::body::Frame<bytes::Bytes>, std::io::Error>> = vec![
+            Ok(hyper::body::Frame::data(bytes::Bytes::copy_from_slice(
+                first,
+            ))),
+            Ok(hyper::body::Frame::data(bytes::Bytes::copy_from_slice(
+                rest,
+            ))),
+        ];
+        let body = http_body_util::StreamBody::new(futures_util::stream::iter(frames));
+        let mut headers = HashMap::new();
+        headers.insert("content-encoding".to_string(), "gzip".to_string());
+
+        let (tx, mut rx) = mpsc::channel(1);
+        let tee = TeeStream::new(
+            body,
+            BodyCapture::new(MAX_BODY_SIZE, None),
+            None,
+            move |capture| {
+                let trace = response_trace(
+                    make_pending(),
+                    200,
+                    headers,
+                    None,
+                    TimingPhases::default(),
+                    "c".into(),
+                    capture,
+                );
+                tx.try_send(trace).unwrap();
+            },
+        );
+        let forwarded: Vec<u8> = tee.map(|chunk| chunk.unwrap().to_vec()).concat().await;
+
+        // The client gets the bytes as sent; the trace stores them decoded.
+        assert_eq!(forwarded, gzipped);
+        let trace = rx.recv().await.unwrap();
+        assert_eq!(trace.response_body.as_deref(), Some(&json[..]));
+        let encoding = trace.response_body_encoding.unwrap();
+        assert_eq!(encoding.encoding, "gzip");
+        assert!(!encoding.truncated);
+    }
+
+    #[tokio::test]
+    async fn test_trace_waits_for_streamed_request_body() {
+        let streamed = StreamedBody::default();
+        let mut info = make_pending();
+        info.streamed_body = Some(streamed.clone());
+        let (tx, mut rx) = mpsc::channel(1);
+        // The server answered while the upload was still going.
+        send_when_complete(tx, info, |info| {
+            response_trace(
+                info,
+                200,
+                HashMap::new(),
+                None,
+                TimingPhases::default(),
+                "c".into(),
+                BodyCapture::new(MAX_BODY_SIZE, None),
+            )
+        });
+        assert!(rx.try_recv().is_err());
+
+        let mut capture = BodyCapture::new(MAX_BODY_SIZE, None);
+        capture.push(b"upload");
+        streamed.finish(capture);
+        let Ok(TraceEvent::Http(trace)) = rx.try_recv() else {
+            panic!("expected an HTTP trace");
+        };
+        assert_eq!(trace.request_body.as_deref(), Some(&b"upload"[..]));
+    }
+
+    #[test]
+    fn test_edited_headers_skip_content_length_and_invalid() {
+        let headers = [
+            ("Host", "api.test"),
+            ("Content-Length", "12"),
+            ("x-debug", "1"),
+            ("x-debug", "2"),
+            ("bad header", "x"),
+        ]
+        .map(|(name, value)| (name.to_string(), value.to_string()));
+        let map = edited_headers(&headers);
+        assert_eq!(map.len(), 3);
+        assert_eq!(map["host"], "api.test");
+        assert!(!map.contains_key("content-length"));
+        assert_eq!(map.get_all("x-debug").iter().count(), 2);
+    }
+
+    #[test]
+    fn test_capture_body_within_limit_is_kept_whole() {
+        let dir = tempfile::tempdir().unwrap();
+        let body = bytes::Bytes::from_static(b"small");
+        let (captured, file) = capture_body(
+            Some(&body),
+            None,
+            Some(dir.path()),
+            &SpanId([1; 8]),
+            "request",
+        );
+        assert_eq!(captured.as_deref(), Some(&b"small"[..]));
+        assert_eq!(file, None);
+        assert_eq!(
+            capture_body(None, None, Some(dir.path()), &SpanId([1; 8]), "request"),
+            (None, None)
+        );
+    }
+
+    #[test]
+    fn test_capture_body_overflow_writes_full_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let body = bytes::Bytes::from(vec![b'x'; MAX_BODY_SIZE + 10]);
+        let span_id = SpanId([0xab; 8]);
+
+        let (captured, file) =
+            capture_body(Some(&body), None, Some(dir.path()), &span_id, "response");
+        assert_eq!(captured.unwrap().len(), MAX_BODY_SIZE);
+        let file = file.unwrap();
+        assert!(file.ends_with("abababababababab.response.bin"));
+        assert_eq!(std::fs::read(&file).unwrap().len(), MAX_BODY_SIZE + 10);
+
+        // Without a body directory the body is still truncated, just not saved.
+        let (captured, file) = capture_body(Some(&body), None, None, &span_id, "response");
+        assert_eq!(captured.unwrap().len(), MAX_BODY_SIZE);
+        assert_eq!(file, None);
+    }
+
+    #[test]
+    fn test_capture_body_policy_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let body = bytes::Bytes::from_static(b"0123456789");
+        let span_id = SpanId([2; 8]);
+
+        // A policy limit truncates without an overflow file.
+        let (captured, file) =
+            capture_body(Some(&body), Some(4), Some(dir.path()), &span_id, "request");
+        assert_eq!(captured.as_deref(), Some(&b"0123"[..]));
+        assert_eq!(file, None);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+
+        assert_eq!(
+            capture_body(Some(&body), Some(0), Some(dir.path()), &span_id, "request"),
+            (None, None)
+        );
+    }
+
+    #[test]
+    fn test_filter_alt_svc_drops_h3_entries() {
+        assert_eq!(filter_alt_svc(r#"h3=":443"; ma=86400"#), None);
+        assert_eq!(
+            filter_alt_svc(r#"h3=":443"; ma=86400, h3-29=":443"; ma=86400"#),
+            None
+        );
+        assert_eq!(
+            filter_alt_svc(r#"h3=":443"; ma=86400, h2="alt.example:443"; ma=60"#).as_deref(),
+            Some(r#"h2="alt.example:443"; ma=60"#)
+        );
+        assert_eq!(filter_alt_svc("clear").as_deref(), Some("clear"));
+    }
+
+    #[test]
+    fn test_strip_h3_alt_svc_header() {
+        let mut headers = http::HeaderMap::new();
+        headers.insert(
+            http::header::ALT_SVC,
+            http::HeaderValue::from_static(r#"h3=":443"; ma=86400"#),
+        );
+        strip_h3_alt_svc(&mut headers);
+        assert!(headers.get(http::header::ALT_SVC).is_none());
+
+        headers.insert(
+            http::header::ALT_SVC,
+            http::HeaderValue::from_static(r#"h2=":8443", h3=":443""#),
+        );
+        strip_h3_alt_svc(&mut headers);
+        assert_eq!(headers[http::header::ALT_SVC], r#"h2=":8443""#);
+    }
+}
@@ -0,0 +1,442 @@
+//! HTTP/3 front end.
+//!
+//! Accepts QUIC connections on a UDP port, terminates them with a leaf
+//! certificate for the client's SNI name signed by the proxy CA, and relays
+//! each HTTP/3 request to phantom's own proxy listener as an absolute-form
+//! `https://` request over loopback. Rewrites, faults and capture policies
+//! apply as to any proxied request, and the proxy forwards it upstream over
+//! TCP. The trace records the request as `HTTP/3.0` from the QUIC client.
+//!
+//! Clients reach the listener through an iptables `REDIRECT` of UDP 443 to
+//! its port, or by being pointed at it (e.g. curl's `--connect-to`). The
+//! request's `:authority` names the upstream host.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+
+use bytes::{Buf, Bytes};
+use h3::server::RequestStream;
+use http::header::HOST;
+use http::{HeaderName, HeaderValue, Request, Response, StatusCode, Uri};
+use http_body_util::{BodyExt, StreamBody};
+use hudsucker::rcgen::{
+    Certificate, CertificateParams, DistinguishedName, DnType, Ia5String, KeyPair, SanType,
+};
+use hyper::body::Frame;
+use hyper_util::rt::TokioIo;
+use phantom_core::error::CaptureError;
+use quinn::rustls;
+use quinn::rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use quinn::rustls::server::{ClientHello, ResolvesServerCert};
+use quinn::rustls::sign::CertifiedKey;
+use tokio::net::TcpStream;
+use tracing::{debug, warn};
+
+use crate::allowlist::ClientAllowlist;
+use crate::ca::ProxyCa;
+
+/// Header on relayed requests naming the HTTP/3 client. The proxy removes
+/// it and records the request as HTTP/3 from that address.
+pub(crate) const H3_CLIENT_HEADER: &str = "x-phantom-h3-client";
+
+/// HTTP/1.1 connection-specific headers, not allowed in HTTP/3 responses
+/// (RFC 9114 section 4.2).
+const CONNECTION_HEADERS: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// Leaf certificates kept before the cache starts over.
+const MAX_LEAVES: usize = 1000;
+
+type H3Stream<S> = RequestStream<S, Bytes>;
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Binds the QUIC endpoint on `ip:port`, serving certificates signed by `ca`.
+pub(crate) fn endpoint(
+    ip: IpAddr,
+    port: u16,
+    ca: &ProxyCa,
+) -> Result<quinn::Endpoint, CaptureError> {
+    let start_failed =
+        |e: String| CaptureError::StartFailed(format!("HTTP/3 listener on {ip}:{port}: {e}"));
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let (ca_cert, ca_key) = ca.issuer()?;
+    let signing_key = provider
+        .key_provider
+        .load_private_key(PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(
+            ca_key.serialize_der(),
+        )))
+        .map_err(|e| start_failed(e.to_string()))?;
+    let resolver = LeafResolver {
+        ca_cert,
+        ca_key,
+        signing_key,
+        leaves: Mutex::default(),
+    };
+    let mut tls = rustls::ServerConfig::builder_with_provider(provider)
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .map_err(|e| start_failed(e.to_string()))?
+        .with_no_client_auth()
+        .with_cert_resolver(Arc::new(resolver));
+    tls.alpn_protocols = vec![b"h3".to_vec()];
+    let crypto = quinn::crypto::rustls::QuicServerConfig::try_from(tls)
+        .map_err(|e| start_failed(e.to_string()))?;
+    let socket = std::net::UdpSocket::bind((ip, port)).map_err(|e| start_failed(e.to_string()))?;
+    quinn::Endpoint::new(
+        quinn::EndpointConfig::default(),
+        Some(quinn::ServerConfig::with_crypto(Arc::new(crypto))),
+        socket,
+        Arc::new(quinn::TokioRuntime),
+    )
+    .map_err(|e| start_failed(e.to_string()))
+}
+
+/// Accepts QUIC connections on `endpoint` and relays their requests to the
+/// HTTP proxy on `proxy_addr`. Clients outside `allowlist` are refused.
+pub(crate) async fn serve(
+    endpoint: quinn::Endpoint,
+    proxy_addr: SocketAddr,
+    allowlist: Arc<ClientAllowlist>,
+) {
+    while let Some(incoming) = endpoint.accept().await {
+        let peer = incoming.remote_address();
+        if !allowlist.allows(peer.ip()) {
+            debug!("HTTP/3 client {peer} not in allowlist, refusing");
+            incoming.refuse();
+            continue;
+        }
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(incoming, proxy_addr).await {
+                debug!("HTTP/3 connection from {peer}: {e}");
+            }
+        });
+    }
+}
+
+async fn handle_connection(
+    incoming: quinn::Incoming,
+    proxy_addr: SocketAddr,
+) -> Result<(), BoxError> {
+    let conn = incoming.await?;
+    let peer = conn.remote_address();
+    let mut h3_conn: h3::server::Connection<_, Bytes> =
+        h3::server::Connection::new(h3_quinn::Connection::new(conn)).await?;
+    loop {
+        match h3_conn.accept().await {
+            Ok(Some(resolver)) => {
+                tokio::spawn(async move {
+                    let result = match resolver.resolve_request().await {
+                        Ok((req, stream)) => relay(req, stream, peer, proxy_addr).await,
+                        Err(e) => Err(e.into()),
+                    };
+                    if let Err(e) = result {
+                        debug!("HTTP/3 request from {peer}: {e}");
+                    }
+                });
+            }
+            Ok(None) => return Ok(()),
+            Err(e) if e.is_h3_no_error() => return Ok(()),
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// Relays one request to the proxy and streams its response back. The
+/// request body streams up as it arrives; a response body that fails
+/// resets the stream rather than ending it as if complete.
+async fn relay<S>(
+    req: Request<()>,
+    stream: H3Stream<S>,
+    peer: SocketAddr,
+    proxy_addr: SocketAddr,
+) -> Result<(), BoxError>
+where
+    S: h3::quic::BidiStream<Bytes> + Send + 'static,
+    S::SendStream: Send,
+    S::RecvStream: Send + 'static,
+{
+    let (mut send, recv) = stream.split();
+    let req = match proxy_request(req, peer) {
+        Ok(req) => req,
+        Err(e) => {
+            warn!("HTTP/3 request from {peer}: {e}");
+            return respond_error(&mut send, StatusCode::BAD_REQUEST).await;
+        }
+    };
+    let body = StreamBody::new(futures_util::stream::unfold(
+        Some(recv),
+        |recv| async move {
+            let mut recv = recv?;
+            match recv.recv_data().await {
+                Ok(Some(mut chunk)) => {
+                    let chunk = chunk.copy_to_bytes(chunk.remaining());
+                    Some((Ok(Frame::data(chunk)), Some(recv)))
+                }
+                Ok(None) => None,
+                Err(e) => Some((Err(e), None)),
+            }
+        },
+    ));
+    let response = match send_to_proxy(proxy_addr, req.map(|()| body)).await {
+        Ok(response) => response,
+        Err(e) => {
+            warn!("HTTP/3 request from {peer}: proxy unreachable: {e}");
+            return respond_error(&mut send, StatusCode::BAD_GATEWAY).await;
+        }
+    };
+
+    let (mut parts, mut body) = response.into_parts();
+    for name in CONNECTION_HEADERS {
+        parts.headers.remove(*name);
+    }
+    parts.version = http::Version::HTTP_3;
+    send.send_response(Response::from_parts(parts, ())).await?;
+    while let Some(frame) = body.frame().await {
+        let frame = match frame {
+            Ok(frame) => frame,
+            Err(e) => {
+                send.stop_stream(h3::error::Code::H3_INTERNAL_ERROR);
+                return Err(e.into());
+            }
+        };
+        match frame.into_data() {
+            Ok(chunk) => send.send_data(chunk).await?,
+            Err(frame) => {
+                if let Ok(trailers) = frame.into_trailers() {
+                    send.send_trailers(trailers).await?;
+                }
+            }
+        }
+    }
+    send.finish().await?;
+    Ok(())
+}
+
+/// The request as sent to the proxy: absolute-form `https://` URI, a `Host`
+/// header, and [`H3_CLIENT_HEADER`] naming the client.
+fn proxy_request(req: Request<()>, peer: SocketAddr) -> Result<Request<()>, BoxError> {
+    let (mut parts, ()) = req.into_parts();
+    let authority = parts
+        .uri
+        .authority()
+        .cloned()
+        .ok_or("request without :authority")?;
+    let path = parts.uri.path_and_query().map_or("/", |path| path.as_str());
+    parts.uri = Uri::builder()
+        .scheme("https")
+        .authority(authority.as_str())
+        .path_and_query(path)
+        .build()?;
+    parts.version = http::Version::HTTP_11;
+    parts
+        .headers
+        .insert(HOST, HeaderValue::from_str(authority.as_str())?);
+    parts.headers.insert(
+        HeaderName::from_static(H3_CLIENT_HEADER),
+        HeaderValue::from_str(&peer.to_string())?,
+    );
+    Ok(Request::from_parts(parts, ()))
+}
+
+async fn send_to_proxy<B>(
+    proxy_addr: SocketAddr,
+    req: Request<B>,
+) -> Result<Response<hyper::body::Incoming>, BoxError>
+where
+    B: hyper::body::Body<Data = Bytes> + Send + 'static,
+    B::Error: Into<BoxError>,
+{
+    let stream = TcpStream::connect(proxy_addr).await?;
+    let (mut sender, conn) = hyper::client::conn::http1::handshake(TokioIo::new(stream)).await?;
+    tokio::spawn(async move {
+        if let Err(e) = conn.await {
+            debug!("HTTP/3 relay connection: {e}");
+        }
+    });
+    Ok(sender.send_request(req).await?)
+}
+
+/// Answers with an empty `status` response from phantom itself.
+async fn respond_error<S>(send: &mut H3Stream<S>, status: StatusCode) -> Result<(), BoxError>
+where
+    S: h3::quic::SendStream<Bytes>,
+{
+    let mut response = Response::new(());
+    *response.status_mut() = status;
+    send.send_response(response).await?;
+    send.finish().await?;
+    Ok(())
+}
+
+/// Serves a leaf certificate for each SNI name, signed by the proxy CA with
+/// the CA's own key, like hudsucker's authority does for TCP.
+struct LeafResolver {
+    ca_cert: Certificate,
+    ca_key: KeyPair,
+    signing_key: Arc<dyn rustls::sign::SigningKey>,
+    leaves: Mutex<HashMap<String, Arc<CertifiedKey>>>,
+}
+
+impl LeafResolver {
+    fn leaf(&self, name: &str) -> Result<CertificateDer<'static>, hudsucker::rcgen::Error> {
+        let mut params = CertificateParams::default();
+        params.serial_number = Some(rand::random::<u64>().into());
+        let not_before = time::OffsetDateTime::now_utc() - time::Duration::minutes(1);
+        params.not_before = not_before;
+        params.not_after = not_before + time::Duration::days(365);
+        let mut distinguished_name = DistinguishedName::new();
+        distinguished_name.push(DnType::CommonName, name);
+        params.distinguished_name = distinguished_name;
+        params
+            .subject_alt_names
+            .push(SanType::DnsName(Ia5String::try_from(name)?));
+        let cert = params.signed_by(&self.ca_key, &self.ca_cert, &self.ca_key)?;
+        Ok(cert.der().clone())
+    }
+}
+
+impl ResolvesServerCert for LeafResolver {
+    fn resolve(&self, hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        let name = hello.server_name()?.to_ascii_lowercase();
+        let mut leaves = self.leaves.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(leaf) = leaves.get(&name) {
+            return Some(Arc::clone(leaf));
+        }
+        let cert = match self.leaf(&name) {
+            Ok(cert) => cert,
+            Err(e) => {
+                warn!("HTTP/3 certificate for {name}: {e}");
+                return None;
+            }
+        };
+        let leaf = Arc::new(CertifiedKey::new(vec![cert], Arc::clone(&self.signing_key)));
+        if leaves.len() >= MAX_LEAVES {
+            leaves.clear();
+        }
+        leaves.insert(name, Arc::clone(&leaf));
+        Some(leaf)
+    }
+}
+
+impl std::fmt::Debug for LeafResolver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LeafResolver").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use http_body_util::Full;
+    use hyper::service::service_fn;
+    use quinn::rustls::pki_types::pem::PemObject;
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    /// A stand-in for the HTTP proxy listener: answers one request with 201
+    /// and returns the request it got, body included.
+    async fn make_fake_proxy() -> (SocketAddr, tokio::task::JoinHandle<Request<Bytes>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let task = tokio::spawn(async move {
+            let (conn, _) = listener.accept().await.unwrap();
+            let (tx, rx) = tokio::sync::oneshot::channel();
+            let tx = Mutex::new(Some(tx));
+            let service = service_fn(move |req: Request<hyper::body::Incoming>| {
+                let tx = tx.lock().unwrap().take();
+                async move {
+                    let (parts, body) = req.into_parts();
+                    let body = body.collect().await.unwrap().to_bytes();
+                    if let Some(tx) = tx {
+                        let _ = tx.send(Request::from_parts(parts, body));
+                    }
+                    Response::builder()
+                        .status(201)
+                        .header("connection", "keep-alive")
+                        .header("x-upstream", "yes")
+                        .body(Full::new(Bytes::from_static(b"created")))
+                }
+            });
+            tokio::spawn(
+                hyper::server::conn::http1::Builder::new()
+                    .serve_connection(TokioIo::new(conn), service),
+            );
+            rx.await.unwrap()
+        });
+        (addr, task)
+    }
+
+    #[tokio::test]
+    async fn test_h3_request_is_relayed_to_proxy() {
+        let ca = ProxyCa::generate().unwrap();
+        let (proxy_addr, proxy) = make_fake_proxy().await;
+        let server = endpoint(IpAddr::V4(Ipv4Addr::LOCALHOST), 0, &ca).unwrap();
+        let server_addr = server.local_addr().unwrap();
+        tokio::spawn(serve(server, proxy_addr, Arc::default()));
+
+        let mut roots = rustls::RootCertStore::empty();
+        roots
+            .add(CertificateDer::from_pem_slice(ca.cert_pem().as_bytes()).unwrap())
+            .unwrap();
+        let mut tls = rustls::ClientConfig::builder_with_provider(Arc::new(
+            rustls::crypto::ring::default_provider(),
+        ))
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .unwrap()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+        tls.alpn_protocols = vec![b"h3".to_vec()];
+        let crypto = quinn::crypto::rustls::QuicClientConfig::try_from(tls).unwrap();
+        let mut client = quinn::Endpoint::client((Ipv4Addr::LOCALHOST, 0).into()).unwrap();
+        client.set_default_client_config(quinn::ClientConfig::new(Arc::new(crypto)));
+        let conn = client
+            .connect(server_addr, "api.example.test")
+            .unwrap()
+            .await
+            .unwrap();
+        let client_addr = client.local_addr().unwrap();
+        let (mut driver, mut sender) = h3::client::new(h3_quinn::Connection::new(conn))
+            .await
+            .unwrap();
+        tokio::spawn(async move { std::future::poll_fn(|cx| driver.poll_close(cx)).await });
+
+        let req = Request::post("https://api.example.test/items?page=2")
+            .body(())
+            .unwrap();
+        let mut stream = sender.send_request(req).await.unwrap();
+        stream
+            .send_data(Bytes::from_static(b"hello"))
+            .await
+            .unwrap();
+        stream.finish().await.unwrap();
+        let response = stream.recv_response().await.unwrap();
+        let mut body = Vec::new();
+        while let Some(mut chunk) = stream.recv_data().await.unwrap() {
+            body.extend_from_slice(&chunk.copy_to_bytes(chunk.remaining()));
+        }
+
+        assert_eq!(response.status(), 201);
+        assert_eq!(response.headers()["x-upstream"], "yes");
+        assert!(response.headers().get("connection").is_none());
+        assert_eq!(body, b"created");
+
+        let relayed = proxy.await.unwrap();
+        assert_eq!(
+            relayed.uri().to_string(),
+            "https://api.example.test/items?page=2"
+        );
+        assert_eq!(relayed.headers()[HOST], "api.example.test");
+        assert_eq!(
+            relayed.headers()[H3_CLIENT_HEADER],
+            client_addr.to_string().as_str()
+        );
+        assert_eq!(relayed.body().as_ref(), b"hello");
+    }
+}
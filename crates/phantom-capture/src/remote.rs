@@ -0,0 +1,250 @@
+//! Remote backend: traces captured by another phantom — cross-platform.
+//!
+//! Connects to a phantom running with `--sink serve:ADDR` (typically in a
+//! Kubernetes pod, reached through `kubectl port-forward`) and reads the
+//! traces it streams, one JSON-serialized [`HttpTrace`] per line, or a
+//! [`ProtocolTrace`] tagged with its `kind`. Nothing is captured locally. A lost connection is retried every
+//! [`RECONNECT_INTERVAL`]; traces captured while disconnected are not
+//! recovered.
+
+use std::time::Duration;
+
+use phantom_core::capture::{CaptureBackend, TraceEvent};
+use phantom_core::error::CaptureError;
+use phantom_core::protocol::ProtocolTrace;
+use phantom_core::trace::HttpTrace;
+use serde::Deserialize;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, oneshot};
+use tracing::{info, warn};
+
+/// Wait between connection attempts.
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+pub struct RemoteCaptureBackend {
+    addr: String,
+    shutdown_tx: Option<oneshot::Sender<()>>,
+    task_handle: Option<tokio::task::JoinHandle<()>>,
+}
+
+impl RemoteCaptureBackend {
+    /// Create a backend reading from the phantom serving at `addr`
+    /// (`HOST:PORT`).
+    pub fn new(addr: String) -> Self {
+        Self {
+            addr,
+            shutdown_tx: None,
+            task_handle: None,
+        }
+    }
+}
+
+/// Reads one streamed line: a non-HTTP trace has a `kind` field, which an
+/// HTTP trace does not.
+fn parse_event(line: &str) -> serde_json::Result<TraceEvent> {
+    let value: serde_json::Value = serde_json::from_str(line)?;
+    if value.get("kind").is_some() {
+        ProtocolTrace::deserialize(value).map(TraceEvent::from)
+    } else {
+        HttpTrace::deserialize(value).map(TraceEvent::from)
+    }
+}
+
+/// Forwards the traces read from one connection until it closes. Returns
+/// `false` once nobody is receiving traces any more.
+async fn forward(stream: TcpStream, addr: &str, trace_tx: &mpsc::Sender<TraceEvent>) -> bool {
+    let mut lines = BufReader::new(stream).lines();
+    loop {
+        let line = match lines.next_line().await {
+            Ok(Some(line)) => line,
+            Ok(None) => {
+                warn!("remote {addr} closed the connection");
+                return true;
+            }
+            Err(e) => {
+                warn!("reading from remote {addr}: {e}");
+                return true;
+            }
+        };
+        match parse_event(&line) {
+            Ok(event) => {
+                if trace_tx.send(event).await.is_err() {
+                    return false;
+                }
+            }
+            // A peer of another phantom version may add fields we can't read.
+            Err(e) => warn!("skipping unreadable trace from remote {addr}: {e}"),
+        }
+    }
+}
+
+async fn receive(addr: String, trace_tx: mpsc::Sender<TraceEvent>) {
+    // Only the first failure of an outage is logged.
+    let mut connected = true;
+    loop {
+        match TcpStream::connect(&addr).await {
+            Ok(stream) => {
+                info!("connected to remote {addr}");
+                connected = true;
+                if !forward(stream, &addr, &trace_tx).await {
+                    return;
+                }
+            }
+            Err(e) if connected => {
+                warn!("cannot connect to remote {addr}: {e}; retrying");
+                connected = false;
+            }
+            Err(_) => {}
+        }
+        if trace_tx.is_closed() {
+            return;
+        }
+        tokio::time::sleep(RECONNECT_INTERVAL).await;
+    }
+}
+
+impl CaptureBackend for RemoteCaptureBackend {
+    fn start(&mut self) -> Result<mpsc::Receiver<TraceEvent>, CaptureError> {
+        if self.addr.rsplit_once(':').is_none() {
+            return Err(CaptureError::StartFailed(format!(
+                "invalid remote address {:?}: expected HOST:PORT",
+                self.addr
+            )));
+        }
+        let (trace_tx, trace_rx) = mpsc::channel(4096);
+        let (shutdown_tx, shutdown_rx) = oneshot::channel();
+        self.shutdown_tx = Some(shutdown_tx);
+        let addr = self.addr.clone();
+        self.task_handle = Some(tokio::spawn(async move {
+            tokio::select! {
+                _ = receive(addr, trace_tx) => {}
+                _ = shutdown_rx => {}
+            }
+        }));
+        Ok(trace_rx)
+    }
+
+    fn stop(&mut self) -> Result<(), CaptureError> {
+        if let Some(tx) = self.shutdown_tx.take() {
+            let _ = tx.send(());
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "remote"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::time::SystemTime;
+
+    use phantom_core::redis::{RedisReplyType, RedisTrace};
+    use phantom_core::trace::{HttpMethod, SpanId, TraceId};
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    fn make_trace(url: &str) -> HttpTrace {
+        HttpTrace {
+            span_id: SpanId([7; 8]),
+            trace_id: TraceId([9; 16]),
+            parent_span_id: None,
+            method: HttpMethod::Get,
+            url: url.to_string(),
+            request_headers: HashMap::new(),
+            request_body: None,
+            status_code: 200,
+            response_headers: HashMap::new(),
+            response_body: Some(b"ok".to_vec()),
+            timestamp: SystemTime::now(),
+            duration: Duration::from_millis(3),
+            source_addr: None,
+            dest_addr: None,
+            protocol_version: "HTTP/1.1".to_string(),
+            request_body_file: None,
+            response_body_file: None,
+            connection_id: None,
+            process: None,
+            response_trailers: HashMap::new(),
+            request_body_encoding: None,
+            response_body_encoding: None,
+            elasticsearch: None,
+            soap: None,
+            graphql: None,
+            thread_id: None,
+            tls: None,
+            timing: None,
+            request_size: None,
+            response_size: None,
+            fault: None,
+            replay: None,
+        }
+    }
+
+    async fn next_trace(rx: &mut mpsc::Receiver<TraceEvent>) -> HttpTrace {
+        match rx.recv().await.unwrap() {
+            TraceEvent::Http(trace) => *trace,
+            other => panic!("expected an HTTP trace, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reads_traces_and_reconnects() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let mut backend = RemoteCaptureBackend::new(addr);
+        let mut rx = backend.start().unwrap();
+
+        // First connection: a garbage line is skipped, then it closes.
+        let (mut conn, _) = listener.accept().await.unwrap();
+        let mut payload = b"not json\n".to_vec();
+        payload.extend(serde_json::to_vec(&make_trace("http://pod/one")).unwrap());
+        payload.push(b'\n');
+        conn.write_all(&payload).await.unwrap();
+        drop(conn);
+        assert_eq!(next_trace(&mut rx).await.url, "http://pod/one");
+
+        // The backend comes back on its own.
+        let (mut conn, _) = listener.accept().await.unwrap();
+        let mut line = serde_json::to_vec(&make_trace("http://pod/two")).unwrap();
+        line.push(b'\n');
+        conn.write_all(&line).await.unwrap();
+        let trace = next_trace(&mut rx).await;
+        assert_eq!(trace.url, "http://pod/two");
+        assert_eq!(trace.response_body.as_deref(), Some(&b"ok"[..]));
+
+        // Non-HTTP traces come through tagged with their kind.
+        let redis = RedisTrace {
+            command: "GET".into(),
+            key: Some("user:1".into()),
+            args: Vec::new(),
+            arg_count: 1,
+            reply_type: RedisReplyType::Bulk,
+            error: None,
+            timestamp: SystemTime::now(),
+            duration: Duration::from_millis(1),
+            dest_addr: Some("10.0.0.5:6379".into()),
+        };
+        let mut line = serde_json::to_vec(&ProtocolTrace::Redis(redis)).unwrap();
+        line.push(b'\n');
+        conn.write_all(&line).await.unwrap();
+        let Some(TraceEvent::Protocol(ProtocolTrace::Redis(got))) = rx.recv().await else {
+            panic!("expected a Redis trace");
+        };
+        assert_eq!(got.command, "GET");
+        assert_eq!(got.dest_addr.as_deref(), Some("10.0.0.5:6379"));
+
+        backend.stop().unwrap();
+    }
+
+    #[test]
+    fn test_rejects_address_without_port() {
+        let mut backend = RemoteCaptureBackend::new("localhost".into());
+        assert!(backend.start().is_err());
+    }
+}
@@ -0,0 +1,320 @@
+//! Request/response rewrite rules (map-local / map-remote).
+//!
+//! Rules are loaded from a TOML file and applied by the proxy in order to
+//! every request whose URL they match:
+//!
+//! ```toml
+//! [[rule]]
+//! match = "api.example.com/v1/*"        # `*` matches any text, unanchored
+//! method = "GET"                        # optional
+//! map_remote = "http://localhost:3000"  # send to another origin instead
+//! request_headers = { "x-debug" = "1" }
+//! remove_request_headers = ["cookie"]
+//! response_headers = { "cache-control" = "no-store" }
+//! remove_response_headers = ["set-cookie"]
+//!
+//! [[rule]]
+//! match = "/v1/users/42"
+//! map_local = "fixtures/user-42.json"   # relative to the rules file
+//! status = 200                          # optional, default 200
+//! ```
+//!
+//! Traces keep what the client sent and what the server answered; a
+//! `map_local` response is traced as served.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use http::header::{HeaderMap, HeaderName, HeaderValue};
+use http::{Method, Uri};
+use phantom_core::error::CaptureError;
+use phantom_core::slo::wildcard_contains;
+use serde::Deserialize;
+
+/// An ordered list of rewrite rules.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RewriteConfig {
+    #[serde(default, rename = "rule")]
+    pub rules: Vec<RewriteRule>,
+}
+
+/// One rewrite rule: a URL (and optional method) filter plus its actions.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RewriteRule {
+    /// URL pattern; `*` matches any text (same syntax as `--slo` filters).
+    #[serde(rename = "match")]
+    pub url_pattern: String,
+    /// If Some, only requests with this method match (case-insensitive).
+    #[serde(default)]
+    pub method: Option<String>,
+    /// Origin (`scheme://host[:port][/prefix]`) to send the request to.
+    #[serde(default)]
+    pub map_remote: Option<String>,
+    /// File served as the response body; the request is not forwarded.
+    #[serde(default)]
+    pub map_local: Option<PathBuf>,
+    /// Status code of a `map_local` response.
+    #[serde(default)]
+    pub status: Option<u16>,
+    #[serde(default)]
+    pub request_headers: HashMap<String, String>,
+    #[serde(default)]
+    pub remove_request_headers: Vec<String>,
+    #[serde(default)]
+    pub response_headers: HashMap<String, String>,
+    #[serde(default)]
+    pub remove_response_headers: Vec<String>,
+}
+
+impl RewriteConfig {
+    /// Loads and validates a rules file. Relative `map_local` paths are
+    /// resolved against the file's directory.
+    pub fn load(path: &Path) -> Result<Self, CaptureError> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| CaptureError::Other(format!("{}: {e}", path.display())))?;
+        let mut config = Self::parse(&text)
+            .map_err(|e| CaptureError::Other(format!("{}: {e}", path.display())))?;
+        let base = path.parent().unwrap_or(Path::new("."));
+        for rule in &mut config.rules {
+            if let Some(file) = &mut rule.map_local
+                && file.is_relative()
+            {
+                *file = base.join(&*file);
+            }
+        }
+        Ok(config)
+    }
+
+    /// Parses and validates rules from TOML text.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let config: Self = toml::from_str(text).map_err(|e| e.to_string())?;
+        for (i, rule) in config.rules.iter().enumerate() {
+            rule.validate()
+                .map_err(|e| format!("rule {}: {e}", i + 1))?;
+        }
+        Ok(config)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Indices of the rules that apply to a request, in file order.
+    pub fn matching(&self, method: &Method, url: &str) -> Vec<usize> {
+        self.rules
+            .iter()
+            .enumerate()
+            .filter(|(_, rule)| rule.matches(method, url))
+            .map(|(i, _)| i)
+            .collect()
+    }
+}
+
+impl RewriteRule {
+    fn validate(&self) -> Result<(), String> {
+        if self.map_local.is_some() && self.map_remote.is_some() {
+            return Err("map_local and map_remote are mutually exclusive".to_string());
+        }
+        if self.status.is_some() && self.map_local.is_none() {
+            return Err("status only applies to map_local".to_string());
+        }
+        if let Some(status) = self.status
+            && !(100..=599).contains(&status)
+        {
+            return Err(format!("status code {status} is out of range 100–599"));
+        }
+        if let Some(target) = &self.map_remote {
+            let uri: Uri = target
+                .parse()
+                .map_err(|_| format!("invalid map_remote URL {target:?}"))?;
+            if !matches!(uri.scheme_str(), Some("http" | "https")) || uri.authority().is_none() {
+                return Err(format!(
+                    "map_remote {target:?} must be an http:// or https:// origin"
+                ));
+            }
+        }
+        for (name, value) in self.request_headers.iter().chain(&self.response_headers) {
+            HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| format!("invalid header name {name:?}"))?;
+            HeaderValue::from_str(value)
+                .map_err(|_| format!("invalid value for header {name:?}"))?;
+        }
+        for name in self
+            .remove_request_headers
+            .iter()
+            .chain(&self.remove_response_headers)
+        {
+            HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| format!("invalid header name {name:?}"))?;
+        }
+        Ok(())
+    }
+
+    /// Returns true if this rule applies to the request.
+    pub fn matches(&self, method: &Method, url: &str) -> bool {
+        self.method
+            .as_deref()
+            .is_none_or(|m| m.eq_ignore_ascii_case(method.as_str()))
+            && wildcard_contains(url, &self.url_pattern)
+    }
+
+    /// `uri` with its origin replaced by `map_remote`; a path in the
+    /// `map_remote` URL is prefixed to the request path.
+    pub fn map_remote_uri(&self, uri: &Uri) -> Option<Uri> {
+        let target: Uri = self.map_remote.as_deref()?.parse().ok()?;
+        let prefix = target.path().trim_end_matches('/');
+        let path_and_query = uri.path_and_query().map(|pq| pq.as_str()).unwrap_or("/");
+        Uri::builder()
+            .scheme(target.scheme_str()?)
+            .authority(target.authority()?.as_str())
+            .path_and_query(format!("{prefix}{path_and_query}"))
+            .build()
+            .ok()
+    }
+
+    pub fn apply_request_headers(&self, headers: &mut HeaderMap) {
+        edit_headers(headers, &self.remove_request_headers, &self.request_headers);
+    }
+
+    pub fn apply_response_headers(&self, headers: &mut HeaderMap) {
+        edit_headers(
+            headers,
+            &self.remove_response_headers,
+            &self.response_headers,
+        );
+    }
+}
+
+/// Removes, then sets, headers. Names and values were checked on load.
+fn edit_headers(headers: &mut HeaderMap, remove: &[String], set: &HashMap<String, String>) {
+    for name in remove {
+        if let Ok(name) = HeaderName::from_bytes(name.as_bytes()) {
+            headers.remove(name);
+        }
+    }
+    for (name, value) in set {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(name.as_bytes()),
+            HeaderValue::from_str(value),
+        ) {
+            headers.insert(name, value);
+        }
+    }
+}
+
+/// Content type of a `map_local` file, from its extension.
+pub(crate) fn content_type_for(path: &Path) -> &'static str {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("json") => "application/json",
+        Some("html" | "htm") => "text/html; charset=utf-8",
+        Some("txt") => "text/plain; charset=utf-8",
+        Some("xml") => "application/xml",
+        Some("js") => "text/javascript",
+        Some("css") => "text/css",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("svg") => "image/svg+xml",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_rule(toml: &str) -> RewriteRule {
+        RewriteConfig::parse(toml).unwrap().rules.remove(0)
+    }
+
+    #[test]
+    fn test_parse_rules_in_order() {
+        let config = RewriteConfig::parse(
+            r#"
+            [[rule]]
+            match = "/api/*"
+            map_remote = "http://localhost:3000"
+
+            [[rule]]
+            match = "/users/42"
+            method = "get"
+            map_local = "user.json"
+            status = 201
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.rules.len(), 2);
+        assert_eq!(config.rules[1].status, Some(201));
+
+        let url = "https://example.com/api/users/42";
+        assert_eq!(config.matching(&Method::GET, url), [0, 1]);
+        assert_eq!(config.matching(&Method::POST, url), [0]);
+        assert!(
+            config
+                .matching(&Method::GET, "https://example.com/")
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_parse_rejects_invalid_rules() {
+        for toml in [
+            "[[rule]]\nmatch = \"/\"\nmap_local = \"a\"\nmap_remote = \"http://b\"",
+            "[[rule]]\nmatch = \"/\"\nstatus = 200",
+            "[[rule]]\nmatch = \"/\"\nmap_remote = \"localhost:3000\"",
+            "[[rule]]\nmatch = \"/\"\nrequest_headers = { \"bad header\" = \"1\" }",
+            "[[rule]]\nmatch = \"/\"\nredirect = \"http://b\"",
+        ] {
+            assert!(RewriteConfig::parse(toml).is_err(), "{toml}");
+        }
+    }
+
+    #[test]
+    fn test_map_remote_replaces_origin() {
+        let rule = make_rule("[[rule]]\nmatch = \"/\"\nmap_remote = \"http://localhost:3000\"");
+        let uri: Uri = "https://api.example.com/v1/users?page=2".parse().unwrap();
+        assert_eq!(
+            rule.map_remote_uri(&uri).unwrap().to_string(),
+            "http://localhost:3000/v1/users?page=2"
+        );
+
+        let rule = make_rule(
+            "[[rule]]\nmatch = \"/\"\nmap_remote = \"https://staging.example.com/base/\"",
+        );
+        assert_eq!(
+            rule.map_remote_uri(&uri).unwrap().to_string(),
+            "https://staging.example.com/base/v1/users?page=2"
+        );
+    }
+
+    #[test]
+    fn test_header_edits_remove_then_set() {
+        let rule = make_rule(
+            "[[rule]]\nmatch = \"/\"\nrequest_headers = { \"x-debug\" = \"1\" }\nremove_request_headers = [\"cookie\", \"x-debug\"]",
+        );
+        let mut headers = HeaderMap::new();
+        headers.insert("cookie", HeaderValue::from_static("session=1"));
+        headers.insert("accept", HeaderValue::from_static("*/*"));
+        rule.apply_request_headers(&mut headers);
+        assert!(headers.get("cookie").is_none());
+        assert_eq!(headers["x-debug"], "1");
+        assert_eq!(headers["accept"], "*/*");
+    }
+
+    #[test]
+    fn test_load_resolves_map_local_relative_to_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("rules.toml");
+        std::fs::write(
+            &path,
+            "[[rule]]\nmatch = \"/\"\nmap_local = \"fixtures/a.json\"",
+        )
+        .unwrap();
+        let config = RewriteConfig::load(&path).unwrap();
+        assert_eq!(
+            config.rules[0].map_local.as_deref(),
+            Some(dir.path().join("fixtures/a.json").as_path())
+        );
+    }
+}
@@ -0,0 +1,216 @@
+/// A network shaping rule: extra latency and bandwidth caps for the
+/// requests to one host (or all hosts).
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShapingRule {
+    /// Added to every request before it is forwarded.
+    pub latency_ms: u64,
+    /// Request body cap in bytes per second.
+    pub up_bytes_per_sec: Option<u64>,
+    /// Response body cap in bytes per second.
+    pub down_bytes_per_sec: Option<u64>,
+    /// If Some, only applies to this host; `*.example.com` also matches
+    /// subdomains.
+    pub host_pattern: Option<String>,
+}
+
+impl ShapingRule {
+    /// Returns true if this rule applies to requests for `host`.
+    pub fn matches_host(&self, host: &str) -> bool {
+        self.host_pattern
+            .as_deref()
+            .is_none_or(|pattern| host_matches(pattern, host))
+    }
+}
+
+/// Returns true if `host` matches a lowercase `pattern`: an exact host name,
+/// or `*.example.com` for example.com and its subdomains.
+pub(crate) fn host_matches(pattern: &str, host: &str) -> bool {
+    let host = host.to_ascii_lowercase();
+    match pattern.strip_prefix("*.") {
+        Some(domain) => host
+            .strip_suffix(domain)
+            .is_some_and(|rest| rest.is_empty() || rest.ends_with('.')),
+        None => host == pattern,
+    }
+}
+
+/// Shaping rules; the first one matching a request's host applies.
+#[derive(Clone, Debug, Default)]
+pub struct ShapingConfig {
+    pub rules: Vec<ShapingRule>,
+}
+
+impl ShapingConfig {
+    pub fn rule_for(&self, host: &str) -> Option<&ShapingRule> {
+        self.rules.iter().find(|rule| rule.matches_host(host))
+    }
+}
+
+/// Time to move `bytes` at `bytes_per_sec`. Body transfers are modelled as
+/// this delay rather than paced chunk by chunk.
+pub fn transfer_time(bytes: usize, bytes_per_sec: Option<u64>) -> std::time::Duration {
+    match bytes_per_sec {
+        Some(rate) if rate > 0 && bytes > 0 => {
+            std::time::Duration::from_secs_f64(bytes as f64 / rate as f64)
+        }
+        _ => std::time::Duration::ZERO,
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// CLI spec parsing
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Parse a throttle specification string into a `ShapingRule`.
+///
+/// Comma-separated settings, optionally followed by `@HOST`:
+///   latency=200ms                     200 ms extra latency on all requests
+///   rate=64KB/s                       cap request and response bodies
+///   down=1MB/s,up=128kbit/s           separate caps per direction
+///   latency=1s,rate=16KB/s@api.example.com
+///   latency=300ms@*.example.com       subdomains of example.com too
+///
+/// Rates take `B/s`, `KB/s`, `MB/s` (1000-based) or `kbit/s`, `mbit/s`.
+pub fn parse_shaping_spec(s: &str) -> Result<ShapingRule, String> {
+    let (settings, host_pattern) = match s.rsplit_once('@') {
+        Some((settings, host)) if !host.is_empty() => {
+            (settings, Some(host.trim().to_ascii_lowercase()))
+        }
+        Some(_) => return Err(format!("invalid throttle spec {s:?}: empty host after '@'")),
+        None => (s, None),
+    };
+    let mut rule = ShapingRule {
+        latency_ms: 0,
+        up_bytes_per_sec: None,
+        down_bytes_per_sec: None,
+        host_pattern,
+    };
+    for setting in settings.split(',').map(str::trim) {
+        let (key, value) = setting.split_once('=').ok_or_else(|| {
+            format!("invalid throttle setting {setting:?} in {s:?}; expected KEY=VALUE")
+        })?;
+        match key.trim() {
+            "latency" => rule.latency_ms = parse_ms(value)?,
+            "rate" => {
+                let rate = parse_rate(value)?;
+                rule.up_bytes_per_sec = Some(rate);
+                rule.down_bytes_per_sec = Some(rate);
+            }
+            "up" => rule.up_bytes_per_sec = Some(parse_rate(value)?),
+            "down" => rule.down_bytes_per_sec = Some(parse_rate(value)?),
+            other => {
+                return Err(format!(
+                    "unknown throttle setting {other:?} in {s:?}; expected latency, rate, up or down"
+                ));
+            }
+        }
+    }
+    Ok(rule)
+}
+
+fn parse_ms(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    if let Some(n) = s.strip_suffix("ms") {
+        n.parse::<u64>()
+            .map_err(|_| format!("invalid duration {s:?}; expected e.g. '100ms'"))
+    } else if let Some(n) = s.strip_suffix('s') {
+        n.parse::<u64>()
+            .map(|v| v * 1000)
+            .map_err(|_| format!("invalid duration {s:?}; expected e.g. '2s'"))
+    } else {
+        Err(format!(
+            "invalid duration {s:?}; expected e.g. '100ms' or '2s'"
+        ))
+    }
+}
+
+fn parse_rate(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let invalid = || format!("invalid rate {s:?}; expected e.g. '64KB/s' or '1mbit/s'");
+    let lower = s.to_ascii_lowercase();
+    let per_sec = lower.strip_suffix("/s").ok_or_else(invalid)?;
+    let (number, bytes_per_unit) = [
+        ("kbit", 1000.0 / 8.0),
+        ("mbit", 1_000_000.0 / 8.0),
+        ("kb", 1000.0),
+        ("mb", 1_000_000.0),
+        ("b", 1.0),
+    ]
+    .into_iter()
+    .find_map(|(unit, factor)| per_sec.strip_suffix(unit).map(|n| (n, factor)))
+    .ok_or_else(invalid)?;
+    let number: f64 = number.trim().parse().map_err(|_| invalid())?;
+    let rate = (number * bytes_per_unit) as u64;
+    if rate == 0 {
+        return Err(format!("rate {s:?} must be at least 1 B/s"));
+    }
+    Ok(rate)
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Tests
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_latency_only() {
+        let r = parse_shaping_spec("latency=200ms").unwrap();
+        assert_eq!(r.latency_ms, 200);
+        assert_eq!(r.up_bytes_per_sec, None);
+        assert_eq!(r.down_bytes_per_sec, None);
+        assert_eq!(r.host_pattern, None);
+    }
+
+    #[test]
+    fn parse_rates_and_host() {
+        let r = parse_shaping_spec("latency=1s,rate=64KB/s@API.example.com").unwrap();
+        assert_eq!(r.latency_ms, 1000);
+        assert_eq!(r.up_bytes_per_sec, Some(64_000));
+        assert_eq!(r.down_bytes_per_sec, Some(64_000));
+        assert_eq!(r.host_pattern.as_deref(), Some("api.example.com"));
+
+        let r = parse_shaping_spec("down=1MB/s,up=128kbit/s").unwrap();
+        assert_eq!(r.down_bytes_per_sec, Some(1_000_000));
+        assert_eq!(r.up_bytes_per_sec, Some(16_000));
+    }
+
+    #[test]
+    fn parse_rejects_bad_specs() {
+        for spec in [
+            "latency",
+            "latency=fast",
+            "rate=64KB",
+            "rate=0B/s",
+            "jitter=5ms",
+            "latency=1s@",
+        ] {
+            assert!(parse_shaping_spec(spec).is_err(), "{spec}");
+        }
+    }
+
+    #[test]
+    fn first_matching_host_rule_applies() {
+        let config = ShapingConfig {
+            rules: vec![
+                parse_shaping_spec("latency=500ms@*.example.com").unwrap(),
+                parse_shaping_spec("latency=100ms").unwrap(),
+            ],
+        };
+        assert_eq!(config.rule_for("api.example.com").unwrap().latency_ms, 500);
+        assert_eq!(config.rule_for("example.com").unwrap().latency_ms, 500);
+        assert_eq!(config.rule_for("notexample.com").unwrap().latency_ms, 100);
+    }
+
+    #[test]
+    fn transfer_time_scales_with_size() {
+        assert_eq!(
+            transfer_time(128_000, Some(64_000)),
+            std::time::Duration::from_secs(2)
+        );
+        assert_eq!(transfer_time(128_000, None), std::time::Duration::ZERO);
+        assert_eq!(transfer_time(0, Some(1)), std::time::Duration::ZERO);
+    }
+}
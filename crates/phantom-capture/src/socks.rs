@@ -0,0 +1,313 @@
+//! SOCKS5 front end for the MITM proxy.
+//!
+//! Clients that only speak SOCKS (`ALL_PROXY=socks5://…`) get the same
+//! capture as HTTP proxy clients: each SOCKS `CONNECT` is turned into an HTTP
+//! `CONNECT` to phantom's own proxy listener and the two streams are spliced,
+//! so TLS interception, fault injection and tracing all happen there.
+
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, warn};
+
+use crate::allowlist::ClientAllowlist;
+
+const SOCKS_VERSION: u8 = 5;
+const METHOD_NO_AUTH: u8 = 0x00;
+const METHOD_NONE_ACCEPTABLE: u8 = 0xff;
+const CMD_CONNECT: u8 = 0x01;
+const ATYP_IPV4: u8 = 0x01;
+const ATYP_DOMAIN: u8 = 0x03;
+const ATYP_IPV6: u8 = 0x04;
+
+const REPLY_SUCCEEDED: u8 = 0x00;
+const REPLY_GENERAL_FAILURE: u8 = 0x01;
+const REPLY_COMMAND_NOT_SUPPORTED: u8 = 0x07;
+const REPLY_ADDRESS_NOT_SUPPORTED: u8 = 0x08;
+
+/// Longest HTTP `CONNECT` response head accepted from the proxy listener.
+const MAX_RESPONSE_HEAD: usize = 8192;
+
+/// Accepts SOCKS5 clients on `listener` and tunnels each one through the
+/// HTTP proxy listening on `proxy_addr`; clients outside `allowlist` are
+/// disconnected at once. Runs until the task is aborted.
+pub(crate) async fn serve(
+    listener: TcpListener,
+    proxy_addr: SocketAddr,
+    allowlist: Arc<ClientAllowlist>,
+) {
+    loop {
+        let (client, peer) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                warn!("SOCKS accept failed: {e}");
+                continue;
+            }
+        };
+        if !allowlist.allows(peer.ip()) {
+            debug!("SOCKS client {peer} not in allowlist, disconnecting");
+            continue;
+        }
+        tokio::spawn(async move {
+            if let Err(e) = handle(client, proxy_addr).await {
+                debug!("SOCKS connection from {peer}: {e}");
+            }
+        });
+    }
+}
+
+async fn handle(mut client: TcpStream, proxy_addr: SocketAddr) -> io::Result<()> {
+    let target = match handshake(&mut client).await? {
+        Ok(target) => target,
+        Err(reply) => return send_reply(&mut client, reply).await,
+    };
+
+    let mut upstream = match connect_via_proxy(proxy_addr, &target).await {
+        Ok(stream) => stream,
+        Err(e) => {
+            send_reply(&mut client, REPLY_GENERAL_FAILURE).await?;
+            return Err(e);
+        }
+    };
+    send_reply(&mut client, REPLY_SUCCEEDED).await?;
+    tokio::io::copy_bidirectional(&mut client, &mut upstream).await?;
+    Ok(())
+}
+
+/// Runs the SOCKS5 greeting and request. Returns the requested `host:port`,
+/// or the reply code refusing it.
+async fn handshake<S>(stream: &mut S) -> io::Result<Result<String, u8>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut header = [0u8; 2];
+    stream.read_exact(&mut header).await?;
+    if header[0] != SOCKS_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unsupported SOCKS version {}", header[0]),
+        ));
+    }
+    let mut methods = vec![0u8; header[1] as usize];
+    stream.read_exact(&mut methods).await?;
+    if !methods.contains(&METHOD_NO_AUTH) {
+        stream
+            .write_all(&[SOCKS_VERSION, METHOD_NONE_ACCEPTABLE])
+            .await?;
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "client requires SOCKS authentication",
+        ));
+    }
+    stream.write_all(&[SOCKS_VERSION, METHOD_NO_AUTH]).await?;
+
+    let mut request = [0u8; 4];
+    stream.read_exact(&mut request).await?;
+    let [version, command, _reserved, address_type] = request;
+    if version != SOCKS_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unsupported SOCKS version {version}"),
+        ));
+    }
+    let host = match address_type {
+        ATYP_IPV4 => {
+            let mut octets = [0u8; 4];
+            stream.read_exact(&mut octets).await?;
+            Ipv4Addr::from(octets).to_string()
+        }
+        ATYP_IPV6 => {
+            let mut octets = [0u8; 16];
+            stream.read_exact(&mut octets).await?;
+            format!("[{}]", Ipv6Addr::from(octets))
+        }
+        ATYP_DOMAIN => {
+            let len = stream.read_u8().await?;
+            let mut name = vec![0u8; len as usize];
+            stream.read_exact(&mut name).await?;
+            String::from_utf8(name)
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "non-UTF-8 host name"))?
+        }
+        _ => return Ok(Err(REPLY_ADDRESS_NOT_SUPPORTED)),
+    };
+    let port = stream.read_u16().await?;
+    if command != CMD_CONNECT {
+        // BIND and UDP ASSOCIATE have no HTTP proxy equivalent.
+        return Ok(Err(REPLY_COMMAND_NOT_SUPPORTED));
+    }
+    Ok(Ok(format!("{host}:{port}")))
+}
+
+/// Opens an HTTP `CONNECT` tunnel to `target` through the proxy listener.
+pub(crate) async fn connect_via_proxy(
+    proxy_addr: SocketAddr,
+    target: &str,
+) -> io::Result<TcpStream> {
+    let mut stream = TcpStream::connect(proxy_addr).await?;
+    stream.set_nodelay(true)?;
+    stream
+        .write_all(format!("CONNECT {target} HTTP/1.1\r\nHost: {target}\r\n\r\n").as_bytes())
+        .await?;
+
+    // Read byte by byte: the client speaks first, but nothing past the
+    // response head may be swallowed here.
+    let mut head = Vec::new();
+    while !head.ends_with(b"\r\n\r\n") {
+        if head.len() > MAX_RESPONSE_HEAD {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "proxy CONNECT response too long",
+            ));
+        }
+        head.push(stream.read_u8().await?);
+    }
+    let head = String::from_utf8_lossy(&head);
+    let status_line = head.lines().next().unwrap_or_default();
+    match status_line.split_whitespace().nth(1) {
+        Some(code) if code.starts_with('2') => Ok(stream),
+        _ => Err(io::Error::other(format!(
+            "proxy refused CONNECT {target}: {status_line}"
+        ))),
+    }
+}
+
+/// Sends a SOCKS5 reply; the bound address is not meaningful here.
+async fn send_reply<S: AsyncWrite + Unpin>(stream: &mut S, reply: u8) -> io::Result<()> {
+    stream
+        .write_all(&[SOCKS_VERSION, reply, 0, ATYP_IPV4, 0, 0, 0, 0, 0, 0])
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A stand-in for the HTTP proxy listener: answers one `CONNECT` with
+    /// 200, then echoes. Returns its address and the received request head.
+    async fn make_echo_proxy() -> (SocketAddr, tokio::task::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let task = tokio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            let mut head = Vec::new();
+            while !head.ends_with(b"\r\n\r\n") {
+                head.push(conn.read_u8().await.unwrap());
+            }
+            conn.write_all(b"HTTP/1.1 200 OK\r\n\r\n").await.unwrap();
+            let mut buf = [0u8; 5];
+            conn.read_exact(&mut buf).await.unwrap();
+            conn.write_all(&buf).await.unwrap();
+            String::from_utf8(head).unwrap()
+        });
+        (addr, task)
+    }
+
+    async fn make_socks_server(proxy_addr: SocketAddr) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener, proxy_addr, Arc::default()));
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_socks_connect_tunnels_through_proxy() {
+        let (proxy_addr, proxy) = make_echo_proxy().await;
+        let socks_addr = make_socks_server(proxy_addr).await;
+
+        let mut client = TcpStream::connect(socks_addr).await.unwrap();
+        client.write_all(&[5, 1, METHOD_NO_AUTH]).await.unwrap();
+        let mut choice = [0u8; 2];
+        client.read_exact(&mut choice).await.unwrap();
+        assert_eq!(choice, [5, METHOD_NO_AUTH]);
+
+        let host = b"example.com";
+        let mut request = vec![5, CMD_CONNECT, 0, ATYP_DOMAIN, host.len() as u8];
+        request.extend_from_slice(host);
+        request.extend_from_slice(&443u16.to_be_bytes());
+        client.write_all(&request).await.unwrap();
+        let mut reply = [0u8; 10];
+        client.read_exact(&mut reply).await.unwrap();
+        assert_eq!(reply[1], REPLY_SUCCEEDED);
+
+        client.write_all(b"hello").await.unwrap();
+        let mut echoed = [0u8; 5];
+        client.read_exact(&mut echoed).await.unwrap();
+        assert_eq!(&echoed, b"hello");
+
+        let head = proxy.await.unwrap();
+        assert!(
+            head.starts_with("CONNECT example.com:443 HTTP/1.1\r\n"),
+            "{head}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_socks_handshake_parses_ip_targets() {
+        let (mut client, mut server) = tokio::io::duplex(256);
+        client
+            .write_all(&[
+                5,
+                1,
+                METHOD_NO_AUTH,
+                5,
+                CMD_CONNECT,
+                0,
+                ATYP_IPV4,
+                10,
+                0,
+                0,
+                7,
+            ])
+            .await
+            .unwrap();
+        client.write_all(&8080u16.to_be_bytes()).await.unwrap();
+        let target = handshake(&mut server).await.unwrap();
+        assert_eq!(target, Ok("10.0.0.7:8080".to_string()));
+
+        let mut request = vec![5, 1, METHOD_NO_AUTH, 5, CMD_CONNECT, 0, ATYP_IPV6];
+        request.extend_from_slice(&Ipv6Addr::LOCALHOST.octets());
+        request.extend_from_slice(&443u16.to_be_bytes());
+        client.write_all(&request).await.unwrap();
+        let target = handshake(&mut server).await.unwrap();
+        assert_eq!(target, Ok("[::1]:443".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_socks_handshake_refuses_unsupported_requests() {
+        let (mut client, mut server) = tokio::io::duplex(256);
+        // UDP ASSOCIATE
+        client
+            .write_all(&[
+                5,
+                1,
+                METHOD_NO_AUTH,
+                5,
+                0x03,
+                0,
+                ATYP_IPV4,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ])
+            .await
+            .unwrap();
+        assert_eq!(
+            handshake(&mut server).await.unwrap(),
+            Err(REPLY_COMMAND_NOT_SUPPORTED)
+        );
+
+        // Username/password authentication only.
+        let (mut client, mut server) = tokio::io::duplex(256);
+        client.write_all(&[5, 1, 0x02]).await.unwrap();
+        assert!(handshake(&mut server).await.is_err());
+        let mut choice = [0u8; 2];
+        client.read_exact(&mut choice).await.unwrap();
+        assert_eq!(choice, [5, METHOD_NONE_ACCEPTABLE]);
+    }
+}
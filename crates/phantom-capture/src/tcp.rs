@@ -0,0 +1,438 @@
+//! TCP stream reassembly from captured packets.
+//!
+//! Packet-level backends hand IPv4/IPv6 packets to a [`TcpReassembler`],
+//! which follows each connection from its SYN and delivers both directions'
+//! payload in sequence order as [`StreamEvent`]s. Segments arriving ahead of
+//! a gap are held until it fills, up to [`MAX_PENDING`] bytes, after which
+//! the gap is reported and skipped. Retransmitted bytes, and packets seen
+//! twice (loopback captures), are dropped. Connections whose SYN was not
+//! seen are ignored, so which side is the client is always known.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::{Duration, SystemTime};
+
+use crate::http1::Direction;
+
+/// Out-of-order bytes held per direction before a gap is given up on.
+const MAX_PENDING: usize = 4 * 1024 * 1024;
+
+const FIN: u8 = 0x01;
+const SYN: u8 = 0x02;
+const RST: u8 = 0x04;
+const ACK: u8 = 0x10;
+
+/// The TCP part of a captured packet.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Segment<'a> {
+    pub(crate) src: SocketAddr,
+    pub(crate) dst: SocketAddr,
+    pub(crate) seq: u32,
+    pub(crate) flags: u8,
+    pub(crate) payload: &'a [u8],
+}
+
+/// Parses an IPv4 or IPv6 packet carrying TCP. Fragments and IPv6
+/// extension headers are not handled.
+pub(crate) fn parse_ip_packet(packet: &[u8]) -> Option<Segment<'_>> {
+    let (src, dst, tcp) = match packet.first()? >> 4 {
+        4 => {
+            let header_len = usize::from(packet[0] & 0x0f) * 4;
+            let total_len = usize::from(u16::from_be_bytes([*packet.get(2)?, *packet.get(3)?]));
+            let fragment = u16::from_be_bytes([*packet.get(6)?, *packet.get(7)?]);
+            // More-fragments flag or a fragment offset.
+            if *packet.get(9)? != 6 || fragment & 0x3fff != 0 {
+                return None;
+            }
+            let src = Ipv4Addr::from(<[u8; 4]>::try_from(packet.get(12..16)?).ok()?);
+            let dst = Ipv4Addr::from(<[u8; 4]>::try_from(packet.get(16..20)?).ok()?);
+            let end = total_len.min(packet.len());
+            (
+                IpAddr::from(src),
+                IpAddr::from(dst),
+                packet.get(header_len..end)?,
+            )
+        }
+        6 => {
+            if *packet.get(6)? != 6 {
+                return None;
+            }
+            let payload_len = usize::from(u16::from_be_bytes([packet[4], packet[5]]));
+            let src = Ipv6Addr::from(<[u8; 16]>::try_from(packet.get(8..24)?).ok()?);
+            let dst = Ipv6Addr::from(<[u8; 16]>::try_from(packet.get(24..40)?).ok()?);
+            let end = (40 + payload_len).min(packet.len());
+            (IpAddr::from(src), IpAddr::from(dst), packet.get(40..end)?)
+        }
+        _ => return None,
+    };
+    let src_port = u16::from_be_bytes(tcp.get(0..2)?.try_into().ok()?);
+    let dst_port = u16::from_be_bytes(tcp.get(2..4)?.try_into().ok()?);
+    let seq = u32::from_be_bytes(tcp.get(4..8)?.try_into().ok()?);
+    let data_offset = usize::from(tcp.get(12)? >> 4) * 4;
+    Some(Segment {
+        src: SocketAddr::new(src, src_port),
+        dst: SocketAddr::new(dst, dst_port),
+        seq,
+        flags: *tcp.get(13)?,
+        payload: tcp.get(data_offset..)?,
+    })
+}
+
+/// A connection, by its endpoints.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct FlowKey {
+    pub(crate) client: SocketAddr,
+    pub(crate) server: SocketAddr,
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum StreamEvent {
+    /// The next bytes of one direction.
+    Data {
+        flow: FlowKey,
+        direction: Direction,
+        data: Vec<u8>,
+    },
+    /// `len` bytes of one direction were never captured.
+    Gap {
+        flow: FlowKey,
+        direction: Direction,
+        len: usize,
+    },
+    /// Both sides sent FIN, either sent RST, or the connection went idle.
+    Closed { flow: FlowKey },
+}
+
+/// Follows TCP connections across packets.
+#[derive(Default)]
+pub(crate) struct TcpReassembler {
+    flows: HashMap<FlowKey, Flow>,
+}
+
+struct Flow {
+    /// Client to server, then server to client.
+    halves: [Half; 2],
+    last_seen: SystemTime,
+}
+
+#[derive(Default)]
+struct Half {
+    /// Sequence number of the next byte to deliver; `None` until the side's
+    /// SYN is seen.
+    next: Option<u32>,
+    /// Segments ahead of `next`, by sequence number.
+    pending: Vec<(u32, Vec<u8>)>,
+    pending_bytes: usize,
+    fin: bool,
+}
+
+impl TcpReassembler {
+    /// Feeds one packet captured at `at`.
+    pub(crate) fn push(&mut self, packet: &[u8], at: SystemTime) -> Vec<StreamEvent> {
+        match parse_ip_packet(packet) {
+            Some(segment) => self.push_segment(&segment, at),
+            None => Vec::new(),
+        }
+    }
+
+    pub(crate) fn push_segment(
+        &mut self,
+        segment: &Segment<'_>,
+        at: SystemTime,
+    ) -> Vec<StreamEvent> {
+        let outbound = FlowKey {
+            client: segment.src,
+            server: segment.dst,
+        };
+        let inbound = FlowKey {
+            client: segment.dst,
+            server: segment.src,
+        };
+        let (flow_key, direction) = if self.flows.contains_key(&outbound) {
+            (outbound, Direction::Outbound)
+        } else if self.flows.contains_key(&inbound) {
+            (inbound, Direction::Inbound)
+        } else if segment.flags & (SYN | ACK) == SYN {
+            // A client opening a connection.
+            let mut flow = Flow {
+                halves: [Half::default(), Half::default()],
+                last_seen: at,
+            };
+            flow.halves[0].next = Some(segment.seq.wrapping_add(1));
+            self.flows.insert(outbound, flow);
+            return Vec::new();
+        } else {
+            return Vec::new();
+        };
+
+        let mut events = Vec::new();
+        let Some(flow) = self.flows.get_mut(&flow_key) else {
+            return events;
+        };
+        flow.last_seen = at;
+        if segment.flags & RST != 0 {
+            self.flows.remove(&flow_key);
+            events.push(StreamEvent::Closed { flow: flow_key });
+            return events;
+        }
+        let half = &mut flow.halves[direction as usize];
+        if segment.flags & SYN != 0 {
+            half.next.get_or_insert(segment.seq.wrapping_add(1));
+            return events;
+        }
+        let Some(next) = half.next else {
+            // Data before the side's SYN was seen.
+            return events;
+        };
+        if !segment.payload.is_empty() {
+            half.accept(next, segment.seq, segment.payload);
+            for chunk in half.drain() {
+                events.push(match chunk {
+                    Ok(data) => StreamEvent::Data {
+                        flow: flow_key,
+                        direction,
+                        data,
+                    },
+                    Err(len) => StreamEvent::Gap {
+                        flow: flow_key,
+                        direction,
+                        len,
+                    },
+                });
+            }
+        }
+        if segment.flags & FIN != 0 {
+            half.fin = true;
+        }
+        if flow.halves.iter().all(|half| half.fin) {
+            self.flows.remove(&flow_key);
+            events.push(StreamEvent::Closed { flow: flow_key });
+        }
+        events
+    }
+
+    /// Ends connections without packets for `idle`, as of `now`.
+    pub(crate) fn expire(&mut self, idle: Duration, now: SystemTime) -> Vec<StreamEvent> {
+        let expired: Vec<FlowKey> = self
+            .flows
+            .iter()
+            .filter(|(_, flow)| now.duration_since(flow.last_seen).unwrap_or_default() >= idle)
+            .map(|(key, _)| *key)
+            .collect();
+        expired
+            .into_iter()
+            .map(|flow| {
+                self.flows.remove(&flow);
+                StreamEvent::Closed { flow }
+            })
+            .collect()
+    }
+
+    /// Ends every connection, as at shutdown.
+    pub(crate) fn close_all(&mut self) -> Vec<StreamEvent> {
+        self.flows
+            .drain()
+            .map(|(flow, _)| StreamEvent::Closed { flow })
+            .collect()
+    }
+}
+
+impl Half {
+    /// Files a segment: bytes before `next` were delivered already.
+    fn accept(&mut self, next: u32, seq: u32, payload: &[u8]) {
+        let ahead = seq.wrapping_sub(next) as i32;
+        let (seq, payload) = if ahead < 0 {
+            let already = ahead.unsigned_abs() as usize;
+            if already >= payload.len() {
+                return;
+            }
+            (next, &payload[already..])
+        } else {
+            (seq, payload)
+        };
+        if self.pending.iter().any(|(s, _)| *s == seq) {
+            return;
+        }
+        self.pending_bytes += payload.len();
+        self.pending.push((seq, payload.to_vec()));
+    }
+
+    /// Takes the bytes now in order: `Ok` data, or `Err(len)` for a gap
+    /// given up on.
+    fn drain(&mut self) -> Vec<Result<Vec<u8>, usize>> {
+        let mut out = Vec::new();
+        loop {
+            let Some(next) = self.next else {
+                return out;
+            };
+            let position = self
+                .pending
+                .iter()
+                .position(|(seq, _)| seq.wrapping_sub(next) as i32 <= 0);
+            let (seq, data) = match position {
+                Some(i) => self.pending.swap_remove(i),
+                None if self.pending_bytes > MAX_PENDING => {
+                    // Skip to the earliest held segment.
+                    let Some(&(seq, _)) = self
+                        .pending
+                        .iter()
+                        .min_by_key(|(seq, _)| seq.wrapping_sub(next))
+                    else {
+                        return out;
+                    };
+                    out.push(Err(seq.wrapping_sub(next) as usize));
+                    self.next = Some(seq);
+                    continue;
+                }
+                None => return out,
+            };
+            self.pending_bytes -= data.len();
+            let already = next.wrapping_sub(seq) as usize;
+            if already < data.len() {
+                self.next = Some(next.wrapping_add((data.len() - already) as u32));
+                out.push(Ok(data[already..].to_vec()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLIENT: &str = "10.0.0.1:40000";
+    const SERVER: &str = "10.0.0.2:443";
+
+    fn segment(from_client: bool, seq: u32, flags: u8, payload: &[u8]) -> Segment<'_> {
+        let (src, dst) = if from_client {
+            (CLIENT, SERVER)
+        } else {
+            (SERVER, CLIENT)
+        };
+        Segment {
+            src: src.parse().unwrap(),
+            dst: dst.parse().unwrap(),
+            seq,
+            flags,
+            payload,
+        }
+    }
+
+    fn flow() -> FlowKey {
+        FlowKey {
+            client: CLIENT.parse().unwrap(),
+            server: SERVER.parse().unwrap(),
+        }
+    }
+
+    fn data(direction: Direction, data: &[u8]) -> StreamEvent {
+        StreamEvent::Data {
+            flow: flow(),
+            direction,
+            data: data.to_vec(),
+        }
+    }
+
+    fn handshake(tcp: &mut TcpReassembler) {
+        let now = SystemTime::UNIX_EPOCH;
+        assert!(
+            tcp.push_segment(&segment(true, 99, SYN, b""), now)
+                .is_empty()
+        );
+        assert!(
+            tcp.push_segment(&segment(false, 499, SYN | ACK, b""), now)
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_parse_ipv4_tcp_packet() {
+        let mut packet = vec![0x45, 0, 0, 0, 0, 0, 0x40, 0, 64, 6, 0, 0];
+        packet.extend_from_slice(&[127, 0, 0, 1, 127, 0, 0, 2]);
+        packet.extend_from_slice(&[0x9c, 0x40, 0, 80]);
+        packet.extend_from_slice(&7u32.to_be_bytes());
+        packet.extend_from_slice(&[0, 0, 0, 0, 0x50, ACK, 0, 0, 0, 0, 0, 0]);
+        packet.extend_from_slice(b"GET");
+        let len = packet.len() as u16;
+        packet[2..4].copy_from_slice(&len.to_be_bytes());
+        let segment = parse_ip_packet(&packet).unwrap();
+        assert_eq!(segment.src, "127.0.0.1:40000".parse().unwrap());
+        assert_eq!(segment.dst, "127.0.0.2:80".parse().unwrap());
+        assert_eq!(segment.seq, 7);
+        assert_eq!(segment.flags, ACK);
+        assert_eq!(segment.payload, b"GET");
+
+        // Not TCP.
+        packet[9] = 17;
+        assert!(parse_ip_packet(&packet).is_none());
+    }
+
+    #[test]
+    fn test_reassembles_in_order_and_drops_duplicates() {
+        let mut tcp = TcpReassembler::default();
+        handshake(&mut tcp);
+        let now = SystemTime::UNIX_EPOCH;
+        let events = tcp.push_segment(&segment(true, 100, ACK, b"hello"), now);
+        assert_eq!(events, [data(Direction::Outbound, b"hello")]);
+        // Seen again (loopback), then overlapping a retransmission.
+        assert!(
+            tcp.push_segment(&segment(true, 100, ACK, b"hello"), now)
+                .is_empty()
+        );
+        let events = tcp.push_segment(&segment(true, 103, ACK, b"lo world"), now);
+        assert_eq!(events, [data(Direction::Outbound, b" world")]);
+        let events = tcp.push_segment(&segment(false, 500, ACK, b"ok"), now);
+        assert_eq!(events, [data(Direction::Inbound, b"ok")]);
+    }
+
+    #[test]
+    fn test_holds_out_of_order_segments() {
+        let mut tcp = TcpReassembler::default();
+        handshake(&mut tcp);
+        let now = SystemTime::UNIX_EPOCH;
+        assert!(
+            tcp.push_segment(&segment(true, 103, ACK, b"def"), now)
+                .is_empty()
+        );
+        let events = tcp.push_segment(&segment(true, 100, ACK, b"abc"), now);
+        assert_eq!(
+            events,
+            [
+                data(Direction::Outbound, b"abc"),
+                data(Direction::Outbound, b"def")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_closes_on_fins_and_ignores_unknown_flows() {
+        let mut tcp = TcpReassembler::default();
+        let now = SystemTime::UNIX_EPOCH;
+        // No SYN seen: ignored.
+        assert!(
+            tcp.push_segment(&segment(true, 1, ACK, b"x"), now)
+                .is_empty()
+        );
+
+        handshake(&mut tcp);
+        assert!(
+            tcp.push_segment(&segment(true, 100, FIN | ACK, b""), now)
+                .is_empty()
+        );
+        let events = tcp.push_segment(&segment(false, 500, FIN | ACK, b""), now);
+        assert_eq!(events, [StreamEvent::Closed { flow: flow() }]);
+        assert!(tcp.flows.is_empty());
+    }
+
+    #[test]
+    fn test_expire_idle_flows() {
+        let mut tcp = TcpReassembler::default();
+        handshake(&mut tcp);
+        let later = SystemTime::UNIX_EPOCH + Duration::from_secs(60);
+        assert!(tcp.expire(Duration::from_secs(120), later).is_empty());
+        assert_eq!(
+            tcp.expire(Duration::from_secs(30), later),
+            [StreamEvent::Closed { flow: flow() }]
+        );
+    }
+}
@@ -0,0 +1,314 @@
+//! Streaming capture of proxied bodies.
+//!
+//! A body is forwarded chunk by chunk while a copy of its first bytes is
+//! kept for the trace, so server-sent events reach the client as they are
+//! produced and large uploads and downloads are never held in memory. The
+//! capture is completed when the body ends, or when its peer goes away.
+
+use std::fs::File;
+use std::future::Future;
+use std::io::Write;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+
+use bytes::Bytes;
+use futures_util::Stream;
+use hyper::body::Body as HttpBody;
+use tokio::time::Sleep;
+use tracing::warn;
+
+use crate::shaping::transfer_time;
+
+/// The captured part of a body: its first `limit` bytes, plus the whole
+/// body in an overflow file when it is longer and a file path is set.
+pub(crate) struct BodyCapture {
+    captured: Vec<u8>,
+    limit: usize,
+    total: usize,
+    overflow_path: Option<PathBuf>,
+    overflow_file: Option<File>,
+}
+
+impl BodyCapture {
+    pub(crate) fn new(limit: usize, overflow_path: Option<PathBuf>) -> Self {
+        Self {
+            captured: Vec::new(),
+            limit,
+            total: 0,
+            overflow_path,
+            overflow_file: None,
+        }
+    }
+
+    /// Records the next chunk of the body.
+    pub(crate) fn push(&mut self, chunk: &[u8]) {
+        if self.total + chunk.len() > self.limit {
+            self.write_overflow(chunk);
+        }
+        let room = self.limit.saturating_sub(self.captured.len());
+        self.captured
+            .extend_from_slice(&chunk[..chunk.len().min(room)]);
+        self.total += chunk.len();
+    }
+
+    fn write_overflow(&mut self, chunk: &[u8]) {
+        let Some(path) = &self.overflow_path else {
+            return;
+        };
+        if self.overflow_file.is_none() {
+            // The file starts with the bytes captured so far.
+            let opened = path
+                .parent()
+                .map_or(Ok(()), std::fs::create_dir_all)
+                .and_then(|()| File::create(path))
+                .and_then(|mut file| file.write_all(&self.captured).map(|()| file));
+            match opened {
+                Ok(file) => self.overflow_file = Some(file),
+                Err(e) => {
+                    warn!("failed to write overflow body {}: {e}", path.display());
+                    self.overflow_path = None;
+                    return;
+                }
+            }
+        }
+        if let Some(file) = &mut self.overflow_file
+            && let Err(e) = file.write_all(chunk)
+        {
+            warn!("failed to write overflow body {}: {e}", path.display());
+            self.overflow_file = None;
+            self.overflow_path = None;
+        }
+    }
+
+    /// Total bytes seen, including those past the limit.
+    #[cfg(test)]
+    pub(crate) fn total(&self) -> usize {
+        self.total
+    }
+
+    /// The captured bytes (`None` for an empty body, or a limit of 0) and
+    /// the overflow file holding the full body, if one was written.
+    pub(crate) fn finish(self) -> (Option<Vec<u8>>, Option<String>) {
+        let file = self
+            .overflow_file
+            .and(self.overflow_path)
+            .map(|path| path.display().to_string());
+        ((!self.captured.is_empty()).then_some(self.captured), file)
+    }
+}
+
+/// Forwards the data of body `B` while recording it in a [`BodyCapture`],
+/// optionally paced to a download rate. `on_end` receives the capture once
+/// the body has ended, failed, or been dropped unfinished.
+pub(crate) struct TeeStream<B> {
+    body: Pin<Box<B>>,
+    capture: Option<BodyCapture>,
+    on_end: Option<Box<dyn FnOnce(BodyCapture) + Send + Sync>>,
+    bytes_per_sec: Option<u64>,
+    /// A chunk held back until its transfer time at `bytes_per_sec` passed.
+    held: Option<(Bytes, Pin<Box<Sleep>>)>,
+}
+
+impl<B> TeeStream<B> {
+    pub(crate) fn new(
+        body: B,
+        capture: BodyCapture,
+        bytes_per_sec: Option<u64>,
+        on_end: impl FnOnce(BodyCapture) + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            body: Box::pin(body),
+            capture: Some(capture),
+            on_end: Some(Box::new(on_end)),
+            bytes_per_sec,
+            held: None,
+        }
+    }
+
+    fn finish(&mut self) {
+        if let (Some(capture), Some(on_end)) = (self.capture.take(), self.on_end.take()) {
+            on_end(capture);
+        }
+    }
+}
+
+impl<B> Stream for TeeStream<B>
+where
+    B: HttpBody<Data = Bytes>,
+{
+    type Item = Result<Bytes, B::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        if let Some((_, delay)) = &mut this.held {
+            ready!(delay.as_mut().poll(cx));
+            return Poll::Ready(this.held.take().map(|(chunk, _)| Ok(chunk)));
+        }
+        loop {
+            match ready!(this.body.as_mut().poll_frame(cx)) {
+                Some(Ok(frame)) => {
+                    // Trailers are not forwarded, as when bodies were buffered.
+                    let Ok(chunk) = frame.into_data() else {
+                        continue;
+                    };
+                    if let Some(capture) = &mut this.capture {
+                        capture.push(&chunk);
+                    }
+                    let pause = transfer_time(chunk.len(), this.bytes_per_sec);
+                    if pause.is_zero() {
+                        return Poll::Ready(Some(Ok(chunk)));
+                    }
+                    let mut delay = Box::pin(tokio::time::sleep(pause));
+                    if delay.as_mut().poll(cx).is_ready() {
+                        return Poll::Ready(Some(Ok(chunk)));
+                    }
+                    this.held = Some((chunk, delay));
+                    return Poll::Pending;
+                }
+                Some(Err(e)) => {
+                    this.finish();
+                    return Poll::Ready(Some(Err(e)));
+                }
+                None => {
+                    this.finish();
+                    return Poll::Ready(None);
+                }
+            }
+        }
+    }
+}
+
+impl<B> Drop for TeeStream<B> {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use futures_util::StreamExt;
+    use http_body_util::StreamBody;
+    use hyper::body::Frame;
+
+    use super::*;
+
+    fn chunked_body(
+        chunks: &[&'static [u8]],
+    ) -> impl HttpBody<Data = Bytes, Error = std::io::Error> {
+        let frames: Vec<Result<Frame<Bytes>, std::io::Error>> = chunks
+            .iter()
+            .map(|chunk| Ok(Frame::data(Bytes::from_static(chunk))))
+            .collect();
+        StreamBody::new(futures_util::stream::iter(frames))
+    }
+
+    #[test]
+    fn test_capture_within_limit() {
+        let mut capture = BodyCapture::new(10, None);
+        capture.push(b"hello");
+        capture.push(b"world");
+        assert_eq!(capture.total(), 10);
+        assert_eq!(capture.finish(), (Some(b"helloworld".to_vec()), None));
+    }
+
+    #[test]
+    fn test_capture_empty_body_is_none() {
+        assert_eq!(BodyCapture::new(10, None).finish(), (None, None));
+    }
+
+    #[test]
+    fn test_capture_zero_limit_is_none() {
+        let mut capture = BodyCapture::new(0, None);
+        capture.push(b"hello");
+        assert_eq!(capture.total(), 5);
+        assert_eq!(capture.finish(), (None, None));
+    }
+
+    #[test]
+    fn test_capture_truncates_without_overflow_dir() {
+        let mut capture = BodyCapture::new(4, None);
+        capture.push(b"abc");
+        capture.push(b"defgh");
+        assert_eq!(capture.total(), 8);
+        assert_eq!(capture.finish(), (Some(b"abcd".to_vec()), None));
+    }
+
+    #[test]
+    fn test_capture_overflow_file_holds_full_body() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bodies").join("span.response.bin");
+        let mut capture = BodyCapture::new(4, Some(path.clone()));
+        capture.push(b"abc");
+        capture.push(b"defgh");
+        capture.push(b"ij");
+        let (body, file) = capture.finish();
+        assert_eq!(body, Some(b"abcd".to_vec()));
+        assert_eq!(file, Some(path.display().to_string()));
+        assert_eq!(std::fs::read(&path).unwrap(), b"abcdefghij");
+    }
+
+    #[test]
+    fn test_capture_no_overflow_file_when_within_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("span.response.bin");
+        let mut capture = BodyCapture::new(4, Some(path.clone()));
+        capture.push(b"abcd");
+        assert_eq!(capture.finish(), (Some(b"abcd".to_vec()), None));
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_tee_forwards_chunks_and_reports_capture() {
+        let seen = Arc::new(Mutex::new(None));
+        let sink = Arc::clone(&seen);
+        let tee = TeeStream::new(
+            chunked_body(&[b"data: 1\n\n", b"data: 2\n\n"]),
+            BodyCapture::new(1024, None),
+            None,
+            move |capture| *sink.lock().unwrap() = Some(capture.finish()),
+        );
+        let chunks: Vec<Bytes> = tee.map(|chunk| chunk.unwrap()).collect().await;
+        assert_eq!(chunks, vec![&b"data: 1\n\n"[..], &b"data: 2\n\n"[..]]);
+        assert_eq!(
+            seen.lock().unwrap().take(),
+            Some((Some(b"data: 1\n\ndata: 2\n\n".to_vec()), None))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_tee_reports_when_dropped_unfinished() {
+        let seen = Arc::new(Mutex::new(None));
+        let sink = Arc::clone(&seen);
+        let mut tee = TeeStream::new(
+            chunked_body(&[b"first", b"second"]),
+            BodyCapture::new(1024, None),
+            None,
+            move |capture| *sink.lock().unwrap() = Some(capture.finish()),
+        );
+        assert_eq!(tee.next().await.unwrap().unwrap(), &b"first"[..]);
+        assert!(seen.lock().unwrap().is_none());
+        drop(tee);
+        assert_eq!(
+            seen.lock().unwrap().take(),
+            Some((Some(b"first".to_vec()), None))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_tee_paces_chunks_to_rate() {
+        let started = std::time::Instant::now();
+        let tee = TeeStream::new(
+            chunked_body(&[&[0u8; 500], &[0u8; 500]]),
+            BodyCapture::new(1024, None),
+            Some(10_000),
+            |_| {},
+        );
+        let chunks: Vec<_> = tee.collect().await;
+        assert_eq!(chunks.len(), 2);
+        // 1000 bytes at 10 KB/s.
+        assert!(started.elapsed() >= std::time::Duration::from_millis(100));
+    }
+}
@@ -0,0 +1,290 @@
+//! Connection phase timing for the proxy's outbound requests.
+//!
+//! [`TimedConnector`] wraps the whole outbound connector (TCP, optional
+//! upstream-proxy tunnel, TLS). Inside it, [`TimedResolver`] and
+//! [`MarkTcp`] note when name resolution and the TCP connection finished in
+//! a task-local clock, so the phases can be told apart without the layers
+//! knowing about each other. The result rides on the connection as a
+//! [`ConnectTiming`], which hyper-util copies into every response's
+//! extensions; only the first response on a connection claims it.
+
+use std::cell::Cell;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use http::Uri;
+use hyper::rt::{Read, ReadBufCursor, Write};
+use hyper_util::client::legacy::connect::dns::{GaiResolver, Name};
+use hyper_util::client::legacy::connect::{Connected, Connection};
+use phantom_core::trace::TimingPhases;
+use tower_service::Service;
+
+tokio::task_local! {
+    static CLOCK: ConnectClock;
+}
+
+/// Phase boundaries noted by the layers inside one [`TimedConnector`] call.
+#[derive(Default)]
+struct ConnectClock {
+    dns: Cell<Option<Duration>>,
+    tcp_done: Cell<Option<Instant>>,
+}
+
+/// Connection phases of a new outbound connection, claimed by the first
+/// response received on it.
+#[derive(Clone, Debug)]
+pub(crate) struct ConnectTiming {
+    phases: TimingPhases,
+    claimed: Arc<AtomicBool>,
+}
+
+impl ConnectTiming {
+    fn new(phases: TimingPhases) -> Self {
+        Self {
+            phases,
+            claimed: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// The connection phases, or `None` if an earlier response on this
+    /// connection already took them.
+    pub(crate) fn claim(&self) -> Option<TimingPhases> {
+        (!self.claimed.swap(true, Ordering::Relaxed)).then_some(self.phases)
+    }
+}
+
+/// DNS resolver that times each lookup.
+#[derive(Clone)]
+pub(crate) struct TimedResolver(GaiResolver);
+
+impl Default for TimedResolver {
+    fn default() -> Self {
+        Self(GaiResolver::new())
+    }
+}
+
+impl Service<Name> for TimedResolver {
+    type Response = <GaiResolver as Service<Name>>::Response;
+    type Error = io::Error;
+    type Future = Pin<Box<dyn Future<Output = io::Result<Self::Response>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.0.poll_ready(cx)
+    }
+
+    fn call(&mut self, name: Name) -> Self::Future {
+        let lookup = self.0.call(name);
+        Box::pin(async move {
+            let started = Instant::now();
+            let addrs = lookup.await?;
+            let _ = CLOCK.try_with(|clock| clock.dns.set(Some(started.elapsed())));
+            Ok(addrs)
+        })
+    }
+}
+
+/// TCP-level connector wrapper that notes when the connection is up, before
+/// any TLS handshake on top of it.
+#[derive(Clone)]
+pub(crate) struct MarkTcp<C>(pub(crate) C);
+
+impl<C> Service<Uri> for MarkTcp<C>
+where
+    C: Service<Uri>,
+    C::Future: Send + 'static,
+{
+    type Response = C::Response;
+    type Error = C::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<C::Response, C::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), C::Error>> {
+        self.0.poll_ready(cx)
+    }
+
+    fn call(&mut self, dst: Uri) -> Self::Future {
+        let connecting = self.0.call(dst);
+        Box::pin(async move {
+            let stream = connecting.await?;
+            let _ = CLOCK.try_with(|clock| clock.tcp_done.set(Some(Instant::now())));
+            Ok(stream)
+        })
+    }
+}
+
+/// Outermost connector: times the connection and attaches the phases to it.
+#[derive(Clone)]
+pub(crate) struct TimedConnector<C>(pub(crate) C);
+
+impl<C> Service<Uri> for TimedConnector<C>
+where
+    C: Service<Uri> + Clone + Send + 'static,
+    C::Response: Send,
+    C::Error: Send,
+    C::Future: Send,
+{
+    type Response = TimedStream<C::Response>;
+    type Error = C::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, C::Error>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), C::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, dst: Uri) -> Self::Future {
+        let mut connector = self.0.clone();
+        let tls = dst.scheme() == Some(&http::uri::Scheme::HTTPS);
+        Box::pin(CLOCK.scope(ConnectClock::default(), async move {
+            let started = Instant::now();
+            futures_util::future::poll_fn(|cx| connector.poll_ready(cx)).await?;
+            let stream = connector.call(dst).await?;
+            let done = Instant::now();
+            let (dns, tcp_done) = CLOCK.with(|clock| (clock.dns.get(), clock.tcp_done.get()));
+            let phases = connect_phases(started, dns, tcp_done, done, tls);
+            Ok(TimedStream {
+                inner: stream,
+                timing: ConnectTiming::new(phases),
+            })
+        }))
+    }
+}
+
+/// Splits the time from `started` to `done` into phases. Without a TCP mark
+/// (an unexpected inner connector) it all counts as `connect`.
+fn connect_phases(
+    started: Instant,
+    dns: Option<Duration>,
+    tcp_done: Option<Instant>,
+    done: Instant,
+    tls: bool,
+) -> TimingPhases {
+    let tcp_done = tcp_done.unwrap_or(done);
+    TimingPhases {
+        dns,
+        connect: Some(
+            tcp_done
+                .duration_since(started)
+                .saturating_sub(dns.unwrap_or_default()),
+        ),
+        tls_handshake: tls.then(|| done.duration_since(tcp_done)),
+        ttfb: None,
+    }
+}
+
+/// A connection opened by [`TimedConnector`], carrying its [`ConnectTiming`].
+pub(crate) struct TimedStream<S> {
+    inner: S,
+    timing: ConnectTiming,
+}
+
+impl<S: Connection> Connection for TimedStream<S> {
+    fn connected(&self) -> Connected {
+        self.inner.connected().extra(self.timing.clone())
+    }
+}
+
+impl<S: Read + Unpin> Read for TimedStream<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: ReadBufCursor<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl<S: Write + Unpin> Write for TimedStream<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write_vectored(cx, bufs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connect_phases_split_dns_tcp_and_tls() {
+        let started = Instant::now();
+        let tcp_done = started + Duration::from_millis(30);
+        let done = started + Duration::from_millis(80);
+        let phases = connect_phases(
+            started,
+            Some(Duration::from_millis(10)),
+            Some(tcp_done),
+            done,
+            true,
+        );
+        assert_eq!(phases.dns, Some(Duration::from_millis(10)));
+        assert_eq!(phases.connect, Some(Duration::from_millis(20)));
+        assert_eq!(phases.tls_handshake, Some(Duration::from_millis(50)));
+        assert_eq!(phases.ttfb, None);
+    }
+
+    #[test]
+    fn test_connect_phases_plain_http_has_no_tls() {
+        let started = Instant::now();
+        let done = started + Duration::from_millis(5);
+        let phases = connect_phases(started, None, Some(done), done, false);
+        assert_eq!(phases.dns, None);
+        assert_eq!(phases.connect, Some(Duration::from_millis(5)));
+        assert_eq!(phases.tls_handshake, None);
+    }
+
+    #[test]
+    fn test_connect_timing_is_claimed_once() {
+        let timing = ConnectTiming::new(TimingPhases {
+            connect: Some(Duration::from_millis(5)),
+            ..TimingPhases::default()
+        });
+        let reused = timing.clone();
+        assert!(timing.claim().is_some());
+        assert_eq!(reused.claim(), None);
+    }
+
+    #[tokio::test]
+    async fn test_timed_connector_records_tcp_connect() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut http = hyper_util::client::legacy::connect::HttpConnector::new_with_resolver(
+            TimedResolver::default(),
+        );
+        http.enforce_http(false);
+        let mut connector = TimedConnector(MarkTcp(http));
+        let uri: Uri = format!("http://{addr}/").parse().unwrap();
+        let stream = connector.call(uri).await.unwrap();
+        let phases = stream.timing.claim().unwrap();
+        // An IP literal needs no lookup.
+        assert_eq!(phases.dns, None);
+        assert!(phases.connect.is_some());
+        assert_eq!(phases.tls_handshake, None);
+    }
+}
@@ -0,0 +1,956 @@
+//! TLS decryption with secrets from an `SSLKEYLOGFILE`.
+//!
+//! A [`TlsSession`] follows the records of one connection. It reads the
+//! randoms, version, cipher suite, SNI and ALPN from the hellos, looks the
+//! connection's secrets up in a [`KeyLog`] by client random and decrypts
+//! application data: TLS 1.2 from the master secret, TLS 1.3 from the
+//! traffic secrets, with the AES-GCM and ChaCha20-Poly1305 suites. CBC
+//! suites, renegotiation and TLS 1.3 key updates are not supported; a
+//! direction stops being decrypted at the first record that doesn't open.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use phantom_core::trace::TlsInfo;
+use ring::{aead, hkdf, hmac};
+
+use crate::http1::Direction;
+
+/// Encrypted bytes held for a connection while its secrets are missing.
+const MAX_HELD: usize = 1024 * 1024;
+
+/// How long after its first encrypted record a connection's secrets may
+/// take to show up in the key log. Connections of other processes never
+/// get theirs.
+const KEY_WAIT: Duration = Duration::from_secs(2);
+
+/// Largest record: 16 KB of plaintext plus expansion.
+const MAX_RECORD: usize = 16384 + 2048;
+
+const CHANGE_CIPHER_SPEC: u8 = 20;
+const ALERT: u8 = 21;
+const HANDSHAKE: u8 = 22;
+const APPLICATION_DATA: u8 = 23;
+
+const CLIENT_HELLO: u8 = 1;
+const SERVER_HELLO: u8 = 2;
+
+const EXT_SERVER_NAME: u16 = 0;
+const EXT_ALPN: u16 = 16;
+const EXT_SUPPORTED_VERSIONS: u16 = 43;
+
+/// A ServerHello with this random is a HelloRetryRequest (RFC 8446 §4.1.3).
+const HELLO_RETRY_RANDOM: [u8; 32] = [
+    0xcf, 0x21, 0xad, 0x74, 0xe5, 0x9a, 0x61, 0x11, 0xbe, 0x1d, 0x8c, 0x02, 0x1e, 0x65, 0xb8, 0x91,
+    0xc2, 0xa2, 0x11, 0x16, 0x7a, 0xbb, 0x8c, 0x5e, 0x07, 0x9e, 0x09, 0xe2, 0xc8, 0xa8, 0x33, 0x9c,
+];
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Key log
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// The secrets logged for one connection.
+#[derive(Debug, Default, Clone, PartialEq)]
+struct Secrets {
+    /// TLS 1.2 `CLIENT_RANDOM`.
+    master: Option<Vec<u8>>,
+    /// TLS 1.3 `CLIENT_TRAFFIC_SECRET_0`.
+    client_traffic: Option<Vec<u8>>,
+    /// TLS 1.3 `SERVER_TRAFFIC_SECRET_0`.
+    server_traffic: Option<Vec<u8>>,
+}
+
+/// An `SSLKEYLOGFILE`, read as it grows.
+pub(crate) struct KeyLog {
+    path: PathBuf,
+    offset: u64,
+    /// The start of a line not yet terminated.
+    partial: String,
+    /// By client random.
+    secrets: HashMap<Vec<u8>, Secrets>,
+}
+
+impl KeyLog {
+    pub(crate) fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            offset: 0,
+            partial: String::new(),
+            secrets: HashMap::new(),
+        }
+    }
+
+    /// Reads the lines appended since the last call.
+    pub(crate) fn refresh(&mut self) {
+        let Ok(mut file) = File::open(&self.path) else {
+            return;
+        };
+        let mut bytes = Vec::new();
+        if file.seek(SeekFrom::Start(self.offset)).is_err() || file.read_to_end(&mut bytes).is_err()
+        {
+            return;
+        }
+        self.offset += bytes.len() as u64;
+        self.partial.push_str(&String::from_utf8_lossy(&bytes));
+        while let Some(end) = self.partial.find('\n') {
+            let line: String = self.partial.drain(..=end).collect();
+            self.add_line(&line);
+        }
+    }
+
+    fn add_line(&mut self, line: &str) {
+        let mut fields = line.split_whitespace();
+        let (Some(label), Some(random), Some(secret)) =
+            (fields.next(), fields.next(), fields.next())
+        else {
+            return;
+        };
+        if !matches!(
+            label,
+            "CLIENT_RANDOM" | "CLIENT_TRAFFIC_SECRET_0" | "SERVER_TRAFFIC_SECRET_0"
+        ) {
+            return;
+        }
+        let (Some(random), Some(secret)) = (decode_hex(random), decode_hex(secret)) else {
+            return;
+        };
+        let secrets = self.secrets.entry(random).or_default();
+        match label {
+            "CLIENT_RANDOM" => secrets.master = Some(secret),
+            "CLIENT_TRAFFIC_SECRET_0" => secrets.client_traffic = Some(secret),
+            _ => secrets.server_traffic = Some(secret),
+        }
+    }
+
+    fn get(&self, client_random: &[u8]) -> Option<&Secrets> {
+        self.secrets.get(client_random)
+    }
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Cipher suites
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Cipher {
+    Aes128,
+    Aes256,
+    ChaCha20,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Suite {
+    name: &'static str,
+    cipher: Cipher,
+    /// SHA-384 for the key schedule, else SHA-256.
+    sha384: bool,
+}
+
+fn suite(id: u16) -> Option<Suite> {
+    use Cipher::*;
+    let (name, cipher, sha384) = match id {
+        0x1301 => ("TLS_AES_128_GCM_SHA256", Aes128, false),
+        0x1302 => ("TLS_AES_256_GCM_SHA384", Aes256, true),
+        0x1303 => ("TLS_CHACHA20_POLY1305_SHA256", ChaCha20, false),
+        0x009c => ("TLS_RSA_WITH_AES_128_GCM_SHA256", Aes128, false),
+        0x009d => ("TLS_RSA_WITH_AES_256_GCM_SHA384", Aes256, true),
+        0x009e => ("TLS_DHE_RSA_WITH_AES_128_GCM_SHA256", Aes128, false),
+        0x009f => ("TLS_DHE_RSA_WITH_AES_256_GCM_SHA384", Aes256, true),
+        0xc02b => ("TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256", Aes128, false),
+        0xc02c => ("TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384", Aes256, true),
+        0xc02f => ("TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256", Aes128, false),
+        0xc030 => ("TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384", Aes256, true),
+        0xcca8 => (
+            "TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256",
+            ChaCha20,
+            false,
+        ),
+        0xcca9 => (
+            "TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256",
+            ChaCha20,
+            false,
+        ),
+        _ => return None,
+    };
+    Some(Suite {
+        name,
+        cipher,
+        sha384,
+    })
+}
+
+impl Suite {
+    fn algorithm(&self) -> &'static aead::Algorithm {
+        match self.cipher {
+            Cipher::Aes128 => &aead::AES_128_GCM,
+            Cipher::Aes256 => &aead::AES_256_GCM,
+            Cipher::ChaCha20 => &aead::CHACHA20_POLY1305,
+        }
+    }
+
+    /// TLS 1.3 record keys from a traffic secret (RFC 8446 §7.3).
+    fn tls13_keys(&self, secret: &[u8]) -> Option<RecordKeys> {
+        let hkdf = if self.sha384 {
+            hkdf::HKDF_SHA384
+        } else {
+            hkdf::HKDF_SHA256
+        };
+        let key = expand_label(hkdf, secret, b"key", self.algorithm().key_len())?;
+        let iv = expand_label(hkdf, secret, b"iv", 12)?;
+        RecordKeys::new(self.algorithm(), &key, iv, Framing::Tls13)
+    }
+
+    /// TLS 1.2 client and server record keys from the master secret
+    /// (RFC 5246 §6.3, RFC 5288, RFC 7905).
+    fn tls12_keys(
+        &self,
+        master: &[u8],
+        client_random: &[u8],
+        server_random: &[u8],
+    ) -> Option<[RecordKeys; 2]> {
+        let hmac = if self.sha384 {
+            hmac::HMAC_SHA384
+        } else {
+            hmac::HMAC_SHA256
+        };
+        let (iv_len, framing) = match self.cipher {
+            Cipher::ChaCha20 => (12, Framing::Tls12XorNonce),
+            _ => (4, Framing::Tls12ExplicitNonce),
+        };
+        let key_len = self.algorithm().key_len();
+        let seed = [server_random, client_random].concat();
+        let block = prf(
+            hmac,
+            master,
+            b"key expansion",
+            &seed,
+            2 * (key_len + iv_len),
+        );
+        let (client_key, rest) = block.split_at(key_len);
+        let (server_key, rest) = rest.split_at(key_len);
+        let (client_iv, server_iv) = rest.split_at(iv_len);
+        Some([
+            RecordKeys::new(self.algorithm(), client_key, client_iv.to_vec(), framing)?,
+            RecordKeys::new(self.algorithm(), server_key, server_iv.to_vec(), framing)?,
+        ])
+    }
+}
+
+/// Output length for ring's HKDF.
+struct OutputLen(usize);
+
+impl hkdf::KeyType for OutputLen {
+    fn len(&self) -> usize {
+        self.0
+    }
+}
+
+/// HKDF-Expand-Label with an empty context (RFC 8446 §7.1).
+fn expand_label(
+    algorithm: hkdf::Algorithm,
+    secret: &[u8],
+    label: &[u8],
+    len: usize,
+) -> Option<Vec<u8>> {
+    let label = [b"tls13 ", label].concat();
+    let len_bytes = (len as u16).to_be_bytes();
+    let label_len = [label.len() as u8];
+    let info: [&[u8]; 4] = [&len_bytes, &label_len, &label, &[0]];
+    let prk = hkdf::Prk::new_less_safe(algorithm, secret);
+    let okm = prk.expand(&info, OutputLen(len)).ok()?;
+    let mut out = vec![0; len];
+    okm.fill(&mut out).ok()?;
+    Some(out)
+}
+
+/// The TLS 1.2 PRF, P_hash over `label` and `seed` (RFC 5246 §5).
+fn prf(
+    algorithm: hmac::Algorithm,
+    secret: &[u8],
+    label: &[u8],
+    seed: &[u8],
+    len: usize,
+) -> Vec<u8> {
+    let key = hmac::Key::new(algorithm, secret);
+    let label_seed = [label, seed].concat();
+    let mut a = hmac::sign(&key, &label_seed);
+    let mut out = Vec::with_capacity(len);
+    while out.len() < len {
+        let mut ctx = hmac::Context::with_key(&key);
+        ctx.update(a.as_ref());
+        ctx.update(&label_seed);
+        out.extend_from_slice(ctx.sign().as_ref());
+        a = hmac::sign(&key, a.as_ref());
+    }
+    out.truncate(len);
+    out
+}
+
+/// How records carry their nonce and authenticate their header.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Framing {
+    /// IV XOR sequence number; the record header is the AAD.
+    Tls13,
+    /// AES-GCM: a 4-byte salt plus the 8 bytes leading each record.
+    Tls12ExplicitNonce,
+    /// ChaCha20-Poly1305: IV XOR sequence number.
+    Tls12XorNonce,
+}
+
+/// One direction's record protection.
+struct RecordKeys {
+    key: aead::LessSafeKey,
+    iv: Vec<u8>,
+    framing: Framing,
+}
+
+impl RecordKeys {
+    fn new(
+        algorithm: &'static aead::Algorithm,
+        key: &[u8],
+        iv: Vec<u8>,
+        framing: Framing,
+    ) -> Option<Self> {
+        let key = aead::UnboundKey::new(algorithm, key).ok()?;
+        Some(Self {
+            key: aead::LessSafeKey::new(key),
+            iv,
+            framing,
+        })
+    }
+
+    /// Decrypts a whole record (header included) with sequence number
+    /// `seq`. TLS 1.3 plaintext still ends with its inner content type.
+    fn open(&self, seq: u64, record: &[u8]) -> Option<Vec<u8>> {
+        let (header, payload) = record.split_at(5);
+        let tag_len = self.key.algorithm().tag_len();
+        let (nonce, mut ciphertext, aad) = match self.framing {
+            Framing::Tls13 => (self.xor_nonce(seq), payload.to_vec(), header.to_vec()),
+            Framing::Tls12ExplicitNonce => {
+                let explicit = payload.get(..8)?;
+                let ciphertext = payload[8..].to_vec();
+                let nonce = [&self.iv[..], explicit].concat();
+                let aad = tls12_aad(seq, header, ciphertext.len().checked_sub(tag_len)?);
+                (nonce, ciphertext, aad)
+            }
+            Framing::Tls12XorNonce => {
+                let aad = tls12_aad(seq, header, payload.len().checked_sub(tag_len)?);
+                (self.xor_nonce(seq), payload.to_vec(), aad)
+            }
+        };
+        let nonce = aead::Nonce::try_assume_unique_for_key(&nonce).ok()?;
+        let plaintext = self
+            .key
+            .open_in_place(nonce, aead::Aad::from(aad), &mut ciphertext)
+            .ok()?;
+        Some(plaintext.to_vec())
+    }
+
+    fn xor_nonce(&self, seq: u64) -> Vec<u8> {
+        let mut nonce = self.iv.clone();
+        for (byte, seq_byte) in nonce[4..].iter_mut().zip(seq.to_be_bytes()) {
+            *byte ^= seq_byte;
+        }
+        nonce
+    }
+}
+
+/// Sequence number, type, version and plaintext length.
+fn tls12_aad(seq: u64, header: &[u8], plaintext_len: usize) -> Vec<u8> {
+    let mut aad = seq.to_be_bytes().to_vec();
+    aad.extend_from_slice(&header[..3]);
+    aad.extend_from_slice(&(plaintext_len as u16).to_be_bytes());
+    aad
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Sessions
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// One TLS connection being decrypted.
+#[derive(Default)]
+pub(crate) struct TlsSession {
+    /// Bytes not yet making up a whole record, per direction.
+    buffers: [Vec<u8>; 2],
+    client_random: Option<Vec<u8>>,
+    server_random: Option<Vec<u8>>,
+    suite: Option<Suite>,
+    tls13: bool,
+    info: TlsInfo,
+    halves: [Half; 2],
+    /// Encrypted records waiting for the secrets, in arrival order.
+    held: Vec<(Direction, Vec<u8>)>,
+    held_bytes: usize,
+    held_since: Option<SystemTime>,
+    /// Not TLS, an unsupported suite, or no secrets: nothing to decrypt.
+    ignored: bool,
+}
+
+#[derive(Default)]
+struct Half {
+    keys: Option<RecordKeys>,
+    /// TLS 1.2: past ChangeCipherSpec. TLS 1.3: past the handshake, when
+    /// the traffic keys opened a record.
+    protected: bool,
+    seq: u64,
+    broken: bool,
+}
+
+impl TlsSession {
+    /// Version, cipher suite, SNI and ALPN, as far as the hellos told.
+    pub(crate) fn info(&self) -> &TlsInfo {
+        &self.info
+    }
+
+    /// Feeds bytes of one direction captured at `at`, returning the
+    /// application data they decrypted to, by direction. Records held for
+    /// missing secrets come out with the first batch after they appear.
+    pub(crate) fn push(
+        &mut self,
+        direction: Direction,
+        data: &[u8],
+        keylog: &mut KeyLog,
+        at: SystemTime,
+    ) -> Vec<(Direction, Vec<u8>)> {
+        let mut out = Vec::new();
+        if self.ignored {
+            return out;
+        }
+        self.buffers[direction as usize].extend_from_slice(data);
+        loop {
+            let buffer = &mut self.buffers[direction as usize];
+            let Some(header) = buffer.get(..5) else {
+                break;
+            };
+            let len = usize::from(u16::from_be_bytes([header[3], header[4]]));
+            if !(CHANGE_CIPHER_SPEC..=APPLICATION_DATA).contains(&header[0])
+                || header[1] != 3
+                || len > MAX_RECORD
+            {
+                self.ignore();
+                return Vec::new();
+            }
+            if buffer.len() < 5 + len {
+                break;
+            }
+            let record: Vec<u8> = buffer.drain(..5 + len).collect();
+            self.record(direction, record, keylog, at, &mut out);
+            if self.ignored {
+                return Vec::new();
+            }
+        }
+        out
+    }
+
+    /// Gives up on the connection, as when some of its bytes were lost.
+    pub(crate) fn ignore(&mut self) {
+        self.ignored = true;
+        self.buffers = Default::default();
+        self.held = Vec::new();
+    }
+
+    fn record(
+        &mut self,
+        direction: Direction,
+        record: Vec<u8>,
+        keylog: &mut KeyLog,
+        at: SystemTime,
+        out: &mut Vec<(Direction, Vec<u8>)>,
+    ) {
+        let content_type = record[0];
+        let protected = self.halves[direction as usize].protected;
+        match content_type {
+            HANDSHAKE if !protected => self.read_hello(&record[5..]),
+            CHANGE_CIPHER_SPEC => {
+                // TLS 1.3 sends it only for middlebox compatibility.
+                if !self.tls13 {
+                    self.halves[direction as usize].protected = true;
+                }
+            }
+            ALERT if !protected => {}
+            _ if self.suite.is_none() => {}
+            _ => self.encrypted(direction, record, keylog, at, out),
+        }
+    }
+
+    fn encrypted(
+        &mut self,
+        direction: Direction,
+        record: Vec<u8>,
+        keylog: &mut KeyLog,
+        at: SystemTime,
+        out: &mut Vec<(Direction, Vec<u8>)>,
+    ) {
+        if self.halves[0].keys.is_none() {
+            if !self.derive_keys(keylog) {
+                let since = *self.held_since.get_or_insert(at);
+                self.held_bytes += record.len();
+                self.held.push((direction, record));
+                if self.held_bytes > MAX_HELD
+                    || at.duration_since(since).unwrap_or_default() > KEY_WAIT
+                {
+                    self.ignore();
+                }
+                return;
+            }
+            for (held_direction, held) in std::mem::take(&mut self.held) {
+                self.open(held_direction, &held, out);
+            }
+            self.held_bytes = 0;
+        }
+        self.open(direction, &record, out);
+    }
+
+    /// Builds both directions' keys once the key log has the secrets.
+    fn derive_keys(&mut self, keylog: &mut KeyLog) -> bool {
+        let (Some(client_random), Some(suite)) = (self.client_random.as_deref(), self.suite) else {
+            return false;
+        };
+        let usable = |secrets: &Secrets| {
+            if self.tls13 {
+                secrets.client_traffic.is_some() && secrets.server_traffic.is_some()
+            } else {
+                secrets.master.is_some()
+            }
+        };
+        if !keylog.get(client_random).is_some_and(usable) {
+            keylog.refresh();
+        }
+        let Some(secrets) = keylog.get(client_random).filter(|s| usable(s)) else {
+            return false;
+        };
+        let keys = if self.tls13 {
+            secrets
+                .client_traffic
+                .as_deref()
+                .and_then(|secret| suite.tls13_keys(secret))
+                .zip(
+                    secrets
+                        .server_traffic
+                        .as_deref()
+                        .and_then(|secret| suite.tls13_keys(secret)),
+                )
+                .map(|(client, server)| [client, server])
+        } else {
+            match (secrets.master.as_deref(), self.server_random.as_deref()) {
+                (Some(master), Some(server_random)) => {
+                    suite.tls12_keys(master, client_random, server_random)
+                }
+                _ => None,
+            }
+        };
+        let Some([client, server]) = keys else {
+            return false;
+        };
+        self.halves[0].keys = Some(client);
+        self.halves[1].keys = Some(server);
+        true
+    }
+
+    fn open(&mut self, direction: Direction, record: &[u8], out: &mut Vec<(Direction, Vec<u8>)>) {
+        let tls13 = self.tls13;
+        let half = &mut self.halves[direction as usize];
+        let Some(keys) = half.keys.as_ref().filter(|_| !half.broken) else {
+            return;
+        };
+        if tls13 && !half.protected {
+            // Handshake records are under handshake keys; the first record
+            // the traffic keys open ends the handshake.
+            let Some(plaintext) = keys.open(0, record) else {
+                return;
+            };
+            half.protected = true;
+            half.seq = 1;
+            deliver_tls13(direction, plaintext, out);
+            return;
+        }
+        match keys.open(half.seq, record) {
+            Some(plaintext) => {
+                half.seq += 1;
+                if tls13 {
+                    deliver_tls13(direction, plaintext, out);
+                } else if record[0] == APPLICATION_DATA {
+                    out.push((direction, plaintext));
+                }
+            }
+            None => half.broken = true,
+        }
+    }
+
+    /// Reads a ClientHello or ServerHello at the start of a handshake
+    /// record. Later handshake messages, and hellos split across records
+    /// before the random, are skipped.
+    fn read_hello(&mut self, message: &[u8]) {
+        let Some(&kind) = message.first() else {
+            return;
+        };
+        let mut body = Cursor(message.get(4..).unwrap_or_default());
+        let Some(version) = body.u16() else {
+            return;
+        };
+        let Some(random) = body.take(32).map(<[u8]>::to_vec) else {
+            return;
+        };
+        match kind {
+            CLIENT_HELLO => {
+                self.client_random = Some(random);
+                let _ = body
+                    .vec8()
+                    .and_then(|_| body.vec16())
+                    .and_then(|_| body.vec8());
+                for (ext, mut data) in body.extensions() {
+                    if ext == EXT_SERVER_NAME {
+                        // server_name_list, name_type, host_name
+                        let name = data.vec16().and_then(|mut list| {
+                            list.u8()?;
+                            list.vec16()
+                        });
+                        if let Some(name) = name {
+                            self.info.server_name =
+                                Some(String::from_utf8_lossy(name.0).into_owned());
+                        }
+                    }
+                }
+            }
+            SERVER_HELLO if random != HELLO_RETRY_RANDOM => {
+                self.server_random = Some(random);
+                let Some(suite_id) = body.vec8().and_then(|_| body.u16()) else {
+                    return;
+                };
+                body.u8();
+                let mut selected_version = version;
+                for (ext, mut data) in body.extensions() {
+                    match ext {
+                        EXT_SUPPORTED_VERSIONS => {
+                            selected_version = data.u16().unwrap_or(version);
+                        }
+                        EXT_ALPN => {
+                            // protocol_name_list holding the one selected
+                            if let Some(protocol) = data.vec16().and_then(|mut list| list.vec8()) {
+                                self.info.alpn =
+                                    Some(String::from_utf8_lossy(protocol.0).into_owned());
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                self.tls13 = selected_version == 0x0304;
+                self.info.version = Some(
+                    match selected_version {
+                        0x0304 => "TLSv1.3",
+                        0x0303 => "TLSv1.2",
+                        0x0302 => "TLSv1.1",
+                        _ => "TLSv1.0",
+                    }
+                    .to_string(),
+                );
+                self.suite = suite(suite_id);
+                match self.suite {
+                    Some(suite) => self.info.cipher = Some(suite.name.to_string()),
+                    None => {
+                        self.info.cipher = Some(format!("0x{suite_id:04x}"));
+                        self.ignore();
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Strips a TLS 1.3 plaintext's padding and passes on application data.
+fn deliver_tls13(
+    direction: Direction,
+    mut plaintext: Vec<u8>,
+    out: &mut Vec<(Direction, Vec<u8>)>,
+) {
+    while plaintext.last() == Some(&0) {
+        plaintext.pop();
+    }
+    if plaintext.pop() == Some(APPLICATION_DATA) {
+        out.push((direction, plaintext));
+    }
+}
+
+/// Reads big-endian fields off the front of a slice.
+struct Cursor<'a>(&'a [u8]);
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.0.len() < n {
+            return None;
+        }
+        let (head, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Some(head)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|b| b[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.take(2).map(|b| u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    /// A vector with a one-byte length.
+    fn vec8(&mut self) -> Option<Cursor<'a>> {
+        let len = usize::from(self.u8()?);
+        self.take(len).map(Cursor)
+    }
+
+    /// A vector with a two-byte length.
+    fn vec16(&mut self) -> Option<Cursor<'a>> {
+        let len = usize::from(self.u16()?);
+        self.take(len).map(Cursor)
+    }
+
+    /// The extensions block ending a hello, as (type, data).
+    fn extensions(&mut self) -> Vec<(u16, Cursor<'a>)> {
+        let mut extensions = Vec::new();
+        let Some(mut block) = self.vec16() else {
+            return extensions;
+        };
+        while let (Some(ext), Some(data)) = (block.u16(), block.vec16()) {
+            extensions.push((ext, data));
+        }
+        extensions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    const REQUEST: &[u8] = b"GET /a HTTP/1.1\r\nHost: example.com\r\n\r\n";
+    const RESPONSE: &[u8] = b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nhi";
+
+    // Records sealed with Python's `cryptography` from the secrets below.
+    const TLS13_CLIENT: &str = "17030300374854e266986c334b31eacf3541d4a4eb59280552dbd4d95e8916ce78d6ef96a6c0f5c0228a827ba75c5a52b4605b1825e8cf122d5d38e9";
+    const TLS13_TICKET: &str = "170303001564a1171288d00f0f99e7a5671b77524be873b72a49";
+    const TLS13_SERVER: &str = "170303003c5a55e0008e45d14bd47f5031f3f7626de66928f2501adc9c874cc755d741e71e59b7f26560df0cb745b176b0535a711feb5eb95e963b749c684cddd2";
+    const TLS12_CLIENT_FINISHED: &str = "16030300280000000000000000cc639898f86a29ca9e91b46fe5ea53e0c9eb2f4cfd58a808af2e6a43bd32e1cb";
+    const TLS12_CLIENT: &str = "170303003e000000000000000123aee63de4eadedf78bdb36eb74058b977f68b761f06b89db3137b8899b43cfbe43e3f6712f35815dbf17d7a8007d7c9337e4a4b9dd7";
+    const TLS12_SERVER_FINISHED: &str = "16030300280000000000000000e0c7374ddf6ef80cfdb370a5bafaacc6c33f4246e944353c3eb97731825b6089";
+    const TLS12_SERVER: &str = "17030300400000000000000001449b5b34b7ae2e0ffcdadcc81ede4191419a1ee3f67ef5633c56ded98210c2b84e5fe596cfd99a2a66c397fe6f6c0acbe98b0a3c4489c15a";
+
+    fn hex(s: &str) -> Vec<u8> {
+        decode_hex(s).unwrap()
+    }
+
+    fn record(content_type: u8, payload: &[u8]) -> Vec<u8> {
+        let mut record = vec![content_type, 3, 3];
+        record.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+        record.extend_from_slice(payload);
+        record
+    }
+
+    fn handshake(message_type: u8, body: &[u8]) -> Vec<u8> {
+        let mut message = vec![message_type, 0];
+        message.extend_from_slice(&(body.len() as u16).to_be_bytes());
+        message.extend_from_slice(body);
+        record(HANDSHAKE, &message)
+    }
+
+    fn with_extensions(mut body: Vec<u8>, extensions: &[(u16, Vec<u8>)]) -> Vec<u8> {
+        let mut block = Vec::new();
+        for (ext, data) in extensions {
+            block.extend_from_slice(&ext.to_be_bytes());
+            block.extend_from_slice(&(data.len() as u16).to_be_bytes());
+            block.extend_from_slice(data);
+        }
+        body.extend_from_slice(&(block.len() as u16).to_be_bytes());
+        body.extend_from_slice(&block);
+        body
+    }
+
+    /// Client random 0x01…, SNI `example.com`.
+    fn client_hello() -> Vec<u8> {
+        let mut body = vec![3, 3];
+        body.extend_from_slice(&[1; 32]);
+        body.extend_from_slice(&[0, 0, 2, 0x13, 0x01, 1, 0]);
+        let mut sni = vec![0, 14, 0, 0, 11];
+        sni.extend_from_slice(b"example.com");
+        handshake(
+            CLIENT_HELLO,
+            &with_extensions(body, &[(EXT_SERVER_NAME, sni)]),
+        )
+    }
+
+    /// Server random 0x02….
+    fn server_hello(suite: u16, tls13: bool) -> Vec<u8> {
+        let mut body = vec![3, 3];
+        body.extend_from_slice(&[2; 32]);
+        body.push(0);
+        body.extend_from_slice(&suite.to_be_bytes());
+        body.push(0);
+        let extensions = if tls13 {
+            vec![(EXT_SUPPORTED_VERSIONS, vec![3, 4])]
+        } else {
+            vec![(
+                EXT_ALPN,
+                vec![0, 9, 8, b'h', b't', b't', b'p', b'/', b'1', b'.', b'1'],
+            )]
+        };
+        handshake(SERVER_HELLO, &with_extensions(body, &extensions))
+    }
+
+    fn keylog_file(text: &str) -> (tempfile::NamedTempFile, KeyLog) {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(text.as_bytes()).unwrap();
+        let keylog = KeyLog::new(file.path().to_path_buf());
+        (file, keylog)
+    }
+
+    #[test]
+    fn test_expand_label_rfc8448() {
+        // Server handshake traffic keys from RFC 8448 §3.
+        let secret = hex("b67b7d690cc16c4e75e54213cb2d37b4e9c912bcded9105d42befd59d391ad38");
+        let key = expand_label(hkdf::HKDF_SHA256, &secret, b"key", 16).unwrap();
+        let iv = expand_label(hkdf::HKDF_SHA256, &secret, b"iv", 12).unwrap();
+        assert_eq!(key, hex("3fce516009c21727d0f2e4e86ee403bc"));
+        assert_eq!(iv, hex("5d313eb2671276ee13000b30"));
+    }
+
+    #[test]
+    fn test_keylog_reads_appended_lines() {
+        let random = "01".repeat(32);
+        let (mut file, mut keylog) = keylog_file(&format!(
+            "# comment\nCLIENT_HANDSHAKE_TRAFFIC_SECRET {random} {}\nCLIENT_RANDOM {random} ab",
+            "00".repeat(32)
+        ));
+        keylog.refresh();
+        assert!(keylog.get(&[1; 32]).is_none());
+
+        // The line is completed by a later write.
+        file.write_all(b"cd\n").unwrap();
+        keylog.refresh();
+        assert_eq!(keylog.get(&[1; 32]).unwrap().master, Some(vec![0xab, 0xcd]));
+    }
+
+    #[test]
+    fn test_decrypts_tls13() {
+        let random = "01".repeat(32);
+        let (_file, mut keylog) = keylog_file(&format!(
+            "CLIENT_TRAFFIC_SECRET_0 {random} {}\nSERVER_TRAFFIC_SECRET_0 {random} {}\n",
+            "11".repeat(32),
+            "22".repeat(32)
+        ));
+        let mut session = TlsSession::default();
+        let now = SystemTime::UNIX_EPOCH;
+        let mut push = |direction, data: &[u8]| session.push(direction, data, &mut keylog, now);
+        assert!(push(Direction::Outbound, &client_hello()).is_empty());
+        assert!(push(Direction::Inbound, &server_hello(0x1301, true)).is_empty());
+        // Handshake records under keys we don't have are passed over.
+        assert!(push(Direction::Inbound, &record(APPLICATION_DATA, &[0x55; 40])).is_empty());
+
+        let out = push(Direction::Outbound, &hex(TLS13_CLIENT));
+        assert_eq!(out, [(Direction::Outbound, REQUEST.to_vec())]);
+
+        // A session ticket, then the response, split mid-record.
+        let inbound = [hex(TLS13_TICKET), hex(TLS13_SERVER)].concat();
+        let (first, second) = inbound.split_at(30);
+        assert!(push(Direction::Inbound, first).is_empty());
+        let out = push(Direction::Inbound, second);
+        assert_eq!(out, [(Direction::Inbound, RESPONSE.to_vec())]);
+
+        let info = session.info();
+        assert_eq!(info.version.as_deref(), Some("TLSv1.3"));
+        assert_eq!(info.cipher.as_deref(), Some("TLS_AES_128_GCM_SHA256"));
+        assert_eq!(info.server_name.as_deref(), Some("example.com"));
+    }
+
+    #[test]
+    fn test_decrypts_tls12_once_secrets_are_logged() {
+        let (mut file, mut keylog) = keylog_file("");
+        let mut session = TlsSession::default();
+        let now = SystemTime::UNIX_EPOCH;
+        let ccs = record(CHANGE_CIPHER_SPEC, &[1]);
+        let outbound = [
+            client_hello(),
+            ccs.clone(),
+            hex(TLS12_CLIENT_FINISHED),
+            hex(TLS12_CLIENT),
+        ]
+        .concat();
+        assert!(
+            session
+                .push(
+                    Direction::Inbound,
+                    &server_hello(0xc02f, false),
+                    &mut keylog,
+                    now
+                )
+                .is_empty()
+        );
+        // Held until the secret shows up.
+        assert!(
+            session
+                .push(Direction::Outbound, &outbound, &mut keylog, now)
+                .is_empty()
+        );
+        writeln!(
+            file,
+            "CLIENT_RANDOM {} {}",
+            "01".repeat(32),
+            "33".repeat(48)
+        )
+        .unwrap();
+
+        let inbound = [ccs, hex(TLS12_SERVER_FINISHED), hex(TLS12_SERVER)].concat();
+        let out = session.push(Direction::Inbound, &inbound, &mut keylog, now);
+        assert_eq!(
+            out,
+            [
+                (Direction::Outbound, REQUEST.to_vec()),
+                (Direction::Inbound, RESPONSE.to_vec()),
+            ]
+        );
+        let info = session.info();
+        assert_eq!(info.version.as_deref(), Some("TLSv1.2"));
+        assert_eq!(
+            info.cipher.as_deref(),
+            Some("TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256")
+        );
+        assert_eq!(info.alpn.as_deref(), Some("http/1.1"));
+    }
+
+    #[test]
+    fn test_ignores_plaintext_and_connections_without_secrets() {
+        let (_file, mut keylog) = keylog_file("");
+        let now = SystemTime::UNIX_EPOCH;
+
+        let mut plain = TlsSession::default();
+        plain.push(Direction::Outbound, REQUEST, &mut keylog, now);
+        assert!(plain.ignored);
+
+        let mut foreign = TlsSession::default();
+        foreign.push(Direction::Outbound, &client_hello(), &mut keylog, now);
+        foreign.push(
+            Direction::Inbound,
+            &server_hello(0x1301, true),
+            &mut keylog,
+            now,
+        );
+        foreign.push(Direction::Outbound, &hex(TLS13_CLIENT), &mut keylog, now);
+        assert!(!foreign.ignored);
+        let later = now + KEY_WAIT * 2;
+        foreign.push(Direction::Outbound, &hex(TLS13_CLIENT), &mut keylog, later);
+        assert!(foreign.ignored);
+    }
+}
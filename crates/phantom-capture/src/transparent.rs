@@ -0,0 +1,283 @@
+//! Transparent proxy mode (Linux).
+//!
+//! Accepts connections that iptables `REDIRECT`ed to phantom, recovers where
+//! each was headed with `SO_ORIGINAL_DST`, and hands it to phantom's own
+//! proxy listener as an HTTP `CONNECT`, like the SOCKS front end does. The
+//! target needs no proxy settings at all. For TLS the tunnel is opened to the
+//! ClientHello's SNI name, so the MITM certificate matches the host the
+//! client asked for.
+
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::os::fd::AsRawFd;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, warn};
+
+use crate::allowlist::ClientAllowlist;
+use crate::socks::connect_via_proxy;
+
+/// TLS record content type of a handshake message.
+const TLS_HANDSHAKE: u8 = 0x16;
+/// Handshake message type of a ClientHello.
+const TLS_CLIENT_HELLO: u8 = 0x01;
+/// Extension type of server_name (RFC 6066).
+const TLS_EXT_SERVER_NAME: u16 = 0x0000;
+/// Most of a ClientHello inspected for its SNI name.
+const MAX_CLIENT_HELLO: usize = 16 * 1024;
+/// How long to wait for the client's first bytes before tunnelling by IP.
+const FIRST_BYTES_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Accepts redirected connections on `listener` and tunnels each one to its
+/// original destination through the HTTP proxy on `proxy_addr`. Clients
+/// outside `allowlist` are disconnected at once.
+pub(crate) async fn serve(
+    listener: TcpListener,
+    proxy_addr: SocketAddr,
+    allowlist: Arc<ClientAllowlist>,
+) {
+    loop {
+        let (client, peer) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                warn!("transparent accept failed: {e}");
+                continue;
+            }
+        };
+        if !allowlist.allows(peer.ip()) {
+            debug!("transparent client {peer} not in allowlist, disconnecting");
+            continue;
+        }
+        tokio::spawn(async move {
+            if let Err(e) = handle(client, proxy_addr).await {
+                debug!("transparent connection from {peer}: {e}");
+            }
+        });
+    }
+}
+
+async fn handle(mut client: TcpStream, proxy_addr: SocketAddr) -> io::Result<()> {
+    let dst = original_dst(&client)?;
+    // A connection made straight to the listener (not redirected) reports
+    // the listener itself; tunnelling it would loop.
+    if dst == client.local_addr()? {
+        return Err(io::Error::other("connection was not redirected"));
+    }
+    let host = match peek_sni(&client).await {
+        Some(name) => name,
+        None => match dst.ip() {
+            IpAddr::V4(ip) => ip.to_string(),
+            IpAddr::V6(ip) => format!("[{ip}]"),
+        },
+    };
+    let mut upstream = connect_via_proxy(proxy_addr, &format!("{host}:{}", dst.port())).await?;
+    tokio::io::copy_bidirectional(&mut client, &mut upstream).await?;
+    Ok(())
+}
+
+/// The pre-NAT destination of a redirected connection.
+fn original_dst(stream: &TcpStream) -> io::Result<SocketAddr> {
+    let (level, name) = match stream.local_addr()? {
+        SocketAddr::V4(_) => (libc::SOL_IP, libc::SO_ORIGINAL_DST),
+        SocketAddr::V6(_) => (libc::SOL_IPV6, libc::IP6T_SO_ORIGINAL_DST),
+    };
+    // SAFETY: an all-zero sockaddr_storage is a valid (empty) address.
+    let mut addr: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
+    let mut len = std::mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+    // SAFETY: addr is a writable sockaddr_storage of `len` bytes and the fd
+    // belongs to `stream` for the duration of the call.
+    let rc = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            level,
+            name,
+            (&mut addr as *mut libc::sockaddr_storage).cast(),
+            &mut len,
+        )
+    };
+    if rc != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    match addr.ss_family as libc::c_int {
+        libc::AF_INET => {
+            // SAFETY: the kernel filled in a sockaddr_in for AF_INET.
+            let sin =
+                unsafe { &*(&addr as *const libc::sockaddr_storage).cast::<libc::sockaddr_in>() };
+            Ok(SocketAddr::new(
+                Ipv4Addr::from(u32::from_be(sin.sin_addr.s_addr)).into(),
+                u16::from_be(sin.sin_port),
+            ))
+        }
+        libc::AF_INET6 => {
+            // SAFETY: the kernel filled in a sockaddr_in6 for AF_INET6.
+            let sin6 =
+                unsafe { &*(&addr as *const libc::sockaddr_storage).cast::<libc::sockaddr_in6>() };
+            Ok(SocketAddr::new(
+                Ipv6Addr::from(sin6.sin6_addr.s6_addr).into(),
+                u16::from_be(sin6.sin6_port),
+            ))
+        }
+        family => Err(io::Error::other(format!(
+            "unexpected original destination family {family}"
+        ))),
+    }
+}
+
+/// Peeks at the client's first bytes and returns the SNI name if they are a
+/// TLS ClientHello. Nothing is consumed.
+async fn peek_sni(stream: &TcpStream) -> Option<String> {
+    let deadline = tokio::time::Instant::now() + FIRST_BYTES_TIMEOUT;
+    let mut buf = vec![0u8; MAX_CLIENT_HELLO];
+    loop {
+        let n = tokio::time::timeout_at(deadline, stream.peek(&mut buf))
+            .await
+            .ok()?
+            .ok()?;
+        if n == 0 || buf[0] != TLS_HANDSHAKE {
+            return None;
+        }
+        let record_len = match buf.get(3..5) {
+            Some(len) => 5 + u16::from_be_bytes([len[0], len[1]]) as usize,
+            None => 5,
+        };
+        if n >= record_len.min(buf.len()) {
+            return client_hello_sni(&buf[..n]);
+        }
+        // peek() returns at once while the rest of the record is in flight.
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+/// Splits the first `n` bytes off `rest`.
+fn take<'a>(rest: &mut &'a [u8], n: usize) -> Option<&'a [u8]> {
+    let (head, tail) = (rest.get(..n)?, rest.get(n..)?);
+    *rest = tail;
+    Some(head)
+}
+
+/// Extracts the server_name from a TLS record holding a ClientHello.
+fn client_hello_sni(record: &[u8]) -> Option<String> {
+    let mut rest = record;
+    let u8_at = |bytes: &[u8]| bytes[0] as usize;
+    let u16_at = |bytes: &[u8]| u16::from_be_bytes([bytes[0], bytes[1]]) as usize;
+
+    if take(&mut rest, 1)?[0] != TLS_HANDSHAKE {
+        return None;
+    }
+    take(&mut rest, 4)?; // record version + length
+    if take(&mut rest, 1)?[0] != TLS_CLIENT_HELLO {
+        return None;
+    }
+    take(&mut rest, 3 + 2 + 32)?; // handshake length, client version, random
+    let session_id = u8_at(take(&mut rest, 1)?);
+    take(&mut rest, session_id)?;
+    let cipher_suites = u16_at(take(&mut rest, 2)?);
+    take(&mut rest, cipher_suites)?;
+    let compression = u8_at(take(&mut rest, 1)?);
+    take(&mut rest, compression)?;
+    let extensions_len = u16_at(take(&mut rest, 2)?);
+    let mut extensions = take(&mut rest, extensions_len)?;
+
+    while extensions.len() >= 4 {
+        let ext_type = u16::from_be_bytes([extensions[0], extensions[1]]);
+        let ext_len = u16_at(&extensions[2..4]);
+        let data = extensions.get(4..4 + ext_len)?;
+        extensions = &extensions[4 + ext_len..];
+        if ext_type != TLS_EXT_SERVER_NAME {
+            continue;
+        }
+        // server_name_list: length, then (type, length, name) entries.
+        let mut names = data.get(2..)?;
+        while names.len() >= 3 {
+            let name_len = u16_at(&names[1..3]);
+            let name = names.get(3..3 + name_len)?;
+            if names[0] == 0 {
+                return std::str::from_utf8(name).ok().map(str::to_string);
+            }
+            names = &names[3 + name_len..];
+        }
+        return None;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A minimal TLS 1.2 ClientHello record, with an SNI extension when
+    /// `server_name` is given.
+    fn make_client_hello(server_name: Option<&str>) -> Vec<u8> {
+        let mut extensions = Vec::new();
+        // An unrelated extension first (supported_groups).
+        extensions.extend_from_slice(&[0x00, 0x0a, 0x00, 0x04, 0x00, 0x02, 0x00, 0x1d]);
+        if let Some(name) = server_name {
+            let name = name.as_bytes();
+            let list_len = 3 + name.len();
+            extensions.extend_from_slice(&TLS_EXT_SERVER_NAME.to_be_bytes());
+            extensions.extend_from_slice(&((2 + list_len) as u16).to_be_bytes());
+            extensions.extend_from_slice(&(list_len as u16).to_be_bytes());
+            extensions.push(0);
+            extensions.extend_from_slice(&(name.len() as u16).to_be_bytes());
+            extensions.extend_from_slice(name);
+        }
+
+        let mut hello = vec![0x03, 0x03];
+        hello.extend_from_slice(&[0u8; 32]);
+        hello.push(0); // session id
+        hello.extend_from_slice(&[0x00, 0x02, 0x13, 0x01]); // one cipher suite
+        hello.extend_from_slice(&[0x01, 0x00]); // null compression
+        hello.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+        hello.extend_from_slice(&extensions);
+
+        let mut handshake = vec![TLS_CLIENT_HELLO];
+        handshake.extend_from_slice(&(hello.len() as u32).to_be_bytes()[1..]);
+        handshake.extend_from_slice(&hello);
+
+        let mut record = vec![TLS_HANDSHAKE, 0x03, 0x01];
+        record.extend_from_slice(&(handshake.len() as u16).to_be_bytes());
+        record.extend_from_slice(&handshake);
+        record
+    }
+
+    #[test]
+    fn test_client_hello_sni_extracts_server_name() {
+        let record = make_client_hello(Some("api.example.com"));
+        assert_eq!(
+            client_hello_sni(&record).as_deref(),
+            Some("api.example.com")
+        );
+    }
+
+    #[test]
+    fn test_client_hello_sni_without_extension_or_tls() {
+        assert_eq!(client_hello_sni(&make_client_hello(None)), None);
+        assert_eq!(client_hello_sni(b"GET / HTTP/1.1\r\n\r\n"), None);
+        let record = make_client_hello(Some("api.example.com"));
+        assert_eq!(client_hello_sni(&record[..20]), None);
+    }
+
+    #[tokio::test]
+    async fn test_peek_sni_leaves_bytes_unread() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let record = make_client_hello(Some("example.org"));
+        let sent = record.clone();
+        tokio::spawn(async move {
+            let mut client = TcpStream::connect(addr).await.unwrap();
+            client.write_all(&sent).await.unwrap();
+            // Keep the connection open until the server has read it all.
+            let _ = client.read(&mut [0u8; 1]).await;
+        });
+
+        let (mut server, _) = listener.accept().await.unwrap();
+        assert_eq!(peek_sni(&server).await.as_deref(), Some("example.org"));
+        let mut received = vec![0u8; record.len()];
+        server.read_exact(&mut received).await.unwrap();
+        assert_eq!(received, record);
+    }
+}
@@ -0,0 +1,307 @@
+//! WinDivert packet-capture backend — Windows only.
+//!
+//! Opens a [WinDivert](https://reqrypt.org/windivert.html) handle in sniff
+//! mode on the network layer, which hands over a copy of every TCP packet —
+//! loopback included — as a bare IPv4 or IPv6 packet, and runs the packets
+//! through the same [`FlowCollector`] as the Linux packet backends. Without a
+//! key log every connection seen from its SYN is parsed as plain HTTP/1,
+//! like the pcap backend; with one (see [`with_keylog`]) only TLS decrypted
+//! with the secrets the traced command logs is, like the keylog backend.
+//! Traces carry no process.
+//!
+//! `WinDivert.dll` is loaded when the backend starts rather than linked, so
+//! phantom builds and runs without it; it and its driver (`WinDivert64.sys`)
+//! must sit next to `phantom.exe` or on `PATH`. Capturing needs
+//! Administrator rights.
+//!
+//! [`with_keylog`]: WinDivertCaptureBackend::with_keylog
+
+use std::ffi::{CString, c_char, c_void};
+use std::io;
+use std::os::windows::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant, SystemTime};
+
+use phantom_core::capture::{CaptureBackend, TraceEvent};
+use phantom_core::error::CaptureError;
+use phantom_core::trace::HttpTrace;
+use tokio::sync::mpsc;
+use tracing::warn;
+
+use crate::flows::FlowCollector;
+use crate::tls::KeyLog;
+
+/// WinDivert's largest packet: an IP packet plus its 40-byte headroom.
+const MAX_PACKET: usize = 40 + 0xFFFF;
+/// How often idle connections are closed, checked as packets arrive.
+const EXPIRY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// `WINDIVERT_LAYER_NETWORK`: IP packets to and from this host.
+const LAYER_NETWORK: i32 = 0;
+/// `WINDIVERT_FLAG_SNIFF`: copy packets instead of diverting them.
+const FLAG_SNIFF: u64 = 0x0001;
+/// `WINDIVERT_FLAG_RECV_ONLY`: the handle never injects packets.
+const FLAG_RECV_ONLY: u64 = 0x0004;
+/// `WINDIVERT_SHUTDOWN_BOTH`.
+const SHUTDOWN_BOTH: i32 = 0x3;
+/// What `WinDivertRecv` fails with once the handle is shut down and drained.
+const ERROR_NO_DATA: i32 = 232;
+/// A packet longer than the buffer; the rest of it is lost.
+const ERROR_INSUFFICIENT_BUFFER: i32 = 122;
+const ERROR_ACCESS_DENIED: i32 = 5;
+
+type RawHandle = *mut c_void;
+
+#[link(name = "kernel32")]
+unsafe extern "system" {
+    fn LoadLibraryW(name: *const u16) -> *mut c_void;
+    fn GetProcAddress(module: *mut c_void, name: *const c_char) -> *mut c_void;
+}
+
+type OpenFn = unsafe extern "C" fn(*const c_char, i32, i16, u64) -> RawHandle;
+type RecvFn = unsafe extern "C" fn(RawHandle, *mut c_void, u32, *mut u32, *mut c_void) -> i32;
+type ShutdownFn = unsafe extern "C" fn(RawHandle, i32) -> i32;
+type CloseFn = unsafe extern "C" fn(RawHandle) -> i32;
+
+/// The functions phantom uses from `WinDivert.dll` (WinDivert 2.x).
+struct WinDivertApi {
+    open: OpenFn,
+    recv: RecvFn,
+    shutdown: ShutdownFn,
+    close: CloseFn,
+}
+
+/// Loads `WinDivert.dll` on first use.
+fn api() -> Result<&'static WinDivertApi, String> {
+    static API: OnceLock<Result<WinDivertApi, String>> = OnceLock::new();
+    API.get_or_init(load_api).as_ref().map_err(Clone::clone)
+}
+
+fn load_api() -> Result<WinDivertApi, String> {
+    let name: Vec<u16> = std::ffi::OsStr::new("WinDivert.dll")
+        .encode_wide()
+        .chain([0])
+        .collect();
+    // SAFETY: `name` is a NUL-terminated UTF-16 string.
+    let module = unsafe { LoadLibraryW(name.as_ptr()) };
+    if module.is_null() {
+        return Err(format!(
+            "cannot load WinDivert.dll: {} (install WinDivert 2.x from \
+             https://reqrypt.org/windivert.html next to phantom.exe)",
+            io::Error::last_os_error()
+        ));
+    }
+    let symbol = |name: &str| -> Result<*mut c_void, String> {
+        let c_name = CString::new(name).map_err(|e| e.to_string())?;
+        // SAFETY: `module` is a loaded library, which is never unloaded, and
+        // `c_name` is NUL-terminated.
+        let address = unsafe { GetProcAddress(module, c_name.as_ptr()) };
+        if address.is_null() {
+            Err(format!(
+                "WinDivert.dll has no {name}: WinDivert 2.x is needed"
+            ))
+        } else {
+            Ok(address)
+        }
+    };
+    // SAFETY: each symbol is the WinDivert 2.x function of that name, whose
+    // C signature the field's type matches.
+    unsafe {
+        Ok(WinDivertApi {
+            open: std::mem::transmute::<*mut c_void, OpenFn>(symbol("WinDivertOpen")?),
+            recv: std::mem::transmute::<*mut c_void, RecvFn>(symbol("WinDivertRecv")?),
+            shutdown: std::mem::transmute::<*mut c_void, ShutdownFn>(symbol("WinDivertShutdown")?),
+            close: std::mem::transmute::<*mut c_void, CloseFn>(symbol("WinDivertClose")?),
+        })
+    }
+}
+
+/// An open WinDivert handle, closed on drop.
+struct Handle {
+    api: &'static WinDivertApi,
+    raw: RawHandle,
+}
+
+// SAFETY: WinDivert handles may be used from any thread, and
+// `WinDivertShutdown` is documented to be called while another thread is
+// blocked in `WinDivertRecv` on the same handle.
+unsafe impl Send for Handle {}
+// SAFETY: as above; every call through `&Handle` is one WinDivert call.
+unsafe impl Sync for Handle {}
+
+impl Handle {
+    /// Sniffs the packets matching `filter` (WinDivert filter language).
+    fn open(filter: &str) -> Result<Self, String> {
+        let api = api()?;
+        let c_filter = CString::new(filter).map_err(|e| e.to_string())?;
+        // SAFETY: `c_filter` is NUL-terminated; the other arguments are
+        // plain values.
+        let raw = unsafe {
+            (api.open)(
+                c_filter.as_ptr(),
+                LAYER_NETWORK,
+                0,
+                FLAG_SNIFF | FLAG_RECV_ONLY,
+            )
+        };
+        // INVALID_HANDLE_VALUE.
+        if raw as isize == -1 {
+            let e = io::Error::last_os_error();
+            return Err(match e.raw_os_error() {
+                Some(ERROR_ACCESS_DENIED) => format!("{e} (needs Administrator)"),
+                _ => format!("{e} (is WinDivert64.sys next to WinDivert.dll?)"),
+            });
+        }
+        Ok(Self { api, raw })
+    }
+
+    /// Waits for the next packet and copies it into `buf`, returning its
+    /// length.
+    fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut len = 0u32;
+        // SAFETY: `buf` is writable for the length passed, `len` is a valid
+        // out pointer and the address is optional.
+        let ok = unsafe {
+            (self.api.recv)(
+                self.raw,
+                buf.as_mut_ptr().cast(),
+                buf.len() as u32,
+                &raw mut len,
+                std::ptr::null_mut(),
+            )
+        };
+        if ok == 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(len as usize)
+        }
+    }
+
+    /// Makes a blocked or later [`recv`](Self::recv) fail once the packets
+    /// already queued are read.
+    fn shutdown(&self) {
+        // SAFETY: `raw` is open until drop.
+        unsafe { (self.api.shutdown)(self.raw, SHUTDOWN_BOTH) };
+    }
+}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        // SAFETY: `raw` is open and no longer used by anyone else.
+        unsafe { (self.api.close)(self.raw) };
+    }
+}
+
+#[derive(Default)]
+pub struct WinDivertCaptureBackend {
+    keylog_path: Option<PathBuf>,
+    handle: Option<Arc<Handle>>,
+    task_handle: Option<std::thread::JoinHandle<()>>,
+}
+
+impl WinDivertCaptureBackend {
+    /// Create a backend parsing every connection as plain HTTP/1.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decrypt TLS with the secrets logged to `keylog_path` instead
+    /// (builder pattern); connections without secrets there are ignored.
+    /// `start` creates (or empties) the file.
+    pub fn with_keylog(mut self, keylog_path: PathBuf) -> Self {
+        self.keylog_path = Some(keylog_path);
+        self
+    }
+
+    pub fn keylog_path(&self) -> Option<&Path> {
+        self.keylog_path.as_deref()
+    }
+
+    /// Sets up `command`'s environment when decrypting: `SSLKEYLOGFILE`,
+    /// and HTTP/1 for Go's HTTP client.
+    pub fn prepare(&self, command: &mut Command) {
+        let Some(keylog_path) = &self.keylog_path else {
+            return;
+        };
+        let godebug = match std::env::var("GODEBUG") {
+            Ok(existing) if !existing.is_empty() => format!("{existing},http2client=0"),
+            _ => "http2client=0".to_string(),
+        };
+        command
+            .env("SSLKEYLOGFILE", keylog_path)
+            .env("GODEBUG", godebug);
+    }
+}
+
+/// Feeds packets from `handle` to `collector` until the handle is shut
+/// down, sending the traces it completes. Open connections are flushed at
+/// the end.
+fn capture(handle: Arc<Handle>, mut collector: FlowCollector, trace_tx: mpsc::Sender<TraceEvent>) {
+    let mut buf = vec![0u8; MAX_PACKET];
+    let mut expired = Instant::now();
+    loop {
+        match handle.recv(&mut buf) {
+            Ok(len) => send_traces(&trace_tx, collector.packet(&buf[..len], SystemTime::now())),
+            Err(e) if e.raw_os_error() == Some(ERROR_NO_DATA) => break,
+            Err(e) if e.raw_os_error() == Some(ERROR_INSUFFICIENT_BUFFER) => {}
+            Err(e) => {
+                warn!("windivert capture: {e}");
+                break;
+            }
+        }
+        if expired.elapsed() >= EXPIRY_INTERVAL {
+            expired = Instant::now();
+            send_traces(&trace_tx, collector.expire(SystemTime::now()));
+        }
+    }
+    send_traces(&trace_tx, collector.flush(SystemTime::now()));
+}
+
+fn send_traces(trace_tx: &mpsc::Sender<TraceEvent>, traces: Vec<HttpTrace>) {
+    for trace in traces {
+        if trace_tx.try_send(trace.into()).is_err() {
+            warn!("Trace channel full, dropping trace");
+        }
+    }
+}
+
+impl CaptureBackend for WinDivertCaptureBackend {
+    fn start(&mut self) -> Result<mpsc::Receiver<TraceEvent>, CaptureError> {
+        let collector = match &self.keylog_path {
+            Some(path) => {
+                std::fs::File::create(path)
+                    .map_err(|e| CaptureError::StartFailed(format!("{}: {e}", path.display())))?;
+                FlowCollector::decrypting(KeyLog::new(path.clone()))
+            }
+            None => FlowCollector::plaintext(),
+        };
+        let handle = Handle::open("tcp")
+            .map(Arc::new)
+            .map_err(|e| CaptureError::StartFailed(format!("opening WinDivert capture: {e}")))?;
+
+        let (trace_tx, trace_rx) = mpsc::channel(4096);
+        let task_handle = {
+            let handle = handle.clone();
+            std::thread::Builder::new()
+                .name("phantom-windivert".into())
+                .spawn(move || capture(handle, collector, trace_tx))
+                .map_err(|e| CaptureError::StartFailed(e.to_string()))?
+        };
+        self.handle = Some(handle);
+        self.task_handle = Some(task_handle);
+        Ok(trace_rx)
+    }
+
+    fn stop(&mut self) -> Result<(), CaptureError> {
+        if let Some(handle) = self.handle.take() {
+            handle.shutdown();
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "windivert"
+    }
+}
@@ -1,20 +1,72 @@
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
 use tokio::sync::mpsc;
 
 use crate::error::CaptureError;
-use crate::trace::HttpTrace;
+use crate::protocol::ProtocolTrace;
+use crate::trace::{HttpTrace, ProcessInfo};
 
 /// Abstraction over different HTTP traffic capture backends.
 ///
-/// On Linux, a bpftime-based eBPF backend can be used.
+/// On Linux, LD_PRELOAD and eBPF backends can be used.
 /// On all platforms, a local MITM proxy backend is available.
 pub trait CaptureBackend: Send {
     /// Start capturing HTTP traffic.
-    /// Returns a receiver that yields captured traces.
-    fn start(&mut self) -> Result<mpsc::Receiver<HttpTrace>, CaptureError>;
+    /// Returns a receiver that yields captured traces and, for backends that
+    /// produce them, non-HTTP traces and agent health reports.
+    fn start(&mut self) -> Result<mpsc::Receiver<TraceEvent>, CaptureError>;
 
     /// Gracefully stop capturing.
     fn stop(&mut self) -> Result<(), CaptureError>;
 
-    /// Human-readable name of this backend (e.g., "proxy", "bpftime").
+    /// Human-readable name of this backend (e.g., "proxy", "ebpf").
     fn name(&self) -> &str;
 }
+
+/// Everything a capture backend reports, in the order it was captured.
+#[derive(Debug, Clone)]
+pub enum TraceEvent {
+    Http(Box<HttpTrace>),
+    /// A non-HTTP exchange (ldpreload and ingest backends).
+    Protocol(ProtocolTrace),
+    /// An in-process agent's health (ldpreload and ingest backends).
+    AgentStats(AgentStats),
+}
+
+impl From<HttpTrace> for TraceEvent {
+    fn from(trace: HttpTrace) -> Self {
+        Self::Http(Box::new(trace))
+    }
+}
+
+impl From<ProtocolTrace> for TraceEvent {
+    fn from(trace: ProtocolTrace) -> Self {
+        Self::Protocol(trace)
+    }
+}
+
+impl From<AgentStats> for TraceEvent {
+    fn from(stats: AgentStats) -> Self {
+        Self::AgentStats(stats)
+    }
+}
+
+/// Periodic health report from an in-process agent (ldpreload backend).
+/// Counts are totals since the process started.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AgentStats {
+    pub process: ProcessInfo,
+    /// Hook families that intercepted traffic, e.g. `"socket"`, `"openssl"`.
+    pub hooks: Vec<String>,
+    /// Messages the agent sent to phantom.
+    pub sent: u64,
+    /// Messages lost before reaching phantom (queue full, too large, socket
+    /// errors).
+    pub dropped: u64,
+    /// Protocol data the agent could not parse (e.g. corrupt HPACK blocks).
+    pub errors: u64,
+    /// Connections the agent is tracking.
+    pub connections: u64,
+    pub timestamp: SystemTime,
+}
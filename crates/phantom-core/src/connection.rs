@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+use serde::Serialize;
+
+use crate::trace::HttpTrace;
+
+/// Connection reuse over a set of traces, keyed by [`HttpTrace::connection_id`].
+///
+/// A connection counts as opened when its first request is seen. Few
+/// requests per connection, or a high share of single-request connections,
+/// points at broken keep-alive; a spike in new connections per second at
+/// pool exhaustion or churn.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ConnectionStats {
+    /// Distinct connections seen.
+    pub connections: u64,
+    /// Requests attributed to a connection.
+    pub requests: u64,
+    /// Requests without a connection id (not included in the figures above).
+    pub untracked_requests: u64,
+    /// Mean requests carried per connection.
+    pub requests_per_connection: f64,
+    /// Most requests carried by a single connection.
+    pub max_requests_per_connection: u64,
+    /// Connections that carried exactly one request.
+    pub single_request_connections: u64,
+    /// Connections opened per second over the observed time window.
+    pub new_connections_per_sec: f64,
+}
+
+impl ConnectionStats {
+    pub fn from_traces(traces: &[HttpTrace]) -> Self {
+        let mut tracker = ConnectionTracker::default();
+        for trace in traces {
+            tracker.record(trace);
+        }
+        tracker.stats()
+    }
+}
+
+/// Incrementally tracks connection reuse as traces arrive.
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionTracker {
+    requests_by_connection: HashMap<String, u64>,
+    untracked: u64,
+    window_start: Option<SystemTime>,
+    window_end: Option<SystemTime>,
+}
+
+impl ConnectionTracker {
+    pub fn record(&mut self, trace: &HttpTrace) {
+        let Some(id) = trace.connection_id.as_deref() else {
+            self.untracked += 1;
+            return;
+        };
+        *self
+            .requests_by_connection
+            .entry(id.to_string())
+            .or_default() += 1;
+
+        let end = trace.timestamp + trace.duration;
+        self.window_start = Some(
+            self.window_start
+                .map_or(trace.timestamp, |s| s.min(trace.timestamp)),
+        );
+        self.window_end = Some(self.window_end.map_or(end, |e| e.max(end)));
+    }
+
+    pub fn stats(&self) -> ConnectionStats {
+        let connections = self.requests_by_connection.len() as u64;
+        let requests: u64 = self.requests_by_connection.values().sum();
+        // At least one second, so a burst does not read as an absurd rate.
+        let window = match (self.window_start, self.window_end) {
+            (Some(start), Some(end)) => end.duration_since(start).unwrap_or_default(),
+            _ => Duration::ZERO,
+        }
+        .max(Duration::from_secs(1));
+
+        ConnectionStats {
+            connections,
+            requests,
+            untracked_requests: self.untracked,
+            requests_per_connection: if connections == 0 {
+                0.0
+            } else {
+                requests as f64 / connections as f64
+            },
+            max_requests_per_connection: self
+                .requests_by_connection
+                .values()
+                .copied()
+                .max()
+                .unwrap_or(0),
+            single_request_connections: self
+                .requests_by_connection
+                .values()
+                .filter(|&&n| n == 1)
+                .count() as u64,
+            new_connections_per_sec: connections as f64 / window.as_secs_f64(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::time::UNIX_EPOCH;
+
+    use super::*;
+    use crate::trace::{HttpMethod, SpanId, TraceId};
+
+    fn make_trace(connection_id: Option<&str>, at_secs: u64) -> HttpTrace {
+        HttpTrace {
+            span_id: SpanId([1; 8]),
+            trace_id: TraceId([2; 16]),
+            parent_span_id: None,
+            method: HttpMethod::Get,
+            url: "http://a/".to_string(),
+            request_headers: HashMap::new(),
+            request_body: None,
+            status_code: 200,
+            response_headers: HashMap::new(),
+            response_body: None,
+            timestamp: UNIX_EPOCH + Duration::from_secs(at_secs),
+            duration: Duration::from_millis(100),
+            source_addr: None,
+            dest_addr: None,
+            protocol_version: "HTTP/1.1".to_string(),
+            request_body_file: None,
+            response_body_file: None,
+            connection_id: connection_id.map(str::to_string),
+            process: None,
+            response_trailers: HashMap::new(),
+            request_body_encoding: None,
+            response_body_encoding: None,
+            elasticsearch: None,
+            soap: None,
+            graphql: None,
+            thread_id: None,
+            tls: None,
+            timing: None,
+            request_size: None,
+            response_size: None,
+            fault: None,
+            replay: None,
+        }
+    }
+
+    #[test]
+    fn test_connection_stats_reuse() {
+        let traces = vec![
+            make_trace(Some("c1"), 100),
+            make_trace(Some("c1"), 101),
+            make_trace(Some("c1"), 102),
+            make_trace(Some("c2"), 103),
+            make_trace(None, 104),
+        ];
+        let stats = ConnectionStats::from_traces(&traces);
+        assert_eq!(stats.connections, 2);
+        assert_eq!(stats.requests, 4);
+        assert_eq!(stats.untracked_requests, 1);
+        assert_eq!(stats.requests_per_connection, 2.0);
+        assert_eq!(stats.max_requests_per_connection, 3);
+        assert_eq!(stats.single_request_connections, 1);
+        // Window 100.0s → 103.1s.
+        assert!((stats.new_connections_per_sec - 2.0 / 3.1).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_connection_stats_empty_and_burst() {
+        assert_eq!(
+            ConnectionStats::from_traces(&[]),
+            ConnectionStats::default()
+        );
+
+        // Ten fresh connections inside one second: rate uses the 1s floor.
+        let burst: Vec<_> = (0..10)
+            .map(|i| make_trace(Some(&format!("c{i}")), 50))
+            .collect();
+        let stats = ConnectionStats::from_traces(&burst);
+        assert_eq!(stats.single_request_connections, 10);
+        assert_eq!(stats.new_connections_per_sec, 10.0);
+    }
+}
@@ -0,0 +1,468 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::trace::{HttpTrace, url_path};
+
+/// Elasticsearch/OpenSearch REST endpoints we recognize, matched against the
+/// first `_`-prefixed path segment.
+const ES_ENDPOINTS: &[&str] = &[
+    "_search",
+    "_msearch",
+    "_count",
+    "_bulk",
+    "_doc",
+    "_create",
+    "_update",
+    "_mget",
+    "_update_by_query",
+    "_delete_by_query",
+];
+
+/// Query DSL clauses that target fields directly (no nested queries).
+const LEAF_QUERIES: &[&str] = &[
+    "match",
+    "match_phrase",
+    "match_phrase_prefix",
+    "match_bool_prefix",
+    "multi_match",
+    "query_string",
+    "simple_query_string",
+    "term",
+    "terms",
+    "range",
+    "prefix",
+    "wildcard",
+    "regexp",
+    "fuzzy",
+    "exists",
+    "ids",
+    "match_all",
+    "match_none",
+    "geo_distance",
+    "geo_bounding_box",
+    "knn",
+];
+
+/// Leaf-query parameters that are not field names.
+const LEAF_PARAMS: &[&str] = &["boost", "_name", "query", "type", "operator"];
+
+/// Longest query summary we produce; longer summaries are cut with `…`.
+const MAX_SUMMARY_LEN: usize = 160;
+
+/// Summary of an Elasticsearch/OpenSearch API call recognized from a trace.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EsOperation {
+    /// Endpoint name, e.g. `"_search"`, `"_bulk"`, `"_doc"`.
+    pub api: String,
+    /// Target index (or comma-separated indices) from the URL path, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub index: Option<String>,
+    /// Compact summary of the query DSL or bulk actions,
+    /// e.g. `"bool(must: match(title), filter: range(price))"`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub query_summary: Option<String>,
+}
+
+impl EsOperation {
+    /// Recognizes an ES call from the trace's URL path and request body.
+    /// Returns `None` for anything that does not hit a known ES endpoint.
+    ///
+    /// `_search` or `_doc` alone is common in other APIs, so a call must
+    /// also come back with the `X-Elastic-Product` header (Elasticsearch
+    /// 7.14+) or have the exact shape of an ES REST path:
+    /// `/[<index>/]<endpoint>`, or `/<index>/_doc[/<id>]` for documents.
+    pub fn detect(trace: &HttpTrace) -> Option<Self> {
+        let path = url_path(&trace.url);
+        let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        let api_pos = segments.iter().position(|s| s.starts_with('_'))?;
+        let api = segments[api_pos];
+        if !ES_ENDPOINTS.contains(&api) {
+            return None;
+        }
+        if !has_product_header(trace) && !is_rest_path(&segments, api_pos) {
+            return None;
+        }
+        // The index is whatever precedes the endpoint (`/products/_search`);
+        // a path that starts with the endpoint (`/_bulk`) has none.
+        let index = (api_pos > 0).then(|| segments[..api_pos].join("/"));
+
+        let body = trace.request_body.as_deref().unwrap_or_default();
+        let query_summary = match api {
+            "_bulk" => summarize_bulk(body),
+            "_msearch" => summarize_msearch(body),
+            "_search" | "_count" | "_update_by_query" | "_delete_by_query" => {
+                serde_json::from_slice::<Value>(body)
+                    .ok()
+                    .and_then(|v| v.get("query").map(summarize_query))
+            }
+            _ => None,
+        }
+        .filter(|s| !s.is_empty())
+        .map(|s| cap_len(s, MAX_SUMMARY_LEN));
+
+        Some(Self {
+            api: api.to_string(),
+            index,
+            query_summary,
+        })
+    }
+
+    /// One-line label for list views, e.g. `"_search products match(title)"`.
+    pub fn label(&self) -> String {
+        let mut label = self.api.clone();
+        if let Some(index) = &self.index {
+            label.push(' ');
+            label.push_str(index);
+        }
+        if let Some(summary) = &self.query_summary {
+            label.push(' ');
+            label.push_str(summary);
+        }
+        label
+    }
+}
+
+/// True when the server identified itself as Elasticsearch.
+fn has_product_header(trace: &HttpTrace) -> bool {
+    trace
+        .response_headers
+        .iter()
+        .any(|(name, _)| name.eq_ignore_ascii_case("x-elastic-product"))
+}
+
+/// True for `/[<index>/]<endpoint>`, and for `/<index>/<endpoint>[/<id>]`
+/// when the endpoint addresses a single document.
+fn is_rest_path(segments: &[&str], api_pos: usize) -> bool {
+    let trailing = segments.len() - api_pos - 1;
+    let shape_ok = match segments[api_pos] {
+        "_doc" | "_create" | "_update" => api_pos == 1 && trailing <= 1,
+        _ => api_pos <= 1 && trailing == 0,
+    };
+    shape_ok && segments[..api_pos].iter().all(|s| is_index_expression(s))
+}
+
+/// True for a comma-separated list of index names or patterns as ES accepts
+/// them: lower-case, and not starting with `-`, `+` or `_`.
+fn is_index_expression(segment: &str) -> bool {
+    segment.split(',').all(|name| {
+        !name.is_empty()
+            && !name.starts_with(['-', '+', '_'])
+            && name
+                .bytes()
+                .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b"-_.+*".contains(&b))
+    })
+}
+
+fn cap_len(mut s: String, max: usize) -> String {
+    if s.len() > max {
+        let mut cut = max.saturating_sub('…'.len_utf8());
+        while !s.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        s.truncate(cut);
+        s.push('…');
+    }
+    s
+}
+
+/// Summarizes a query DSL object into nested `clause(fields)` form.
+fn summarize_query(query: &Value) -> String {
+    summarize_query_at(query, 0)
+}
+
+fn summarize_query_at(query: &Value, depth: usize) -> String {
+    let Some(obj) = query.as_object() else {
+        return String::new();
+    };
+    obj.iter()
+        .map(|(name, body)| summarize_clause(name, body, depth))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn summarize_clause(name: &str, body: &Value, depth: usize) -> String {
+    if LEAF_QUERIES.contains(&name) {
+        let fields = leaf_fields(name, body);
+        return if fields.is_empty() {
+            name.to_string()
+        } else {
+            format!("{name}({})", fields.join(","))
+        };
+    }
+    if depth >= 4 {
+        return format!("{name}(…)");
+    }
+    // Compound query (bool, dis_max, nested, constant_score, …): summarize
+    // every nested sub-query, labelled by the clause that holds it.
+    let Some(obj) = body.as_object() else {
+        return name.to_string();
+    };
+    let parts: Vec<String> = obj
+        .iter()
+        .filter_map(|(clause, value)| {
+            let inner = match value {
+                Value::Array(items) => items
+                    .iter()
+                    .map(|q| summarize_query_at(q, depth + 1))
+                    .filter(|s| !s.is_empty())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                Value::Object(_) => summarize_query_at(value, depth + 1),
+                _ => String::new(),
+            };
+            (!inner.is_empty()).then(|| format!("{clause}: {inner}"))
+        })
+        .collect();
+    if parts.is_empty() {
+        name.to_string()
+    } else {
+        format!("{name}({})", parts.join(", "))
+    }
+}
+
+/// Field names a leaf query applies to.
+fn leaf_fields(name: &str, body: &Value) -> Vec<String> {
+    match name {
+        "exists" | "knn" => body
+            .get("field")
+            .and_then(Value::as_str)
+            .map(|f| vec![f.to_string()])
+            .unwrap_or_default(),
+        "multi_match" | "query_string" | "simple_query_string" => body
+            .get("fields")
+            .and_then(Value::as_array)
+            .map(|fs| {
+                fs.iter()
+                    .filter_map(Value::as_str)
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default(),
+        _ => body
+            .as_object()
+            .map(|o| {
+                o.keys()
+                    .filter(|k| !LEAF_PARAMS.contains(&k.as_str()))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default(),
+    }
+}
+
+/// Counts bulk actions in an NDJSON `_bulk` body, e.g. `"3 ops (index 2, delete 1)"`.
+fn summarize_bulk(body: &[u8]) -> Option<String> {
+    let text = std::str::from_utf8(body).ok()?;
+    let mut counts: Vec<(String, usize)> = Vec::new();
+    let mut lines = text.lines().filter(|l| !l.trim().is_empty());
+    while let Some(line) = lines.next() {
+        let Ok(Value::Object(action)) = serde_json::from_str::<Value>(line) else {
+            continue;
+        };
+        let Some(kind) = action.keys().next().cloned() else {
+            continue;
+        };
+        // Every action except delete is followed by a source document line.
+        if kind != "delete" {
+            lines.next();
+        }
+        match counts.iter_mut().find(|(k, _)| *k == kind) {
+            Some((_, n)) => *n += 1,
+            None => counts.push((kind, 1)),
+        }
+    }
+    let total: usize = counts.iter().map(|(_, n)| n).sum();
+    if total == 0 {
+        return None;
+    }
+    let breakdown = counts
+        .iter()
+        .map(|(k, n)| format!("{k} {n}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    Some(format!("{total} ops ({breakdown})"))
+}
+
+/// Counts header/body pairs in an NDJSON `_msearch` body.
+fn summarize_msearch(body: &[u8]) -> Option<String> {
+    let text = std::str::from_utf8(body).ok()?;
+    let lines = text.lines().filter(|l| !l.trim().is_empty()).count();
+    (lines >= 2).then(|| format!("{} searches", lines / 2))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::*;
+    use crate::trace::{HttpMethod, SpanId, TraceId};
+
+    fn make_trace(method: HttpMethod, url: &str, body: &str) -> HttpTrace {
+        HttpTrace {
+            span_id: SpanId([1; 8]),
+            trace_id: TraceId([2; 16]),
+            parent_span_id: None,
+            method,
+            url: url.to_string(),
+            request_headers: HashMap::new(),
+            request_body: (!body.is_empty()).then(|| body.as_bytes().to_vec()),
+            status_code: 200,
+            response_headers: HashMap::new(),
+            response_body: None,
+            timestamp: UNIX_EPOCH,
+            duration: Duration::from_millis(5),
+            source_addr: None,
+            dest_addr: None,
+            protocol_version: "HTTP/1.1".to_string(),
+            request_body_file: None,
+            response_body_file: None,
+            connection_id: None,
+            process: None,
+            response_trailers: HashMap::new(),
+            request_body_encoding: None,
+            response_body_encoding: None,
+            elasticsearch: None,
+            soap: None,
+            graphql: None,
+            thread_id: None,
+            tls: None,
+            timing: None,
+            request_size: None,
+            response_size: None,
+            fault: None,
+            replay: None,
+        }
+    }
+
+    #[test]
+    fn test_detect_search_with_index_and_query() {
+        let body = r#"{"size":10,"query":{"bool":{"must":[{"match":{"title":"phone"}}],
+            "filter":[{"range":{"price":{"lte":100}}}]}}}"#;
+        let t = make_trace(
+            HttpMethod::Post,
+            "http://es:9200/products/_search?pretty",
+            body,
+        );
+        let op = EsOperation::detect(&t).unwrap();
+        assert_eq!(op.api, "_search");
+        assert_eq!(op.index.as_deref(), Some("products"));
+        assert_eq!(
+            op.query_summary.as_deref(),
+            Some("bool(filter: range(price), must: match(title))")
+        );
+    }
+
+    #[test]
+    fn test_detect_search_without_index() {
+        let t = make_trace(HttpMethod::Get, "http://es:9200/_search", "");
+        let op = EsOperation::detect(&t).unwrap();
+        assert_eq!(op.index, None);
+        assert_eq!(op.query_summary, None);
+    }
+
+    #[test]
+    fn test_detect_multi_index_and_exists() {
+        let body = r#"{"query":{"exists":{"field":"user"}}}"#;
+        let t = make_trace(
+            HttpMethod::Post,
+            "http://es:9200/logs-a,logs-b/_count",
+            body,
+        );
+        let op = EsOperation::detect(&t).unwrap();
+        assert_eq!(op.api, "_count");
+        assert_eq!(op.index.as_deref(), Some("logs-a,logs-b"));
+        assert_eq!(op.query_summary.as_deref(), Some("exists(user)"));
+    }
+
+    #[test]
+    fn test_detect_bulk_counts_actions() {
+        let body = concat!(
+            "{\"index\":{\"_index\":\"a\",\"_id\":\"1\"}}\n",
+            "{\"f\":1}\n",
+            "{\"delete\":{\"_index\":\"a\",\"_id\":\"2\"}}\n",
+            "{\"index\":{\"_index\":\"a\",\"_id\":\"3\"}}\n",
+            "{\"f\":3}\n",
+        );
+        let t = make_trace(HttpMethod::Post, "http://es:9200/_bulk", body);
+        let op = EsOperation::detect(&t).unwrap();
+        assert_eq!(op.api, "_bulk");
+        assert_eq!(op.index, None);
+        assert_eq!(
+            op.query_summary.as_deref(),
+            Some("3 ops (index 2, delete 1)")
+        );
+    }
+
+    #[test]
+    fn test_detect_doc_endpoint() {
+        let t = make_trace(HttpMethod::Put, "https://es/orders/_doc/42", r#"{"a":1}"#);
+        let op = EsOperation::detect(&t).unwrap();
+        assert_eq!(op.api, "_doc");
+        assert_eq!(op.index.as_deref(), Some("orders"));
+        assert_eq!(op.label(), "_doc orders");
+    }
+
+    #[test]
+    fn test_non_es_urls_are_ignored() {
+        for url in [
+            "http://api/users",
+            "http://api/_internal/health",
+            "http://api/",
+            "http://api/v1/users?_search=1",
+        ] {
+            let t = make_trace(HttpMethod::Get, url, "");
+            assert_eq!(EsOperation::detect(&t), None, "{url}");
+        }
+    }
+
+    #[test]
+    fn test_search_like_paths_need_an_es_signal() {
+        for url in [
+            "http://api/v1/products/_search",
+            "http://api/Products/_search",
+            "http://api/products/_search/extra",
+            "http://api/_doc/42",
+            "http://api/orders/_doc/42/history",
+        ] {
+            let t = make_trace(HttpMethod::Get, url, "");
+            assert_eq!(EsOperation::detect(&t), None, "{url}");
+        }
+    }
+
+    #[test]
+    fn test_product_header_accepts_any_endpoint_path() {
+        let mut t = make_trace(HttpMethod::Get, "http://gw/es/v1/products/_search", "");
+        t.response_headers
+            .insert("X-Elastic-Product".into(), "Elasticsearch".into());
+        let op = EsOperation::detect(&t).unwrap();
+        assert_eq!(op.api, "_search");
+        assert_eq!(op.index.as_deref(), Some("es/v1/products"));
+    }
+
+    #[test]
+    fn test_index_patterns_are_rest_paths() {
+        let t = make_trace(
+            HttpMethod::Get,
+            "http://es:9200/logs-*,metrics-2024.06/_search",
+            "",
+        );
+        let op = EsOperation::detect(&t).unwrap();
+        assert_eq!(op.index.as_deref(), Some("logs-*,metrics-2024.06"));
+    }
+
+    #[test]
+    fn test_summary_is_capped() {
+        let clauses: Vec<String> = (0..60)
+            .map(|i| format!(r#"{{"term":{{"field_{i}":1}}}}"#))
+            .collect();
+        let body = format!(
+            r#"{{"query":{{"bool":{{"should":[{}]}}}}}}"#,
+            clauses.join(",")
+        );
+        let t = make_trace(HttpMethod::Post, "http://es/_search", &body);
+        let summary = EsOperation::detect(&t).unwrap().query_summary.unwrap();
+        assert!(summary.len() <= MAX_SUMMARY_LEN);
+        assert!(summary.ends_with('…'));
+    }
+}
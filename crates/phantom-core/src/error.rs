@@ -20,4 +20,6 @@ pub enum StorageError {
     Read(String),
     #[error("serialization error: {0}")]
     Serialization(String),
+    #[error("integrity check failed: {0}")]
+    Integrity(String),
 }
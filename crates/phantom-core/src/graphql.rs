@@ -0,0 +1,304 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::trace::{HttpMethod, HttpTrace, url_path};
+
+/// Kind of a GraphQL operation definition.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GraphQlOperationType {
+    Query,
+    Mutation,
+    Subscription,
+}
+
+impl fmt::Display for GraphQlOperationType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::Query => "query",
+            Self::Mutation => "mutation",
+            Self::Subscription => "subscription",
+        };
+        f.write_str(s)
+    }
+}
+
+/// Summary of a GraphQL request recognized from a trace.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GraphQlOperation {
+    /// Operation type from the query document; `None` when the request
+    /// carries no document (e.g. an automatic persisted query).
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub operation_type: Option<GraphQlOperationType>,
+    /// `operationName`, falling back to the name in the document.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+impl GraphQlOperation {
+    /// Recognizes a JSON `POST` to a `/graphql` path. For batched requests
+    /// (a JSON array) the first operation is reported.
+    pub fn detect(trace: &HttpTrace) -> Option<Self> {
+        if trace.method != HttpMethod::Post
+            || !url_path(&trace.url)
+                .to_ascii_lowercase()
+                .contains("/graphql")
+        {
+            return None;
+        }
+        let body: Value = serde_json::from_slice(trace.request_body.as_deref()?).ok()?;
+        let request = match &body {
+            Value::Array(batch) => batch.first()?,
+            other => other,
+        };
+        let operation_name = request
+            .get("operationName")
+            .and_then(Value::as_str)
+            .filter(|n| !n.is_empty());
+        let query = request.get("query").and_then(Value::as_str);
+        if operation_name.is_none() && query.is_none() {
+            return None;
+        }
+
+        let operations = query.map(operations).unwrap_or_default();
+        // With several operations in the document, `operationName` picks one.
+        let selected = match operation_name {
+            Some(name) => operations.iter().find(|(_, n)| n.as_deref() == Some(name)),
+            None => operations.first(),
+        };
+        Some(Self {
+            operation_type: selected.map(|(t, _)| *t),
+            name: operation_name
+                .map(String::from)
+                .or_else(|| selected.and_then(|(_, n)| n.clone())),
+        })
+    }
+
+    /// One-line label for list views, e.g. `"mutation CreateOrder"`.
+    pub fn label(&self) -> String {
+        let kind = self
+            .operation_type
+            .map_or_else(|| "graphql".to_string(), |t| t.to_string());
+        match &self.name {
+            Some(name) => format!("{kind} {name}"),
+            None => kind,
+        }
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Document scanning
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// A token outside any selection set or argument list.
+enum TopToken<'a> {
+    Name(&'a str),
+    /// Opening brace of a top-level selection set.
+    Block,
+    Punct,
+}
+
+/// Top-level tokens of a GraphQL document. Strings, comments, and anything
+/// inside braces or parentheses are skipped.
+fn top_level_tokens(doc: &str) -> Vec<TopToken<'_>> {
+    let bytes = doc.as_bytes();
+    let mut tokens = Vec::new();
+    let (mut braces, mut parens) = (0usize, 0usize);
+    let mut i = 0;
+    while i < bytes.len() {
+        let top = braces == 0 && parens == 0;
+        match bytes[i] {
+            b'#' => {
+                i = doc[i..].find('\n').map_or(bytes.len(), |n| i + n);
+                continue;
+            }
+            b'"' if doc[i..].starts_with("\"\"\"") => {
+                i = doc[i + 3..]
+                    .find("\"\"\"")
+                    .map_or(bytes.len(), |n| i + n + 6);
+                continue;
+            }
+            b'"' => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != b'"' && bytes[i] != b'\n' {
+                    i += if bytes[i] == b'\\' { 2 } else { 1 };
+                }
+            }
+            b'{' => {
+                if top {
+                    tokens.push(TopToken::Block);
+                }
+                braces += 1;
+            }
+            b'}' => braces = braces.saturating_sub(1),
+            b'(' => parens += 1,
+            b')' => parens = parens.saturating_sub(1),
+            b if b == b'_' || b.is_ascii_alphabetic() => {
+                let start = i;
+                while i < bytes.len() && (bytes[i] == b'_' || bytes[i].is_ascii_alphanumeric()) {
+                    i += 1;
+                }
+                if top {
+                    tokens.push(TopToken::Name(&doc[start..i]));
+                }
+                continue;
+            }
+            b if b.is_ascii_whitespace() || b == b',' => {}
+            _ => {
+                if top {
+                    tokens.push(TopToken::Punct);
+                }
+            }
+        }
+        i += 1;
+    }
+    tokens
+}
+
+/// Operation definitions of a document, in order, with their names. A bare
+/// selection set (`{ me { id } }`) is an anonymous query; fragments are
+/// skipped.
+fn operations(doc: &str) -> Vec<(GraphQlOperationType, Option<String>)> {
+    enum State {
+        /// Expecting the start of a definition.
+        Start,
+        /// After an operation keyword, expecting an optional name.
+        Keyword(GraphQlOperationType),
+        /// Inside a definition's header, until its selection set.
+        Header,
+    }
+
+    let mut ops = Vec::new();
+    let mut state = State::Start;
+    for token in top_level_tokens(doc) {
+        state = match (state, token) {
+            (State::Start, TopToken::Name(word)) => match word {
+                "query" => State::Keyword(GraphQlOperationType::Query),
+                "mutation" => State::Keyword(GraphQlOperationType::Mutation),
+                "subscription" => State::Keyword(GraphQlOperationType::Subscription),
+                _ => State::Header,
+            },
+            (State::Start, TopToken::Block) => {
+                ops.push((GraphQlOperationType::Query, None));
+                State::Start
+            }
+            (State::Keyword(kind), TopToken::Name(name)) => {
+                ops.push((kind, Some(name.to_string())));
+                State::Header
+            }
+            (State::Keyword(kind), TopToken::Block) => {
+                ops.push((kind, None));
+                State::Start
+            }
+            (State::Keyword(kind), TopToken::Punct) => {
+                ops.push((kind, None));
+                State::Header
+            }
+            (_, TopToken::Block) => State::Start,
+            (state, _) => state,
+        };
+    }
+    ops
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::*;
+    use crate::trace::{SpanId, TraceId};
+
+    fn make_trace(method: HttpMethod, url: &str, body: &str) -> HttpTrace {
+        HttpTrace {
+            span_id: SpanId([1; 8]),
+            trace_id: TraceId([2; 16]),
+            parent_span_id: None,
+            method,
+            url: url.to_string(),
+            request_headers: HashMap::new(),
+            request_body: (!body.is_empty()).then(|| body.as_bytes().to_vec()),
+            status_code: 200,
+            response_headers: HashMap::new(),
+            response_body: None,
+            timestamp: UNIX_EPOCH,
+            duration: Duration::from_millis(5),
+            source_addr: None,
+            dest_addr: None,
+            protocol_version: "HTTP/1.1".to_string(),
+            request_body_file: None,
+            response_body_file: None,
+            connection_id: None,
+            process: None,
+            response_trailers: HashMap::new(),
+            request_body_encoding: None,
+            response_body_encoding: None,
+            elasticsearch: None,
+            soap: None,
+            graphql: None,
+            thread_id: None,
+            tls: None,
+            timing: None,
+            request_size: None,
+            response_size: None,
+            fault: None,
+            replay: None,
+        }
+    }
+
+    fn detect(body: &str) -> Option<GraphQlOperation> {
+        GraphQlOperation::detect(&make_trace(
+            HttpMethod::Post,
+            "http://api.local/v1/graphql?x=1",
+            body,
+        ))
+    }
+
+    #[test]
+    fn test_detect_named_mutation() {
+        let op = detect(
+            r#"{"query":"mutation CreateOrder($input: OrderInput!) { createOrder(input: $input) { id } }","variables":{}}"#,
+        )
+        .unwrap();
+        assert_eq!(op.operation_type, Some(GraphQlOperationType::Mutation));
+        assert_eq!(op.name.as_deref(), Some("CreateOrder"));
+        assert_eq!(op.label(), "mutation CreateOrder");
+    }
+
+    #[test]
+    fn test_detect_operation_name_selects_definition() {
+        let op = detect(
+            r##"{"operationName":"Watch","query":"# comment { x }\nquery Get { a(s: \"{\") }\nfragment F on T { b }\nsubscription Watch { c }"}"##,
+        )
+        .unwrap();
+        assert_eq!(op.operation_type, Some(GraphQlOperationType::Subscription));
+        assert_eq!(op.name.as_deref(), Some("Watch"));
+    }
+
+    #[test]
+    fn test_detect_anonymous_and_persisted() {
+        let op = detect(r#"{"query":"{ me { id } }"}"#).unwrap();
+        assert_eq!(op.operation_type, Some(GraphQlOperationType::Query));
+        assert_eq!(op.name, None);
+        assert_eq!(op.label(), "query");
+
+        let op =
+            detect(r#"[{"operationName":"Feed","extensions":{"persistedQuery":{"version":1}}}]"#)
+                .unwrap();
+        assert_eq!(op.operation_type, None);
+        assert_eq!(op.label(), "graphql Feed");
+    }
+
+    #[test]
+    fn test_non_graphql_requests_are_ignored() {
+        let body = r#"{"query":"{ me { id } }"}"#;
+        let get = make_trace(HttpMethod::Get, "http://api.local/graphql", body);
+        assert!(GraphQlOperation::detect(&get).is_none());
+        let rest = make_trace(HttpMethod::Post, "http://api.local/orders", body);
+        assert!(GraphQlOperation::detect(&rest).is_none());
+        assert!(detect("not json").is_none());
+        assert!(detect(r#"{"variables":{}}"#).is_none());
+    }
+}
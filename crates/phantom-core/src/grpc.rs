@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+use crate::trace::HttpTrace;
+
+/// A gRPC call: one request stream matched to its response and status.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GrpcTrace {
+    /// Fully-qualified service name, e.g. `"helloworld.Greeter"`.
+    pub service: String,
+    /// Method name, e.g. `"SayHello"`.
+    pub method: String,
+    /// Host (and port) the call was addressed to, from `:authority`.
+    pub authority: Option<String>,
+    /// `grpc-status` code (`0` OK, `5` NOT_FOUND, `14` UNAVAILABLE, …);
+    /// `None` when the stream ended without one.
+    pub status: Option<u32>,
+    /// `grpc-message` of a failed call, as sent (percent-encoded).
+    pub message: Option<String>,
+    /// Length-prefixed messages the client sent; more than one for client
+    /// and bidirectional streaming.
+    #[serde(default)]
+    pub request_messages: u32,
+    /// Length-prefixed messages the server sent.
+    #[serde(default)]
+    pub response_messages: u32,
+    /// Wall-clock time the call started.
+    pub timestamp: SystemTime,
+    /// Time from the request headers to the status.
+    pub duration: Duration,
+    /// Destination socket address, if available.
+    pub dest_addr: Option<String>,
+}
+
+impl GrpcTrace {
+    /// Reads a gRPC call out of an HTTP trace: a request with an
+    /// `application/grpc` content type to a `/package.Service/Method` path.
+    /// Message counts come from the captured bodies, so they undercount
+    /// bodies cut short by the capture limit.
+    pub fn from_http(trace: &HttpTrace) -> Option<Self> {
+        let content_type = header(&trace.request_headers, "content-type")?;
+        if !content_type.starts_with("application/grpc") {
+            return None;
+        }
+        let (authority, path) = split_url(&trace.url);
+        let (service, method) = path.strip_prefix('/')?.split_once('/')?;
+        if service.is_empty() || method.is_empty() || method.contains('/') {
+            return None;
+        }
+        // A trailers-only response carries the status in its headers.
+        let status_header = |name: &str| {
+            header(&trace.response_trailers, name).or_else(|| header(&trace.response_headers, name))
+        };
+        Some(Self {
+            service: service.to_string(),
+            method: method.to_string(),
+            authority: (!authority.is_empty()).then(|| authority.to_string()),
+            status: status_header("grpc-status").and_then(|s| s.trim().parse().ok()),
+            message: status_header("grpc-message")
+                .filter(|m| !m.is_empty())
+                .map(String::from),
+            request_messages: count_messages(trace.request_body.as_deref()),
+            response_messages: count_messages(trace.response_body.as_deref()),
+            timestamp: trace.timestamp,
+            duration: trace.duration,
+            dest_addr: trace.dest_addr.clone(),
+        })
+    }
+
+    /// `"package.Service/Method"`, as gRPC tooling names a call.
+    pub fn full_method(&self) -> String {
+        format!("{}/{}", self.service, self.method)
+    }
+
+    /// Name of the status code, e.g. `"NOT_FOUND"`.
+    pub fn status_name(&self) -> Option<&'static str> {
+        self.status.and_then(status_name)
+    }
+}
+
+/// Name of a gRPC status code, `None` for codes outside the spec.
+pub fn status_name(code: u32) -> Option<&'static str> {
+    const NAMES: [&str; 17] = [
+        "OK",
+        "CANCELLED",
+        "UNKNOWN",
+        "INVALID_ARGUMENT",
+        "DEADLINE_EXCEEDED",
+        "NOT_FOUND",
+        "ALREADY_EXISTS",
+        "PERMISSION_DENIED",
+        "RESOURCE_EXHAUSTED",
+        "FAILED_PRECONDITION",
+        "ABORTED",
+        "OUT_OF_RANGE",
+        "UNIMPLEMENTED",
+        "INTERNAL",
+        "UNAVAILABLE",
+        "DATA_LOSS",
+        "UNAUTHENTICATED",
+    ];
+    NAMES.get(code as usize).copied()
+}
+
+fn header<'a>(headers: &'a HashMap<String, String>, name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.as_str())
+}
+
+/// Splits a full URL into its authority and its path (without query
+/// string).
+fn split_url(url: &str) -> (&str, &str) {
+    let after_scheme = url.split_once("://").map(|(_, rest)| rest).unwrap_or(url);
+    let (authority, path) = match after_scheme.find('/') {
+        Some(i) => after_scheme.split_at(i),
+        None => (after_scheme, "/"),
+    };
+    (authority, path.split(['?', '#']).next().unwrap_or(path))
+}
+
+/// Counts the complete length-prefixed messages (1-byte compressed flag,
+/// 4-byte big-endian length, payload) at the start of a gRPC body.
+fn count_messages(body: Option<&[u8]>) -> u32 {
+    let mut rest = body.unwrap_or_default();
+    let mut count = 0;
+    while let Some(prefix) = rest.get(..5) {
+        let len = u32::from_be_bytes([prefix[1], prefix[2], prefix[3], prefix[4]]) as usize;
+        let Some(next) = rest.get(5 + len..) else {
+            break;
+        };
+        count += 1;
+        rest = next;
+    }
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::UNIX_EPOCH;
+
+    use super::*;
+    use crate::trace::{HttpMethod, SpanId, TraceId};
+
+    fn make_trace(url: &str, content_type: &str) -> HttpTrace {
+        HttpTrace {
+            span_id: SpanId([1; 8]),
+            trace_id: TraceId([2; 16]),
+            parent_span_id: None,
+            method: HttpMethod::Post,
+            url: url.to_string(),
+            request_headers: HashMap::from([(
+                "content-type".to_string(),
+                content_type.to_string(),
+            )]),
+            request_body: None,
+            status_code: 200,
+            response_headers: HashMap::new(),
+            response_body: None,
+            timestamp: UNIX_EPOCH,
+            duration: Duration::from_millis(5),
+            source_addr: None,
+            dest_addr: Some("10.0.0.7:50051".to_string()),
+            protocol_version: "HTTP/2".to_string(),
+            request_body_file: None,
+            response_body_file: None,
+            connection_id: None,
+            process: None,
+            response_trailers: HashMap::new(),
+            request_body_encoding: None,
+            response_body_encoding: None,
+            elasticsearch: None,
+            soap: None,
+            graphql: None,
+            thread_id: None,
+            tls: None,
+            timing: None,
+            request_size: None,
+            response_size: None,
+            fault: None,
+            replay: None,
+        }
+    }
+
+    /// A length-prefixed, uncompressed gRPC message.
+    fn frame(payload: &[u8]) -> Vec<u8> {
+        let mut out = vec![0];
+        out.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        out.extend_from_slice(payload);
+        out
+    }
+
+    #[test]
+    fn test_from_http_reads_call_and_status() {
+        let mut trace = make_trace(
+            "http://greeter.local:50051/helloworld.Greeter/SayHello",
+            "application/grpc+proto",
+        );
+        trace.request_body = Some(frame(b"\x0a\x03ada"));
+        trace.response_body = Some([frame(b"a"), frame(b"bc")].concat());
+        trace
+            .response_trailers
+            .insert("grpc-status".to_string(), "5".to_string());
+        trace
+            .response_trailers
+            .insert("grpc-message".to_string(), "user%20not%20found".to_string());
+
+        let grpc = GrpcTrace::from_http(&trace).unwrap();
+        assert_eq!(grpc.service, "helloworld.Greeter");
+        assert_eq!(grpc.method, "SayHello");
+        assert_eq!(grpc.full_method(), "helloworld.Greeter/SayHello");
+        assert_eq!(grpc.authority.as_deref(), Some("greeter.local:50051"));
+        assert_eq!(grpc.status, Some(5));
+        assert_eq!(grpc.status_name(), Some("NOT_FOUND"));
+        assert_eq!(grpc.message.as_deref(), Some("user%20not%20found"));
+        assert_eq!(grpc.request_messages, 1);
+        assert_eq!(grpc.response_messages, 2);
+        assert_eq!(grpc.dest_addr.as_deref(), Some("10.0.0.7:50051"));
+    }
+
+    #[test]
+    fn test_trailers_only_status_and_truncated_body() {
+        let mut trace = make_trace("https://api.local/pkg.Svc/Get", "application/grpc");
+        trace
+            .response_headers
+            .insert("Grpc-Status".to_string(), "14".to_string());
+        let mut body = frame(b"first");
+        body.extend_from_slice(&frame(b"second")[..4]);
+        trace.request_body = Some(body);
+
+        let grpc = GrpcTrace::from_http(&trace).unwrap();
+        assert_eq!(grpc.status_name(), Some("UNAVAILABLE"));
+        assert_eq!(grpc.message, None);
+        assert_eq!(grpc.request_messages, 1);
+        assert_eq!(grpc.response_messages, 0);
+    }
+
+    #[test]
+    fn test_non_grpc_requests_are_ignored() {
+        let json = make_trace("https://api.local/pkg.Svc/Get", "application/json");
+        assert!(GrpcTrace::from_http(&json).is_none());
+        let bad_path = make_trace("https://api.local/v1/pkg.Svc/Get", "application/grpc");
+        assert!(GrpcTrace::from_http(&bad_path).is_none());
+        let no_method = make_trace("https://api.local/pkg.Svc", "application/grpc");
+        assert!(GrpcTrace::from_http(&no_method).is_none());
+    }
+
+    #[test]
+    fn test_status_names() {
+        assert_eq!(status_name(0), Some("OK"));
+        assert_eq!(status_name(16), Some("UNAUTHENTICATED"));
+        assert_eq!(status_name(17), None);
+    }
+}
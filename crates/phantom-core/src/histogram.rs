@@ -0,0 +1,236 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::Serialize;
+
+use crate::report::{normalize_endpoint, percentile};
+use crate::trace::HttpTrace;
+
+/// Percentiles reported for every endpoint, as `(label, percentile)`.
+const PERCENTILES: &[(&str, f64)] = &[
+    ("p50", 50.0),
+    ("p75", 75.0),
+    ("p90", 90.0),
+    ("p95", 95.0),
+    ("p99", 99.0),
+    ("p99_9", 99.9),
+    ("p99_99", 99.99),
+];
+
+/// Latency distributions per endpoint, the data behind
+/// `phantom export --format histogram`. Values are in microseconds, the unit
+/// HdrHistogram tooling expects.
+#[derive(Debug, Clone, Serialize)]
+pub struct HistogramExport {
+    pub unit: &'static str,
+    /// Distribution across every exported trace.
+    pub overall: LatencyHistogram,
+    /// One distribution per `METHOD endpoint`, most requests first.
+    pub endpoints: Vec<EndpointHistogram>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EndpointHistogram {
+    pub method: String,
+    /// Endpoint with ID-like segments collapsed, as in `phantom report`.
+    pub endpoint: String,
+    #[serde(flatten)]
+    pub histogram: LatencyHistogram,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LatencyHistogram {
+    pub count: u64,
+    pub min: u64,
+    pub max: u64,
+    pub mean: f64,
+    pub stddev: f64,
+    /// Nearest-rank percentiles keyed `p50` … `p99_99`.
+    pub percentiles: BTreeMap<String, u64>,
+    /// Percentile distribution in HdrHistogram's `outputPercentileDistribution`
+    /// layout: the step towards 100% halves at every row.
+    pub distribution: Vec<DistributionRow>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DistributionRow {
+    pub value: u64,
+    /// Fraction of requests at or below `value`, in `0.0..=1.0`.
+    pub percentile: f64,
+    pub total_count: u64,
+}
+
+impl LatencyHistogram {
+    /// Builds a histogram from latencies in any order.
+    pub fn from_values(mut values: Vec<u64>) -> Self {
+        if values.is_empty() {
+            return Self::default();
+        }
+        values.sort_unstable();
+        let count = values.len() as u64;
+        let mean = values.iter().sum::<u64>() as f64 / count as f64;
+        let variance = values
+            .iter()
+            .map(|&v| (v as f64 - mean).powi(2))
+            .sum::<f64>()
+            / count as f64;
+        Self {
+            count,
+            min: values[0],
+            max: values[values.len() - 1],
+            mean,
+            stddev: variance.sqrt(),
+            percentiles: PERCENTILES
+                .iter()
+                .map(|&(label, p)| (label.to_string(), percentile(&values, p)))
+                .collect(),
+            distribution: distribution(&values),
+        }
+    }
+}
+
+/// Rows at 0%, 50%, 75%, 87.5%, … until every value is covered, then 100%.
+fn distribution(sorted: &[u64]) -> Vec<DistributionRow> {
+    let total = sorted.len() as u64;
+    let mut rows = Vec::new();
+    let mut p = 0.0_f64;
+    loop {
+        let value = percentile(sorted, p * 100.0);
+        let total_count = sorted.partition_point(|&v| v <= value) as u64;
+        if total_count == total {
+            break;
+        }
+        rows.push(DistributionRow {
+            value,
+            percentile: total_count as f64 / total as f64,
+            total_count,
+        });
+        p += (1.0 - p) / 2.0;
+    }
+    rows.push(DistributionRow {
+        value: sorted[sorted.len() - 1],
+        percentile: 1.0,
+        total_count: total,
+    });
+    rows
+}
+
+fn micros(trace: &HttpTrace) -> u64 {
+    trace.duration.as_micros() as u64
+}
+
+impl HistogramExport {
+    pub fn build(traces: &[HttpTrace]) -> Self {
+        let mut groups: HashMap<(String, String), Vec<u64>> = HashMap::new();
+        for t in traces {
+            groups
+                .entry((t.method.to_string(), normalize_endpoint(&t.url)))
+                .or_default()
+                .push(micros(t));
+        }
+        let mut endpoints: Vec<EndpointHistogram> = groups
+            .into_iter()
+            .map(|((method, endpoint), values)| EndpointHistogram {
+                method,
+                endpoint,
+                histogram: LatencyHistogram::from_values(values),
+            })
+            .collect();
+        endpoints.sort_by(|a, b| {
+            b.histogram
+                .count
+                .cmp(&a.histogram.count)
+                .then_with(|| a.endpoint.cmp(&b.endpoint))
+                .then_with(|| a.method.cmp(&b.method))
+        });
+        Self {
+            unit: "us",
+            overall: LatencyHistogram::from_values(traces.iter().map(micros).collect()),
+            endpoints,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::time::{Duration, SystemTime};
+
+    use super::*;
+    use crate::trace::{HttpMethod, SpanId, TraceId};
+
+    fn make_trace(url: &str, duration_us: u64) -> HttpTrace {
+        HttpTrace {
+            span_id: SpanId([1; 8]),
+            trace_id: TraceId([2; 16]),
+            parent_span_id: None,
+            method: HttpMethod::Get,
+            url: url.to_string(),
+            request_headers: HashMap::new(),
+            request_body: None,
+            status_code: 200,
+            response_headers: HashMap::new(),
+            response_body: None,
+            timestamp: SystemTime::UNIX_EPOCH,
+            duration: Duration::from_micros(duration_us),
+            source_addr: None,
+            dest_addr: None,
+            protocol_version: "HTTP/1.1".to_string(),
+            request_body_file: None,
+            response_body_file: None,
+            connection_id: None,
+            process: None,
+            response_trailers: HashMap::new(),
+            request_body_encoding: None,
+            response_body_encoding: None,
+            elasticsearch: None,
+            soap: None,
+            graphql: None,
+            thread_id: None,
+            tls: None,
+            timing: None,
+            request_size: None,
+            response_size: None,
+            fault: None,
+            replay: None,
+        }
+    }
+
+    #[test]
+    fn test_histogram_summary_and_percentiles() {
+        let h = LatencyHistogram::from_values((1..=100).rev().collect());
+        assert_eq!((h.count, h.min, h.max), (100, 1, 100));
+        assert_eq!(h.mean, 50.5);
+        assert_eq!(h.percentiles["p50"], 50);
+        assert_eq!(h.percentiles["p99"], 99);
+        assert_eq!(h.percentiles["p99_99"], 100);
+    }
+
+    #[test]
+    fn test_distribution_halves_towards_max() {
+        let h = LatencyHistogram::from_values((1..=8).collect());
+        let values: Vec<u64> = h.distribution.iter().map(|r| r.value).collect();
+        assert_eq!(values, [1, 4, 6, 7, 8]);
+        let last = h.distribution.last().unwrap();
+        assert_eq!((last.percentile, last.total_count), (1.0, 8));
+        assert!(
+            LatencyHistogram::from_values(vec![])
+                .distribution
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_export_groups_by_endpoint() {
+        let traces = vec![
+            make_trace("http://h/users/1", 1_000),
+            make_trace("http://h/users/2", 3_000),
+            make_trace("http://h/health", 50),
+        ];
+        let export = HistogramExport::build(&traces);
+        assert_eq!(export.unit, "us");
+        assert_eq!(export.overall.count, 3);
+        assert_eq!(export.endpoints[0].endpoint, "h/users/:id");
+        assert_eq!(export.endpoints[0].histogram.max, 3_000);
+        assert_eq!(export.endpoints[1].histogram.count, 1);
+    }
+}
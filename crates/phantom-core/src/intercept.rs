@@ -0,0 +1,196 @@
+use tokio::sync::oneshot;
+
+use crate::trace::HttpMethod;
+
+/// A request the proxy holds until the user lets it through or drops it.
+///
+/// `headers` and `body` may be edited before [`forward`](Self::forward).
+/// Dropping the value without deciding forwards the request unchanged, so
+/// nothing stays held once the UI is gone.
+#[derive(Debug)]
+pub struct InterceptedRequest {
+    pub method: HttpMethod,
+    pub url: String,
+    /// Header lines in the order they will be sent.
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    /// Whether `headers` or `body` differ from what the client sent.
+    pub edited: bool,
+    decision: Option<oneshot::Sender<InterceptDecision>>,
+}
+
+/// What becomes of an intercepted request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InterceptDecision {
+    /// Send the request upstream with these headers and body.
+    Forward {
+        headers: Vec<(String, String)>,
+        body: Vec<u8>,
+        edited: bool,
+    },
+    /// Answer the client without forwarding.
+    Drop,
+}
+
+impl InterceptedRequest {
+    /// A held request and the receiver its decision arrives on.
+    pub fn new(
+        method: HttpMethod,
+        url: String,
+        headers: Vec<(String, String)>,
+        body: Vec<u8>,
+    ) -> (Self, oneshot::Receiver<InterceptDecision>) {
+        let (tx, rx) = oneshot::channel();
+        let request = Self {
+            method,
+            url,
+            headers,
+            body,
+            edited: false,
+            decision: Some(tx),
+        };
+        (request, rx)
+    }
+
+    /// Lets the request through with its current headers and body.
+    pub fn forward(mut self) {
+        self.decide_forward();
+    }
+
+    /// Answers the client without forwarding the request.
+    pub fn drop_request(mut self) {
+        self.decide(InterceptDecision::Drop);
+    }
+
+    fn decide_forward(&mut self) {
+        let decision = InterceptDecision::Forward {
+            headers: std::mem::take(&mut self.headers),
+            body: std::mem::take(&mut self.body),
+            edited: self.edited,
+        };
+        self.decide(decision);
+    }
+
+    fn decide(&mut self, decision: InterceptDecision) {
+        if let Some(tx) = self.decision.take() {
+            // The client may have gone away meanwhile; nothing to tell then.
+            let _ = tx.send(decision);
+        }
+    }
+
+    /// The request as editable text: header lines, a blank line, then the
+    /// body (lossily decoded as UTF-8).
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for (name, value) in &self.headers {
+            text.push_str(&format!("{name}: {value}\n"));
+        }
+        text.push('\n');
+        text.push_str(&String::from_utf8_lossy(&self.body));
+        text
+    }
+
+    /// Replaces headers and body with those in `text` (see [`to_text`]),
+    /// marking the request edited if they changed. Lines without a `:` in
+    /// the header block are rejected.
+    ///
+    /// [`to_text`]: Self::to_text
+    pub fn apply_text(&mut self, text: &str) -> Result<(), String> {
+        let text = text.replace("\r\n", "\n");
+        // No headers leaves the text starting with the blank line.
+        let (head, body) = match text.strip_prefix('\n') {
+            Some(body) => ("", body),
+            None => text.split_once("\n\n").unwrap_or((text.as_str(), "")),
+        };
+        let mut headers = Vec::new();
+        for line in head.lines().filter(|line| !line.trim().is_empty()) {
+            let (name, value) = line
+                .split_once(':')
+                .ok_or_else(|| format!("invalid header line {line:?}: expected NAME: VALUE"))?;
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+        let body = body.as_bytes().to_vec();
+        if headers != self.headers || body != self.body {
+            self.headers = headers;
+            self.body = body;
+            self.edited = true;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for InterceptedRequest {
+    fn drop(&mut self) {
+        if self.decision.is_some() {
+            self.decide_forward();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn held() -> (InterceptedRequest, oneshot::Receiver<InterceptDecision>) {
+        InterceptedRequest::new(
+            HttpMethod::Post,
+            "http://api.test/users".to_string(),
+            vec![("content-type".to_string(), "application/json".to_string())],
+            br#"{"name":"a"}"#.to_vec(),
+        )
+    }
+
+    #[test]
+    fn test_forward_sends_current_request() {
+        let (request, mut rx) = held();
+        request.forward();
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            InterceptDecision::Forward {
+                headers: vec![("content-type".to_string(), "application/json".to_string())],
+                body: br#"{"name":"a"}"#.to_vec(),
+                edited: false,
+            }
+        );
+    }
+
+    #[test]
+    fn test_drop_request() {
+        let (request, mut rx) = held();
+        request.drop_request();
+        assert_eq!(rx.try_recv().unwrap(), InterceptDecision::Drop);
+    }
+
+    #[test]
+    fn test_undecided_request_is_forwarded() {
+        let (request, mut rx) = held();
+        drop(request);
+        assert!(matches!(
+            rx.try_recv().unwrap(),
+            InterceptDecision::Forward { edited: false, .. }
+        ));
+    }
+
+    #[test]
+    fn test_text_round_trip_and_edit() {
+        let (mut request, _rx) = held();
+        let text = request.to_text();
+        assert_eq!(text, "content-type: application/json\n\n{\"name\":\"a\"}");
+        request.apply_text(&text).unwrap();
+        assert!(!request.edited);
+
+        request
+            .apply_text("content-type: application/json\nx-debug: 1\n\n{\"name\":\"b\"}")
+            .unwrap();
+        assert!(request.edited);
+        assert_eq!(request.headers.len(), 2);
+        assert_eq!(request.body, br#"{"name":"b"}"#);
+    }
+
+    #[test]
+    fn test_apply_text_rejects_bad_header_line() {
+        let (mut request, _rx) = held();
+        assert!(request.apply_text("not a header\n\nbody").is_err());
+        assert!(!request.edited);
+    }
+}
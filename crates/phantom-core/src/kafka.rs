@@ -0,0 +1,53 @@
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+/// A Kafka request matched to its response by correlation ID.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KafkaTrace {
+    /// Numeric API key (`0` Produce, `1` Fetch, `3` Metadata, …).
+    pub api_key: i16,
+    /// API name, e.g. `"Produce"`; `"ApiKey(N)"` for keys the agent does not
+    /// know.
+    pub api: String,
+    pub api_version: i16,
+    pub correlation_id: i32,
+    /// `client.id` from the request header.
+    pub client_id: Option<String>,
+    /// Topics named by a Produce, Fetch or Metadata request. Fetch v13+
+    /// identifies topics by ID only and reports none.
+    #[serde(default)]
+    pub topics: Vec<String>,
+    /// First non-zero error code found in the response, e.g. `3` for
+    /// `UNKNOWN_TOPIC_OR_PARTITION`.
+    pub error_code: Option<i16>,
+    /// Produce with `acks=0`: the broker sends no response and the duration
+    /// is zero.
+    #[serde(default)]
+    pub no_response: bool,
+    /// Wall-clock time the request was sent.
+    pub timestamp: SystemTime,
+    /// Time from request to response.
+    pub duration: Duration,
+    /// Destination socket address, if available.
+    pub dest_addr: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kafka_trace_defaults_optional_fields() {
+        let json = r#"{
+            "api_key": 3, "api": "Metadata", "api_version": 12, "correlation_id": 7,
+            "client_id": null, "error_code": null,
+            "timestamp": {"secs_since_epoch": 0, "nanos_since_epoch": 0},
+            "duration": {"secs": 0, "nanos": 1000000}, "dest_addr": null
+        }"#;
+        let trace: KafkaTrace = serde_json::from_str(json).unwrap();
+        assert!(trace.topics.is_empty());
+        assert!(!trace.no_response);
+        assert_eq!(trace.duration, Duration::from_millis(1));
+    }
+}
@@ -1,6 +1,24 @@
 pub mod capture;
+pub mod connection;
+pub mod elasticsearch;
 pub mod error;
+pub mod graphql;
+pub mod grpc;
+pub mod histogram;
+pub mod intercept;
+pub mod kafka;
+pub mod postgres;
+pub mod protocol;
 pub mod query;
+pub mod redis;
+pub mod report;
+pub mod sink;
+pub mod slo;
+pub mod soap;
+pub mod sse;
+pub mod stitch;
 pub mod storage;
+pub mod thrift;
 pub mod trace;
 pub mod view;
+pub mod websocket;
@@ -0,0 +1,67 @@
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+/// Fields of a PostgreSQL `ErrorResponse` message.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PostgresError {
+    /// Non-localized severity (`"ERROR"`, `"FATAL"`, `"PANIC"`).
+    pub severity: String,
+    /// SQLSTATE code, e.g. `"42P01"` for an undefined table.
+    pub code: String,
+    /// Primary human-readable message.
+    pub message: String,
+}
+
+/// A completed PostgreSQL query: a simple `Query` or an extended-protocol
+/// `Execute`, matched to the server's completion or error.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PostgresTrace {
+    /// SQL text as sent by the client (truncated by the agent at 16 KB).
+    pub query: String,
+    /// Command of the completion tag (`"SELECT"`, `"INSERT"`, …), if the
+    /// query completed.
+    pub command: Option<String>,
+    /// Rows returned or affected, from the completion tag or counted rows.
+    pub rows: Option<u64>,
+    /// Server error, when the query failed.
+    pub error: Option<PostgresError>,
+    /// Database named in the startup message.
+    pub database: Option<String>,
+    /// User named in the startup message.
+    pub user: Option<String>,
+    /// Wall-clock time the query was sent.
+    pub timestamp: SystemTime,
+    /// Time from query to completion.
+    pub duration: Duration,
+    /// Destination socket address, if available.
+    pub dest_addr: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_postgres_trace_roundtrip() {
+        let trace = PostgresTrace {
+            query: "select * from missing".to_string(),
+            command: None,
+            rows: None,
+            error: Some(PostgresError {
+                severity: "ERROR".to_string(),
+                code: "42P01".to_string(),
+                message: "relation \"missing\" does not exist".to_string(),
+            }),
+            database: Some("app".to_string()),
+            user: Some("postgres".to_string()),
+            timestamp: SystemTime::UNIX_EPOCH,
+            duration: Duration::from_millis(3),
+            dest_addr: Some("127.0.0.1:5432".to_string()),
+        };
+        let json = serde_json::to_string(&trace).unwrap();
+        let parsed: PostgresTrace = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.error, trace.error);
+        assert_eq!(parsed.database.as_deref(), Some("app"));
+    }
+}
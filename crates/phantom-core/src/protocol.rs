@@ -0,0 +1,26 @@
+use serde::{Deserialize, Serialize};
+
+use crate::grpc::GrpcTrace;
+use crate::kafka::KafkaTrace;
+use crate::postgres::PostgresTrace;
+use crate::redis::RedisTrace;
+use crate::sse::SseEvent;
+use crate::thrift::ThriftTrace;
+use crate::websocket::WebSocketFrame;
+
+/// A captured exchange on a non-HTTP protocol.
+///
+/// Capture backends that understand more than HTTP deliver these as
+/// [`TraceEvent::Protocol`](crate::capture::TraceEvent::Protocol) on the same
+/// channel as their HTTP traces.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum ProtocolTrace {
+    Thrift(ThriftTrace),
+    Postgres(PostgresTrace),
+    Redis(RedisTrace),
+    Kafka(KafkaTrace),
+    Grpc(GrpcTrace),
+    WebSocket(WebSocketFrame),
+    Sse(SseEvent),
+}
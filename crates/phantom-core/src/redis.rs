@@ -0,0 +1,83 @@
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+/// RESP2/RESP3 type of a Redis reply.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RedisReplyType {
+    /// `+OK`-style simple string.
+    Simple,
+    /// `-ERR …` or RESP3 bulk error.
+    Error,
+    Integer,
+    Bulk,
+    /// RESP2 null bulk/array or RESP3 `_`.
+    Null,
+    Array,
+    Map,
+    Set,
+    Boolean,
+    Double,
+    BigNumber,
+    Verbatim,
+}
+
+impl fmt::Display for RedisReplyType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::Simple => "simple",
+            Self::Error => "error",
+            Self::Integer => "integer",
+            Self::Bulk => "bulk",
+            Self::Null => "null",
+            Self::Array => "array",
+            Self::Map => "map",
+            Self::Set => "set",
+            Self::Boolean => "boolean",
+            Self::Double => "double",
+            Self::BigNumber => "big_number",
+            Self::Verbatim => "verbatim",
+        };
+        f.write_str(s)
+    }
+}
+
+/// A Redis command matched to its reply.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RedisTrace {
+    /// Upper-cased command name, with the subcommand for container commands
+    /// (`"GET"`, `"CLIENT SETNAME"`).
+    pub command: String,
+    /// First key argument, for commands that take one.
+    pub key: Option<String>,
+    /// Leading arguments after the key, each cut short; redacted for `AUTH`
+    /// and `HELLO`.
+    pub args: Vec<String>,
+    /// Number of arguments after the command name, including the key.
+    pub arg_count: u32,
+    pub reply_type: RedisReplyType,
+    /// Message of an error reply.
+    pub error: Option<String>,
+    /// Wall-clock time the command was sent.
+    pub timestamp: SystemTime,
+    /// Time from command to reply.
+    pub duration: Duration,
+    /// Destination socket address, if available.
+    pub dest_addr: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reply_type_serializes_snake_case() {
+        let json = serde_json::to_string(&RedisReplyType::BigNumber).unwrap();
+        assert_eq!(json, "\"big_number\"");
+        let parsed: RedisReplyType = serde_json::from_str("\"error\"").unwrap();
+        assert_eq!(parsed, RedisReplyType::Error);
+        assert_eq!(RedisReplyType::BigNumber.to_string(), "big_number");
+    }
+}
@@ -0,0 +1,411 @@
+use std::collections::{BTreeMap, HashMap};
+use std::time::UNIX_EPOCH;
+
+use serde::Serialize;
+
+use crate::trace::HttpTrace;
+
+/// Maximum number of buckets in the traffic timeline.
+const TIMELINE_BUCKETS: u64 = 30;
+
+/// Aggregated view of a capture session, the data behind `phantom report`.
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionReport {
+    pub summary: TrafficSummary,
+    /// Busiest endpoints, most requests first.
+    pub endpoints: Vec<EndpointStats>,
+    /// Failing requests grouped by endpoint and status, most frequent first.
+    pub errors: Vec<ErrorGroup>,
+    /// Slowest individual requests, slowest first.
+    pub slowest: Vec<SlowTrace>,
+    /// Request counts over time, oldest bucket first.
+    pub timeline: Vec<TimelineBucket>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TrafficSummary {
+    pub requests: u64,
+    /// Requests with a 4xx/5xx status.
+    pub errors: u64,
+    pub first_ms: u64,
+    pub last_ms: u64,
+    pub p50_ms: u64,
+    pub p95_ms: u64,
+    pub p99_ms: u64,
+    /// Request counts per status class (`"2xx"`, `"4xx"`, …).
+    pub status_classes: BTreeMap<String, u64>,
+}
+
+/// Traffic to one `METHOD host/path` endpoint, with ID-like path segments
+/// collapsed to `:id`.
+#[derive(Debug, Clone, Serialize)]
+pub struct EndpointStats {
+    pub method: String,
+    pub endpoint: String,
+    pub requests: u64,
+    pub errors: u64,
+    pub p50_ms: u64,
+    pub p95_ms: u64,
+    pub max_ms: u64,
+}
+
+/// Failures sharing an endpoint and status code, with one representative trace.
+#[derive(Debug, Clone, Serialize)]
+pub struct ErrorGroup {
+    pub method: String,
+    pub endpoint: String,
+    pub status_code: u16,
+    pub count: u64,
+    /// Span ID of the most recent failure in the group.
+    pub sample_span_id: String,
+    pub sample_url: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SlowTrace {
+    pub span_id: String,
+    pub method: String,
+    pub url: String,
+    pub status_code: u16,
+    pub duration_ms: u64,
+    pub timestamp_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TimelineBucket {
+    pub start_ms: u64,
+    pub requests: u64,
+    pub errors: u64,
+}
+
+fn timestamp_ms(trace: &HttpTrace) -> u64 {
+    trace
+        .timestamp
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+fn is_error(trace: &HttpTrace) -> bool {
+    trace.status_code >= 400
+}
+
+/// Nearest-rank percentile of an ascending slice (0 when empty).
+pub(crate) fn percentile(sorted: &[u64], p: f64) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// True for path segments that identify a resource rather than name a route:
+/// numbers, UUIDs, and long hex strings.
+fn is_id_segment(segment: &str) -> bool {
+    if segment.is_empty() {
+        return false;
+    }
+    if segment.bytes().all(|b| b.is_ascii_digit()) {
+        return true;
+    }
+    let hex = segment.bytes().filter(|b| *b != b'-').collect::<Vec<_>>();
+    hex.len() >= 16 && hex.iter().all(|b| b.is_ascii_hexdigit())
+}
+
+/// `"https://api.test/users/42?x=1"` → `"api.test/users/:id"`.
+pub fn normalize_endpoint(url: &str) -> String {
+    let without_scheme = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let without_query = without_scheme
+        .split(['?', '#'])
+        .next()
+        .unwrap_or(without_scheme);
+    without_query
+        .split('/')
+        .enumerate()
+        .map(|(i, seg)| {
+            if i > 0 && is_id_segment(seg) {
+                ":id"
+            } else {
+                seg
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+impl SessionReport {
+    /// Aggregates `traces`, keeping the `top` entries of each ranked table.
+    pub fn build(traces: &[HttpTrace], top: usize) -> Self {
+        Self {
+            summary: summarize(traces),
+            endpoints: top_endpoints(traces, top),
+            errors: error_groups(traces, top),
+            slowest: slowest(traces, top),
+            timeline: timeline(traces),
+        }
+    }
+}
+
+fn summarize(traces: &[HttpTrace]) -> TrafficSummary {
+    let mut durations: Vec<u64> = traces
+        .iter()
+        .map(|t| t.duration.as_millis() as u64)
+        .collect();
+    durations.sort_unstable();
+    let mut status_classes = BTreeMap::new();
+    for t in traces {
+        *status_classes
+            .entry(format!("{}xx", t.status_code / 100))
+            .or_insert(0) += 1;
+    }
+    let timestamps = traces.iter().map(timestamp_ms);
+    TrafficSummary {
+        requests: traces.len() as u64,
+        errors: traces.iter().filter(|t| is_error(t)).count() as u64,
+        first_ms: timestamps.clone().min().unwrap_or(0),
+        last_ms: timestamps.max().unwrap_or(0),
+        p50_ms: percentile(&durations, 50.0),
+        p95_ms: percentile(&durations, 95.0),
+        p99_ms: percentile(&durations, 99.0),
+        status_classes,
+    }
+}
+
+fn top_endpoints(traces: &[HttpTrace], top: usize) -> Vec<EndpointStats> {
+    let mut groups: HashMap<(String, String), Vec<&HttpTrace>> = HashMap::new();
+    for t in traces {
+        groups
+            .entry((t.method.to_string(), normalize_endpoint(&t.url)))
+            .or_default()
+            .push(t);
+    }
+    let mut endpoints: Vec<EndpointStats> = groups
+        .into_iter()
+        .map(|((method, endpoint), group)| {
+            let mut durations: Vec<u64> = group
+                .iter()
+                .map(|t| t.duration.as_millis() as u64)
+                .collect();
+            durations.sort_unstable();
+            EndpointStats {
+                method,
+                endpoint,
+                requests: group.len() as u64,
+                errors: group.iter().filter(|t| is_error(t)).count() as u64,
+                p50_ms: percentile(&durations, 50.0),
+                p95_ms: percentile(&durations, 95.0),
+                max_ms: durations.last().copied().unwrap_or(0),
+            }
+        })
+        .collect();
+    endpoints.sort_by(|a, b| {
+        b.requests
+            .cmp(&a.requests)
+            .then_with(|| a.endpoint.cmp(&b.endpoint))
+            .then_with(|| a.method.cmp(&b.method))
+    });
+    endpoints.truncate(top);
+    endpoints
+}
+
+fn error_groups(traces: &[HttpTrace], top: usize) -> Vec<ErrorGroup> {
+    let mut groups: HashMap<(String, String, u16), (u64, &HttpTrace)> = HashMap::new();
+    for t in traces.iter().filter(|t| is_error(t)) {
+        let key = (
+            t.method.to_string(),
+            normalize_endpoint(&t.url),
+            t.status_code,
+        );
+        let entry = groups.entry(key).or_insert((0, t));
+        entry.0 += 1;
+        if t.timestamp > entry.1.timestamp {
+            entry.1 = t;
+        }
+    }
+    let mut errors: Vec<ErrorGroup> = groups
+        .into_iter()
+        .map(
+            |((method, endpoint, status_code), (count, sample))| ErrorGroup {
+                method,
+                endpoint,
+                status_code,
+                count,
+                sample_span_id: sample.span_id.to_string(),
+                sample_url: sample.url.clone(),
+            },
+        )
+        .collect();
+    errors.sort_by(|a, b| {
+        b.count
+            .cmp(&a.count)
+            .then_with(|| a.endpoint.cmp(&b.endpoint))
+            .then_with(|| a.status_code.cmp(&b.status_code))
+    });
+    errors.truncate(top);
+    errors
+}
+
+fn slowest(traces: &[HttpTrace], top: usize) -> Vec<SlowTrace> {
+    let mut sorted: Vec<&HttpTrace> = traces.iter().collect();
+    sorted.sort_by_key(|t| std::cmp::Reverse(t.duration));
+    sorted
+        .into_iter()
+        .take(top)
+        .map(|t| SlowTrace {
+            span_id: t.span_id.to_string(),
+            method: t.method.to_string(),
+            url: t.url.clone(),
+            status_code: t.status_code,
+            duration_ms: t.duration.as_millis() as u64,
+            timestamp_ms: timestamp_ms(t),
+        })
+        .collect()
+}
+
+fn timeline(traces: &[HttpTrace]) -> Vec<TimelineBucket> {
+    let Some(first) = traces.iter().map(timestamp_ms).min() else {
+        return Vec::new();
+    };
+    let last = traces.iter().map(timestamp_ms).max().unwrap_or(first);
+    // At least one-second buckets, at most TIMELINE_BUCKETS of them.
+    let width = (last - first + 1).div_ceil(TIMELINE_BUCKETS).max(1000);
+    let count = (last - first) / width + 1;
+    let mut buckets: Vec<TimelineBucket> = (0..count)
+        .map(|i| TimelineBucket {
+            start_ms: first + i * width,
+            requests: 0,
+            errors: 0,
+        })
+        .collect();
+    for t in traces {
+        let bucket = &mut buckets[((timestamp_ms(t) - first) / width) as usize];
+        bucket.requests += 1;
+        if is_error(t) {
+            bucket.errors += 1;
+        }
+    }
+    buckets
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use super::*;
+    use crate::trace::{HttpMethod, SpanId, TraceId};
+
+    fn make_trace(url: &str, status_code: u16, duration_ms: u64, at_ms: u64) -> HttpTrace {
+        HttpTrace {
+            span_id: SpanId([(at_ms / 10) as u8; 8]),
+            trace_id: TraceId([2; 16]),
+            parent_span_id: None,
+            method: HttpMethod::Get,
+            url: url.to_string(),
+            request_headers: HashMap::new(),
+            request_body: None,
+            status_code,
+            response_headers: HashMap::new(),
+            response_body: None,
+            timestamp: SystemTime::UNIX_EPOCH + Duration::from_millis(at_ms),
+            duration: Duration::from_millis(duration_ms),
+            source_addr: None,
+            dest_addr: None,
+            protocol_version: "HTTP/1.1".to_string(),
+            request_body_file: None,
+            response_body_file: None,
+            connection_id: None,
+            process: None,
+            response_trailers: HashMap::new(),
+            request_body_encoding: None,
+            response_body_encoding: None,
+            elasticsearch: None,
+            soap: None,
+            graphql: None,
+            thread_id: None,
+            tls: None,
+            timing: None,
+            request_size: None,
+            response_size: None,
+            fault: None,
+            replay: None,
+        }
+    }
+
+    #[test]
+    fn test_normalize_endpoint_collapses_ids() {
+        assert_eq!(
+            normalize_endpoint("https://api.test/users/42/orders?page=2"),
+            "api.test/users/:id/orders"
+        );
+        assert_eq!(
+            normalize_endpoint("http://h/items/3f2b8c1e-9d4a-4e5f-8a7b-1c2d3e4f5a6b"),
+            "h/items/:id"
+        );
+        assert_eq!(normalize_endpoint("http://h/v2/health"), "h/v2/health");
+    }
+
+    #[test]
+    fn test_percentile_nearest_rank() {
+        let sorted: Vec<u64> = (1..=100).collect();
+        assert_eq!(percentile(&sorted, 50.0), 50);
+        assert_eq!(percentile(&sorted, 99.0), 99);
+        assert_eq!(percentile(&[7], 95.0), 7);
+        assert_eq!(percentile(&[], 50.0), 0);
+    }
+
+    #[test]
+    fn test_build_groups_endpoints_and_errors() {
+        let traces = vec![
+            make_trace("http://h/users/1", 200, 10, 1_000),
+            make_trace("http://h/users/2", 200, 30, 2_000),
+            make_trace("http://h/users/3", 500, 900, 3_000),
+            make_trace("http://h/users/4", 500, 20, 4_000),
+            make_trace("http://h/health", 404, 5, 5_000),
+        ];
+        let report = SessionReport::build(&traces, 10);
+
+        assert_eq!(report.summary.requests, 5);
+        assert_eq!(report.summary.errors, 3);
+        assert_eq!(report.summary.status_classes["5xx"], 2);
+        assert_eq!(report.summary.first_ms, 1_000);
+        assert_eq!(report.summary.last_ms, 5_000);
+
+        assert_eq!(report.endpoints[0].endpoint, "h/users/:id");
+        assert_eq!(report.endpoints[0].requests, 4);
+        assert_eq!(report.endpoints[0].errors, 2);
+        assert_eq!(report.endpoints[0].max_ms, 900);
+
+        assert_eq!(report.errors[0].status_code, 500);
+        assert_eq!(report.errors[0].count, 2);
+        // Representative trace is the most recent failure.
+        assert_eq!(report.errors[0].sample_url, "http://h/users/4");
+
+        assert_eq!(report.slowest[0].duration_ms, 900);
+    }
+
+    #[test]
+    fn test_top_limits_tables() {
+        let traces: Vec<HttpTrace> = (0..5)
+            .map(|i| make_trace(&format!("http://h/r{i}"), 200, i, i * 10))
+            .collect();
+        let report = SessionReport::build(&traces, 2);
+        assert_eq!(report.endpoints.len(), 2);
+        assert_eq!(report.slowest.len(), 2);
+        assert_eq!(report.slowest[0].duration_ms, 4);
+    }
+
+    #[test]
+    fn test_timeline_buckets_cover_session() {
+        let traces = vec![
+            make_trace("http://h/a", 200, 1, 0),
+            make_trace("http://h/a", 503, 1, 500),
+            make_trace("http://h/a", 200, 1, 2_500),
+        ];
+        let timeline = SessionReport::build(&traces, 10).timeline;
+        assert_eq!(timeline.len(), 3);
+        assert_eq!((timeline[0].requests, timeline[0].errors), (2, 1));
+        assert_eq!(timeline[1].requests, 0);
+        assert_eq!(timeline[2].start_ms, 2_000);
+        assert!(SessionReport::build(&[], 10).timeline.is_empty());
+    }
+}
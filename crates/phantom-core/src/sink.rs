@@ -0,0 +1,475 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::error::StorageError;
+use crate::protocol::ProtocolTrace;
+use crate::query::TraceQuery;
+use crate::storage::TraceStore;
+use crate::trace::{HttpTrace, SpanId, TraceId};
+
+/// A write-only destination for captured traces (a file, a remote collector,
+/// another store). Sinks receive traces after the primary store accepted them.
+pub trait TraceSink: Send + Sync {
+    /// Short human-readable name used in error messages, e.g. `"jsonl:/tmp/x.jsonl"`.
+    fn name(&self) -> &str;
+
+    /// Deliver one trace. Implementations may buffer; buffered data must be
+    /// delivered when the sink is dropped.
+    fn write(&self, trace: &HttpTrace) -> Result<(), StorageError>;
+
+    /// Deliver one non-HTTP trace. Sinks with no form for them (e.g. OTLP
+    /// spans) ignore them.
+    fn write_protocol(&self, trace: &ProtocolTrace) -> Result<(), StorageError> {
+        let _ = trace;
+        Ok(())
+    }
+}
+
+// ─── Sink specs ──────────────────────────────────────────────────────────────
+
+/// Where a `--sink` sends its traces.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SinkTarget {
+    /// Append one JSON object per line to a file.
+    Jsonl(PathBuf),
+    /// Export spans to an OpenTelemetry collector (OTLP/HTTP JSON).
+    Otlp(String),
+    /// Insert into a second phantom data directory.
+    Store(PathBuf),
+    /// Stream traces to `phantom connect` clients over TCP, listening on
+    /// `ADDR:PORT`.
+    Serve(String),
+}
+
+/// A parsed `--sink` flag: a target plus the filter a trace must match to be
+/// delivered there.
+#[derive(Debug, Clone)]
+pub struct SinkSpec {
+    pub target: SinkTarget,
+    pub filter: TraceQuery,
+}
+
+/// Parses a sink spec: `KIND:TARGET[?FILTER&FILTER…]`.
+///
+/// KIND is `jsonl`, `otlp`, `store` or `serve`. Filters are `method=POST` (repeatable),
+/// `status=5xx` (any [`StatusRange`](crate::query::StatusRange) form) and
+/// `url=/api` (case-insensitive substring).
+pub fn parse_sink_spec(s: &str) -> Result<SinkSpec, String> {
+    let (kind, rest) = s.split_once(':').ok_or_else(|| {
+        format!("invalid sink spec {s:?}: expected 'jsonl:…', 'otlp:…', 'store:…' or 'serve:…'")
+    })?;
+    let (target, filters) = match rest.rsplit_once('?') {
+        Some((target, filters)) => (target, Some(filters)),
+        None => (rest, None),
+    };
+    if target.is_empty() {
+        return Err(format!(
+            "invalid sink spec {s:?}: missing target after '{kind}:'"
+        ));
+    }
+    let target = match kind {
+        "jsonl" => SinkTarget::Jsonl(PathBuf::from(target)),
+        "otlp" => SinkTarget::Otlp(target.to_string()),
+        "store" => SinkTarget::Store(PathBuf::from(target)),
+        "serve" => SinkTarget::Serve(target.to_string()),
+        other => {
+            return Err(format!(
+                "unknown sink kind {other:?} (expected 'jsonl', 'otlp', 'store' or 'serve')"
+            ));
+        }
+    };
+
+    let mut filter = TraceQuery::default();
+    for pair in filters.into_iter().flat_map(|f| f.split('&')) {
+        if pair.is_empty() {
+            continue;
+        }
+        let (key, value) = pair
+            .split_once('=')
+            .ok_or_else(|| format!("invalid sink filter {pair:?}: expected KEY=VALUE"))?;
+        match key {
+            "method" => filter.methods.push(
+                value
+                    .parse()
+                    .map_err(|e| format!("sink filter {pair:?}: {e}"))?,
+            ),
+            "status" => {
+                filter.status = Some(
+                    value
+                        .parse()
+                        .map_err(|e| format!("sink filter {pair:?}: {e}"))?,
+                )
+            }
+            "url" => filter.url_contains = Some(value.to_string()),
+            other => {
+                return Err(format!(
+                    "unknown sink filter {other:?} (expected 'method', 'status' or 'url')"
+                ));
+            }
+        }
+    }
+    Ok(SinkSpec { target, filter })
+}
+
+// ─── Fan-out ─────────────────────────────────────────────────────────────────
+
+/// Forwards traces into another [`TraceStore`], e.g. a second data directory.
+pub struct StoreSink {
+    name: String,
+    store: Arc<dyn TraceStore>,
+}
+
+impl StoreSink {
+    pub fn new(name: impl Into<String>, store: Arc<dyn TraceStore>) -> Self {
+        Self {
+            name: name.into(),
+            store,
+        }
+    }
+}
+
+impl TraceSink for StoreSink {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn write(&self, trace: &HttpTrace) -> Result<(), StorageError> {
+        self.store.insert(trace)
+    }
+
+    fn write_protocol(&self, trace: &ProtocolTrace) -> Result<(), StorageError> {
+        self.store.insert_protocol(trace)
+    }
+}
+
+/// A [`TraceStore`] that tees every inserted trace to additional sinks.
+///
+/// Reads go to the primary store only. An insert first writes the primary
+/// store, then offers the trace to each sink whose filter matches; every sink
+/// is attempted even if an earlier one fails, and the first sink error is
+/// returned so the caller can report it. Non-HTTP traces go to the sinks
+/// without a filter, since the filters select HTTP methods, statuses and URLs.
+pub struct TeeTraceStore {
+    primary: Arc<dyn TraceStore>,
+    sinks: Vec<(TraceQuery, Box<dyn TraceSink>)>,
+}
+
+impl TeeTraceStore {
+    pub fn new(primary: Arc<dyn TraceStore>, sinks: Vec<(TraceQuery, Box<dyn TraceSink>)>) -> Self {
+        Self { primary, sinks }
+    }
+}
+
+impl TeeTraceStore {
+    /// Offers a trace to each sink `wants`, returning the first sink error.
+    fn fan_out(
+        &self,
+        wants: impl Fn(&TraceQuery) -> bool,
+        write: impl Fn(&dyn TraceSink) -> Result<(), StorageError>,
+    ) -> Result<(), StorageError> {
+        let mut first_err = None;
+        for (filter, sink) in &self.sinks {
+            if !wants(filter) {
+                continue;
+            }
+            if let Err(e) = write(sink.as_ref()) {
+                first_err.get_or_insert(StorageError::Write(format!("sink {}: {e}", sink.name())));
+            }
+        }
+        first_err.map_or(Ok(()), Err)
+    }
+}
+
+/// True when a sink filter selects HTTP traces by method, status or URL.
+fn filters_http(filter: &TraceQuery) -> bool {
+    !filter.methods.is_empty() || filter.status.is_some() || filter.url_contains.is_some()
+}
+
+impl TraceStore for TeeTraceStore {
+    fn insert(&self, trace: &HttpTrace) -> Result<(), StorageError> {
+        self.primary.insert(trace)?;
+        self.fan_out(|filter| filter.matches(trace), |sink| sink.write(trace))
+    }
+
+    fn get_by_span_id(&self, span_id: &SpanId) -> Result<Option<HttpTrace>, StorageError> {
+        self.primary.get_by_span_id(span_id)
+    }
+
+    fn list_recent(&self, limit: usize, offset: usize) -> Result<Vec<HttpTrace>, StorageError> {
+        self.primary.list_recent(limit, offset)
+    }
+
+    fn get_by_trace_id(&self, trace_id: &TraceId) -> Result<Vec<HttpTrace>, StorageError> {
+        self.primary.get_by_trace_id(trace_id)
+    }
+
+    fn search_by_url(&self, pattern: &str, limit: usize) -> Result<Vec<HttpTrace>, StorageError> {
+        self.primary.search_by_url(pattern, limit)
+    }
+
+    fn count(&self) -> Result<u64, StorageError> {
+        self.primary.count()
+    }
+
+    fn query(&self, query: &TraceQuery) -> Result<Vec<HttpTrace>, StorageError> {
+        self.primary.query(query)
+    }
+
+    /// Clears the primary store only; sinks are append-only destinations.
+    fn clear(&self) -> Result<(), StorageError> {
+        self.primary.clear()
+    }
+
+    fn insert_protocol(&self, trace: &ProtocolTrace) -> Result<(), StorageError> {
+        self.primary.insert_protocol(trace)?;
+        self.fan_out(
+            |filter| !filters_http(filter),
+            |sink| sink.write_protocol(trace),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+    use std::time::{Duration, SystemTime};
+
+    use super::*;
+    use crate::query::StatusRange;
+    use crate::redis::{RedisReplyType, RedisTrace};
+    use crate::trace::HttpMethod;
+
+    fn make_trace(method: HttpMethod, url: &str, status: u16) -> HttpTrace {
+        HttpTrace {
+            span_id: SpanId([status as u8; 8]),
+            trace_id: TraceId([2; 16]),
+            parent_span_id: None,
+            method,
+            url: url.to_string(),
+            request_headers: HashMap::new(),
+            request_body: None,
+            status_code: status,
+            response_headers: HashMap::new(),
+            response_body: None,
+            timestamp: SystemTime::now(),
+            duration: Duration::from_millis(10),
+            source_addr: None,
+            dest_addr: None,
+            protocol_version: "HTTP/1.1".to_string(),
+            request_body_file: None,
+            response_body_file: None,
+            connection_id: None,
+            process: None,
+            response_trailers: HashMap::new(),
+            request_body_encoding: None,
+            response_body_encoding: None,
+            elasticsearch: None,
+            soap: None,
+            graphql: None,
+            thread_id: None,
+            tls: None,
+            timing: None,
+            request_size: None,
+            response_size: None,
+            fault: None,
+            replay: None,
+        }
+    }
+
+    /// In-memory store/sink recording inserted URLs.
+    #[derive(Default)]
+    struct Recorder {
+        urls: Mutex<Vec<String>>,
+        fail: bool,
+    }
+
+    impl TraceStore for Recorder {
+        fn insert(&self, trace: &HttpTrace) -> Result<(), StorageError> {
+            if self.fail {
+                return Err(StorageError::Write("disk full".into()));
+            }
+            self.urls.lock().unwrap().push(trace.url.clone());
+            Ok(())
+        }
+        fn get_by_span_id(&self, _: &SpanId) -> Result<Option<HttpTrace>, StorageError> {
+            Ok(None)
+        }
+        fn list_recent(&self, _: usize, _: usize) -> Result<Vec<HttpTrace>, StorageError> {
+            Ok(Vec::new())
+        }
+        fn get_by_trace_id(&self, _: &TraceId) -> Result<Vec<HttpTrace>, StorageError> {
+            Ok(Vec::new())
+        }
+        fn search_by_url(&self, _: &str, _: usize) -> Result<Vec<HttpTrace>, StorageError> {
+            Ok(Vec::new())
+        }
+        fn count(&self) -> Result<u64, StorageError> {
+            Ok(self.urls.lock().unwrap().len() as u64)
+        }
+        fn query(&self, _: &TraceQuery) -> Result<Vec<HttpTrace>, StorageError> {
+            Ok(Vec::new())
+        }
+        fn clear(&self) -> Result<(), StorageError> {
+            self.urls.lock().unwrap().clear();
+            Ok(())
+        }
+        fn insert_protocol(&self, trace: &ProtocolTrace) -> Result<(), StorageError> {
+            let ProtocolTrace::Redis(redis) = trace else {
+                return Ok(());
+            };
+            self.urls
+                .lock()
+                .unwrap()
+                .push(format!("redis:{}", redis.command));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_parse_sink_spec_targets() {
+        let spec = parse_sink_spec("jsonl:/tmp/out.jsonl").unwrap();
+        assert_eq!(
+            spec.target,
+            SinkTarget::Jsonl(PathBuf::from("/tmp/out.jsonl"))
+        );
+        assert!(spec.filter.methods.is_empty());
+        assert!(spec.filter.status.is_none());
+
+        let spec = parse_sink_spec("otlp:http://localhost:4318").unwrap();
+        assert_eq!(
+            spec.target,
+            SinkTarget::Otlp("http://localhost:4318".into())
+        );
+
+        let spec = parse_sink_spec("store:/var/phantom-archive").unwrap();
+        assert_eq!(
+            spec.target,
+            SinkTarget::Store(PathBuf::from("/var/phantom-archive"))
+        );
+
+        let spec = parse_sink_spec("serve:0.0.0.0:9464?status=5xx").unwrap();
+        assert_eq!(spec.target, SinkTarget::Serve("0.0.0.0:9464".into()));
+        assert!(spec.filter.status.is_some());
+    }
+
+    #[test]
+    fn test_parse_sink_spec_filters() {
+        let spec = parse_sink_spec("jsonl:errors.jsonl?status=5xx&method=POST&method=put&url=/api")
+            .unwrap();
+        assert_eq!(spec.filter.status, Some(StatusRange { min: 500, max: 599 }));
+        assert_eq!(spec.filter.methods, vec![HttpMethod::Post, HttpMethod::Put]);
+        assert_eq!(spec.filter.url_contains.as_deref(), Some("/api"));
+    }
+
+    #[test]
+    fn test_parse_sink_spec_errors() {
+        assert!(parse_sink_spec("jsonl").is_err());
+        assert!(parse_sink_spec("jsonl:").is_err());
+        assert!(parse_sink_spec("kafka:topic").is_err());
+        assert!(parse_sink_spec("jsonl:x?status=9xx").is_err());
+        assert!(parse_sink_spec("jsonl:x?method=FETCH").is_err());
+        assert!(parse_sink_spec("jsonl:x?color=red").is_err());
+        assert!(parse_sink_spec("jsonl:x?status").is_err());
+    }
+
+    #[test]
+    fn test_tee_applies_filters_per_sink() {
+        let primary = Arc::new(Recorder::default());
+        let all = Arc::new(Recorder::default());
+        let errors = Arc::new(Recorder::default());
+        let tee = TeeTraceStore::new(
+            primary.clone(),
+            vec![
+                (
+                    TraceQuery::default(),
+                    Box::new(StoreSink::new("all", all.clone())),
+                ),
+                (
+                    parse_sink_spec("store:x?status=5xx").unwrap().filter,
+                    Box::new(StoreSink::new("errors", errors.clone())),
+                ),
+            ],
+        );
+
+        tee.insert(&make_trace(HttpMethod::Get, "http://a/ok", 200))
+            .unwrap();
+        tee.insert(&make_trace(HttpMethod::Get, "http://a/boom", 503))
+            .unwrap();
+
+        assert_eq!(primary.count().unwrap(), 2);
+        assert_eq!(all.count().unwrap(), 2);
+        assert_eq!(
+            *errors.urls.lock().unwrap(),
+            vec!["http://a/boom".to_string()]
+        );
+        assert_eq!(tee.count().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_tee_failing_sink_does_not_starve_others() {
+        let primary = Arc::new(Recorder::default());
+        let broken = Arc::new(Recorder {
+            fail: true,
+            ..Default::default()
+        });
+        let healthy = Arc::new(Recorder::default());
+        let tee = TeeTraceStore::new(
+            primary.clone(),
+            vec![
+                (
+                    TraceQuery::default(),
+                    Box::new(StoreSink::new("broken", broken)),
+                ),
+                (
+                    TraceQuery::default(),
+                    Box::new(StoreSink::new("healthy", healthy.clone())),
+                ),
+            ],
+        );
+
+        let err = tee
+            .insert(&make_trace(HttpMethod::Get, "http://a/", 200))
+            .unwrap_err();
+        assert!(err.to_string().contains("sink broken"));
+        assert_eq!(primary.count().unwrap(), 1);
+        assert_eq!(healthy.count().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_tee_sends_protocol_traces_to_unfiltered_sinks() {
+        let primary = Arc::new(Recorder::default());
+        let all = Arc::new(Recorder::default());
+        let errors = Arc::new(Recorder::default());
+        let tee = TeeTraceStore::new(
+            primary.clone(),
+            vec![
+                (
+                    TraceQuery::default(),
+                    Box::new(StoreSink::new("all", all.clone())),
+                ),
+                (
+                    parse_sink_spec("store:x?status=5xx").unwrap().filter,
+                    Box::new(StoreSink::new("errors", errors.clone())),
+                ),
+            ],
+        );
+
+        let redis = RedisTrace {
+            command: "GET".into(),
+            key: Some("k".into()),
+            args: Vec::new(),
+            arg_count: 1,
+            reply_type: RedisReplyType::Bulk,
+            error: None,
+            timestamp: SystemTime::now(),
+            duration: Duration::from_millis(1),
+            dest_addr: None,
+        };
+        tee.insert_protocol(&ProtocolTrace::Redis(redis)).unwrap();
+
+        assert_eq!(*primary.urls.lock().unwrap(), ["redis:GET"]);
+        assert_eq!(*all.urls.lock().unwrap(), ["redis:GET"]);
+        assert!(errors.urls.lock().unwrap().is_empty());
+    }
+}
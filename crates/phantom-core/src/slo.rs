@@ -0,0 +1,334 @@
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::trace::HttpTrace;
+
+/// What an SLO promises about the traces it covers.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SloObjective {
+    /// At least `target` (0.0–1.0) of requests complete within `threshold`.
+    Latency { threshold: Duration, target: f64 },
+    /// At most `max_rate` (0.0–1.0) of requests fail with a 5xx status.
+    ErrorRate { max_rate: f64 },
+}
+
+/// A service-level objective over the traces whose URL matches `url_pattern`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Slo {
+    /// The spec this SLO was parsed from, used as its display name.
+    pub spec: String,
+    pub objective: SloObjective,
+    /// If Some, only URLs matching this pattern count (`*` matches any text).
+    pub url_pattern: Option<String>,
+}
+
+impl Slo {
+    /// Returns true if the trace falls under this SLO.
+    pub fn matches(&self, trace: &HttpTrace) -> bool {
+        self.url_pattern
+            .as_deref()
+            .is_none_or(|p| wildcard_contains(&trace.url, p))
+    }
+
+    /// Returns true if the trace counts against this SLO's error budget.
+    pub fn is_bad(&self, trace: &HttpTrace) -> bool {
+        match self.objective {
+            SloObjective::Latency { threshold, .. } => trace.duration > threshold,
+            SloObjective::ErrorRate { .. } => trace.status_code >= 500,
+        }
+    }
+
+    /// Fraction of matching traces allowed to be bad.
+    fn budget_fraction(&self) -> f64 {
+        match self.objective {
+            SloObjective::Latency { target, .. } => 1.0 - target,
+            SloObjective::ErrorRate { max_rate } => max_rate,
+        }
+    }
+}
+
+/// Unanchored match where each `*` in `pattern` matches any run of characters.
+/// Without a `*` this is a plain substring check.
+pub fn wildcard_contains(text: &str, pattern: &str) -> bool {
+    let mut rest = text;
+    for piece in pattern.split('*').filter(|p| !p.is_empty()) {
+        match rest.find(piece) {
+            Some(pos) => rest = &rest[pos + piece.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Budget tracking
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Point-in-time budget state of one SLO.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SloStatus {
+    /// The SLO spec, e.g. `"latency:300ms:99%:/api/*"`.
+    pub spec: String,
+    /// Matching traces seen so far.
+    pub total: u64,
+    /// Matching traces that violated the objective.
+    pub bad: u64,
+    /// Share of the error budget consumed (1.0 = exhausted).
+    pub budget_used: f64,
+    /// True once `bad` exceeds the budget.
+    pub breached: bool,
+}
+
+/// Tracks error-budget burn for a set of SLOs over a capture session.
+#[derive(Debug, Clone, Default)]
+pub struct SloTracker {
+    entries: Vec<(Slo, u64, u64)>,
+}
+
+impl SloTracker {
+    pub fn new(slos: Vec<Slo>) -> Self {
+        Self {
+            entries: slos.into_iter().map(|slo| (slo, 0, 0)).collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Counts a captured trace against every SLO it matches.
+    pub fn record(&mut self, trace: &HttpTrace) {
+        for (slo, total, bad) in &mut self.entries {
+            if slo.matches(trace) {
+                *total += 1;
+                if slo.is_bad(trace) {
+                    *bad += 1;
+                }
+            }
+        }
+    }
+
+    pub fn statuses(&self) -> Vec<SloStatus> {
+        self.entries
+            .iter()
+            .map(|(slo, total, bad)| {
+                let allowed = *total as f64 * slo.budget_fraction();
+                let budget_used = if *bad == 0 {
+                    0.0
+                } else if allowed > 0.0 {
+                    *bad as f64 / allowed
+                } else {
+                    f64::INFINITY
+                };
+                SloStatus {
+                    spec: slo.spec.clone(),
+                    total: *total,
+                    bad: *bad,
+                    budget_used,
+                    breached: *bad as f64 > allowed,
+                }
+            })
+            .collect()
+    }
+
+    /// True if any SLO has exhausted its error budget.
+    pub fn any_breached(&self) -> bool {
+        self.statuses().iter().any(|s| s.breached)
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// CLI spec parsing
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Parse an SLO specification string.
+///
+/// Formats:
+///   latency:300ms:99%           99% of requests complete within 300 ms
+///   latency:1s:0.95:/api/*      95% of URLs matching "/api/*" within 1 s
+///   errors:1%                   fewer than 1% of requests return 5xx
+///   errors:0.5%:/checkout       error budget for URLs containing "/checkout"
+pub fn parse_slo_spec(s: &str) -> Result<Slo, String> {
+    let (kind, rest) = s
+        .split_once(':')
+        .ok_or_else(|| format!("invalid SLO spec {s:?}: expected 'latency:…' or 'errors:…'"))?;
+    let (rest, url_pattern) = match rest.split_once(":/") {
+        Some((head, path)) => (head, Some(format!("/{path}"))),
+        None => (rest, None),
+    };
+    let objective = match kind {
+        "latency" => {
+            let (threshold, target) = rest.split_once(':').ok_or_else(|| {
+                format!("invalid latency SLO {s:?}: expected 'latency:<DURATION>:<TARGET>'")
+            })?;
+            SloObjective::Latency {
+                threshold: parse_duration(threshold)?,
+                target: parse_fraction(target)?,
+            }
+        }
+        "errors" => SloObjective::ErrorRate {
+            max_rate: parse_fraction(rest)?,
+        },
+        _ => {
+            return Err(format!(
+                "unknown SLO type {kind:?} in {s:?}; expected 'latency' or 'errors'"
+            ));
+        }
+    };
+    Ok(Slo {
+        spec: s.to_string(),
+        objective,
+        url_pattern,
+    })
+}
+
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let parsed = if let Some(n) = s.strip_suffix("ms") {
+        n.parse().map(Duration::from_millis)
+    } else if let Some(n) = s.strip_suffix('s') {
+        n.parse().map(Duration::from_secs)
+    } else {
+        s.parse().map(Duration::from_millis)
+    };
+    parsed.map_err(|_| format!("invalid duration {s:?}; expected e.g. '300ms' or '2s'"))
+}
+
+/// Parses `"99%"` or `"0.99"` into a fraction in 0.0–1.0.
+fn parse_fraction(s: &str) -> Result<f64, String> {
+    let s = s.trim();
+    let value = match s.strip_suffix('%') {
+        Some(pct) => pct.parse::<f64>().map(|v| v / 100.0),
+        None => s.parse::<f64>(),
+    }
+    .map_err(|_| format!("invalid target {s:?}; expected e.g. '99%' or '0.99'"))?;
+    if !(0.0..=1.0).contains(&value) {
+        return Err(format!("target {s:?} is out of range 0–100%"));
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::time::UNIX_EPOCH;
+
+    use super::*;
+    use crate::trace::{HttpMethod, SpanId, TraceId};
+
+    fn make_trace(url: &str, status_code: u16, duration_ms: u64) -> HttpTrace {
+        HttpTrace {
+            span_id: SpanId([1; 8]),
+            trace_id: TraceId([2; 16]),
+            parent_span_id: None,
+            method: HttpMethod::Get,
+            url: url.to_string(),
+            request_headers: HashMap::new(),
+            request_body: None,
+            status_code,
+            response_headers: HashMap::new(),
+            response_body: None,
+            timestamp: UNIX_EPOCH,
+            duration: Duration::from_millis(duration_ms),
+            source_addr: None,
+            dest_addr: None,
+            protocol_version: "HTTP/1.1".to_string(),
+            request_body_file: None,
+            response_body_file: None,
+            connection_id: None,
+            process: None,
+            response_trailers: HashMap::new(),
+            request_body_encoding: None,
+            response_body_encoding: None,
+            elasticsearch: None,
+            soap: None,
+            graphql: None,
+            thread_id: None,
+            tls: None,
+            timing: None,
+            request_size: None,
+            response_size: None,
+            fault: None,
+            replay: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_latency_spec() {
+        let slo = parse_slo_spec("latency:300ms:99%:/api/*").unwrap();
+        assert_eq!(
+            slo.objective,
+            SloObjective::Latency {
+                threshold: Duration::from_millis(300),
+                target: 0.99
+            }
+        );
+        assert_eq!(slo.url_pattern.as_deref(), Some("/api/*"));
+
+        let slo = parse_slo_spec("latency:2s:0.95").unwrap();
+        assert_eq!(
+            slo.objective,
+            SloObjective::Latency {
+                threshold: Duration::from_secs(2),
+                target: 0.95
+            }
+        );
+        assert_eq!(slo.url_pattern, None);
+    }
+
+    #[test]
+    fn test_parse_error_rate_spec() {
+        let slo = parse_slo_spec("errors:1%").unwrap();
+        assert_eq!(slo.objective, SloObjective::ErrorRate { max_rate: 0.01 });
+    }
+
+    #[test]
+    fn test_parse_rejects_bad_specs() {
+        assert!(parse_slo_spec("latency").is_err());
+        assert!(parse_slo_spec("latency:300ms").is_err());
+        assert!(parse_slo_spec("latency:fast:99%").is_err());
+        assert!(parse_slo_spec("errors:150%").is_err());
+        assert!(parse_slo_spec("uptime:99%").is_err());
+    }
+
+    #[test]
+    fn test_wildcard_url_matching() {
+        let slo = parse_slo_spec("errors:1%:/api/*/orders").unwrap();
+        assert!(slo.matches(&make_trace("http://h/api/v1/orders?x=1", 200, 1)));
+        assert!(!slo.matches(&make_trace("http://h/api/v1/users", 200, 1)));
+        assert!(!slo.matches(&make_trace("http://h/health", 200, 1)));
+    }
+
+    #[test]
+    fn test_tracker_burns_latency_budget() {
+        let mut tracker =
+            SloTracker::new(vec![parse_slo_spec("latency:300ms:90%:/api/*").unwrap()]);
+        for _ in 0..19 {
+            tracker.record(&make_trace("http://h/api/items", 200, 50));
+        }
+        tracker.record(&make_trace("http://h/api/items", 200, 900));
+        // Unmatched traffic does not count.
+        tracker.record(&make_trace("http://h/static/app.js", 200, 5000));
+
+        let status = &tracker.statuses()[0];
+        assert_eq!((status.total, status.bad), (20, 1));
+        assert!((status.budget_used - 0.5).abs() < 1e-9);
+        assert!(!status.breached);
+
+        for _ in 0..2 {
+            tracker.record(&make_trace("http://h/api/items", 200, 900));
+        }
+        assert!(tracker.any_breached());
+    }
+
+    #[test]
+    fn test_tracker_error_rate_counts_5xx_only() {
+        let mut tracker = SloTracker::new(vec![parse_slo_spec("errors:10%").unwrap()]);
+        tracker.record(&make_trace("http://h/a", 404, 1));
+        tracker.record(&make_trace("http://h/a", 503, 1));
+        let status = &tracker.statuses()[0];
+        assert_eq!((status.total, status.bad), (2, 1));
+        assert!(status.breached);
+    }
+}
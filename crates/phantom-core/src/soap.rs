@@ -0,0 +1,330 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::trace::HttpTrace;
+
+/// Summary of a SOAP call recognized from a trace.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SoapOperation {
+    /// Operation name: the first element inside `<soap:Body>`, falling back
+    /// to the last segment of the SOAP action (e.g. `"GetUser"`).
+    pub operation: String,
+    /// SOAP action from the `SOAPAction` header (SOAP 1.1) or the `action`
+    /// parameter of `application/soap+xml` (SOAP 1.2).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub action: Option<String>,
+}
+
+impl SoapOperation {
+    /// Recognizes a SOAP request from its action header/parameter or an
+    /// `Envelope`/`Body` wrapper in the request body.
+    pub fn detect(trace: &HttpTrace) -> Option<Self> {
+        let action = soap_action(&trace.request_headers);
+        let body_operation = trace
+            .request_body
+            .as_deref()
+            .and_then(|b| std::str::from_utf8(b).ok())
+            .and_then(body_operation);
+        let operation = body_operation.or_else(|| action.as_deref().map(action_operation))?;
+        Some(Self { operation, action })
+    }
+
+    /// One-line label for list views, e.g. `"SOAP GetUser"`.
+    pub fn label(&self) -> String {
+        format!("SOAP {}", self.operation)
+    }
+}
+
+fn header<'a>(headers: &'a HashMap<String, String>, name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.as_str())
+}
+
+fn soap_action(headers: &HashMap<String, String>) -> Option<String> {
+    let from_header = header(headers, "soapaction").map(|v| v.trim().trim_matches('"'));
+    let from_content_type = || {
+        header(headers, "content-type")?
+            .split(';')
+            .filter_map(|p| p.trim().strip_prefix("action="))
+            .map(|v| v.trim_matches('"'))
+            .next()
+    };
+    from_header
+        .or_else(from_content_type)
+        .filter(|a| !a.is_empty())
+        .map(String::from)
+}
+
+/// Last path/fragment segment of an action URI: `"urn:svc/GetUser"` → `"GetUser"`.
+fn action_operation(action: &str) -> String {
+    action
+        .rsplit(['/', '#', ':'])
+        .find(|s| !s.is_empty())
+        .unwrap_or(action)
+        .to_string()
+}
+
+/// Local name of the first element inside the envelope's `Body`.
+fn body_operation(text: &str) -> Option<String> {
+    let tokens = tokenize(text.trim())?;
+    let mut seen_envelope = false;
+    let mut in_body = false;
+    for token in tokens {
+        let XmlToken::Open { name, .. } = token else {
+            continue;
+        };
+        let local = local_name(name);
+        if in_body {
+            return Some(local.to_string());
+        }
+        match local {
+            "Envelope" => seen_envelope = true,
+            "Body" if seen_envelope => in_body = true,
+            _ => {}
+        }
+    }
+    None
+}
+
+fn local_name(name: &str) -> &str {
+    name.rsplit(':').next().unwrap_or(name)
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// XML tokenizing and pretty-printing
+// ─────────────────────────────────────────────────────────────────────────────
+
+enum XmlToken<'a> {
+    /// Start tag (`<a x="1">`) or self-closing tag (`<a/>`).
+    Open {
+        name: &'a str,
+        raw: &'a str,
+        self_closing: bool,
+    },
+    /// End tag (`</a>`).
+    Close(&'a str),
+    /// Declaration, processing instruction, comment, or CDATA section.
+    Other(&'a str),
+    /// Character data between tags.
+    Text(&'a str),
+}
+
+/// Splits XML into tags and text. Returns `None` on an unterminated tag —
+/// a lightweight check that the input is XML at all, not a validating parse.
+fn tokenize(text: &str) -> Option<Vec<XmlToken<'_>>> {
+    let mut tokens = Vec::new();
+    let mut rest = text;
+    while !rest.is_empty() {
+        let (token, len) = if rest.starts_with("<!--") {
+            let end = rest.find("-->")? + 3;
+            (XmlToken::Other(&rest[..end]), end)
+        } else if rest.starts_with("<![CDATA[") {
+            let end = rest.find("]]>")? + 3;
+            (XmlToken::Other(&rest[..end]), end)
+        } else if rest.starts_with("</") {
+            let end = rest.find('>')? + 1;
+            (XmlToken::Close(&rest[..end]), end)
+        } else if rest.starts_with("<?") || rest.starts_with("<!") {
+            let end = rest.find('>')? + 1;
+            (XmlToken::Other(&rest[..end]), end)
+        } else if rest.starts_with('<') {
+            let end = rest.find('>')? + 1;
+            let raw = &rest[..end];
+            let name = raw[1..]
+                .split(|c: char| c.is_whitespace() || c == '/' || c == '>')
+                .next()
+                .unwrap_or_default();
+            let token = XmlToken::Open {
+                name,
+                raw,
+                self_closing: raw.ends_with("/>"),
+            };
+            (token, end)
+        } else {
+            let end = rest.find('<').unwrap_or(rest.len());
+            (XmlToken::Text(&rest[..end]), end)
+        };
+        tokens.push(token);
+        rest = &rest[len..];
+    }
+    Some(tokens)
+}
+
+/// Re-indents an XML document with one element per line (two-space indent).
+/// Elements holding only text stay on one line: `<id>42</id>`.
+///
+/// Returns `None` when the input does not look like XML.
+pub fn pretty_print_xml(text: &str) -> Option<String> {
+    let tokens = tokenize(text.trim())?;
+    if !tokens.iter().any(|t| matches!(t, XmlToken::Open { .. })) {
+        return None;
+    }
+
+    let mut out = String::new();
+    let mut push_line = |depth: usize, line: &str| {
+        out.push_str(&"  ".repeat(depth));
+        out.push_str(line);
+        out.push('\n');
+    };
+
+    let mut depth = 0usize;
+    let mut i = 0;
+    while i < tokens.len() {
+        match &tokens[i] {
+            XmlToken::Open {
+                raw,
+                self_closing: false,
+                ..
+            } => match (tokens.get(i + 1), tokens.get(i + 2)) {
+                (Some(XmlToken::Text(text)), Some(XmlToken::Close(close))) => {
+                    push_line(depth, &format!("{raw}{}{close}", text.trim()));
+                    i += 2;
+                }
+                (Some(XmlToken::Close(close)), _) => {
+                    push_line(depth, &format!("{raw}{close}"));
+                    i += 1;
+                }
+                _ => {
+                    push_line(depth, raw);
+                    depth += 1;
+                }
+            },
+            XmlToken::Open { raw, .. } | XmlToken::Other(raw) => push_line(depth, raw),
+            XmlToken::Close(raw) => {
+                depth = depth.saturating_sub(1);
+                push_line(depth, raw);
+            }
+            XmlToken::Text(text) => {
+                let text = text.trim();
+                if !text.is_empty() {
+                    push_line(depth, text);
+                }
+            }
+        }
+        i += 1;
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::*;
+    use crate::trace::{HttpMethod, SpanId, TraceId};
+
+    const ENVELOPE: &str = r#"<?xml version="1.0"?><soap:Envelope xmlns:soap="http://schemas.xmlsoap.org/soap/envelope/"><soap:Header/><soap:Body><m:GetUser xmlns:m="urn:users"><m:Id>42</m:Id></m:GetUser></soap:Body></soap:Envelope>"#;
+
+    fn make_trace(headers: &[(&str, &str)], body: Option<&str>) -> HttpTrace {
+        HttpTrace {
+            span_id: SpanId([1; 8]),
+            trace_id: TraceId([2; 16]),
+            parent_span_id: None,
+            method: HttpMethod::Post,
+            url: "http://legacy/UserService.asmx".to_string(),
+            request_headers: headers
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            request_body: body.map(|b| b.as_bytes().to_vec()),
+            status_code: 200,
+            response_headers: HashMap::new(),
+            response_body: None,
+            timestamp: UNIX_EPOCH,
+            duration: Duration::from_millis(5),
+            source_addr: None,
+            dest_addr: None,
+            protocol_version: "HTTP/1.1".to_string(),
+            request_body_file: None,
+            response_body_file: None,
+            connection_id: None,
+            process: None,
+            response_trailers: HashMap::new(),
+            request_body_encoding: None,
+            response_body_encoding: None,
+            elasticsearch: None,
+            soap: None,
+            graphql: None,
+            thread_id: None,
+            tls: None,
+            timing: None,
+            request_size: None,
+            response_size: None,
+            fault: None,
+            replay: None,
+        }
+    }
+
+    #[test]
+    fn test_detect_operation_from_body_and_action_header() {
+        let t = make_trace(
+            &[
+                ("content-type", "text/xml; charset=utf-8"),
+                ("soapaction", "\"urn:users/GetUser\""),
+            ],
+            Some(ENVELOPE),
+        );
+        let op = SoapOperation::detect(&t).unwrap();
+        assert_eq!(op.operation, "GetUser");
+        assert_eq!(op.action.as_deref(), Some("urn:users/GetUser"));
+        assert_eq!(op.label(), "SOAP GetUser");
+    }
+
+    #[test]
+    fn test_detect_soap12_action_parameter() {
+        let t = make_trace(
+            &[(
+                "Content-Type",
+                "application/soap+xml; charset=utf-8; action=\"http://tempuri.org/IOrders/PlaceOrder\"",
+            )],
+            None,
+        );
+        let op = SoapOperation::detect(&t).unwrap();
+        assert_eq!(op.operation, "PlaceOrder");
+        assert_eq!(
+            op.action.as_deref(),
+            Some("http://tempuri.org/IOrders/PlaceOrder")
+        );
+    }
+
+    #[test]
+    fn test_plain_xml_is_not_soap() {
+        let t = make_trace(
+            &[("content-type", "application/xml")],
+            Some("<order><id>1</id></order>"),
+        );
+        assert_eq!(SoapOperation::detect(&t), None);
+        assert_eq!(SoapOperation::detect(&make_trace(&[], None)), None);
+    }
+
+    #[test]
+    fn test_pretty_print_indents_nested_elements() {
+        let pretty = pretty_print_xml(ENVELOPE).unwrap();
+        let expected = r#"<?xml version="1.0"?>
+<soap:Envelope xmlns:soap="http://schemas.xmlsoap.org/soap/envelope/">
+  <soap:Header/>
+  <soap:Body>
+    <m:GetUser xmlns:m="urn:users">
+      <m:Id>42</m:Id>
+    </m:GetUser>
+  </soap:Body>
+</soap:Envelope>
+"#;
+        assert_eq!(pretty, expected);
+    }
+
+    #[test]
+    fn test_pretty_print_keeps_empty_elements_and_comments() {
+        let pretty = pretty_print_xml("<a><!-- note --><b></b></a>").unwrap();
+        assert_eq!(pretty, "<a>\n  <!-- note -->\n  <b></b>\n</a>\n");
+    }
+
+    #[test]
+    fn test_pretty_print_rejects_non_xml() {
+        assert_eq!(pretty_print_xml("just text"), None);
+        assert_eq!(pretty_print_xml("<unterminated"), None);
+    }
+}
@@ -0,0 +1,55 @@
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+/// One Server-Sent Event from a `text/event-stream` response.
+///
+/// The response itself is captured as an [`HttpTrace`](crate::trace::HttpTrace)
+/// when its headers arrive; events follow as they are dispatched.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SseEvent {
+    /// URL of the request that opened the stream.
+    pub url: String,
+    /// `event:` field; `None` means the default `message` type.
+    pub event: Option<String>,
+    /// Last event ID in effect, carried over from earlier events.
+    pub id: Option<String>,
+    /// `data:` lines joined with `\n` (cut by the agent at 16 KB).
+    pub data: String,
+    /// Full size of the data before truncation.
+    pub data_bytes: u64,
+    /// `retry:` reconnection time, when the event set one.
+    pub retry_ms: Option<u64>,
+    /// Connection the stream arrives on; matches the response trace's
+    /// `connection_id`.
+    pub connection_id: Option<String>,
+    /// Wall-clock time the event was dispatched.
+    pub timestamp: SystemTime,
+    /// Time from the request to this event.
+    pub elapsed: Duration,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sse_event_roundtrip() {
+        let event = SseEvent {
+            url: "http://localhost:3000/events".to_string(),
+            event: Some("update".to_string()),
+            id: Some("17".to_string()),
+            data: "line one\nline two".to_string(),
+            data_bytes: 17,
+            retry_ms: None,
+            connection_id: Some("42-1".to_string()),
+            timestamp: SystemTime::UNIX_EPOCH,
+            elapsed: Duration::from_millis(1500),
+        };
+        let json = serde_json::to_string(&event).unwrap();
+        let parsed: SseEvent = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.data, "line one\nline two");
+        assert_eq!(parsed.event.as_deref(), Some("update"));
+        assert_eq!(parsed.elapsed, Duration::from_millis(1500));
+    }
+}
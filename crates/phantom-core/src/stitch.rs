@@ -0,0 +1,292 @@
+use std::collections::{HashMap, HashSet};
+use std::time::SystemTime;
+
+use crate::error::StorageError;
+use crate::query::TraceQuery;
+use crate::storage::TraceStore;
+use crate::trace::{HttpTrace, SpanId, TraceId};
+
+/// Most traces scanned around a trace's time window when stitching hops.
+const STITCH_SCAN_LIMIT: usize = 10_000;
+
+fn end_of(trace: &HttpTrace) -> SystemTime {
+    trace.timestamp + trace.duration
+}
+
+/// True when `outer` strictly encloses `inner` in time.
+fn encloses(outer: &HttpTrace, inner: &HttpTrace) -> bool {
+    outer.timestamp <= inner.timestamp
+        && end_of(outer) >= end_of(inner)
+        && (outer.timestamp < inner.timestamp || end_of(outer) > end_of(inner))
+}
+
+/// True when `outer` could have caused `inner`: requests on the same
+/// connection are sequential, and calls from the same process are siblings,
+/// not hops.
+fn could_cause(outer: &HttpTrace, inner: &HttpTrace) -> bool {
+    let same_connection =
+        outer.connection_id.is_some() && outer.connection_id == inner.connection_id;
+    let same_process = outer.process.is_some() && outer.process == inner.process;
+    encloses(outer, inner) && !same_connection && !same_process
+}
+
+/// Follows `remap` from `id` to the trace it finally merges into.
+fn resolve(remap: &HashMap<TraceId, TraceId>, id: &TraceId) -> TraceId {
+    let mut id = id.clone();
+    // Bounded: nesting is strict in time, but guard against cycles anyway.
+    for _ in 0..=remap.len() {
+        match remap.get(&id) {
+            Some(next) if *next != id => id = next.clone(),
+            _ => break,
+        }
+    }
+    id
+}
+
+/// Joins captured hops into single trace trees.
+///
+/// A span is re-parented under the innermost span that encloses it in time
+/// when it has no known parent:
+///
+/// - a root span (no `parent_span_id`) moves, with the rest of its trace, into
+///   the enclosing span's trace;
+/// - a span whose `parent_span_id` points outside the set (e.g. taken from a
+///   propagated `traceparent`) keeps its trace and is attached to an enclosing
+///   span of that same trace.
+///
+/// Spans are left alone when the enclosing candidates are not nested in one
+/// another, since concurrent requests make the caller ambiguous. Returns the
+/// number of spans re-parented.
+///
+/// Spans are swept in start order, testing each only against the spans still
+/// open when it starts, so the work grows with concurrency rather than with
+/// the square of the window.
+pub fn stitch_hops(traces: &mut [HttpTrace]) -> usize {
+    let known: HashSet<SpanId> = traces.iter().map(|t| t.span_id.clone()).collect();
+
+    let mut order: Vec<usize> = (0..traces.len()).collect();
+    order.sort_by_key(|&i| traces[i].timestamp);
+
+    let mut links = Vec::new();
+    // Spans started at or before the current one that have not ended before it.
+    let mut open: Vec<usize> = Vec::new();
+    let mut started = 0;
+    for &i in &order {
+        let inner = &traces[i];
+        while let Some(&j) = order.get(started) {
+            if traces[j].timestamp > inner.timestamp {
+                break;
+            }
+            open.push(j);
+            started += 1;
+        }
+        // Later spans start no earlier, so one that ended before this one
+        // started encloses none of them.
+        open.retain(|&j| end_of(&traces[j]) >= inner.timestamp);
+
+        let orphan = match &inner.parent_span_id {
+            None => false,
+            Some(parent) if known.contains(parent) => continue,
+            Some(_) => true,
+        };
+        let is_candidate = |j: usize| {
+            j != i
+                && could_cause(&traces[j], inner)
+                && (!orphan || traces[j].trace_id == inner.trace_id)
+        };
+        let Some(innermost) = open
+            .iter()
+            .copied()
+            .filter(|&j| is_candidate(j))
+            .min_by_key(|&j| end_of(&traces[j]).duration_since(traces[j].timestamp).ok())
+        else {
+            continue;
+        };
+        if open
+            .iter()
+            .copied()
+            .filter(|&j| is_candidate(j))
+            .all(|j| j == innermost || encloses(&traces[j], &traces[innermost]))
+        {
+            links.push((i, innermost));
+        }
+    }
+    links.sort_unstable();
+
+    // A root's whole trace follows it into the caller's trace.
+    let mut remap: HashMap<TraceId, TraceId> = HashMap::new();
+    for &(i, parent) in &links {
+        if traces[i].parent_span_id.is_none() {
+            remap.insert(traces[i].trace_id.clone(), traces[parent].trace_id.clone());
+        }
+    }
+    let parents: Vec<_> = links
+        .iter()
+        .map(|&(i, parent)| (i, traces[parent].span_id.clone()))
+        .collect();
+    let trace_ids: Vec<TraceId> = traces
+        .iter()
+        .map(|t| resolve(&remap, &t.trace_id))
+        .collect();
+    for (trace, trace_id) in traces.iter_mut().zip(trace_ids) {
+        trace.trace_id = trace_id;
+    }
+    let stitched = parents.len();
+    for (i, parent) in parents {
+        traces[i].parent_span_id = Some(parent);
+    }
+    stitched
+}
+
+/// Runs `query` against `store`, stitching in hops when it selects a trace ID.
+///
+/// Spans captured during the trace's time window are fetched alongside it and
+/// passed through [`stitch_hops`], so downstream calls recorded as separate
+/// traces come back as part of the requested one. Other queries run unchanged.
+pub fn query_stitched(
+    store: &dyn TraceStore,
+    query: &TraceQuery,
+) -> Result<Vec<HttpTrace>, StorageError> {
+    let Some(trace_id) = &query.trace_id else {
+        return store.query(query);
+    };
+    let spans = store.get_by_trace_id(trace_id)?;
+    let (Some(start), Some(end)) = (
+        spans.iter().map(|t| t.timestamp).min(),
+        spans.iter().map(end_of).max(),
+    ) else {
+        return store.query(query);
+    };
+
+    let mut traces = store.query(&TraceQuery {
+        since: Some(start),
+        until: Some(end),
+        limit: STITCH_SCAN_LIMIT,
+        ..TraceQuery::default()
+    })?;
+    let seen: HashSet<SpanId> = traces.iter().map(|t| t.span_id.clone()).collect();
+    traces.extend(spans.into_iter().filter(|t| !seen.contains(&t.span_id)));
+    stitch_hops(&mut traces);
+
+    traces.retain(|t| query.matches(t));
+    traces.sort_by_key(|t| std::cmp::Reverse(t.timestamp));
+    let limit = if query.limit == 0 {
+        usize::MAX
+    } else {
+        query.limit
+    };
+    Ok(traces.into_iter().skip(query.offset).take(limit).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::*;
+    use crate::trace::{HttpMethod, ProcessInfo};
+
+    fn make_trace(span: u8, trace: u8, start_ms: u64, duration_ms: u64) -> HttpTrace {
+        HttpTrace {
+            span_id: SpanId([span; 8]),
+            trace_id: TraceId([trace; 16]),
+            parent_span_id: None,
+            method: HttpMethod::Get,
+            url: format!("http://svc{span}/"),
+            request_headers: HashMap::new(),
+            request_body: None,
+            status_code: 200,
+            response_headers: HashMap::new(),
+            response_body: None,
+            timestamp: UNIX_EPOCH + Duration::from_millis(start_ms),
+            duration: Duration::from_millis(duration_ms),
+            source_addr: None,
+            dest_addr: None,
+            protocol_version: "HTTP/1.1".to_string(),
+            request_body_file: None,
+            response_body_file: None,
+            connection_id: None,
+            process: None,
+            response_trailers: HashMap::new(),
+            request_body_encoding: None,
+            response_body_encoding: None,
+            elasticsearch: None,
+            soap: None,
+            graphql: None,
+            thread_id: None,
+            tls: None,
+            timing: None,
+            request_size: None,
+            response_size: None,
+            fault: None,
+            replay: None,
+        }
+    }
+
+    #[test]
+    fn test_stitch_chains_nested_hops() {
+        // A→B (0..100) triggers B→C (10..60), which triggers C→D (20..30).
+        let mut traces = vec![
+            make_trace(3, 3, 20, 10),
+            make_trace(1, 1, 0, 100),
+            make_trace(2, 2, 10, 50),
+        ];
+        assert_eq!(stitch_hops(&mut traces), 2);
+        assert!(traces.iter().all(|t| t.trace_id == TraceId([1; 16])));
+        assert_eq!(traces[0].parent_span_id, Some(SpanId([2; 8])));
+        assert_eq!(traces[2].parent_span_id, Some(SpanId([1; 8])));
+        assert_eq!(traces[1].parent_span_id, None);
+    }
+
+    #[test]
+    fn test_stitch_skips_ambiguous_and_sibling_calls() {
+        // Two overlapping, non-nested outer calls both enclose the inner one.
+        let mut traces = vec![
+            make_trace(1, 1, 0, 100),
+            make_trace(2, 2, 5, 100),
+            make_trace(3, 3, 20, 10),
+        ];
+        assert_eq!(stitch_hops(&mut traces), 0);
+
+        // Calls from the same process are siblings, not hops.
+        let process = Some(ProcessInfo {
+            comm: "worker".into(),
+            pid: 7,
+        });
+        let mut outer = make_trace(1, 1, 0, 100);
+        let mut inner = make_trace(2, 2, 10, 10);
+        outer.process = process.clone();
+        inner.process = process;
+        let mut traces = vec![outer, inner];
+        assert_eq!(stitch_hops(&mut traces), 0);
+    }
+
+    #[test]
+    fn test_stitch_nests_spans_sharing_a_start() {
+        // The outer call and the hop it triggers start in the same millisecond;
+        // a later call starts after the outer one has ended.
+        let mut traces = vec![
+            make_trace(2, 2, 0, 40),
+            make_trace(3, 3, 120, 10),
+            make_trace(1, 1, 0, 100),
+        ];
+        assert_eq!(stitch_hops(&mut traces), 1);
+        assert_eq!(traces[0].parent_span_id, Some(SpanId([1; 8])));
+        assert_eq!(traces[0].trace_id, TraceId([1; 16]));
+        assert_eq!(traces[1].parent_span_id, None);
+        assert_eq!(traces[1].trace_id, TraceId([3; 16]));
+    }
+
+    #[test]
+    fn test_stitch_attaches_orphans_within_their_trace() {
+        // The inner span carries a propagated parent that was never captured.
+        let mut orphan = make_trace(2, 1, 10, 10);
+        orphan.parent_span_id = Some(SpanId([9; 8]));
+        // Span 3 encloses it more tightly but belongs to another trace.
+        let mut traces = vec![make_trace(1, 1, 0, 100), orphan, make_trace(3, 5, 5, 50)];
+        assert_eq!(stitch_hops(&mut traces), 2);
+        assert_eq!(traces[1].parent_span_id, Some(SpanId([1; 8])));
+        assert_eq!(traces[1].trace_id, TraceId([1; 16]));
+        assert_eq!(traces[2].parent_span_id, Some(SpanId([1; 8])));
+    }
+}
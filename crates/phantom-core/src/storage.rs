@@ -1,5 +1,9 @@
 use crate::error::StorageError;
+use crate::grpc::GrpcTrace;
+use crate::postgres::PostgresTrace;
+use crate::protocol::ProtocolTrace;
 use crate::query::TraceQuery;
+use crate::redis::RedisTrace;
 use crate::trace::{HttpTrace, SpanId, TraceId};
 
 /// Abstraction over trace storage backends.
@@ -27,6 +31,84 @@ pub trait TraceStore: Send + Sync {
     /// the implementation's default page size.
     fn query(&self, query: &TraceQuery) -> Result<Vec<HttpTrace>, StorageError>;
 
-    /// Delete all stored traces and their indices.
+    /// Delete all stored traces and their indices, protocol records
+    /// included.
     fn clear(&self) -> Result<(), StorageError>;
+
+    /// Store a non-HTTP record in its typed store (Redis, PostgreSQL or
+    /// gRPC). Protocols without one (Thrift, Kafka, WebSocket, SSE) are
+    /// not persisted.
+    fn insert_protocol(&self, trace: &ProtocolTrace) -> Result<(), StorageError>;
+}
+
+/// Storage for captured Redis commands.
+pub trait RedisTraceStore: Send + Sync {
+    /// Store a new command.
+    fn insert_redis(&self, trace: &RedisTrace) -> Result<(), StorageError>;
+
+    /// List recent commands (newest first), with pagination.
+    fn list_recent_redis(
+        &self,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<RedisTrace>, StorageError>;
+
+    /// List recent commands (newest first) named `command`, compared
+    /// case-insensitively.
+    fn list_redis_by_command(
+        &self,
+        command: &str,
+        limit: usize,
+    ) -> Result<Vec<RedisTrace>, StorageError>;
+
+    /// Get the stored command count.
+    fn count_redis(&self) -> Result<u64, StorageError>;
+
+    /// Delete all stored commands.
+    fn clear_redis(&self) -> Result<(), StorageError>;
+}
+
+/// Storage for captured PostgreSQL queries.
+pub trait PostgresTraceStore: Send + Sync {
+    /// Store a new query.
+    fn insert_postgres(&self, trace: &PostgresTrace) -> Result<(), StorageError>;
+
+    /// List recent queries (newest first), with pagination.
+    fn list_recent_postgres(
+        &self,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<PostgresTrace>, StorageError>;
+
+    /// List recent queries (newest first) that failed with a server error.
+    fn list_failed_postgres(&self, limit: usize) -> Result<Vec<PostgresTrace>, StorageError>;
+
+    /// Get the stored query count.
+    fn count_postgres(&self) -> Result<u64, StorageError>;
+
+    /// Delete all stored queries.
+    fn clear_postgres(&self) -> Result<(), StorageError>;
+}
+
+/// Storage for captured gRPC calls.
+pub trait GrpcTraceStore: Send + Sync {
+    /// Store a new call.
+    fn insert_grpc(&self, trace: &GrpcTrace) -> Result<(), StorageError>;
+
+    /// List recent calls (newest first), with pagination.
+    fn list_recent_grpc(&self, limit: usize, offset: usize)
+    -> Result<Vec<GrpcTrace>, StorageError>;
+
+    /// List recent calls (newest first) to the fully-qualified `service`.
+    fn list_grpc_by_service(
+        &self,
+        service: &str,
+        limit: usize,
+    ) -> Result<Vec<GrpcTrace>, StorageError>;
+
+    /// Get the stored call count.
+    fn count_grpc(&self) -> Result<u64, StorageError>;
+
+    /// Delete all stored calls.
+    fn clear_grpc(&self) -> Result<(), StorageError>;
 }
@@ -0,0 +1,80 @@
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+/// Thrift message type from the message header (`TMessageType`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ThriftMessageType {
+    Call,
+    Reply,
+    Exception,
+    Oneway,
+}
+
+impl fmt::Display for ThriftMessageType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::Call => "call",
+            Self::Reply => "reply",
+            Self::Exception => "exception",
+            Self::Oneway => "oneway",
+        };
+        f.write_str(s)
+    }
+}
+
+/// Thrift wire encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ThriftProtocol {
+    /// `TBinaryProtocol` (strict, versioned header).
+    Binary,
+    /// `TCompactProtocol`.
+    Compact,
+}
+
+/// A completed Thrift RPC: a call matched to its reply by sequence id.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThriftTrace {
+    /// Service method name from the message header.
+    pub method: String,
+    /// Sequence id shared by the call and its reply.
+    pub seq_id: i32,
+    /// Type of the closing message: `Reply`, `Exception`, or `Oneway` for
+    /// calls that expect no reply.
+    pub message_type: ThriftMessageType,
+    /// Wire encoding of the messages.
+    pub protocol: ThriftProtocol,
+    /// True for `TFramedTransport` (4-byte length prefix per message).
+    pub framed: bool,
+    /// Wall-clock time the call was sent.
+    pub timestamp: SystemTime,
+    /// Time from call to reply (zero for oneway calls).
+    pub duration: Duration,
+    /// Destination socket address, if available.
+    pub dest_addr: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_type_serializes_lowercase() {
+        let json = serde_json::to_string(&ThriftMessageType::Exception).unwrap();
+        assert_eq!(json, "\"exception\"");
+        let parsed: ThriftMessageType = serde_json::from_str("\"oneway\"").unwrap();
+        assert_eq!(parsed, ThriftMessageType::Oneway);
+        assert_eq!(ThriftMessageType::Reply.to_string(), "reply");
+    }
+
+    #[test]
+    fn test_protocol_roundtrip() {
+        let json = serde_json::to_string(&ThriftProtocol::Compact).unwrap();
+        assert_eq!(json, "\"compact\"");
+        let parsed: ThriftProtocol = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, ThriftProtocol::Compact);
+    }
+}
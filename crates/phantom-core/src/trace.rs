@@ -5,6 +5,10 @@ use std::time::{Duration, SystemTime};
 
 use serde::{Deserialize, Serialize};
 
+use crate::elasticsearch::EsOperation;
+use crate::graphql::GraphQlOperation;
+use crate::soap::SoapOperation;
+
 /// Decodes a fixed-length lowercase/uppercase hex string into a byte array.
 fn decode_hex<const N: usize>(s: &str) -> Option<[u8; N]> {
     if s.len() != N * 2 || !s.is_ascii() {
@@ -67,8 +71,39 @@ impl fmt::Display for SpanId {
     }
 }
 
+/// Trace ID and parent span ID carried by a W3C `traceparent` header value
+/// (`<version>-<trace-id>-<parent-id>-<flags>`). Version `ff` and all-zero
+/// IDs are invalid; later versions may append fields, which are ignored.
+pub fn parse_traceparent(value: &str) -> Option<(TraceId, SpanId)> {
+    let mut parts = value.trim().split('-');
+    let version = parts.next()?;
+    let (trace_id, parent_id, flags) = (parts.next()?, parts.next()?, parts.next()?);
+    if version.len() != 2
+        || version.eq_ignore_ascii_case("ff")
+        || decode_hex::<1>(version).is_none()
+        || decode_hex::<1>(flags).is_none()
+        || (version == "00" && parts.next().is_some())
+    {
+        return None;
+    }
+    let trace_id = TraceId::from_hex(trace_id).filter(|id| id.0 != [0; 16])?;
+    let parent_id = SpanId::from_hex(parent_id).filter(|id| id.0 != [0; 8])?;
+    Some((trace_id, parent_id))
+}
+
+/// Path of a full URL, without its query string or fragment; `"/"` when it
+/// has none.
+pub fn url_path(url: &str) -> &str {
+    let after_scheme = url.split_once("://").map(|(_, rest)| rest).unwrap_or(url);
+    let path = after_scheme
+        .find('/')
+        .map(|i| &after_scheme[i..])
+        .unwrap_or("/");
+    path.split(['?', '#']).next().unwrap_or(path)
+}
+
 /// HTTP method.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(rename_all = "UPPERCASE")]
 pub enum HttpMethod {
     Get,
@@ -123,6 +158,113 @@ impl FromStr for HttpMethod {
     }
 }
 
+/// The process that issued a request, as reported by the capture backend.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct ProcessInfo {
+    /// Command name (`/proc/<pid>/comm`), empty when unknown.
+    #[serde(default)]
+    pub comm: String,
+    pub pid: u32,
+}
+
+impl fmt::Display for ProcessInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.comm.is_empty() {
+            write!(f, "pid {}", self.pid)
+        } else {
+            write!(f, "{} ({})", self.comm, self.pid)
+        }
+    }
+}
+
+/// TLS session details negotiated for a request, as reported by the TLS
+/// library. Any part the library could not report is `None`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TlsInfo {
+    /// Server name sent in the ClientHello (SNI).
+    #[serde(default)]
+    pub server_name: Option<String>,
+    /// Protocol version, e.g. `"TLSv1.3"`.
+    #[serde(default)]
+    pub version: Option<String>,
+    /// Negotiated cipher suite, e.g. `"TLS_AES_128_GCM_SHA256"`.
+    #[serde(default)]
+    pub cipher: Option<String>,
+    /// Protocol selected through ALPN, e.g. `"h2"`.
+    #[serde(default)]
+    pub alpn: Option<String>,
+}
+
+impl fmt::Display for TlsInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts: Vec<String> = [
+            self.version.clone(),
+            self.cipher.clone(),
+            self.alpn.as_ref().map(|alpn| format!("alpn {alpn}")),
+            self.server_name.as_ref().map(|sni| format!("sni {sni}")),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        f.write_str(&parts.join(", "))
+    }
+}
+
+/// Breakdown of a request's duration, like `curl -w`'s timers. The
+/// connection phases are only known for the first request on a connection.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimingPhases {
+    /// Host name resolution, when it happened separately from `connect`
+    /// (the proxy resolves names itself; the agent only sees `connect()`).
+    #[serde(default)]
+    pub dns: Option<Duration>,
+    /// `connect()` call until the connection was up.
+    #[serde(default)]
+    pub connect: Option<Duration>,
+    /// Connection up until the TLS handshake finished.
+    #[serde(default)]
+    pub tls_handshake: Option<Duration>,
+    /// Request start until the first response byte (time to first byte).
+    #[serde(default)]
+    pub ttfb: Option<Duration>,
+}
+
+impl fmt::Display for TimingPhases {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts: Vec<String> = [
+            ("DNS", self.dns),
+            ("connect", self.connect),
+            ("TLS", self.tls_handshake),
+            ("TTFB", self.ttfb),
+        ]
+        .into_iter()
+        .filter_map(|(name, phase)| phase.map(|d| format!("{name} {} ms", d.as_millis())))
+        .collect();
+        f.write_str(&parts.join(", "))
+    }
+}
+
+/// How the proxy's `--replay` mode handled a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReplayOutcome {
+    /// Answered from the recording; the request never left phantom.
+    Hit,
+    /// Not in the recording: forwarded upstream and recorded.
+    Miss,
+}
+
+/// Original `Content-Encoding` of a body that was stored decompressed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BodyEncoding {
+    /// Content coding as sent, e.g. `"gzip"` or `"br"`.
+    pub encoding: String,
+    /// Decoding stopped early: the compressed bytes were cut short at capture
+    /// time, or the decoded body hit the capture limit.
+    #[serde(default)]
+    pub truncated: bool,
+}
+
 /// A complete HTTP request-response pair with timing metadata.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HttpTrace {
@@ -142,6 +284,10 @@ pub struct HttpTrace {
     // -- Response --
     pub status_code: u16,
     pub response_headers: HashMap<String, String>,
+    /// Trailing headers sent after the response body (HTTP/2 trailers, e.g.
+    /// `grpc-status`), lower-cased. Empty when there were none.
+    #[serde(default)]
+    pub response_trailers: HashMap<String, String>,
     pub response_body: Option<Vec<u8>>,
 
     // -- Timing --
@@ -152,6 +298,91 @@ pub struct HttpTrace {
     pub source_addr: Option<String>,
     pub dest_addr: Option<String>,
     pub protocol_version: String,
+
+    // -- Body overflow --
+    /// Path of a file holding the full request body when `request_body` was
+    /// cut at the capture limit.
+    #[serde(default)]
+    pub request_body_file: Option<String>,
+    /// Path of a file holding the full response body when `response_body`
+    /// was cut at the capture limit.
+    #[serde(default)]
+    pub response_body_file: Option<String>,
+
+    // -- Connection --
+    /// Identifies the client connection the request travelled on; requests
+    /// reusing a keep-alive connection share it. `None` when unknown.
+    #[serde(default)]
+    pub connection_id: Option<String>,
+
+    // -- Process --
+    /// Originating process, when the backend runs inside it (ldpreload).
+    #[serde(default)]
+    pub process: Option<ProcessInfo>,
+    /// Thread (Linux TID) that sent the request, alongside `process`.
+    #[serde(default)]
+    pub thread_id: Option<u32>,
+
+    // -- TLS --
+    /// Negotiated TLS session details, for HTTPS captured inside the process.
+    #[serde(default)]
+    pub tls: Option<TlsInfo>,
+
+    // -- Timing phases --
+    /// Where the request's time went, when the backend could observe it.
+    #[serde(default)]
+    pub timing: Option<TimingPhases>,
+
+    // -- Size --
+    /// Bytes of the request as sent on the wire (head and encoded body, or
+    /// the stream's HTTP/2 frames), before any capture truncation.
+    #[serde(default)]
+    pub request_size: Option<u64>,
+    /// Bytes of the response as received on the wire.
+    #[serde(default)]
+    pub response_size: Option<u64>,
+
+    // -- Fault injection --
+    /// Faults the proxy injected into this exchange, in the order applied,
+    /// e.g. `"delay:120ms, error:503"`. `None` for untouched traffic.
+    #[serde(default)]
+    pub fault: Option<String>,
+
+    // -- Replay --
+    /// Set in `--replay` mode: whether the response came from the recording.
+    #[serde(default)]
+    pub replay: Option<ReplayOutcome>,
+
+    // -- Content coding --
+    /// Set when `request_body` was decompressed from its `Content-Encoding`.
+    /// Body files keep the bytes as sent.
+    #[serde(default)]
+    pub request_body_encoding: Option<BodyEncoding>,
+    /// Set when `response_body` was decompressed from its `Content-Encoding`.
+    #[serde(default)]
+    pub response_body_encoding: Option<BodyEncoding>,
+
+    // -- Protocol tags --
+    /// Elasticsearch/OpenSearch call recognized in the exchange.
+    #[serde(default)]
+    pub elasticsearch: Option<EsOperation>,
+    /// SOAP call recognized in the exchange.
+    #[serde(default)]
+    pub soap: Option<SoapOperation>,
+    /// GraphQL operation recognized in the exchange.
+    #[serde(default)]
+    pub graphql: Option<GraphQlOperation>,
+}
+
+impl HttpTrace {
+    /// Fills in the protocol tags (`elasticsearch`, `soap`, `graphql`).
+    /// Run once as a finished trace enters the pipeline, so views read the
+    /// stored tags instead of re-parsing bodies.
+    pub fn tag_operations(&mut self) {
+        self.elasticsearch = EsOperation::detect(self);
+        self.soap = SoapOperation::detect(self);
+        self.graphql = GraphQlOperation::detect(self);
+    }
 }
 
 #[cfg(test)]
@@ -188,6 +419,42 @@ mod tests {
         assert_eq!(TraceId::from_hex("0123456789abcdef"), None); // span-length for trace
     }
 
+    #[test]
+    fn test_parse_traceparent() {
+        let (trace_id, parent_id) =
+            parse_traceparent("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01").unwrap();
+        assert_eq!(trace_id.to_string(), "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(parent_id.to_string(), "00f067aa0ba902b7");
+        // A later version may carry more fields.
+        assert!(
+            parse_traceparent("01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-xyz")
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn test_parse_traceparent_rejects_invalid() {
+        for value in [
+            "",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra",
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e47-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-zz",
+        ] {
+            assert!(parse_traceparent(value).is_none(), "{value}");
+        }
+    }
+
+    #[test]
+    fn test_url_path() {
+        assert_eq!(url_path("https://api.test/v1/users?page=2#top"), "/v1/users");
+        assert_eq!(url_path("http://api.test"), "/");
+        assert_eq!(url_path("/graphql?x=1"), "/graphql");
+    }
+
     #[test]
     fn test_http_method_from_str() {
         assert_eq!("GET".parse::<HttpMethod>().unwrap(), HttpMethod::Get);
@@ -197,4 +464,36 @@ mod tests {
         assert!("FETCH".parse::<HttpMethod>().is_err());
         assert!("".parse::<HttpMethod>().is_err());
     }
+
+    #[test]
+    fn test_tls_info_display_skips_missing_parts() {
+        let tls = TlsInfo {
+            server_name: Some("example.com".to_string()),
+            version: Some("TLSv1.3".to_string()),
+            cipher: None,
+            alpn: Some("h2".to_string()),
+        };
+        assert_eq!(tls.to_string(), "TLSv1.3, alpn h2, sni example.com");
+        assert_eq!(TlsInfo::default().to_string(), "");
+    }
+
+    #[test]
+    fn test_timing_phases_display_skips_unknown_phases() {
+        let timing = TimingPhases {
+            dns: None,
+            connect: None,
+            tls_handshake: Some(Duration::from_millis(12)),
+            ttfb: Some(Duration::from_millis(40)),
+        };
+        assert_eq!(timing.to_string(), "TLS 12 ms, TTFB 40 ms");
+        let timing = TimingPhases {
+            dns: Some(Duration::from_millis(2)),
+            connect: Some(Duration::from_millis(5)),
+            ..timing
+        };
+        assert_eq!(
+            timing.to_string(),
+            "DNS 2 ms, connect 5 ms, TLS 12 ms, TTFB 40 ms"
+        );
+    }
 }
@@ -1,9 +1,17 @@
 use std::collections::HashMap;
-use std::time::UNIX_EPOCH;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use serde::Serialize;
 
-use crate::trace::HttpTrace;
+use crate::elasticsearch::EsOperation;
+use crate::graphql::GraphQlOperation;
+use crate::postgres::PostgresError;
+use crate::protocol::ProtocolTrace;
+use crate::redis::RedisReplyType;
+use crate::soap::SoapOperation;
+use crate::thrift::{ThriftMessageType, ThriftProtocol};
+use crate::trace::{HttpTrace, ProcessInfo, ReplayOutcome, TimingPhases, TlsInfo};
+use crate::websocket::{WebSocketDirection, WebSocketOpcode};
 
 /// Controls how much of a trace is included when rendering a [`TraceView`].
 ///
@@ -39,7 +47,7 @@ impl RenderOptions {
 /// This is the canonical JSON shape shared by the JSONL output stream, the
 /// query CLI, and the MCP server. With default [`RenderOptions`] the emitted
 /// fields are a superset of the historical JSONL schema (add-only:
-/// `*_body_bytes` and `*_body_truncated`).
+/// `*_body_bytes`, `*_body_truncated`, `*_body_file` and `*_body_encoding`).
 #[derive(Debug, Clone, Serialize)]
 pub struct TraceView {
     /// Unix timestamp of the request in milliseconds.
@@ -56,6 +64,9 @@ pub struct TraceView {
     pub request_headers: HashMap<String, String>,
     /// Response headers (lower-cased keys).
     pub response_headers: HashMap<String, String>,
+    /// HTTP/2 response trailers (lower-cased keys), e.g. `grpc-status`.
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub response_trailers: HashMap<String, String>,
     /// Request body decoded as UTF-8 (replacement chars for non-UTF-8 bytes).
     #[serde(skip_serializing_if = "Option::is_none")]
     pub request_body: Option<String>,
@@ -68,24 +79,73 @@ pub struct TraceView {
     /// Original response body size in bytes (present iff a body existed).
     #[serde(skip_serializing_if = "Option::is_none")]
     pub response_body_bytes: Option<u64>,
-    /// True when `request_body` was truncated by `max_body`.
+    /// True when `request_body` was truncated by `max_body` or at capture time.
     #[serde(skip_serializing_if = "std::ops::Not::not")]
     pub request_body_truncated: bool,
-    /// True when `response_body` was truncated by `max_body`.
+    /// True when `response_body` was truncated by `max_body` or at capture time.
     #[serde(skip_serializing_if = "std::ops::Not::not")]
     pub response_body_truncated: bool,
+    /// File holding the full request body when it exceeded the capture limit.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_body_file: Option<String>,
+    /// File holding the full response body when it exceeded the capture limit.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_body_file: Option<String>,
+    /// `Content-Encoding` the request body was decompressed from.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_body_encoding: Option<String>,
+    /// `Content-Encoding` the response body was decompressed from.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_body_encoding: Option<String>,
     /// Source socket address, if available.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub source_addr: Option<String>,
     /// Destination socket address, if available.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub dest_addr: Option<String>,
+    /// Process that issued the request (ldpreload captures only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub process: Option<ProcessInfo>,
+    /// Thread (Linux TID) that sent the request (ldpreload captures only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thread_id: Option<u32>,
+    /// Negotiated TLS session details (ldpreload HTTPS captures only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls: Option<TlsInfo>,
+    /// Connect, TLS handshake and time-to-first-byte phases, when captured.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timing: Option<TimingView>,
+    /// Wire bytes of the request, before truncation (ldpreload captures only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_size: Option<u64>,
+    /// Wire bytes of the response, before truncation (ldpreload captures only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_size: Option<u64>,
+    /// Faults injected by the proxy (`--fault`), e.g. `"delay:120ms, reset"`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fault: Option<String>,
+    /// `"hit"` when `--replay` answered from the recording, `"miss"` when the
+    /// request went upstream.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replay: Option<ReplayOutcome>,
+    /// Elasticsearch/OpenSearch call details, when the URL hits an ES endpoint.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub elasticsearch: Option<EsOperation>,
+    /// SOAP operation and action, when the request is a SOAP call.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub soap: Option<SoapOperation>,
+    /// GraphQL operation type and name, when the request is a GraphQL call.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub graphql: Option<GraphQlOperation>,
     /// HTTP protocol version string (e.g. "HTTP/1.1").
     pub protocol_version: String,
     /// 128-bit W3C trace ID (hex).
     pub trace_id: String,
     /// 64-bit span ID (hex).
     pub span_id: String,
+    /// Span ID of the calling span (hex), for spans stitched into a trace tree.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent_span_id: Option<String>,
 }
 
 /// Decodes a body as lossy UTF-8, applying `headers_only`/`max_body` policy.
@@ -129,13 +189,40 @@ fn render_headers(headers: &HashMap<String, String>, redact: &[String]) -> HashM
         .collect()
 }
 
+/// [`TimingPhases`] in milliseconds, matching `duration_ms`.
+#[derive(Debug, Clone, Serialize)]
+pub struct TimingView {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dns_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connect_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls_handshake_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ttfb_ms: Option<u64>,
+}
+
+impl TimingView {
+    fn from_phases(phases: &TimingPhases) -> Self {
+        let ms = |d: Option<std::time::Duration>| d.map(|d| d.as_millis() as u64);
+        Self {
+            dns_ms: ms(phases.dns),
+            connect_ms: ms(phases.connect),
+            tls_handshake_ms: ms(phases.tls_handshake),
+            ttfb_ms: ms(phases.ttfb),
+        }
+    }
+}
+
+fn unix_millis(ts: SystemTime) -> u64 {
+    ts.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
 impl TraceView {
     pub fn render(trace: &HttpTrace, opts: &RenderOptions) -> Self {
-        let timestamp_ms = trace
-            .timestamp
-            .duration_since(UNIX_EPOCH)
-            .map(|d| d.as_millis() as u64)
-            .unwrap_or(0);
+        let timestamp_ms = unix_millis(trace.timestamp);
         let (request_body, request_body_bytes, request_body_truncated) =
             render_body(&trace.request_body, opts);
         let (response_body, response_body_bytes, response_body_truncated) =
@@ -149,17 +236,50 @@ impl TraceView {
             status_code: trace.status_code,
             request_headers: render_headers(&trace.request_headers, &opts.redact_headers),
             response_headers: render_headers(&trace.response_headers, &opts.redact_headers),
+            response_trailers: render_headers(&trace.response_trailers, &opts.redact_headers),
             request_body,
             response_body,
             request_body_bytes,
             response_body_bytes,
-            request_body_truncated,
-            response_body_truncated,
+            request_body_truncated: request_body_truncated
+                || trace.request_body_file.is_some()
+                || trace
+                    .request_body_encoding
+                    .as_ref()
+                    .is_some_and(|e| e.truncated),
+            response_body_truncated: response_body_truncated
+                || trace.response_body_file.is_some()
+                || trace
+                    .response_body_encoding
+                    .as_ref()
+                    .is_some_and(|e| e.truncated),
+            request_body_file: trace.request_body_file.clone(),
+            response_body_file: trace.response_body_file.clone(),
+            request_body_encoding: trace
+                .request_body_encoding
+                .as_ref()
+                .map(|e| e.encoding.clone()),
+            response_body_encoding: trace
+                .response_body_encoding
+                .as_ref()
+                .map(|e| e.encoding.clone()),
             source_addr: trace.source_addr.clone(),
             dest_addr: trace.dest_addr.clone(),
+            process: trace.process.clone(),
+            thread_id: trace.thread_id,
+            tls: trace.tls.clone(),
+            timing: trace.timing.as_ref().map(TimingView::from_phases),
+            request_size: trace.request_size,
+            response_size: trace.response_size,
+            fault: trace.fault.clone(),
+            replay: trace.replay,
+            elasticsearch: trace.elasticsearch.clone(),
+            soap: trace.soap.clone(),
+            graphql: trace.graphql.clone(),
             protocol_version: trace.protocol_version.clone(),
             trace_id: trace.trace_id.to_string(),
             span_id: trace.span_id.to_string(),
+            parent_span_id: trace.parent_span_id.as_ref().map(ToString::to_string),
         }
     }
 }
@@ -170,12 +290,309 @@ impl From<&HttpTrace> for TraceView {
     }
 }
 
+/// JSON shape of a [`ProtocolTrace`] in the JSONL stream, tagged by `kind`
+/// so consumers can tell it apart from HTTP [`TraceView`] lines.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum ProtocolTraceView {
+    Thrift(ThriftView),
+    Postgres(PostgresView),
+    Redis(RedisView),
+    Kafka(KafkaView),
+    Grpc(GrpcView),
+    WebSocket(WebSocketView),
+    Sse(SseView),
+}
+
+/// A Thrift call/reply pair.
+#[derive(Debug, Clone, Serialize)]
+pub struct ThriftView {
+    /// Unix timestamp of the call in milliseconds.
+    pub timestamp_ms: u64,
+    /// Call-to-reply latency in milliseconds.
+    pub duration_ms: u64,
+    /// Service method name.
+    pub method: String,
+    /// Sequence id of the call.
+    pub seq_id: i32,
+    /// `"reply"`, `"exception"`, or `"oneway"`.
+    pub message_type: ThriftMessageType,
+    /// `"binary"` or `"compact"`.
+    pub protocol: ThriftProtocol,
+    /// True for framed transport.
+    pub framed: bool,
+    /// Destination socket address, if available.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dest_addr: Option<String>,
+}
+
+/// A PostgreSQL query and its outcome.
+#[derive(Debug, Clone, Serialize)]
+pub struct PostgresView {
+    /// Unix timestamp of the query in milliseconds.
+    pub timestamp_ms: u64,
+    /// Query-to-completion latency in milliseconds.
+    pub duration_ms: u64,
+    /// SQL text.
+    pub query: String,
+    /// Completion command (`"SELECT"`, `"UPDATE"`, …).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    /// Rows returned or affected.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rows: Option<u64>,
+    /// Server error (`severity`, `code`, `message`), when the query failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<PostgresError>,
+    /// Database from the connection's startup message.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub database: Option<String>,
+    /// User from the connection's startup message.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    /// Destination socket address, if available.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dest_addr: Option<String>,
+}
+
+/// A Redis command and its reply.
+#[derive(Debug, Clone, Serialize)]
+pub struct RedisView {
+    /// Unix timestamp of the command in milliseconds.
+    pub timestamp_ms: u64,
+    /// Command-to-reply latency in milliseconds.
+    pub duration_ms: u64,
+    /// Upper-cased command name (`"GET"`, `"CLIENT SETNAME"`).
+    pub command: String,
+    /// First key argument, for keyed commands.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    /// Leading arguments after the key (shortened; redacted for `AUTH`).
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+    /// Number of arguments after the command name.
+    pub arg_count: u32,
+    /// `"simple"`, `"error"`, `"integer"`, `"bulk"`, `"null"`, `"array"`, …
+    pub reply_type: RedisReplyType,
+    /// Message of an error reply.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Destination socket address, if available.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dest_addr: Option<String>,
+}
+
+/// A Kafka request and its response.
+#[derive(Debug, Clone, Serialize)]
+pub struct KafkaView {
+    /// Unix timestamp of the request in milliseconds.
+    pub timestamp_ms: u64,
+    /// Request-to-response latency in milliseconds.
+    pub duration_ms: u64,
+    pub api_key: i16,
+    /// `"Produce"`, `"Fetch"`, `"Metadata"`, …
+    pub api: String,
+    pub api_version: i16,
+    pub correlation_id: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub topics: Vec<String>,
+    /// First non-zero Kafka error code in the response.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<i16>,
+    /// Produce with `acks=0`, which gets no response.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub no_response: bool,
+    /// Destination socket address, if available.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dest_addr: Option<String>,
+}
+
+/// A gRPC call and its status.
+#[derive(Debug, Clone, Serialize)]
+pub struct GrpcView {
+    /// Unix timestamp of the call in milliseconds.
+    pub timestamp_ms: u64,
+    /// Call-to-status latency in milliseconds.
+    pub duration_ms: u64,
+    /// `"helloworld.Greeter"`.
+    pub service: String,
+    /// `"SayHello"`.
+    pub method: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub authority: Option<String>,
+    /// `grpc-status` code; omitted when the call ended without one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<u32>,
+    /// Name of the status code, e.g. `"NOT_FOUND"`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status_name: Option<&'static str>,
+    /// `grpc-message` of a failed call.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    pub request_messages: u32,
+    pub response_messages: u32,
+    /// Destination socket address, if available.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dest_addr: Option<String>,
+}
+
+/// One WebSocket frame.
+#[derive(Debug, Clone, Serialize)]
+pub struct WebSocketView {
+    /// Unix timestamp the frame was seen, in milliseconds.
+    pub timestamp_ms: u64,
+    /// URL of the upgrade request.
+    pub url: String,
+    /// `"send"` or `"recv"`, from the traced process's side.
+    pub direction: WebSocketDirection,
+    /// `"text"`, `"binary"`, `"close"`, `"ping"`, `"pong"`, `"continuation"`.
+    pub opcode: WebSocketOpcode,
+    pub fin: bool,
+    pub masked: bool,
+    /// Payload deflated by `permessage-deflate`.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub compressed: bool,
+    pub payload_len: u64,
+    /// Leading payload bytes as lossy UTF-8 (omitted when compressed).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preview: Option<String>,
+    /// Status code of a close frame.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub close_code: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connection_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dest_addr: Option<String>,
+}
+
+/// One Server-Sent Event.
+#[derive(Debug, Clone, Serialize)]
+pub struct SseView {
+    /// Unix timestamp the event was dispatched, in milliseconds.
+    pub timestamp_ms: u64,
+    /// Milliseconds from the request to this event.
+    pub elapsed_ms: u64,
+    /// URL of the request that opened the stream.
+    pub url: String,
+    /// Event type; omitted for the default `message`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub event: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub data: String,
+    /// Full data size; larger than `data` when it was truncated.
+    pub data_bytes: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connection_id: Option<String>,
+}
+
+impl From<&ProtocolTrace> for ProtocolTraceView {
+    fn from(trace: &ProtocolTrace) -> Self {
+        match trace {
+            ProtocolTrace::Thrift(t) => Self::Thrift(ThriftView {
+                timestamp_ms: unix_millis(t.timestamp),
+                duration_ms: t.duration.as_millis() as u64,
+                method: t.method.clone(),
+                seq_id: t.seq_id,
+                message_type: t.message_type,
+                protocol: t.protocol,
+                framed: t.framed,
+                dest_addr: t.dest_addr.clone(),
+            }),
+            ProtocolTrace::Postgres(p) => Self::Postgres(PostgresView {
+                timestamp_ms: unix_millis(p.timestamp),
+                duration_ms: p.duration.as_millis() as u64,
+                query: p.query.clone(),
+                command: p.command.clone(),
+                rows: p.rows,
+                error: p.error.clone(),
+                database: p.database.clone(),
+                user: p.user.clone(),
+                dest_addr: p.dest_addr.clone(),
+            }),
+            ProtocolTrace::Redis(r) => Self::Redis(RedisView {
+                timestamp_ms: unix_millis(r.timestamp),
+                duration_ms: r.duration.as_millis() as u64,
+                command: r.command.clone(),
+                key: r.key.clone(),
+                args: r.args.clone(),
+                arg_count: r.arg_count,
+                reply_type: r.reply_type,
+                error: r.error.clone(),
+                dest_addr: r.dest_addr.clone(),
+            }),
+            ProtocolTrace::Kafka(k) => Self::Kafka(KafkaView {
+                timestamp_ms: unix_millis(k.timestamp),
+                duration_ms: k.duration.as_millis() as u64,
+                api_key: k.api_key,
+                api: k.api.clone(),
+                api_version: k.api_version,
+                correlation_id: k.correlation_id,
+                client_id: k.client_id.clone(),
+                topics: k.topics.clone(),
+                error_code: k.error_code,
+                no_response: k.no_response,
+                dest_addr: k.dest_addr.clone(),
+            }),
+            ProtocolTrace::Grpc(g) => Self::Grpc(GrpcView {
+                timestamp_ms: unix_millis(g.timestamp),
+                duration_ms: g.duration.as_millis() as u64,
+                service: g.service.clone(),
+                method: g.method.clone(),
+                authority: g.authority.clone(),
+                status: g.status,
+                status_name: g.status_name(),
+                message: g.message.clone(),
+                request_messages: g.request_messages,
+                response_messages: g.response_messages,
+                dest_addr: g.dest_addr.clone(),
+            }),
+            ProtocolTrace::WebSocket(w) => Self::WebSocket(WebSocketView {
+                timestamp_ms: unix_millis(w.timestamp),
+                url: w.url.clone(),
+                direction: w.direction,
+                opcode: w.opcode,
+                fin: w.fin,
+                masked: w.masked,
+                compressed: w.compressed,
+                payload_len: w.payload_len,
+                preview: (!w.compressed && !w.preview.is_empty())
+                    .then(|| String::from_utf8_lossy(&w.preview).into_owned()),
+                close_code: w.close_code,
+                connection_id: w.connection_id.clone(),
+                dest_addr: w.dest_addr.clone(),
+            }),
+            ProtocolTrace::Sse(e) => Self::Sse(SseView {
+                timestamp_ms: unix_millis(e.timestamp),
+                elapsed_ms: e.elapsed.as_millis() as u64,
+                url: e.url.clone(),
+                event: e.event.clone(),
+                id: e.id.clone(),
+                data: e.data.clone(),
+                data_bytes: e.data_bytes,
+                retry_ms: e.retry_ms,
+                connection_id: e.connection_id.clone(),
+            }),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use std::time::{Duration, SystemTime};
+    use std::time::Duration;
 
     use super::*;
+    use crate::grpc::GrpcTrace;
+    use crate::kafka::KafkaTrace;
+    use crate::postgres::PostgresTrace;
+    use crate::redis::RedisTrace;
+    use crate::thrift::ThriftTrace;
     use crate::trace::{HttpMethod, SpanId, TraceId};
+    use crate::websocket::WebSocketFrame;
 
     fn make_trace(request_body: Option<Vec<u8>>, response_body: Option<Vec<u8>>) -> HttpTrace {
         let mut request_headers = HashMap::new();
@@ -197,6 +614,23 @@ mod tests {
             source_addr: None,
             dest_addr: None,
             protocol_version: "HTTP/1.1".to_string(),
+            request_body_file: None,
+            response_body_file: None,
+            connection_id: None,
+            process: None,
+            response_trailers: HashMap::new(),
+            request_body_encoding: None,
+            response_body_encoding: None,
+            elasticsearch: None,
+            soap: None,
+            graphql: None,
+            thread_id: None,
+            tls: None,
+            timing: None,
+            request_size: None,
+            response_size: None,
+            fault: None,
+            replay: None,
         }
     }
 
@@ -340,6 +774,236 @@ mod tests {
         assert!(!obj.contains_key("request_body"));
         assert!(!obj.contains_key("request_body_bytes"));
         assert!(!obj.contains_key("request_body_truncated"));
+        assert!(!obj.contains_key("elasticsearch"));
+        assert!(!obj.contains_key("soap"));
+        assert!(!obj.contains_key("graphql"));
+        assert!(!obj.contains_key("response_body_file"));
+        assert!(!obj.contains_key("process"));
+        assert!(!obj.contains_key("response_trailers"));
         assert_eq!(obj["method"], "POST");
     }
+
+    #[test]
+    fn test_render_includes_trailers() {
+        let mut t = make_trace(None, None);
+        t.response_trailers
+            .insert("grpc-status".to_string(), "14".to_string());
+        t.response_trailers
+            .insert("grpc-message".to_string(), "unavailable".to_string());
+        let json = serde_json::to_value(TraceView::render(&t, &RenderOptions::default())).unwrap();
+        assert_eq!(json["response_trailers"]["grpc-status"], "14");
+        assert_eq!(json["response_trailers"]["grpc-message"], "unavailable");
+    }
+
+    #[test]
+    fn test_render_includes_replay_outcome() {
+        let mut t = make_trace(None, None);
+        let json = serde_json::to_value(TraceView::from(&t)).unwrap();
+        assert!(json.get("replay").is_none());
+        t.replay = Some(ReplayOutcome::Miss);
+        let json = serde_json::to_value(TraceView::from(&t)).unwrap();
+        assert_eq!(json["replay"], "miss");
+    }
+
+    #[test]
+    fn test_render_includes_process() {
+        let mut t = make_trace(None, None);
+        t.process = Some(ProcessInfo {
+            comm: "curl".into(),
+            pid: 4242,
+        });
+        let json = serde_json::to_value(TraceView::from(&t)).unwrap();
+        assert_eq!(json["process"]["comm"], "curl");
+        assert_eq!(json["process"]["pid"], 4242);
+        assert_eq!(t.process.unwrap().to_string(), "curl (4242)");
+    }
+
+    #[test]
+    fn test_render_flags_capture_overflow() {
+        let mut t = make_trace(None, Some(b"partial".to_vec()));
+        t.response_body_file = Some("/data/bodies/0101010101010101.response.bin".into());
+        let v = TraceView::render(&t, &RenderOptions::default());
+        assert!(v.response_body_truncated);
+        assert!(!v.request_body_truncated);
+        let json = serde_json::to_value(&v).unwrap();
+        assert_eq!(
+            json["response_body_file"],
+            "/data/bodies/0101010101010101.response.bin"
+        );
+    }
+
+    #[test]
+    fn test_render_tags_elasticsearch_calls() {
+        let mut t = make_trace(Some(br#"{"query":{"term":{"sku":"a1"}}}"#.to_vec()), None);
+        t.url = "http://localhost:9200/products/_search".to_string();
+        t.tag_operations();
+        let json = serde_json::to_value(TraceView::from(&t)).unwrap();
+        assert_eq!(json["elasticsearch"]["api"], "_search");
+        assert_eq!(json["elasticsearch"]["index"], "products");
+        assert_eq!(json["elasticsearch"]["query_summary"], "term(sku)");
+    }
+
+    #[test]
+    fn test_render_tags_graphql_calls() {
+        let body = br#"{"operationName":"CreateOrder","query":"mutation CreateOrder { createOrder { id } }"}"#;
+        let mut t = make_trace(Some(body.to_vec()), None);
+        t.url = "http://api.local/graphql".to_string();
+        t.tag_operations();
+        let json = serde_json::to_value(TraceView::from(&t)).unwrap();
+        assert_eq!(json["graphql"]["type"], "mutation");
+        assert_eq!(json["graphql"]["name"], "CreateOrder");
+    }
+
+    #[test]
+    fn test_render_tags_soap_calls() {
+        let body = br#"<s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/"><s:Body><GetQuote/></s:Body></s:Envelope>"#;
+        let mut t = make_trace(Some(body.to_vec()), None);
+        t.request_headers.insert(
+            "soapaction".to_string(),
+            "\"urn:quotes#GetQuote\"".to_string(),
+        );
+        t.tag_operations();
+        let json = serde_json::to_value(TraceView::from(&t)).unwrap();
+        assert_eq!(json["soap"]["operation"], "GetQuote");
+        assert_eq!(json["soap"]["action"], "urn:quotes#GetQuote");
+    }
+
+    #[test]
+    fn test_protocol_view_thrift_shape() {
+        let t = ProtocolTrace::Thrift(ThriftTrace {
+            method: "getUser".to_string(),
+            seq_id: 7,
+            message_type: ThriftMessageType::Exception,
+            protocol: ThriftProtocol::Compact,
+            framed: true,
+            timestamp: SystemTime::UNIX_EPOCH + Duration::from_millis(2000),
+            duration: Duration::from_millis(12),
+            dest_addr: None,
+        });
+        let json = serde_json::to_value(ProtocolTraceView::from(&t)).unwrap();
+        assert_eq!(json["kind"], "thrift");
+        assert_eq!(json["method"], "getUser");
+        assert_eq!(json["seq_id"], 7);
+        assert_eq!(json["message_type"], "exception");
+        assert_eq!(json["protocol"], "compact");
+        assert_eq!(json["timestamp_ms"], 2000);
+        assert_eq!(json["duration_ms"], 12);
+        assert!(json.get("dest_addr").is_none());
+    }
+
+    #[test]
+    fn test_protocol_view_postgres_shape() {
+        let t = ProtocolTrace::Postgres(PostgresTrace {
+            query: "UPDATE users SET name = $1 WHERE id = $2".to_string(),
+            command: Some("UPDATE".to_string()),
+            rows: Some(1),
+            error: None,
+            database: Some("app".to_string()),
+            user: None,
+            timestamp: SystemTime::UNIX_EPOCH + Duration::from_millis(5000),
+            duration: Duration::from_millis(4),
+            dest_addr: Some("127.0.0.1:5432".to_string()),
+        });
+        let json = serde_json::to_value(ProtocolTraceView::from(&t)).unwrap();
+        assert_eq!(json["kind"], "postgres");
+        assert_eq!(json["command"], "UPDATE");
+        assert_eq!(json["rows"], 1);
+        assert_eq!(json["database"], "app");
+        assert_eq!(json["duration_ms"], 4);
+        assert!(json.get("error").is_none());
+        assert!(json.get("user").is_none());
+    }
+
+    #[test]
+    fn test_protocol_view_redis_shape() {
+        let t = ProtocolTrace::Redis(RedisTrace {
+            command: "HSET".to_string(),
+            key: Some("user:1".to_string()),
+            args: vec!["name".to_string(), "ada".to_string()],
+            arg_count: 3,
+            reply_type: RedisReplyType::Integer,
+            error: None,
+            timestamp: SystemTime::UNIX_EPOCH + Duration::from_millis(1000),
+            duration: Duration::from_millis(1),
+            dest_addr: None,
+        });
+        let json = serde_json::to_value(ProtocolTraceView::from(&t)).unwrap();
+        assert_eq!(json["kind"], "redis");
+        assert_eq!(json["command"], "HSET");
+        assert_eq!(json["key"], "user:1");
+        assert_eq!(json["args"][1], "ada");
+        assert_eq!(json["reply_type"], "integer");
+        assert!(json.get("error").is_none());
+    }
+
+    #[test]
+    fn test_protocol_view_websocket_shape() {
+        let t = ProtocolTrace::WebSocket(WebSocketFrame {
+            url: "wss://example.com/socket".to_string(),
+            direction: WebSocketDirection::Send,
+            opcode: WebSocketOpcode::Text,
+            fin: true,
+            masked: true,
+            compressed: false,
+            payload_len: 2048,
+            preview: b"{\"op\":\"subscribe\"".to_vec(),
+            close_code: None,
+            timestamp: SystemTime::UNIX_EPOCH + Duration::from_millis(1000),
+            connection_id: Some("7-3".to_string()),
+            dest_addr: None,
+        });
+        let json = serde_json::to_value(ProtocolTraceView::from(&t)).unwrap();
+        assert_eq!(json["kind"], "websocket");
+        assert_eq!(json["direction"], "send");
+        assert_eq!(json["opcode"], "text");
+        assert_eq!(json["payload_len"], 2048);
+        assert_eq!(json["preview"], "{\"op\":\"subscribe\"");
+        assert!(json.get("compressed").is_none());
+    }
+
+    #[test]
+    fn test_protocol_view_kafka_shape() {
+        let t = ProtocolTrace::Kafka(KafkaTrace {
+            api_key: 0,
+            api: "Produce".to_string(),
+            api_version: 9,
+            correlation_id: 12,
+            client_id: Some("orders-svc".to_string()),
+            topics: vec!["orders".to_string()],
+            error_code: Some(3),
+            no_response: false,
+            timestamp: SystemTime::UNIX_EPOCH + Duration::from_millis(1000),
+            duration: Duration::from_millis(4),
+            dest_addr: Some("10.0.0.5:9092".to_string()),
+        });
+        let json = serde_json::to_value(ProtocolTraceView::from(&t)).unwrap();
+        assert_eq!(json["kind"], "kafka");
+        assert_eq!(json["api"], "Produce");
+        assert_eq!(json["topics"][0], "orders");
+        assert_eq!(json["error_code"], 3);
+        assert!(json.get("no_response").is_none());
+    }
+
+    #[test]
+    fn test_protocol_view_grpc_shape() {
+        let t = ProtocolTrace::Grpc(GrpcTrace {
+            service: "helloworld.Greeter".to_string(),
+            method: "SayHello".to_string(),
+            authority: None,
+            status: Some(14),
+            message: Some("connection refused".to_string()),
+            request_messages: 1,
+            response_messages: 0,
+            timestamp: SystemTime::UNIX_EPOCH + Duration::from_millis(1000),
+            duration: Duration::from_millis(7),
+            dest_addr: Some("10.0.0.7:50051".to_string()),
+        });
+        let json = serde_json::to_value(ProtocolTraceView::from(&t)).unwrap();
+        assert_eq!(json["kind"], "grpc");
+        assert_eq!(json["method"], "SayHello");
+        assert_eq!(json["status"], 14);
+        assert_eq!(json["status_name"], "UNAVAILABLE");
+        assert_eq!(json["response_messages"], 0);
+        assert!(json.get("authority").is_none());
+    }
 }
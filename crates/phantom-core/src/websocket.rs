@@ -0,0 +1,95 @@
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+/// Which peer sent a WebSocket frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WebSocketDirection {
+    /// Sent by the traced process.
+    Send,
+    /// Received by the traced process.
+    Recv,
+}
+
+/// RFC 6455 frame opcode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WebSocketOpcode {
+    Continuation,
+    Text,
+    Binary,
+    Close,
+    Ping,
+    Pong,
+    /// Opcodes RFC 6455 leaves reserved.
+    Reserved,
+}
+
+/// One WebSocket frame on a connection upgraded from HTTP/1.1.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebSocketFrame {
+    /// URL of the upgrade request (`ws://` or `wss://`).
+    pub url: String,
+    pub direction: WebSocketDirection,
+    pub opcode: WebSocketOpcode,
+    /// Final fragment of its message.
+    pub fin: bool,
+    /// Payload masked by the client, as RFC 6455 requires client frames to be.
+    pub masked: bool,
+    /// Payload compressed by `permessage-deflate` (RSV1 set); the preview is
+    /// then deflated bytes.
+    #[serde(default)]
+    pub compressed: bool,
+    /// Full payload length from the frame header.
+    pub payload_len: u64,
+    /// Leading payload bytes, unmasked (the agent keeps 256). For a close
+    /// frame this is the reason, after the status code.
+    #[serde(default)]
+    pub preview: Vec<u8>,
+    /// Status code of a close frame.
+    pub close_code: Option<u16>,
+    /// Wall-clock time the frame was seen.
+    pub timestamp: SystemTime,
+    /// Connection the upgrade request was made on; matches the upgrade
+    /// trace's `connection_id`.
+    pub connection_id: Option<String>,
+    /// Destination socket address, if available.
+    pub dest_addr: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_frame(opcode: WebSocketOpcode, preview: &[u8]) -> WebSocketFrame {
+        WebSocketFrame {
+            url: "ws://localhost:8080/chat".to_string(),
+            direction: WebSocketDirection::Recv,
+            opcode,
+            fin: true,
+            masked: false,
+            compressed: false,
+            payload_len: preview.len() as u64,
+            preview: preview.to_vec(),
+            close_code: None,
+            timestamp: SystemTime::UNIX_EPOCH,
+            connection_id: Some("42-1".to_string()),
+            dest_addr: None,
+        }
+    }
+
+    #[test]
+    fn test_frame_roundtrip_and_wire_names() {
+        let mut frame = make_frame(WebSocketOpcode::Close, b"going away");
+        frame.close_code = Some(1001);
+        let json = serde_json::to_value(&frame).unwrap();
+        assert_eq!(json["direction"], "recv");
+        assert_eq!(json["opcode"], "close");
+
+        let parsed: WebSocketFrame = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed.close_code, Some(1001));
+        assert_eq!(parsed.preview, b"going away");
+        assert!(!parsed.compressed);
+    }
+}
@@ -0,0 +1,538 @@
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use phantom_core::error::StorageError;
+use phantom_core::protocol::ProtocolTrace;
+use phantom_core::query::TraceQuery;
+use phantom_core::storage::TraceStore;
+use phantom_core::trace::{HttpTrace, SpanId, TraceId};
+use ring::digest::{SHA256, digest};
+use ring::rand::SystemRandom;
+use ring::signature::{ED25519, Ed25519KeyPair, KeyPair, UnparsedPublicKey};
+use serde_json::{Value, json};
+
+/// Format version written in the header line.
+const AUDIT_VERSION: u64 = 1;
+
+// Audit log format (JSON Lines, append-only):
+//
+//   {"phantom_audit":1,"created_ms":…,"public_key":"<hex>"|null}
+//   {"seq":1,"prev":"<hex>","hash":"<hex>","sig":"<hex>","record":"<trace JSON>"}
+//   …
+//
+// hash(n) = SHA-256(prev || seq as u64 BE || record), where prev is the
+// previous entry's hash, or SHA-256 of the header line for the first entry.
+// `record` is the trace serialized as a JSON string so the hashed bytes are
+// exactly what is on disk. `sig` is an Ed25519 signature over `hash`,
+// present when the log was opened with a signing key.
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn unhex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn chain_hash(prev: &[u8], seq: u64, record: &str) -> Vec<u8> {
+    let mut input = Vec::with_capacity(prev.len() + 8 + record.len());
+    input.extend_from_slice(prev);
+    input.extend_from_slice(&seq.to_be_bytes());
+    input.extend_from_slice(record.as_bytes());
+    digest(&SHA256, &input).as_ref().to_vec()
+}
+
+/// Generates an Ed25519 signing key, writes it to `path` (PKCS#8 DER, owner
+/// read/write only), and returns the hex public key to pin when verifying.
+pub fn generate_audit_key(path: impl AsRef<Path>) -> Result<String, StorageError> {
+    let path = path.as_ref();
+    let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+        .map_err(|_| StorageError::Write("failed to generate signing key".into()))?;
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options
+        .open(path)
+        .map_err(|e| StorageError::Write(format!("{}: {e}", path.display())))?;
+    file.write_all(pkcs8.as_ref())
+        .map_err(|e| StorageError::Write(format!("{}: {e}", path.display())))?;
+    let key = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref())
+        .map_err(|_| StorageError::Write("generated key is invalid".into()))?;
+    Ok(hex(key.public_key().as_ref()))
+}
+
+fn load_key(path: &Path) -> Result<Ed25519KeyPair, StorageError> {
+    let bytes =
+        std::fs::read(path).map_err(|e| StorageError::Open(format!("{}: {e}", path.display())))?;
+    Ed25519KeyPair::from_pkcs8(&bytes).map_err(|_| {
+        StorageError::Open(format!(
+            "{} is not a PKCS#8 Ed25519 key (create one with `phantom audit keygen`)",
+            path.display()
+        ))
+    })
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Writing
+// ─────────────────────────────────────────────────────────────────────────────
+
+struct AuditState {
+    file: std::fs::File,
+    prev: Vec<u8>,
+    seq: u64,
+    key: Option<Ed25519KeyPair>,
+}
+
+/// Append-only, hash-chained log of captured traces, optionally signed.
+pub struct AuditLog {
+    state: Mutex<AuditState>,
+}
+
+impl AuditLog {
+    /// Opens (or creates) the log at `path`, continuing an existing chain.
+    /// An existing log must be reopened with the same signing key it was
+    /// created with, or without one if it is unsigned.
+    pub fn open(path: impl AsRef<Path>, key_path: Option<&Path>) -> Result<Self, StorageError> {
+        let path = path.as_ref();
+        let key = key_path.map(load_key).transpose()?;
+        let public_key = key.as_ref().map(|k| hex(k.public_key().as_ref()));
+
+        let (prev, seq) = match std::fs::File::open(path) {
+            Ok(file) => {
+                let (header, last) = read_ends(file, path)?;
+                let logged_key = header.value["public_key"].as_str().map(String::from);
+                if logged_key != public_key {
+                    return Err(StorageError::Open(format!(
+                        "{} was created {}; reopen it with the same key",
+                        path.display(),
+                        match &logged_key {
+                            Some(k) => format!("with signing key {k}"),
+                            None => "without a signing key".to_string(),
+                        }
+                    )));
+                }
+                match last {
+                    Some(entry) => {
+                        let hash = entry["hash"].as_str().and_then(unhex);
+                        let seq = entry["seq"].as_u64();
+                        let (Some(hash), Some(seq)) = (hash, seq) else {
+                            return Err(StorageError::Integrity(format!(
+                                "{}: last entry is malformed",
+                                path.display()
+                            )));
+                        };
+                        (hash, seq)
+                    }
+                    None => (digest(&SHA256, header.line.as_bytes()).as_ref().to_vec(), 0),
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                let created_ms = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_millis() as u64)
+                    .unwrap_or(0);
+                let header = json!({
+                    "phantom_audit": AUDIT_VERSION,
+                    "created_ms": created_ms,
+                    "public_key": public_key,
+                })
+                .to_string();
+                std::fs::write(path, format!("{header}\n"))
+                    .map_err(|e| StorageError::Write(format!("{}: {e}", path.display())))?;
+                (digest(&SHA256, header.as_bytes()).as_ref().to_vec(), 0)
+            }
+            Err(e) => return Err(StorageError::Open(format!("{}: {e}", path.display()))),
+        };
+
+        let file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(path)
+            .map_err(|e| StorageError::Open(format!("{}: {e}", path.display())))?;
+        Ok(Self {
+            state: Mutex::new(AuditState {
+                file,
+                prev,
+                seq,
+                key,
+            }),
+        })
+    }
+
+    /// Appends one trace to the chain.
+    pub fn append(&self, trace: &HttpTrace) -> Result<(), StorageError> {
+        let record =
+            serde_json::to_string(trace).map_err(|e| StorageError::Serialization(e.to_string()))?;
+        let mut state = self.state.lock().expect("audit log poisoned");
+        let seq = state.seq + 1;
+        let hash = chain_hash(&state.prev, seq, &record);
+        let sig = state.key.as_ref().map(|k| hex(k.sign(&hash).as_ref()));
+        let mut entry = json!({
+            "seq": seq,
+            "prev": hex(&state.prev),
+            "hash": hex(&hash),
+            "record": record,
+        });
+        if let Some(sig) = sig {
+            entry["sig"] = Value::String(sig);
+        }
+        state
+            .file
+            .write_all(format!("{entry}\n").as_bytes())
+            .map_err(|e| StorageError::Write(e.to_string()))?;
+        state.prev = hash;
+        state.seq = seq;
+        Ok(())
+    }
+}
+
+struct Header {
+    line: String,
+    value: Value,
+}
+
+/// Reads the header line and the last entry (if any) of an existing log.
+fn read_ends(file: std::fs::File, path: &Path) -> Result<(Header, Option<Value>), StorageError> {
+    let mut lines = BufReader::new(file).lines();
+    let line = lines
+        .next()
+        .transpose()
+        .map_err(|e| StorageError::Read(e.to_string()))?
+        .ok_or_else(|| StorageError::Integrity(format!("{}: missing header", path.display())))?;
+    let header = parse_header(line, path)?;
+    let mut last = None;
+    for line in lines {
+        let line = line.map_err(|e| StorageError::Read(e.to_string()))?;
+        if !line.is_empty() {
+            last = Some(line);
+        }
+    }
+    let last = last
+        .map(|l| serde_json::from_str(&l))
+        .transpose()
+        .map_err(|e| StorageError::Integrity(format!("{}: last entry: {e}", path.display())))?;
+    Ok((header, last))
+}
+
+fn parse_header(line: String, path: &Path) -> Result<Header, StorageError> {
+    let value: Value = serde_json::from_str(&line)
+        .map_err(|e| StorageError::Integrity(format!("{}: header: {e}", path.display())))?;
+    if value["phantom_audit"].as_u64() != Some(AUDIT_VERSION) {
+        return Err(StorageError::Integrity(format!(
+            "{} is not a phantom audit log (version {AUDIT_VERSION})",
+            path.display()
+        )));
+    }
+    Ok(Header { line, value })
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Verification
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Summary of a successfully verified audit log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditVerification {
+    pub entries: u64,
+    /// Hash of the last entry. Record it elsewhere to detect later truncation.
+    pub head: String,
+    /// Hex Ed25519 public key every entry was verified against, if signed.
+    pub public_key: Option<String>,
+}
+
+/// Walks the whole chain, checking every hash link and signature.
+///
+/// Pass `expected_public_key` to pin the signer: without it, a signed log
+/// only proves it was written by whoever holds the key named in its header.
+pub fn verify_audit_log(
+    path: impl AsRef<Path>,
+    expected_public_key: Option<&str>,
+) -> Result<AuditVerification, StorageError> {
+    let path = path.as_ref();
+    let file = std::fs::File::open(path)
+        .map_err(|e| StorageError::Open(format!("{}: {e}", path.display())))?;
+    let mut lines = BufReader::new(file).lines();
+    let header_line = lines
+        .next()
+        .transpose()
+        .map_err(|e| StorageError::Read(e.to_string()))?
+        .ok_or_else(|| StorageError::Integrity(format!("{}: missing header", path.display())))?;
+    let header = parse_header(header_line, path)?;
+    let public_key = header.value["public_key"].as_str().map(String::from);
+    if let Some(expected) = expected_public_key
+        && public_key.as_deref() != Some(&expected.to_lowercase())
+    {
+        return Err(StorageError::Integrity(format!(
+            "log is signed by {}, expected {expected}",
+            public_key.as_deref().unwrap_or("nobody")
+        )));
+    }
+    let verifier = public_key
+        .as_deref()
+        .map(|k| {
+            unhex(k)
+                .map(|bytes| UnparsedPublicKey::new(&ED25519, bytes))
+                .ok_or_else(|| StorageError::Integrity("header public_key is not hex".into()))
+        })
+        .transpose()?;
+
+    let mut prev = digest(&SHA256, header.line.as_bytes()).as_ref().to_vec();
+    let mut seq = 0u64;
+    for line in lines {
+        let line = line.map_err(|e| StorageError::Read(e.to_string()))?;
+        if line.is_empty() {
+            continue;
+        }
+        let expected_seq = seq + 1;
+        let fail = |msg: &str| StorageError::Integrity(format!("entry {expected_seq}: {msg}"));
+        let entry: Value = serde_json::from_str(&line).map_err(|e| fail(&e.to_string()))?;
+        if entry["seq"].as_u64() != Some(expected_seq) {
+            return Err(fail("out of sequence (entries removed or reordered)"));
+        }
+        if entry["prev"].as_str() != Some(hex(&prev).as_str()) {
+            return Err(fail("does not link to the previous entry"));
+        }
+        let record = entry["record"]
+            .as_str()
+            .ok_or_else(|| fail("missing record"))?;
+        let hash = chain_hash(&prev, expected_seq, record);
+        if entry["hash"].as_str() != Some(hex(&hash).as_str()) {
+            return Err(fail("hash mismatch (record modified)"));
+        }
+        if let Some(verifier) = &verifier {
+            let sig = entry["sig"]
+                .as_str()
+                .and_then(unhex)
+                .ok_or_else(|| fail("missing signature"))?;
+            verifier
+                .verify(&hash, &sig)
+                .map_err(|_| fail("bad signature"))?;
+        }
+        prev = hash;
+        seq = expected_seq;
+    }
+
+    Ok(AuditVerification {
+        entries: seq,
+        head: hex(&prev),
+        public_key,
+    })
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Store wrapper
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// A [`TraceStore`] that appends every inserted trace to an [`AuditLog`]
+/// after storing it. Reads go straight to the inner store.
+pub struct AuditedTraceStore {
+    inner: Arc<dyn TraceStore>,
+    log: AuditLog,
+}
+
+impl AuditedTraceStore {
+    pub fn new(inner: Arc<dyn TraceStore>, log: AuditLog) -> Self {
+        Self { inner, log }
+    }
+}
+
+impl TraceStore for AuditedTraceStore {
+    fn insert(&self, trace: &HttpTrace) -> Result<(), StorageError> {
+        self.inner.insert(trace)?;
+        self.log.append(trace)
+    }
+
+    fn get_by_span_id(&self, span_id: &SpanId) -> Result<Option<HttpTrace>, StorageError> {
+        self.inner.get_by_span_id(span_id)
+    }
+
+    fn list_recent(&self, limit: usize, offset: usize) -> Result<Vec<HttpTrace>, StorageError> {
+        self.inner.list_recent(limit, offset)
+    }
+
+    fn get_by_trace_id(&self, trace_id: &TraceId) -> Result<Vec<HttpTrace>, StorageError> {
+        self.inner.get_by_trace_id(trace_id)
+    }
+
+    fn search_by_url(&self, pattern: &str, limit: usize) -> Result<Vec<HttpTrace>, StorageError> {
+        self.inner.search_by_url(pattern, limit)
+    }
+
+    fn count(&self) -> Result<u64, StorageError> {
+        self.inner.count()
+    }
+
+    fn query(&self, query: &TraceQuery) -> Result<Vec<HttpTrace>, StorageError> {
+        self.inner.query(query)
+    }
+
+    /// Clears the store only; the audit log is append-only by design.
+    fn clear(&self) -> Result<(), StorageError> {
+        self.inner.clear()
+    }
+
+    /// Stores without auditing: the log covers HTTP traces.
+    fn insert_protocol(&self, trace: &ProtocolTrace) -> Result<(), StorageError> {
+        self.inner.insert_protocol(trace)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    use phantom_core::trace::HttpMethod;
+
+    fn make_trace(url: &str) -> HttpTrace {
+        HttpTrace {
+            span_id: SpanId(rand::random()),
+            trace_id: TraceId(rand::random()),
+            parent_span_id: None,
+            method: HttpMethod::Get,
+            url: url.to_string(),
+            request_headers: HashMap::new(),
+            request_body: None,
+            status_code: 200,
+            response_headers: HashMap::new(),
+            response_body: None,
+            timestamp: SystemTime::now(),
+            duration: Duration::from_millis(42),
+            source_addr: None,
+            dest_addr: None,
+            protocol_version: "HTTP/1.1".to_string(),
+            request_body_file: None,
+            response_body_file: None,
+            connection_id: None,
+            process: None,
+            response_trailers: HashMap::new(),
+            request_body_encoding: None,
+            response_body_encoding: None,
+            elasticsearch: None,
+            soap: None,
+            graphql: None,
+            thread_id: None,
+            tls: None,
+            timing: None,
+            request_size: None,
+            response_size: None,
+            fault: None,
+            replay: None,
+        }
+    }
+
+    fn write_log(path: &Path, key: Option<&Path>, urls: &[&str]) {
+        let log = AuditLog::open(path, key).unwrap();
+        for url in urls {
+            log.append(&make_trace(url)).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_chain_verifies_and_survives_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        write_log(&path, None, &["http://a/1", "http://a/2"]);
+        write_log(&path, None, &["http://a/3"]);
+
+        let result = verify_audit_log(&path, None).unwrap();
+        assert_eq!(result.entries, 3);
+        assert_eq!(result.public_key, None);
+        assert_eq!(result.head.len(), 64);
+    }
+
+    #[test]
+    fn test_detects_modified_record() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        write_log(&path, None, &["http://a/1", "http://a/2"]);
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::write(&path, text.replace("http://a/1", "http://a/X")).unwrap();
+
+        let err = verify_audit_log(&path, None).unwrap_err();
+        assert!(err.to_string().contains("entry 1: hash mismatch"), "{err}");
+    }
+
+    #[test]
+    fn test_detects_removed_entry() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        write_log(&path, None, &["http://a/1", "http://a/2", "http://a/3"]);
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        let kept: Vec<&str> = text
+            .lines()
+            .enumerate()
+            .filter(|(i, _)| *i != 2)
+            .map(|(_, l)| l)
+            .collect();
+        std::fs::write(&path, kept.join("\n")).unwrap();
+
+        let err = verify_audit_log(&path, None).unwrap_err();
+        assert!(err.to_string().contains("entry 2"), "{err}");
+    }
+
+    #[test]
+    fn test_signed_log_pins_public_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = dir.path().join("audit.key");
+        let other_key = dir.path().join("other.key");
+        let public_key = generate_audit_key(&key).unwrap();
+        generate_audit_key(&other_key).unwrap();
+        let path = dir.path().join("audit.jsonl");
+        write_log(&path, Some(&key), &["http://a/1"]);
+
+        let result = verify_audit_log(&path, Some(&public_key)).unwrap();
+        assert_eq!(result.public_key.as_deref(), Some(public_key.as_str()));
+        assert!(verify_audit_log(&path, Some(&"00".repeat(32))).is_err());
+
+        // Appending requires the same key.
+        assert!(AuditLog::open(&path, None).is_err());
+        assert!(AuditLog::open(&path, Some(&other_key)).is_err());
+        assert!(AuditLog::open(&path, Some(&key)).is_ok());
+    }
+
+    #[test]
+    fn test_detects_forged_signature() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = dir.path().join("audit.key");
+        generate_audit_key(&key).unwrap();
+        let path = dir.path().join("audit.jsonl");
+        write_log(&path, Some(&key), &["http://a/1"]);
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        let mut entry: Value = serde_json::from_str(text.lines().nth(1).unwrap()).unwrap();
+        entry["sig"] = Value::String("00".repeat(64));
+        let forged = format!("{}\n{entry}\n", text.lines().next().unwrap());
+        std::fs::write(&path, forged).unwrap();
+
+        let err = verify_audit_log(&path, None).unwrap_err();
+        assert!(err.to_string().contains("bad signature"), "{err}");
+    }
+
+    #[test]
+    fn test_audited_store_logs_inserts() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        let inner: Arc<dyn TraceStore> =
+            Arc::new(crate::FjallTraceStore::open(dir.path().join("data")).unwrap());
+        let store = AuditedTraceStore::new(inner, AuditLog::open(&path, None).unwrap());
+
+        store.insert(&make_trace("http://a/1")).unwrap();
+        store.clear().unwrap();
+        store.insert(&make_trace("http://a/2")).unwrap();
+
+        assert_eq!(store.list_recent(10, 0).unwrap().len(), 1);
+        assert_eq!(verify_audit_log(&path, None).unwrap().entries, 2);
+    }
+}
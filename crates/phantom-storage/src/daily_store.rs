@@ -0,0 +1,781 @@
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use phantom_core::error::StorageError;
+use phantom_core::grpc::GrpcTrace;
+use phantom_core::postgres::PostgresTrace;
+use phantom_core::protocol::ProtocolTrace;
+use phantom_core::query::TraceQuery;
+use phantom_core::redis::RedisTrace;
+use phantom_core::storage::{GrpcTraceStore, PostgresTraceStore, RedisTraceStore, TraceStore};
+use phantom_core::trace::{HttpTrace, SpanId, TraceId};
+
+use crate::fjall_store::{ConsistencyReport, FjallTraceStore};
+
+/// Marker file identifying a day-partitioned data directory.
+const LAYOUT_FILE: &str = "layout";
+const LAYOUT_DAILY: &str = "daily";
+
+const SECS_PER_DAY: u64 = 86_400;
+
+/// Day stores kept open between calls. Every open day holds its own fjall
+/// keyspace (journal, memtables, file handles), so a query over months of
+/// history must not leave them all open.
+const MAX_OPEN_DAYS: usize = 8;
+
+/// Returns true if `path` holds a day-partitioned store.
+pub fn is_daily_layout(path: &Path) -> bool {
+    std::fs::read_to_string(path.join(LAYOUT_FILE)).is_ok_and(|s| s.trim() == LAYOUT_DAILY)
+}
+
+/// A [`TraceStore`] sharded into one [`FjallTraceStore`] per UTC day
+/// (`<root>/2024-06-01/`, …).
+///
+/// Queries span every day transparently, newest day first, and skip days
+/// outside a `since`/`until` range. Old days can be archived or deleted by
+/// moving or removing their directory while no phantom process holds the
+/// store. Day stores are opened lazily on first use, and only the
+/// [`MAX_OPEN_DAYS`] most recently used stay open.
+pub struct DailyTraceStore {
+    root: PathBuf,
+    days: Mutex<OpenDays>,
+    /// Same lock file a flat store uses, so the two layouts exclude each other.
+    _lock: std::fs::File,
+}
+
+/// A day's name and store, or the error opening it.
+type DayResult = Result<(String, Arc<FjallTraceStore>), StorageError>;
+
+/// Open day stores.
+#[derive(Default)]
+struct OpenDays {
+    /// Recently used days, most recent last; holding them keeps them open.
+    recent: VecDeque<(String, Arc<FjallTraceStore>)>,
+    /// Every day store still alive, including ones evicted from `recent`
+    /// but still in use by a caller. A day's store holds its directory's
+    /// lock, so it is shared rather than opened a second time.
+    live: HashMap<String, Weak<FjallTraceStore>>,
+}
+
+impl OpenDays {
+    /// Marks `name` as most recently used, closing the least recently used
+    /// day once more than [`MAX_OPEN_DAYS`] are held.
+    fn touch(&mut self, name: &str, store: Arc<FjallTraceStore>) {
+        self.recent.retain(|(day, _)| day != name);
+        self.recent.push_back((name.to_string(), store));
+        if self.recent.len() > MAX_OPEN_DAYS {
+            self.recent.pop_front();
+            self.live.retain(|_, store| store.strong_count() > 0);
+        }
+    }
+}
+
+impl DailyTraceStore {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, StorageError> {
+        let root = path.as_ref().to_path_buf();
+        std::fs::create_dir_all(&root).map_err(|e| StorageError::Open(e.to_string()))?;
+        let lock_path = root.join("phantom.lock");
+        let lock = std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&lock_path)
+            .map_err(|e| StorageError::Open(format!("{}: {e}", lock_path.display())))?;
+        lock.try_lock().map_err(|_| {
+            StorageError::Open(format!(
+                "data dir {} holds an active store lock (another phantom process is using it)",
+                root.display()
+            ))
+        })?;
+
+        if !is_daily_layout(&root) {
+            // fjall keeps its partitions under `partitions/`; finding one at
+            // the root means a flat store already lives here.
+            if root.join("partitions").exists() {
+                return Err(StorageError::Open(format!(
+                    "data dir {} holds a store that is not partitioned by day; \
+                     use another data directory",
+                    root.display()
+                )));
+            }
+            std::fs::write(root.join(LAYOUT_FILE), format!("{LAYOUT_DAILY}\n"))
+                .map_err(|e| StorageError::Open(e.to_string()))?;
+        }
+
+        Ok(Self {
+            root,
+            days: Mutex::new(OpenDays::default()),
+            _lock: lock,
+        })
+    }
+
+    /// Names of the day directories on disk, newest first.
+    pub fn day_names(&self) -> Result<Vec<String>, StorageError> {
+        let entries =
+            std::fs::read_dir(&self.root).map_err(|e| StorageError::Read(e.to_string()))?;
+        let mut names: Vec<String> = entries
+            .filter_map(|e| e.ok())
+            .filter(|e| e.path().is_dir())
+            .filter_map(|e| e.file_name().into_string().ok())
+            .filter(|name| parse_day(name).is_some())
+            .collect();
+        names.sort_unstable_by(|a, b| b.cmp(a));
+        Ok(names)
+    }
+
+    /// The store for one day, opening (and creating) it if needed.
+    fn day(&self, name: &str) -> Result<Arc<FjallTraceStore>, StorageError> {
+        let mut days = self
+            .days
+            .lock()
+            .map_err(|_| StorageError::Open("day store lock poisoned".into()))?;
+        let store = match days.live.get(name).and_then(Weak::upgrade) {
+            Some(store) => store,
+            None => {
+                let store = Arc::new(FjallTraceStore::open(self.root.join(name))?);
+                days.live.insert(name.to_string(), Arc::downgrade(&store));
+                store
+            }
+        };
+        days.touch(name, store.clone());
+        Ok(store)
+    }
+
+    /// Day stores, newest first. Each day is opened as the iterator reaches
+    /// it, so stopping early leaves older days closed.
+    pub fn day_stores(
+        &self,
+    ) -> Result<impl DoubleEndedIterator<Item = DayResult> + '_, StorageError> {
+        Ok(self
+            .day_names()?
+            .into_iter()
+            .map(|name| Ok((name.clone(), self.day(&name)?))))
+    }
+
+    /// Day stores that can hold traces matching `query`, newest first.
+    fn days_for<'a>(
+        &'a self,
+        query: &'a TraceQuery,
+    ) -> Result<impl Iterator<Item = DayResult> + 'a, StorageError> {
+        let names = self.day_names()?.into_iter().filter(move |name| {
+            if query.trace_id.is_some() {
+                return true;
+            }
+            let Some(start) = parse_day(name) else {
+                return false;
+            };
+            let end = start + Duration::from_secs(SECS_PER_DAY);
+            query.since.is_none_or(|since| since < end)
+                && query.until.is_none_or(|until| until >= start)
+        });
+        Ok(names.map(|name| Ok((name.clone(), self.day(&name)?))))
+    }
+
+    /// Collects up to `limit` records (newest first) across days, skipping
+    /// the first `offset`; `list(store, n)` lists a day's newest `n`.
+    fn list_days<T>(
+        &self,
+        limit: usize,
+        offset: usize,
+        list: impl Fn(&FjallTraceStore, usize) -> Result<Vec<T>, StorageError>,
+    ) -> Result<Vec<T>, StorageError> {
+        let mut to_skip = offset;
+        let mut results = Vec::new();
+        for day in self.day_stores()? {
+            let (_, store) = day?;
+            if results.len() >= limit {
+                break;
+            }
+            let remaining = limit - results.len();
+            let matches = list(&store, to_skip + remaining)?;
+            let skipped = to_skip.min(matches.len());
+            to_skip -= skipped;
+            results.extend(matches.into_iter().skip(skipped).take(remaining));
+        }
+        Ok(results)
+    }
+
+    /// Runs [`FjallTraceStore::verify_and_repair`] on every day.
+    pub fn verify_and_repair(&self) -> Result<ConsistencyReport, StorageError> {
+        let mut total = ConsistencyReport::default();
+        for day in self.day_stores()? {
+            let (_, store) = day?;
+            let report = store.verify_and_repair()?;
+            total.records += report.records;
+            total.dangling_index_removed += report.dangling_index_removed;
+            total.missing_index_restored += report.missing_index_restored;
+            total.unreadable_records += report.unreadable_records;
+        }
+        Ok(total)
+    }
+
+    pub fn compact(&self) -> Result<(), StorageError> {
+        for day in self.day_stores()? {
+            let (_, store) = day?;
+            store.compact()?;
+        }
+        Ok(())
+    }
+
+    /// Backs every day up into a day-partitioned store at `dest`, which
+    /// must not exist or be empty. Returns the number of records copied.
+    pub fn backup_to(&self, dest: impl AsRef<Path>) -> Result<u64, StorageError> {
+        let dest = dest.as_ref();
+        let non_empty = std::fs::read_dir(dest).is_ok_and(|mut entries| entries.next().is_some());
+        if non_empty {
+            return Err(StorageError::Open(format!(
+                "backup directory {} is not empty",
+                dest.display()
+            )));
+        }
+        // Write the layout marker and hold the lock while copying.
+        let _backup = DailyTraceStore::open(dest)?;
+        let mut records = 0;
+        for day in self.day_stores()? {
+            let (name, store) = day?;
+            records += store.backup_to(dest.join(name))?;
+        }
+        Ok(records)
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Day naming (UTC, proleptic Gregorian)
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// `"YYYY-MM-DD"` of the UTC day containing `ts`.
+fn day_name(ts: &SystemTime) -> String {
+    let secs = ts
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_secs();
+    let (y, m, d) = civil_from_days((secs / SECS_PER_DAY) as i64);
+    format!("{y:04}-{m:02}-{d:02}")
+}
+
+/// Start of the UTC day named `"YYYY-MM-DD"`, or None if `name` is not a date.
+fn parse_day(name: &str) -> Option<SystemTime> {
+    let mut parts = name.splitn(3, '-');
+    let (y, m, d) = (parts.next()?, parts.next()?, parts.next()?);
+    if y.len() != 4 || m.len() != 2 || d.len() != 2 {
+        return None;
+    }
+    let (y, m, d): (i64, u32, u32) = (y.parse().ok()?, m.parse().ok()?, d.parse().ok()?);
+    let days = days_from_civil(y, m, d);
+    if days < 0 || civil_from_days(days) != (y, m, d) {
+        return None;
+    }
+    Some(UNIX_EPOCH + Duration::from_secs(days as u64 * SECS_PER_DAY))
+}
+
+/// Days since 1970-01-01 → (year, month, day). Howard Hinnant's algorithm.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+    let y = yoe + era * 400 + i64::from(m <= 2);
+    (y, m, d)
+}
+
+/// (year, month, day) → days since 1970-01-01. Inverse of [`civil_from_days`].
+fn days_from_civil(y: i64, m: u32, d: u32) -> i64 {
+    let y = y - i64::from(m <= 2);
+    let era = y.div_euclid(400);
+    let yoe = y.rem_euclid(400);
+    let m = i64::from(m);
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + i64::from(d) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+impl TraceStore for DailyTraceStore {
+    fn insert(&self, trace: &HttpTrace) -> Result<(), StorageError> {
+        self.day(&day_name(&trace.timestamp))?.insert(trace)
+    }
+
+    fn get_by_span_id(&self, span_id: &SpanId) -> Result<Option<HttpTrace>, StorageError> {
+        for day in self.day_stores()? {
+            let (_, store) = day?;
+            if let Some(trace) = store.get_by_span_id(span_id)? {
+                return Ok(Some(trace));
+            }
+        }
+        Ok(None)
+    }
+
+    fn list_recent(&self, limit: usize, offset: usize) -> Result<Vec<HttpTrace>, StorageError> {
+        if limit == 0 {
+            return Ok(Vec::new());
+        }
+        self.query(&TraceQuery {
+            limit,
+            offset,
+            ..Default::default()
+        })
+    }
+
+    fn get_by_trace_id(&self, trace_id: &TraceId) -> Result<Vec<HttpTrace>, StorageError> {
+        let mut results = Vec::new();
+        // Oldest day first, so a trace crossing midnight reads in order.
+        for day in self.day_stores()?.rev() {
+            let (_, store) = day?;
+            results.extend(store.get_by_trace_id(trace_id)?);
+        }
+        Ok(results)
+    }
+
+    fn search_by_url(&self, pattern: &str, limit: usize) -> Result<Vec<HttpTrace>, StorageError> {
+        let mut results = Vec::new();
+        for day in self.day_stores()? {
+            let (_, store) = day?;
+            if results.len() >= limit {
+                break;
+            }
+            results.extend(store.search_by_url(pattern, limit - results.len())?);
+        }
+        Ok(results)
+    }
+
+    fn count(&self) -> Result<u64, StorageError> {
+        self.day_stores()?.map(|day| day?.1.count()).sum()
+    }
+
+    fn query(&self, query: &TraceQuery) -> Result<Vec<HttpTrace>, StorageError> {
+        const DEFAULT_LIMIT: usize = 100;
+        let limit = if query.limit == 0 {
+            DEFAULT_LIMIT
+        } else {
+            query.limit
+        };
+
+        // Each day returns its matches newest first; ask for enough to cover
+        // the offset still to skip, then skip it here.
+        let mut to_skip = query.offset;
+        let mut results = Vec::new();
+        for day in self.days_for(query)? {
+            let (_, store) = day?;
+            let remaining = limit - results.len();
+            let day_query = TraceQuery {
+                limit: to_skip + remaining,
+                offset: 0,
+                ..query.clone()
+            };
+            let matches = store.query(&day_query)?;
+            let skipped = to_skip.min(matches.len());
+            to_skip -= skipped;
+            results.extend(matches.into_iter().skip(skipped).take(remaining));
+            if results.len() >= limit {
+                break;
+            }
+        }
+        Ok(results)
+    }
+
+    /// Deletes every day directory.
+    fn clear(&self) -> Result<(), StorageError> {
+        let mut days = self
+            .days
+            .lock()
+            .map_err(|_| StorageError::Write("day store lock poisoned".into()))?;
+        // Close day stores (releasing their locks) before removing them.
+        *days = OpenDays::default();
+        for name in self.day_names()? {
+            std::fs::remove_dir_all(self.root.join(&name))
+                .map_err(|e| StorageError::Write(format!("{name}: {e}")))?;
+        }
+        Ok(())
+    }
+
+    fn insert_protocol(&self, trace: &ProtocolTrace) -> Result<(), StorageError> {
+        match trace {
+            ProtocolTrace::Redis(t) => self.insert_redis(t),
+            ProtocolTrace::Postgres(t) => self.insert_postgres(t),
+            ProtocolTrace::Grpc(t) => self.insert_grpc(t),
+            ProtocolTrace::Thrift(_)
+            | ProtocolTrace::Kafka(_)
+            | ProtocolTrace::WebSocket(_)
+            | ProtocolTrace::Sse(_) => Ok(()),
+        }
+    }
+}
+
+impl RedisTraceStore for DailyTraceStore {
+    fn insert_redis(&self, trace: &RedisTrace) -> Result<(), StorageError> {
+        self.day(&day_name(&trace.timestamp))?.insert_redis(trace)
+    }
+
+    fn list_recent_redis(
+        &self,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<RedisTrace>, StorageError> {
+        self.list_days(limit, offset, |store, n| store.list_recent_redis(n, 0))
+    }
+
+    fn list_redis_by_command(
+        &self,
+        command: &str,
+        limit: usize,
+    ) -> Result<Vec<RedisTrace>, StorageError> {
+        self.list_days(limit, 0, |store, n| store.list_redis_by_command(command, n))
+    }
+
+    fn count_redis(&self) -> Result<u64, StorageError> {
+        self.day_stores()?.map(|day| day?.1.count_redis()).sum()
+    }
+
+    fn clear_redis(&self) -> Result<(), StorageError> {
+        for day in self.day_stores()? {
+            day?.1.clear_redis()?;
+        }
+        Ok(())
+    }
+}
+
+impl PostgresTraceStore for DailyTraceStore {
+    fn insert_postgres(&self, trace: &PostgresTrace) -> Result<(), StorageError> {
+        self.day(&day_name(&trace.timestamp))?
+            .insert_postgres(trace)
+    }
+
+    fn list_recent_postgres(
+        &self,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<PostgresTrace>, StorageError> {
+        self.list_days(limit, offset, |store, n| store.list_recent_postgres(n, 0))
+    }
+
+    fn list_failed_postgres(&self, limit: usize) -> Result<Vec<PostgresTrace>, StorageError> {
+        self.list_days(limit, 0, FjallTraceStore::list_failed_postgres)
+    }
+
+    fn count_postgres(&self) -> Result<u64, StorageError> {
+        self.day_stores()?.map(|day| day?.1.count_postgres()).sum()
+    }
+
+    fn clear_postgres(&self) -> Result<(), StorageError> {
+        for day in self.day_stores()? {
+            day?.1.clear_postgres()?;
+        }
+        Ok(())
+    }
+}
+
+impl GrpcTraceStore for DailyTraceStore {
+    fn insert_grpc(&self, trace: &GrpcTrace) -> Result<(), StorageError> {
+        self.day(&day_name(&trace.timestamp))?.insert_grpc(trace)
+    }
+
+    fn list_recent_grpc(
+        &self,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<GrpcTrace>, StorageError> {
+        self.list_days(limit, offset, |store, n| store.list_recent_grpc(n, 0))
+    }
+
+    fn list_grpc_by_service(
+        &self,
+        service: &str,
+        limit: usize,
+    ) -> Result<Vec<GrpcTrace>, StorageError> {
+        self.list_days(limit, 0, |store, n| store.list_grpc_by_service(service, n))
+    }
+
+    fn count_grpc(&self) -> Result<u64, StorageError> {
+        self.day_stores()?.map(|day| day?.1.count_grpc()).sum()
+    }
+
+    fn clear_grpc(&self) -> Result<(), StorageError> {
+        for day in self.day_stores()? {
+            day?.1.clear_grpc()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    use phantom_core::trace::HttpMethod;
+
+    const DAY: u64 = SECS_PER_DAY;
+
+    fn make_trace(url: &str, ts_secs: u64) -> HttpTrace {
+        HttpTrace {
+            span_id: SpanId(rand::random()),
+            trace_id: TraceId(rand::random()),
+            parent_span_id: None,
+            method: HttpMethod::Get,
+            url: url.to_string(),
+            request_headers: HashMap::new(),
+            request_body: None,
+            status_code: 200,
+            response_headers: HashMap::new(),
+            response_body: None,
+            timestamp: UNIX_EPOCH + Duration::from_secs(ts_secs),
+            duration: Duration::from_millis(42),
+            source_addr: None,
+            dest_addr: None,
+            protocol_version: "HTTP/1.1".to_string(),
+            request_body_file: None,
+            response_body_file: None,
+            connection_id: None,
+            process: None,
+            response_trailers: HashMap::new(),
+            request_body_encoding: None,
+            response_body_encoding: None,
+            elasticsearch: None,
+            soap: None,
+            graphql: None,
+            thread_id: None,
+            tls: None,
+            timing: None,
+            request_size: None,
+            response_size: None,
+            fault: None,
+            replay: None,
+        }
+    }
+
+    #[test]
+    fn test_day_name_roundtrip() {
+        assert_eq!(day_name(&UNIX_EPOCH), "1970-01-01");
+        let ts = UNIX_EPOCH + Duration::from_secs(1_717_200_000); // 2024-06-01T00:00:00Z
+        assert_eq!(day_name(&ts), "2024-06-01");
+        assert_eq!(parse_day("2024-06-01"), Some(ts));
+        assert_eq!(day_name(&(ts - Duration::from_secs(1))), "2024-05-31");
+        assert_eq!(
+            day_name(&(UNIX_EPOCH + Duration::from_secs(1_709_164_800))),
+            "2024-02-29"
+        );
+    }
+
+    #[test]
+    fn test_parse_day_rejects_non_dates() {
+        for name in [
+            "2024-02-30",
+            "2024-13-01",
+            "24-06-01",
+            "partitions",
+            "2024-6-1",
+        ] {
+            assert_eq!(parse_day(name), None, "{name}");
+        }
+    }
+
+    #[test]
+    fn test_inserts_land_in_day_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = DailyTraceStore::open(dir.path()).unwrap();
+
+        store.insert(&make_trace("http://a/1", 10)).unwrap();
+        store.insert(&make_trace("http://a/2", DAY + 10)).unwrap();
+
+        assert!(dir.path().join("1970-01-01").is_dir());
+        assert!(dir.path().join("1970-01-02").is_dir());
+        assert!(is_daily_layout(dir.path()));
+        assert_eq!(store.day_names().unwrap(), ["1970-01-02", "1970-01-01"]);
+    }
+
+    #[test]
+    fn test_query_spans_days_newest_first_with_offset() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = DailyTraceStore::open(dir.path()).unwrap();
+        for (i, ts) in [10, 20, DAY + 10, DAY + 20, 2 * DAY + 10]
+            .into_iter()
+            .enumerate()
+        {
+            store
+                .insert(&make_trace(&format!("http://a/{i}"), ts))
+                .unwrap();
+        }
+
+        let urls = |q: TraceQuery| -> Vec<String> {
+            store
+                .query(&q)
+                .unwrap()
+                .into_iter()
+                .map(|t| t.url)
+                .collect()
+        };
+        assert_eq!(
+            urls(TraceQuery::default()),
+            [
+                "http://a/4",
+                "http://a/3",
+                "http://a/2",
+                "http://a/1",
+                "http://a/0"
+            ]
+        );
+        // Offset and limit cross day boundaries.
+        assert_eq!(
+            urls(TraceQuery {
+                offset: 2,
+                limit: 2,
+                ..Default::default()
+            }),
+            ["http://a/2", "http://a/1"]
+        );
+        // A time range only touches the days it overlaps.
+        assert_eq!(
+            urls(TraceQuery {
+                since: Some(UNIX_EPOCH + Duration::from_secs(DAY)),
+                until: Some(UNIX_EPOCH + Duration::from_secs(DAY + 15)),
+                ..Default::default()
+            }),
+            ["http://a/2"]
+        );
+        assert_eq!(store.list_recent(1, 1).unwrap()[0].url, "http://a/3");
+    }
+
+    #[test]
+    fn test_protocol_records_span_days() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = DailyTraceStore::open(dir.path()).unwrap();
+        for (i, ts) in [10, DAY + 10, DAY + 20].into_iter().enumerate() {
+            let call = GrpcTrace {
+                service: "users.Users".to_string(),
+                method: format!("Call{i}"),
+                authority: None,
+                status: Some(0),
+                message: None,
+                request_messages: 1,
+                response_messages: 1,
+                timestamp: UNIX_EPOCH + Duration::from_secs(ts),
+                duration: Duration::from_millis(3),
+                dest_addr: None,
+            };
+            store.insert_protocol(&ProtocolTrace::Grpc(call)).unwrap();
+        }
+
+        assert!(dir.path().join("1970-01-02").is_dir());
+        assert_eq!(store.count_grpc().unwrap(), 3);
+        let methods: Vec<_> = store
+            .list_recent_grpc(2, 1)
+            .unwrap()
+            .into_iter()
+            .map(|t| t.method)
+            .collect();
+        assert_eq!(methods, ["Call1", "Call0"]);
+        assert_eq!(
+            store.list_grpc_by_service("users.Users", 10).unwrap().len(),
+            3
+        );
+        // HTTP traces live apart.
+        assert!(store.list_recent(10, 0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_lookups_span_days() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = DailyTraceStore::open(dir.path()).unwrap();
+
+        let first = make_trace("http://a/before-midnight", DAY - 1);
+        let mut second = make_trace("http://a/after-midnight", DAY);
+        second.trace_id = first.trace_id.clone();
+        store.insert(&first).unwrap();
+        store.insert(&second).unwrap();
+
+        assert!(store.get_by_span_id(&first.span_id).unwrap().is_some());
+        let spans = store.get_by_trace_id(&first.trace_id).unwrap();
+        assert_eq!(spans.len(), 2);
+        assert_eq!(spans[0].url, "http://a/before-midnight");
+        assert_eq!(store.search_by_url("midnight", 10).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_keeps_a_bounded_number_of_days_open() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = DailyTraceStore::open(dir.path()).unwrap();
+        let days = MAX_OPEN_DAYS as u64 + 4;
+        for day in 0..days {
+            store.insert(&make_trace("http://a/x", day * DAY)).unwrap();
+        }
+        assert_eq!(store.count().unwrap(), days);
+
+        let open = store.days.lock().unwrap();
+        assert_eq!(open.recent.len(), MAX_OPEN_DAYS);
+        assert_eq!(open.live.len(), MAX_OPEN_DAYS);
+        // Newest first, so the oldest days were used last.
+        assert_eq!(open.recent.back().unwrap().0, "1970-01-01");
+    }
+
+    #[test]
+    fn test_evicted_day_in_use_is_shared() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = DailyTraceStore::open(dir.path()).unwrap();
+        let first = store.day("1970-01-01").unwrap();
+        for day in 1..=MAX_OPEN_DAYS as u64 {
+            store.insert(&make_trace("http://a/x", day * DAY)).unwrap();
+        }
+        // Evicted but still held: reopening would fail on its lock.
+        let again = store.day("1970-01-01").unwrap();
+        assert!(Arc::ptr_eq(&first, &again));
+    }
+
+    #[test]
+    fn test_deleted_day_disappears_from_queries() {
+        let dir = tempfile::tempdir().unwrap();
+        {
+            let store = DailyTraceStore::open(dir.path()).unwrap();
+            store.insert(&make_trace("http://a/old", 10)).unwrap();
+            store.insert(&make_trace("http://a/new", DAY + 10)).unwrap();
+        }
+        std::fs::remove_dir_all(dir.path().join("1970-01-01")).unwrap();
+
+        let store = DailyTraceStore::open(dir.path()).unwrap();
+        let results = store.query(&TraceQuery::default()).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].url, "http://a/new");
+    }
+
+    #[test]
+    fn test_clear_removes_day_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = DailyTraceStore::open(dir.path()).unwrap();
+        store.insert(&make_trace("http://a/x", 10)).unwrap();
+
+        store.clear().unwrap();
+        assert!(store.day_names().unwrap().is_empty());
+        assert!(store.query(&TraceQuery::default()).unwrap().is_empty());
+        // Still usable afterwards.
+        store.insert(&make_trace("http://a/y", 10)).unwrap();
+        assert_eq!(store.list_recent(10, 0).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_refuses_flat_store_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        drop(FjallTraceStore::open(dir.path()).unwrap());
+        assert!(matches!(
+            DailyTraceStore::open(dir.path()),
+            Err(StorageError::Open(_))
+        ));
+    }
+
+    #[test]
+    fn test_backup_keeps_day_layout() {
+        let dir = tempfile::tempdir().unwrap();
+        let backup_dir = tempfile::tempdir().unwrap();
+        let store = DailyTraceStore::open(dir.path()).unwrap();
+        store.insert(&make_trace("http://a/1", 10)).unwrap();
+        store.insert(&make_trace("http://a/2", DAY + 10)).unwrap();
+
+        assert_eq!(store.backup_to(backup_dir.path()).unwrap(), 2);
+        assert!(is_daily_layout(backup_dir.path()));
+        let backup = DailyTraceStore::open(backup_dir.path()).unwrap();
+        assert_eq!(backup.query(&TraceQuery::default()).unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,58 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use phantom_core::error::StorageError;
+use phantom_core::storage::TraceStore;
+
+use crate::daily_store::{DailyTraceStore, is_daily_layout};
+use crate::fjall_store::{ConsistencyReport, FjallTraceStore};
+
+/// A trace store opened with whichever layout its data directory uses.
+pub enum DataStore {
+    /// One keyspace for all traces.
+    Flat(Arc<FjallTraceStore>),
+    /// One keyspace per UTC day.
+    Daily(Arc<DailyTraceStore>),
+}
+
+impl DataStore {
+    /// Opens the store at `path`. A day-partitioned directory is always
+    /// opened as such; `partition_by_day` creates one in a new directory
+    /// (and fails on an existing flat store).
+    pub fn open(path: impl AsRef<Path>, partition_by_day: bool) -> Result<Self, StorageError> {
+        let path = path.as_ref();
+        if partition_by_day || is_daily_layout(path) {
+            Ok(Self::Daily(Arc::new(DailyTraceStore::open(path)?)))
+        } else {
+            Ok(Self::Flat(Arc::new(FjallTraceStore::open(path)?)))
+        }
+    }
+
+    pub fn trace_store(&self) -> Arc<dyn TraceStore> {
+        match self {
+            Self::Flat(store) => store.clone(),
+            Self::Daily(store) => store.clone(),
+        }
+    }
+
+    pub fn verify_and_repair(&self) -> Result<ConsistencyReport, StorageError> {
+        match self {
+            Self::Flat(store) => store.verify_and_repair(),
+            Self::Daily(store) => store.verify_and_repair(),
+        }
+    }
+
+    pub fn compact(&self) -> Result<(), StorageError> {
+        match self {
+            Self::Flat(store) => store.compact(),
+            Self::Daily(store) => store.compact(),
+        }
+    }
+
+    pub fn backup_to(&self, dest: impl AsRef<Path>) -> Result<u64, StorageError> {
+        match self {
+            Self::Flat(store) => store.backup_to(dest),
+            Self::Daily(store) => store.backup_to(dest),
+        }
+    }
+}
@@ -1,16 +1,28 @@
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use fjall::{Config, Keyspace, PartitionCreateOptions, PartitionHandle};
+use fjall::{Config, Keyspace, PartitionCreateOptions, PartitionHandle, PersistMode};
 use phantom_core::error::StorageError;
+use phantom_core::grpc::GrpcTrace;
+use phantom_core::postgres::PostgresTrace;
+use phantom_core::protocol::ProtocolTrace;
 use phantom_core::query::TraceQuery;
-use phantom_core::storage::TraceStore;
+use phantom_core::redis::RedisTrace;
+use phantom_core::storage::{GrpcTraceStore, PostgresTraceStore, RedisTraceStore, TraceStore};
 use phantom_core::trace::{HttpTrace, SpanId, TraceId};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
 
 pub struct FjallTraceStore {
     keyspace: Keyspace,
     traces: PartitionHandle,
     by_time: PartitionHandle,
     by_trace_id: PartitionHandle,
+    /// Redis commands, PostgreSQL queries and gRPC calls, each keyed by
+    /// [`record_key`].
+    redis: PartitionHandle,
+    postgres: PartitionHandle,
+    grpc: PartitionHandle,
     /// Advisory exclusive lock on the data directory, released on drop.
     /// fjall itself does not lock across processes, and two writers on one
     /// keyspace would corrupt it — so we enforce single-process access here.
@@ -54,16 +66,255 @@ impl FjallTraceStore {
             .open_partition("by_trace_id", PartitionCreateOptions::default())
             .map_err(|e| StorageError::Open(e.to_string()))?;
 
+        let [redis, postgres, grpc] = ["redis", "postgres", "grpc"].map(|name| {
+            keyspace
+                .open_partition(name, PartitionCreateOptions::default())
+                .map_err(|e| StorageError::Open(e.to_string()))
+        });
+
         Ok(Self {
             keyspace,
             traces,
             by_time,
             by_trace_id,
+            redis: redis?,
+            postgres: postgres?,
+            grpc: grpc?,
             _lock: lock,
         })
     }
 }
 
+/// Result of [`FjallTraceStore::verify_and_repair`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConsistencyReport {
+    /// Trace records checked.
+    pub records: u64,
+    /// Index entries pointing at a missing record, now removed.
+    pub dangling_index_removed: u64,
+    /// Index entries missing for an existing record, now restored.
+    pub missing_index_restored: u64,
+    /// Records that no longer deserialize. Left in place for inspection.
+    pub unreadable_records: u64,
+}
+
+/// Index entries copied per write batch during backup and repair.
+const BATCH_SIZE: usize = 1000;
+
+impl FjallTraceStore {
+    /// Checks that every index entry points at a stored record and every
+    /// record is reachable from both indices, repairing what it can.
+    pub fn verify_and_repair(&self) -> Result<ConsistencyReport, StorageError> {
+        let mut report = ConsistencyReport::default();
+
+        for index in [&self.by_time, &self.by_trace_id] {
+            let mut dangling = Vec::new();
+            for entry in index.iter() {
+                let (key, value) = entry.map_err(|e| StorageError::Read(e.to_string()))?;
+                let exists = value.len() == 8
+                    && self
+                        .traces
+                        .contains_key(&value)
+                        .map_err(|e| StorageError::Read(e.to_string()))?;
+                if !exists {
+                    dangling.push(key);
+                }
+            }
+            report.dangling_index_removed += dangling.len() as u64;
+            for chunk in dangling.chunks(BATCH_SIZE) {
+                let mut batch = self.keyspace.batch();
+                for key in chunk {
+                    batch.remove(index, key.clone());
+                }
+                batch
+                    .commit()
+                    .map_err(|e| StorageError::Write(e.to_string()))?;
+            }
+        }
+
+        let mut batch = self.keyspace.batch();
+        let mut pending = 0;
+        for entry in self.traces.iter() {
+            let (_key, value) = entry.map_err(|e| StorageError::Read(e.to_string()))?;
+            report.records += 1;
+            let Ok(trace) = serde_json::from_slice::<HttpTrace>(&value) else {
+                report.unreadable_records += 1;
+                continue;
+            };
+            let span_key = trace.span_id.as_bytes();
+            let time_k = time_key(&trace.timestamp, &trace.span_id);
+            let trace_id_k = trace_id_key(&trace.trace_id, &trace.span_id);
+            for (index, key) in [
+                (&self.by_time, &time_k[..]),
+                (&self.by_trace_id, &trace_id_k[..]),
+            ] {
+                if !index
+                    .contains_key(key)
+                    .map_err(|e| StorageError::Read(e.to_string()))?
+                {
+                    batch.insert(index, key, span_key);
+                    report.missing_index_restored += 1;
+                    pending += 1;
+                }
+            }
+            if pending >= BATCH_SIZE {
+                batch
+                    .commit()
+                    .map_err(|e| StorageError::Write(e.to_string()))?;
+                batch = self.keyspace.batch();
+                pending = 0;
+            }
+        }
+        batch
+            .commit()
+            .map_err(|e| StorageError::Write(e.to_string()))?;
+
+        Ok(report)
+    }
+
+    /// Flushes the journal and runs a major compaction on every partition,
+    /// dropping tombstones left by `clear` and repairs.
+    pub fn compact(&self) -> Result<(), StorageError> {
+        self.keyspace
+            .persist(PersistMode::SyncAll)
+            .map_err(|e| StorageError::Write(e.to_string()))?;
+        for partition in self.partitions() {
+            partition
+                .major_compact()
+                .map_err(|e| StorageError::Write(e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    /// Copies every record and index entry into a new store at `dest`,
+    /// which must not exist or be empty. Returns the number of records copied.
+    ///
+    /// The source holds the exclusive store lock for the duration, so the
+    /// copy is a consistent snapshot. The backup is a regular data directory
+    /// usable with `--data-dir`.
+    pub fn backup_to(&self, dest: impl AsRef<std::path::Path>) -> Result<u64, StorageError> {
+        let dest = dest.as_ref();
+        let non_empty = std::fs::read_dir(dest).is_ok_and(|mut entries| entries.next().is_some());
+        if non_empty {
+            return Err(StorageError::Open(format!(
+                "backup directory {} is not empty",
+                dest.display()
+            )));
+        }
+        let backup = FjallTraceStore::open(dest)?;
+
+        let records = backup.copy_partition(&self.traces, &backup.traces)?;
+        backup.copy_partition(&self.by_time, &backup.by_time)?;
+        backup.copy_partition(&self.by_trace_id, &backup.by_trace_id)?;
+        backup.copy_partition(&self.redis, &backup.redis)?;
+        backup.copy_partition(&self.postgres, &backup.postgres)?;
+        backup.copy_partition(&self.grpc, &backup.grpc)?;
+        backup
+            .keyspace
+            .persist(PersistMode::SyncAll)
+            .map_err(|e| StorageError::Write(e.to_string()))?;
+        Ok(records)
+    }
+
+    /// Copies all entries of `source` (from another store) into `target`,
+    /// one of this store's partitions. Returns the number of entries copied.
+    fn copy_partition(
+        &self,
+        source: &PartitionHandle,
+        target: &PartitionHandle,
+    ) -> Result<u64, StorageError> {
+        let mut copied: u64 = 0;
+        let mut batch = self.keyspace.batch();
+        for entry in source.iter() {
+            let (key, value) = entry.map_err(|e| StorageError::Read(e.to_string()))?;
+            batch.insert(target, key, value);
+            copied += 1;
+            if copied.is_multiple_of(BATCH_SIZE as u64) {
+                batch
+                    .commit()
+                    .map_err(|e| StorageError::Write(e.to_string()))?;
+                batch = self.keyspace.batch();
+            }
+        }
+        batch
+            .commit()
+            .map_err(|e| StorageError::Write(e.to_string()))?;
+        Ok(copied)
+    }
+}
+
+impl FjallTraceStore {
+    fn partitions(&self) -> [&PartitionHandle; 6] {
+        [
+            &self.traces,
+            &self.by_time,
+            &self.by_trace_id,
+            &self.redis,
+            &self.postgres,
+            &self.grpc,
+        ]
+    }
+
+    /// Stores `record` in one of the protocol partitions.
+    fn insert_record<T: Serialize>(
+        &self,
+        partition: &PartitionHandle,
+        timestamp: &SystemTime,
+        record: &T,
+    ) -> Result<(), StorageError> {
+        let serialized =
+            serde_json::to_vec(record).map_err(|e| StorageError::Serialization(e.to_string()))?;
+        let mut batch = self.keyspace.batch();
+        batch.insert(partition, record_key(timestamp)?, serialized);
+        batch
+            .commit()
+            .map_err(|e| StorageError::Write(e.to_string()))
+    }
+
+    /// Lists records of a protocol partition (newest first) that `keep`
+    /// accepts, skipping the first `offset` of them.
+    fn list_records<T: DeserializeOwned>(
+        partition: &PartitionHandle,
+        limit: usize,
+        offset: usize,
+        keep: impl Fn(&T) -> bool,
+    ) -> Result<Vec<T>, StorageError> {
+        let mut skipped = 0;
+        let mut results = Vec::new();
+        for entry in partition.iter().rev() {
+            if results.len() >= limit {
+                break;
+            }
+            let (_key, value) = entry.map_err(|e| StorageError::Read(e.to_string()))?;
+            let record: T = serde_json::from_slice(&value)
+                .map_err(|e| StorageError::Serialization(e.to_string()))?;
+            if !keep(&record) {
+                continue;
+            }
+            if skipped < offset {
+                skipped += 1;
+                continue;
+            }
+            results.push(record);
+        }
+        Ok(results)
+    }
+
+    fn clear_partition(&self, partition: &PartitionHandle) -> Result<(), StorageError> {
+        let keys: Vec<_> = partition
+            .keys()
+            .collect::<Result<_, _>>()
+            .map_err(|e| StorageError::Read(e.to_string()))?;
+        let mut batch = self.keyspace.batch();
+        for key in keys {
+            batch.remove(partition, key);
+        }
+        batch
+            .commit()
+            .map_err(|e| StorageError::Write(e.to_string()))
+    }
+}
+
 /// Encode a `SystemTime` as big-endian nanoseconds since UNIX epoch.
 fn encode_timestamp(ts: &SystemTime) -> [u8; 8] {
     let nanos = ts
@@ -81,6 +332,17 @@ fn time_key(ts: &SystemTime, span_id: &SpanId) -> [u8; 16] {
     key
 }
 
+/// Build a protocol record key: `{timestamp_be (8B)}{random (8B)}`, so
+/// records sort by time and records of the same instant don't collide.
+fn record_key(ts: &SystemTime) -> Result<[u8; 16], StorageError> {
+    let mut key = [0u8; 16];
+    key[..8].copy_from_slice(&encode_timestamp(ts));
+    SystemRandom::new()
+        .fill(&mut key[8..])
+        .map_err(|_| StorageError::Write("no randomness for a record key".into()))?;
+    Ok(key)
+}
+
 /// Build the `by_trace_id` key: `{trace_id (16B)}{span_id (8B)}`.
 fn trace_id_key(trace_id: &TraceId, span_id: &SpanId) -> [u8; 24] {
     let mut key = [0u8; 24];
@@ -102,6 +364,13 @@ impl TraceStore for FjallTraceStore {
         batch.insert(&self.traces, span_key, &serialized);
         batch.insert(&self.by_time, time_k, span_key);
         batch.insert(&self.by_trace_id, trace_id_k, span_key);
+        // A gRPC call is also kept as a typed record, written atomically
+        // with the trace it came from.
+        if let Some(call) = GrpcTrace::from_http(trace) {
+            let record = serde_json::to_vec(&call)
+                .map_err(|e| StorageError::Serialization(e.to_string()))?;
+            batch.insert(&self.grpc, record_key(&call.timestamp)?, record);
+        }
         batch
             .commit()
             .map_err(|e| StorageError::Write(e.to_string()))?;
@@ -230,21 +499,113 @@ impl TraceStore for FjallTraceStore {
     }
 
     fn clear(&self) -> Result<(), StorageError> {
-        for partition in [&self.traces, &self.by_time, &self.by_trace_id] {
-            let keys: Vec<_> = partition
-                .keys()
-                .collect::<Result<_, _>>()
-                .map_err(|e| StorageError::Read(e.to_string()))?;
-            let mut batch = self.keyspace.batch();
-            for key in keys {
-                batch.remove(partition, key);
-            }
-            batch
-                .commit()
-                .map_err(|e| StorageError::Write(e.to_string()))?;
+        for partition in self.partitions() {
+            self.clear_partition(partition)?;
         }
         Ok(())
     }
+
+    fn insert_protocol(&self, trace: &ProtocolTrace) -> Result<(), StorageError> {
+        match trace {
+            ProtocolTrace::Redis(t) => self.insert_redis(t),
+            ProtocolTrace::Postgres(t) => self.insert_postgres(t),
+            ProtocolTrace::Grpc(t) => self.insert_grpc(t),
+            ProtocolTrace::Thrift(_)
+            | ProtocolTrace::Kafka(_)
+            | ProtocolTrace::WebSocket(_)
+            | ProtocolTrace::Sse(_) => Ok(()),
+        }
+    }
+}
+
+impl RedisTraceStore for FjallTraceStore {
+    fn insert_redis(&self, trace: &RedisTrace) -> Result<(), StorageError> {
+        self.insert_record(&self.redis, &trace.timestamp, trace)
+    }
+
+    fn list_recent_redis(
+        &self,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<RedisTrace>, StorageError> {
+        Self::list_records(&self.redis, limit, offset, |_| true)
+    }
+
+    fn list_redis_by_command(
+        &self,
+        command: &str,
+        limit: usize,
+    ) -> Result<Vec<RedisTrace>, StorageError> {
+        Self::list_records(&self.redis, limit, 0, |t: &RedisTrace| {
+            t.command.eq_ignore_ascii_case(command)
+        })
+    }
+
+    fn count_redis(&self) -> Result<u64, StorageError> {
+        Ok(self.redis.approximate_len() as u64)
+    }
+
+    fn clear_redis(&self) -> Result<(), StorageError> {
+        self.clear_partition(&self.redis)
+    }
+}
+
+impl PostgresTraceStore for FjallTraceStore {
+    fn insert_postgres(&self, trace: &PostgresTrace) -> Result<(), StorageError> {
+        self.insert_record(&self.postgres, &trace.timestamp, trace)
+    }
+
+    fn list_recent_postgres(
+        &self,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<PostgresTrace>, StorageError> {
+        Self::list_records(&self.postgres, limit, offset, |_| true)
+    }
+
+    fn list_failed_postgres(&self, limit: usize) -> Result<Vec<PostgresTrace>, StorageError> {
+        Self::list_records(&self.postgres, limit, 0, |t: &PostgresTrace| {
+            t.error.is_some()
+        })
+    }
+
+    fn count_postgres(&self) -> Result<u64, StorageError> {
+        Ok(self.postgres.approximate_len() as u64)
+    }
+
+    fn clear_postgres(&self) -> Result<(), StorageError> {
+        self.clear_partition(&self.postgres)
+    }
+}
+
+impl GrpcTraceStore for FjallTraceStore {
+    fn insert_grpc(&self, trace: &GrpcTrace) -> Result<(), StorageError> {
+        self.insert_record(&self.grpc, &trace.timestamp, trace)
+    }
+
+    fn list_recent_grpc(
+        &self,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<GrpcTrace>, StorageError> {
+        Self::list_records(&self.grpc, limit, offset, |_| true)
+    }
+
+    fn list_grpc_by_service(
+        &self,
+        service: &str,
+        limit: usize,
+    ) -> Result<Vec<GrpcTrace>, StorageError> {
+        Self::list_records(&self.grpc, limit, 0, |t: &GrpcTrace| t.service == service)
+    }
+
+    fn count_grpc(&self) -> Result<u64, StorageError> {
+        Ok(self.grpc.approximate_len() as u64)
+    }
+
+    fn clear_grpc(&self) -> Result<(), StorageError> {
+        self.clear_partition(&self.grpc)
+    }
 }
 
 #[cfg(test)]
@@ -270,6 +631,23 @@ mod tests {
             source_addr: None,
             dest_addr: None,
             protocol_version: "HTTP/1.1".to_string(),
+            request_body_file: None,
+            response_body_file: None,
+            connection_id: None,
+            process: None,
+            response_trailers: HashMap::new(),
+            request_body_encoding: None,
+            response_body_encoding: None,
+            elasticsearch: None,
+            soap: None,
+            graphql: None,
+            thread_id: None,
+            tls: None,
+            timing: None,
+            request_size: None,
+            response_size: None,
+            fault: None,
+            replay: None,
         }
     }
 
@@ -544,6 +922,107 @@ mod tests {
         assert!(FjallTraceStore::open(dir.path()).is_ok());
     }
 
+    #[test]
+    fn test_verify_removes_dangling_index_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FjallTraceStore::open(dir.path()).unwrap();
+
+        let orphan = make_trace("http://a/orphan", 200);
+        store.insert(&orphan).unwrap();
+        store.insert(&make_trace("http://a/kept", 200)).unwrap();
+        // Simulate a half-applied delete: the record is gone, indices remain.
+        store.traces.remove(orphan.span_id.as_bytes()).unwrap();
+
+        let report = store.verify_and_repair().unwrap();
+        assert_eq!(report.records, 1);
+        assert_eq!(report.dangling_index_removed, 2);
+        assert_eq!(report.missing_index_restored, 0);
+        assert_eq!(store.by_time.iter().count(), 1);
+        assert_eq!(store.by_trace_id.iter().count(), 1);
+
+        // A second pass finds nothing to do.
+        let report = store.verify_and_repair().unwrap();
+        assert_eq!(report.dangling_index_removed, 0);
+    }
+
+    #[test]
+    fn test_verify_restores_missing_index_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FjallTraceStore::open(dir.path()).unwrap();
+
+        let trace = make_trace("http://a/x", 200);
+        store.insert(&trace).unwrap();
+        store
+            .by_time
+            .remove(time_key(&trace.timestamp, &trace.span_id))
+            .unwrap();
+        assert!(store.list_recent(10, 0).unwrap().is_empty());
+
+        let report = store.verify_and_repair().unwrap();
+        assert_eq!(report.missing_index_restored, 1);
+        assert_eq!(store.list_recent(10, 0).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_verify_counts_unreadable_records() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FjallTraceStore::open(dir.path()).unwrap();
+
+        store
+            .traces
+            .insert([7u8; 8], b"not json".as_slice())
+            .unwrap();
+        let report = store.verify_and_repair().unwrap();
+        assert_eq!(report.records, 1);
+        assert_eq!(report.unreadable_records, 1);
+    }
+
+    #[test]
+    fn test_compact_keeps_data() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FjallTraceStore::open(dir.path()).unwrap();
+
+        for i in 0..3 {
+            store
+                .insert(&make_trace(&format!("http://a/{i}"), 200))
+                .unwrap();
+        }
+        store.compact().unwrap();
+        assert_eq!(store.list_recent(10, 0).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_backup_copies_records_and_indices() {
+        let dir = tempfile::tempdir().unwrap();
+        let backup_dir = tempfile::tempdir().unwrap();
+        let store = FjallTraceStore::open(dir.path()).unwrap();
+
+        let trace = make_trace("http://a/x", 200);
+        store.insert(&trace).unwrap();
+        store.insert(&make_trace("http://a/y", 500)).unwrap();
+
+        let copied = store.backup_to(backup_dir.path()).unwrap();
+        assert_eq!(copied, 2);
+
+        let backup = FjallTraceStore::open(backup_dir.path()).unwrap();
+        assert!(backup.get_by_span_id(&trace.span_id).unwrap().is_some());
+        assert_eq!(backup.get_by_trace_id(&trace.trace_id).unwrap().len(), 1);
+        assert_eq!(backup.list_recent(10, 0).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_backup_refuses_non_empty_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let backup_dir = tempfile::tempdir().unwrap();
+        std::fs::write(backup_dir.path().join("existing"), b"x").unwrap();
+        let store = FjallTraceStore::open(dir.path()).unwrap();
+
+        assert!(matches!(
+            store.backup_to(backup_dir.path()),
+            Err(StorageError::Open(_))
+        ));
+    }
+
     #[test]
     fn test_clear() {
         let dir = tempfile::tempdir().unwrap();
@@ -554,10 +1033,146 @@ mod tests {
         store.insert(&trace).unwrap();
         store.insert(&make_trace("http://a/y", 200)).unwrap();
 
+        store.insert_redis(&make_redis("GET", 1)).unwrap();
+
         store.clear().unwrap();
 
         assert!(store.get_by_span_id(&span_id).unwrap().is_none());
         assert!(store.list_recent(10, 0).unwrap().is_empty());
         assert!(store.query(&TraceQuery::default()).unwrap().is_empty());
+        assert!(store.list_recent_redis(10, 0).unwrap().is_empty());
+    }
+
+    fn make_redis(command: &str, ts_secs: u64) -> RedisTrace {
+        RedisTrace {
+            command: command.to_string(),
+            key: Some("user:1".to_string()),
+            args: Vec::new(),
+            arg_count: 1,
+            reply_type: phantom_core::redis::RedisReplyType::Bulk,
+            error: None,
+            timestamp: UNIX_EPOCH + Duration::from_secs(ts_secs),
+            duration: Duration::from_millis(1),
+            dest_addr: None,
+        }
+    }
+
+    #[test]
+    fn test_redis_store_lists_newest_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FjallTraceStore::open(dir.path()).unwrap();
+
+        store.insert_redis(&make_redis("GET", 1)).unwrap();
+        store.insert_redis(&make_redis("SET", 2)).unwrap();
+        // Same instant as the first: must not overwrite it.
+        store.insert_redis(&make_redis("GET", 1)).unwrap();
+        store.insert_redis(&make_redis("GET", 3)).unwrap();
+
+        assert_eq!(store.count_redis().unwrap(), 4);
+        let recent = store.list_recent_redis(2, 1).unwrap();
+        let commands: Vec<_> = recent.iter().map(|t| t.command.as_str()).collect();
+        assert_eq!(commands, ["SET", "GET"]);
+        assert_eq!(store.list_redis_by_command("get", 10).unwrap().len(), 3);
+        // HTTP traces live apart.
+        assert_eq!(store.list_recent(10, 0).unwrap().len(), 0);
+
+        store.clear_redis().unwrap();
+        assert!(store.list_recent_redis(10, 0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_postgres_store_lists_failed_queries() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FjallTraceStore::open(dir.path()).unwrap();
+
+        let query = |sql: &str, failed: bool| PostgresTrace {
+            query: sql.to_string(),
+            command: (!failed).then(|| "SELECT".to_string()),
+            rows: (!failed).then_some(1),
+            error: failed.then(|| phantom_core::postgres::PostgresError {
+                severity: "ERROR".to_string(),
+                code: "42P01".to_string(),
+                message: "relation \"missing\" does not exist".to_string(),
+            }),
+            database: None,
+            user: None,
+            timestamp: SystemTime::now(),
+            duration: Duration::from_millis(2),
+            dest_addr: None,
+        };
+        store.insert_postgres(&query("select 1", false)).unwrap();
+        store
+            .insert_postgres(&query("select * from missing", true))
+            .unwrap();
+
+        let failed = store.list_failed_postgres(10).unwrap();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].query, "select * from missing");
+        assert_eq!(store.list_recent_postgres(10, 0).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_insert_protocol_routes_to_typed_stores() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FjallTraceStore::open(dir.path()).unwrap();
+
+        store
+            .insert_protocol(&ProtocolTrace::Redis(make_redis("GET", 1)))
+            .unwrap();
+        assert_eq!(store.list_recent_redis(10, 0).unwrap().len(), 1);
+        assert!(store.list_recent(10, 0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_insert_keeps_grpc_calls_as_typed_records() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FjallTraceStore::open(dir.path()).unwrap();
+
+        let mut call = make_trace("http://users.local/users.Users/Get", 200);
+        call.request_headers
+            .insert("content-type".to_string(), "application/grpc".to_string());
+        store.insert(&call).unwrap();
+        store.insert(&make_trace("http://a/users", 200)).unwrap();
+
+        let calls = store.list_recent_grpc(10, 0).unwrap();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].full_method(), "users.Users/Get");
+        assert_eq!(store.list_recent(10, 0).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_grpc_store_filters_by_service_and_is_backed_up() {
+        let dir = tempfile::tempdir().unwrap();
+        let backup_dir = tempfile::tempdir().unwrap();
+        let store = FjallTraceStore::open(dir.path()).unwrap();
+
+        let call = |service: &str, ts_secs: u64| GrpcTrace {
+            service: service.to_string(),
+            method: "Get".to_string(),
+            authority: None,
+            status: Some(0),
+            message: None,
+            request_messages: 1,
+            response_messages: 1,
+            timestamp: UNIX_EPOCH + Duration::from_secs(ts_secs),
+            duration: Duration::from_millis(3),
+            dest_addr: None,
+        };
+        store.insert_grpc(&call("users.Users", 1)).unwrap();
+        store.insert_grpc(&call("orders.Orders", 2)).unwrap();
+        assert_eq!(
+            store.list_grpc_by_service("users.Users", 10).unwrap().len(),
+            1
+        );
+
+        store.backup_to(backup_dir.path()).unwrap();
+        let backup = FjallTraceStore::open(backup_dir.path()).unwrap();
+        let services: Vec<_> = backup
+            .list_recent_grpc(10, 0)
+            .unwrap()
+            .into_iter()
+            .map(|t| t.service)
+            .collect();
+        assert_eq!(services, ["orders.Orders", "users.Users"]);
     }
 }
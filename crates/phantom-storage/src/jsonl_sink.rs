@@ -0,0 +1,176 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+
+use phantom_core::error::StorageError;
+use phantom_core::protocol::ProtocolTrace;
+use phantom_core::sink::TraceSink;
+use phantom_core::trace::HttpTrace;
+use phantom_core::view::{ProtocolTraceView, RenderOptions, TraceView};
+use serde::Serialize;
+
+/// A [`TraceSink`] appending one [`TraceView`] JSON object per line to a file,
+/// the same records `phantom run --output jsonl` prints: non-HTTP traces are
+/// [`ProtocolTraceView`] lines, told apart by their `kind` field. Each line is
+/// written with a single `write_all`, so the file can be tailed while
+/// capturing.
+pub struct JsonlFileSink {
+    name: String,
+    file: Mutex<File>,
+    opts: RenderOptions,
+}
+
+impl JsonlFileSink {
+    /// Opens (creating if needed) `path` for appending.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, StorageError> {
+        let path = path.as_ref();
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| StorageError::Open(format!("{}: {e}", path.display())))?;
+        Ok(Self {
+            name: format!("jsonl:{}", path.display()),
+            file: Mutex::new(file),
+            opts: RenderOptions::default(),
+        })
+    }
+}
+
+impl JsonlFileSink {
+    fn append(&self, record: &impl Serialize) -> Result<(), StorageError> {
+        let mut line =
+            serde_json::to_vec(record).map_err(|e| StorageError::Serialization(e.to_string()))?;
+        line.push(b'\n');
+        let mut file = self
+            .file
+            .lock()
+            .map_err(|_| StorageError::Write("jsonl sink lock poisoned".into()))?;
+        file.write_all(&line)
+            .map_err(|e| StorageError::Write(e.to_string()))
+    }
+}
+
+impl TraceSink for JsonlFileSink {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn write(&self, trace: &HttpTrace) -> Result<(), StorageError> {
+        self.append(&TraceView::render(trace, &self.opts))
+    }
+
+    fn write_protocol(&self, trace: &ProtocolTrace) -> Result<(), StorageError> {
+        self.append(&ProtocolTraceView::from(trace))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::time::{Duration, SystemTime};
+
+    use phantom_core::redis::{RedisReplyType, RedisTrace};
+    use phantom_core::trace::{HttpMethod, SpanId, TraceId};
+
+    use super::*;
+
+    fn make_trace(url: &str) -> HttpTrace {
+        HttpTrace {
+            span_id: SpanId([1; 8]),
+            trace_id: TraceId([2; 16]),
+            parent_span_id: None,
+            method: HttpMethod::Get,
+            url: url.to_string(),
+            request_headers: HashMap::new(),
+            request_body: None,
+            status_code: 200,
+            response_headers: HashMap::new(),
+            response_body: Some(b"ok".to_vec()),
+            timestamp: SystemTime::now(),
+            duration: Duration::from_millis(5),
+            source_addr: None,
+            dest_addr: None,
+            protocol_version: "HTTP/1.1".to_string(),
+            request_body_file: None,
+            response_body_file: None,
+            connection_id: None,
+            process: None,
+            response_trailers: HashMap::new(),
+            request_body_encoding: None,
+            response_body_encoding: None,
+            elasticsearch: None,
+            soap: None,
+            graphql: None,
+            thread_id: None,
+            tls: None,
+            timing: None,
+            request_size: None,
+            response_size: None,
+            fault: None,
+            replay: None,
+        }
+    }
+
+    #[test]
+    fn test_jsonl_sink_appends_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tee.jsonl");
+
+        let sink = JsonlFileSink::open(&path).unwrap();
+        sink.write(&make_trace("http://a/1")).unwrap();
+        sink.write(&make_trace("http://a/2")).unwrap();
+        drop(sink);
+        // Re-opening appends rather than truncating.
+        JsonlFileSink::open(&path)
+            .unwrap()
+            .write(&make_trace("http://a/3"))
+            .unwrap();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let urls: Vec<String> = content
+            .lines()
+            .map(|l| {
+                let v: serde_json::Value = serde_json::from_str(l).unwrap();
+                v["url"].as_str().unwrap().to_string()
+            })
+            .collect();
+        assert_eq!(urls, ["http://a/1", "http://a/2", "http://a/3"]);
+        let first: serde_json::Value =
+            serde_json::from_str(content.lines().next().unwrap()).unwrap();
+        assert_eq!(first["response_body"], "ok");
+    }
+
+    #[test]
+    fn test_jsonl_sink_tags_protocol_traces_by_kind() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tee.jsonl");
+
+        let sink = JsonlFileSink::open(&path).unwrap();
+        sink.write(&make_trace("http://a/1")).unwrap();
+        let redis = RedisTrace {
+            command: "GET".into(),
+            key: Some("user:1".into()),
+            args: Vec::new(),
+            arg_count: 1,
+            reply_type: RedisReplyType::Bulk,
+            error: None,
+            timestamp: SystemTime::now(),
+            duration: Duration::from_millis(1),
+            dest_addr: None,
+        };
+        sink.write_protocol(&ProtocolTrace::Redis(redis)).unwrap();
+        drop(sink);
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<serde_json::Value> = content
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["url"], "http://a/1");
+        assert_eq!(lines[1]["kind"], "redis");
+        assert_eq!(lines[1]["command"], "GET");
+    }
+}
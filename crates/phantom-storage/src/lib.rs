@@ -1,3 +1,13 @@
+mod audit;
+mod daily_store;
+mod data_store;
 mod fjall_store;
+mod jsonl_sink;
 
-pub use fjall_store::FjallTraceStore;
+pub use audit::{
+    AuditLog, AuditVerification, AuditedTraceStore, generate_audit_key, verify_audit_log,
+};
+pub use daily_store::DailyTraceStore;
+pub use data_store::DataStore;
+pub use fjall_store::{ConsistencyReport, FjallTraceStore};
+pub use jsonl_sink::JsonlFileSink;
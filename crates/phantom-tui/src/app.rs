@@ -1,4 +1,14 @@
-use phantom_core::trace::HttpTrace;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::time::{Duration, SystemTime};
+
+use phantom_core::capture::AgentStats;
+use phantom_core::connection::ConnectionTracker;
+use phantom_core::intercept::InterceptedRequest;
+use phantom_core::slo::SloTracker;
+use phantom_core::trace::{HttpTrace, ProcessInfo};
+
+/// An agent that has not reported for this long is no longer counted.
+const AGENT_STALE_AFTER: Duration = Duration::from_secs(15);
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Pane {
@@ -11,10 +21,30 @@ pub struct App {
     pub selected_index: usize,
     pub filter: String,
     pub filter_active: bool,
+    /// Only show traces from this process (cycled with `p`).
+    pub process_filter: Option<ProcessInfo>,
     pub active_pane: Pane,
     pub should_quit: bool,
     pub trace_count: u64,
     pub backend_name: String,
+    /// Error-budget tracking for `--slo` objectives (empty when none given).
+    pub slo: SloTracker,
+    /// Connection reuse of traces captured this session.
+    pub connections: ConnectionTracker,
+    /// Overflow body file to show in a pager on the next loop iteration.
+    pub open_body: Option<String>,
+    /// Trace rows visible in the table, updated after every draw.
+    pub page_size: usize,
+    /// Digits typed after `:`; `Some` while the go-to-row prompt is open.
+    pub goto_input: Option<String>,
+    /// Latest health report of each in-process agent, by pid.
+    pub agents: BTreeMap<u32, AgentStats>,
+    /// Requests held by `--intercept`, oldest first; the first is shown.
+    pub held: VecDeque<InterceptedRequest>,
+    /// Open the shown held request in `$EDITOR` on the next loop iteration.
+    pub edit_held: bool,
+    /// Why the last edit of a held request was not applied.
+    pub intercept_error: Option<String>,
 }
 
 impl App {
@@ -24,23 +54,52 @@ impl App {
             selected_index: 0,
             filter: String::new(),
             filter_active: false,
+            process_filter: None,
             active_pane: Pane::TraceList,
             should_quit: false,
             trace_count: 0,
             backend_name: backend_name.to_string(),
+            slo: SloTracker::default(),
+            connections: ConnectionTracker::default(),
+            open_body: None,
+            page_size: 1,
+            goto_input: None,
+            agents: BTreeMap::new(),
+            held: VecDeque::new(),
+            edit_held: false,
+            intercept_error: None,
         }
     }
 
     pub fn filtered_traces(&self) -> Vec<&HttpTrace> {
-        if self.filter.is_empty() {
-            self.traces.iter().collect()
-        } else {
-            let filter_lower = self.filter.to_lowercase();
-            self.traces
-                .iter()
-                .filter(|t| t.url.to_lowercase().contains(&filter_lower))
-                .collect()
-        }
+        let filter_lower = self.filter.to_lowercase();
+        self.traces
+            .iter()
+            .filter(|t| filter_lower.is_empty() || t.url.to_lowercase().contains(&filter_lower))
+            .filter(|t| {
+                self.process_filter.is_none() || t.process.as_ref() == self.process_filter.as_ref()
+            })
+            .collect()
+    }
+
+    /// Distinct processes that issued the loaded traces, in name/pid order.
+    pub fn processes(&self) -> Vec<ProcessInfo> {
+        self.traces
+            .iter()
+            .filter_map(|t| t.process.clone())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+
+    /// Steps the process filter through every seen process, then back to all.
+    pub fn cycle_process_filter(&mut self) {
+        let processes = self.processes();
+        self.process_filter = match &self.process_filter {
+            None => processes.into_iter().next(),
+            Some(current) => processes.into_iter().find(|p| p > current),
+        };
+        self.selected_index = 0;
     }
 
     pub fn selected_trace(&self) -> Option<&HttpTrace> {
@@ -61,6 +120,28 @@ impl App {
         }
     }
 
+    /// Moves the selection by `delta` rows, clamped to the filtered list.
+    fn move_by(&mut self, delta: isize) {
+        let max = self.filtered_traces().len().saturating_sub(1);
+        self.selected_index = self.selected_index.saturating_add_signed(delta).min(max);
+    }
+
+    pub fn page_down(&mut self) {
+        self.move_by(self.page_size as isize);
+    }
+
+    pub fn page_up(&mut self) {
+        self.move_by(-(self.page_size as isize));
+    }
+
+    pub fn half_page_down(&mut self) {
+        self.move_by((self.page_size / 2).max(1) as isize);
+    }
+
+    pub fn half_page_up(&mut self) {
+        self.move_by(-((self.page_size / 2).max(1) as isize));
+    }
+
     pub fn jump_top(&mut self) {
         self.selected_index = 0;
     }
@@ -76,6 +157,47 @@ impl App {
         };
     }
 
+    /// Queues the selected trace's full body for viewing — the response
+    /// body if it overflowed, otherwise the request body.
+    pub fn open_full_body(&mut self) {
+        self.open_body = self.selected_trace().and_then(|t| {
+            t.response_body_file
+                .clone()
+                .or_else(|| t.request_body_file.clone())
+        });
+    }
+
+    pub fn activate_goto(&mut self) {
+        self.goto_input = Some(String::new());
+    }
+
+    pub fn cancel_goto(&mut self) {
+        self.goto_input = None;
+    }
+
+    pub fn push_goto_char(&mut self, c: char) {
+        if let Some(input) = &mut self.goto_input
+            && c.is_ascii_digit()
+        {
+            input.push(c);
+        }
+    }
+
+    pub fn pop_goto_char(&mut self) {
+        if let Some(input) = &mut self.goto_input {
+            input.pop();
+        }
+    }
+
+    /// Selects the typed 1-based row (clamped to the list) and closes the prompt.
+    pub fn apply_goto(&mut self) {
+        let Some(row) = self.goto_input.take().and_then(|s| s.parse::<usize>().ok()) else {
+            return;
+        };
+        let max = self.filtered_traces().len().saturating_sub(1);
+        self.selected_index = row.saturating_sub(1).min(max);
+    }
+
     pub fn activate_filter(&mut self) {
         self.filter_active = true;
     }
@@ -86,6 +208,7 @@ impl App {
 
     pub fn clear_filter(&mut self) {
         self.filter.clear();
+        self.process_filter = None;
         self.filter_active = false;
         self.selected_index = 0;
     }
@@ -100,7 +223,64 @@ impl App {
         self.selected_index = 0;
     }
 
+    pub fn record_agent_stats(&mut self, stats: AgentStats) {
+        self.agents.insert(stats.process.pid, stats);
+    }
+
+    /// Agents that reported recently, with their total dropped messages and
+    /// parse errors. Drops and errors of exited agents still count.
+    pub fn agent_health(&self) -> (usize, u64, u64) {
+        let now = SystemTime::now();
+        let live = self
+            .agents
+            .values()
+            // A report stamped in the future (clock skew) counts as fresh.
+            .filter(|s| {
+                now.duration_since(s.timestamp)
+                    .map_or(true, |age| age < AGENT_STALE_AFTER)
+            })
+            .count();
+        let dropped = self.agents.values().map(|s| s.dropped).sum();
+        let errors = self.agents.values().map(|s| s.errors).sum();
+        (live, dropped, errors)
+    }
+
+    pub fn hold(&mut self, request: InterceptedRequest) {
+        self.held.push_back(request);
+    }
+
+    /// Lets the shown held request through, edited or not.
+    pub fn forward_held(&mut self) {
+        if let Some(request) = self.held.pop_front() {
+            request.forward();
+        }
+        self.intercept_error = None;
+    }
+
+    /// Lets every held request through.
+    pub fn forward_all_held(&mut self) {
+        for request in self.held.drain(..) {
+            request.forward();
+        }
+        self.intercept_error = None;
+    }
+
+    /// Answers the shown held request without forwarding it.
+    pub fn drop_held(&mut self) {
+        if let Some(request) = self.held.pop_front() {
+            request.drop_request();
+        }
+        self.intercept_error = None;
+    }
+
+    /// Queues the shown held request for editing.
+    pub fn request_edit_held(&mut self) {
+        self.edit_held = !self.held.is_empty();
+    }
+
     pub fn add_trace(&mut self, trace: HttpTrace) {
+        self.slo.record(&trace);
+        self.connections.record(&trace);
         self.traces.insert(0, trace);
         self.trace_count += 1;
         // Keep selection stable when new traces arrive
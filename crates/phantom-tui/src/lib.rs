@@ -7,8 +7,10 @@ use std::sync::Arc;
 use crossterm::event::{KeyCode, KeyModifiers};
 use crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
 use crossterm::{event::KeyEventKind, execute};
+use phantom_core::capture::TraceEvent;
+use phantom_core::intercept::InterceptedRequest;
+use phantom_core::slo::SloTracker;
 use phantom_core::storage::TraceStore;
-use phantom_core::trace::HttpTrace;
 use ratatui::Terminal;
 use ratatui::backend::CrosstermBackend;
 use tokio::sync::mpsc;
@@ -16,11 +18,21 @@ use tokio::sync::mpsc;
 use crate::app::App;
 use crate::event::{Event, EventHandler};
 
+/// Runs the interactive UI until the user quits.
+///
+/// Newly captured traces are counted against `slo`; its final state is
+/// returned so the caller can act on breached objectives. Agent health
+/// reports on `events` are shown in the status bar; non-HTTP traces are not
+/// listed.
+/// Requests from `intercepts` are held on screen until forwarded, edited or
+/// dropped; any still held on quit are forwarded.
 pub async fn run_tui(
     store: Arc<dyn TraceStore>,
-    mut trace_rx: mpsc::Receiver<HttpTrace>,
+    mut events: mpsc::Receiver<TraceEvent>,
+    mut intercepts: Option<mpsc::Receiver<InterceptedRequest>>,
     backend_name: &str,
-) -> std::io::Result<()> {
+    slo: SloTracker,
+) -> std::io::Result<SloTracker> {
     // Initialize terminal
     terminal::enable_raw_mode()?;
     let mut stdout = std::io::stdout();
@@ -29,6 +41,7 @@ pub async fn run_tui(
     let mut terminal = Terminal::new(backend)?;
 
     let mut app = App::new(backend_name);
+    app.slo = slo;
 
     // Load existing traces from storage
     if let Ok(existing) = store.list_recent(1000, 0) {
@@ -36,26 +49,43 @@ pub async fn run_tui(
         app.traces = existing;
     }
 
-    let events = EventHandler::new(50); // 50ms tick
+    let input = EventHandler::new(50); // 50ms tick
 
     loop {
         // Draw UI
-        terminal.draw(|frame| ui::render(frame, &app))?;
+        let frame_area = terminal.draw(|frame| ui::render(frame, &app))?.area;
+        app.page_size = ui::trace_page_size(frame_area);
 
-        // Drain all pending traces from the channel (non-blocking)
-        while let Ok(trace) = trace_rx.try_recv() {
-            let _ = store.insert(&trace);
-            app.add_trace(trace);
+        // Drain all pending events from the channel (non-blocking)
+        while let Ok(event) = events.try_recv() {
+            match event {
+                TraceEvent::Http(mut trace) => {
+                    trace.tag_operations();
+                    let _ = store.insert(&trace);
+                    app.add_trace(*trace);
+                }
+                TraceEvent::AgentStats(stats) => app.record_agent_stats(stats),
+                TraceEvent::Protocol(trace) => {
+                    let _ = store.insert_protocol(&trace);
+                }
+            }
+        }
+        if let Some(rx) = intercepts.as_mut() {
+            while let Ok(request) = rx.try_recv() {
+                app.hold(request);
+            }
         }
 
         // Handle events
-        match events.poll()? {
+        match input.poll()? {
             Event::Key(key) => {
                 if key.kind != KeyEventKind::Press {
                     continue;
                 }
                 if app.filter_active {
                     handle_filter_key(&mut app, key.code);
+                } else if app.goto_input.is_some() {
+                    handle_goto_key(&mut app, key.code);
                 } else {
                     handle_normal_key(&mut app, key.code, key.modifiers);
                 }
@@ -63,6 +93,15 @@ pub async fn run_tui(
             Event::Tick => {}
         }
 
+        if let Some(path) = app.open_body.take() {
+            open_in_pager(&mut terminal, &path)?;
+        }
+        if std::mem::take(&mut app.edit_held)
+            && let Some(request) = app.held.front_mut()
+        {
+            app.intercept_error = edit_in_editor(&mut terminal, request)?.err();
+        }
+
         if app.should_quit {
             break;
         }
@@ -73,7 +112,49 @@ pub async fn run_tui(
     execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
     terminal.show_cursor()?;
 
-    Ok(())
+    Ok(app.slo)
+}
+
+/// Shows `path` in `$PAGER` (default `less`), suspending the UI meanwhile.
+fn open_in_pager(
+    terminal: &mut Terminal<CrosstermBackend<std::io::Stdout>>,
+    path: &str,
+) -> std::io::Result<()> {
+    terminal::disable_raw_mode()?;
+    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    let pager = std::env::var("PAGER").unwrap_or_else(|_| "less".to_string());
+    // A missing pager must not tear down the capture session.
+    let _ = std::process::Command::new(&pager).arg(path).status();
+    execute!(terminal.backend_mut(), EnterAlternateScreen)?;
+    terminal::enable_raw_mode()?;
+    terminal.clear()
+}
+
+/// Opens `request` as text in `$EDITOR` (default `vi`), suspending the UI
+/// meanwhile, and applies the saved text. The inner error says why the edit
+/// was not applied.
+fn edit_in_editor(
+    terminal: &mut Terminal<CrosstermBackend<std::io::Stdout>>,
+    request: &mut InterceptedRequest,
+) -> std::io::Result<Result<(), String>> {
+    let path = std::env::temp_dir().join(format!("phantom-intercept-{}.txt", std::process::id()));
+    std::fs::write(&path, request.to_text())?;
+    terminal::disable_raw_mode()?;
+    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    let editor = std::env::var("EDITOR").unwrap_or_else(|_| "vi".to_string());
+    let status = std::process::Command::new(&editor).arg(&path).status();
+    execute!(terminal.backend_mut(), EnterAlternateScreen)?;
+    terminal::enable_raw_mode()?;
+    terminal.clear()?;
+    let edited = match status {
+        Ok(status) if status.success() => std::fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| request.apply_text(&text)),
+        Ok(status) => Err(format!("{editor} exited with {status}")),
+        Err(e) => Err(format!("{editor}: {e}")),
+    };
+    let _ = std::fs::remove_file(&path);
+    Ok(edited)
 }
 
 fn handle_normal_key(app: &mut App, code: KeyCode, modifiers: KeyModifiers) {
@@ -84,10 +165,25 @@ fn handle_normal_key(app: &mut App, code: KeyCode, modifiers: KeyModifiers) {
         }
         KeyCode::Char('j') | KeyCode::Down => app.move_down(),
         KeyCode::Char('k') | KeyCode::Up => app.move_up(),
+        KeyCode::Char('d') if modifiers.contains(KeyModifiers::CONTROL) => {
+            app.half_page_down();
+        }
+        KeyCode::Char('u') if modifiers.contains(KeyModifiers::CONTROL) => {
+            app.half_page_up();
+        }
+        KeyCode::PageDown => app.page_down(),
+        KeyCode::PageUp => app.page_up(),
+        KeyCode::Char(':') => app.activate_goto(),
         KeyCode::Char('g') | KeyCode::Home => app.jump_top(),
         KeyCode::Char('G') | KeyCode::End => app.jump_bottom(),
         KeyCode::Tab => app.toggle_pane(),
         KeyCode::Char('/') => app.activate_filter(),
+        KeyCode::Char('o') => app.open_full_body(),
+        KeyCode::Char('p') => app.cycle_process_filter(),
+        KeyCode::Char('f') => app.forward_held(),
+        KeyCode::Char('F') => app.forward_all_held(),
+        KeyCode::Char('x') => app.drop_held(),
+        KeyCode::Char('e') => app.request_edit_held(),
         KeyCode::Esc => app.clear_filter(),
         _ => {}
     }
@@ -102,3 +198,13 @@ fn handle_filter_key(app: &mut App, code: KeyCode) {
         _ => {}
     }
 }
+
+fn handle_goto_key(app: &mut App, code: KeyCode) {
+    match code {
+        KeyCode::Esc => app.cancel_goto(),
+        KeyCode::Enter => app.apply_goto(),
+        KeyCode::Backspace => app.pop_goto_char(),
+        KeyCode::Char(c) => app.push_goto_char(c),
+        _ => {}
+    }
+}
@@ -1,9 +1,14 @@
+use std::collections::HashMap;
+
 use ratatui::Frame;
 use ratatui::layout::{Constraint, Direction, Layout, Rect};
 use ratatui::style::{Color, Modifier, Style};
 use ratatui::text::{Line, Span, Text};
 use ratatui::widgets::{Block, Borders, Cell, Paragraph, Row, Table, TableState, Wrap};
 
+use phantom_core::soap::pretty_print_xml;
+use phantom_core::trace::BodyEncoding;
+
 use crate::app::{App, Pane};
 
 pub fn render(frame: &mut Frame, app: &App) {
@@ -22,7 +27,7 @@ pub fn render(frame: &mut Frame, app: &App) {
 }
 
 fn render_status_bar(frame: &mut Frame, app: &App, area: Rect) {
-    let status = Line::from(vec![
+    let mut spans = vec![
         Span::styled(
             " phantom",
             Style::default()
@@ -36,14 +41,82 @@ fn render_status_bar(frame: &mut Frame, app: &App, area: Rect) {
         ),
         Span::raw(" | Capturing via "),
         Span::styled(&app.backend_name, Style::default().fg(Color::Yellow)),
-    ]);
+    ];
+    // Keep-alive health: mean requests per connection and opening rate.
+    let conns = app.connections.stats();
+    if conns.connections > 0 {
+        spans.push(Span::raw(" | "));
+        spans.push(Span::styled(
+            format!(
+                "Conns: {} ({:.1} req/conn, {:.1} new/s)",
+                conns.connections, conns.requests_per_connection, conns.new_connections_per_sec
+            ),
+            Style::default().fg(Color::Cyan),
+        ));
+    }
+    // In-process agents: how many are alive, and anything they lost.
+    if !app.agents.is_empty() {
+        let (live, dropped, errors) = app.agent_health();
+        let color = if dropped > 0 || errors > 0 {
+            Color::Yellow
+        } else {
+            Color::Green
+        };
+        let mut text = format!("Agents: {live}");
+        if dropped > 0 {
+            text.push_str(&format!(" ({dropped} dropped)"));
+        }
+        if errors > 0 {
+            text.push_str(&format!(" ({errors} parse errors)"));
+        }
+        spans.push(Span::raw(" | "));
+        spans.push(Span::styled(text, Style::default().fg(color)));
+    }
+    // One indicator per SLO: spec and share of error budget burned.
+    for status in app.slo.statuses() {
+        let color = if status.breached {
+            Color::Red
+        } else if status.budget_used >= 0.75 {
+            Color::Yellow
+        } else {
+            Color::Green
+        };
+        let budget = if status.budget_used.is_finite() {
+            format!("{:.0}%", status.budget_used * 100.0)
+        } else {
+            "∞".to_string()
+        };
+        spans.push(Span::raw(" | "));
+        spans.push(Span::styled(
+            format!("SLO {} {budget}", status.spec),
+            Style::default().fg(color),
+        ));
+    }
+    let status = Line::from(spans);
     frame.render_widget(
         Paragraph::new(status).style(Style::default().bg(Color::DarkGray)),
         area,
     );
 }
 
+/// Trace rows visible in the table for a terminal of size `area`: everything
+/// but the status and help bars, the filter box, the table border and header.
+pub fn trace_page_size(area: Rect) -> usize {
+    (area.height.saturating_sub(8) as usize).max(1)
+}
+
 fn render_main(frame: &mut Frame, app: &App, area: Rect) {
+    // Held requests take the top of the screen until they are dealt with.
+    let area = if app.held.is_empty() {
+        area
+    } else {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Percentage(40), Constraint::Min(0)])
+            .split(area);
+        render_intercept(frame, app, chunks[0]);
+        chunks[1]
+    };
     let chunks = Layout::default()
         .direction(Direction::Horizontal)
         .constraints([Constraint::Percentage(45), Constraint::Percentage(55)])
@@ -99,7 +172,17 @@ fn render_trace_list(frame: &mut Frame, app: &App, area: Rect) {
         .map(|(i, trace)| {
             let time = format_time(&trace.timestamp);
             let method = trace.method.to_string();
-            let url = truncate_url(&trace.url, 30);
+            // Search calls read better as "_search products match(title)"
+            // than as an opaque POST to the cluster URL.
+            let url = if let Some(op) = &trace.elasticsearch {
+                truncate_str(&op.label(), 30)
+            } else if let Some(op) = &trace.soap {
+                truncate_str(&op.label(), 30)
+            } else if let Some(op) = &trace.graphql {
+                truncate_str(&op.label(), 30)
+            } else {
+                truncate_url(&trace.url, 30)
+            };
             let status = trace.status_code.to_string();
             let dur = format!("{:.0?}", trace.duration);
 
@@ -151,7 +234,10 @@ fn render_trace_list(frame: &mut Frame, app: &App, area: Rect) {
         Block::default()
             .borders(Borders::ALL)
             .border_style(border_style)
-            .title(format!(" Traces ({}) ", filtered.len())),
+            .title(match &app.process_filter {
+                Some(process) => format!(" Traces ({}) · {process} ", filtered.len()),
+                None => format!(" Traces ({}) ", filtered.len()),
+            }),
     );
 
     let mut state = TableState::default();
@@ -198,6 +284,68 @@ fn render_trace_detail(frame: &mut Frame, app: &App, area: Rect) {
         Span::raw(" "),
         Span::raw(&trace.url),
     ]));
+    if let Some(process) = &trace.process {
+        let mut spans = vec![
+            Span::styled("Process: ", Style::default().fg(Color::DarkGray)),
+            Span::raw(process.to_string()),
+        ];
+        if let Some(tid) = trace.thread_id {
+            spans.push(Span::styled(
+                format!(" thread {tid}"),
+                Style::default().fg(Color::DarkGray),
+            ));
+        }
+        lines.push(Line::from(spans));
+    }
+    if let Some(timing) = &trace.timing {
+        lines.push(Line::from(vec![
+            Span::styled("Timing: ", Style::default().fg(Color::DarkGray)),
+            Span::raw(format!("{timing}, total {} ms", trace.duration.as_millis())),
+        ]));
+    }
+    if let Some(fault) = &trace.fault {
+        lines.push(Line::from(vec![
+            Span::styled("Fault: ", Style::default().fg(Color::DarkGray)),
+            Span::styled(fault.clone(), Style::default().fg(Color::Red)),
+        ]));
+    }
+    if let (Some(sent), Some(received)) = (trace.request_size, trace.response_size) {
+        lines.push(Line::from(vec![
+            Span::styled("Size: ", Style::default().fg(Color::DarkGray)),
+            Span::raw(format!("{sent} B sent, {received} B received")),
+        ]));
+    }
+    if let Some(tls) = &trace.tls {
+        lines.push(Line::from(vec![
+            Span::styled("TLS: ", Style::default().fg(Color::DarkGray)),
+            Span::raw(tls.to_string()),
+        ]));
+    }
+    if let Some(op) = &trace.elasticsearch {
+        lines.push(Line::from(vec![
+            Span::styled("Elasticsearch: ", Style::default().fg(Color::Magenta)),
+            Span::raw(op.label()),
+        ]));
+    }
+    if let Some(op) = &trace.soap {
+        let mut spans = vec![
+            Span::styled("SOAP: ", Style::default().fg(Color::Magenta)),
+            Span::raw(op.operation.clone()),
+        ];
+        if let Some(action) = &op.action {
+            spans.push(Span::styled(
+                format!("  ({action})"),
+                Style::default().fg(Color::DarkGray),
+            ));
+        }
+        lines.push(Line::from(spans));
+    }
+    if let Some(op) = &trace.graphql {
+        lines.push(Line::from(vec![
+            Span::styled("GraphQL: ", Style::default().fg(Color::Magenta)),
+            Span::raw(op.label()),
+        ]));
+    }
     lines.push(Line::from(""));
 
     // Request headers
@@ -212,10 +360,13 @@ fn render_trace_detail(frame: &mut Frame, app: &App, area: Rect) {
     if let Some(body) = &trace.request_body {
         lines.push(Line::from(""));
         lines.push(Line::from(Span::styled(
-            "Body:",
+            body_label(trace.request_body_encoding.as_ref()),
             Style::default().fg(Color::DarkGray),
         )));
-        append_body_lines(&mut lines, body);
+        append_body_lines(&mut lines, body, &trace.request_headers);
+        if trace.request_body_file.is_some() {
+            lines.push(overflow_hint());
+        }
     }
 
     lines.push(Line::from(""));
@@ -263,10 +414,28 @@ fn render_trace_detail(frame: &mut Frame, app: &App, area: Rect) {
     if let Some(body) = &trace.response_body {
         lines.push(Line::from(""));
         lines.push(Line::from(Span::styled(
-            "Body:",
+            body_label(trace.response_body_encoding.as_ref()),
             Style::default().fg(Color::DarkGray),
         )));
-        append_body_lines(&mut lines, body);
+        append_body_lines(&mut lines, body, &trace.response_headers);
+        if trace.response_body_file.is_some() {
+            lines.push(overflow_hint());
+        }
+    }
+
+    // Response trailers
+    if !trace.response_trailers.is_empty() {
+        lines.push(Line::from(""));
+        lines.push(Line::from(Span::styled(
+            "Trailers:",
+            Style::default().fg(Color::DarkGray),
+        )));
+        for (key, value) in &trace.response_trailers {
+            lines.push(Line::from(vec![
+                Span::styled(format!("{key}: "), Style::default().fg(Color::Yellow)),
+                Span::raw(truncate_str(value, 60)),
+            ]));
+        }
     }
 
     let detail = Paragraph::new(Text::from(lines))
@@ -275,8 +444,96 @@ fn render_trace_detail(frame: &mut Frame, app: &App, area: Rect) {
     frame.render_widget(detail, area);
 }
 
+fn render_intercept(frame: &mut Frame, app: &App, area: Rect) {
+    let Some(request) = app.held.front() else {
+        return;
+    };
+    let mut title = format!(" Intercepted (1 of {}) ", app.held.len());
+    if request.edited {
+        title.push_str("· edited ");
+    }
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Red))
+        .title(title);
+
+    let mut lines = vec![Line::from(vec![
+        Span::styled(
+            request.method.to_string(),
+            Style::default()
+                .fg(Color::Green)
+                .add_modifier(Modifier::BOLD),
+        ),
+        Span::raw(" "),
+        Span::raw(&request.url),
+    ])];
+    if let Some(error) = &app.intercept_error {
+        lines.push(Line::from(Span::styled(
+            format!("Edit not applied: {error}"),
+            Style::default().fg(Color::Red),
+        )));
+    }
+    lines.push(Line::from(""));
+    for (key, value) in &request.headers {
+        lines.push(Line::from(vec![
+            Span::styled(format!("{key}: "), Style::default().fg(Color::Yellow)),
+            Span::raw(truncate_str(value, 60)),
+        ]));
+    }
+    if !request.body.is_empty() {
+        let headers: HashMap<String, String> = request.headers.iter().cloned().collect();
+        lines.push(Line::from(""));
+        append_body_lines(&mut lines, &request.body, &headers);
+    }
+
+    let panel = Paragraph::new(Text::from(lines))
+        .block(block)
+        .wrap(Wrap { trim: false });
+    frame.render_widget(panel, area);
+}
+
+/// Heading for a body, noting a `Content-Encoding` it was decoded from.
+fn body_label(encoding: Option<&BodyEncoding>) -> String {
+    match encoding {
+        Some(e) if e.truncated => format!("Body (decoded from {}, incomplete):", e.encoding),
+        Some(e) => format!("Body (decoded from {}):", e.encoding),
+        None => "Body:".to_string(),
+    }
+}
+
+fn overflow_hint() -> Line<'static> {
+    Line::from(Span::styled(
+        "… truncated — press o to open full body",
+        Style::default()
+            .fg(Color::Yellow)
+            .add_modifier(Modifier::ITALIC),
+    ))
+}
+
 fn render_help_bar(frame: &mut Frame, app: &App, area: Rect) {
-    let help = if app.filter_active {
+    let help = if let Some(input) = &app.goto_input {
+        Line::from(vec![
+            Span::styled(format!(" :{input}"), Style::default().fg(Color::White)),
+            Span::raw("  "),
+            Span::styled("[Enter]", Style::default().fg(Color::Yellow)),
+            Span::raw("go to row  "),
+            Span::styled("[Esc]", Style::default().fg(Color::Yellow)),
+            Span::raw("cancel"),
+        ])
+    } else if !app.held.is_empty() {
+        Line::from(vec![
+            Span::styled(" [f]", Style::default().fg(Color::Yellow)),
+            Span::raw("orward  "),
+            Span::styled("[e]", Style::default().fg(Color::Yellow)),
+            Span::raw("dit  "),
+            Span::styled("[x]", Style::default().fg(Color::Yellow)),
+            Span::raw(" drop  "),
+            Span::styled("[F]", Style::default().fg(Color::Yellow)),
+            Span::raw("orward all  "),
+            Span::styled("[q]", Style::default().fg(Color::Yellow)),
+            Span::raw("uit (forwards held)"),
+        ])
+    } else if app.filter_active {
         Line::from(vec![
             Span::styled(" [Esc]", Style::default().fg(Color::Yellow)),
             Span::raw("cancel  "),
@@ -295,8 +552,16 @@ fn render_help_bar(frame: &mut Frame, app: &App, area: Rect) {
             Span::raw("navigate  "),
             Span::styled("[Tab]", Style::default().fg(Color::Yellow)),
             Span::raw("switch  "),
+            Span::styled("[PgUp/PgDn ^u/^d]", Style::default().fg(Color::Yellow)),
+            Span::raw("page  "),
             Span::styled("[g/G]", Style::default().fg(Color::Yellow)),
-            Span::raw("top/bottom"),
+            Span::raw("top/bottom  "),
+            Span::styled("[:n]", Style::default().fg(Color::Yellow)),
+            Span::raw("row  "),
+            Span::styled("[p]", Style::default().fg(Color::Yellow)),
+            Span::raw("rocess  "),
+            Span::styled("[o]", Style::default().fg(Color::Yellow)),
+            Span::raw("pen full body"),
         ])
     };
     frame.render_widget(
@@ -329,13 +594,25 @@ fn truncate_url(url: &str, max_len: usize) -> String {
 
 fn truncate_str(s: &str, max_len: usize) -> String {
     if s.len() > max_len {
-        format!("{}…", &s[..max_len - 1])
+        let mut cut = max_len - 1;
+        while !s.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        format!("{}…", &s[..cut])
     } else {
         s.to_string()
     }
 }
 
-fn append_body_lines(lines: &mut Vec<Line>, body: &[u8]) {
+fn is_xml(headers: &HashMap<String, String>, text: &str) -> bool {
+    let content_type_xml = headers
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case("content-type"))
+        .is_some_and(|(_, v)| v.to_ascii_lowercase().contains("xml"));
+    content_type_xml || text.trim_start().starts_with("<?xml")
+}
+
+fn append_body_lines(lines: &mut Vec<Line>, body: &[u8], headers: &HashMap<String, String>) {
     if let Ok(text) = std::str::from_utf8(body) {
         // Try pretty-printing JSON
         if let Ok(json) = serde_json::from_str::<serde_json::Value>(text)
@@ -349,6 +626,18 @@ fn append_body_lines(lines: &mut Vec<Line>, body: &[u8]) {
             }
             return;
         }
+        // Pretty-print XML (SOAP envelopes arrive as one long line)
+        if is_xml(headers, text)
+            && let Some(pretty) = pretty_print_xml(text)
+        {
+            for line in pretty.lines().take(30) {
+                lines.push(Line::from(Span::styled(
+                    line.to_string(),
+                    Style::default().fg(Color::White),
+                )));
+            }
+            return;
+        }
         // Plain text
         for line in text.lines().take(30) {
             lines.push(Line::from(line.to_string()));
@@ -12,6 +12,30 @@ pub enum Backend {
     /// LD_PRELOAD agent — captures HTTP + HTTPS, Linux only. No proxy config needed.
     #[cfg(target_os = "linux")]
     Ldpreload,
+    /// eBPF probes on socket syscalls, OpenSSL and Go's crypto/tls — HTTP/1 +
+    /// HTTPS, Linux only, needs root. Also captures statically linked programs.
+    #[cfg(target_os = "linux")]
+    Ebpf,
+    /// Packet capture decrypted with the command's SSLKEYLOGFILE secrets —
+    /// HTTPS over HTTP/1, Linux only, needs root or CAP_NET_RAW. Also
+    /// captures rustls, Go and certificate-pinning clients.
+    #[cfg(target_os = "linux")]
+    Keylog,
+    /// Live packet capture of plain HTTP/1 on an interface — Linux only,
+    /// needs root or CAP_NET_RAW. Sees processes phantom didn't spawn.
+    #[cfg(target_os = "linux")]
+    Pcap,
+    /// WinDivert packet capture — plain HTTP/1, or HTTPS over HTTP/1 with
+    /// --keylog-file. Windows only, needs Administrator and WinDivert.dll.
+    #[cfg(windows)]
+    Windivert,
+    /// Follows a proxy's JSON access log or Envoy tap output (--access-log)
+    /// — cross-platform, no interception. Sees service-mesh traffic.
+    Accesslog,
+    /// Receives traces from agents for other runtimes (a Java agent, a
+    /// Node.js hook) over TCP or HTTP (--ingest-addr) — cross-platform.
+    /// See docs/ingest-protocol.md.
+    Ingest,
 }
 
 #[derive(Debug, Clone, Default, ValueEnum)]
@@ -35,6 +59,24 @@ pub enum QueryFormat {
     Table,
 }
 
+/// Output format for `phantom report`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ReportFormat {
+    /// Markdown, for pasting into issues, PRs, and wikis.
+    #[default]
+    Md,
+    /// Self-contained HTML page with inline charts.
+    Html,
+}
+
+/// Output format for `phantom export`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    /// Per-endpoint latency percentiles and HdrHistogram-style percentile
+    /// distribution, as JSON (microseconds).
+    Histogram,
+}
+
 #[derive(Parser)]
 #[command(
     name = "phantom",
@@ -56,6 +98,9 @@ Typical workflows:\n\
   # Query previously captured traces:\n\
   phantom list --status 5xx --since 10m\n\
   phantom get <SPAN_ID>\n\
+\n\
+  # Render a shareable report of the last hour:\n\
+  phantom report --since 1h --format html --output report.html\n\
 \n\
   # Run as an MCP server (capture control + queries over stdio):\n\
   phantom mcp\n\
@@ -75,6 +120,12 @@ pub struct Cli {
     #[arg(short, long, global = true)]
     pub quiet: bool,
 
+    /// Store traces in one sub-directory per UTC day (<data-dir>/2024-06-01/…)
+    /// so old days can be archived or deleted wholesale. Only needed when
+    /// creating a data directory; an existing day-partitioned one is detected.
+    #[arg(long, global = true)]
+    pub partition_by_day: bool,
+
     #[command(subcommand)]
     pub command: Commands,
 }
@@ -82,7 +133,7 @@ pub struct Cli {
 #[derive(Subcommand)]
 pub enum Commands {
     /// Capture HTTP(S) traffic, optionally spawning a command to trace.
-    Run(RunArgs),
+    Run(Box<RunArgs>),
     /// List captured traces (newest first) with filters.
     List(ListArgs),
     /// Show a single trace by span ID.
@@ -93,6 +144,27 @@ pub enum Commands {
     Stats,
     /// Delete all captured traces.
     Clear(ClearArgs),
+    /// Render a shareable Markdown or HTML report of captured traffic.
+    Report(ReportArgs),
+    /// Export captured traffic for external analysis tools.
+    Export(ExportArgs),
+    /// Verify and repair the trace store; optionally compact and back it up.
+    Maintenance(MaintenanceArgs),
+    /// Verify audit logs written by `run --audit`, or create a signing key.
+    #[command(subcommand)]
+    Audit(AuditCommand),
+    /// Export or install the proxy's MITM CA certificate.
+    #[command(subcommand)]
+    Ca(CaCommand),
+    /// Serve captured responses as an offline mock HTTP server.
+    Mock(MockArgs),
+    /// Load HTTP traffic from a pcap/pcapng capture or HAR file into the store.
+    Import(ImportArgs),
+    /// Trace an already-running process by loading the agent into it.
+    #[cfg(target_os = "linux")]
+    Attach(AttachArgs),
+    /// Browse traces streamed by a remote phantom (`run --sink serve:ADDR`).
+    Connect(ConnectArgs),
     /// Run as an MCP (Model Context Protocol) server over stdio.
     ///
     /// Exposes capture control and trace queries as MCP tools for AI coding
@@ -138,6 +210,44 @@ pub enum Commands {
     (captured above the TLS layer, before encryption). No proxy config\n\
     required and no MITM certificate involved — works for any dynamically\n\
     linked process, language-agnostic (e.g. PHP's curl extension).\n\
+\n\
+  ebpf  (Linux only, root)\n\
+    Loads eBPF probes (--bpf-object) on the socket syscalls, OpenSSL's\n\
+    SSL_write/SSL_read and, in Go 1.18+ binaries (stripped ones too),\n\
+    crypto/tls.(*Conn).Write/Read of the command and the children it\n\
+    forks.  Nothing is injected, so Go and statically linked programs are\n\
+    captured too.  HTTP/1 only (Go is switched to it via GODEBUG).\n\
+\n\
+  keylog  (Linux only, root or CAP_NET_RAW)\n\
+    Runs the command with SSLKEYLOGFILE set, captures its packets and\n\
+    decrypts TLS with the secrets it logs.  Certificates are untouched, so\n\
+    rustls, Go and pinned clients work — if their TLS library honours\n\
+    SSLKEYLOGFILE.  HTTPS over HTTP/1 only.\n\
+\n\
+  pcap  (Linux only, root or CAP_NET_RAW)\n\
+    Captures packets on --interface (default: all) and parses every TCP\n\
+    connection as plain HTTP/1 — including processes phantom did not\n\
+    spawn.  The command is optional.\n\
+\n\
+  windivert  (Windows only, Administrator)\n\
+    Captures TCP packets with WinDivert (WinDivert.dll and WinDivert64.sys\n\
+    next to phantom.exe) and parses every connection as plain HTTP/1, like\n\
+    pcap.  With --keylog-file the command runs with SSLKEYLOGFILE set and\n\
+    its TLS is decrypted instead, like keylog.  The command is optional.\n\
+\n\
+  accesslog  (cross-platform)\n\
+    Follows what a proxy already logs (--access-log): Envoy or NGINX JSON\n\
+    access logs, and Envoy tap traces — a file, or a file_per_tap\n\
+    directory.  Nothing is intercepted, so service-mesh sidecars and\n\
+    ingress gateways are traced as they run.  Access-log entries carry no\n\
+    bodies; tap traces do.  The command is optional.\n\
+\n\
+  ingest  (cross-platform)\n\
+    Listens on --ingest-addr for traces sent by agents that live outside\n\
+    this repository — a Java agent, a Node.js hook — in the ldpreload\n\
+    agent's message format: JSON lines over TCP, or HTTP POST /v1/traces.\n\
+    The command is optional; it gets the address in PHANTOM_INGEST_ADDR.\n\
+    See docs/ingest-protocol.md.\n\
 \n\
 ━━━ OUTPUT MODES ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━\n\
 \n\
@@ -150,6 +260,7 @@ pub enum Commands {
   JSONL record schema (all fields always present unless marked optional):\n\
     trace_id                 string   W3C-compatible 128-bit trace ID (hex, 32 chars)\n\
     span_id                  string   64-bit span ID (hex, 16 chars)\n\
+    parent_span_id           string?  Calling span (hex); set on stitched hops\n\
     timestamp_ms             number   Unix epoch milliseconds — request start time\n\
     duration_ms              number   Round-trip latency in milliseconds\n\
     method                   string   HTTP verb: \"GET\", \"POST\", \"PUT\", \"DELETE\", …\n\
@@ -165,7 +276,22 @@ pub enum Commands {
     request_body_truncated   bool?    Present (true) when --max-body truncated the body\n\
     response_body_truncated  bool?    Present (true) when --max-body truncated the body\n\
     source_addr              string?  Client socket address, e.g. \"127.0.0.1:54321\"\n\
-    dest_addr                string?  Server socket address, e.g. \"93.184.216.34:443\"",
+    dest_addr                string?  Server socket address, e.g. \"93.184.216.34:443\"\n\
+    elasticsearch            object?  ES/OpenSearch call: api, index, query_summary\n\
+    soap                     object?  SOAP call: operation, action\n\
+    graphql                  object?  GraphQL call: type, name\n\
+\n\
+  Non-HTTP traces from the ldpreload and ingest agents are written as\n\
+  separate lines tagged with \"kind\" (e.g. \"thrift\": method, seq_id,\n\
+  message_type, protocol, framed, timestamp_ms, duration_ms; \"postgres\":\n\
+  query, command, rows, error, database, user, timestamp_ms, duration_ms;\n\
+  \"redis\": command, key, args, arg_count, reply_type, error, timestamp_ms,\n\
+  duration_ms;\n\
+  \"kafka\": api, api_version, correlation_id, client_id, topics,\n\
+  error_code, timestamp_ms, duration_ms;\n\
+  \"websocket\": url, direction, opcode, fin, payload_len, preview,\n\
+  close_code, connection_id, timestamp_ms; \"sse\": url, event, id, data,\n\
+  data_bytes, connection_id, timestamp_ms, elapsed_ms).",
     after_long_help = "EXAMPLES\n\
 \n\
   # Trace a Node.js app — HTTP + HTTPS captured, zero app changes:\n\
@@ -179,22 +305,44 @@ pub enum Commands {
 \n\
   # Filter errors with jq:\n\
   phantom run --output jsonl -- node app.js | jq 'select(.status_code >= 400)'\n\
+\n\
+  # Browse in the TUI while exporting to a collector and logging 5xx to a file:\n\
+  phantom run --sink otlp:http://localhost:4318 \\\n\
+          --sink 'jsonl:errors.jsonl?status=5xx' -- node app.js\n\
 \n\
   # LD_PRELOAD mode (Linux only):\n\
   cargo build -p phantom-agent\n\
   phantom run --backend ldpreload \\\n\
           --agent-lib ./target/debug/libphantom_agent.so \\\n\
           -- curl http://api.example.com/v1/users\n\
+\n\
+  # Hermetic integration tests: record on the first run, replay afterwards:\n\
+  phantom run --output jsonl --replay tests/recording -- npm test\n\
 \n\
   # Run as a Docker Compose sidecar, tracing a container you don't spawn:\n\
   phantom run --bind 0.0.0.0 --output jsonl --data-dir /data\n\
   # then, in the target container:\n\
   HTTP_PROXY=http://phantom:8080 HTTPS_PROXY=http://phantom:8080 your-app\n\
   # trust /data/ca.pem in the target container for HTTPS. See\n\
-  # examples/docker-sidecar/."
+  # examples/docker-sidecar/.\n\
+\n\
+  # Capture inside a Kubernetes pod and browse it locally (see\n\
+  # examples/kubernetes/):\n\
+  phantom run --backend pcap --output jsonl --sink serve:0.0.0.0:9464\n\
+  kubectl port-forward pod/my-app 9464 &\n\
+  phantom connect 127.0.0.1:9464\n\
+\n\
+  # Follow an Envoy sidecar's JSON access log (no interception):\n\
+  phantom run --backend accesslog --access-log /var/log/envoy/access.json\n\
+\n\
+  # Take traces from an agent of your own, e.g. a Java agent:\n\
+  phantom run --backend ingest -- java -javaagent:my-agent.jar -jar app.jar"
 )]
 pub struct RunArgs {
-    /// Capture backend: 'proxy' (MITM, cross-platform) or 'ldpreload' (Linux, HTTP + HTTPS).
+    /// Capture backend: 'proxy' (MITM, cross-platform), 'ldpreload' or 'ebpf'
+    /// (Linux, HTTP + HTTPS), 'keylog' (Linux, HTTPS), 'pcap' (Linux, HTTP),
+    /// 'windivert' (Windows, HTTP or HTTPS), 'accesslog' (proxy logs, cross-platform), 'ingest' (third-party
+    /// agents, cross-platform).
     #[arg(short, long, value_enum, default_value = "proxy")]
     pub backend: Backend,
 
@@ -214,15 +362,82 @@ pub struct RunArgs {
     /// phantom as a Docker Compose sidecar service (`phantom`) next to a target
     /// container configured with HTTP_PROXY=http://phantom:8080. There is no
     /// authentication on the proxy port: only bind 0.0.0.0 on a trusted/private
-    /// network (an internal Docker network, not anything internet-facing).
-    #[arg(long, default_value = "127.0.0.1", value_name = "ADDR")]
+    /// network (an internal Docker network, not anything internet-facing),
+    /// or restrict clients with --allow-client.
+    #[arg(
+        long,
+        visible_alias = "listen-addr",
+        default_value = "127.0.0.1",
+        value_name = "ADDR"
+    )]
     pub bind: IpAddr,
 
+    /// Only serve proxy clients from this network (repeatable), e.g.
+    /// 192.168.1.0/24 for devices on the LAN or 10.0.0.7 for one phone.
+    /// Other clients get 403 (SOCKS/transparent: disconnected) and are not
+    /// traced. Loopback clients are always allowed.
+    #[arg(long, value_name = "CIDR")]
+    pub allow_client: Vec<String>,
+
     /// Disable TLS certificate verification for connections to backend servers.
     /// Use when tracing apps that talk to servers with self-signed certificates.
     #[arg(long, default_value = "false")]
     pub insecure: bool,
 
+    /// Keep HTTP/3 clients on TCP, where phantom can capture them: the
+    /// proxy strips `h3` Alt-Svc advertisements and the LD_PRELOAD agent
+    /// refuses QUIC handshakes. To capture HTTP/3 itself, see --h3-port.
+    #[arg(long)]
+    pub block_quic: bool,
+
+    /// Sign intercepted HTTPS with this existing CA certificate (PEM)
+    /// instead of phantom's own CA in the data directory. Needs --ca-key.
+    #[arg(long, value_name = "PATH", requires = "ca_key")]
+    pub ca_cert: Option<PathBuf>,
+
+    /// Private key (PEM, PKCS#8 or SEC1) of the --ca-cert certificate.
+    #[arg(long, value_name = "PATH", requires = "ca_cert")]
+    pub ca_key: Option<PathBuf>,
+
+    /// Also accept SOCKS5 clients (ALL_PROXY=socks5h://HOST:PORT) on this
+    /// port, bound to the same --bind address. Captured like proxy traffic.
+    #[arg(long, value_name = "PORT")]
+    pub socks_port: Option<u16>,
+
+    /// Transparent mode (Linux): also accept connections iptables REDIRECTs
+    /// to this port and intercept them at their original destination, for
+    /// targets that ignore proxy settings. Exclude phantom's own traffic
+    /// from the REDIRECT rule (e.g. `-m owner ! --uid-owner`) or it loops.
+    #[arg(long, value_name = "PORT")]
+    pub transparent: Option<u16>,
+
+    /// Also accept HTTP/3 (QUIC) clients on this UDP port, bound to the same
+    /// --bind address, e.g. with iptables REDIRECTing udp/443 to it. Requests
+    /// go upstream over TCP to their :authority and are captured like proxy
+    /// traffic, as HTTP/3.0. Clients must trust the MITM CA.
+    #[arg(long, value_name = "PORT")]
+    pub h3_port: Option<u16>,
+
+    /// Forward the proxy's outbound connections through this HTTP proxy
+    /// (http://[user:pass@]host:port). Defaults to HTTPS_PROXY / HTTP_PROXY
+    /// from phantom's environment; hosts in NO_PROXY are reached directly.
+    #[arg(long, value_name = "URL")]
+    pub upstream_proxy: Option<String>,
+
+    /// Present a client certificate (mutual TLS) when the proxy connects to
+    /// HOST (repeatable, proxy backend only). SPEC: HOST=CERT[,KEY] with PEM
+    /// files; without KEY the key is read from CERT. `*.example.com` also
+    /// matches subdomains; the first matching rule applies.
+    #[arg(long, value_name = "SPEC")]
+    pub client_cert: Vec<String>,
+
+    /// Hold matching requests in the TUI until you forward, edit or drop
+    /// them (repeatable, proxy backend with TUI output only). PATTERN:
+    /// `[METHOD] URL_PATTERN` (`*` matches any text), a bare METHOD, or `*`
+    /// for every request, e.g. `POST /api/*/orders`.
+    #[arg(long, value_name = "PATTERN")]
+    pub intercept: Vec<String>,
+
     /// Path to libphantom_agent.so  [required for --backend ldpreload]
     ///
     /// Build with: cargo build -p phantom-agent
@@ -230,6 +445,44 @@ pub struct RunArgs {
     #[arg(long, value_name = "PATH")]
     pub agent_lib: Option<PathBuf>,
 
+    /// Compiled eBPF probes  [required for --backend ebpf]
+    ///
+    /// Build with: clang -O2 -g -target bpf -D__TARGET_ARCH_x86 \
+    ///   -c crates/phantom-capture/bpf/phantom.bpf.c -o phantom.bpf.o
+    #[arg(long, value_name = "PATH")]
+    pub bpf_object: Option<PathBuf>,
+
+    /// Attach the eBPF OpenSSL probes to this libssl or binary, or the Go
+    /// crypto/tls probes to this Go binary (repeatable, ebpf backend only).
+    /// Defaults to the system libssl; the traced command's own binary is
+    /// always tried too.
+    #[arg(long, value_name = "PATH")]
+    pub ssl_lib: Vec<PathBuf>,
+
+    /// Where the traced command logs its TLS secrets (keylog and windivert
+    /// backends). The keylog backend defaults to a temporary file deleted
+    /// afterwards; a file given here is kept, e.g. to open the traffic in
+    /// Wireshark. The windivert backend decrypts TLS only when one is given.
+    #[arg(long, value_name = "FILE")]
+    pub keylog_file: Option<PathBuf>,
+
+    /// Capture packets on this network interface only (keylog and pcap
+    /// backends), e.g. `lo`. Defaults to every interface.
+    #[arg(long, value_name = "NAME")]
+    pub interface: Option<String>,
+
+    /// The log file or Envoy `file_per_tap` directory to follow (accesslog
+    /// backend only). A file is read from its current end, and again from
+    /// the start when it is rotated.
+    #[arg(long, value_name = "PATH")]
+    pub access_log: Option<PathBuf>,
+
+    /// Where to listen for agent messages (ingest backend only), as
+    /// HOST:PORT. No authentication: keep it on loopback or a private
+    /// network.
+    #[arg(long, value_name = "HOST:PORT", default_value = "127.0.0.1:9465")]
+    pub ingest_addr: String,
+
     /// Inject faults into proxied requests (proxy backend only).
     ///
     /// SPEC formats:
@@ -239,13 +492,102 @@ pub struct RunArgs {
     ///   error:503                return HTTP 503 for all requests
     ///   error:503:0.5            return HTTP 503 with 50% probability
     ///   error:500:0.1:/api       10% chance of HTTP 500 on URLs containing "/api"
+    ///   reset:0.1                drop 10% of connections
+    ///   truncate:/download       cut response bodies off halfway
+    ///   timeout:30s:0.05         hold 5% of requests 30 s, then return 504
     ///
-    /// Rules are applied in order; delays and errors can be combined.
+    /// Rules are applied in order and can be combined; injected faults are
+    /// recorded in each trace's `fault` field.
     /// Repeat the flag to add multiple rules:
     ///   --fault delay:50ms --fault error:500:0.1
     #[arg(long, value_name = "SPEC")]
     pub fault: Vec<String>,
 
+    /// Simulate a slow network per host (proxy backend only).
+    ///
+    /// SPEC: comma-separated settings, optionally followed by @HOST:
+    ///   latency=200ms                   extra latency on every request
+    ///   rate=64KB/s                     cap request and response bodies
+    ///   down=1MB/s,up=128kbit/s         separate caps per direction
+    ///   latency=1s@api.example.com      only for this host
+    ///   rate=16KB/s@*.example.com       and its subdomains
+    ///
+    /// The first rule matching a request's host applies; repeat the flag
+    /// for several hosts. Traced durations include the throttling.
+    #[arg(long, value_name = "SPEC")]
+    pub throttle: Vec<String>,
+
+    /// Rewrite matching requests and responses in flight (proxy backend
+    /// only): set or remove headers, send to another origin (map_remote) or
+    /// answer from a local file (map_local). FILE is a TOML list of
+    /// [[rule]] tables, applied in order before any --fault rules.
+    #[arg(long, value_name = "FILE")]
+    pub rewrite: Option<PathBuf>,
+
+    /// Capture less from some hosts (proxy backend only). FILE is a TOML
+    /// list of [[host]] tables, first match wins: `match = "*.example.com"`
+    /// plus `skip` (no trace), `skip_bodies`, `max_body_size` (bytes) or
+    /// `no_tls_intercept` (tunnel HTTPS undecrypted, e.g. for pinned apps).
+    #[arg(long, value_name = "FILE")]
+    pub capture_policy: Option<PathBuf>,
+
+    /// Record-then-replay (proxy backend only): answer requests recorded in
+    /// DIR (a phantom data directory) from it, forward the rest and add them
+    /// to DIR. The first run records everything; later runs only reach the
+    /// network for new requests. Requests match on method, full URL and
+    /// body; responses carry `x-phantom-replay: hit|miss`, traces `replay`.
+    #[arg(long, value_name = "DIR")]
+    pub replay: Option<PathBuf>,
+
+    /// Track a service-level objective's error budget over the session.
+    ///
+    /// SPEC formats:
+    ///   latency:300ms:99%          99% of requests complete within 300 ms
+    ///   latency:1s:99%:/api/*      same, for URLs matching "/api/*" (* = any text)
+    ///   errors:1%                  fewer than 1% of requests return 5xx
+    ///   errors:0.5%:/checkout      error budget for URLs containing "/checkout"
+    ///
+    /// Budget burn is shown in the TUI status bar and in the JSONL exit
+    /// summary on stderr. Repeat the flag to track several SLOs.
+    #[arg(long, value_name = "SPEC")]
+    pub slo: Vec<String>,
+
+    /// Check mode: exit with status 3 if any --slo objective is breached
+    /// (a failing child's own exit code takes precedence).
+    #[arg(long, requires = "slo")]
+    pub check: bool,
+
+    /// Append every captured trace to a hash-chained audit log,
+    /// <data-dir>/audit.jsonl, so the capture can later be proven
+    /// untampered with `phantom audit verify`.
+    #[arg(long)]
+    pub audit: bool,
+
+    /// Sign audit log entries with this Ed25519 key (implies --audit).
+    /// Create one with `phantom audit keygen <PATH>`.
+    #[arg(long, value_name = "PATH")]
+    pub audit_key: Option<PathBuf>,
+
+    /// Also send captured traces to another destination, alongside the
+    /// data directory the TUI and query commands read.
+    ///
+    /// SPEC formats:
+    ///   jsonl:PATH               append one JSON object per line to PATH
+    ///   otlp:URL                 export spans to an OpenTelemetry collector
+    ///                            (OTLP/HTTP JSON, e.g. http://localhost:4318)
+    ///   store:DIR                insert into a second phantom data directory
+    ///   serve:ADDR:PORT          stream to `phantom connect` clients over TCP
+    ///                            (no authentication; e.g. 0.0.0.0:9464 in a
+    ///                            pod, reached through kubectl port-forward)
+    ///
+    /// Append ?FILTER&FILTER… to send only matching traces, with filters
+    /// method=POST (repeatable), status=5xx and url=/api:
+    ///   --sink 'jsonl:errors.jsonl?status=5xx'
+    ///
+    /// Repeat the flag to add several sinks, each with its own filter.
+    #[arg(long, value_name = "SPEC")]
+    pub sink: Vec<String>,
+
     /// Truncate request/response bodies to N bytes in JSONL output
     /// (0 = unlimited). Truncated records carry `*_body_truncated: true`
     /// and the original size in `*_body_bytes`.
@@ -286,7 +628,8 @@ pub struct FilterArgs {
     #[arg(long, value_name = "TIME")]
     pub until: Option<String>,
 
-    /// Only spans belonging to this 32-char hex trace ID.
+    /// Only spans belonging to this 32-char hex trace ID, including downstream
+    /// hops captured inside its time window (stitched into the trace tree).
     #[arg(long, value_name = "HEX32")]
     pub trace_id: Option<String>,
 
@@ -362,6 +705,308 @@ pub struct GetArgs {
     pub headers_only: bool,
 }
 
+#[derive(Args)]
+#[command(after_long_help = "EXAMPLES\n\
+\n\
+  # Markdown summary of everything captured, on stdout:\n\
+  phantom report\n\
+\n\
+  # HTML report of the last 30 minutes, top 20 per table:\n\
+  phantom report --since 30m --top 20 --format html --output report.html")]
+pub struct ReportArgs {
+    /// Report format.
+    #[arg(long, value_enum, default_value = "md")]
+    pub format: ReportFormat,
+
+    /// Only traces newer than this: RFC3339 or a relative duration ago.
+    #[arg(long, value_name = "TIME")]
+    pub since: Option<String>,
+
+    /// Only traces older than this: RFC3339 or a relative duration ago.
+    #[arg(long, value_name = "TIME")]
+    pub until: Option<String>,
+
+    /// Maximum number of (most recent) traces to include.
+    #[arg(long, default_value = "10000")]
+    pub limit: usize,
+
+    /// Rows per ranked table (endpoints, errors, slowest requests).
+    #[arg(long, value_name = "N", default_value = "10")]
+    pub top: usize,
+
+    /// Write the report to this file instead of stdout.
+    #[arg(short, long, value_name = "PATH")]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Args)]
+#[command(
+    after_long_help = "Latency values are microseconds. Endpoints are grouped as in\n\
+`phantom report` (METHOD + host/path, ID-like segments collapsed to :id).\n\
+\n\
+EXAMPLES\n\
+\n\
+  # Latency percentiles per endpoint for the last hour:\n\
+  phantom export --format histogram --since 1h --output latency.json\n\
+\n\
+  # p99 per endpoint with jq:\n\
+  phantom export --format histogram | jq '.endpoints[] | [.endpoint, .percentiles.p99]'"
+)]
+pub struct ExportArgs {
+    /// Export format.
+    #[arg(long, value_enum)]
+    pub format: ExportFormat,
+
+    /// Only traces newer than this: RFC3339 or a relative duration ago.
+    #[arg(long, value_name = "TIME")]
+    pub since: Option<String>,
+
+    /// Only traces older than this: RFC3339 or a relative duration ago.
+    #[arg(long, value_name = "TIME")]
+    pub until: Option<String>,
+
+    /// Maximum number of (most recent) traces to include.
+    #[arg(long, default_value = "10000")]
+    pub limit: usize,
+
+    /// Write the export to this file instead of stdout.
+    #[arg(short, long, value_name = "PATH")]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Args)]
+#[command(
+    after_long_help = "Always checks that every index entry points at a stored trace and\n\
+every trace is indexed, removing dangling entries and restoring missing\n\
+ones. Prints a JSON summary on stdout.\n\
+\n\
+EXAMPLES\n\
+\n\
+  # Consistency check and repair only:\n\
+  phantom maintenance\n\
+\n\
+  # Reclaim space and snapshot the store (restore with --data-dir):\n\
+  phantom maintenance --compact --backup ~/phantom-backup"
+)]
+pub struct MaintenanceArgs {
+    /// Run a major compaction after the consistency check.
+    #[arg(long)]
+    pub compact: bool,
+
+    /// Copy the store to this directory (must not exist or be empty).
+    #[arg(long, value_name = "DIR")]
+    pub backup: Option<PathBuf>,
+}
+
+#[derive(Args)]
+#[command(
+    after_long_help = "Requests are matched on method and path + query (the host is ignored);\n\
+an unknown query string falls back to a recording of the same path. Traces\n\
+sharing a key are replayed in capture order, the last one repeating.\n\
+Unmatched requests get 404. Responses carry `x-phantom-mock: hit|miss`.\n\
+The store is only read at startup, so `phantom run` can capture meanwhile.\n\
+\n\
+EXAMPLES\n\
+\n\
+  # Capture once, then point the tests at the stub instead of the API:\n\
+  phantom run --output jsonl -- npm run fetch-fixtures\n\
+  phantom mock --port 8081 --url api.example.com &\n\
+  API_BASE_URL=http://127.0.0.1:8081 npm test\n\
+\n\
+  # Distinguish POSTs by their body (e.g. GraphQL or search queries):\n\
+  phantom mock --match-body --since 1h"
+)]
+pub struct MockArgs {
+    /// TCP port the mock server listens on.
+    #[arg(short, long, default_value = "8081")]
+    pub port: u16,
+
+    /// IP address the mock server binds to.
+    #[arg(long, default_value = "127.0.0.1", value_name = "ADDR")]
+    pub bind: IpAddr,
+
+    /// Only serve traces whose URL contains this substring (case-insensitive).
+    #[arg(long, value_name = "SUBSTR")]
+    pub url: Option<String>,
+
+    /// Only traces newer than this: RFC3339 or a relative duration ago.
+    #[arg(long, value_name = "TIME")]
+    pub since: Option<String>,
+
+    /// Only traces older than this: RFC3339 or a relative duration ago.
+    #[arg(long, value_name = "TIME")]
+    pub until: Option<String>,
+
+    /// Maximum number of (most recent) traces to load.
+    #[arg(long, default_value = "10000")]
+    pub limit: usize,
+
+    /// Also match on a hash of the request body, so requests to the same
+    /// path with different payloads get their own recorded responses.
+    #[arg(long)]
+    pub match_body: bool,
+}
+
+#[derive(Args)]
+#[command(
+    after_long_help = "Runs the packets through the same TCP reassembly and HTTP/1 parsing as\n\
+`run --backend pcap`, timed by the packets' own timestamps, and stores the\n\
+resulting traces. Reads pcap and pcapng with Ethernet, Linux cooked\n\
+(`-i any`), loopback or raw IP framing. Only connections whose SYN was\n\
+captured are followed; HTTP/2 and other protocols are skipped.\n\
+\n\
+HAR files (\"Save all as HAR\" in browser developer tools; detected by the\n\
+.har extension or JSON content) are converted entry by entry, HTTP/2 and\n\
+HTTPS included, keeping the browser's timings. Entries with methods\n\
+phantom does not model (e.g. PROPFIND) are skipped.\n\
+\n\
+EXAMPLES\n\
+\n\
+  # Capture on a server, then browse the traffic locally:\n\
+  sudo tcpdump -i any -s 0 -w api.pcap 'tcp port 8080'\n\
+  phantom import api.pcap\n\
+  phantom list --status 5xx\n\
+\n\
+  # HTTPS, with the secrets the client logged to SSLKEYLOGFILE:\n\
+  phantom import tls.pcapng --keylog-file keys.log\n\
+\n\
+  # A browser session exported from the Network tab:\n\
+  phantom import session.har\n\
+  phantom search /api/"
+)]
+pub struct ImportArgs {
+    /// Capture file to read (pcap, pcapng or HAR).
+    pub file: PathBuf,
+
+    /// Decrypt TLS connections with the secrets in this SSLKEYLOGFILE
+    /// (pcap/pcapng only). Plain HTTP connections are then skipped.
+    #[arg(long, value_name = "FILE")]
+    pub keylog_file: Option<PathBuf>,
+}
+
+#[cfg(target_os = "linux")]
+#[derive(Args)]
+#[command(
+    after_long_help = "Loads the ldpreload agent into the process with ptrace and dlopen(), then\n\
+redirects the process's libc socket calls to it. Plain HTTP and the other\n\
+protocols the agent decodes are captured from then on, on connections\n\
+opened before or after attaching. HTTPS is not: libssl and other libraries\n\
+the process loaded itself stay unhooked, as do libraries it loads later.\n\
+Restart the process under `run --backend ldpreload` to capture those.\n\
+\n\
+Needs root (CAP_SYS_PTRACE), or the process's own user with\n\
+/proc/sys/kernel/yama/ptrace_scope at 0. Linux x86_64 only; processes in\n\
+another mount namespace (containers) are refused. The agent stays loaded\n\
+after phantom exits, dropping what it captures; attaching again resumes\n\
+the stream.\n\
+\n\
+EXAMPLES\n\
+\n\
+  # Watch a running server in the TUI:\n\
+  sudo phantom attach $(pgrep -f my-server) --agent-lib ./target/debug/libphantom_agent.so\n\
+\n\
+  # Stream its traffic as JSONL until it exits (or Ctrl-C):\n\
+  sudo phantom attach 4242 --agent-lib ./libphantom_agent.so -o jsonl"
+)]
+pub struct AttachArgs {
+    /// PID of the process to trace.
+    pub pid: u32,
+
+    /// Path to libphantom_agent.so (build with: cargo build -p phantom-agent).
+    #[arg(long, value_name = "PATH")]
+    pub agent_lib: PathBuf,
+
+    /// Output mode: 'tui' opens the interactive UI; 'jsonl' streams one trace
+    /// per line to stdout and exits when the process does.
+    #[arg(short, long, value_enum, default_value = "tui")]
+    pub output: OutputMode,
+
+    /// Truncate request/response bodies to N bytes in JSONL output
+    /// (0 = unlimited).
+    #[arg(long, value_name = "N", default_value = "0")]
+    pub max_body: usize,
+
+    /// Omit request/response bodies from JSONL output entirely.
+    #[arg(long)]
+    pub headers_only: bool,
+}
+
+#[derive(Args)]
+#[command(
+    after_long_help = "Connects to a phantom started with `--sink serve:ADDR:PORT` and shows the\n\
+traces it captures, stored in the local data directory as if captured\n\
+here. A dropped connection is retried every second until Ctrl-C; traces\n\
+captured while disconnected are not sent again. Only HTTP traces are\n\
+streamed.\n\
+\n\
+EXAMPLES\n\
+\n\
+  # A phantom sidecar or debug container in a pod (see examples/kubernetes/):\n\
+  kubectl port-forward pod/my-app 9464 &\n\
+  phantom connect 127.0.0.1:9464\n\
+\n\
+  # A remote host, over SSH:\n\
+  ssh -N -L 9464:127.0.0.1:9464 build-box &\n\
+  phantom connect 127.0.0.1:9464 -o jsonl | jq 'select(.status_code >= 500)'"
+)]
+pub struct ConnectArgs {
+    /// Address of the remote phantom's serve sink, as HOST:PORT.
+    #[arg(value_name = "HOST:PORT")]
+    pub addr: String,
+
+    /// Output mode: 'tui' opens the interactive UI; 'jsonl' streams one trace
+    /// per line to stdout until Ctrl-C.
+    #[arg(short, long, value_enum, default_value = "tui")]
+    pub output: OutputMode,
+
+    /// Truncate request/response bodies to N bytes in JSONL output
+    /// (0 = unlimited).
+    #[arg(long, value_name = "N", default_value = "0")]
+    pub max_body: usize,
+
+    /// Omit request/response bodies from JSONL output entirely.
+    #[arg(long)]
+    pub headers_only: bool,
+}
+
+#[derive(Subcommand)]
+pub enum AuditCommand {
+    /// Check every hash link and signature of an audit log. Prints a JSON
+    /// summary; exits 1 if the log was tampered with.
+    Verify {
+        /// Audit log to check (default: <data-dir>/audit.jsonl).
+        path: Option<PathBuf>,
+
+        /// Require entries to be signed by this hex Ed25519 public key
+        /// (as printed by `phantom audit keygen`).
+        #[arg(long, value_name = "HEX")]
+        public_key: Option<String>,
+    },
+    /// Create an Ed25519 signing key for `run --audit-key` and print its
+    /// public key.
+    Keygen {
+        /// Where to write the private key (must not exist).
+        path: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum CaCommand {
+    /// Print the MITM CA certificate (PEM) that `run` and `mcp` sign HTTPS
+    /// with, creating it in the data directory on first use. It stays the
+    /// same across runs, so it only has to be trusted once.
+    Export {
+        /// Write the certificate to this file instead of stdout.
+        #[arg(short, long, value_name = "PATH")]
+        out: Option<PathBuf>,
+
+        /// Also add it to the system trust store (`update-ca-certificates`,
+        /// `update-ca-trust` or the macOS System keychain); usually needs root.
+        #[arg(long)]
+        install: bool,
+    },
+}
+
 #[derive(Args)]
 pub struct ClearArgs {
     /// Confirm deletion (required; refuses to run without it).
@@ -0,0 +1,47 @@
+use std::path::Path;
+
+use phantom_core::error::StorageError;
+use phantom_storage::{generate_audit_key, verify_audit_log};
+
+/// Returns `false` (exit code 1) when the log fails verification.
+pub fn verify(path: &Path, public_key: Option<&str>) -> anyhow::Result<bool> {
+    match verify_audit_log(path, public_key) {
+        Ok(result) => {
+            println!(
+                "{}",
+                serde_json::json!({
+                    "ok": true,
+                    "path": path.display().to_string(),
+                    "entries": result.entries,
+                    "head": result.head,
+                    "public_key": result.public_key,
+                })
+            );
+            Ok(true)
+        }
+        Err(StorageError::Integrity(reason)) => {
+            println!(
+                "{}",
+                serde_json::json!({
+                    "ok": false,
+                    "path": path.display().to_string(),
+                    "error": reason,
+                })
+            );
+            Ok(false)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub fn keygen(path: &Path, quiet: bool) -> anyhow::Result<()> {
+    let public_key = generate_audit_key(path)?;
+    if !quiet {
+        eprintln!(
+            "phantom: wrote signing key to {}; keep it private and pin the public key below",
+            path.display()
+        );
+    }
+    println!("{public_key}");
+    Ok(())
+}
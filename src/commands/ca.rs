@@ -0,0 +1,104 @@
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::Context;
+use phantom_capture::ProxyCa;
+use phantom_capture::ca::CA_CERT_FILE;
+
+/// System trust store directories and the command that rebuilds the bundle
+/// from them, tried in order.
+const SYSTEM_TRUST_STORES: &[(&str, &str, &[&str])] = &[
+    // Debian, Ubuntu, Alpine
+    (
+        "/usr/local/share/ca-certificates",
+        "phantom-ca.crt",
+        &["update-ca-certificates"],
+    ),
+    // Fedora, RHEL, CentOS
+    (
+        "/etc/pki/ca-trust/source/anchors",
+        "phantom-ca.pem",
+        &["update-ca-trust", "extract"],
+    ),
+    // Arch, openSUSE
+    (
+        "/etc/ca-certificates/trust-source/anchors",
+        "phantom-ca.pem",
+        &["trust", "extract-compat"],
+    ),
+];
+
+/// Prints the proxy's CA certificate (creating the CA on first use), or
+/// writes it to `out`; with `install`, also adds it to the system trust store.
+pub fn export(
+    data_dir: &Path,
+    out: Option<&Path>,
+    install: bool,
+    quiet: bool,
+) -> anyhow::Result<()> {
+    let ca = ProxyCa::load_or_create(data_dir).map_err(|e| anyhow::anyhow!("{e}"))?;
+    match out {
+        Some(path) => std::fs::write(path, ca.cert_pem())
+            .with_context(|| format!("failed to write {}", path.display()))?,
+        None => print!("{}", ca.cert_pem()),
+    }
+    if install {
+        install_system(data_dir, ca.cert_pem(), quiet)?;
+    }
+    if !quiet {
+        let path = out.map_or_else(|| data_dir.join(CA_CERT_FILE), Path::to_path_buf);
+        let path = path.display();
+        eprintln!("phantom: CA certificate at {path}; per-tool trust:");
+        eprintln!("  curl:    curl --cacert {path} / CURL_CA_BUNDLE={path}");
+        eprintln!("  Node.js: NODE_EXTRA_CA_CERTS={path}");
+        eprintln!("  Python:  REQUESTS_CA_BUNDLE={path} / SSL_CERT_FILE={path}");
+        eprintln!(
+            "  Java:    keytool -importcert -alias phantom -file {path} -keystore <truststore>"
+        );
+    }
+    Ok(())
+}
+
+fn install_system(data_dir: &Path, pem: &str, quiet: bool) -> anyhow::Result<()> {
+    if cfg!(target_os = "macos") {
+        let path = data_dir.join(CA_CERT_FILE);
+        let path = path.to_string_lossy();
+        return run(&[
+            "security",
+            "add-trusted-cert",
+            "-d",
+            "-r",
+            "trustRoot",
+            "-k",
+            "/Library/Keychains/System.keychain",
+            &path,
+        ]);
+    }
+    let Some((dir, file, refresh)) = SYSTEM_TRUST_STORES
+        .iter()
+        .find(|(dir, _, _)| Path::new(dir).is_dir())
+    else {
+        anyhow::bail!("no known system trust store found; add the certificate manually");
+    };
+    let target = Path::new(dir).join(file);
+    std::fs::write(&target, pem).with_context(|| {
+        format!(
+            "failed to write {} (installing needs root)",
+            target.display()
+        )
+    })?;
+    run(refresh)?;
+    if !quiet {
+        eprintln!("phantom: installed CA certificate as {}", target.display());
+    }
+    Ok(())
+}
+
+fn run(argv: &[&str]) -> anyhow::Result<()> {
+    let status = Command::new(argv[0])
+        .args(&argv[1..])
+        .status()
+        .with_context(|| format!("failed to run {}", argv[0]))?;
+    anyhow::ensure!(status.success(), "{} failed ({status})", argv.join(" "));
+    Ok(())
+}
@@ -0,0 +1,34 @@
+use phantom_core::histogram::HistogramExport;
+use phantom_core::query::TraceQuery;
+use phantom_core::storage::TraceStore;
+
+use crate::cli::{ExportArgs, ExportFormat};
+use crate::commands::query::parse_time;
+
+pub fn export(store: &dyn TraceStore, args: ExportArgs, quiet: bool) -> anyhow::Result<()> {
+    let query = TraceQuery {
+        since: args.since.as_deref().map(parse_time).transpose()?,
+        until: args.until.as_deref().map(parse_time).transpose()?,
+        limit: args.limit,
+        ..TraceQuery::default()
+    };
+    let traces = store.query(&query)?;
+    let mut rendered = match args.format {
+        ExportFormat::Histogram => serde_json::to_string_pretty(&HistogramExport::build(&traces))?,
+    };
+    rendered.push('\n');
+    match args.output {
+        Some(path) => {
+            std::fs::write(&path, rendered)?;
+            if !quiet {
+                eprintln!(
+                    "phantom: exported {} traces to {}",
+                    traces.len(),
+                    path.display()
+                );
+            }
+        }
+        None => print!("{rendered}"),
+    }
+    Ok(())
+}
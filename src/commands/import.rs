@@ -0,0 +1,76 @@
+use std::io::Read;
+use std::path::Path;
+
+use phantom_capture::{CaptureFile, HarFile};
+use phantom_core::storage::TraceStore;
+
+use crate::cli::ImportArgs;
+
+/// Reads the capture or HAR file's HTTP traffic into the store.
+pub fn import(store: &dyn TraceStore, args: ImportArgs, quiet: bool) -> anyhow::Result<()> {
+    if is_har(&args.file) {
+        return import_har(store, args, quiet);
+    }
+    let mut capture = CaptureFile::open(&args.file)?;
+    if let Some(keylog) = args.keylog_file {
+        // A missing key log would just leave every connection undecrypted.
+        std::fs::metadata(&keylog).map_err(|e| anyhow::anyhow!("{}: {e}", keylog.display()))?;
+        capture = capture.with_keylog(keylog);
+    }
+    let mut imported = 0u64;
+    for trace in &mut capture {
+        let mut trace = trace?;
+        trace.tag_operations();
+        store.insert(&trace)?;
+        imported += 1;
+    }
+    if !quiet {
+        eprintln!(
+            "phantom: imported {imported} traces from {} packets in {}",
+            capture.packet_count(),
+            args.file.display()
+        );
+    }
+    Ok(())
+}
+
+fn import_har(store: &dyn TraceStore, args: ImportArgs, quiet: bool) -> anyhow::Result<()> {
+    if args.keylog_file.is_some() {
+        anyhow::bail!("--keylog-file only applies to pcap/pcapng files");
+    }
+    let mut har = HarFile::open(&args.file)?;
+    let mut imported = 0u64;
+    for trace in &mut har {
+        let mut trace = trace?;
+        trace.tag_operations();
+        store.insert(&trace)?;
+        imported += 1;
+    }
+    if !quiet {
+        eprintln!(
+            "phantom: imported {imported} traces from {} HAR entries in {}",
+            har.entry_count(),
+            args.file.display()
+        );
+    }
+    Ok(())
+}
+
+/// A `.har` file, or one that starts like JSON: capture files start with a
+/// binary magic number.
+fn is_har(path: &Path) -> bool {
+    if path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("har"))
+    {
+        return true;
+    }
+    let mut head = [0u8; 64];
+    let Ok(n) = std::fs::File::open(path).and_then(|mut f| f.read(&mut head)) else {
+        return false;
+    };
+    let head = head[..n]
+        .strip_prefix(b"\xef\xbb\xbf")
+        .unwrap_or(&head[..n]);
+    head.iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'{')
+}
@@ -0,0 +1,44 @@
+use phantom_storage::DataStore;
+
+use crate::cli::MaintenanceArgs;
+
+pub fn maintenance(store: &DataStore, args: MaintenanceArgs, quiet: bool) -> anyhow::Result<()> {
+    let report = store.verify_and_repair()?;
+    if !quiet && report.unreadable_records > 0 {
+        eprintln!(
+            "phantom: {} stored traces could not be decoded and were left in place",
+            report.unreadable_records
+        );
+    }
+
+    if args.compact {
+        store.compact()?;
+    }
+
+    let backup = match &args.backup {
+        Some(path) => {
+            let records = store.backup_to(path)?;
+            if !quiet {
+                eprintln!("phantom: backed up {records} traces to {}", path.display());
+            }
+            serde_json::json!({
+                "path": path.display().to_string(),
+                "records": records,
+            })
+        }
+        None => serde_json::Value::Null,
+    };
+
+    println!(
+        "{}",
+        serde_json::json!({
+            "records": report.records,
+            "dangling_index_removed": report.dangling_index_removed,
+            "missing_index_restored": report.missing_index_restored,
+            "unreadable_records": report.unreadable_records,
+            "compacted": args.compact,
+            "backup": backup,
+        })
+    );
+    Ok(())
+}
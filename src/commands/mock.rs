@@ -0,0 +1,54 @@
+use std::sync::Arc;
+
+use phantom_capture::mock::MatchOptions;
+use phantom_capture::{MockRoutes, serve_mock};
+use phantom_core::query::TraceQuery;
+use phantom_core::storage::TraceStore;
+
+use crate::cli::MockArgs;
+use crate::commands::query::parse_time;
+
+/// Loads the matching traces, releases the store, and serves their
+/// responses until Ctrl-C.
+pub async fn mock(store: Arc<dyn TraceStore>, args: MockArgs, quiet: bool) -> anyhow::Result<()> {
+    let query = TraceQuery {
+        url_contains: args.url.clone(),
+        since: args.since.as_deref().map(parse_time).transpose()?,
+        until: args.until.as_deref().map(parse_time).transpose()?,
+        limit: args.limit,
+        ..TraceQuery::default()
+    };
+    let mut traces = store.query(&query)?;
+    // Close the store so `phantom run` can capture while the mock serves.
+    drop(store);
+    traces.reverse();
+    let options = MatchOptions {
+        body: args.match_body,
+        origin: false,
+        query_fallback: true,
+    };
+    let routes = MockRoutes::from_traces(&traces, options);
+    if routes.is_empty() {
+        anyhow::bail!("no captured traces to serve; capture some with `phantom run` first");
+    }
+
+    let listener = tokio::net::TcpListener::bind((args.bind, args.port))
+        .await
+        .map_err(|e| anyhow::anyhow!("mock server on {}:{}: {e}", args.bind, args.port))?;
+    if !quiet {
+        eprintln!(
+            "phantom: mock server listening on http://{}:{} ({} endpoints from {} traces)",
+            args.bind,
+            args.port,
+            routes.len(),
+            traces.len()
+        );
+    }
+    drop(traces);
+
+    tokio::select! {
+        result = serve_mock(listener, routes) => result?,
+        _ = tokio::signal::ctrl_c() => {}
+    }
+    Ok(())
+}
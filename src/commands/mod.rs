@@ -1,2 +1,9 @@
+pub mod audit;
+pub mod ca;
+pub mod export;
+pub mod import;
+pub mod maintenance;
+pub mod mock;
 pub mod query;
+pub mod report;
 pub mod run;
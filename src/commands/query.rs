@@ -1,6 +1,8 @@
 use std::time::SystemTime;
 
+use phantom_core::connection::ConnectionStats;
 use phantom_core::query::TraceQuery;
+use phantom_core::stitch::query_stitched;
 use phantom_core::storage::TraceStore;
 use phantom_core::trace::{HttpTrace, SpanId, TraceId};
 use phantom_core::view::{RenderOptions, TraceView};
@@ -9,7 +11,7 @@ use crate::cli::{FilterArgs, GetArgs, ListArgs, QueryFormat, SearchArgs};
 
 /// Parse a `--since`/`--until` value: RFC3339 timestamp, or a relative
 /// duration meaning "that long ago" (e.g. "30s", "10m", "2h").
-fn parse_time(s: &str) -> anyhow::Result<SystemTime> {
+pub(crate) fn parse_time(s: &str) -> anyhow::Result<SystemTime> {
     if let Ok(duration) = humantime::parse_duration(s) {
         return SystemTime::now()
             .checked_sub(duration)
@@ -89,7 +91,7 @@ fn output_traces(
 
 pub fn list(store: &dyn TraceStore, args: ListArgs) -> anyhow::Result<()> {
     let query = build_query(args.url, &args.filter)?;
-    let traces = store.query(&query)?;
+    let traces = query_stitched(store, &query)?;
     let opts = render_options(
         args.filter.max_body,
         args.filter.headers_only,
@@ -100,7 +102,7 @@ pub fn list(store: &dyn TraceStore, args: ListArgs) -> anyhow::Result<()> {
 
 pub fn search(store: &dyn TraceStore, args: SearchArgs) -> anyhow::Result<()> {
     let query = build_query(Some(args.pattern), &args.filter)?;
-    let traces = store.query(&query)?;
+    let traces = query_stitched(store, &query)?;
     let opts = render_options(
         args.filter.max_body,
         args.filter.headers_only,
@@ -129,12 +131,17 @@ pub fn get(store: &dyn TraceStore, args: GetArgs) -> anyhow::Result<bool> {
     Ok(true)
 }
 
+/// Number of most recent traces `stats` derives connection reuse from.
+pub const STATS_WINDOW: usize = 10_000;
+
 pub fn stats(store: &dyn TraceStore, data_dir: &std::path::Path) -> anyhow::Result<()> {
+    let recent = store.list_recent(STATS_WINDOW, 0)?;
     println!(
         "{}",
         serde_json::json!({
             "total_traces": store.count()?,
             "data_dir": data_dir.display().to_string(),
+            "connections": ConnectionStats::from_traces(&recent),
         })
     );
     Ok(())
@@ -0,0 +1,459 @@
+use std::fmt::Write as _;
+use std::time::{Duration, UNIX_EPOCH};
+
+use phantom_core::query::TraceQuery;
+use phantom_core::report::SessionReport;
+use phantom_core::storage::TraceStore;
+
+use crate::cli::{ReportArgs, ReportFormat};
+use crate::commands::query::parse_time;
+
+/// Width of the longest bar in Markdown charts, in characters.
+const MD_BAR_WIDTH: u64 = 40;
+
+pub fn report(store: &dyn TraceStore, args: ReportArgs, quiet: bool) -> anyhow::Result<()> {
+    let query = TraceQuery {
+        since: args.since.as_deref().map(parse_time).transpose()?,
+        until: args.until.as_deref().map(parse_time).transpose()?,
+        limit: args.limit,
+        ..TraceQuery::default()
+    };
+    let traces = store.query(&query)?;
+    let report = SessionReport::build(&traces, args.top);
+    let rendered = match args.format {
+        ReportFormat::Md => render_markdown(&report),
+        ReportFormat::Html => render_html(&report),
+    };
+    match args.output {
+        Some(path) => {
+            std::fs::write(&path, rendered)?;
+            if !quiet {
+                eprintln!(
+                    "phantom: wrote report of {} traces to {}",
+                    traces.len(),
+                    path.display()
+                );
+            }
+        }
+        None => print!("{rendered}"),
+    }
+    Ok(())
+}
+
+fn format_time(ms: u64) -> String {
+    humantime::format_rfc3339_seconds(UNIX_EPOCH + Duration::from_millis(ms)).to_string()
+}
+
+/// `"HH:MM:SS"` part of a timestamp, for chart labels.
+fn format_clock(ms: u64) -> String {
+    format_time(ms)[11..19].to_string()
+}
+
+fn error_rate(errors: u64, requests: u64) -> f64 {
+    if requests == 0 {
+        0.0
+    } else {
+        errors as f64 * 100.0 / requests as f64
+    }
+}
+
+fn status_classes(report: &SessionReport) -> String {
+    report
+        .summary
+        .status_classes
+        .iter()
+        .map(|(class, n)| format!("{class}: {n}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Markdown
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Escapes text for a Markdown table cell.
+fn md_cell(s: &str) -> String {
+    s.replace('|', "\\|")
+}
+
+fn md_bar(value: u64, max: u64) -> String {
+    let len = if max == 0 {
+        0
+    } else {
+        (value * MD_BAR_WIDTH).div_ceil(max)
+    };
+    "█".repeat(len as usize)
+}
+
+pub fn render_markdown(report: &SessionReport) -> String {
+    let s = &report.summary;
+    let mut out = String::from("# phantom session report\n\n");
+    if s.requests == 0 {
+        out.push_str("No traces captured in the selected range.\n");
+        return out;
+    }
+    let _ = writeln!(
+        out,
+        "{} requests between {} and {}.\n",
+        s.requests,
+        format_time(s.first_ms),
+        format_time(s.last_ms)
+    );
+
+    out.push_str("## Traffic summary\n\n| Metric | Value |\n|---|---|\n");
+    let _ = writeln!(out, "| Requests | {} |", s.requests);
+    let _ = writeln!(
+        out,
+        "| Errors (4xx/5xx) | {} ({:.1}%) |",
+        s.errors,
+        error_rate(s.errors, s.requests)
+    );
+    let _ = writeln!(
+        out,
+        "| Latency p50 / p95 / p99 | {} / {} / {} ms |",
+        s.p50_ms, s.p95_ms, s.p99_ms
+    );
+    let _ = writeln!(out, "| Status codes | {} |", status_classes(report));
+
+    out.push_str("\n## Top endpoints\n\n");
+    out.push_str("| Endpoint | Requests | Errors | p50 | p95 | Max |\n|---|--:|--:|--:|--:|--:|\n");
+    for e in &report.endpoints {
+        let _ = writeln!(
+            out,
+            "| `{} {}` | {} | {} | {} ms | {} ms | {} ms |",
+            e.method,
+            md_cell(&e.endpoint),
+            e.requests,
+            e.errors,
+            e.p50_ms,
+            e.p95_ms,
+            e.max_ms
+        );
+    }
+
+    out.push_str("\n## Errors\n\n");
+    if report.errors.is_empty() {
+        out.push_str("No 4xx/5xx responses.\n");
+    } else {
+        out.push_str("| Status | Endpoint | Count | Example span |\n|--:|---|--:|---|\n");
+        for e in &report.errors {
+            let _ = writeln!(
+                out,
+                "| {} | `{} {}` | {} | `{}` |",
+                e.status_code,
+                e.method,
+                md_cell(&e.endpoint),
+                e.count,
+                e.sample_span_id
+            );
+        }
+        out.push_str("\nInspect an example with `phantom get <SPAN_ID>`.\n");
+    }
+
+    out.push_str("\n## Slowest requests\n\n");
+    out.push_str("| Duration | Method | URL | Status | Span |\n|--:|---|---|--:|---|\n");
+    for t in &report.slowest {
+        let _ = writeln!(
+            out,
+            "| {} ms | {} | {} | {} | `{}` |",
+            t.duration_ms,
+            t.method,
+            md_cell(&t.url),
+            t.status_code,
+            t.span_id
+        );
+    }
+
+    out.push_str("\n## Traffic over time\n\n```\n");
+    let max = report
+        .timeline
+        .iter()
+        .map(|b| b.requests)
+        .max()
+        .unwrap_or(0);
+    for b in &report.timeline {
+        let _ = write!(
+            out,
+            "{} {:<width$} {}",
+            format_clock(b.start_ms),
+            md_bar(b.requests, max),
+            b.requests,
+            width = MD_BAR_WIDTH as usize
+        );
+        if b.errors > 0 {
+            let _ = write!(out, " ({} errors)", b.errors);
+        }
+        out.push('\n');
+    }
+    out.push_str("```\n");
+    out
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// HTML
+// ─────────────────────────────────────────────────────────────────────────────
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+const HTML_STYLE: &str = "body{font-family:system-ui,sans-serif;margin:2rem auto;max-width:1100px;color:#222}\
+table{border-collapse:collapse;margin:0.5rem 0 1.5rem}\
+th,td{border:1px solid #ddd;padding:4px 8px;text-align:left}\
+td.n{text-align:right}code{font-size:0.9em}\
+.bad{color:#b00020}rect.req{fill:#4a7bd0}rect.err{fill:#d04a4a}";
+
+/// Stacked column chart of requests (and errors) per timeline bucket.
+fn svg_timeline(report: &SessionReport) -> String {
+    const HEIGHT: u64 = 120;
+    const COLUMN: u64 = 24;
+    let max = report
+        .timeline
+        .iter()
+        .map(|b| b.requests)
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let width = COLUMN * report.timeline.len() as u64;
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{}\" role=\"img\">",
+        HEIGHT + 16
+    );
+    for (i, b) in report.timeline.iter().enumerate() {
+        let x = i as u64 * COLUMN;
+        let h = b.requests * HEIGHT / max;
+        let eh = b.errors * HEIGHT / max;
+        let _ = write!(
+            svg,
+            "<g><title>{}: {} requests, {} errors</title>\
+             <rect class=\"req\" x=\"{x}\" y=\"{}\" width=\"{}\" height=\"{h}\"/>\
+             <rect class=\"err\" x=\"{x}\" y=\"{}\" width=\"{}\" height=\"{eh}\"/></g>",
+            format_clock(b.start_ms),
+            b.requests,
+            b.errors,
+            HEIGHT - h,
+            COLUMN - 4,
+            HEIGHT - eh,
+            COLUMN - 4
+        );
+    }
+    if let (Some(first), Some(last)) = (report.timeline.first(), report.timeline.last()) {
+        let _ = write!(
+            svg,
+            "<text x=\"0\" y=\"{y}\" font-size=\"11\">{}</text>\
+             <text x=\"{width}\" y=\"{y}\" font-size=\"11\" text-anchor=\"end\">{}</text>",
+            format_clock(first.start_ms),
+            format_clock(last.start_ms),
+            y = HEIGHT + 13
+        );
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+/// Horizontal bar sized relative to `max`, for table cells.
+fn html_bar(value: u64, max: u64) -> String {
+    let pct = (value * 100).checked_div(max).unwrap_or(0);
+    format!("<div style=\"background:#4a7bd0;height:8px;width:{pct}px\" title=\"{value}\"></div>")
+}
+
+pub fn render_html(report: &SessionReport) -> String {
+    let s = &report.summary;
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\">\
+         <title>phantom session report</title><style>{HTML_STYLE}</style></head><body>\n\
+         <h1>phantom session report</h1>\n"
+    );
+    if s.requests == 0 {
+        out.push_str("<p>No traces captured in the selected range.</p>\n</body></html>\n");
+        return out;
+    }
+    let _ = writeln!(
+        out,
+        "<p>{} requests between {} and {}.</p>",
+        s.requests,
+        format_time(s.first_ms),
+        format_time(s.last_ms)
+    );
+
+    out.push_str("<h2>Traffic summary</h2>\n<table>\n");
+    let _ = writeln!(out, "<tr><th>Requests</th><td>{}</td></tr>", s.requests);
+    let _ = writeln!(
+        out,
+        "<tr><th>Errors (4xx/5xx)</th><td>{} ({:.1}%)</td></tr>",
+        s.errors,
+        error_rate(s.errors, s.requests)
+    );
+    let _ = writeln!(
+        out,
+        "<tr><th>Latency p50 / p95 / p99</th><td>{} / {} / {} ms</td></tr>",
+        s.p50_ms, s.p95_ms, s.p99_ms
+    );
+    let _ = writeln!(
+        out,
+        "<tr><th>Status codes</th><td>{}</td></tr>\n</table>",
+        status_classes(report)
+    );
+
+    out.push_str("<h2>Traffic over time</h2>\n");
+    out.push_str(&svg_timeline(report));
+    out.push('\n');
+
+    out.push_str(
+        "<h2>Top endpoints</h2>\n<table>\n<tr><th>Endpoint</th><th>Requests</th><th></th>\
+         <th>Errors</th><th>p50</th><th>p95</th><th>Max</th></tr>\n",
+    );
+    let max = report.endpoints.first().map_or(0, |e| e.requests);
+    for e in &report.endpoints {
+        let _ = writeln!(
+            out,
+            "<tr><td><code>{} {}</code></td><td class=\"n\">{}</td><td>{}</td>\
+             <td class=\"n{}\">{}</td><td class=\"n\">{} ms</td><td class=\"n\">{} ms</td>\
+             <td class=\"n\">{} ms</td></tr>",
+            e.method,
+            html_escape(&e.endpoint),
+            e.requests,
+            html_bar(e.requests, max),
+            if e.errors > 0 { " bad" } else { "" },
+            e.errors,
+            e.p50_ms,
+            e.p95_ms,
+            e.max_ms
+        );
+    }
+    out.push_str("</table>\n<h2>Errors</h2>\n");
+    if report.errors.is_empty() {
+        out.push_str("<p>No 4xx/5xx responses.</p>\n");
+    } else {
+        out.push_str(
+            "<table>\n<tr><th>Status</th><th>Endpoint</th><th>Count</th>\
+             <th>Example span</th><th>Example URL</th></tr>\n",
+        );
+        for e in &report.errors {
+            let _ = writeln!(
+                out,
+                "<tr><td class=\"n bad\">{}</td><td><code>{} {}</code></td><td class=\"n\">{}</td>\
+                 <td><code>{}</code></td><td>{}</td></tr>",
+                e.status_code,
+                e.method,
+                html_escape(&e.endpoint),
+                e.count,
+                e.sample_span_id,
+                html_escape(&e.sample_url)
+            );
+        }
+        out.push_str("</table>\n");
+    }
+
+    out.push_str(
+        "<h2>Slowest requests</h2>\n<table>\n<tr><th>Duration</th><th>Method</th>\
+         <th>URL</th><th>Status</th><th>Span</th><th>Time</th></tr>\n",
+    );
+    for t in &report.slowest {
+        let _ = writeln!(
+            out,
+            "<tr><td class=\"n\">{} ms</td><td>{}</td><td>{}</td><td class=\"n\">{}</td>\
+             <td><code>{}</code></td><td>{}</td></tr>",
+            t.duration_ms,
+            t.method,
+            html_escape(&t.url),
+            t.status_code,
+            t.span_id,
+            format_time(t.timestamp_ms)
+        );
+    }
+    out.push_str("</table>\n</body></html>\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::time::SystemTime;
+
+    use phantom_core::trace::{HttpMethod, HttpTrace, SpanId, TraceId};
+
+    use super::*;
+
+    fn make_trace(url: &str, status_code: u16, duration_ms: u64) -> HttpTrace {
+        HttpTrace {
+            span_id: SpanId([0xab; 8]),
+            trace_id: TraceId([2; 16]),
+            parent_span_id: None,
+            method: HttpMethod::Get,
+            url: url.to_string(),
+            request_headers: HashMap::new(),
+            request_body: None,
+            status_code,
+            response_headers: HashMap::new(),
+            response_body: None,
+            timestamp: SystemTime::UNIX_EPOCH + Duration::from_secs(1_767_225_600),
+            duration: Duration::from_millis(duration_ms),
+            source_addr: None,
+            dest_addr: None,
+            protocol_version: "HTTP/1.1".to_string(),
+            request_body_file: None,
+            response_body_file: None,
+            connection_id: None,
+            process: None,
+            response_trailers: HashMap::new(),
+            request_body_encoding: None,
+            response_body_encoding: None,
+            elasticsearch: None,
+            soap: None,
+            graphql: None,
+            thread_id: None,
+            tls: None,
+            timing: None,
+            request_size: None,
+            response_size: None,
+            fault: None,
+            replay: None,
+        }
+    }
+
+    fn make_report() -> SessionReport {
+        let traces = vec![
+            make_trace("http://h/users/1", 200, 10),
+            make_trace("http://h/users/2?q=a|b", 500, 700),
+        ];
+        SessionReport::build(&traces, 10)
+    }
+
+    #[test]
+    fn test_markdown_has_all_sections() {
+        let md = render_markdown(&make_report());
+        for heading in [
+            "## Traffic summary",
+            "## Top endpoints",
+            "## Errors",
+            "## Slowest requests",
+            "## Traffic over time",
+        ] {
+            assert!(md.contains(heading), "missing {heading}");
+        }
+        assert!(md.contains("| Errors (4xx/5xx) | 1 (50.0%) |"));
+        assert!(md.contains("| 500 | `GET h/users/:id` | 1 | `abababababababab` |"));
+        // Pipes in URLs must not break the table.
+        assert!(md.contains("q=a\\|b"));
+    }
+
+    #[test]
+    fn test_html_escapes_and_embeds_chart() {
+        let report = SessionReport::build(&[make_trace("http://h/a?x=<script>", 200, 5)], 10);
+        let html = render_html(&report);
+        assert!(html.contains("<svg"));
+        assert!(html.contains("x=&lt;script&gt;"));
+        assert!(!html.contains("<script>"));
+    }
+
+    #[test]
+    fn test_empty_report() {
+        let report = SessionReport::build(&[], 10);
+        assert!(render_markdown(&report).contains("No traces captured"));
+        assert!(render_html(&report).contains("No traces captured"));
+    }
+}
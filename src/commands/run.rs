@@ -1,19 +1,47 @@
 use std::process::ExitStatus;
 use std::sync::Arc;
 
-use phantom_capture::ProxyCaptureBackend;
-use phantom_core::capture::CaptureBackend;
+#[cfg(target_os = "linux")]
+use phantom_capture::IpcMode;
+use phantom_capture::ca::CA_CERT_FILE;
+use phantom_capture::{
+    AccessLogCaptureBackend, CapturePolicies, INGEST_PATH, IngestCaptureBackend, ProxyCa,
+    ProxyCaptureBackend, RemoteCaptureBackend, RewriteConfig,
+};
+use phantom_core::capture::{CaptureBackend, TraceEvent};
+use phantom_core::query::TraceQuery;
+use phantom_core::sink::TeeTraceStore;
+use phantom_core::slo::SloTracker;
 use phantom_core::storage::TraceStore;
-use phantom_core::trace::HttpTrace;
-use phantom_core::view::{RenderOptions, TraceView};
-use phantom_storage::FjallTraceStore;
+use phantom_core::view::{ProtocolTraceView, RenderOptions, TraceView};
 use tracing::warn;
 
-use crate::cli::{GlobalOpts, OutputMode, RunArgs};
+#[cfg(target_os = "linux")]
+use crate::cli::AttachArgs;
+use crate::cli::{ConnectArgs, GlobalOpts, OutputMode, RunArgs};
 use crate::runner::{
-    TempScript, build_fault_config, loopback_safe, spawn_proxy_child, wait_for_proxy,
+    TempScript, build_client_allowlist, build_client_cert_config, build_fault_config,
+    build_intercept_config, build_shaping_config, build_slo_tracker, build_upstream_proxy,
+    loopback_safe, open_replay_recording, spawn_proxy_child, wait_for_proxy,
 };
 
+/// Result of a `phantom run` session.
+pub struct RunOutcome {
+    /// The child's exit status, when a child was spawned and exited.
+    pub child_status: Option<ExitStatus>,
+    /// Final error-budget state of the `--slo` objectives.
+    pub slo: SloTracker,
+}
+
+/// The process a JSONL session ends with.
+enum Target {
+    /// A process phantom spawned; its exit status is reported.
+    Child(std::process::Child),
+    /// A process phantom attached to, which is not its child.
+    #[cfg(target_os = "linux")]
+    Attached(u32),
+}
+
 /// Render options for the JSONL stream, from `run` flags.
 fn jsonl_render_options(args: &RunArgs) -> RenderOptions {
     RenderOptions {
@@ -26,32 +54,48 @@ fn jsonl_render_options(args: &RunArgs) -> RenderOptions {
 /// Runs the JSONL output loop: each captured trace is serialized and written to
 /// stdout as a single JSON object followed by a newline.
 ///
+/// Non-HTTP traces are written as [`ProtocolTraceView`] lines, distinguished
+/// by their `kind` field. Agent health reports are not written.
+///
 /// Exits when:
-/// - The trace channel is closed (sender dropped),
+/// - The event channel is closed (sender dropped),
 /// - Ctrl-C is received, or
-/// - The optional `child` process exits.
+/// - The optional `target` process exits.
+///
+/// Every HTTP trace is counted against `slo`; the exit summary includes the
+/// final budget state when any SLOs are defined.
 ///
 /// Returns the child's exit status (when a child was spawned and exited) so
-/// the caller can propagate its exit code.
+/// the caller can propagate its exit code. An attached process has none.
 async fn run_jsonl_output(
-    store: Arc<FjallTraceStore>,
-    mut trace_rx: tokio::sync::mpsc::Receiver<HttpTrace>,
-    child: Option<std::process::Child>,
+    store: Arc<dyn TraceStore>,
+    mut events: tokio::sync::mpsc::Receiver<TraceEvent>,
+    target: Option<Target>,
     opts: &RenderOptions,
+    slo: &mut SloTracker,
     quiet: bool,
 ) -> anyhow::Result<Option<ExitStatus>> {
     // Spawn a background thread to wait() on the child so we don't block the
     // async executor.  The child's exit status is sent through a oneshot.
-    let mut child_done: Option<tokio::sync::oneshot::Receiver<std::io::Result<ExitStatus>>> =
-        if let Some(mut c) = child {
+    // An attached process cannot be waited on, so its /proc entry is polled.
+    let mut child_done: Option<tokio::sync::oneshot::Receiver<Option<ExitStatus>>> =
+        target.map(|target| {
             let (tx, rx) = tokio::sync::oneshot::channel();
-            std::thread::spawn(move || {
-                let _ = tx.send(c.wait());
+            std::thread::spawn(move || match target {
+                Target::Child(mut c) => {
+                    let _ = tx.send(c.wait().ok());
+                }
+                #[cfg(target_os = "linux")]
+                Target::Attached(pid) => {
+                    let proc_dir = std::path::PathBuf::from(format!("/proc/{pid}"));
+                    while proc_dir.exists() {
+                        std::thread::sleep(std::time::Duration::from_millis(500));
+                    }
+                    let _ = tx.send(None);
+                }
             });
-            Some(rx)
-        } else {
-            None
-        };
+            rx
+        });
 
     let ctrl_c = tokio::signal::ctrl_c();
     tokio::pin!(ctrl_c);
@@ -59,18 +103,33 @@ async fn run_jsonl_output(
     let mut traces_captured: u64 = 0;
     let mut child_status: Option<ExitStatus> = None;
 
-    let mut emit = |t: &HttpTrace| -> anyhow::Result<()> {
-        store.insert(t).ok();
-        println!("{}", serde_json::to_string(&TraceView::render(t, opts))?);
-        traces_captured += 1;
+    let mut emit = |event: TraceEvent| -> anyhow::Result<()> {
+        match event {
+            TraceEvent::Http(mut t) => {
+                t.tag_operations();
+                if let Err(e) = store.insert(&t) {
+                    warn!("failed to store trace: {e}");
+                }
+                slo.record(&t);
+                println!("{}", serde_json::to_string(&TraceView::render(&t, opts))?);
+                traces_captured += 1;
+            }
+            TraceEvent::Protocol(p) => {
+                if let Err(e) = store.insert_protocol(&p) {
+                    warn!("failed to store protocol trace: {e}");
+                }
+                println!("{}", serde_json::to_string(&ProtocolTraceView::from(&p))?);
+            }
+            TraceEvent::AgentStats(_) => {}
+        }
         Ok(())
     };
 
     loop {
         tokio::select! {
-            maybe_trace = trace_rx.recv() => {
-                match maybe_trace {
-                    Some(t) => emit(&t)?,
+            maybe_event = events.recv() => {
+                match maybe_event {
+                    Some(event) => emit(event)?,
                     None => break,
                 }
             }
@@ -84,12 +143,12 @@ async fn run_jsonl_output(
                     std::future::pending().await
                 }
             } => {
-                if let Ok(Ok(status)) = status {
+                if let Ok(Some(status)) = status {
                     child_status = Some(status);
                 }
                 tokio::time::sleep(std::time::Duration::from_millis(200)).await;
-                while let Ok(t) = trace_rx.try_recv() {
-                    emit(&t)?;
+                while let Ok(event) = events.try_recv() {
+                    emit(event)?;
                 }
                 break;
             }
@@ -98,29 +157,114 @@ async fn run_jsonl_output(
 
     if !quiet {
         // Machine-readable end-of-run summary on stderr (stdout stays pure JSONL).
-        eprintln!(
-            "{}",
-            serde_json::json!({
-                "event": "exit",
-                "child_exit_code": child_status.and_then(|s| s.code()),
-                "traces_captured": traces_captured,
-            })
-        );
+        let mut summary = serde_json::json!({
+            "event": "exit",
+            "child_exit_code": child_status.and_then(|s| s.code()),
+            "traces_captured": traces_captured,
+        });
+        if !slo.is_empty() {
+            summary["slo"] = serde_json::to_value(slo.statuses())?;
+        }
+        eprintln!("{summary}");
     }
 
     Ok(child_status)
 }
 
+/// Prints the final SLO budget state to stderr after an interactive session.
+fn report_slo(slo: &SloTracker, quiet: bool) {
+    if quiet {
+        return;
+    }
+    for status in slo.statuses() {
+        eprintln!(
+            "phantom: SLO {}: {} of {} requests missed the objective, {:.0}% of error budget used{}",
+            status.spec,
+            status.bad,
+            status.total,
+            status.budget_used * 100.0,
+            if status.breached { " — BREACHED" } else { "" }
+        );
+    }
+}
+
 pub async fn run_proxy(
     globals: &GlobalOpts,
     args: RunArgs,
-    store: Arc<FjallTraceStore>,
-) -> anyhow::Result<Option<ExitStatus>> {
+    mut store: Arc<dyn TraceStore>,
+) -> anyhow::Result<RunOutcome> {
     let fault_config = build_fault_config(&args.fault)?;
-    let mut backend =
-        ProxyCaptureBackend::new(args.bind, args.port, args.insecure).with_faults(fault_config);
+    let shaping = build_shaping_config(&args.throttle)?;
+    let allowlist = build_client_allowlist(&args.allow_client)?;
+    let client_certs = build_client_cert_config(&args.client_cert)?;
+    let intercepts = build_intercept_config(&args.intercept)?;
+    if !intercepts.is_empty() && matches!(args.output, OutputMode::Jsonl) {
+        anyhow::bail!("--intercept requires the TUI output");
+    }
+    let mut slo = build_slo_tracker(&args.slo)?;
+    // The MITM CA lives in <data_dir>/ca.pem (+ ca-key.pem) and is reused
+    // across runs, so it only has to be trusted once. Out-of-process
+    // consumers can bind-mount ca.pem into another container's trust store.
+    // --ca-cert/--ca-key substitute a CA the team already distributes.
+    let ca = match (&args.ca_cert, &args.ca_key) {
+        (Some(cert), Some(key)) => ProxyCa::from_pem_files(cert, key),
+        _ => ProxyCa::load_or_create(&globals.data_dir),
+    }
+    .map_err(|e| anyhow::anyhow!("{e}"))?;
+    let mut backend = ProxyCaptureBackend::new(args.bind, args.port, args.insecure)
+        .with_faults(fault_config)
+        .with_shaping(shaping)
+        .with_client_allowlist(allowlist)
+        .with_client_certs(client_certs)
+        .with_intercepts(intercepts)
+        .with_body_dir(globals.data_dir.join("bodies"))
+        .with_ca(ca);
+    if let Some(upstream) = build_upstream_proxy(args.upstream_proxy.as_deref(), args.port)? {
+        backend = backend.with_upstream_proxy(upstream);
+    }
+    if let Some(path) = &args.rewrite {
+        let rewrites = RewriteConfig::load(path).map_err(|e| anyhow::anyhow!("--rewrite: {e}"))?;
+        backend = backend.with_rewrites(rewrites);
+    }
+    if let Some(path) = &args.capture_policy {
+        let policies =
+            CapturePolicies::load(path).map_err(|e| anyhow::anyhow!("--capture-policy: {e}"))?;
+        backend = backend.with_capture_policies(policies);
+    }
+    if let Some(dir) = &args.replay {
+        let (recording, recorder) = open_replay_recording(dir, &globals.data_dir)?;
+        if !globals.quiet {
+            eprintln!(
+                "phantom: replaying {} recorded requests from {}",
+                recording.len(),
+                dir.display()
+            );
+        }
+        backend = backend.with_replay(recording);
+        store = Arc::new(TeeTraceStore::new(
+            store,
+            vec![(TraceQuery::default(), recorder)],
+        ));
+    }
+    if let Some(socks_port) = args.socks_port {
+        backend = backend.with_socks_port(socks_port);
+    }
+    if let Some(h3_port) = args.h3_port {
+        backend = backend.with_h3_port(h3_port);
+    }
+    if args.block_quic {
+        backend = backend.with_block_quic();
+    }
+    if let Some(transparent_port) = args.transparent {
+        #[cfg(target_os = "linux")]
+        {
+            backend = backend.with_transparent_port(transparent_port);
+        }
+        #[cfg(not(target_os = "linux"))]
+        anyhow::bail!("--transparent {transparent_port} requires Linux (SO_ORIGINAL_DST)");
+    }
     let backend_name = backend.name().to_string();
-    let trace_rx = backend.start().map_err(|e| anyhow::anyhow!("{e}"))?;
+    let events = backend.start().map_err(|e| anyhow::anyhow!("{e}"))?;
 
     // Always wait for the proxy to actually be listening — needed both to
     // safely inject env vars/CA into a spawned child (if any) and to export
@@ -130,24 +274,11 @@ pub async fn run_proxy(
     let connect_ip = loopback_safe(args.bind);
     wait_for_proxy(connect_ip, args.port).await?;
 
-    // Export the MITM CA certificate to a stable path so it can be trusted
-    // by out-of-process consumers (bind-mount <data_dir>/ca.pem into another
-    // container's trust store). Regenerated fresh every run.
     let ca_cert_pem = backend.ca_cert_pem();
-    let ca_cert_path = globals.data_dir.join("ca.pem");
-    match ca_cert_pem.as_deref() {
-        Some(pem) => {
-            std::fs::write(&ca_cert_path, pem).map_err(|e| {
-                anyhow::anyhow!("failed to write CA cert to {}: {e}", ca_cert_path.display())
-            })?;
-        }
-        None => {
-            // Should not happen: ca_cert_pem is populated before the proxy
-            // binds, and wait_for_proxy() above already confirmed the port
-            // is accepting connections.
-            warn!("MITM CA certificate unavailable after proxy became ready; ca.pem not written");
-        }
-    }
+    let ca_cert_path = args
+        .ca_cert
+        .clone()
+        .unwrap_or_else(|| globals.data_dir.join(CA_CERT_FILE));
 
     // Optionally spawn a child command routed through the proxy.
     let child_and_script: Option<(std::process::Child, Option<TempScript>)> =
@@ -195,12 +326,20 @@ pub async fn run_proxy(
                             args.bind, args.port
                         );
                     }
+                    if let Some(socks_port) = args.socks_port {
+                        eprintln!("  SOCKS5: ALL_PROXY=socks5h://{}:{socks_port}", connect_ip);
+                    }
+                    if let Some(h3_port) = args.h3_port {
+                        eprintln!("  HTTP/3: udp/{}:{h3_port}", connect_ip);
+                    }
                     eprintln!("  MITM CA certificate: {}", ca_cert_path.display());
                     eprintln!("  Trust this CA in your target app/container for HTTPS capture.");
                 }
                 eprintln!("phantom: traces stored in {}", globals.data_dir.display());
             }
-            phantom_tui::run_tui(store, trace_rx, &backend_name).await?;
+            let intercepted = backend.take_intercepted();
+            slo = phantom_tui::run_tui(store, events, intercepted, &backend_name, slo).await?;
+            report_slo(&slo, globals.quiet);
         }
         OutputMode::Jsonl => {
             if !globals.quiet {
@@ -218,23 +357,65 @@ pub async fn run_proxy(
                 None => (None, None),
             };
             let opts = jsonl_render_options(&args);
-            child_status = run_jsonl_output(store, trace_rx, child, &opts, globals.quiet).await?;
+            child_status = run_jsonl_output(
+                store,
+                events,
+                child.map(Target::Child),
+                &opts,
+                &mut slo,
+                globals.quiet,
+            )
+            .await?;
             // _script_guard dropped here — temp file deleted after child exits.
         }
     }
 
     backend.stop().map_err(|e| anyhow::anyhow!("{e}"))?;
-    Ok(child_status)
+    Ok(RunOutcome { child_status, slo })
+}
+
+/// Fails on flags only the proxy backend implements.
+fn reject_proxy_only_flags(args: &RunArgs) -> anyhow::Result<()> {
+    if args.replay.is_some() {
+        anyhow::bail!("--replay requires the proxy backend");
+    }
+    if !args.allow_client.is_empty() {
+        anyhow::bail!("--allow-client requires the proxy backend");
+    }
+    if !args.client_cert.is_empty() {
+        anyhow::bail!("--client-cert requires the proxy backend");
+    }
+    if !args.intercept.is_empty() {
+        anyhow::bail!("--intercept requires the proxy backend");
+    }
+    if args.capture_policy.is_some() {
+        anyhow::bail!("--capture-policy requires the proxy backend");
+    }
+    Ok(())
+}
+
+/// The agent transport picked by PHANTOM_IPC_MODE; the default is datagrams.
+#[cfg(target_os = "linux")]
+fn ipc_mode_from_env() -> anyhow::Result<IpcMode> {
+    match std::env::var("PHANTOM_IPC_MODE") {
+        Ok(mode) => mode
+            .parse()
+            .map_err(|e| anyhow::anyhow!("PHANTOM_IPC_MODE: {e}")),
+        Err(_) => Ok(IpcMode::default()),
+    }
 }
 
 #[cfg(target_os = "linux")]
 pub async fn run_ldpreload(
     globals: &GlobalOpts,
     args: RunArgs,
-    store: Arc<FjallTraceStore>,
-) -> anyhow::Result<Option<ExitStatus>> {
+    store: Arc<dyn TraceStore>,
+) -> anyhow::Result<RunOutcome> {
     use phantom_capture::LdPreloadCaptureBackend;
 
+    let mut slo = build_slo_tracker(&args.slo)?;
+    reject_proxy_only_flags(&args)?;
+
     let agent_lib = args.agent_lib.clone().ok_or_else(|| {
         anyhow::anyhow!(
             "--agent-lib <PATH> is required for --backend ldpreload\n\
@@ -258,23 +439,35 @@ pub async fn run_ldpreload(
             .unwrap_or(0)
     ));
 
-    let mut backend = LdPreloadCaptureBackend::new(socket_path.clone());
+    let ipc_mode = ipc_mode_from_env()?;
+    let mut backend = LdPreloadCaptureBackend::new(socket_path.clone()).with_ipc_mode(ipc_mode);
     let backend_name = backend.name().to_string();
-    let trace_rx = backend.start().map_err(|e| anyhow::anyhow!("{e}"))?;
+    let events = backend.start().map_err(|e| anyhow::anyhow!("{e}"))?;
 
     if !globals.quiet {
         eprintln!("phantom: ldpreload backend active");
         eprintln!("  agent lib : {}", agent_lib.display());
-        eprintln!("  socket    : {}", socket_path.display());
+        eprintln!(
+            "  socket    : {} ({})",
+            socket_path.display(),
+            ipc_mode.as_str()
+        );
         eprintln!("  command   : {}", args.command.join(" "));
         eprintln!("phantom: traces stored in {}", globals.data_dir.display());
     }
 
     // Spawn the target process with LD_PRELOAD and PHANTOM_SOCKET set.
-    let child = std::process::Command::new(&args.command[0])
+    let mut command = std::process::Command::new(&args.command[0]);
+    command
         .args(&args.command[1..])
         .env("LD_PRELOAD", &agent_lib)
         .env("PHANTOM_SOCKET", &socket_path)
+        .env("PHANTOM_IPC_MODE", ipc_mode.as_str())
+        .env("PHANTOM_BODY_DIR", globals.data_dir.join("bodies"));
+    if args.block_quic {
+        command.env("PHANTOM_BLOCK_QUIC", "1");
+    }
+    let child = command
         .spawn()
         .map_err(|e| anyhow::anyhow!("failed to spawn {:?}: {e}", args.command[0]))?;
 
@@ -286,16 +479,538 @@ pub async fn run_ldpreload(
     match args.output {
         OutputMode::Tui => {
             // In TUI mode the user quits manually; child runs in background.
-            phantom_tui::run_tui(store, trace_rx, &backend_name).await?;
+            slo = phantom_tui::run_tui(store, events, None, &backend_name, slo).await?;
+            report_slo(&slo, globals.quiet);
         }
         OutputMode::Jsonl => {
             // In JSONL mode we exit automatically when the child finishes.
             let opts = jsonl_render_options(&args);
-            child_status =
-                run_jsonl_output(store, trace_rx, Some(child), &opts, globals.quiet).await?;
+            child_status = run_jsonl_output(
+                store,
+                events,
+                Some(Target::Child(child)),
+                &opts,
+                &mut slo,
+                globals.quiet,
+            )
+            .await?;
         }
     }
 
     backend.stop().map_err(|e| anyhow::anyhow!("{e}"))?;
-    Ok(child_status)
+    Ok(RunOutcome { child_status, slo })
+}
+
+#[cfg(target_os = "linux")]
+pub async fn run_ebpf(
+    globals: &GlobalOpts,
+    args: RunArgs,
+    store: Arc<dyn TraceStore>,
+) -> anyhow::Result<RunOutcome> {
+    use phantom_capture::EbpfCaptureBackend;
+
+    let mut slo = build_slo_tracker(&args.slo)?;
+    reject_proxy_only_flags(&args)?;
+
+    let bpf_object = args.bpf_object.clone().ok_or_else(|| {
+        anyhow::anyhow!(
+            "--bpf-object <PATH> is required for --backend ebpf\n\
+            Build it from crates/phantom-capture/bpf/phantom.bpf.c (see the clang line at its top)"
+        )
+    })?;
+
+    if args.command.is_empty() {
+        anyhow::bail!(
+            "A command to trace is required for --backend ebpf.\n\
+            Usage: phantom run --backend ebpf --bpf-object ./phantom.bpf.o -- curl http://example.com"
+        );
+    }
+
+    let mut backend =
+        EbpfCaptureBackend::new(bpf_object.clone()).with_ssl_libs(args.ssl_lib.clone());
+    let backend_name = backend.name().to_string();
+    let events = backend.start().map_err(|e| anyhow::anyhow!("{e}"))?;
+
+    // The child waits before exec until the probes follow its PID.
+    let mut command = std::process::Command::new(&args.command[0]);
+    command.args(&args.command[1..]);
+    let child = backend
+        .spawn(&mut command)
+        .map_err(|e| anyhow::anyhow!("failed to spawn {:?}: {e}", args.command[0]))?;
+
+    if !globals.quiet {
+        eprintln!("phantom: ebpf backend active");
+        eprintln!("  probes    : {}", bpf_object.display());
+        eprintln!("  command   : {}", args.command.join(" "));
+        eprintln!("phantom: spawned PID {}", child.id());
+        eprintln!("phantom: traces stored in {}", globals.data_dir.display());
+    }
+
+    let mut child_status = None;
+    match args.output {
+        OutputMode::Tui => {
+            slo = phantom_tui::run_tui(store, events, None, &backend_name, slo).await?;
+            report_slo(&slo, globals.quiet);
+        }
+        OutputMode::Jsonl => {
+            let opts = jsonl_render_options(&args);
+            child_status = run_jsonl_output(
+                store,
+                events,
+                Some(Target::Child(child)),
+                &opts,
+                &mut slo,
+                globals.quiet,
+            )
+            .await?;
+        }
+    }
+
+    backend.stop().map_err(|e| anyhow::anyhow!("{e}"))?;
+    Ok(RunOutcome { child_status, slo })
+}
+
+#[cfg(target_os = "linux")]
+pub async fn run_keylog(
+    globals: &GlobalOpts,
+    args: RunArgs,
+    store: Arc<dyn TraceStore>,
+) -> anyhow::Result<RunOutcome> {
+    use phantom_capture::KeyLogCaptureBackend;
+
+    let mut slo = build_slo_tracker(&args.slo)?;
+    reject_proxy_only_flags(&args)?;
+
+    if args.command.is_empty() {
+        anyhow::bail!(
+            "A command to trace is required for --backend keylog.\n\
+            Usage: phantom run --backend keylog -- curl https://example.com"
+        );
+    }
+
+    // A temporary key log is removed afterwards; it unlocks the capture.
+    let temporary = args.keylog_file.is_none();
+    let keylog_path = args.keylog_file.clone().unwrap_or_else(|| {
+        std::env::temp_dir().join(format!(
+            "phantom-{}.keys",
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_millis())
+                .unwrap_or(0)
+        ))
+    });
+
+    let mut backend = KeyLogCaptureBackend::new(keylog_path.clone());
+    if let Some(interface) = &args.interface {
+        backend = backend.with_interface(interface.clone());
+    }
+    let backend_name = backend.name().to_string();
+    let events = backend.start().map_err(|e| anyhow::anyhow!("{e}"))?;
+
+    let mut command = std::process::Command::new(&args.command[0]);
+    command.args(&args.command[1..]);
+    backend.prepare(&mut command);
+    let child = command
+        .spawn()
+        .map_err(|e| anyhow::anyhow!("failed to spawn {:?}: {e}", args.command[0]))?;
+
+    if !globals.quiet {
+        eprintln!("phantom: keylog backend active");
+        eprintln!("  key log   : {}", keylog_path.display());
+        eprintln!(
+            "  interface : {}",
+            args.interface.as_deref().unwrap_or("all")
+        );
+        eprintln!("  command   : {}", args.command.join(" "));
+        eprintln!("phantom: spawned PID {}", child.id());
+        eprintln!("phantom: traces stored in {}", globals.data_dir.display());
+    }
+
+    let mut child_status = None;
+    match args.output {
+        OutputMode::Tui => {
+            slo = phantom_tui::run_tui(store, events, None, &backend_name, slo).await?;
+            report_slo(&slo, globals.quiet);
+        }
+        OutputMode::Jsonl => {
+            let opts = jsonl_render_options(&args);
+            child_status = run_jsonl_output(
+                store,
+                events,
+                Some(Target::Child(child)),
+                &opts,
+                &mut slo,
+                globals.quiet,
+            )
+            .await?;
+        }
+    }
+
+    backend.stop().map_err(|e| anyhow::anyhow!("{e}"))?;
+    if temporary {
+        let _ = std::fs::remove_file(&keylog_path);
+    }
+    Ok(RunOutcome { child_status, slo })
+}
+
+#[cfg(target_os = "linux")]
+pub async fn run_pcap(
+    globals: &GlobalOpts,
+    args: RunArgs,
+    store: Arc<dyn TraceStore>,
+) -> anyhow::Result<RunOutcome> {
+    use phantom_capture::PcapCaptureBackend;
+
+    let mut slo = build_slo_tracker(&args.slo)?;
+    reject_proxy_only_flags(&args)?;
+
+    let mut backend = PcapCaptureBackend::new();
+    if let Some(interface) = &args.interface {
+        backend = backend.with_interface(interface.clone());
+    }
+    let backend_name = backend.name().to_string();
+    let events = backend.start().map_err(|e| anyhow::anyhow!("{e}"))?;
+
+    // A command is optional: the capture sees whatever crosses the interface.
+    let child = match args.command.split_first() {
+        Some((program, rest)) => Some(
+            std::process::Command::new(program)
+                .args(rest)
+                .spawn()
+                .map_err(|e| anyhow::anyhow!("failed to spawn {program:?}: {e}"))?,
+        ),
+        None => None,
+    };
+
+    if !globals.quiet {
+        eprintln!("phantom: pcap backend active");
+        eprintln!(
+            "  interface : {}",
+            args.interface.as_deref().unwrap_or("all")
+        );
+        if let Some(child) = &child {
+            eprintln!("  command   : {}", args.command.join(" "));
+            eprintln!("phantom: spawned PID {}", child.id());
+        }
+        eprintln!("phantom: traces stored in {}", globals.data_dir.display());
+    }
+
+    let mut child_status = None;
+    match args.output {
+        OutputMode::Tui => {
+            slo = phantom_tui::run_tui(store, events, None, &backend_name, slo).await?;
+            report_slo(&slo, globals.quiet);
+        }
+        OutputMode::Jsonl => {
+            let opts = jsonl_render_options(&args);
+            child_status = run_jsonl_output(
+                store,
+                events,
+                child.map(Target::Child),
+                &opts,
+                &mut slo,
+                globals.quiet,
+            )
+            .await?;
+        }
+    }
+
+    backend.stop().map_err(|e| anyhow::anyhow!("{e}"))?;
+    Ok(RunOutcome { child_status, slo })
+}
+
+#[cfg(windows)]
+pub async fn run_windivert(
+    globals: &GlobalOpts,
+    args: RunArgs,
+    store: Arc<dyn TraceStore>,
+) -> anyhow::Result<RunOutcome> {
+    use phantom_capture::WinDivertCaptureBackend;
+
+    let mut slo = build_slo_tracker(&args.slo)?;
+    reject_proxy_only_flags(&args)?;
+    if args.interface.is_some() {
+        anyhow::bail!("--interface is not supported by --backend windivert");
+    }
+
+    let mut backend = WinDivertCaptureBackend::new();
+    if let Some(keylog_path) = &args.keylog_file {
+        backend = backend.with_keylog(keylog_path.clone());
+    }
+    let backend_name = backend.name().to_string();
+    let events = backend.start().map_err(|e| anyhow::anyhow!("{e}"))?;
+
+    // A command is optional: the capture sees every connection on the host.
+    let child = match args.command.split_first() {
+        Some((program, rest)) => {
+            let mut command = std::process::Command::new(program);
+            command.args(rest);
+            backend.prepare(&mut command);
+            Some(
+                command
+                    .spawn()
+                    .map_err(|e| anyhow::anyhow!("failed to spawn {program:?}: {e}"))?,
+            )
+        }
+        None => None,
+    };
+
+    if !globals.quiet {
+        eprintln!("phantom: windivert backend active");
+        if let Some(keylog_path) = backend.keylog_path() {
+            eprintln!("  key log   : {}", keylog_path.display());
+        }
+        if let Some(child) = &child {
+            eprintln!("  command   : {}", args.command.join(" "));
+            eprintln!("phantom: spawned PID {}", child.id());
+        }
+        eprintln!("phantom: traces stored in {}", globals.data_dir.display());
+    }
+
+    let mut child_status = None;
+    match args.output {
+        OutputMode::Tui => {
+            slo = phantom_tui::run_tui(store, events, None, &backend_name, slo).await?;
+            report_slo(&slo, globals.quiet);
+        }
+        OutputMode::Jsonl => {
+            let opts = jsonl_render_options(&args);
+            child_status = run_jsonl_output(
+                store,
+                events,
+                child.map(Target::Child),
+                &opts,
+                &mut slo,
+                globals.quiet,
+            )
+            .await?;
+        }
+    }
+
+    backend.stop().map_err(|e| anyhow::anyhow!("{e}"))?;
+    Ok(RunOutcome { child_status, slo })
+}
+
+pub async fn run_accesslog(
+    globals: &GlobalOpts,
+    args: RunArgs,
+    store: Arc<dyn TraceStore>,
+) -> anyhow::Result<RunOutcome> {
+    let mut slo = build_slo_tracker(&args.slo)?;
+    reject_proxy_only_flags(&args)?;
+    let path = args.access_log.clone().ok_or_else(|| {
+        anyhow::anyhow!("--access-log <PATH> is required for --backend accesslog")
+    })?;
+
+    let mut backend = AccessLogCaptureBackend::new(path.clone());
+    let backend_name = backend.name().to_string();
+    let events = backend.start().map_err(|e| anyhow::anyhow!("{e}"))?;
+
+    // A command is optional: the proxy logs whatever passes through it.
+    let child = match args.command.split_first() {
+        Some((program, rest)) => Some(
+            std::process::Command::new(program)
+                .args(rest)
+                .spawn()
+                .map_err(|e| anyhow::anyhow!("failed to spawn {program:?}: {e}"))?,
+        ),
+        None => None,
+    };
+
+    if !globals.quiet {
+        eprintln!("phantom: accesslog backend active");
+        eprintln!("  following : {}", path.display());
+        if let Some(child) = &child {
+            eprintln!("  command   : {}", args.command.join(" "));
+            eprintln!("phantom: spawned PID {}", child.id());
+        }
+        eprintln!("phantom: traces stored in {}", globals.data_dir.display());
+    }
+
+    let mut child_status = None;
+    match args.output {
+        OutputMode::Tui => {
+            slo = phantom_tui::run_tui(store, events, None, &backend_name, slo).await?;
+            report_slo(&slo, globals.quiet);
+        }
+        OutputMode::Jsonl => {
+            let opts = jsonl_render_options(&args);
+            child_status = run_jsonl_output(
+                store,
+                events,
+                child.map(Target::Child),
+                &opts,
+                &mut slo,
+                globals.quiet,
+            )
+            .await?;
+        }
+    }
+
+    backend.stop().map_err(|e| anyhow::anyhow!("{e}"))?;
+    Ok(RunOutcome { child_status, slo })
+}
+
+pub async fn run_ingest(
+    globals: &GlobalOpts,
+    args: RunArgs,
+    store: Arc<dyn TraceStore>,
+) -> anyhow::Result<RunOutcome> {
+    let mut slo = build_slo_tracker(&args.slo)?;
+    reject_proxy_only_flags(&args)?;
+
+    let mut backend = IngestCaptureBackend::new(args.ingest_addr.clone());
+    let backend_name = backend.name().to_string();
+    let events = backend.start().map_err(|e| anyhow::anyhow!("{e}"))?;
+    let addr = backend
+        .local_addr()
+        .map_or_else(|| args.ingest_addr.clone(), |addr| addr.to_string());
+
+    // A command is optional: agents may run anywhere that reaches the port.
+    let child = match args.command.split_first() {
+        Some((program, rest)) => Some(
+            std::process::Command::new(program)
+                .args(rest)
+                .env("PHANTOM_INGEST_ADDR", &addr)
+                .spawn()
+                .map_err(|e| anyhow::anyhow!("failed to spawn {program:?}: {e}"))?,
+        ),
+        None => None,
+    };
+
+    if !globals.quiet {
+        eprintln!("phantom: ingest backend active");
+        eprintln!("  listening : {addr} (JSON lines, or HTTP POST {INGEST_PATH})");
+        if let Some(child) = &child {
+            eprintln!("  command   : {}", args.command.join(" "));
+            eprintln!("phantom: spawned PID {}", child.id());
+        }
+        eprintln!("phantom: traces stored in {}", globals.data_dir.display());
+    }
+
+    let mut child_status = None;
+    match args.output {
+        OutputMode::Tui => {
+            slo = phantom_tui::run_tui(store, events, None, &backend_name, slo).await?;
+            report_slo(&slo, globals.quiet);
+        }
+        OutputMode::Jsonl => {
+            let opts = jsonl_render_options(&args);
+            child_status = run_jsonl_output(
+                store,
+                events,
+                child.map(Target::Child),
+                &opts,
+                &mut slo,
+                globals.quiet,
+            )
+            .await?;
+        }
+    }
+
+    backend.stop().map_err(|e| anyhow::anyhow!("{e}"))?;
+    Ok(RunOutcome { child_status, slo })
+}
+
+/// `phantom attach`: loads the agent into a running process and streams what
+/// it captures until Ctrl-C, or in JSONL mode until the process exits.
+#[cfg(target_os = "linux")]
+pub async fn run_attach(
+    globals: &GlobalOpts,
+    args: AttachArgs,
+    store: Arc<dyn TraceStore>,
+) -> anyhow::Result<()> {
+    use phantom_capture::LdPreloadCaptureBackend;
+
+    // The agent keeps the socket path it was first given, so attaching to
+    // the same process again must listen at the same place.
+    let socket_path = std::env::temp_dir().join(format!("phantom-attach-{}.sock", args.pid));
+    let ipc_mode = ipc_mode_from_env()?;
+    let mut backend = LdPreloadCaptureBackend::new(socket_path.clone()).with_ipc_mode(ipc_mode);
+    let backend_name = backend.name().to_string();
+    let events = backend.start().map_err(|e| anyhow::anyhow!("{e}"))?;
+
+    let hooked = match backend.attach(args.pid, &args.agent_lib) {
+        Ok(hooked) => hooked,
+        Err(e) => {
+            let _ = backend.stop();
+            return Err(anyhow::anyhow!("{e}"));
+        }
+    };
+    if !globals.quiet {
+        eprintln!("phantom: attached to PID {}", args.pid);
+        eprintln!("  agent lib : {}", args.agent_lib.display());
+        eprintln!(
+            "  socket    : {} ({})",
+            socket_path.display(),
+            ipc_mode.as_str()
+        );
+        eprintln!("  hooked    : {hooked} call sites");
+        if hooked == 0 {
+            eprintln!(
+                "phantom: no libc socket calls found to hook; statically linked programs \
+                 cannot be traced this way"
+            );
+        }
+        eprintln!("phantom: traces stored in {}", globals.data_dir.display());
+    }
+
+    let mut slo = build_slo_tracker(&[])?;
+    match args.output {
+        OutputMode::Tui => {
+            phantom_tui::run_tui(store, events, None, &backend_name, slo).await?;
+        }
+        OutputMode::Jsonl => {
+            let opts = RenderOptions {
+                max_body: (args.max_body > 0).then_some(args.max_body),
+                headers_only: args.headers_only,
+                redact_headers: Vec::new(),
+            };
+            run_jsonl_output(
+                store,
+                events,
+                Some(Target::Attached(args.pid)),
+                &opts,
+                &mut slo,
+                globals.quiet,
+            )
+            .await?;
+        }
+    }
+
+    backend.stop().map_err(|e| anyhow::anyhow!("{e}"))
+}
+
+/// `phantom connect`: shows traces streamed by a remote phantom's serve sink
+/// until Ctrl-C.
+pub async fn run_connect(
+    globals: &GlobalOpts,
+    args: ConnectArgs,
+    store: Arc<dyn TraceStore>,
+) -> anyhow::Result<()> {
+    let mut backend = RemoteCaptureBackend::new(args.addr.clone());
+    let backend_name = backend.name().to_string();
+    let events = backend.start().map_err(|e| anyhow::anyhow!("{e}"))?;
+
+    if !globals.quiet {
+        eprintln!("phantom: reading traces from {}", args.addr);
+        eprintln!("phantom: traces stored in {}", globals.data_dir.display());
+    }
+
+    let mut slo = build_slo_tracker(&[])?;
+    match args.output {
+        OutputMode::Tui => {
+            phantom_tui::run_tui(store, events, None, &backend_name, slo).await?;
+        }
+        OutputMode::Jsonl => {
+            let opts = RenderOptions {
+                max_body: (args.max_body > 0).then_some(args.max_body),
+                headers_only: args.headers_only,
+                redact_headers: Vec::new(),
+            };
+            run_jsonl_output(store, events, None, &opts, &mut slo, globals.quiet).await?;
+        }
+    }
+
+    backend.stop().map_err(|e| anyhow::anyhow!("{e}"))
 }
@@ -1,15 +1,21 @@
 mod cli;
 mod commands;
 mod mcp;
+mod otlp;
 mod runner;
+mod stream;
 
 use std::process::ExitCode;
 use std::sync::Arc;
 
 use clap::Parser;
-use phantom_storage::FjallTraceStore;
+use phantom_core::sink::TeeTraceStore;
+use phantom_storage::{AuditLog, AuditedTraceStore, DataStore};
 
-use cli::{Backend, Cli, Commands, GlobalOpts, default_data_dir};
+use cli::{AuditCommand, Backend, CaCommand, Cli, Commands, GlobalOpts, default_data_dir};
+
+/// Exit code for `phantom run --check` when an SLO is breached.
+const SLO_BREACH_EXIT_CODE: u8 = 3;
 
 /// Maps a child process's exit status onto our own exit code:
 /// the child's code clamped to u8, or 128+signal on Unix signal death.
@@ -29,8 +35,11 @@ fn exit_code_from_status(status: std::process::ExitStatus) -> ExitCode {
 
 /// Opens the trace store for a query command, adding a hint about fjall's
 /// single-process lock when another phantom instance holds it.
-fn open_store_for_query(data_dir: &std::path::Path) -> anyhow::Result<FjallTraceStore> {
-    FjallTraceStore::open(data_dir).map_err(|e| {
+fn open_store_for_query(
+    data_dir: &std::path::Path,
+    partition_by_day: bool,
+) -> anyhow::Result<DataStore> {
+    DataStore::open(data_dir, partition_by_day).map_err(|e| {
         anyhow::anyhow!(
             "{e}\n\
              hint: another phantom process (run/mcp) may hold the store lock on\n\
@@ -59,6 +68,7 @@ async fn main() -> anyhow::Result<ExitCode> {
         .init();
 
     let data_dir = cli.data_dir.clone().unwrap_or_else(default_data_dir);
+    let partition_by_day = cli.partition_by_day;
     std::fs::create_dir_all(&data_dir)?;
     let globals = GlobalOpts {
         quiet: cli.quiet,
@@ -67,29 +77,54 @@ async fn main() -> anyhow::Result<ExitCode> {
 
     match cli.command {
         Commands::Run(args) => {
-            let store = Arc::new(FjallTraceStore::open(&data_dir)?);
-            let child_status = match args.backend {
+            let args = *args;
+            let mut store = DataStore::open(&data_dir, partition_by_day)?.trace_store();
+            if args.audit || args.audit_key.is_some() {
+                let log = AuditLog::open(data_dir.join("audit.jsonl"), args.audit_key.as_deref())?;
+                store = Arc::new(AuditedTraceStore::new(store, log));
+            }
+            if !args.sink.is_empty() {
+                let sinks = runner::build_sinks(&args.sink, partition_by_day)?;
+                store = Arc::new(TeeTraceStore::new(store, sinks));
+            }
+            let check = args.check;
+            let outcome = match args.backend {
                 Backend::Proxy => commands::run::run_proxy(&globals, args, store).await?,
                 #[cfg(target_os = "linux")]
                 Backend::Ldpreload => commands::run::run_ldpreload(&globals, args, store).await?,
+                #[cfg(target_os = "linux")]
+                Backend::Ebpf => commands::run::run_ebpf(&globals, args, store).await?,
+                #[cfg(target_os = "linux")]
+                Backend::Keylog => commands::run::run_keylog(&globals, args, store).await?,
+                #[cfg(target_os = "linux")]
+                Backend::Pcap => commands::run::run_pcap(&globals, args, store).await?,
+                #[cfg(windows)]
+                Backend::Windivert => commands::run::run_windivert(&globals, args, store).await?,
+                Backend::Accesslog => commands::run::run_accesslog(&globals, args, store).await?,
+                Backend::Ingest => commands::run::run_ingest(&globals, args, store).await?,
             };
-            Ok(child_status
+            let child_failed = outcome.child_status.is_some_and(|s| !s.success());
+            if check && !child_failed && outcome.slo.any_breached() {
+                return Ok(ExitCode::from(SLO_BREACH_EXIT_CODE));
+            }
+            Ok(outcome
+                .child_status
                 .map(exit_code_from_status)
                 .unwrap_or(ExitCode::SUCCESS))
         }
         Commands::List(args) => {
-            let store = open_store_for_query(&data_dir)?;
-            commands::query::list(&store, args)?;
+            let store = open_store_for_query(&data_dir, partition_by_day)?.trace_store();
+            commands::query::list(&*store, args)?;
             Ok(ExitCode::SUCCESS)
         }
         Commands::Search(args) => {
-            let store = open_store_for_query(&data_dir)?;
-            commands::query::search(&store, args)?;
+            let store = open_store_for_query(&data_dir, partition_by_day)?.trace_store();
+            commands::query::search(&*store, args)?;
             Ok(ExitCode::SUCCESS)
         }
         Commands::Get(args) => {
-            let store = open_store_for_query(&data_dir)?;
-            let found = commands::query::get(&store, args)?;
+            let store = open_store_for_query(&data_dir, partition_by_day)?.trace_store();
+            let found = commands::query::get(&*store, args)?;
             Ok(if found {
                 ExitCode::SUCCESS
             } else {
@@ -97,21 +132,78 @@ async fn main() -> anyhow::Result<ExitCode> {
             })
         }
         Commands::Stats => {
-            let store = open_store_for_query(&data_dir)?;
-            commands::query::stats(&store, &data_dir)?;
+            let store = open_store_for_query(&data_dir, partition_by_day)?.trace_store();
+            commands::query::stats(&*store, &data_dir)?;
             Ok(ExitCode::SUCCESS)
         }
         Commands::Clear(args) => {
-            let store = open_store_for_query(&data_dir)?;
-            let cleared = commands::query::clear(&store, args.yes, globals.quiet)?;
+            let store = open_store_for_query(&data_dir, partition_by_day)?.trace_store();
+            let cleared = commands::query::clear(&*store, args.yes, globals.quiet)?;
+            if cleared {
+                // Overflow bodies belong to the deleted traces.
+                let _ = std::fs::remove_dir_all(data_dir.join("bodies"));
+            }
             Ok(if cleared {
                 ExitCode::SUCCESS
             } else {
                 ExitCode::FAILURE
             })
         }
+        Commands::Report(args) => {
+            let store = open_store_for_query(&data_dir, partition_by_day)?.trace_store();
+            commands::report::report(&*store, args, globals.quiet)?;
+            Ok(ExitCode::SUCCESS)
+        }
+        Commands::Export(args) => {
+            let store = open_store_for_query(&data_dir, partition_by_day)?.trace_store();
+            commands::export::export(&*store, args, globals.quiet)?;
+            Ok(ExitCode::SUCCESS)
+        }
+        Commands::Maintenance(args) => {
+            let store = open_store_for_query(&data_dir, partition_by_day)?;
+            commands::maintenance::maintenance(&store, args, globals.quiet)?;
+            Ok(ExitCode::SUCCESS)
+        }
+        Commands::Audit(AuditCommand::Verify { path, public_key }) => {
+            let path = path.unwrap_or_else(|| data_dir.join("audit.jsonl"));
+            let verified = commands::audit::verify(&path, public_key.as_deref())?;
+            Ok(if verified {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            })
+        }
+        Commands::Audit(AuditCommand::Keygen { path }) => {
+            commands::audit::keygen(&path, globals.quiet)?;
+            Ok(ExitCode::SUCCESS)
+        }
+        Commands::Ca(CaCommand::Export { out, install }) => {
+            commands::ca::export(&data_dir, out.as_deref(), install, globals.quiet)?;
+            Ok(ExitCode::SUCCESS)
+        }
+        Commands::Mock(args) => {
+            let store = open_store_for_query(&data_dir, partition_by_day)?.trace_store();
+            commands::mock::mock(store, args, globals.quiet).await?;
+            Ok(ExitCode::SUCCESS)
+        }
+        Commands::Import(args) => {
+            let store = open_store_for_query(&data_dir, partition_by_day)?.trace_store();
+            commands::import::import(&*store, args, globals.quiet)?;
+            Ok(ExitCode::SUCCESS)
+        }
+        #[cfg(target_os = "linux")]
+        Commands::Attach(args) => {
+            let store = DataStore::open(&data_dir, partition_by_day)?.trace_store();
+            commands::run::run_attach(&globals, args, store).await?;
+            Ok(ExitCode::SUCCESS)
+        }
+        Commands::Connect(args) => {
+            let store = DataStore::open(&data_dir, partition_by_day)?.trace_store();
+            commands::run::run_connect(&globals, args, store).await?;
+            Ok(ExitCode::SUCCESS)
+        }
         Commands::Mcp => {
-            let store = Arc::new(open_store_for_query(&data_dir)?);
+            let store = open_store_for_query(&data_dir, partition_by_day)?.trace_store();
             mcp::run_mcp(store, data_dir).await?;
             Ok(ExitCode::SUCCESS)
         }
@@ -10,11 +10,14 @@ use rmcp::{
 };
 use serde::Deserialize;
 
+use phantom_core::connection::ConnectionStats;
 use phantom_core::query::TraceQuery;
+use phantom_core::stitch::query_stitched;
 use phantom_core::storage::TraceStore;
 use phantom_core::trace::{SpanId, TraceId};
 use phantom_core::view::{RenderOptions, TraceView};
-use phantom_storage::FjallTraceStore;
+
+use crate::commands::query::STATS_WINDOW;
 
 use super::session::{CaptureManager, ChildState, SessionStatus};
 
@@ -44,7 +47,8 @@ pub struct StartCaptureParams {
     /// Disable TLS verification toward backend servers (self-signed certs).
     #[serde(default)]
     pub insecure: bool,
-    /// Fault injection specs, e.g. "delay:100ms", "error:503:0.5:/api".
+    /// Fault injection specs, e.g. "delay:100ms", "error:503:0.5:/api",
+    /// "reset:0.1", "truncate", "timeout:30s".
     #[serde(default)]
     pub fault: Vec<String>,
 }
@@ -74,7 +78,8 @@ pub struct ListTracesParams {
     pub since_ms: Option<u64>,
     /// Only traces at or before this Unix-epoch-milliseconds timestamp.
     pub until_ms: Option<u64>,
-    /// Only spans of this 32-char hex trace ID.
+    /// Only spans of this 32-char hex trace ID, plus downstream hops captured
+    /// within its time window (stitched in with parent_span_id set).
     pub trace_id: Option<String>,
     /// Maximum traces to return (default 20).
     pub limit: Option<u32>,
@@ -117,7 +122,7 @@ pub struct ClearTracesParams {
 
 #[derive(Clone)]
 pub struct PhantomMcp {
-    store: Arc<FjallTraceStore>,
+    store: Arc<dyn TraceStore>,
     sessions: Arc<CaptureManager>,
     data_dir: PathBuf,
     tool_router: ToolRouter<Self>,
@@ -179,7 +184,7 @@ fn render_options(
 #[tool_router(router = tool_router)]
 impl PhantomMcp {
     pub fn new(
-        store: Arc<FjallTraceStore>,
+        store: Arc<dyn TraceStore>,
         sessions: Arc<CaptureManager>,
         data_dir: PathBuf,
     ) -> Self {
@@ -195,12 +200,10 @@ impl PhantomMcp {
     async fn query_store<T, F>(&self, f: F) -> Result<T, McpError>
     where
         T: Send + 'static,
-        F: FnOnce(&FjallTraceStore) -> Result<T, phantom_core::error::StorageError>
-            + Send
-            + 'static,
+        F: FnOnce(&dyn TraceStore) -> Result<T, phantom_core::error::StorageError> + Send + 'static,
     {
         let store = self.store.clone();
-        tokio::task::spawn_blocking(move || f(&store))
+        tokio::task::spawn_blocking(move || f(store.as_ref()))
             .await
             .map_err(internal_error)?
             .map_err(internal_error)
@@ -215,7 +218,14 @@ impl PhantomMcp {
     ) -> Result<CallToolResult, McpError> {
         let status = self
             .sessions
-            .start(self.store.clone(), p.command, p.port, p.insecure, &p.fault)
+            .start(
+                self.store.clone(),
+                p.command,
+                p.port,
+                p.insecure,
+                &p.fault,
+                &self.data_dir,
+            )
             .await
             .map_err(internal_error)?;
         json_result(session_status_json(&status))
@@ -289,7 +299,7 @@ impl PhantomMcp {
             limit: p.limit.unwrap_or(20) as usize,
             offset: p.offset.unwrap_or(0) as usize,
         };
-        let traces = self.query_store(move |s| s.query(&query)).await?;
+        let traces = self.query_store(move |s| query_stitched(s, &query)).await?;
 
         let opts = render_options(p.max_body, 256, p.headers_only, p.redact_sensitive_headers);
         let views: Vec<TraceView> = traces.iter().map(|t| TraceView::render(t, &opts)).collect();
@@ -315,14 +325,16 @@ impl PhantomMcp {
     }
 
     #[tool(
-        description = "Trace store statistics: total stored traces (approximate), data directory, number of active capture sessions."
+        description = "Trace store statistics: total stored traces (approximate), data directory, number of active capture sessions, and connection reuse over the 10,000 most recent traces (requests per connection, single-request connections, new connections per second — low reuse means broken keep-alive or pool churn)."
     )]
     async fn get_stats(&self) -> Result<CallToolResult, McpError> {
         let total = self.query_store(|s| s.count()).await?;
+        let recent = self.query_store(|s| s.list_recent(STATS_WINDOW, 0)).await?;
         json_result(serde_json::json!({
             "total_traces": total,
             "data_dir": self.data_dir.display().to_string(),
             "active_sessions": self.sessions.active_count(),
+            "connections": ConnectionStats::from_traces(&recent),
         }))
     }
 
@@ -351,7 +363,7 @@ impl ServerHandler for PhantomMcp {
 
 /// Serves MCP over stdio until the client disconnects, then tears down all
 /// capture sessions.
-pub async fn run_mcp(store: Arc<FjallTraceStore>, data_dir: PathBuf) -> anyhow::Result<()> {
+pub async fn run_mcp(store: Arc<dyn TraceStore>, data_dir: PathBuf) -> anyhow::Result<()> {
     let sessions = Arc::new(CaptureManager::default());
     let server = PhantomMcp::new(store, sessions.clone(), data_dir);
     let service = server
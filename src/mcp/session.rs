@@ -1,15 +1,17 @@
 use std::collections::HashMap;
 use std::net::{IpAddr, Ipv4Addr};
+use std::path::Path;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::{SystemTime, UNIX_EPOCH};
 
-use phantom_capture::ProxyCaptureBackend;
-use phantom_core::capture::CaptureBackend;
+use phantom_capture::{ProxyCa, ProxyCaptureBackend};
+use phantom_core::capture::{CaptureBackend, TraceEvent};
 use phantom_core::storage::TraceStore;
-use phantom_storage::FjallTraceStore;
 
-use crate::runner::{TempScript, build_fault_config, spawn_proxy_child, wait_for_proxy};
+use crate::runner::{
+    TempScript, build_fault_config, build_upstream_proxy, spawn_proxy_child, wait_for_proxy,
+};
 
 /// Lifecycle state of a capture session's traced child process.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -83,13 +85,16 @@ fn free_port() -> anyhow::Result<u16> {
 impl CaptureManager {
     /// Starts a proxy capture session, optionally spawning `command` through it.
     /// Traces are pumped into `store`; the session runs until `stop()`.
+    /// Bodies over the capture limit are saved in full under
+    /// `<data_dir>/bodies`; HTTPS is signed with the CA kept in `data_dir`.
     pub async fn start(
         &self,
-        store: Arc<FjallTraceStore>,
+        store: Arc<dyn TraceStore>,
         command: Vec<String>,
         port: Option<u16>,
         insecure: bool,
         fault: &[String],
+        data_dir: &Path,
     ) -> anyhow::Result<SessionStatus> {
         let port = match port {
             Some(p) => p,
@@ -100,9 +105,15 @@ impl CaptureManager {
         // is no --bind flag in this mode.
         let bind_ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
         let fault_config = build_fault_config(fault)?;
-        let mut backend =
-            ProxyCaptureBackend::new(bind_ip, port, insecure).with_faults(fault_config);
-        let mut trace_rx = backend.start().map_err(|e| anyhow::anyhow!("{e}"))?;
+        let ca = ProxyCa::load_or_create(data_dir).map_err(|e| anyhow::anyhow!("{e}"))?;
+        let mut backend = ProxyCaptureBackend::new(bind_ip, port, insecure)
+            .with_faults(fault_config)
+            .with_body_dir(data_dir.join("bodies"))
+            .with_ca(ca);
+        if let Some(upstream) = build_upstream_proxy(None, port)? {
+            backend = backend.with_upstream_proxy(upstream);
+        }
+        let mut events = backend.start().map_err(|e| anyhow::anyhow!("{e}"))?;
         wait_for_proxy(bind_ip, port).await?;
 
         // Pump captured traces into the store off the async executor.
@@ -110,7 +121,12 @@ impl CaptureManager {
         let pump_count = trace_count.clone();
         let pump_store = store.clone();
         tokio::spawn(async move {
-            while let Some(trace) = trace_rx.recv().await {
+            while let Some(event) = events.recv().await {
+                // The proxy reports HTTP traces only.
+                let TraceEvent::Http(mut trace) = event else {
+                    continue;
+                };
+                trace.tag_operations();
                 let store = pump_store.clone();
                 let insert = tokio::task::spawn_blocking(move || store.insert(&trace)).await;
                 match insert {
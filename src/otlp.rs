@@ -0,0 +1,385 @@
+//! OTLP/HTTP (JSON) trace export for `--sink otlp:URL`.
+//!
+//! Spans are handed to a background thread over a channel and POSTed in
+//! batches, so a slow or unreachable collector never stalls capture.
+//! Only plain `http://` endpoints are supported — point it at a local
+//! OpenTelemetry Collector for TLS/auth to the final backend.
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::sync::Mutex;
+use std::sync::mpsc::{Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::thread::JoinHandle;
+use std::time::{Duration, UNIX_EPOCH};
+
+use phantom_core::error::StorageError;
+use phantom_core::report::normalize_endpoint;
+use phantom_core::sink::TraceSink;
+use phantom_core::trace::HttpTrace;
+use serde_json::{Value, json};
+use tracing::warn;
+
+/// Spans per export request.
+const BATCH_SIZE: usize = 256;
+/// Longest a span waits in a partial batch before being sent.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+/// Spans buffered while the exporter is busy; further spans are dropped.
+const QUEUE_CAPACITY: usize = 8192;
+/// Connect and read/write timeout for each export request.
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// OTLP/HTTP default port and trace path.
+const DEFAULT_PORT: u16 = 4318;
+const DEFAULT_PATH: &str = "/v1/traces";
+
+/// Host, port and path of an OTLP/HTTP endpoint.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Endpoint {
+    host: String,
+    port: u16,
+    path: String,
+}
+
+/// Parses `http://host[:port][/path]`; the path defaults to `/v1/traces`.
+fn parse_endpoint(url: &str) -> Result<Endpoint, String> {
+    let rest = url.strip_prefix("http://").ok_or_else(|| {
+        format!("unsupported OTLP endpoint {url:?}: only http:// URLs are supported")
+    })?;
+    let (authority, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, ""),
+    };
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => (
+            host,
+            port.parse::<u16>()
+                .map_err(|_| format!("invalid port in OTLP endpoint {url:?}"))?,
+        ),
+        None => (authority, DEFAULT_PORT),
+    };
+    if host.is_empty() {
+        return Err(format!("missing host in OTLP endpoint {url:?}"));
+    }
+    let path = if path.is_empty() || path == "/" {
+        DEFAULT_PATH.to_string()
+    } else {
+        path.to_string()
+    };
+    Ok(Endpoint {
+        host: host.to_string(),
+        port,
+        path,
+    })
+}
+
+fn unix_nanos(ts: std::time::SystemTime) -> u128 {
+    ts.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0)
+}
+
+fn string_attr(key: &str, value: &str) -> Value {
+    json!({ "key": key, "value": { "stringValue": value } })
+}
+
+/// Converts a trace into an OTLP JSON span (client kind, HTTP semantic
+/// conventions). 4xx/5xx responses get an error status.
+fn encode_span(trace: &HttpTrace) -> Value {
+    let start = unix_nanos(trace.timestamp);
+    let end = start + trace.duration.as_nanos();
+    let normalized = normalize_endpoint(&trace.url);
+    let route = normalized.split_once('/').map_or("", |(_, path)| path);
+
+    let mut span = json!({
+        "traceId": trace.trace_id.to_string(),
+        "spanId": trace.span_id.to_string(),
+        "name": format!("{} /{route}", trace.method),
+        "kind": 3,
+        // OTLP JSON encodes 64-bit integers as strings.
+        "startTimeUnixNano": start.to_string(),
+        "endTimeUnixNano": end.to_string(),
+        "attributes": [
+            string_attr("http.request.method", &trace.method.to_string()),
+            string_attr("url.full", &trace.url),
+            json!({
+                "key": "http.response.status_code",
+                "value": { "intValue": trace.status_code.to_string() },
+            }),
+            string_attr("network.protocol.version", &trace.protocol_version),
+        ],
+    });
+    if let Some(parent) = &trace.parent_span_id {
+        span["parentSpanId"] = json!(parent.to_string());
+    }
+    if trace.status_code >= 400 {
+        span["status"] = json!({ "code": 2 });
+    }
+    span
+}
+
+/// Wraps spans in the `ExportTraceServiceRequest` envelope.
+fn encode_request(spans: Vec<Value>) -> Value {
+    json!({
+        "resourceSpans": [{
+            "resource": { "attributes": [string_attr("service.name", "phantom")] },
+            "scopeSpans": [{
+                "scope": { "name": "phantom", "version": env!("CARGO_PKG_VERSION") },
+                "spans": spans,
+            }],
+        }],
+    })
+}
+
+/// POSTs one export request and checks for a 2xx response.
+fn post(endpoint: &Endpoint, body: &[u8]) -> std::io::Result<()> {
+    use std::net::ToSocketAddrs;
+
+    let addr = (endpoint.host.as_str(), endpoint.port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| std::io::Error::other("endpoint did not resolve"))?;
+    let mut stream = TcpStream::connect_timeout(&addr, IO_TIMEOUT)?;
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+    write!(
+        stream,
+        "POST {} HTTP/1.1\r\nHost: {}:{}\r\nContent-Type: application/json\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n",
+        endpoint.path,
+        endpoint.host,
+        endpoint.port,
+        body.len()
+    )?;
+    stream.write_all(body)?;
+
+    let mut status_line = [0u8; 12];
+    stream.read_exact(&mut status_line)?;
+    let status = std::str::from_utf8(&status_line[9..12])
+        .ok()
+        .and_then(|s| s.parse::<u16>().ok())
+        .unwrap_or(0);
+    if !(200..300).contains(&status) {
+        return Err(std::io::Error::other(format!(
+            "collector returned HTTP {status}"
+        )));
+    }
+    Ok(())
+}
+
+fn export_loop(endpoint: Endpoint, rx: Receiver<Value>) {
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+    loop {
+        let disconnected = match rx.recv_timeout(FLUSH_INTERVAL) {
+            Ok(span) => {
+                batch.push(span);
+                if batch.len() < BATCH_SIZE {
+                    continue;
+                }
+                false
+            }
+            Err(RecvTimeoutError::Timeout) => false,
+            Err(RecvTimeoutError::Disconnected) => true,
+        };
+        if !batch.is_empty() {
+            let count = batch.len();
+            let body = encode_request(std::mem::take(&mut batch)).to_string();
+            if let Err(e) = post(&endpoint, body.as_bytes()) {
+                warn!(
+                    "OTLP export to {}:{}{} failed, {count} spans dropped: {e}",
+                    endpoint.host, endpoint.port, endpoint.path
+                );
+            }
+        }
+        if disconnected {
+            return;
+        }
+    }
+}
+
+/// A [`TraceSink`] exporting spans to an OpenTelemetry collector.
+///
+/// Dropping the sink flushes the pending batch and waits for the exporter.
+pub struct OtlpSink {
+    name: String,
+    tx: Mutex<Option<SyncSender<Value>>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl OtlpSink {
+    pub fn new(url: &str) -> anyhow::Result<Self> {
+        let endpoint = parse_endpoint(url).map_err(|e| anyhow::anyhow!(e))?;
+        let (tx, rx) = std::sync::mpsc::sync_channel(QUEUE_CAPACITY);
+        let worker = std::thread::Builder::new()
+            .name("phantom-otlp".into())
+            .spawn(move || export_loop(endpoint, rx))?;
+        Ok(Self {
+            name: format!("otlp:{url}"),
+            tx: Mutex::new(Some(tx)),
+            worker: Some(worker),
+        })
+    }
+}
+
+impl TraceSink for OtlpSink {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn write(&self, trace: &HttpTrace) -> Result<(), StorageError> {
+        let tx = self
+            .tx
+            .lock()
+            .map_err(|_| StorageError::Write("OTLP sink lock poisoned".into()))?;
+        let Some(tx) = tx.as_ref() else {
+            return Err(StorageError::Write("OTLP exporter stopped".into()));
+        };
+        match tx.try_send(encode_span(trace)) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => Err(StorageError::Write(
+                "OTLP export queue full, span dropped".into(),
+            )),
+            Err(TrySendError::Disconnected(_)) => {
+                Err(StorageError::Write("OTLP exporter stopped".into()))
+            }
+        }
+    }
+}
+
+impl Drop for OtlpSink {
+    fn drop(&mut self) {
+        // Closing the channel makes the exporter send its last batch and exit.
+        if let Ok(mut tx) = self.tx.lock() {
+            tx.take();
+        }
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::net::TcpListener;
+
+    use phantom_core::trace::{HttpMethod, SpanId, TraceId};
+
+    use super::*;
+
+    fn make_trace(status: u16) -> HttpTrace {
+        HttpTrace {
+            span_id: SpanId([0xab; 8]),
+            trace_id: TraceId([0x01; 16]),
+            parent_span_id: Some(SpanId([0xcd; 8])),
+            method: HttpMethod::Post,
+            url: "https://api.test/users/42/orders?x=1".to_string(),
+            request_headers: HashMap::new(),
+            request_body: None,
+            status_code: status,
+            response_headers: HashMap::new(),
+            response_body: None,
+            timestamp: UNIX_EPOCH + Duration::from_secs(10),
+            duration: Duration::from_millis(250),
+            source_addr: None,
+            dest_addr: None,
+            protocol_version: "HTTP/1.1".to_string(),
+            request_body_file: None,
+            response_body_file: None,
+            connection_id: None,
+            process: None,
+            response_trailers: HashMap::new(),
+            request_body_encoding: None,
+            response_body_encoding: None,
+            elasticsearch: None,
+            soap: None,
+            graphql: None,
+            thread_id: None,
+            tls: None,
+            timing: None,
+            request_size: None,
+            response_size: None,
+            fault: None,
+            replay: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_endpoint() {
+        assert_eq!(
+            parse_endpoint("http://collector").unwrap(),
+            Endpoint {
+                host: "collector".into(),
+                port: 4318,
+                path: "/v1/traces".into()
+            }
+        );
+        assert_eq!(
+            parse_endpoint("http://127.0.0.1:9999/custom/traces").unwrap(),
+            Endpoint {
+                host: "127.0.0.1".into(),
+                port: 9999,
+                path: "/custom/traces".into()
+            }
+        );
+        assert!(parse_endpoint("https://collector").is_err());
+        assert!(parse_endpoint("http://:4318").is_err());
+        assert!(parse_endpoint("http://host:notaport").is_err());
+    }
+
+    #[test]
+    fn test_encode_span() {
+        let span = encode_span(&make_trace(503));
+        assert_eq!(span["traceId"], "01".repeat(16));
+        assert_eq!(span["spanId"], "ab".repeat(8));
+        assert_eq!(span["parentSpanId"], "cd".repeat(8));
+        assert_eq!(span["name"], "POST /users/:id/orders");
+        assert_eq!(span["startTimeUnixNano"], "10000000000");
+        assert_eq!(span["endTimeUnixNano"], "10250000000");
+        assert_eq!(span["status"]["code"], 2);
+        assert_eq!(span["attributes"][2]["value"]["intValue"], "503");
+
+        let ok = encode_span(&make_trace(200));
+        assert!(ok.get("status").is_none());
+    }
+
+    #[test]
+    fn test_sink_flushes_on_drop() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let (mut conn, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 4096];
+            // Read until the full body (per Content-Length) has arrived.
+            loop {
+                let n = conn.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..n]);
+                let text = String::from_utf8_lossy(&request).to_string();
+                if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                    let len: usize = head
+                        .lines()
+                        .find_map(|l| l.strip_prefix("Content-Length: "))
+                        .unwrap()
+                        .parse()
+                        .unwrap();
+                    if body.len() >= len {
+                        conn.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+                            .unwrap();
+                        return text;
+                    }
+                }
+            }
+        });
+
+        let sink = OtlpSink::new(&format!("http://127.0.0.1:{port}")).unwrap();
+        sink.write(&make_trace(200)).unwrap();
+        sink.write(&make_trace(500)).unwrap();
+        drop(sink);
+
+        let request = server.join().unwrap();
+        assert!(request.starts_with("POST /v1/traces HTTP/1.1\r\n"));
+        let body: Value = serde_json::from_str(request.split_once("\r\n\r\n").unwrap().1).unwrap();
+        let spans = &body["resourceSpans"][0]["scopeSpans"][0]["spans"];
+        assert_eq!(spans.as_array().unwrap().len(), 2);
+    }
+}
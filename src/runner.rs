@@ -1,7 +1,20 @@
 use std::net::IpAddr;
 use std::path::{Path, PathBuf};
 
-use phantom_capture::FaultConfig;
+use phantom_capture::mock::MatchOptions;
+use phantom_capture::{
+    ClientAllowlist, ClientCertConfig, FaultConfig, InterceptConfig, MockRoutes, ShapingConfig,
+    UpstreamProxy,
+};
+use phantom_core::error::StorageError;
+use phantom_core::query::TraceQuery;
+use phantom_core::sink::{SinkTarget, StoreSink, TraceSink, parse_sink_spec};
+use phantom_core::slo::SloTracker;
+use phantom_core::trace::{HttpTrace, ReplayOutcome};
+use phantom_storage::{DataStore, JsonlFileSink};
+
+use crate::otlp::OtlpSink;
+use crate::stream::StreamSink;
 
 // ─────────────────────────────────────────────────────────────────────────────
 // Embedded injection assets
@@ -215,3 +228,176 @@ pub fn build_fault_config(specs: &[String]) -> anyhow::Result<FaultConfig> {
     }
     Ok(FaultConfig { rules })
 }
+
+/// Parse repeated `--throttle SPEC` flags into a `ShapingConfig`.
+pub fn build_shaping_config(specs: &[String]) -> anyhow::Result<ShapingConfig> {
+    let mut rules = Vec::new();
+    for spec in specs {
+        let rule = phantom_capture::parse_shaping_spec(spec)
+            .map_err(|e| anyhow::anyhow!("--throttle {spec:?}: {e}"))?;
+        rules.push(rule);
+    }
+    Ok(ShapingConfig { rules })
+}
+
+/// Parse repeated `--allow-client CIDR` flags into a `ClientAllowlist`.
+pub fn build_client_allowlist(specs: &[String]) -> anyhow::Result<ClientAllowlist> {
+    let mut ranges = Vec::new();
+    for spec in specs {
+        let range = phantom_capture::parse_cidr(spec)
+            .map_err(|e| anyhow::anyhow!("--allow-client {spec:?}: {e}"))?;
+        ranges.push(range);
+    }
+    Ok(ClientAllowlist { ranges })
+}
+
+/// Parse repeated `--client-cert SPEC` flags into a `ClientCertConfig`.
+pub fn build_client_cert_config(specs: &[String]) -> anyhow::Result<ClientCertConfig> {
+    let mut rules = Vec::new();
+    for spec in specs {
+        let rule = phantom_capture::parse_client_cert_spec(spec)
+            .map_err(|e| anyhow::anyhow!("--client-cert {spec:?}: {e}"))?;
+        rules.push(rule);
+    }
+    Ok(ClientCertConfig { rules })
+}
+
+/// Parse repeated `--intercept PATTERN` flags into an `InterceptConfig`.
+pub fn build_intercept_config(specs: &[String]) -> anyhow::Result<InterceptConfig> {
+    let mut rules = Vec::new();
+    for spec in specs {
+        let rule = phantom_capture::parse_intercept_spec(spec)
+            .map_err(|e| anyhow::anyhow!("--intercept {spec:?}: {e}"))?;
+        rules.push(rule);
+    }
+    Ok(InterceptConfig { rules })
+}
+
+/// The upstream proxy from `--upstream-proxy URL`, else from `HTTPS_PROXY` /
+/// `HTTP_PROXY` / `ALL_PROXY`; `NO_PROXY` applies to both. An environment
+/// proxy pointing back at phantom's own port (e.g. exported for an earlier
+/// capture) is ignored rather than looping.
+pub fn build_upstream_proxy(
+    flag: Option<&str>,
+    port: u16,
+) -> anyhow::Result<Option<UpstreamProxy>> {
+    let is_self = |upstream: &UpstreamProxy| {
+        let (host, upstream_port) = upstream.addr();
+        let loopback = host == "localhost"
+            || host
+                .parse::<IpAddr>()
+                .is_ok_and(|ip| ip.is_loopback() || ip.is_unspecified());
+        loopback && upstream_port == port
+    };
+    if let Some(url) = flag {
+        let upstream = UpstreamProxy::parse(url)
+            .map_err(|e| anyhow::anyhow!("--upstream-proxy: {e}"))?
+            .with_env_no_proxy();
+        if is_self(&upstream) {
+            anyhow::bail!("--upstream-proxy {url:?} points at phantom's own port {port}");
+        }
+        return Ok(Some(upstream));
+    }
+    let upstream = UpstreamProxy::from_env().map_err(|e| anyhow::anyhow!("{e}"))?;
+    Ok(upstream.filter(|upstream| {
+        let looped = is_self(upstream);
+        if looped {
+            tracing::warn!("ignoring proxy environment pointing at phantom itself (port {port})");
+        }
+        !looped
+    }))
+}
+
+/// Parse repeated `--slo SPEC` flags into an `SloTracker`.
+pub fn build_slo_tracker(specs: &[String]) -> anyhow::Result<SloTracker> {
+    let mut slos = Vec::new();
+    for spec in specs {
+        let slo = phantom_core::slo::parse_slo_spec(spec)
+            .map_err(|e| anyhow::anyhow!("--slo {spec:?}: {e}"))?;
+        slos.push(slo);
+    }
+    Ok(SloTracker::new(slos))
+}
+
+/// Parse repeated `--sink SPEC` flags and open each destination.
+pub fn build_sinks(
+    specs: &[String],
+    partition_by_day: bool,
+) -> anyhow::Result<Vec<(TraceQuery, Box<dyn TraceSink>)>> {
+    let mut sinks = Vec::new();
+    for spec in specs {
+        let parsed = parse_sink_spec(spec).map_err(|e| anyhow::anyhow!("--sink {spec:?}: {e}"))?;
+        let sink: Box<dyn TraceSink> = match &parsed.target {
+            SinkTarget::Jsonl(path) => Box::new(
+                JsonlFileSink::open(path).map_err(|e| anyhow::anyhow!("--sink {spec:?}: {e}"))?,
+            ),
+            SinkTarget::Otlp(url) => {
+                Box::new(OtlpSink::new(url).map_err(|e| anyhow::anyhow!("--sink {spec:?}: {e}"))?)
+            }
+            SinkTarget::Store(dir) => {
+                std::fs::create_dir_all(dir)?;
+                let store = DataStore::open(dir, partition_by_day)
+                    .map_err(|e| anyhow::anyhow!("--sink {spec:?}: {e}"))?;
+                Box::new(StoreSink::new(
+                    format!("store:{}", dir.display()),
+                    store.trace_store(),
+                ))
+            }
+            SinkTarget::Serve(addr) => Box::new(
+                StreamSink::bind(addr).map_err(|e| anyhow::anyhow!("--sink {spec:?}: {e}"))?,
+            ),
+        };
+        sinks.push((parsed.filter, sink));
+    }
+    Ok(sinks)
+}
+
+/// Adds requests `--replay` forwarded upstream to the recording, so the next
+/// run replays them too. Responses shaped by `--fault` are not recorded.
+struct RecordMisses(StoreSink);
+
+impl TraceSink for RecordMisses {
+    fn name(&self) -> &str {
+        self.0.name()
+    }
+
+    fn write(&self, trace: &HttpTrace) -> Result<(), StorageError> {
+        if trace.replay == Some(ReplayOutcome::Miss) && trace.fault.is_none() {
+            self.0.write(trace)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Opens the `--replay` recording in `dir` (a phantom data directory,
+/// created if missing). Returns its traces indexed by request fingerprint —
+/// method, full URL and body hash — plus the sink that records misses.
+pub fn open_replay_recording(
+    dir: &Path,
+    data_dir: &Path,
+) -> anyhow::Result<(MockRoutes, Box<dyn TraceSink>)> {
+    std::fs::create_dir_all(dir)?;
+    if dir.canonicalize()? == data_dir.canonicalize()? {
+        anyhow::bail!(
+            "--replay {}: the recording must be a different directory than --data-dir",
+            dir.display()
+        );
+    }
+    let store = DataStore::open(dir, false)
+        .map_err(|e| anyhow::anyhow!("--replay {}: {e}", dir.display()))?
+        .trace_store();
+    let mut traces = store.query(&TraceQuery {
+        limit: usize::MAX,
+        ..TraceQuery::default()
+    })?;
+    traces.reverse();
+    let options = MatchOptions {
+        body: true,
+        origin: true,
+        query_fallback: false,
+    };
+    let routes = MockRoutes::from_traces(&traces, options);
+    let recorder = RecordMisses(StoreSink::new(format!("replay:{}", dir.display()), store));
+    Ok((routes, Box::new(recorder)))
+}
@@ -0,0 +1,316 @@
+//! Trace streaming for `--sink serve:ADDR`, read by `phantom connect`.
+//!
+//! The sink listens on a TCP port and writes every trace to each connected
+//! client as one JSON-serialized [`HttpTrace`] per line — the full trace,
+//! bodies included, so the client stores exactly what was captured. Non-HTTP
+//! traces are [`ProtocolTrace`] lines, which carry a `kind` field. Each
+//! client has its own writer thread and queue: a slow or stalled client
+//! loses traces (or is dropped) without holding up capture or the others.
+//! There is no authentication; reach the port through `kubectl
+//! port-forward`, an SSH tunnel or a private network.
+
+use std::io::{BufWriter, ErrorKind, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use phantom_core::error::StorageError;
+use phantom_core::protocol::ProtocolTrace;
+use phantom_core::sink::TraceSink;
+use phantom_core::trace::HttpTrace;
+use serde::Serialize;
+use tracing::{info, warn};
+
+/// Lines buffered per client while it is busy; further traces are dropped
+/// for that client.
+const QUEUE_CAPACITY: usize = 4096;
+/// A client that accepts no data for this long is disconnected.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+/// How often the accept loop checks whether the sink was dropped.
+const ACCEPT_POLL: Duration = Duration::from_millis(200);
+
+/// A connected client: its queue and writer thread.
+struct Client {
+    addr: SocketAddr,
+    tx: SyncSender<Arc<str>>,
+    worker: JoinHandle<()>,
+}
+
+type Clients = Arc<Mutex<Vec<Client>>>;
+
+fn write_loop(stream: TcpStream, rx: Receiver<Arc<str>>) {
+    let mut out = BufWriter::new(stream);
+    while let Ok(line) = rx.recv() {
+        let mut result = out.write_all(line.as_bytes());
+        // Batch whatever else is already queued into the same flush.
+        while result.is_ok() {
+            match rx.try_recv() {
+                Ok(line) => result = out.write_all(line.as_bytes()),
+                Err(_) => break,
+            }
+        }
+        if result.and_then(|()| out.flush()).is_err() {
+            return;
+        }
+    }
+}
+
+fn accept_loop(listener: TcpListener, clients: Clients, stop: Arc<AtomicBool>) {
+    while !stop.load(Ordering::Relaxed) {
+        let (stream, addr) = match listener.accept() {
+            Ok(conn) => conn,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                std::thread::sleep(ACCEPT_POLL);
+                continue;
+            }
+            Err(e) => {
+                warn!("trace stream: accept failed: {e}");
+                std::thread::sleep(ACCEPT_POLL);
+                continue;
+            }
+        };
+        if let Err(e) = stream
+            .set_nonblocking(false)
+            .and_then(|()| stream.set_write_timeout(Some(WRITE_TIMEOUT)))
+        {
+            warn!("trace stream: client {addr}: {e}");
+            continue;
+        }
+        let (tx, rx) = std::sync::mpsc::sync_channel(QUEUE_CAPACITY);
+        let worker = match std::thread::Builder::new()
+            .name("phantom-stream".into())
+            .spawn(move || write_loop(stream, rx))
+        {
+            Ok(worker) => worker,
+            Err(e) => {
+                warn!("trace stream: client {addr}: {e}");
+                continue;
+            }
+        };
+        info!("trace stream: client {addr} connected");
+        if let Ok(mut clients) = clients.lock() {
+            clients.push(Client { addr, tx, worker });
+        }
+    }
+}
+
+/// A [`TraceSink`] streaming traces to `phantom connect` clients.
+///
+/// Traces are only sent to clients connected at the time; nothing is
+/// replayed to late joiners. Dropping the sink stops listening and lets each
+/// client's writer deliver what it has queued.
+pub struct StreamSink {
+    name: String,
+    clients: Clients,
+    stop: Arc<AtomicBool>,
+    acceptor: Option<JoinHandle<()>>,
+}
+
+impl StreamSink {
+    /// Starts listening on `addr` (`HOST:PORT`).
+    pub fn bind(addr: &str) -> anyhow::Result<Self> {
+        let listener =
+            TcpListener::bind(addr).map_err(|e| anyhow::anyhow!("cannot listen on {addr}: {e}"))?;
+        listener.set_nonblocking(true)?;
+        // Report the bound address, so port 0 shows the port picked.
+        let name = format!("serve:{}", listener.local_addr()?);
+        let clients = Clients::default();
+        let stop = Arc::new(AtomicBool::new(false));
+        let acceptor = {
+            let clients = clients.clone();
+            let stop = stop.clone();
+            std::thread::Builder::new()
+                .name("phantom-stream-accept".into())
+                .spawn(move || accept_loop(listener, clients, stop))?
+        };
+        Ok(Self {
+            name,
+            clients,
+            stop,
+            acceptor: Some(acceptor),
+        })
+    }
+}
+
+impl StreamSink {
+    /// Queues `record` as one line for every connected client.
+    fn broadcast(&self, record: &impl Serialize) -> Result<(), StorageError> {
+        let mut clients = self
+            .clients
+            .lock()
+            .map_err(|_| StorageError::Write("trace stream lock poisoned".into()))?;
+        if clients.is_empty() {
+            return Ok(());
+        }
+        let mut line = serde_json::to_string(record)
+            .map_err(|e| StorageError::Serialization(e.to_string()))?;
+        line.push('\n');
+        let line: Arc<str> = line.into();
+        clients.retain(|client| match client.tx.try_send(line.clone()) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                warn!(
+                    "trace stream: client {} is not keeping up, trace dropped",
+                    client.addr
+                );
+                true
+            }
+            Err(TrySendError::Disconnected(_)) => {
+                info!("trace stream: client {} disconnected", client.addr);
+                false
+            }
+        });
+        Ok(())
+    }
+}
+
+impl TraceSink for StreamSink {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn write(&self, trace: &HttpTrace) -> Result<(), StorageError> {
+        self.broadcast(trace)
+    }
+
+    fn write_protocol(&self, trace: &ProtocolTrace) -> Result<(), StorageError> {
+        self.broadcast(trace)
+    }
+}
+
+impl Drop for StreamSink {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(acceptor) = self.acceptor.take() {
+            let _ = acceptor.join();
+        }
+        // Closing each queue makes its writer send the rest and exit.
+        let clients = match self.clients.lock() {
+            Ok(mut clients) => std::mem::take(&mut *clients),
+            Err(_) => return,
+        };
+        for client in clients {
+            drop(client.tx);
+            let _ = client.worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader};
+    use std::time::{Instant, SystemTime};
+
+    use phantom_core::redis::{RedisReplyType, RedisTrace};
+    use phantom_core::trace::{HttpMethod, SpanId, TraceId};
+
+    use super::*;
+
+    fn make_trace(url: &str) -> HttpTrace {
+        HttpTrace {
+            span_id: SpanId([0xab; 8]),
+            trace_id: TraceId([0x01; 16]),
+            parent_span_id: None,
+            method: HttpMethod::Post,
+            url: url.to_string(),
+            request_headers: HashMap::from([("x-test".into(), "1".into())]),
+            request_body: Some(b"{\"a\":1}".to_vec()),
+            status_code: 201,
+            response_headers: HashMap::new(),
+            response_body: None,
+            timestamp: SystemTime::now(),
+            duration: Duration::from_millis(25),
+            source_addr: None,
+            dest_addr: None,
+            protocol_version: "HTTP/1.1".to_string(),
+            request_body_file: None,
+            response_body_file: None,
+            connection_id: None,
+            process: None,
+            response_trailers: HashMap::new(),
+            request_body_encoding: None,
+            response_body_encoding: None,
+            elasticsearch: None,
+            soap: None,
+            graphql: None,
+            thread_id: None,
+            tls: None,
+            timing: None,
+            request_size: None,
+            response_size: None,
+            fault: None,
+            replay: None,
+        }
+    }
+
+    fn wait_for_clients(sink: &StreamSink, n: usize) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while sink.clients.lock().unwrap().len() < n {
+            assert!(Instant::now() < deadline, "client was never accepted");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn test_streams_full_traces_to_each_client() {
+        let sink = StreamSink::bind("127.0.0.1:0").unwrap();
+        // Nobody is connected yet: the trace is simply not sent.
+        sink.write(&make_trace("http://svc/before")).unwrap();
+
+        let addr = sink.name().strip_prefix("serve:").unwrap().to_string();
+        let a = TcpStream::connect(&addr).unwrap();
+        let b = TcpStream::connect(&addr).unwrap();
+        wait_for_clients(&sink, 2);
+
+        let trace = make_trace("http://svc/orders");
+        sink.write(&trace).unwrap();
+        let redis = RedisTrace {
+            command: "GET".into(),
+            key: Some("user:1".into()),
+            args: Vec::new(),
+            arg_count: 1,
+            reply_type: RedisReplyType::Bulk,
+            error: None,
+            timestamp: SystemTime::now(),
+            duration: Duration::from_millis(1),
+            dest_addr: None,
+        };
+        sink.write_protocol(&ProtocolTrace::Redis(redis)).unwrap();
+        drop(sink);
+
+        for conn in [a, b] {
+            let lines: Vec<String> = BufReader::new(conn).lines().map(Result::unwrap).collect();
+            assert_eq!(lines.len(), 2);
+            let Ok(ProtocolTrace::Redis(got)) = serde_json::from_str(&lines[1]) else {
+                panic!("expected a Redis trace: {}", lines[1]);
+            };
+            assert_eq!(got.key.as_deref(), Some("user:1"));
+            let got: HttpTrace = serde_json::from_str(&lines[0]).unwrap();
+            assert_eq!(got.url, trace.url);
+            assert_eq!(got.request_body, trace.request_body);
+            assert_eq!(got.request_headers, trace.request_headers);
+            assert_eq!(got.span_id, trace.span_id);
+        }
+    }
+
+    #[test]
+    fn test_disconnected_client_is_forgotten() {
+        let sink = StreamSink::bind("127.0.0.1:0").unwrap();
+        let addr = sink.name().strip_prefix("serve:").unwrap().to_string();
+        drop(TcpStream::connect(&addr).unwrap());
+        wait_for_clients(&sink, 1);
+
+        // The writer notices the closed socket on a failed write and exits;
+        // the sink drops the client on a later trace.
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !sink.clients.lock().unwrap().is_empty() {
+            assert!(Instant::now() < deadline, "client was never dropped");
+            sink.write(&make_trace("http://svc/")).unwrap();
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
@@ -198,6 +198,12 @@ fn test_fault_error_always() {
         "status_code should be 503 (fault injected), trace: {t}"
     );
 
+    // Nothing was forwarded, so there is no destination socket.
+    assert!(
+        t["dest_addr"].is_null(),
+        "dest_addr should be absent for a locally answered request, trace: {t}"
+    );
+
     // Response body must contain the fault marker.
     let body = t["response_body"].as_str().unwrap_or("");
     assert!(
@@ -298,6 +304,14 @@ fn test_fault_delay_adds_latency() {
         "status_code should be 200 (real backend response), trace: {t}"
     );
 
+    // The forwarded request records the backend socket it reached.
+    let expected_dest = format!("127.0.0.1:{backend_port}");
+    assert_eq!(
+        t["dest_addr"].as_str(),
+        Some(expected_dest.as_str()),
+        "dest_addr should be the backend address, trace: {t}"
+    );
+
     // Duration must reflect the injected delay.
     let duration_ms = t["duration_ms"].as_u64().expect("duration_ms present");
     assert!(
@@ -305,6 +319,18 @@ fn test_fault_delay_adds_latency() {
         "duration_ms ({duration_ms}) should be >= injected delay ({DELAY_MS}ms)"
     );
 
+    // A fresh plain-HTTP connection to an IP literal: connect and TTFB, no
+    // lookup or handshake. TTFB starts after the delay.
+    let timing = &t["timing"];
+    assert!(timing["connect_ms"].is_u64(), "trace: {t}");
+    assert!(timing["dns_ms"].is_null(), "trace: {t}");
+    assert!(timing["tls_handshake_ms"].is_null(), "trace: {t}");
+    let ttfb_ms = timing["ttfb_ms"].as_u64().expect("ttfb_ms present");
+    assert!(
+        ttfb_ms < duration_ms,
+        "ttfb_ms ({ttfb_ms}) should exclude the injected delay (duration {duration_ms}ms)"
+    );
+
     eprintln!(
         "test_fault_delay_adds_latency: OK — status=200, duration={duration_ms}ms (>= {DELAY_MS}ms)"
     );